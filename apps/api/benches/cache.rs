@@ -0,0 +1,124 @@
+//! Cache reads under contention: `TtlCache` against the `RwLock<HashMap>` it replaced
+//!
+//! Each iteration runs reader threads doing cache hits while one writer keeps
+//! inserting, the way concurrent recommendation requests hit the result cache.
+//! The threads live for a whole sample, so only the reads and writes are timed.
+//!
+//! Run with `cargo bench --bench cache`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use recommend_a_book_api::cache::TtlCache;
+use std::collections::HashMap;
+use std::hint::black_box;
+use std::sync::{Barrier, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const KEYS: usize = 500;
+const READS_PER_THREAD: usize = 2_000;
+const WRITES: usize = 200;
+
+fn keys() -> Vec<String> {
+    (0..KEYS).map(|i| format!("query {}:100", i)).collect()
+}
+
+/// Time `iters` rounds of `threads` readers and one writer, each round ending
+/// when every thread is done. The threads are spawned once per sample and
+/// synchronize on a barrier, so thread creation isn't part of the timings.
+fn run_contended(
+    iters: u64,
+    threads: usize,
+    read: impl Fn(usize) + Sync,
+    write: impl Fn(usize) + Sync,
+) -> Duration {
+    let barrier = Barrier::new(threads + 2);
+    thread::scope(|scope| {
+        scope.spawn(|| {
+            for _ in 0..iters {
+                barrier.wait();
+                for i in 0..WRITES {
+                    write(i);
+                }
+                barrier.wait();
+            }
+        });
+        for _ in 0..threads {
+            scope.spawn(|| {
+                for _ in 0..iters {
+                    barrier.wait();
+                    for i in 0..READS_PER_THREAD {
+                        read(i);
+                    }
+                    barrier.wait();
+                }
+            });
+        }
+
+        let mut elapsed = Duration::ZERO;
+        for _ in 0..iters {
+            let start = Instant::now();
+            barrier.wait();
+            barrier.wait();
+            elapsed += start.elapsed();
+        }
+        elapsed
+    })
+}
+
+fn contended_reads(c: &mut Criterion) {
+    let keys = keys();
+    let mut group = c.benchmark_group("contended_reads");
+
+    for threads in [1, 4, 8] {
+        let cache: TtlCache<String, Vec<u32>> =
+            TtlCache::new("bench", KEYS * 2, Duration::from_secs(300));
+        for key in &keys {
+            cache.insert(key.clone(), vec![0; 100]);
+        }
+        group.bench_with_input(
+            BenchmarkId::new("ttl_cache", threads),
+            &threads,
+            |b, &threads| {
+                b.iter_custom(|iters| {
+                    run_contended(
+                        iters,
+                        threads,
+                        |i| {
+                            black_box(cache.get(&keys[i % KEYS]));
+                        },
+                        |i| cache.insert(keys[i % KEYS].clone(), vec![0; 100]),
+                    )
+                })
+            },
+        );
+
+        let locked: RwLock<HashMap<String, Vec<u32>>> =
+            RwLock::new(keys.iter().map(|key| (key.clone(), vec![0; 100])).collect());
+        group.bench_with_input(
+            BenchmarkId::new("rwlock_hashmap", threads),
+            &threads,
+            |b, &threads| {
+                b.iter_custom(|iters| {
+                    run_contended(
+                        iters,
+                        threads,
+                        |i| {
+                            black_box(locked.read().unwrap().get(&keys[i % KEYS]).cloned());
+                        },
+                        |i| {
+                            locked
+                                .write()
+                                .unwrap()
+                                .insert(keys[i % KEYS].clone(), vec![0; 100]);
+                        },
+                    )
+                })
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, contended_reads);
+criterion_main!(benches);
@@ -0,0 +1,154 @@
+//! Hot paths of a recommendation request that don't need upstreams
+//!
+//! - query enhancement: every uncached query runs the template regexes
+//! - ranking: top_k=200 ranks 600 candidates from the vector search
+//! - dedup and normalization: run per row when indexing
+//!
+//! Run with `cargo bench --bench ranking`.
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use recommend_a_book_api::ingest::{
+    dedup::{deduplicate, normalize_title, DedupConfig},
+    normalize_author,
+};
+use recommend_a_book_api::models::Book;
+use recommend_a_book_api::services::{
+    ranking::{finalize_results, rank_results, QueryIntent},
+    semantic_classifier::SemanticQueryInfo,
+    templates::{EnhancedQuery, QueryPattern},
+};
+use std::hint::black_box;
+
+/// Candidates the vector search returns for top_k=200
+const CANDIDATES: usize = 600;
+const TOP_K: usize = 200;
+
+const QUERIES: &[&str] = &[
+    "fantasy books with dragons and magic",
+    "books like The Name of the Wind",
+    "mystery novels by Agatha Christie",
+    "short sci-fi from the 1970s about first contact",
+    "cozy romance set in a small bookshop",
+    "non-fiction about the history of mathematics",
+];
+
+const CATEGORIES: &[&str] = &[
+    "Fantasy",
+    "Science Fiction",
+    "Mystery",
+    "Romance",
+    "History",
+    "Young Adult Fiction",
+];
+
+fn candidates(count: usize) -> Vec<Book> {
+    (0..count)
+        .map(|i| {
+            // Every tenth book is another edition of the one before, so dedup has work to do
+            let edition_of = if i % 10 == 9 { i - 1 } else { i };
+            let category = CATEGORIES[edition_of % CATEGORIES.len()];
+            serde_json::from_value(serde_json::json!({
+                "id": format!("book-{}", i),
+                "title": format!("The {} Chronicles, Volume {}", category, edition_of),
+                "author": format!("Author {}", edition_of % 97),
+                "description": format!(
+                    "A {} story about dragons, magic and a journey across a divided kingdom. {}",
+                    category.to_lowercase(),
+                    "Long descriptions are common in the index. ".repeat(8)
+                ),
+                "categories": [category, "Fiction"],
+                "rating": (i % 50) as f32 / 10.0,
+                "year": 1950 + (i % 70) as i32,
+            }))
+            .expect("valid book")
+        })
+        .collect()
+}
+
+fn query_info(query: &str, author: Option<&str>) -> SemanticQueryInfo {
+    SemanticQueryInfo {
+        original_query: query.to_string(),
+        themes: vec![
+            ("fantasy".to_string(), 0.9),
+            ("dragons".to_string(), 0.8),
+            ("magic".to_string(), 0.7),
+        ],
+        author: author.map(str::to_string),
+        temporal_filter: None,
+        length_filter: None,
+        is_similar_query: false,
+        semantic_tags: vec!["Fantasy".to_string()],
+        moods: vec![],
+        award: None,
+        settings: vec![],
+        intent_confidence: 1.0,
+        pattern: QueryPattern::default(),
+    }
+}
+
+fn query_enhancement(c: &mut Criterion) {
+    c.bench_function("enhanced_query_from_query", |b| {
+        b.iter(|| {
+            for query in QUERIES {
+                black_box(EnhancedQuery::from_query(black_box(query)));
+            }
+        })
+    });
+}
+
+fn ranking(c: &mut Criterion) {
+    let books = candidates(CANDIDATES);
+    let mut group = c.benchmark_group("rank_600_candidates");
+
+    for (label, info) in [
+        (
+            "general",
+            query_info("fantasy books with dragons and magic", None),
+        ),
+        ("author", query_info("books by Author 7", Some("Author 7"))),
+    ] {
+        let intent = QueryIntent::from_query_info(&info);
+        group.bench_function(label, |b| {
+            b.iter_batched(
+                || books.clone(),
+                |books| rank_results(books, &intent, &info, TOP_K, 2.0, 50),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+
+    let info = query_info("fantasy books with dragons and magic", None);
+    group.bench_function("finalize_only", |b| {
+        b.iter_batched(
+            || books.clone(),
+            |books| finalize_results(books, &info, TOP_K),
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.finish();
+}
+
+fn dedup_and_normalization(c: &mut Criterion) {
+    let books = candidates(CANDIDATES);
+
+    c.bench_function("deduplicate_600", |b| {
+        b.iter_batched(
+            || books.iter().cloned().enumerate().collect::<Vec<_>>(),
+            |rows| deduplicate(rows, &DedupConfig::default()),
+            BatchSize::LargeInput,
+        )
+    });
+
+    c.bench_function("normalize_titles_and_authors_600", |b| {
+        b.iter(|| {
+            for book in &books {
+                black_box(normalize_title(book.title.as_deref().unwrap_or_default()));
+                black_box(normalize_author(book.author.as_deref().unwrap_or_default()));
+            }
+        })
+    });
+}
+
+criterion_group!(benches, query_enhancement, ranking, dedup_and_normalization);
+criterion_main!(benches);
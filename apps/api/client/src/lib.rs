@@ -0,0 +1,263 @@
+//! Typed client for the Recommend a Book API
+//!
+//! Wraps the endpoints scripts and other Rust services call, with typed bodies,
+//! API errors decoded from the server's error responses, and retries with
+//! exponential backoff for throttled or briefly unavailable servers.
+//!
+//! ```no_run
+//! # async fn example() -> Result<(), recommend_a_book_client::ClientError> {
+//! use recommend_a_book_client::{Client, RecommendationRequest};
+//!
+//! let client = Client::new("http://localhost:8000");
+//! let response = client
+//!     .recommendations(&RecommendationRequest::new("cozy fantasy with dragons").with_top_k(10))
+//!     .await?;
+//! for book in response.recommendations {
+//!     println!("{}", book.title.unwrap_or_default());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+mod retry;
+mod types;
+
+pub use retry::RetryPolicy;
+pub use types::*;
+
+use reqwest::{header::RETRY_AFTER, Method, RequestBuilder, StatusCode};
+use serde::de::DeserializeOwned;
+use std::time::Duration;
+use thiserror::Error;
+
+/// Default timeout of a single attempt
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Error)]
+pub enum ClientError {
+    /// The server answered with an error response
+    #[error("API error {status} ({code}): {message}")]
+    Api {
+        status: u16,
+        /// Machine-readable code, e.g. `invalid_input`; `unknown` for non-JSON errors
+        code: String,
+        message: String,
+    },
+
+    /// The request couldn't be sent, timed out, or the response couldn't be decoded
+    #[error("Request failed: {0}")]
+    Http(#[from] reqwest::Error),
+}
+
+impl ClientError {
+    /// HTTP status of an API error
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            Self::Api { status, .. } => Some(*status),
+            Self::Http(e) => e.status().map(|status| status.as_u16()),
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, ClientError>;
+
+/// Client for one API server
+#[derive(Debug, Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    base_url: String,
+    timeout: Duration,
+    retry: RetryPolicy,
+    admin_key: Option<String>,
+    bearer_token: Option<String>,
+    actor: Option<String>,
+}
+
+impl Client {
+    /// Client for the server at `base_url`, e.g. `http://localhost:8000`
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            timeout: DEFAULT_TIMEOUT,
+            retry: RetryPolicy::default(),
+            admin_key: None,
+            bearer_token: None,
+            actor: None,
+        }
+    }
+
+    /// Timeout of each attempt (default: 30 seconds)
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Key sent as `X-Admin-Key` to the admin endpoints
+    pub fn with_admin_key(mut self, admin_key: impl Into<String>) -> Self {
+        self.admin_key = Some(admin_key.into());
+        self
+    }
+
+    /// A user's access token, sent as `Authorization: Bearer` to the per-user endpoints
+    pub fn with_bearer_token(mut self, token: impl Into<String>) -> Self {
+        self.bearer_token = Some(token.into());
+        self
+    }
+
+    /// Name recorded in the audit log for admin actions
+    pub fn with_actor(mut self, actor: impl Into<String>) -> Self {
+        self.actor = Some(actor.into());
+        self
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// `GET /api/health`
+    pub async fn health(&self) -> Result<HealthResponse> {
+        self.send(Method::GET, "/api/health", |request| request)
+            .await
+    }
+
+    /// `POST /api/recommendations`
+    pub async fn recommendations(
+        &self,
+        request: &RecommendationRequest,
+    ) -> Result<RecommendationResponse> {
+        self.send(Method::POST, "/api/recommendations", |builder| {
+            builder.json(request)
+        })
+        .await
+    }
+
+    /// `POST /api/recommendations/for-you`, with the user's bearer token or the admin key
+    pub async fn for_you(&self, request: &ForYouRequest) -> Result<ForYouResponse> {
+        self.send(Method::POST, "/api/recommendations/for-you", |builder| {
+            builder.json(request)
+        })
+        .await
+    }
+
+    /// `POST /api/recommendations/similar-to`
+    pub async fn similar_to(&self, request: &SimilarToRequest) -> Result<SimilarToResponse> {
+        self.send(Method::POST, "/api/recommendations/similar-to", |builder| {
+            builder.json(request)
+        })
+        .await
+    }
+
+    /// `POST /api/explanations`
+    pub async fn explanations(
+        &self,
+        request: &ExplanationsRequest,
+    ) -> Result<ExplanationsResponse> {
+        self.send(Method::POST, "/api/explanations", |builder| {
+            builder.json(request)
+        })
+        .await
+    }
+
+    /// `POST /api/query/parse`
+    pub async fn parse_query(&self, request: &QueryParseRequest) -> Result<QueryParseResponse> {
+        self.send(Method::POST, "/api/query/parse", |builder| {
+            builder.json(request)
+        })
+        .await
+    }
+
+    /// `GET /api/catalog/titles`: up to `limit` titles starting with `prefix`
+    pub async fn suggest_titles(
+        &self,
+        prefix: &str,
+        limit: usize,
+    ) -> Result<TitleSuggestionsResponse> {
+        self.send(Method::GET, "/api/catalog/titles", |builder| {
+            builder.query(&[("prefix", prefix), ("limit", &limit.to_string())])
+        })
+        .await
+    }
+
+    /// `POST /api/admin/cache/clear`: clear the server's caches
+    pub async fn clear_caches(&self) -> Result<AdminActionResponse> {
+        self.send(Method::POST, "/api/admin/cache/clear", |builder| builder)
+            .await
+    }
+
+    /// Send a request, retrying transient failures, and decode a JSON response
+    ///
+    /// `build` is called once per attempt to add the body and query.
+    async fn send<T, F>(&self, method: Method, path: &str, build: F) -> Result<T>
+    where
+        T: DeserializeOwned,
+        F: Fn(RequestBuilder) -> RequestBuilder,
+    {
+        let url = format!("{}{}", self.base_url, path);
+        let mut attempt = 0;
+        loop {
+            let mut request = self
+                .http
+                .request(method.clone(), &url)
+                .timeout(self.timeout);
+            if let Some(admin_key) = &self.admin_key {
+                request = request.header("X-Admin-Key", admin_key);
+            }
+            if let Some(token) = &self.bearer_token {
+                request = request.bearer_auth(token);
+            }
+            if let Some(actor) = &self.actor {
+                request = request.header("X-Admin-Actor", actor);
+            }
+
+            let (error, retry_after) = match build(request).send().await {
+                Ok(response) if response.status().is_success() => {
+                    return Ok(response.json().await?);
+                }
+                Ok(response) => {
+                    let status = response.status();
+                    let retry_after = response
+                        .headers()
+                        .get(RETRY_AFTER)
+                        .and_then(|value| value.to_str().ok())
+                        .and_then(|value| value.trim().parse::<u64>().ok())
+                        .map(Duration::from_secs);
+                    let error = api_error(status, response.text().await.unwrap_or_default());
+                    if !RetryPolicy::is_retryable_status(status) {
+                        return Err(error);
+                    }
+                    (error, retry_after)
+                }
+                Err(e) if e.is_connect() || e.is_timeout() => (ClientError::Http(e), None),
+                Err(e) => return Err(ClientError::Http(e)),
+            };
+
+            let Some(delay) = self.retry.delay(attempt, retry_after) else {
+                return Err(error);
+            };
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+/// Decode an error response, keeping the raw body when it isn't the API's JSON
+fn api_error(status: StatusCode, body: String) -> ClientError {
+    match serde_json::from_str::<ErrorResponse>(&body) {
+        Ok(error) => ClientError::Api {
+            status: status.as_u16(),
+            code: error.code,
+            message: error.error,
+        },
+        Err(_) => ClientError::Api {
+            status: status.as_u16(),
+            code: "unknown".to_string(),
+            message: body,
+        },
+    }
+}
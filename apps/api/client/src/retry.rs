@@ -0,0 +1,95 @@
+//! Retries with exponential backoff
+//!
+//! Requests are retried when the server is throttling (429), overloaded or
+//! restarting (502-504), or couldn't be reached or answered in time. A
+//! `Retry-After` header, which the API sends with its 503s, replaces the
+//! computed delay.
+
+use reqwest::StatusCode;
+use std::time::Duration;
+
+/// How often and how long to wait before retrying a failed request
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt; 0 disables retrying
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled for each retry after it
+    pub base_delay: Duration,
+    /// Longest delay between attempts, including delays asked for by `Retry-After`
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay: Duration::from_millis(250),
+            max_delay: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Never retry
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            ..Self::default()
+        }
+    }
+
+    pub fn is_retryable_status(status: StatusCode) -> bool {
+        matches!(status.as_u16(), 429 | 502 | 503 | 504)
+    }
+
+    /// Delay before retry number `attempt + 1`, or None when retries are used up
+    pub fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Option<Duration> {
+        if attempt >= self.max_retries {
+            return None;
+        }
+        let backoff = self.base_delay.saturating_mul(2u32.saturating_pow(attempt));
+        Some(retry_after.unwrap_or(backoff).min(self.max_delay))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delays_double_up_to_the_cap_and_honor_retry_after() {
+        let policy = RetryPolicy {
+            max_retries: 4,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(500),
+        };
+
+        let delays: Vec<Option<Duration>> =
+            (0..5).map(|attempt| policy.delay(attempt, None)).collect();
+        assert_eq!(
+            delays,
+            vec![
+                Some(Duration::from_millis(100)),
+                Some(Duration::from_millis(200)),
+                Some(Duration::from_millis(400)),
+                Some(Duration::from_millis(500)),
+                None,
+            ]
+        );
+
+        assert_eq!(
+            policy.delay(0, Some(Duration::from_millis(300))),
+            Some(Duration::from_millis(300))
+        );
+        assert_eq!(
+            policy.delay(0, Some(Duration::from_secs(60))),
+            Some(Duration::from_millis(500))
+        );
+        assert_eq!(RetryPolicy::none().delay(0, None), None);
+
+        assert!(RetryPolicy::is_retryable_status(
+            StatusCode::SERVICE_UNAVAILABLE
+        ));
+        assert!(!RetryPolicy::is_retryable_status(StatusCode::BAD_REQUEST));
+    }
+}
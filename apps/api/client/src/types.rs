@@ -0,0 +1,534 @@
+//! Request and response bodies of the API
+//!
+//! These mirror the server's models without their OpenAPI annotations. Fields
+//! the server may omit default, so a client keeps working when fields are added
+//! or a request projects books onto a few fields.
+
+use serde::{Deserialize, Serialize};
+
+/// A recommended book
+///
+/// Requests with `fields` set only fill in those fields (and `id`).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Book {
+    pub id: Option<String>,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    /// A 300-character preview unless full descriptions were requested
+    pub description: Option<String>,
+    pub categories: Vec<String>,
+    /// Genres from the controlled vocabulary
+    pub genres: Vec<String>,
+    /// How the book feels to read, e.g. "cozy" or "suspenseful"
+    pub moods: Vec<String>,
+    /// Literary awards the book won, e.g. "Hugo Award"
+    pub awards: Vec<String>,
+    /// Places the book is set in, e.g. "Tokyo", with the regions containing them
+    pub settings: Vec<String>,
+    /// Content warnings, e.g. "violence"; flagged books are left out by safe search
+    pub content_flags: Vec<String>,
+    /// ISO country codes of the markets the book is available in; empty when unknown
+    pub available_regions: Vec<String>,
+    pub thumbnail: Option<String>,
+    /// Average rating from 0 to 5
+    pub rating: f32,
+    pub year: Option<i32>,
+    pub isbn: Option<String>,
+    pub page_count: Option<i32>,
+    pub ratings_count: Option<i32>,
+    pub language: Option<String>,
+    pub publisher: Option<String>,
+    /// Why the book was recommended, e.g. "Fantasy" or "Author: Tolkien"
+    pub relevance_indicators: Vec<String>,
+    /// How well the book matches the query, from 0 to 1
+    pub confidence_score: f32,
+    /// One sentence on why the book fits the query, for the top results when requested
+    pub explanation: Option<String>,
+}
+
+/// Body of `POST /api/recommendations`
+#[derive(Debug, Clone, Serialize)]
+pub struct RecommendationRequest {
+    pub query: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fields: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub description_full: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub safe_search: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exploration: Option<f32>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub explain: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lang: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub context: Vec<String>,
+}
+
+impl RecommendationRequest {
+    pub fn new(query: impl Into<String>) -> Self {
+        Self {
+            query: query.into(),
+            top_k: None,
+            user_id: None,
+            session_id: None,
+            fields: None,
+            description_full: false,
+            safe_search: None,
+            exploration: None,
+            explain: false,
+            lang: None,
+            region: None,
+            context: Vec::new(),
+        }
+    }
+
+    /// Number of books to return, 1 to 200 (server default: 100)
+    pub fn with_top_k(mut self, top_k: usize) -> Self {
+        self.top_k = Some(top_k);
+        self
+    }
+
+    /// Personalize with the user's ratings and record the query in their history
+    pub fn with_user_id(mut self, user_id: impl Into<String>) -> Self {
+        self.user_id = Some(user_id.into());
+        self
+    }
+
+    /// Record the query under an anonymous session and leave out books dismissed in it
+    pub fn with_session_id(mut self, session_id: impl Into<String>) -> Self {
+        self.session_id = Some(session_id.into());
+        self
+    }
+
+    /// Only return these book fields; `id` is always included
+    pub fn with_fields<I, S>(mut self, fields: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.fields = Some(fields.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Return full descriptions instead of 300-character previews
+    pub fn with_full_descriptions(mut self) -> Self {
+        self.description_full = true;
+        self
+    }
+
+    /// Turn safe search on or off, overriding the user's saved preference
+    pub fn with_safe_search(mut self, safe_search: bool) -> Self {
+        self.safe_search = Some(safe_search);
+        self
+    }
+
+    /// Mix in well-rated books from lower down the ranking, from 0 (none) to 1
+    pub fn with_exploration(mut self, exploration: f32) -> Self {
+        self.exploration = Some(exploration);
+        self
+    }
+
+    /// Add a one-sentence explanation to each of the top 5 results
+    pub fn with_explanations(mut self) -> Self {
+        self.explain = true;
+        self
+    }
+
+    /// Language of explanations and semantic tags: en, de or es
+    pub fn with_lang(mut self, lang: impl Into<String>) -> Self {
+        self.lang = Some(lang.into());
+        self
+    }
+
+    /// Leave out books known not to be published or sold in this market, e.g. "GB"
+    pub fn with_region(mut self, region: impl Into<String>) -> Self {
+        self.region = Some(region.into());
+        self
+    }
+
+    /// Earlier queries and selections of the conversation, oldest first
+    pub fn with_context(mut self, context: Vec<String>) -> Self {
+        self.context = context;
+        self
+    }
+}
+
+/// Body of `POST /api/explanations`
+#[derive(Debug, Clone, Serialize)]
+pub struct ExplanationsRequest {
+    pub query: String,
+    pub book_ids: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lang: Option<String>,
+}
+
+impl ExplanationsRequest {
+    /// Explanations of up to 20 books shown for `query`
+    pub fn new<I, S>(query: impl Into<String>, book_ids: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            query: query.into(),
+            book_ids: book_ids.into_iter().map(Into::into).collect(),
+            lang: None,
+        }
+    }
+
+    /// Language of the explanations: en, de or es
+    pub fn with_lang(mut self, lang: impl Into<String>) -> Self {
+        self.lang = Some(lang.into());
+        self
+    }
+}
+
+/// Body of `POST /api/query/parse`
+#[derive(Debug, Clone, Serialize)]
+pub struct QueryParseRequest {
+    pub query: String,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub llm: bool,
+}
+
+impl QueryParseRequest {
+    pub fn new(query: impl Into<String>) -> Self {
+        Self {
+            query: query.into(),
+            llm: false,
+        }
+    }
+
+    /// Let the server's language model fill in constraints its templates miss;
+    /// needs the client's admin key, and the search doesn't apply what it adds
+    pub fn with_llm(mut self) -> Self {
+        self.llm = true;
+        self
+    }
+}
+
+/// Constraints a search for a query applies
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct QueryFilters {
+    pub author: Option<String>,
+    pub genres: Vec<String>,
+    pub themes: Vec<String>,
+    pub min_rating: Option<f32>,
+    pub min_pages: Option<i32>,
+    pub max_pages: Option<i32>,
+    pub min_year: Option<i32>,
+    pub max_year: Option<i32>,
+    /// "children" or "young adult"
+    pub audience: Option<String>,
+    pub settings: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct QueryParseResponse {
+    pub query: String,
+    /// What the query mostly asks for, e.g. "Author" or "Genre"
+    pub pattern: String,
+    pub filters: QueryFilters,
+    #[serde(default)]
+    pub extracted_terms: Vec<String>,
+    /// "templates", or "templates_and_llm" when a language model filled in
+    /// constraints, which are advisory
+    pub parsed_by: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BookExplanation {
+    pub book_id: String,
+    pub explanation: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExplanationsResponse {
+    pub explanations: Vec<BookExplanation>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RecommendationResponse {
+    pub recommendations: Vec<Book>,
+    /// Themes and topics detected in the query
+    #[serde(default)]
+    pub semantic_tags: Vec<String>,
+    /// What the query was read as, for filter chips
+    #[serde(default)]
+    pub tags: Vec<SemanticTag>,
+    /// Suggested narrower queries
+    #[serde(default)]
+    pub refinements: Vec<Refinement>,
+    /// Query constraints dropped because too few books met them: "year", "length",
+    /// "award" or "setting"
+    #[serde(default)]
+    pub relaxed: Vec<String>,
+    /// Ranking experiment variants the request was bucketed into
+    #[serde(default)]
+    pub experiments: Vec<ExperimentAssignment>,
+    /// "hit" when the ranked results came from the server's result cache, else "miss"
+    #[serde(default)]
+    pub cache: String,
+    /// Time the server took to answer, in milliseconds
+    #[serde(default)]
+    pub took_ms: u64,
+    /// The results come from the fallback search instead of the vector search
+    #[serde(default)]
+    pub degraded: bool,
+    /// Embedding model the query was encoded with
+    #[serde(default)]
+    pub model_used: Option<String>,
+    /// Which search answered the query: "vector", "sparse" or "cached"
+    #[serde(default)]
+    pub search_path: String,
+    /// How the request's context changed the query
+    #[serde(default)]
+    pub conversation: Option<ConversationState>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ConversationState {
+    /// The query with earlier turns' constraints added and negations taken out
+    pub searched_query: String,
+    /// Genres and terms books were left out for
+    pub excluded: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct SemanticTag {
+    pub label: String,
+    /// "theme", "genre", "mood", "author" or "era"
+    pub kind: String,
+    /// From 0 to 1
+    pub confidence: f32,
+    /// The constraint the tag names, untranslated
+    #[serde(default)]
+    pub filter: TagFilter,
+}
+
+/// Only the field of the tag's kind is set; era tags set the years
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct TagFilter {
+    pub genre: Option<String>,
+    pub theme: Option<String>,
+    pub mood: Option<String>,
+    pub author: Option<String>,
+    pub min_year: Option<i32>,
+    pub max_year: Option<i32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Refinement {
+    pub label: String,
+    /// The query to send to apply the refinement
+    pub query: String,
+    /// "genre", "era", "length", "mood" or "setting"
+    pub kind: String,
+    /// How many of the current results already fit it
+    pub matches: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ExperimentAssignment {
+    pub experiment: String,
+    pub variant: String,
+}
+
+/// Body of `POST /api/recommendations/for-you`
+#[derive(Debug, Clone, Serialize)]
+pub struct ForYouRequest {
+    pub user_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fields: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub description_full: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+}
+
+impl ForYouRequest {
+    pub fn new(user_id: impl Into<String>) -> Self {
+        Self {
+            user_id: user_id.into(),
+            top_k: None,
+            fields: None,
+            description_full: false,
+            region: None,
+        }
+    }
+
+    /// Number of books to return, 1 to 200 (server default: 100)
+    pub fn with_top_k(mut self, top_k: usize) -> Self {
+        self.top_k = Some(top_k);
+        self
+    }
+
+    /// Only return these book fields; `id` is always included
+    pub fn with_fields<I, S>(mut self, fields: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.fields = Some(fields.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Return full descriptions instead of 300-character previews
+    pub fn with_full_descriptions(mut self) -> Self {
+        self.description_full = true;
+        self
+    }
+
+    /// Leave out books known not to be published or sold in this market, e.g. "GB"
+    pub fn with_region(mut self, region: impl Into<String>) -> Self {
+        self.region = Some(region.into());
+        self
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ForYouResponse {
+    pub recommendations: Vec<Book>,
+    /// Highly rated books the taste profile was built from
+    pub profile_size: usize,
+}
+
+/// Body of `POST /api/recommendations/similar-to`
+#[derive(Debug, Clone, Serialize)]
+pub struct SimilarToRequest {
+    pub book_ids: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub avoid_ids: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fields: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub description_full: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+}
+
+impl SimilarToRequest {
+    /// Books similar to these seed books, at most 10
+    pub fn new<I, S>(book_ids: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            book_ids: book_ids.into_iter().map(Into::into).collect(),
+            avoid_ids: Vec::new(),
+            top_k: None,
+            fields: None,
+            description_full: false,
+            region: None,
+        }
+    }
+
+    /// Steer away from these books the reader disliked, at most 10
+    pub fn with_avoid_ids<I, S>(mut self, avoid_ids: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.avoid_ids = avoid_ids.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Number of books to return, 1 to 200 (server default: 100)
+    pub fn with_top_k(mut self, top_k: usize) -> Self {
+        self.top_k = Some(top_k);
+        self
+    }
+
+    /// Only return these book fields; `id` is always included
+    pub fn with_fields<I, S>(mut self, fields: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.fields = Some(fields.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Return full descriptions instead of 300-character previews
+    pub fn with_full_descriptions(mut self) -> Self {
+        self.description_full = true;
+        self
+    }
+
+    /// Leave out books known not to be published or sold in this market, e.g. "GB"
+    pub fn with_region(mut self, region: impl Into<String>) -> Self {
+        self.region = Some(region.into());
+        self
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SimilarToResponse {
+    pub recommendations: Vec<Book>,
+    /// The seeds searched together, one list per centroid
+    pub seed_groups: Vec<Vec<String>>,
+    /// Seeds that aren't indexed
+    #[serde(default)]
+    pub missing_seeds: Vec<String>,
+    /// Books to avoid that aren't indexed
+    #[serde(default)]
+    pub missing_avoided: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct HealthResponse {
+    pub status: String,
+    /// RFC3339 timestamp
+    pub timestamp: String,
+}
+
+/// A title completing an autocomplete prefix
+#[derive(Debug, Clone, Deserialize)]
+pub struct TitleEntry {
+    pub id: Option<String>,
+    pub title: String,
+    pub author: Option<String>,
+    pub rating: f32,
+    pub ratings_count: i32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TitleSuggestionsResponse {
+    /// Most rated first
+    pub titles: Vec<TitleEntry>,
+}
+
+/// Result of an admin action
+#[derive(Debug, Clone, Deserialize)]
+pub struct AdminActionResponse {
+    /// e.g. `cache.clear`
+    pub action: String,
+    /// Id of the audit log entry recording the action
+    pub audit_id: i64,
+}
+
+/// Body of every error response
+#[derive(Debug, Clone, Deserialize)]
+pub struct ErrorResponse {
+    pub error: String,
+    /// Machine-readable code, e.g. `invalid_input` or `service_unavailable`
+    pub code: String,
+    pub status: u16,
+}
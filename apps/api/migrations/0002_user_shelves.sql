@@ -0,0 +1,11 @@
+-- Per-user reading shelves (want to read, reading, read); a book sits on at most one shelf per user
+CREATE TABLE IF NOT EXISTS user_shelves (
+    user_id TEXT NOT NULL,
+    book_id TEXT NOT NULL,
+    status TEXT NOT NULL CHECK (status IN ('want_to_read', 'reading', 'read')),
+    added_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (user_id, book_id)
+);
+
+CREATE INDEX IF NOT EXISTS user_shelves_user_status_idx ON user_shelves (user_id, status, updated_at DESC);
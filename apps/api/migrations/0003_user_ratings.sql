@@ -0,0 +1,11 @@
+-- Per-user star ratings (1-5), one per book
+CREATE TABLE IF NOT EXISTS user_ratings (
+    user_id TEXT NOT NULL,
+    book_id TEXT NOT NULL,
+    rating SMALLINT NOT NULL CHECK (rating BETWEEN 1 AND 5),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (user_id, book_id)
+);
+
+CREATE INDEX IF NOT EXISTS user_ratings_user_updated_idx ON user_ratings (user_id, updated_at DESC);
@@ -0,0 +1,11 @@
+-- Recent recommendation queries per user or anonymous session, with the tags extracted from them
+CREATE TABLE IF NOT EXISTS query_history (
+    id BIGSERIAL PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    query TEXT NOT NULL,
+    semantic_tags TEXT[] NOT NULL DEFAULT '{}',
+    result_count INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS query_history_user_created_idx ON query_history (user_id, created_at DESC);
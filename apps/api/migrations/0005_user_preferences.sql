@@ -0,0 +1,9 @@
+-- Per-user content preferences, applied as filters to that user's recommendations
+CREATE TABLE IF NOT EXISTS user_preferences (
+    user_id TEXT PRIMARY KEY,
+    preferred_languages TEXT[] NOT NULL DEFAULT '{}',
+    max_page_count INTEGER CHECK (max_page_count > 0),
+    blocked_genres TEXT[] NOT NULL DEFAULT '{}',
+    blocked_content_warnings TEXT[] NOT NULL DEFAULT '{}',
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,14 @@
+-- Anonymous impression, click and add-to-shelf events sent by the frontend
+CREATE TABLE IF NOT EXISTS analytics_events (
+    id BIGSERIAL PRIMARY KEY,
+    event_type TEXT NOT NULL CHECK (event_type IN ('impression', 'click', 'add_to_shelf')),
+    book_id TEXT NOT NULL,
+    session_id TEXT,
+    query TEXT,
+    position INTEGER,
+    occurred_at TIMESTAMPTZ NOT NULL,
+    received_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS analytics_events_type_occurred_idx ON analytics_events (event_type, occurred_at DESC);
+CREATE INDEX IF NOT EXISTS analytics_events_book_idx ON analytics_events (book_id, event_type);
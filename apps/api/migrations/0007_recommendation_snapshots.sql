@@ -0,0 +1,18 @@
+-- Recommendations sent to users outside the API, such as weekly digests
+CREATE TABLE IF NOT EXISTS recommendation_snapshots (
+    id BIGSERIAL PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    payload JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS recommendation_snapshots_user_kind_idx ON recommendation_snapshots (user_id, kind, created_at DESC);
+
+-- Books already recommended to each user, so later snapshots don't repeat them
+CREATE TABLE IF NOT EXISTS recommended_books (
+    user_id TEXT NOT NULL,
+    book_id TEXT NOT NULL,
+    first_recommended_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (user_id, book_id)
+);
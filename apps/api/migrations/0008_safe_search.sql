@@ -0,0 +1,2 @@
+-- Safe search: leave books with content flags out of the user's recommendations
+ALTER TABLE user_preferences ADD COLUMN IF NOT EXISTS safe_search BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,11 @@
+-- Each change to a book's stored metadata, with the fields that changed
+CREATE TABLE IF NOT EXISTS book_versions (
+    id BIGSERIAL PRIMARY KEY,
+    book_id TEXT NOT NULL,
+    version INTEGER NOT NULL,
+    source TEXT NOT NULL,
+    changes JSONB NOT NULL DEFAULT '{}'::jsonb,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS book_versions_book_idx ON book_versions (book_id, version DESC);
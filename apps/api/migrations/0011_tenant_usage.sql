@@ -0,0 +1,10 @@
+-- Metered usage of each tenant per calendar month (UTC), added to by every instance
+CREATE TABLE IF NOT EXISTS tenant_usage (
+    tenant TEXT NOT NULL,
+    month TEXT NOT NULL,
+    requests BIGINT NOT NULL DEFAULT 0,
+    embeddings BIGINT NOT NULL DEFAULT 0,
+    pinecone_reads BIGINT NOT NULL DEFAULT 0,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (tenant, month)
+);
@@ -0,0 +1,5 @@
+-- Query history is keyed by 'user:<id>' or 'session:<id>' so a session id can't
+-- read a user's history. Earlier rows can't be told apart, so they're kept as
+-- session history, which never reaches a signed-in user's ranking
+UPDATE query_history SET user_id = 'session:' || user_id
+WHERE user_id NOT LIKE 'user:%' AND user_id NOT LIKE 'session:%';
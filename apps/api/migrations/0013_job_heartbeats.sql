@@ -0,0 +1,3 @@
+-- The instance holding an unfinished job touches it regularly, so an instance
+-- starting up can tell the jobs nobody holds any more from those still running
+ALTER TABLE jobs ADD COLUMN IF NOT EXISTS heartbeat_at TIMESTAMPTZ;
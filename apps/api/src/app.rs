@@ -1,18 +1,81 @@
 use crate::{
+    cache::{CacheMemory, CacheStats},
     config,
-    error::Result,
-    ml::huggingface_embedder::HuggingFaceEmbedder,
-    models::{Book, ErrorResponse, HealthResponse, RecommendationRequest, RecommendationResponse},
-    routes::{api_routes, openapi_route, swagger_redirect_route, swagger_routes},
+    error::{ApiError, Result},
+    handlers::{
+        admin::{
+            AdminActionResponse, AuditLogResponse, BookPatchRequest, BookPatchResponse,
+            BookReembedResponse, BookVisibilityResponse, CacheStatusResponse, EnrichBooksRequest,
+            FeatureFlagsResponse, HiddenBooksResponse, HideBookRequest, JobCancelResponse,
+            JobSubmittedResponse, JobsResponse, PreloadResponse, ShadowReport, SlowQueriesResponse,
+            SynonymsReloadResponse, UsageResponse,
+        },
+        books::BookHistoryResponse,
+        catalog::{CorpusSummary, TitleSuggestionsResponse},
+        digest::DigestRequest,
+        events::{EventBatch, EventPayload, EventsAccepted},
+        health::ReadinessResponse,
+        history::QueryHistoryResponse,
+        query::{QueryParseRequest, QueryParseResponse, QueryParsedBy},
+        ratings::{RatingsResponse, SetRatingRequest},
+        sessions::DismissedBooksResponse,
+        shelves::{SetShelfRequest, ShelfResponse},
+        tools::{ToolCallRequest, ToolCallResponse, ToolDefinition, ToolsResponse},
+    },
+    ingest::{
+        corpus_stats::{NameCount, TitleEntry},
+        progress::{IndexProgress, IndexRunState},
+    },
+    middleware::{CatchPanic, Metering},
+    ml::huggingface_embedder::persist_embeddings,
+    models::{
+        BadGateway, Book, BookExplanation, CacheStatus, ConversationState, EndpointCheck,
+        EndpointProbe, ErrorCode, ErrorResponse, ExperimentAssignment, ExplanationsRequest,
+        ExplanationsResponse, ForYouRequest, ForYouResponse, HealthResponse, InternalServerError,
+        RecommendationRequest, RecommendationResponse, Refinement, RefinementKind,
+        RelaxedConstraint, SearchPath, SemanticTag, ServiceUnavailable, SimilarToRequest,
+        SimilarToResponse, TagFilter, TagKind, TooManyRequests, Unauthorized, UpstreamState,
+        UpstreamStatus, UserUnauthorized, WhyNotOutcome, WhyNotResponse,
+    },
+    routes::{
+        api_routes, feed_routes, opds_routes, openapi_route, swagger_redirect_route, swagger_routes,
+    },
     services::{
-        neo4j::{BookNode, GraphRelationshipResponse, GraphResponse, GraphStats, Neo4jClient},
-        Pinecone, RecommendationService,
+        audit_log::AuditEntry,
+        author_profile::{AuthorProfile, SimilarAuthor},
+        book_patch::{BookPatch, PatchResult, PatchStatus},
+        book_versions::BookVersion,
+        bootstrap,
+        compare::{BookComparison, PairComparison},
+        covers::CoverProxy,
+        deadline,
+        digest::{Digest, DigestPick},
+        event_buffer::EventBuffer,
+        feature_flags::{FlagRule, FlagSet},
+        jobs::{Job, JobStatus},
+        load_shedding,
+        maintenance::{MaintenanceScheduler, MaintenanceTaskStatus},
+        neo4j::{BookNode, GraphRelationshipResponse, GraphResponse, GraphStats},
+        personalization::ContentPreferences,
+        prewarm::{ActivityTracker, PrewarmSchedule, PrewarmScheduler},
+        query_preloader::{PreloadedQuery, QueryPreloader},
+        ranking::Ranker,
+        session_store::SessionStore,
+        shadow::{RankMove, ShadowComparison, ShadowSummary},
+        slow_query_log::{SlowQueryEntry, UpstreamTimings},
+        supabase::{
+            EventType, QueryHistoryEntry, ShelfEntry, ShelfStatus, UserPreferences, UserRating,
+        },
+        templates::{QueryFilters, QueryPattern},
+        usage::{TenantUsage, UsageCounts, UsageQuota, USAGE_FLUSH_INTERVAL},
     },
+    telemetry::{self, QualityDay, QualityStats, TelemetrySnapshot, VariantStats},
 };
 use actix_cors::Cors;
-use actix_web::{middleware::Logger, web, App, HttpResponse, HttpServer};
+use actix_web::{dev::Service, middleware::Logger, web, App, HttpResponse, HttpServer};
 use log::{error, info, warn};
 use std::net::TcpListener;
+use std::sync::Arc;
 
 use utoipa::OpenApi;
 
@@ -21,12 +84,79 @@ use utoipa::OpenApi;
 #[openapi(
     paths(
         crate::handlers::health::health_check,
+        crate::handlers::health::readiness_check,
         crate::handlers::recommendations::get_recommendations,
+        crate::handlers::recommendations::get_for_you,
+        crate::handlers::recommendations::get_similar_to,
+        crate::handlers::recommendations::why_not,
+        crate::handlers::explanations::get_explanations,
+        crate::handlers::query::parse_query,
         crate::handlers::prewarm::prewarm,
+        crate::handlers::metrics::metrics,
+        crate::handlers::metrics::quality_metrics,
         crate::handlers::graph::get_book_graph,
         crate::handlers::graph::get_similar_books,
         crate::handlers::graph::search_books,
         crate::handlers::graph::get_graph_stats,
+        crate::handlers::admin::get_slow_queries,
+        crate::handlers::admin::clear_slow_queries,
+        crate::handlers::admin::clear_caches,
+        crate::handlers::admin::get_cache_status,
+        crate::handlers::admin::preload_queries,
+        crate::handlers::admin::get_audit_log,
+        crate::handlers::admin::get_indexer_status,
+        crate::handlers::admin::reload_synonyms,
+        crate::handlers::admin::get_hidden_books,
+        crate::handlers::admin::hide_book,
+        crate::handlers::admin::restore_book,
+        crate::handlers::admin::patch_books,
+        crate::handlers::admin::reembed_book,
+        crate::handlers::admin::enrich_books,
+        crate::handlers::admin::sync_graph,
+        crate::handlers::admin::list_jobs,
+        crate::handlers::admin::get_job,
+        crate::handlers::admin::cancel_job,
+        crate::handlers::admin::get_usage,
+        crate::handlers::admin::get_feature_flags,
+        crate::handlers::admin::get_shadow_report,
+        crate::handlers::shelves::get_shelves,
+        crate::handlers::shelves::get_shelf_entry,
+        crate::handlers::shelves::set_shelf,
+        crate::handlers::shelves::remove_from_shelf,
+        crate::handlers::ratings::get_ratings,
+        crate::handlers::ratings::get_rating,
+        crate::handlers::ratings::set_rating,
+        crate::handlers::ratings::remove_rating,
+        crate::handlers::history::get_history,
+        crate::handlers::history::get_session_history,
+        crate::handlers::events::record_events,
+        crate::handlers::preferences::get_preferences,
+        crate::handlers::preferences::set_preferences,
+        crate::handlers::preferences::remove_preferences,
+        crate::handlers::sessions::get_dismissed,
+        crate::handlers::sessions::dismiss_book,
+        crate::handlers::sessions::undismiss_book,
+        crate::handlers::digest::generate_digest,
+        crate::handlers::digest::latest_digest,
+        crate::handlers::catalog::get_catalog_stats,
+        crate::handlers::catalog::suggest_titles,
+        crate::handlers::books::get_book_json_ld,
+        crate::handlers::books::get_book_history,
+        crate::handlers::books::get_sitemap,
+        crate::handlers::books::compare_books,
+        crate::handlers::covers::get_cover,
+        crate::handlers::authors::get_author_profile,
+        crate::handlers::feeds::trending_feed,
+        crate::handlers::feeds::new_books_feed,
+        crate::handlers::opds::catalog_root,
+        crate::handlers::opds::popular_books,
+        crate::handlers::opds::new_books,
+        crate::handlers::opds::browse_authors,
+        crate::handlers::opds::browse_categories,
+        crate::handlers::opds::search,
+        crate::handlers::opds::opensearch_description,
+        crate::handlers::tools::list_tools,
+        crate::handlers::tools::call_tool,
     ),
     components(
         schemas(
@@ -37,15 +167,145 @@ use utoipa::OpenApi;
             GraphStats,
             RecommendationRequest,
             RecommendationResponse,
+            CacheStatus,
+            SearchPath,
+            SemanticTag,
+            TagFilter,
+            TagKind,
+            Refinement,
+            RefinementKind,
+            RelaxedConstraint,
+            ConversationState,
+            ForYouRequest,
+            ForYouResponse,
+            SimilarToRequest,
+            SimilarToResponse,
+            ExplanationsRequest,
+            ExplanationsResponse,
+            QueryParseRequest,
+            QueryParseResponse,
+            QueryParsedBy,
+            QueryPattern,
+            QueryFilters,
+            BookExplanation,
+            WhyNotResponse,
+            WhyNotOutcome,
             HealthResponse,
-            ErrorResponse
+            ReadinessResponse,
+            MaintenanceTaskStatus,
+            UpstreamStatus,
+            UpstreamState,
+            EndpointProbe,
+            EndpointCheck,
+            ErrorResponse,
+            ErrorCode,
+            TelemetrySnapshot,
+            VariantStats,
+            QualityStats,
+            QualityDay,
+            CacheStats,
+            CacheMemory,
+            ExperimentAssignment,
+            SlowQueriesResponse,
+            SlowQueryEntry,
+            UpstreamTimings,
+            AdminActionResponse,
+            CacheStatusResponse,
+            PreloadResponse,
+            PreloadedQuery,
+            AuditLogResponse,
+            AuditEntry,
+            IndexProgress,
+            IndexRunState,
+            SynonymsReloadResponse,
+            HideBookRequest,
+            BookVisibilityResponse,
+            HiddenBooksResponse,
+            BookPatchRequest,
+            BookPatch,
+            BookPatchResponse,
+            BookReembedResponse,
+            EnrichBooksRequest,
+            JobSubmittedResponse,
+            JobsResponse,
+            JobCancelResponse,
+            Job,
+            JobStatus,
+            UsageResponse,
+            TenantUsage,
+            FeatureFlagsResponse,
+            FlagRule,
+            FlagSet,
+            ShadowReport,
+            ShadowSummary,
+            ShadowComparison,
+            RankMove,
+            Ranker,
+            UsageCounts,
+            UsageQuota,
+            PatchResult,
+            PatchStatus,
+            ShelfResponse,
+            ShelfEntry,
+            ShelfStatus,
+            SetShelfRequest,
+            RatingsResponse,
+            UserRating,
+            SetRatingRequest,
+            QueryHistoryResponse,
+            DismissedBooksResponse,
+            ContentPreferences,
+            UserPreferences,
+            EventBatch,
+            EventPayload,
+            EventsAccepted,
+            EventType,
+            DigestRequest,
+            Digest,
+            DigestPick,
+            BookComparison,
+            PairComparison,
+            BookHistoryResponse,
+            BookVersion,
+            AuthorProfile,
+            SimilarAuthor,
+            CorpusSummary,
+            NameCount,
+            TitleSuggestionsResponse,
+            TitleEntry,
+            QueryHistoryEntry,
+            ToolsResponse,
+            ToolDefinition,
+            ToolCallRequest,
+            ToolCallResponse
+        ),
+        responses(
+            Unauthorized,
+            UserUnauthorized,
+            InternalServerError,
+            BadGateway,
+            ServiceUnavailable,
+            TooManyRequests
         )
     ),
     tags(
         (name = "Health", description = "Health check endpoints"),
         (name = "Recommendations", description = "Book recommendation endpoints"),
         (name = "Graph", description = "Book relationship graph endpoints"),
-        (name = "System", description = "System management endpoints for performance optimization")
+        (name = "System", description = "System management endpoints for performance optimization"),
+        (name = "Admin", description = "Operational endpoints protected by the X-Admin-Key header"),
+        (name = "Shelves", description = "Per-user reading shelves (want to read, reading, read)"),
+        (name = "Ratings", description = "Per-user book ratings, used to personalize recommendations"),
+        (name = "History", description = "Recent searches per user or anonymous session"),
+        (name = "Preferences", description = "Per-user content filters applied to recommendations"),
+        (name = "Events", description = "Anonymous impression, click and shelving analytics"),
+        (name = "Sessions", description = "Books dismissed with \"don't show again\" in an anonymous session"),
+        (name = "Digest", description = "Weekly personalized picks for external mailers"),
+        (name = "Catalog", description = "Catalog statistics, title suggestions and author profiles from the indexer's snapshot"),
+        (name = "Books", description = "Structured data, a sitemap, comparisons and cover images for server-rendered book pages"),
+        (name = "Feeds", description = "Atom feeds of trending and newly indexed books"),
+        (name = "OPDS", description = "OPDS 1.2 catalog for e-reader apps"),
+        (name = "Tools", description = "Recommendation and graph search as tools for LLM agents")
     ),
     info(
         title = "Book Recommendation API with Graph Database",
@@ -95,59 +355,13 @@ impl Application {
 
     pub async fn run_with_listener(&self, listener: TcpListener) -> Result<()> {
         info!("Initializing services with optimized cold start configuration");
+        telemetry::init();
 
         // Initialize service dependencies concurrently to reduce startup time
         let (pinecone_result, sentence_encoder_result, neo4j_result) = tokio::join!(
-            // Initialize Pinecone client asynchronously with timeout protection
-            async {
-                let pinecone_future = Pinecone::new(
-                    &self.config.pinecone_api_key,
-                    &self.config.pinecone_environment,
-                    &self.config.pinecone_index,
-                );
-
-                match tokio::time::timeout(std::time::Duration::from_secs(30), pinecone_future)
-                    .await
-                {
-                    Ok(result) => result,
-                    Err(_) => {
-                        warn!("Pinecone initialization timed out after 30s. Will retry on first request");
-                        Pinecone::new_with_lazy_init(
-                            &self.config.pinecone_api_key,
-                            &self.config.pinecone_environment,
-                            &self.config.pinecone_index,
-                        )
-                    }
-                }
-            },
-            // Initialize ML model with timeout protection
-            async {
-                let encoder_future = HuggingFaceEmbedder::new();
-                match tokio::time::timeout(std::time::Duration::from_secs(30), encoder_future).await
-                {
-                    Ok(result) => result,
-                    Err(_) => {
-                        warn!("HuggingFace initialization timed out after 30s. Will retry on first request");
-                        HuggingFaceEmbedder::new_with_deferred_init()
-                    }
-                }
-            },
-            // Initialize Neo4j client
-            async {
-                if let (Some(uri), Some(user), Some(password)) = (
-                    &self.config.neo4j_uri,
-                    &self.config.neo4j_user,
-                    &self.config.neo4j_password,
-                ) {
-                    info!("Initializing Neo4j client...");
-                    Neo4jClient::new(uri, user, password).await
-                } else {
-                    warn!("Neo4j configuration not found, graph endpoints will be unavailable");
-                    Err(crate::error::ApiError::ExternalServiceError(
-                        "Neo4j not configured".to_string(),
-                    ))
-                }
-            }
+            bootstrap::init_pinecone(&self.config),
+            bootstrap::init_embedder(),
+            bootstrap::init_neo4j(&self.config)
         );
 
         // Handle initialization results
@@ -169,20 +383,104 @@ impl Application {
             }
         };
 
-        // Create shareable recommendation service with optimized configuration
-        let recommendation_service =
-            web::Data::new(RecommendationService::new(sentence_encoder, pinecone));
+        // User data and the admin audit log are persisted to Supabase when configured,
+        // in memory otherwise
+        let supabase = bootstrap::init_supabase(&self.config).await;
+        let audit_log = bootstrap::init_audit_log(&supabase).await;
+        let audit_log_data = web::Data::new(audit_log);
+        let book_history_data = web::Data::new(bootstrap::init_book_history(&supabase).await);
+
+        // Create shareable recommendation service with optimized configuration.
+        // User ratings personalize results, using the graph to find related books when available
+        let mut recommendation_service =
+            bootstrap::recommendation_service(&self.config, sentence_encoder, pinecone)
+                .with_user_data(supabase.clone());
+        if let Some(neo4j) = &neo4j_data {
+            recommendation_service = recommendation_service.with_graph(neo4j.get_ref().clone());
+        }
+        // Embeddings, and results unless Redis holds them, are kept on disk when configured
+        let disk_cache = bootstrap::init_disk_cache(&self.config);
+        if let Some(disk_cache) = &disk_cache {
+            persist_embeddings(disk_cache.clone());
+        }
+        // Results are shared with other instances through Redis when configured
+        if let Some(shared_cache) = bootstrap::init_shared_cache(&self.config)
+            .await
+            .or(disk_cache)
+        {
+            recommendation_service = recommendation_service.with_shared_cache(shared_cache);
+        }
+        // Flag rules are refreshed from the remote provider when one is configured
+        tokio::spawn(recommendation_service.feature_flags().clone().run());
+        let recommendation_service = web::Data::new(recommendation_service);
+        let config_data = web::Data::new(self.config.clone());
+        // Analytics events are buffered in memory and written in the background
+        let event_buffer = EventBuffer::new(supabase.clone());
+        tokio::spawn(event_buffer.clone().run(std::time::Duration::from_secs(
+            self.config.events_flush_interval_secs,
+        )));
+        let event_buffer_data = web::Data::new(event_buffer.clone());
+        let supabase_data = web::Data::new(supabase);
+        let session_store_data = web::Data::new(SessionStore::new(self.config.session_ttl_minutes));
+        let cover_proxy_data = web::Data::new(CoverProxy::new()?);
+
+        // Long-running work runs as background jobs admins can follow and cancel
+        let job_queue = bootstrap::init_job_queue(&supabase_data).await;
+        let job_queue_data = web::Data::new(job_queue.clone());
 
         // Start background prewarmer in non-blocking way
-        let rs_clone = recommendation_service.clone();
-        tokio::spawn(async move {
-            info!("Starting background prewarm process");
-            if let Err(e) = rs_clone.prewarm().await {
-                warn!("Background prewarming encountered an error: {}", e);
-            } else {
-                info!("Background prewarming completed successfully");
-            }
-        });
+        let prewarm_job =
+            bootstrap::queue_prewarm(recommendation_service.clone().into_inner(), &job_queue).await;
+        if let Err(e) = prewarm_job {
+            warn!("Failed to queue the background prewarm: {}", e);
+        }
+
+        // Re-warm the caches on a schedule and before the host's idle timeout, then
+        // preload the most frequent recent queries once the server is quiet
+        let query_preloader = QueryPreloader::new(
+            recommendation_service.clone().into_inner(),
+            supabase_data.get_ref().clone(),
+            self.config.preload_query_count,
+            self.config.preload_window_hours,
+        );
+        let query_preloader_data = web::Data::new(query_preloader.clone());
+        let activity = ActivityTracker::new();
+        let mut prewarm_scheduler = PrewarmScheduler::new(
+            recommendation_service.clone().into_inner(),
+            PrewarmSchedule::new(
+                self.config.prewarm_interval_minutes,
+                self.config.idle_timeout_minutes,
+            ),
+            activity.clone(),
+        )
+        .with_preloader(query_preloader);
+        if let Some(url) = &self.config.keepalive_url {
+            prewarm_scheduler = prewarm_scheduler.with_keepalive_url(url.clone());
+        }
+        tokio::spawn(prewarm_scheduler.run());
+
+        // Housekeeping on the intervals of the [maintenance] table
+        let maintenance = MaintenanceScheduler::new(
+            recommendation_service.clone().into_inner(),
+            self.config.maintenance.clone(),
+            job_queue.clone(),
+            book_history_data.get_ref().clone(),
+            session_store_data.get_ref().clone(),
+        )?
+        .with_preloader(query_preloader_data.get_ref().clone());
+        let maintenance_status_data = web::Data::new(maintenance.status());
+        tokio::spawn(maintenance.run());
+
+        // Requests with a tenant's X-Api-Key are metered against its monthly quotas
+        let usage_meter = bootstrap::init_usage_meter(&self.config, &supabase_data).await;
+        tokio::spawn(usage_meter.clone().run(USAGE_FLUSH_INTERVAL));
+        let usage_meter_data = web::Data::new(usage_meter);
+
+        let server = self.config.server.clone();
+        info!("Starting {} workers", server.worker_count());
+        let request_deadline = server.request_deadline();
+        let deadline_paths = Arc::new(server.deadline_paths.clone());
+        let shed_exempt_paths = Arc::new(server.shed_exempt_paths.clone());
 
         // Create a new HTTP server with optimized configuration
         HttpServer::new(move || {
@@ -203,8 +501,11 @@ impl Application {
                         "Authorization",
                         "X-Requested-With",
                         "X-Prewarm-Source",
+                        "X-Admin-Key",
+                        "X-Admin-Actor",
+                        "X-Api-Key",
                     ])
-                    .expose_headers(vec!["content-disposition", "Content-Length"])
+                    .expose_headers(vec!["content-disposition", "Content-Length", "Retry-After"])
                     .supports_credentials()
                     .max_age(3600)
             } else {
@@ -217,8 +518,11 @@ impl Application {
                         "Authorization",
                         "X-Requested-With",
                         "X-Prewarm-Source",
+                        "X-Admin-Key",
+                        "X-Admin-Actor",
+                        "X-Api-Key",
                     ])
-                    .expose_headers(vec!["content-disposition", "Content-Length"])
+                    .expose_headers(vec!["content-disposition", "Content-Length", "Retry-After"])
                     .max_age(3600)
             };
 
@@ -230,31 +534,83 @@ impl Application {
                 .wrap(cors)
                 // Use a more informative logger format for better debugging
                 .wrap(Logger::new("%r %s %b %{User-Agent}i %D ms"))
-                // Add request payload limit
-                .app_data(web::JsonConfig::default().limit(1024 * 1024)) // 1MB limit
-                // Add increased timeout for cold starts
-                .app_data(web::Data::new(
-                    web::JsonConfig::default()
-                        .limit(1024 * 1024)
-                        .error_handler(|err, _req| {
-                            error!("JSON payload error: {}", err);
-                            let err_str = err.to_string();
-                            actix_web::error::InternalError::from_response(
-                                err,
-                                HttpResponse::BadRequest()
-                                    .content_type("application/json")
-                                    .json(serde_json::json!({"error": err_str})),
-                            )
-                            .into()
-                        }),
+                // Track inbound requests so the prewarm scheduler knows when the server is idle
+                .wrap_fn({
+                    let activity = activity.clone();
+                    move |req, srv| {
+                        activity.touch();
+                        srv.call(req)
+                    }
+                })
+                // Bound the upstream calls and retries each recommendation request makes by its deadline
+                .wrap_fn({
+                    let deadline_paths = deadline_paths.clone();
+                    move |req, srv| {
+                        let budget = request_deadline.filter(|_| {
+                            deadline_paths
+                                .iter()
+                                .any(|prefix| req.path().starts_with(prefix.as_str()))
+                        });
+                        let response = srv.call(req);
+                        async move {
+                            match budget {
+                                Some(budget) => deadline::within(budget, response).await,
+                                None => response.await,
+                            }
+                        }
+                    }
+                })
+                // Let requests off the exempt paths be shed while too many queries are queued
+                .wrap_fn({
+                    let exempt_paths = shed_exempt_paths.clone();
+                    move |req, srv| {
+                        let exempt = exempt_paths
+                            .iter()
+                            .any(|prefix| req.path().starts_with(prefix.as_str()));
+                        let response = srv.call(req);
+                        async move {
+                            if exempt {
+                                response.await
+                            } else {
+                                load_shedding::sheddable(response).await
+                            }
+                        }
+                    }
+                })
+                // Add request payload limit (1MB); malformed bodies and query strings get
+                // the same JSON error body as every other error
+                .app_data(web::JsonConfig::default().limit(1024 * 1024).error_handler(
+                    |err, _req| {
+                        error!("JSON payload error: {}", err);
+                        ApiError::InvalidInput(err.to_string()).into()
+                    },
                 ))
+                .app_data(
+                    web::QueryConfig::default()
+                        .error_handler(|err, _req| ApiError::InvalidInput(err.to_string()).into()),
+                )
                 .app_data(recommendation_service.clone())
+                .app_data(config_data.clone())
+                .app_data(audit_log_data.clone())
+                .app_data(book_history_data.clone())
+                .app_data(supabase_data.clone())
+                .app_data(session_store_data.clone())
+                .app_data(cover_proxy_data.clone())
+                .app_data(event_buffer_data.clone())
+                .app_data(query_preloader_data.clone())
+                .app_data(job_queue_data.clone())
+                .app_data(maintenance_status_data.clone())
+                .app_data(usage_meter_data.clone())
                 // Enable compression for responses
                 .wrap(actix_web::middleware::Compress::default())
                 // Add path normalization without affecting trailing slashes (for Swagger UI compatibility)
                 .wrap(actix_web::middleware::NormalizePath::new(
                     actix_web::middleware::TrailingSlash::MergeOnly,
                 ))
+                // Identify tenants by their X-Api-Key and count their requests
+                .wrap(Metering)
+                // Convert handler panics into JSON 500s instead of dropped connections
+                .wrap(CatchPanic)
                 // Add security headers (CORS is handled by the CORS middleware above)
                 .wrap(
                     actix_web::middleware::DefaultHeaders::new()
@@ -267,7 +623,9 @@ impl Application {
                 .service(swagger_ui)
                 .service(openapi_route())
                 .service(swagger_redirect_route())
-                .service(api_routes());
+                .service(api_routes())
+                .service(feed_routes())
+                .service(opds_routes());
 
             // Add Neo4j data if available
             if let Some(neo4j) = &neo4j_data {
@@ -277,21 +635,67 @@ impl Application {
             app
         })
         .listen(listener)?
-        // Configure server with worker settings for better performance
-        .workers(std::cmp::max(2, num_cpus::get()))
-        // Add backlog configuration for better connection handling
-        .backlog(2048)
-        // Increase max connection rate for better performance under load
-        .max_connection_rate(256)
-        // Add keep-alive timeout for better connection reuse
-        .keep_alive(std::time::Duration::from_secs(75))
-        // Add client timeout to prevent requests from hanging indefinitely
-        .client_request_timeout(std::time::Duration::from_secs(60))
-        // Set graceful shutdown timeout
-        .shutdown_timeout(5)
+        .workers(server.worker_count())
+        .backlog(server.backlog)
+        .max_connection_rate(server.max_connection_rate)
+        .keep_alive(std::time::Duration::from_secs(server.keep_alive_secs))
+        // Keep clients from holding a worker with a request that never arrives
+        .client_request_timeout(std::time::Duration::from_secs(
+            server.client_request_timeout_secs,
+        ))
+        .shutdown_timeout(server.shutdown_timeout_secs)
         .run()
         .await?;
 
+        // Write out events that arrived since the last background flush
+        event_buffer.flush().await;
+
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use utoipa::openapi::{RefOr, Response};
+
+    #[test]
+    fn test_error_responses_have_bodies() {
+        let doc = ApiDoc::openapi();
+        let components = doc.components.expect("components");
+
+        for (path, item) in &doc.paths.paths {
+            let operations = [&item.get, &item.post, &item.put, &item.delete];
+            for operation in operations.into_iter().flatten() {
+                for (status, response) in &operation.responses.responses {
+                    if !status.starts_with('4') && !status.starts_with('5') {
+                        continue;
+                    }
+                    let response: &Response = match response {
+                        RefOr::T(response) => response,
+                        RefOr::Ref(reference) => {
+                            let name = reference
+                                .ref_location
+                                .rsplit('/')
+                                .next()
+                                .unwrap_or_default();
+                            match components.responses.get(name) {
+                                Some(RefOr::T(response)) => response,
+                                _ => panic!(
+                                    "{} {} refers to a missing response {}",
+                                    path, status, name
+                                ),
+                            }
+                        }
+                    };
+                    assert!(
+                        !response.content.is_empty(),
+                        "{} {} has no body",
+                        path,
+                        status
+                    );
+                }
+            }
+        }
+    }
+}
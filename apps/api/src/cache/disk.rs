@@ -0,0 +1,206 @@
+//! On-disk backend for [`SharedCache`], enabled with the `sled` feature
+//!
+//! Keeps cached results and embeddings in a directory that outlives the
+//! process, so an instance restarted on a persistent disk answers its first
+//! queries from the entries the previous process left behind. Entries are only
+//! read back as they're asked for, so opening the cache doesn't slow startup.
+//! Expired entries are swept in the background on open and every
+//! [`SWEEP_INTERVAL`] after, so results of one-off queries don't pile up.
+//! Reads and writes block on disk, so they run on tokio's blocking pool.
+
+use super::SharedCache;
+use crate::error::{ApiError, Result};
+use futures::future::{BoxFuture, FutureExt};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+/// How often expired entries are dropped from disk
+pub const SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Shared cache stored in a sled database; errors are logged and treated as misses
+///
+/// Each value is stored after the Unix time it expires at, as 8 big-endian bytes.
+#[derive(Clone)]
+pub struct DiskCache {
+    db: sled::Db,
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// The value of a stored entry, unless it has expired
+fn unexpired(stored: &[u8], now: u64) -> Option<&[u8]> {
+    let (expires_at, value) = stored.split_first_chunk::<8>()?;
+    (u64::from_be_bytes(*expires_at) > now).then_some(value)
+}
+
+/// Run `op` against `db` on the blocking pool, or None if the task panicked
+async fn blocking<T: Send + 'static>(
+    db: &sled::Db,
+    op: impl FnOnce(sled::Db) -> T + Send + 'static,
+) -> Option<T> {
+    let db = db.clone();
+    match tokio::task::spawn_blocking(move || op(db)).await {
+        Ok(result) => Some(result),
+        Err(e) => {
+            warn!("Disk cache task failed: {}", e);
+            None
+        }
+    }
+}
+
+impl DiskCache {
+    /// Open or create the cache in `dir`, keeping up to `memory_bytes` of it in
+    /// memory, and drop expired entries in the background now and every [`SWEEP_INTERVAL`]
+    pub fn open(dir: &Path, memory_bytes: u64) -> Result<Self> {
+        let cache = Self::at(dir, memory_bytes)?;
+        let sweeper = cache.clone();
+        std::thread::spawn(move || loop {
+            let removed = sweeper.remove_expired();
+            if removed > 0 {
+                info!("Dropped {} expired disk cache entries", removed);
+            }
+            std::thread::sleep(SWEEP_INTERVAL);
+        });
+        Ok(cache)
+    }
+
+    fn at(dir: &Path, memory_bytes: u64) -> Result<Self> {
+        let db = sled::Config::new()
+            .path(dir)
+            .cache_capacity(memory_bytes)
+            .open()
+            .map_err(|e| {
+                ApiError::InternalError(format!(
+                    "Failed to open the disk cache at {}: {}",
+                    dir.display(),
+                    e
+                ))
+            })?;
+        Ok(Self { db })
+    }
+
+    fn remove_expired(&self) -> usize {
+        let now = now_secs();
+        let mut removed = 0;
+        for entry in self.db.iter() {
+            let Ok((key, stored)) = entry else { continue };
+            if unexpired(&stored, now).is_none() && self.db.remove(key).is_ok() {
+                removed += 1;
+            }
+        }
+        removed
+    }
+}
+
+impl SharedCache for DiskCache {
+    fn get(&self, key: &str) -> BoxFuture<'_, Option<Vec<u8>>> {
+        let key = key.to_string();
+        blocking(&self.db, move |db| {
+            let stored = match db.get(&key) {
+                Ok(stored) => stored?,
+                Err(e) => {
+                    warn!("Disk cache read of {} failed: {}", key, e);
+                    return None;
+                }
+            };
+            match unexpired(&stored, now_secs()) {
+                Some(value) => Some(value.to_vec()),
+                None => {
+                    let _ = db.remove(&key);
+                    None
+                }
+            }
+        })
+        .map(Option::flatten)
+        .boxed()
+    }
+
+    fn set(&self, key: &str, value: Vec<u8>, ttl: Duration) -> BoxFuture<'_, ()> {
+        let key = key.to_string();
+        blocking(&self.db, move |db| {
+            let expires_at = now_secs().saturating_add(ttl.as_secs().max(1));
+            let mut stored = Vec::with_capacity(8 + value.len());
+            stored.extend_from_slice(&expires_at.to_be_bytes());
+            stored.extend_from_slice(&value);
+            if let Err(e) = db.insert(&key, stored) {
+                warn!("Disk cache write of {} failed: {}", key, e);
+            }
+        })
+        .map(drop)
+        .boxed()
+    }
+
+    fn delete(&self, key: &str) -> BoxFuture<'_, ()> {
+        let key = key.to_string();
+        blocking(&self.db, move |db| {
+            if let Err(e) = db.remove(&key) {
+                warn!("Disk cache delete of {} failed: {}", key, e);
+            }
+        })
+        .map(drop)
+        .boxed()
+    }
+
+    fn clear(&self, prefix: &str) -> BoxFuture<'_, ()> {
+        let prefix = prefix.to_string();
+        blocking(&self.db, move |db| {
+            for entry in db.scan_prefix(&prefix) {
+                let removed = entry.and_then(|(key, _)| db.remove(key));
+                if let Err(e) = removed {
+                    warn!("Disk cache clear of {} failed: {}", prefix, e);
+                    return;
+                }
+            }
+        })
+        .map(drop)
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_entries_outlive_the_process_until_they_expire() {
+        let dir = std::env::temp_dir().join(format!("rab-disk-cache-{}", std::process::id()));
+        {
+            let cache = DiskCache::at(&dir, 1024 * 1024).unwrap();
+            cache
+                .set("results:dune", b"[1]".to_vec(), Duration::from_secs(60))
+                .await;
+            cache
+                .set("results:emma", b"[2]".to_vec(), Duration::from_secs(60))
+                .await;
+            cache
+                .set("embeddings:dune", b"[3]".to_vec(), Duration::from_secs(60))
+                .await;
+            cache.db.flush().unwrap();
+        }
+
+        let cache = DiskCache::at(&dir, 1024 * 1024).unwrap();
+        assert_eq!(cache.get("results:dune").await, Some(b"[1]".to_vec()));
+        cache.clear("results:").await;
+        assert_eq!(cache.get("results:emma").await, None);
+        assert_eq!(cache.get("embeddings:dune").await, Some(b"[3]".to_vec()));
+
+        // An entry stored by an earlier process that has since expired
+        let mut stored = (now_secs() - 1).to_be_bytes().to_vec();
+        stored.extend_from_slice(b"[4]");
+        cache.db.insert("results:stale", stored.clone()).unwrap();
+        assert_eq!(cache.get("results:stale").await, None);
+        assert!(!cache.db.contains_key("results:stale").unwrap());
+
+        cache.db.insert("results:stale", stored).unwrap();
+        assert_eq!(cache.remove_expired(), 1);
+        assert!(cache.get("embeddings:dune").await.is_some());
+
+        drop(cache);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,618 @@
+//! In-process caches with expiry, LRU eviction and hit/miss counters
+//!
+//! Every service cache is a [`TtlCache`]: entries expire after a TTL, the least
+//! recently used entry is evicted when the cache is full, and counters for hits,
+//! misses, evictions and expirations are reported under the cache's name in
+//! `/api/metrics`. Caches are backed by [`moka`], whose reads take no lock, so
+//! concurrent requests don't wait on each other or on writers.
+//!
+//! Entries are also weighed by their approximate size in bytes ([`CacheWeight`]).
+//! Capacities only bound the number of entries, so every cache also counts
+//! against one process-wide memory budget: when the caches together hold more
+//! than [`set_memory_budget`] allows, the oldest entries of the largest cache
+//! are evicted until they're back under it.
+//!
+//! A cache can also be backed by a [`SharedCache`] such as Redis, so instances
+//! behind a load balancer share results: local misses fall through to the shared
+//! backend, and inserts are written to both. A [`SharedCache`] on disk instead
+//! keeps entries across restarts of a single instance.
+
+#[cfg(feature = "sled")]
+pub mod disk;
+#[cfg(feature = "redis")]
+pub mod redis;
+
+use crate::error::recover_lock;
+use futures::future::BoxFuture;
+use moka::notification::RemovalCause;
+use moka::policy::EvictionPolicy;
+use moka::sync::Cache;
+use moka::Expiry;
+use serde::{de::DeserializeOwned, Serialize};
+use std::fmt::Display;
+use std::hash::Hash;
+use std::mem::size_of;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, RwLock, Weak};
+use std::time::{Duration, Instant};
+use tracing::warn;
+use utoipa::ToSchema;
+
+lazy_static::lazy_static! {
+    static ref REGISTRY: RwLock<Vec<Weak<dyn StatsSource>>> = RwLock::new(Vec::new());
+}
+
+/// Approximate bytes held by the entries of every cache
+static MEMORY_USED: AtomicU64 = AtomicU64::new(0);
+/// Most bytes the caches may hold together; 0 for no limit
+static MEMORY_BUDGET: AtomicU64 = AtomicU64::new(0);
+/// Set while the caches are being shrunk, so concurrent inserts don't shrink them too
+static SHRINKING: AtomicBool = AtomicBool::new(false);
+
+/// Default megabytes of the on-disk cache kept in memory
+pub const DEFAULT_DISK_CACHE_MEMORY_MB: u64 = 64;
+
+/// Bookkeeping of an entry beyond its key and value, such as moka's access order
+const ENTRY_OVERHEAD: usize = 64;
+
+/// Approximate memory held by a cache key or value
+pub trait CacheWeight {
+    /// Bytes owned on the heap, not counting the value's own `size_of`
+    fn heap_size(&self) -> usize;
+}
+
+macro_rules! no_heap_weight {
+    ($($ty:ty),*) => {
+        $(impl CacheWeight for $ty {
+            fn heap_size(&self) -> usize {
+                0
+            }
+        })*
+    };
+}
+
+no_heap_weight!(bool, u32, u64, usize, i32, i64, f32, f64);
+
+impl CacheWeight for String {
+    fn heap_size(&self) -> usize {
+        self.capacity()
+    }
+}
+
+impl<T: CacheWeight> CacheWeight for Vec<T> {
+    fn heap_size(&self) -> usize {
+        self.capacity() * size_of::<T>() + self.iter().map(T::heap_size).sum::<usize>()
+    }
+}
+
+impl<T: CacheWeight> CacheWeight for Option<T> {
+    fn heap_size(&self) -> usize {
+        self.as_ref().map_or(0, T::heap_size)
+    }
+}
+
+/// Counts the whole pointee, though other caches or requests may share it
+impl<T: CacheWeight> CacheWeight for Arc<T> {
+    fn heap_size(&self) -> usize {
+        size_of::<T>() + self.as_ref().heap_size()
+    }
+}
+
+impl<A: CacheWeight, B: CacheWeight> CacheWeight for (A, B) {
+    fn heap_size(&self) -> usize {
+        self.0.heap_size() + self.1.heap_size()
+    }
+}
+
+/// Let the caches hold at most about `bytes` together; 0 removes the limit
+pub fn set_memory_budget(bytes: u64) {
+    MEMORY_BUDGET.store(bytes, Ordering::Relaxed);
+    enforce_memory_budget();
+}
+
+/// Approximate memory held by the caches, as reported by `/api/metrics`
+#[derive(Debug, Clone, Default, serde::Serialize, ToSchema)]
+pub struct CacheMemory {
+    /// Approximate bytes held by the entries of every cache
+    #[schema(example = 48_000_000)]
+    pub used_bytes: u64,
+    /// Most bytes the caches may hold together; 0 for no limit
+    #[schema(example = 134_217_728)]
+    pub budget_bytes: u64,
+}
+
+pub fn memory_usage() -> CacheMemory {
+    CacheMemory {
+        used_bytes: MEMORY_USED.load(Ordering::Relaxed),
+        budget_bytes: MEMORY_BUDGET.load(Ordering::Relaxed),
+    }
+}
+
+fn live_sources() -> Vec<Arc<dyn StatsSource>> {
+    let mut registry = recover_lock(REGISTRY.write(), "cache registry");
+    registry.retain(|source| source.strong_count() > 0);
+    registry.iter().filter_map(Weak::upgrade).collect()
+}
+
+/// Evict entries until the caches are back under 90% of the budget, so the
+/// next inserts don't each have to evict again
+fn enforce_memory_budget() {
+    let budget = MEMORY_BUDGET.load(Ordering::Relaxed);
+    if budget == 0 || MEMORY_USED.load(Ordering::Relaxed) <= budget {
+        return;
+    }
+    if SHRINKING.swap(true, Ordering::Acquire) {
+        return;
+    }
+
+    let sources = live_sources();
+    // Entries past their capacity or TTL may not have been dropped yet
+    for source in &sources {
+        source.purge();
+    }
+    let target = budget - budget / 10;
+    loop {
+        let used = MEMORY_USED.load(Ordering::Relaxed);
+        if used <= target {
+            break;
+        }
+        let Some(largest) = sources.iter().max_by_key(|source| source.bytes()) else {
+            break;
+        };
+        if largest.shrink(used - target) == 0 {
+            break;
+        }
+    }
+    SHRINKING.store(false, Ordering::Release);
+}
+
+/// Counters for one cache, as reported by `/api/metrics`
+#[derive(Debug, Clone, Default, serde::Serialize, ToSchema)]
+pub struct CacheStats {
+    #[schema(example = "recommendation_results")]
+    pub name: String,
+    /// Fresh entries
+    #[schema(example = 37)]
+    pub entries: usize,
+    #[schema(example = 512)]
+    pub capacity: usize,
+    #[schema(example = 1200)]
+    pub hits: u64,
+    #[schema(example = 300)]
+    pub misses: u64,
+    /// Entries dropped to make room for new ones
+    #[schema(example = 12)]
+    pub evictions: u64,
+    /// Entries dropped because they outlived their TTL
+    #[schema(example = 40)]
+    pub expirations: u64,
+    /// Approximate bytes held by the entries
+    #[schema(example = 2_400_000)]
+    pub bytes: u64,
+}
+
+/// Stats of every live cache, sorted by name
+pub fn all_stats() -> Vec<CacheStats> {
+    let mut stats: Vec<CacheStats> = live_sources().iter().map(|source| source.stats()).collect();
+    stats.sort_by(|a, b| a.name.cmp(&b.name));
+    stats
+}
+
+/// Drop the expired entries of every live cache; returns how many were dropped
+///
+/// Expired entries are otherwise only removed as their cache is written to, so
+/// a cache nobody writes keeps holding its stale values.
+pub fn purge_expired() -> u64 {
+    live_sources().iter().map(|source| source.purge()).sum()
+}
+
+trait StatsSource: Send + Sync {
+    fn stats(&self) -> CacheStats;
+    /// Apply pending evictions and expirations; returns how many entries expired
+    fn purge(&self) -> u64;
+    /// Approximate bytes held by the entries
+    fn bytes(&self) -> u64;
+    /// Evict the oldest entries until about `bytes` are freed; returns the bytes freed
+    fn shrink(&self, bytes: u64) -> u64;
+}
+
+/// A cache shared between instances, storing serialized values under string keys
+pub trait SharedCache: Send + Sync {
+    fn get(&self, key: &str) -> BoxFuture<'_, Option<Vec<u8>>>;
+    fn set(&self, key: &str, value: Vec<u8>, ttl: Duration) -> BoxFuture<'_, ()>;
+    fn delete(&self, key: &str) -> BoxFuture<'_, ()>;
+    /// Delete every key starting with `prefix`
+    fn clear(&self, prefix: &str) -> BoxFuture<'_, ()>;
+}
+
+#[derive(Clone)]
+struct Entry<V> {
+    value: V,
+    ttl: Duration,
+    /// Approximate bytes held by the entry, key included
+    weight: u64,
+    inserted_at: Instant,
+}
+
+/// Expires each entry after its own TTL, restarting it when the entry is replaced
+struct EntryTtl;
+
+impl<K, V> Expiry<K, Entry<V>> for EntryTtl {
+    fn expire_after_create(
+        &self,
+        _key: &K,
+        entry: &Entry<V>,
+        _created_at: Instant,
+    ) -> Option<Duration> {
+        Some(entry.ttl)
+    }
+
+    fn expire_after_update(
+        &self,
+        _key: &K,
+        entry: &Entry<V>,
+        _updated_at: Instant,
+        _duration_until_expiry: Option<Duration>,
+    ) -> Option<Duration> {
+        Some(entry.ttl)
+    }
+}
+
+#[derive(Default)]
+struct Counters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+    expirations: AtomicU64,
+    bytes: AtomicU64,
+}
+
+impl Counters {
+    fn release(&self, weight: u64) {
+        self.bytes.fetch_sub(weight, Ordering::Relaxed);
+        MEMORY_USED.fetch_sub(weight, Ordering::Relaxed);
+    }
+}
+
+struct Inner<K, V> {
+    name: &'static str,
+    entries: Cache<K, Entry<V>>,
+    capacity: usize,
+    counters: Arc<Counters>,
+}
+
+impl<K, V> StatsSource for Inner<K, V>
+where
+    K: Hash + Eq + CacheWeight + Send + Sync + 'static,
+    V: Clone + CacheWeight + Send + Sync + 'static,
+{
+    fn stats(&self) -> CacheStats {
+        // Apply pending evictions and expirations so the count is exact
+        self.entries.run_pending_tasks();
+        CacheStats {
+            name: self.name.to_string(),
+            entries: self.entries.entry_count() as usize,
+            capacity: self.capacity,
+            hits: self.counters.hits.load(Ordering::Relaxed),
+            misses: self.counters.misses.load(Ordering::Relaxed),
+            evictions: self.counters.evictions.load(Ordering::Relaxed),
+            expirations: self.counters.expirations.load(Ordering::Relaxed),
+            bytes: self.bytes(),
+        }
+    }
+
+    fn purge(&self) -> u64 {
+        let before = self.counters.expirations.load(Ordering::Relaxed);
+        self.entries.run_pending_tasks();
+        self.counters
+            .expirations
+            .load(Ordering::Relaxed)
+            .saturating_sub(before)
+    }
+
+    fn bytes(&self) -> u64 {
+        self.counters.bytes.load(Ordering::Relaxed)
+    }
+
+    /// Entries mostly share their cache's TTL, so the oldest are the closest to expiring
+    fn shrink(&self, bytes: u64) -> u64 {
+        let mut entries: Vec<(Arc<K>, Instant)> = self
+            .entries
+            .iter()
+            .map(|(key, entry)| (key, entry.inserted_at))
+            .collect();
+        entries.sort_unstable_by_key(|&(_, inserted_at)| inserted_at);
+
+        let mut freed = 0;
+        for (key, _) in entries {
+            if freed >= bytes {
+                break;
+            }
+            if let Some(entry) = self.entries.remove(key.as_ref()) {
+                freed += entry.weight;
+                self.counters.evictions.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        freed
+    }
+}
+
+/// Concurrent LRU cache whose entries expire after a TTL
+pub struct TtlCache<K, V> {
+    inner: Arc<Inner<K, V>>,
+    ttl: Duration,
+    shared: Option<Arc<dyn SharedCache>>,
+}
+
+impl<K, V> Clone for TtlCache<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            ttl: self.ttl,
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<K, V> TtlCache<K, V>
+where
+    K: Hash + Eq + CacheWeight + Send + Sync + 'static,
+    V: Clone + CacheWeight + Send + Sync + 'static,
+{
+    /// Cache holding up to `capacity` entries for `ttl`, reported under `name`
+    pub fn new(name: &'static str, capacity: usize, ttl: Duration) -> Self {
+        let capacity = capacity.max(1);
+        let counters = Arc::new(Counters::default());
+        let listener_counters = counters.clone();
+        let entries = Cache::builder()
+            .name(name)
+            .max_capacity(capacity as u64)
+            .eviction_policy(EvictionPolicy::lru())
+            .expire_after(EntryTtl)
+            .eviction_listener(move |_key, entry: Entry<V>, cause| {
+                listener_counters.release(entry.weight);
+                let counter = match cause {
+                    RemovalCause::Size => &listener_counters.evictions,
+                    RemovalCause::Expired => &listener_counters.expirations,
+                    RemovalCause::Explicit | RemovalCause::Replaced => return,
+                };
+                counter.fetch_add(1, Ordering::Relaxed);
+            })
+            .build();
+        let inner = Arc::new(Inner {
+            name,
+            entries,
+            capacity,
+            counters,
+        });
+
+        let source: Arc<dyn StatsSource> = inner.clone();
+        recover_lock(REGISTRY.write(), "cache registry").push(Arc::downgrade(&source));
+
+        Self {
+            inner,
+            ttl,
+            shared: None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.inner.name
+    }
+
+    /// Default TTL of new entries
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// The cached value, if present and not expired
+    pub fn get(&self, key: &K) -> Option<V> {
+        let counters = &self.inner.counters;
+        match self.inner.entries.get(key) {
+            Some(entry) => {
+                counters.hits.fetch_add(1, Ordering::Relaxed);
+                Some(entry.value)
+            }
+            None => {
+                counters.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    /// Cache a value for the default TTL
+    pub fn insert(&self, key: K, value: V) {
+        self.insert_with_ttl(key, value, self.ttl);
+    }
+
+    /// Cache a value for a TTL of its own
+    pub fn insert_with_ttl(&self, key: K, value: V, ttl: Duration) {
+        let weight = (size_of::<K>()
+            + key.heap_size()
+            + size_of::<Entry<V>>()
+            + value.heap_size()
+            + ENTRY_OVERHEAD) as u64;
+        // Counted before inserting, as the listener releases a replaced entry's weight
+        self.inner
+            .counters
+            .bytes
+            .fetch_add(weight, Ordering::Relaxed);
+        MEMORY_USED.fetch_add(weight, Ordering::Relaxed);
+        self.inner.entries.insert(
+            key,
+            Entry {
+                value,
+                ttl,
+                weight,
+                inserted_at: Instant::now(),
+            },
+        );
+        enforce_memory_budget();
+    }
+
+    pub fn remove(&self, key: &K) -> Option<V> {
+        self.inner.entries.remove(key).map(|entry| entry.value)
+    }
+
+    /// Drop every local entry
+    pub fn clear(&self) {
+        self.inner.entries.invalidate_all();
+        self.inner.entries.run_pending_tasks();
+    }
+
+    /// Number of fresh entries
+    pub fn len(&self) -> usize {
+        self.stats().entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.inner.stats()
+    }
+}
+
+impl<K, V> TtlCache<K, V>
+where
+    K: Hash + Eq + Clone + Display + CacheWeight + Send + Sync + 'static,
+    V: Clone + Serialize + DeserializeOwned + CacheWeight + Send + Sync + 'static,
+{
+    /// Share entries with other instances through this backend
+    pub fn with_shared(mut self, shared: Arc<dyn SharedCache>) -> Self {
+        self.shared = Some(shared);
+        self
+    }
+
+    fn shared_key(&self, key: &K) -> String {
+        format!("{}:{}", self.inner.name, key)
+    }
+
+    /// The cached value, falling back to the shared backend on a local miss
+    ///
+    /// Values found in the shared backend are cached locally for the default TTL.
+    pub async fn get_shared(&self, key: &K) -> Option<V> {
+        if let Some(value) = self.get(key) {
+            return Some(value);
+        }
+        let shared = self.shared.as_ref()?;
+        let bytes = shared.get(&self.shared_key(key)).await?;
+        match serde_json::from_slice::<V>(&bytes) {
+            Ok(value) => {
+                self.insert(key.clone(), value.clone());
+                Some(value)
+            }
+            Err(e) => {
+                warn!(
+                    "Ignoring undecodable shared entry in {}: {}",
+                    self.inner.name, e
+                );
+                None
+            }
+        }
+    }
+
+    /// Cache a value locally and in the shared backend
+    pub async fn insert_shared(&self, key: K, value: V, ttl: Duration) {
+        if let Some(shared) = &self.shared {
+            match serde_json::to_vec(&value) {
+                Ok(bytes) => shared.set(&self.shared_key(&key), bytes, ttl).await,
+                Err(e) => warn!(
+                    "Failed to serialize entry for shared {}: {}",
+                    self.inner.name, e
+                ),
+            }
+        }
+        self.insert_with_ttl(key, value, ttl);
+    }
+
+    /// Remove a value locally and from the shared backend
+    pub async fn remove_shared(&self, key: &K) {
+        self.remove(key);
+        if let Some(shared) = &self.shared {
+            shared.delete(&self.shared_key(key)).await;
+        }
+    }
+
+    /// Drop every entry, locally and in the shared backend
+    pub async fn clear_shared(&self) {
+        self.clear();
+        if let Some(shared) = &self.shared {
+            shared.clear(&format!("{}:", self.inner.name)).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entries_expire_and_least_recently_used_are_evicted() {
+        let cache: TtlCache<String, u32> = TtlCache::new("test", 2, Duration::from_secs(60));
+        cache.insert("a".to_string(), 1);
+        cache.insert("b".to_string(), 2);
+        // Writes, reads and evictions are recorded in batches; counting entries applies them
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(&"a".to_string()), Some(1));
+        cache.insert("c".to_string(), 3);
+        assert_eq!(cache.len(), 2);
+
+        // "b" was least recently used
+        assert_eq!(cache.get(&"b".to_string()), None);
+        assert_eq!(cache.get(&"c".to_string()), Some(3));
+
+        // "a" is evicted to make room for "d", which has already expired
+        cache.insert_with_ttl("d".to_string(), 4, Duration::ZERO);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(&"d".to_string()), None);
+        // Expirations are tracked on a timer wheel whose finest slots span about a second
+        std::thread::sleep(Duration::from_millis(1500));
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses), (2, 2));
+        assert_eq!((stats.evictions, stats.expirations), (2, 1));
+        assert!(all_stats().iter().any(|stats| stats.name == "test"));
+    }
+
+    #[test]
+    fn test_purging_drops_expired_entries() {
+        let cache: TtlCache<String, u32> = TtlCache::new("test_purge", 4, Duration::from_secs(60));
+        cache.insert("kept".to_string(), 1);
+        cache.insert_with_ttl("expired".to_string(), 2, Duration::ZERO);
+        // Expirations are tracked on a timer wheel whose finest slots span about a second
+        std::thread::sleep(Duration::from_millis(1500));
+
+        // Other tests may purge concurrently, so the cache's own counters are checked
+        purge_expired();
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.expirations), (1, 1));
+    }
+
+    #[test]
+    fn test_entries_are_weighed_and_shrinking_evicts_the_oldest() {
+        let cache: TtlCache<String, Vec<String>> =
+            TtlCache::new("test_weigh", 8, Duration::from_secs(60));
+        let books = |n: usize| vec!["x".repeat(1000); n];
+        cache.insert("small".to_string(), books(1));
+        std::thread::sleep(Duration::from_millis(2));
+        cache.insert("large".to_string(), books(10));
+        let small = cache.stats().bytes;
+        assert!(small > 11_000, "{} bytes", small);
+
+        // Replacing an entry releases the weight of the old value
+        cache.insert("small".to_string(), books(2));
+        let book = (1000 + size_of::<String>()) as u64;
+        assert_eq!(cache.stats().bytes, small + book);
+
+        // "large" is now the oldest entry
+        let freed = cache.inner.shrink(1);
+        assert!(freed > 10_000, "{} bytes freed", freed);
+        assert_eq!(cache.get(&"large".to_string()), None);
+        assert!(cache.get(&"small".to_string()).is_some());
+        let stats = cache.stats();
+        assert_eq!(stats.bytes, small + book - freed);
+        assert_eq!(stats.evictions, 1);
+    }
+}
@@ -0,0 +1,108 @@
+//! Redis backend for [`SharedCache`], enabled with the `redis` feature
+
+use super::SharedCache;
+use crate::error::{ApiError, Result};
+use futures::future::BoxFuture;
+use redis::{aio::ConnectionManager, AsyncCommands};
+use std::time::Duration;
+use tracing::warn;
+
+/// Keys deleted per SCAN page when clearing a prefix
+const SCAN_PAGE_SIZE: usize = 500;
+
+/// Shared cache stored in Redis; errors are logged and treated as misses
+#[derive(Clone)]
+pub struct RedisCache {
+    connection: ConnectionManager,
+    /// Prepended to every key so several deployments can share one Redis
+    namespace: String,
+}
+
+impl RedisCache {
+    pub async fn connect(url: &str, namespace: &str) -> Result<Self> {
+        let client = redis::Client::open(url)
+            .map_err(|e| ApiError::InvalidInput(format!("Invalid Redis URL: {}", e)))?;
+        let connection = ConnectionManager::new(client).await.map_err(|e| {
+            ApiError::ExternalServiceError(format!("Failed to connect to Redis: {}", e))
+        })?;
+        Ok(Self {
+            connection,
+            namespace: namespace.to_string(),
+        })
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("{}:{}", self.namespace, key)
+    }
+}
+
+impl SharedCache for RedisCache {
+    fn get(&self, key: &str) -> BoxFuture<'_, Option<Vec<u8>>> {
+        let key = self.key(key);
+        Box::pin(async move {
+            let mut connection = self.connection.clone();
+            match connection.get::<_, Option<Vec<u8>>>(&key).await {
+                Ok(value) => value,
+                Err(e) => {
+                    warn!("Redis GET {} failed: {}", key, e);
+                    None
+                }
+            }
+        })
+    }
+
+    fn set(&self, key: &str, value: Vec<u8>, ttl: Duration) -> BoxFuture<'_, ()> {
+        let key = self.key(key);
+        Box::pin(async move {
+            let mut connection = self.connection.clone();
+            let seconds = ttl.as_secs().max(1) as usize;
+            if let Err(e) = connection.set_ex::<_, _, ()>(&key, value, seconds).await {
+                warn!("Redis SETEX {} failed: {}", key, e);
+            }
+        })
+    }
+
+    fn delete(&self, key: &str) -> BoxFuture<'_, ()> {
+        let key = self.key(key);
+        Box::pin(async move {
+            let mut connection = self.connection.clone();
+            if let Err(e) = connection.del::<_, ()>(&key).await {
+                warn!("Redis DEL {} failed: {}", key, e);
+            }
+        })
+    }
+
+    fn clear(&self, prefix: &str) -> BoxFuture<'_, ()> {
+        let pattern = format!("{}*", self.key(prefix));
+        Box::pin(async move {
+            let mut connection = self.connection.clone();
+            let mut cursor: u64 = 0;
+            loop {
+                let page: redis::RedisResult<(u64, Vec<String>)> = redis::cmd("SCAN")
+                    .arg(cursor)
+                    .arg("MATCH")
+                    .arg(&pattern)
+                    .arg("COUNT")
+                    .arg(SCAN_PAGE_SIZE)
+                    .query_async(&mut connection)
+                    .await;
+                let (next, keys) = match page {
+                    Ok(page) => page,
+                    Err(e) => {
+                        warn!("Redis SCAN {} failed: {}", pattern, e);
+                        return;
+                    }
+                };
+                if !keys.is_empty() {
+                    if let Err(e) = connection.del::<_, ()>(keys).await {
+                        warn!("Redis DEL of {} keys failed: {}", pattern, e);
+                    }
+                }
+                if next == 0 {
+                    return;
+                }
+                cursor = next;
+            }
+        })
+    }
+}
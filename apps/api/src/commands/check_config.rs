@@ -0,0 +1,218 @@
+//! `rab-admin check-config`: show the effective configuration and optionally test connectivity
+
+use crate::{
+    config::Config,
+    services::{bootstrap, supabase::SupabaseClient, Pinecone},
+};
+use anyhow::Result;
+use std::env;
+
+/// Options for the `check-config` command
+#[derive(Debug, Clone, Default)]
+pub struct CheckConfigOptions {
+    /// Also connect to every configured upstream
+    pub connect: bool,
+}
+
+pub async fn run(config: &Config, options: CheckConfigOptions) -> Result<()> {
+    let mut problems = Vec::new();
+
+    println!();
+    println!("Configuration");
+    println!("==================================================");
+    println!("  Server:               {}:{}", config.host, config.port);
+    println!(
+        "  Pinecone:             index '{}' in '{}', API key {}",
+        config.pinecone_index,
+        config.pinecone_environment,
+        secret_status(&config.pinecone_api_key)
+    );
+    println!(
+        "  HuggingFace:          API key {}",
+        secret_status(&env::var("APP_HUGGINGFACE_API_KEY").unwrap_or_default())
+    );
+    println!(
+        "  Neo4j:                {}",
+        config.neo4j_uri.as_deref().unwrap_or("not configured")
+    );
+    println!(
+        "  Database:             {}",
+        if config.database_url.is_some() {
+            "configured (user data and audit log are persistent)"
+        } else {
+            "not configured (user data and audit log are in memory)"
+        }
+    );
+    println!(
+        "  Admin API:            {}",
+        if config.admin_api_key.is_some() {
+            "enabled"
+        } else {
+            "disabled"
+        }
+    );
+    println!(
+        "  User tokens:          {}",
+        if config.user_jwt_secret.is_some() {
+            "verified (users can access their own data)"
+        } else {
+            "not configured (user data needs the admin key)"
+        }
+    );
+    println!(
+        "  Slow query log:       >= {} ms, {} entries",
+        config.slow_query_threshold_ms, config.slow_query_log_capacity
+    );
+    println!(
+        "  Indexer status file:  {}",
+        config.indexer_status_file.as_deref().unwrap_or("disabled")
+    );
+    println!(
+        "  Corpus statistics:    {}",
+        config.corpus_stats_file.as_deref().unwrap_or("disabled")
+    );
+    println!(
+        "  Site URL:             {}",
+        config
+            .site_url
+            .as_deref()
+            .unwrap_or("not configured (no sitemap)")
+    );
+    println!(
+        "  Catalog webhooks:     {}",
+        if config.webhook_urls.is_empty() {
+            "none".to_string()
+        } else {
+            format!(
+                "{} ({})",
+                config.webhook_urls.join(", "),
+                if config.webhook_secret.is_some() {
+                    "signed"
+                } else {
+                    "unsigned"
+                }
+            )
+        }
+    );
+    println!(
+        "  Catalog event stream: {}",
+        config
+            .catalog_events_stream
+            .as_deref()
+            .unwrap_or("disabled")
+    );
+
+    for (name, value) in [
+        ("pinecone_api_key", &config.pinecone_api_key),
+        ("pinecone_environment", &config.pinecone_environment),
+        ("pinecone_index", &config.pinecone_index),
+    ] {
+        if is_placeholder(value) {
+            problems.push(format!("{} is empty or a placeholder", name));
+        }
+    }
+    if config.neo4j_uri.as_deref().is_some_and(is_placeholder) {
+        problems.push("neo4j_uri is a placeholder".to_string());
+    }
+    for url in &config.webhook_urls {
+        if reqwest::Url::parse(url).is_err() {
+            problems.push(format!("webhook URL {} is not a valid URL", url));
+        }
+    }
+    if config.catalog_events_stream.is_some() && config.redis_url.is_none() {
+        problems.push("catalog_events_stream is set without redis_url".to_string());
+    }
+    if is_placeholder(&env::var("APP_HUGGINGFACE_API_KEY").unwrap_or_default()) {
+        problems.push("APP_HUGGINGFACE_API_KEY is not set".to_string());
+    }
+
+    if options.connect {
+        println!();
+        println!("Connectivity");
+
+        match Pinecone::new(
+            &config.pinecone_api_key,
+            &config.pinecone_environment,
+            &config.pinecone_index,
+        )
+        .await
+        {
+            Ok(_) => println!("  Pinecone:             ok"),
+            Err(e) => {
+                println!("  Pinecone:             FAILED");
+                problems.push(format!("Pinecone: {}", e));
+            }
+        }
+
+        match bootstrap::init_embedder().await {
+            Ok(embedder) => match embedder.encode("connectivity check").await {
+                Ok(_) => println!("  HuggingFace:          ok"),
+                Err(e) => {
+                    println!("  HuggingFace:          FAILED");
+                    problems.push(format!("HuggingFace: {}", e));
+                }
+            },
+            Err(e) => {
+                println!("  HuggingFace:          FAILED");
+                problems.push(format!("HuggingFace: {}", e));
+            }
+        }
+
+        if config.neo4j_password.is_some() {
+            match bootstrap::init_neo4j(config).await {
+                Ok(neo4j) => match neo4j.get_graph_stats().await {
+                    Ok(stats) => println!(
+                        "  Neo4j:                ok ({} books, {} relationships)",
+                        stats.total_books, stats.total_relationships
+                    ),
+                    Err(e) => {
+                        println!("  Neo4j:                FAILED");
+                        problems.push(format!("Neo4j: {}", e));
+                    }
+                },
+                Err(e) => {
+                    println!("  Neo4j:                FAILED");
+                    problems.push(format!("Neo4j: {}", e));
+                }
+            }
+        }
+
+        if let Some(database_url) = &config.database_url {
+            match SupabaseClient::connect(database_url).await {
+                Ok(_) => println!("  Database:             ok"),
+                Err(e) => {
+                    println!("  Database:             FAILED");
+                    problems.push(format!("Database: {}", e));
+                }
+            }
+        }
+    }
+
+    println!();
+    if problems.is_empty() {
+        println!("No problems found.");
+        Ok(())
+    } else {
+        println!("Problems");
+        for problem in &problems {
+            println!("  - {}", problem);
+        }
+        Err(anyhow::anyhow!(
+            "{} configuration problem(s) found",
+            problems.len()
+        ))
+    }
+}
+
+fn is_placeholder(value: &str) -> bool {
+    let value = value.trim();
+    value.is_empty() || value.contains("your") || value.starts_with("${")
+}
+
+fn secret_status(value: &str) -> &'static str {
+    if is_placeholder(value) {
+        "missing"
+    } else {
+        "set"
+    }
+}
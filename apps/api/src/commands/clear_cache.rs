@@ -0,0 +1,38 @@
+//! `rab-admin clear-cache`: clear the caches of a running API server
+//!
+//! Caches live in the server process, so this goes through the admin endpoint,
+//! which also records the action in the audit log.
+
+use crate::config::Config;
+use anyhow::{Context, Result};
+use log::info;
+use recommend_a_book_client::Client;
+
+/// Options for the `clear-cache` command
+#[derive(Debug, Clone)]
+pub struct ClearCacheOptions {
+    /// Base URL of the API server, e.g. `http://localhost:8000`
+    pub api_url: String,
+    /// Recorded as the actor in the audit log
+    pub actor: String,
+}
+
+pub async fn run(config: &Config, options: ClearCacheOptions) -> Result<()> {
+    let admin_key = config
+        .admin_api_key
+        .as_deref()
+        .context("APP_ADMIN_API_KEY must be set to call the admin API")?;
+
+    let client = Client::new(options.api_url)
+        .with_admin_key(admin_key)
+        .with_actor(options.actor);
+    info!("Clearing caches via {}", client.base_url());
+
+    let result = client.clear_caches().await.context("Cache clear failed")?;
+    info!(
+        "✅ {} completed (audit entry {})",
+        result.action, result.audit_id
+    );
+
+    Ok(())
+}
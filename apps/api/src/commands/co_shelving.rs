@@ -0,0 +1,121 @@
+//! `rab-admin co-shelving`: write collaborative-filtering scores from users' shelves into the graph
+
+use crate::{
+    config::Config,
+    services::{
+        bootstrap,
+        collaborative::{co_shelving_pairs, CoShelvedPair, CoShelvingConfig},
+        neo4j::RelationType,
+        supabase::SupabaseClient,
+    },
+};
+use anyhow::{Context, Result};
+use log::info;
+use std::collections::HashSet;
+
+/// Pairs written to Neo4j per query
+const WRITE_BATCH_SIZE: usize = 500;
+
+/// Pairs shown in the report
+const TOP_PAIRS_SHOWN: usize = 10;
+
+/// Options for the `co-shelving` command
+#[derive(Debug, Clone, Default)]
+pub struct CoShelvingOptions {
+    pub cf: CoShelvingConfig,
+    /// Print the report without touching the graph
+    pub dry_run: bool,
+}
+
+/// Compute "users who shelved X also shelved Y" scores and store them on
+/// SIMILAR_TO and READ_NEXT relationships as `cf_score`
+pub async fn run(config: &Config, options: CoShelvingOptions) -> Result<()> {
+    let database_url = config
+        .database_url
+        .as_deref()
+        .context("database_url is not configured; shelves are only persisted to a database")?;
+
+    info!("Loading shelves from Supabase...");
+    let supabase = SupabaseClient::connect(database_url)
+        .await
+        .context("Failed to connect to Supabase")?;
+    let entries = supabase.all_shelf_entries().await?;
+
+    let users: HashSet<&str> = entries.iter().map(|e| e.user_id.as_str()).collect();
+    let books: HashSet<&str> = entries.iter().map(|e| e.book_id.as_str()).collect();
+    info!(
+        "Loaded {} shelf entries from {} users covering {} books",
+        entries.len(),
+        users.len(),
+        books.len()
+    );
+
+    let pairs = co_shelving_pairs(&entries, &options.cf);
+    let read_next: Vec<_> = pairs.iter().filter(|pair| pair.read_next).collect();
+    info!(
+        "Found {} co-shelved pairs ({} read-next) with at least {} shared users",
+        pairs.len(),
+        read_next.len(),
+        options.cf.min_shared_users
+    );
+
+    let mut strongest: Vec<_> = pairs.iter().collect();
+    strongest.sort_by(|a, b| {
+        b.cf_score
+            .partial_cmp(&a.cf_score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    for pair in strongest.iter().take(TOP_PAIRS_SHOWN) {
+        info!(
+            "  {} -> {}: cf_score {:.3} ({} users{})",
+            pair.from_id,
+            pair.to_id,
+            pair.cf_score,
+            pair.shared_users,
+            if pair.read_next { ", read next" } else { "" }
+        );
+    }
+
+    if options.dry_run {
+        info!("Dry run: graph not updated");
+        return Ok(());
+    }
+
+    let neo4j = bootstrap::init_neo4j(config)
+        .await
+        .context("Failed to initialize Neo4j client")?;
+
+    info!("Clearing previous co-shelving scores...");
+    neo4j.clear_cf_scores().await?;
+
+    let rows = |pairs: &[&CoShelvedPair]| {
+        pairs
+            .iter()
+            .map(|p| {
+                (
+                    p.from_id.clone(),
+                    p.to_id.clone(),
+                    p.cf_score,
+                    p.shared_users,
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+    let all: Vec<_> = pairs.iter().collect();
+    for (relation_type, pairs) in [
+        (RelationType::SimilarTo, rows(&all)),
+        (RelationType::ReadNext, rows(&read_next)),
+    ] {
+        for chunk in pairs.chunks(WRITE_BATCH_SIZE) {
+            neo4j.write_cf_scores(&relation_type, chunk).await?;
+        }
+        info!(
+            "Wrote {} {:?} co-shelving scores",
+            pairs.len(),
+            relation_type
+        );
+    }
+
+    info!("✅ Co-shelving scores updated");
+    Ok(())
+}
@@ -0,0 +1,78 @@
+//! `rab-admin digest`: generate a user's weekly digest for an external mailer
+
+use super::require_env;
+use crate::{
+    config::Config,
+    services::{bootstrap, supabase::SupabaseClient},
+};
+use anyhow::{Context, Result};
+use log::info;
+use std::{fs::File, path::PathBuf};
+
+/// Options for the `digest` command
+#[derive(Debug, Clone)]
+pub struct DigestOptions {
+    pub user_id: String,
+    /// Number of picks
+    pub count: usize,
+    /// Print the digest without recording it as sent
+    pub dry_run: bool,
+    /// Write the digest here instead of printing it
+    pub output_path: Option<PathBuf>,
+}
+
+/// Generate a digest from the user's shelves and print or save it as JSON
+pub async fn run(config: &Config, options: DigestOptions) -> Result<()> {
+    require_env(&[
+        "APP_HUGGINGFACE_API_KEY",
+        "APP_PINECONE_API_KEY",
+        "APP_PINECONE_ENV",
+        "APP_PINECONE_INDEX_NAME",
+    ])?;
+    let database_url = config
+        .database_url
+        .as_deref()
+        .context("database_url is not configured; shelves are only persisted to a database")?;
+
+    info!("Connecting to Supabase...");
+    let supabase = SupabaseClient::connect(database_url)
+        .await
+        .context("Failed to connect to Supabase")?;
+
+    info!("Initializing Pinecone client...");
+    let pinecone = bootstrap::init_pinecone(config)
+        .await
+        .context("Failed to initialize Pinecone client")?;
+
+    info!("Initializing HuggingFace embedder...");
+    let embedder = bootstrap::init_embedder()
+        .await
+        .context("Failed to initialize HuggingFace embedder")?;
+
+    let service =
+        bootstrap::recommendation_service(config, embedder, pinecone).with_user_data(supabase);
+    let digest = service
+        .weekly_digest(&options.user_id, options.count, !options.dry_run)
+        .await?;
+    info!(
+        "Generated {} picks for user {} from {} seed books",
+        digest.picks.len(),
+        digest.user_id,
+        digest.seed_count
+    );
+    if options.dry_run {
+        info!("Dry run: digest not recorded");
+    }
+
+    match &options.output_path {
+        Some(path) => {
+            let file = File::create(path)
+                .with_context(|| format!("Failed to create digest: {}", path.display()))?;
+            serde_json::to_writer_pretty(file, &digest).context("Failed to write digest")?;
+            info!("Digest written to {}", path.display());
+        }
+        None => println!("{}", serde_json::to_string_pretty(&digest)?),
+    }
+
+    Ok(())
+}
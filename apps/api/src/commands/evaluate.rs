@@ -0,0 +1,154 @@
+//! `rab-admin evaluate`: score ranking quality against a YAML query suite
+
+use super::require_env;
+use crate::{
+    config::Config,
+    evaluation::{score_query, EvaluationReport, EvaluationSuite},
+    services::bootstrap,
+};
+use anyhow::{Context, Result};
+use log::{info, warn};
+use std::{fs::File, path::PathBuf};
+
+/// Options for the `evaluate` command
+#[derive(Debug, Clone)]
+pub struct EvaluateOptions {
+    pub suite_path: PathBuf,
+    /// Overrides the cutoff set in the suite
+    pub k: Option<usize>,
+    /// Write the full report as JSON, e.g. to use as a baseline later
+    pub output_path: Option<PathBuf>,
+    /// Previous JSON report to compare against
+    pub baseline_path: Option<PathBuf>,
+}
+
+/// Run an evaluation suite through the recommendation pipeline and print the scores
+pub async fn run(config: &Config, options: EvaluateOptions) -> Result<EvaluationReport> {
+    require_env(&[
+        "APP_HUGGINGFACE_API_KEY",
+        "APP_PINECONE_API_KEY",
+        "APP_PINECONE_ENV",
+        "APP_PINECONE_INDEX_NAME",
+    ])?;
+
+    let suite = EvaluationSuite::load(&options.suite_path)
+        .map_err(|e| anyhow::anyhow!("Failed to load evaluation suite: {}", e))?;
+    let k = options.k.unwrap_or(suite.k);
+    info!(
+        "Loaded {} queries from {} (k = {})",
+        suite.queries.len(),
+        options.suite_path.display(),
+        k
+    );
+
+    let baseline: Option<EvaluationReport> = match &options.baseline_path {
+        Some(path) => {
+            let file = File::open(path)
+                .with_context(|| format!("Failed to open baseline: {}", path.display()))?;
+            Some(
+                serde_json::from_reader(file)
+                    .with_context(|| format!("Failed to parse baseline: {}", path.display()))?,
+            )
+        }
+        None => None,
+    };
+
+    // Initialize services
+    info!("Initializing Pinecone client...");
+    let pinecone = bootstrap::init_pinecone(config)
+        .await
+        .context("Failed to initialize Pinecone client")?;
+
+    info!("Initializing HuggingFace embedder...");
+    let embedder = bootstrap::init_embedder()
+        .await
+        .context("Failed to initialize HuggingFace embedder")?;
+    let (model_name, _) = embedder.model_info();
+    info!("Using model: {}", model_name);
+
+    let service = bootstrap::recommendation_service(config, embedder, pinecone);
+
+    let mut scores = Vec::with_capacity(suite.queries.len());
+    for (i, query) in suite.queries.iter().enumerate() {
+        let results = match service.get_recommendations(&query.query, k).await {
+            Ok((books, _)) => books,
+            Err(e) => {
+                warn!("Query '{}' failed, scoring it as empty: {}", query.query, e);
+                Vec::new()
+            }
+        };
+        let score = score_query(query, &results, k);
+        info!(
+            "[{}/{}] recall={:.3} ndcg={:.3} rr={:.3}  {}",
+            i + 1,
+            suite.queries.len(),
+            score.recall,
+            score.ndcg,
+            score.reciprocal_rank,
+            query.query
+        );
+        scores.push(score);
+    }
+
+    let report = EvaluationReport::from_scores(k, scores);
+    print_report(&report, baseline.as_ref());
+
+    if let Some(path) = &options.output_path {
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create report: {}", path.display()))?;
+        serde_json::to_writer_pretty(file, &report).context("Failed to write report")?;
+        info!("Report written to {}", path.display());
+    }
+
+    Ok(report)
+}
+
+fn print_report(report: &EvaluationReport, baseline: Option<&EvaluationReport>) {
+    println!();
+    println!(
+        "Evaluation report ({} queries, k = {})",
+        report.queries.len(),
+        report.k
+    );
+    println!("==================================================");
+
+    for score in &report.queries {
+        println!();
+        println!("  {}", score.query);
+        println!(
+            "    recall@{} {:.3}   nDCG@{} {:.3}   RR {:.3}",
+            report.k, score.recall, report.k, score.ndcg, score.reciprocal_rank
+        );
+        if !score.missing.is_empty() {
+            println!("    missing: {}", score.missing.join(", "));
+        }
+    }
+
+    let delta = |current: f64, previous: Option<f64>| match previous {
+        Some(previous) => format!("  ({:+.3} vs baseline)", current - previous),
+        None => String::new(),
+    };
+
+    println!();
+    println!("Summary");
+    println!(
+        "  recall@{}  {:.3}{}",
+        report.k,
+        report.recall,
+        delta(report.recall, baseline.map(|b| b.recall))
+    );
+    println!(
+        "  nDCG@{}    {:.3}{}",
+        report.k,
+        report.ndcg,
+        delta(report.ndcg, baseline.map(|b| b.ndcg))
+    );
+    println!(
+        "  MRR        {:.3}{}",
+        report.mrr,
+        delta(report.mrr, baseline.map(|b| b.mrr))
+    );
+    if baseline.is_some_and(|b| b.k != report.k) {
+        println!("  Note: the baseline was computed with a different k");
+    }
+}
@@ -1,61 +1,54 @@
-use anyhow::{Context, Result};
-use log::{error, info, warn};
-use recommend_a_book_api::{
+//! `rab-admin rebuild-graph`: build the Neo4j relationship graph from indexed books
+
+use super::require_env;
+use crate::{
     config::Config,
-    ml::huggingface_embedder::HuggingFaceEmbedder,
+    ingest::authors::author_key,
+    models::Book,
     services::{
-        neo4j::{BookRelationship, Neo4jClient, RelationType},
-        pinecone::Pinecone,
+        bootstrap,
+        catalog_events::CatalogEvent,
+        neo4j::{BookRelationship, RelationType},
+        personalization::cosine_similarity,
     },
 };
+use anyhow::{Context, Result};
+use log::{info, warn};
 use std::collections::HashMap;
-use std::env;
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
-
-/// Calculate cosine similarity between two embeddings
-fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
-    let dot_product: f32 = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
-    let norm_a: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
-    let norm_b: f32 = b.iter().map(|x| x * x).sum::<f32>().sqrt();
-
-    if norm_a == 0.0 || norm_b == 0.0 {
-        0.0
-    } else {
-        dot_product / (norm_a * norm_b)
-    }
+
+/// Options for the `rebuild-graph` command
+#[derive(Debug, Clone, Default)]
+pub struct GraphOptions {
+    /// Delete every node and relationship before rebuilding
+    pub clear: bool,
 }
 
-async fn build_book_graph() -> Result<()> {
-    info!("Starting graph building process...");
+/// Rebuild the Neo4j book graph from the books stored in Pinecone
+pub async fn run(config: &Config, options: GraphOptions) -> Result<()> {
+    require_env(&[
+        "APP_HUGGINGFACE_API_KEY",
+        "APP_PINECONE_API_KEY",
+        "APP_PINECONE_ENV",
+        "APP_PINECONE_INDEX_NAME",
+        "APP_NEO4J_PASSWORD",
+    ])?;
 
-    // Load configuration
-    let config = Config::load().context("Failed to load configuration")?;
+    info!("Starting graph building process...");
 
     // Initialize Neo4j client
-    let neo4j_uri =
-        env::var("APP_NEO4J_URI").unwrap_or_else(|_| "bolt://localhost:7687".to_string());
-    let neo4j_user = env::var("APP_NEO4J_USER").unwrap_or_else(|_| "neo4j".to_string());
-    let neo4j_password = env::var("APP_NEO4J_PASSWORD")
-        .context("Missing APP_NEO4J_PASSWORD environment variable")?;
-
-    info!("Connecting to Neo4j at {}", neo4j_uri);
-    let neo4j = Neo4jClient::new(&neo4j_uri, &neo4j_user, &neo4j_password)
+    let neo4j = bootstrap::init_neo4j(config)
         .await
         .context("Failed to initialize Neo4j client")?;
 
     // Initialize Pinecone to fetch books
     info!("Initializing Pinecone client...");
-    let pinecone = Pinecone::new(
-        &config.pinecone_api_key,
-        &config.pinecone_environment,
-        &config.pinecone_index,
-    )
-    .await
-    .context("Failed to initialize Pinecone client")?;
+    let pinecone = bootstrap::init_pinecone(config)
+        .await
+        .context("Failed to initialize Pinecone client")?;
 
     // Initialize HuggingFace embedder
     info!("Initializing HuggingFace embedder...");
-    let embedder = HuggingFaceEmbedder::new()
+    let embedder = bootstrap::init_embedder()
         .await
         .context("Failed to initialize HuggingFace embedder")?;
 
@@ -65,7 +58,7 @@ async fn build_book_graph() -> Result<()> {
     info!("Retrieved {} books from Pinecone", all_books.len());
 
     // Clear existing graph if requested
-    if env::var("CLEAR_GRAPH").unwrap_or_default() == "true" {
+    if options.clear {
         info!("Clearing existing graph...");
         neo4j.clear_graph().await?;
     }
@@ -86,13 +79,12 @@ async fn build_book_graph() -> Result<()> {
     info!("Building relationships between books...");
 
     // Group books by author
-    let mut books_by_author: HashMap<String, Vec<&recommend_a_book_api::models::Book>> =
-        HashMap::new();
+    let mut books_by_author: HashMap<String, Vec<&Book>> = HashMap::new();
     for book in &all_books {
         if let Some(author) = &book.author {
             books_by_author
-                .entry(author.to_lowercase())
-                .or_insert_with(Vec::new)
+                .entry(author_key(author))
+                .or_default()
                 .push(book);
         }
     }
@@ -142,13 +134,18 @@ async fn build_book_graph() -> Result<()> {
     }
 
     // Group books by genre
-    let mut books_by_genre: HashMap<String, Vec<&recommend_a_book_api::models::Book>> =
-        HashMap::new();
+    let mut books_by_genre: HashMap<String, Vec<&Book>> = HashMap::new();
     for book in &all_books {
-        for category in &book.categories {
+        // Books indexed before the taxonomy existed only have raw categories
+        let genres = if book.genres.is_empty() {
+            &book.categories
+        } else {
+            &book.genres
+        };
+        for genre in genres {
             books_by_genre
-                .entry(category.to_lowercase())
-                .or_insert_with(Vec::new)
+                .entry(genre.to_lowercase())
+                .or_default()
                 .push(book);
         }
     }
@@ -240,8 +237,7 @@ async fn build_book_graph() -> Result<()> {
         let id1 = &book_ids[i];
         let emb1 = &book_embeddings[id1];
 
-        for j in (i + 1)..book_ids.len() {
-            let id2 = &book_ids[j];
+        for id2 in &book_ids[(i + 1)..] {
             let emb2 = &book_embeddings[id2];
 
             let similarity = cosine_similarity(emb1, emb2);
@@ -289,54 +285,14 @@ async fn build_book_graph() -> Result<()> {
     info!("  Total books: {}", stats.total_books);
     info!("  Total relationships: {}", stats.total_relationships);
 
-    Ok(())
-}
-
-#[tokio::main]
-async fn main() -> Result<()> {
-    // Initialize logging
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "build_graph=info,recommend_a_book_api=info".into()),
-        )
-        .with(
-            tracing_subscriber::fmt::layer()
-                .with_target(false)
-                .with_level(true),
-        )
-        .init();
-
-    // Load environment variables
-    dotenv::dotenv().ok();
-
-    // Check for required environment variables
-    let required_vars = [
-        "APP_HUGGINGFACE_API_KEY",
-        "APP_PINECONE_API_KEY",
-        "APP_PINECONE_ENV",
-        "APP_PINECONE_INDEX_NAME",
-        "APP_NEO4J_PASSWORD",
-    ];
-
-    for var in &required_vars {
-        if env::var(var).is_err() {
-            error!("Missing required environment variable: {}", var);
-            std::process::exit(1);
-        }
+    if let Some(publisher) = bootstrap::init_catalog_events(config).await {
+        publisher
+            .publish(CatalogEvent::GraphRebuilt {
+                books: stats.total_books,
+                relationships: stats.total_relationships,
+            })
+            .await;
     }
 
-    info!("Book Graph Builder");
-    info!("==================");
-
-    match build_book_graph().await {
-        Ok(_) => {
-            info!("✅ Graph building completed successfully!");
-            std::process::exit(0);
-        }
-        Err(e) => {
-            error!("❌ Graph building failed: {}", e);
-            std::process::exit(1);
-        }
-    }
+    Ok(())
 }
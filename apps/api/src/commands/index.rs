@@ -0,0 +1,911 @@
+//! `rab-admin index`: read a catalog, embed it and upsert it to Pinecone
+
+use super::require_env;
+use crate::{
+    config::Config,
+    ingest::{
+        content_flags::flag_book,
+        corpus_stats::CorpusStats,
+        covers::CoverResolver,
+        dedup::{deduplicate, DedupConfig, DuplicateCluster},
+        enrichment::{needs_enrichment, MetadataEnricher},
+        fulltext::FullTextIndex,
+        language::tag_language,
+        moods::MoodClassifier,
+        open_source,
+        pipeline::{spawn_pipeline, PipelineConfig},
+        progress::{IndexRunState, ProgressTracker},
+        searchable_text::{content_hash, create_searchable_text},
+        settings::settings_in_description,
+        taxonomy::CategoryTaxonomy,
+        IngestFormat, IngestSource,
+    },
+    models::Book,
+    services::{
+        book_versions::BookHistory, bootstrap, catalog_events::CatalogEvent, pinecone::Pinecone,
+    },
+};
+use anyhow::{Context, Result};
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    env,
+    fs::File,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Save the manifest after this many completed batches
+const MANIFEST_SAVE_INTERVAL: usize = 20;
+
+/// Unmapped categories listed in the dry run report
+const UNMAPPED_CATEGORIES_SHOWN: usize = 30;
+
+/// Where the catalog comes from and how it is normalized, shared by `index` and `prune`
+#[derive(Debug, Clone)]
+pub struct CatalogOptions {
+    pub input_path: PathBuf,
+    /// Format of the input file, from `--format` or the file extension
+    pub format: IngestFormat,
+    /// Open Library authors dump used to resolve author keys to names
+    pub authors_dump: Option<PathBuf>,
+    /// Sidecar file recording the content hash of every indexed book
+    pub manifest_path: PathBuf,
+    /// Fuzzy duplicate detection threshold and merge behaviour
+    pub dedup: DedupConfig,
+    /// YAML alias map resolving author spellings to canonical names
+    pub author_aliases_path: Option<PathBuf>,
+    /// YAML taxonomy mapping categories onto the controlled genre vocabulary
+    pub taxonomy_path: Option<PathBuf>,
+    /// CSV of award winners credited to the books they match
+    pub awards_path: Option<PathBuf>,
+}
+
+impl CatalogOptions {
+    /// Options for `input_path` with the format guessed from its extension and the
+    /// manifest stored next to it
+    pub fn new(input_path: impl Into<PathBuf>) -> Self {
+        let input_path = input_path.into();
+        Self {
+            format: IngestFormat::from_path(&input_path),
+            manifest_path: input_path.with_extension("manifest.json"),
+            authors_dump: None,
+            dedup: DedupConfig::default(),
+            author_aliases_path: None,
+            taxonomy_path: None,
+            awards_path: None,
+            input_path,
+        }
+    }
+}
+
+/// Options for the `index` command
+#[derive(Debug, Clone)]
+pub struct IndexOptions {
+    pub catalog: CatalogOptions,
+    /// Only re-embed and upsert books whose content hash changed
+    pub incremental: bool,
+    /// Validate the input and print a report without calling any external API
+    pub dry_run: bool,
+    /// Fill missing metadata from Google Books / Open Library before embedding
+    pub enrich: bool,
+    /// Cache of enrichment lookups, so reruns don't query the same ISBNs again
+    pub enrichment_cache_path: PathBuf,
+    /// Check every thumbnail and replace broken ones with Open Library covers
+    pub resolve_covers: bool,
+    /// Batch size and worker counts for the embed/upsert pipeline
+    pub pipeline: PipelineConfig,
+    /// Write the duplicate clusters as JSON
+    pub duplicates_report_path: Option<PathBuf>,
+    /// JSON progress snapshot, readable through `/api/admin/indexer/status`
+    pub status_file: Option<PathBuf>,
+    /// Corpus statistics snapshot the API loads at startup
+    pub corpus_stats_path: Option<PathBuf>,
+    /// Full-text index directory the API opens at startup
+    pub fulltext_index_path: Option<PathBuf>,
+}
+
+/// Content hashes of indexed books, written next to the input file after each run
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct IndexManifest {
+    /// Embedding model the hashes were computed with
+    pub(crate) model: String,
+    /// Book id -> content hash
+    pub(crate) books: BTreeMap<String, String>,
+}
+
+impl IndexManifest {
+    pub(crate) fn load(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open manifest: {}", path.display()))?;
+        let manifest = serde_json::from_reader(file)
+            .with_context(|| format!("Failed to parse manifest: {}", path.display()))?;
+        Ok(Some(manifest))
+    }
+
+    pub(crate) fn save(&self, path: &Path) -> Result<()> {
+        // Write to a temporary file first so an interrupted run never leaves a truncated manifest
+        let tmp_path = path.with_extension("json.tmp");
+        let file = File::create(&tmp_path)
+            .with_context(|| format!("Failed to create manifest: {}", tmp_path.display()))?;
+        serde_json::to_writer_pretty(file, self).context("Failed to write manifest")?;
+        std::fs::rename(&tmp_path, path)
+            .with_context(|| format!("Failed to replace manifest: {}", path.display()))?;
+        Ok(())
+    }
+}
+
+/// Read the content hashes stored in Pinecone metadata for the given ids
+async fn fetch_stored_hashes(
+    pinecone: &Pinecone,
+    ids: &[String],
+) -> Result<BTreeMap<String, String>> {
+    let mut hashes = BTreeMap::new();
+
+    for chunk in ids.chunks(100) {
+        let metadata = pinecone
+            .fetch_metadata(chunk)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to fetch stored hashes from Pinecone: {}", e))?;
+
+        for (id, metadata) in metadata {
+            if let Some(hash) = metadata.get("content_hash").and_then(|h| h.as_str()) {
+                hashes.insert(id, hash.to_string());
+            }
+        }
+    }
+
+    Ok(hashes)
+}
+
+/// The input after parsing, normalization, validation and deduplication
+#[derive(Debug, Default)]
+pub(crate) struct ParsedCatalog {
+    /// Unique books, in input order
+    pub(crate) books: Vec<Book>,
+    /// Rows that were rejected, with the reason
+    pub(crate) invalid_rows: Vec<(usize, String)>,
+    /// Rows that were accepted but have data problems
+    pub(crate) row_warnings: Vec<(usize, String)>,
+    pub(crate) duplicate_clusters: Vec<DuplicateCluster>,
+    /// Books without a language whose language was detected from their text
+    pub(crate) languages_detected: usize,
+    /// Used again after enrichment fills in missing categories
+    pub(crate) taxonomy: CategoryTaxonomy,
+}
+
+/// Parse, normalize, validate and deduplicate the input without touching any external service
+pub(crate) fn load_catalog(
+    source: &mut dyn IngestSource,
+    options: &CatalogOptions,
+) -> Result<ParsedCatalog> {
+    let author_aliases = bootstrap::load_author_aliases(options.author_aliases_path.as_ref());
+    let taxonomy = bootstrap::load_category_taxonomy(options.taxonomy_path.as_ref());
+    let awards = bootstrap::load_awards(options.awards_path.as_ref());
+
+    info!("Reading {} input...", source.name());
+
+    let mut catalog = ParsedCatalog::default();
+    let mut books = Vec::new();
+
+    // Process input records
+    for (row_index, result) in source.records()?.enumerate() {
+        let row = row_index + 1;
+        let record = match result {
+            Ok(record) => record,
+            Err(reason) => {
+                error!("Error parsing row {}: {}", row, reason);
+                catalog.invalid_rows.push((row, reason));
+                continue;
+            }
+        };
+
+        let problems = record.problems();
+
+        match record.into_book(row) {
+            Some(mut book) => {
+                book.author = book
+                    .author
+                    .map(|author| author_aliases.canonicalize(&author));
+                book.genres = taxonomy.classify(&book.categories);
+                book.awards = awards.awards_for(&book);
+                book.settings = book
+                    .description
+                    .as_deref()
+                    .map(settings_in_description)
+                    .unwrap_or_default();
+                flag_book(&mut book);
+                if tag_language(&mut book) {
+                    catalog.languages_detected += 1;
+                }
+                for problem in problems {
+                    catalog.row_warnings.push((row, problem));
+                }
+                books.push((row, book));
+            }
+            None => catalog
+                .invalid_rows
+                .push((row, "missing title".to_string())),
+        }
+
+        if books.len() % 1000 == 0 && !books.is_empty() {
+            info!("Processed {} books...", books.len());
+        }
+    }
+
+    info!("Parsing complete:");
+    info!("  ✅ Valid books: {}", books.len());
+    info!("  ❌ Skipped rows: {}", catalog.invalid_rows.len());
+    info!("  🌐 Languages detected: {}", catalog.languages_detected);
+
+    // Deduplicate books
+    let outcome = deduplicate(books, &options.dedup);
+    let duplicate_count: usize = outcome.clusters.iter().map(|c| c.rows.len() - 1).sum();
+    catalog.books = outcome.books;
+    catalog.duplicate_clusters = outcome.clusters;
+
+    info!("Deduplication complete:");
+    info!("  ✅ Unique books: {}", catalog.books.len());
+    info!("  🔄 Duplicates removed: {}", duplicate_count);
+
+    catalog.taxonomy = taxonomy;
+    Ok(catalog)
+}
+
+fn write_duplicates_report(path: &Path, clusters: &[DuplicateCluster]) -> Result<()> {
+    let file = File::create(path)
+        .with_context(|| format!("Failed to create duplicates report: {}", path.display()))?;
+    serde_json::to_writer_pretty(file, clusters).context("Failed to write duplicates report")?;
+    info!(
+        "Duplicates report ({} clusters) written to {}",
+        clusters.len(),
+        path.display()
+    );
+    Ok(())
+}
+
+/// Rough wall-clock time per batch per embedding worker: embedding call plus Pinecone upsert
+const ESTIMATED_SECONDS_PER_BATCH: f64 = 2.5;
+/// Rough price of hosted embedding inference, in USD per million tokens
+const ESTIMATED_COST_PER_MILLION_TOKENS: f64 = 0.02;
+/// Average characters per token for English text
+const CHARS_PER_TOKEN: f64 = 4.0;
+
+/// Print what a real run would do, without calling HuggingFace or Pinecone
+fn print_dry_run_report(catalog: &ParsedCatalog, options: &IndexOptions) -> Result<()> {
+    println!();
+    println!(
+        "Dry run report for {}",
+        options.catalog.input_path.display()
+    );
+    println!("==================================================");
+
+    println!();
+    println!("Rows");
+    println!("  Unique books:       {}", catalog.books.len());
+    println!("  Invalid rows:       {}", catalog.invalid_rows.len());
+    println!(
+        "  Duplicate rows:     {}",
+        catalog
+            .duplicate_clusters
+            .iter()
+            .map(|c| c.rows.len() - 1)
+            .sum::<usize>()
+    );
+    println!("  Rows with warnings: {}", catalog.row_warnings.len());
+    println!(
+        "  Award winners:      {}",
+        catalog
+            .books
+            .iter()
+            .filter(|book| !book.awards.is_empty())
+            .count()
+    );
+    println!(
+        "  Books with settings: {}",
+        catalog
+            .books
+            .iter()
+            .filter(|book| !book.settings.is_empty())
+            .count()
+    );
+    println!("  Languages detected: {}", catalog.languages_detected);
+    println!(
+        "  Flagged books:      {}",
+        catalog
+            .books
+            .iter()
+            .filter(|book| !book.content_flags.is_empty())
+            .count()
+    );
+
+    if !catalog.invalid_rows.is_empty() {
+        println!();
+        println!("Invalid rows");
+        for (row, reason) in &catalog.invalid_rows {
+            println!("  row {}: {}", row, reason);
+        }
+    }
+
+    if !catalog.row_warnings.is_empty() {
+        println!();
+        println!("Warnings");
+        for (row, problem) in &catalog.row_warnings {
+            println!("  row {}: {}", row, problem);
+        }
+    }
+
+    if !catalog.duplicate_clusters.is_empty() {
+        println!();
+        println!(
+            "Duplicate clusters (first row is kept, threshold {:.2}{})",
+            options.catalog.dedup.threshold,
+            if options.catalog.dedup.merge {
+                ", metadata merged"
+            } else {
+                ""
+            }
+        );
+        for cluster in &catalog.duplicate_clusters {
+            println!("  {} (similarity >= {:.2})", cluster.key, cluster.min_score);
+            for (row, title) in cluster.rows.iter().zip(&cluster.titles) {
+                println!("    row {}: {}", row, title);
+            }
+        }
+    }
+
+    let mut category_counts: HashMap<&str, usize> = HashMap::new();
+    for category in catalog.books.iter().flat_map(|b| &b.categories) {
+        *category_counts.entry(category.as_str()).or_default() += 1;
+    }
+    let mut categories: Vec<_> = category_counts.into_iter().collect();
+    categories.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+
+    println!();
+    println!("Category distribution ({} categories)", categories.len());
+    for (category, count) in &categories {
+        println!("  {:<40} {}", category, count);
+    }
+
+    if !catalog.taxonomy.is_empty() {
+        let mut genre_counts: HashMap<&str, usize> = HashMap::new();
+        let mut unmapped_counts: HashMap<&str, usize> = HashMap::new();
+        for book in &catalog.books {
+            for genre in &book.genres {
+                *genre_counts.entry(genre.as_str()).or_default() += 1;
+            }
+            for category in &book.categories {
+                if catalog.taxonomy.classify_category(category).is_empty() {
+                    *unmapped_counts.entry(category.as_str()).or_default() += 1;
+                }
+            }
+        }
+        let mut genres: Vec<_> = genre_counts.into_iter().collect();
+        genres.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        let mut unmapped: Vec<_> = unmapped_counts.into_iter().collect();
+        unmapped.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+
+        println!();
+        println!(
+            "Genre distribution ({} genres, {} books without a genre)",
+            genres.len(),
+            catalog.books.iter().filter(|b| b.genres.is_empty()).count()
+        );
+        for (genre, count) in &genres {
+            println!("  {:<40} {}", genre, count);
+        }
+
+        println!();
+        println!(
+            "Categories not in the taxonomy ({}, most common first)",
+            unmapped.len()
+        );
+        for (category, count) in unmapped.iter().take(UNMAPPED_CATEGORIES_SHOWN) {
+            println!("  {:<40} {}", category, count);
+        }
+    }
+
+    let enrichment_candidates = catalog
+        .books
+        .iter()
+        .filter(|book| needs_enrichment(book))
+        .count();
+    println!();
+    println!(
+        "Enrichment: {} books are missing metadata and have an ISBN{}",
+        enrichment_candidates,
+        if options.enrich {
+            " (cached lookups are not applied in a dry run)"
+        } else {
+            " (run with --enrich to fill them)"
+        }
+    );
+
+    let without_cover = catalog
+        .books
+        .iter()
+        .filter(|book| book.thumbnail.is_none())
+        .count();
+    let insecure_cover = catalog
+        .books
+        .iter()
+        .filter(|book| {
+            book.thumbnail
+                .as_deref()
+                .is_some_and(|url| url.trim().starts_with("http://"))
+        })
+        .count();
+    println!();
+    println!(
+        "Covers: {} books have no thumbnail and {} an http one{}",
+        without_cover,
+        insecure_cover,
+        if options.resolve_covers {
+            " (covers are not checked in a dry run)"
+        } else {
+            " (run with --resolve-covers to check and replace them)"
+        }
+    );
+
+    // In incremental mode only books that differ from the manifest would be embedded
+    let to_embed: Vec<&Book> = match IndexManifest::load(&options.catalog.manifest_path)? {
+        Some(manifest) if options.incremental => {
+            let mut pending = Vec::new();
+            for book in &catalog.books {
+                let hash = content_hash(book, &manifest.model).context("Failed to hash book")?;
+                let stored = book.id.as_ref().and_then(|id| manifest.books.get(id));
+                if stored != Some(&hash) {
+                    pending.push(book);
+                }
+            }
+            println!();
+            println!(
+                "Incremental: {} of {} books are new or changed since {}",
+                pending.len(),
+                catalog.books.len(),
+                options.catalog.manifest_path.display()
+            );
+            pending
+        }
+        None if options.incremental => {
+            println!();
+            println!(
+                "Incremental: no manifest at {}; a real run would compare against Pinecone metadata",
+                options.catalog.manifest_path.display()
+            );
+            catalog.books.iter().collect()
+        }
+        _ => catalog.books.iter().collect(),
+    };
+
+    let total_chars: usize = to_embed
+        .iter()
+        .map(|book| create_searchable_text(book).len())
+        .sum();
+    let estimated_tokens = (total_chars as f64 / CHARS_PER_TOKEN).ceil();
+    let batches = to_embed.len().div_ceil(options.pipeline.batch_size);
+    let estimated_seconds =
+        batches as f64 * ESTIMATED_SECONDS_PER_BATCH / options.pipeline.embed_concurrency as f64;
+
+    println!();
+    println!("Embedding estimate");
+    println!("  Books to embed:     {}", to_embed.len());
+    println!("  Batches:            {}", batches);
+    println!("  Tokens (approx):    {:.0}", estimated_tokens);
+    println!(
+        "  Cost (approx):      ${:.4}",
+        estimated_tokens / 1_000_000.0 * ESTIMATED_COST_PER_MILLION_TOKENS
+    );
+    println!(
+        "  Time (approx):      {}m {}s",
+        estimated_seconds as u64 / 60,
+        estimated_seconds as u64 % 60
+    );
+    println!();
+    println!("No external APIs were called.");
+
+    Ok(())
+}
+
+/// Embed and upsert the catalog, or only report on it for a dry run
+pub async fn run(config: &Config, options: IndexOptions) -> Result<()> {
+    // A dry run never calls the APIs
+    if !options.dry_run {
+        require_env(&[
+            "APP_HUGGINGFACE_API_KEY",
+            "APP_PINECONE_API_KEY",
+            "APP_PINECONE_ENV",
+            "APP_PINECONE_INDEX_NAME",
+        ])?;
+    }
+    if !options.catalog.input_path.exists() {
+        return Err(anyhow::anyhow!(
+            "Input file does not exist: {}",
+            options.catalog.input_path.display()
+        ));
+    }
+
+    info!("Starting book indexing process...");
+    info!(
+        "Input file: {} ({:?})",
+        options.catalog.input_path.display(),
+        options.catalog.format
+    );
+    if options.incremental {
+        info!("Incremental mode: only new or changed books will be indexed");
+    }
+
+    let mut source = open_source(
+        options.catalog.format,
+        &options.catalog.input_path,
+        options.catalog.authors_dump.clone(),
+    )?;
+    let catalog = load_catalog(source.as_mut(), &options.catalog)?;
+    if let Some(path) = &options.duplicates_report_path {
+        write_duplicates_report(path, &catalog.duplicate_clusters)?;
+    }
+
+    if options.dry_run {
+        print_dry_run_report(&catalog, &options)?;
+        return Ok(());
+    }
+
+    if catalog.books.is_empty() {
+        return Err(anyhow::anyhow!("No valid books found in input file"));
+    }
+    let mut unique_books = catalog.books;
+    let taxonomy = catalog.taxonomy;
+
+    if options.enrich {
+        info!("Enriching missing metadata from Google Books / Open Library...");
+        let mut enricher = MetadataEnricher::new(env::var("APP_GOOGLE_BOOKS_API_KEY").ok())?
+            .with_cache_file(&options.enrichment_cache_path)?;
+        let stats = enricher.enrich_all(&mut unique_books).await?;
+
+        info!("Enrichment complete:");
+        info!(
+            "  🔎 Candidates: {} ({} cached, {} looked up, {} failed)",
+            stats.candidates, stats.cache_hits, stats.lookups, stats.failures
+        );
+        info!("  ✨ Books enriched: {}", stats.enriched);
+        info!(
+            "  Filled: {} descriptions, {} thumbnails, {} page counts, {} category sets, {} region lists",
+            stats.descriptions,
+            stats.thumbnails,
+            stats.page_counts,
+            stats.categories,
+            stats.available_regions
+        );
+        info!("  Flagged as mature: {}", stats.mature);
+
+        // Enrichment may have replaced placeholder categories and filled descriptions
+        for book in &mut unique_books {
+            book.genres = taxonomy.classify(&book.categories);
+            if book.settings.is_empty() {
+                book.settings = book
+                    .description
+                    .as_deref()
+                    .map(settings_in_description)
+                    .unwrap_or_default();
+            }
+            flag_book(book);
+            // Descriptions filled in may be enough to tell the language
+            tag_language(book);
+        }
+    }
+
+    if options.resolve_covers {
+        info!("Checking cover thumbnails...");
+        let stats = CoverResolver::new()?.resolve_all(&mut unique_books).await;
+        info!("Cover check complete:");
+        info!(
+            "  🖼️  {} checked: {} kept, {} upgraded to https, {} replaced from Open Library",
+            stats.checked, stats.kept, stats.upgraded, stats.replaced
+        );
+        info!("  Without a cover: {}", stats.missing);
+    }
+
+    // Initialize services
+    info!("Initializing HuggingFace embedder...");
+    let embedder = bootstrap::init_embedder()
+        .await
+        .context("Failed to initialize HuggingFace embedder")?;
+
+    let (model_name, embedding_size) = embedder.model_info();
+    info!(
+        "Using model: {} ({}D embeddings)",
+        model_name, embedding_size
+    );
+
+    info!("Initializing Pinecone client...");
+    let pinecone = bootstrap::init_pinecone(config)
+        .await
+        .context("Failed to initialize Pinecone client")?;
+
+    // Hidden books stay out of the corpus snapshot and full-text index
+    let hidden: HashSet<String> = pinecone
+        .hidden_ids()
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to fetch hidden books from Pinecone: {}", e))?
+        .into_iter()
+        .collect();
+    if !hidden.is_empty() {
+        info!("{} books are hidden and stay hidden", hidden.len());
+    }
+
+    // Hash every book so unchanged ones can be skipped on the next run
+    let hashes: Vec<String> = unique_books
+        .iter()
+        .map(|book| content_hash(book, &model_name))
+        .collect::<crate::error::Result<_>>()
+        .context("Failed to hash books")?;
+
+    let mut manifest = match IndexManifest::load(&options.catalog.manifest_path)? {
+        Some(manifest) if manifest.model == model_name => manifest,
+        Some(manifest) => {
+            warn!(
+                "Manifest was built with model '{}', current model is '{}'. All books will be re-embedded",
+                manifest.model, model_name
+            );
+            IndexManifest::default()
+        }
+        None if options.incremental => {
+            info!(
+                "No manifest at {}, reading stored hashes from Pinecone metadata...",
+                options.catalog.manifest_path.display()
+            );
+            let ids: Vec<String> = unique_books.iter().filter_map(|b| b.id.clone()).collect();
+            IndexManifest {
+                model: model_name.to_string(),
+                books: fetch_stored_hashes(&pinecone, &ids).await?,
+            }
+        }
+        None => IndexManifest::default(),
+    };
+    manifest.model = model_name.to_string();
+    // Ids indexed by earlier runs, so the snapshot can list the books this run adds
+    let previously_indexed: HashSet<String> = manifest.books.keys().cloned().collect();
+
+    let pending: Vec<(Book, String)> = unique_books
+        .iter()
+        .cloned()
+        .zip(hashes)
+        .filter(|(book, hash)| {
+            !options.incremental
+                || book
+                    .id
+                    .as_ref()
+                    .and_then(|id| manifest.books.get(id))
+                    .is_none_or(|stored| stored != hash)
+        })
+        .collect();
+
+    if options.incremental {
+        let new_count = pending
+            .iter()
+            .filter(|(book, _)| {
+                book.id
+                    .as_ref()
+                    .is_none_or(|id| !manifest.books.contains_key(id))
+            })
+            .count();
+        info!("Change detection complete:");
+        info!("  🆕 New books: {}", new_count);
+        info!("  ✏️  Changed books: {}", pending.len() - new_count);
+        info!(
+            "  ⏭️  Unchanged books: {}",
+            unique_books.len() - pending.len()
+        );
+    }
+
+    if pending.is_empty() {
+        info!("Nothing to index, the index is up to date");
+        manifest.save(&options.catalog.manifest_path)?;
+        // The hidden set or catalog may still have changed since the snapshots were written
+        return write_snapshots(&options, &unique_books, &hidden, &[]);
+    }
+
+    // Mood prototypes are embedded once per run; without them moods come from keywords only
+    let moods = match MoodClassifier::new(&embedder).await {
+        Ok(classifier) => Some(Arc::new(classifier)),
+        Err(e) => {
+            warn!(
+                "Failed to embed mood prototypes, tagging moods from keywords only: {}",
+                e
+            );
+            None
+        }
+    };
+
+    // Versions of the books whose metadata changes are recorded when there's a database
+    let history = match &config.database_url {
+        Some(database_url) => match BookHistory::connect(database_url).await {
+            Ok(history) => Some(history),
+            Err(e) => {
+                warn!(
+                    "Failed to connect to the database, book versions won't be recorded: {}",
+                    e
+                );
+                None
+            }
+        },
+        None => None,
+    };
+
+    // Embed and upsert concurrently
+    let pending_count = pending.len();
+    let mut pipeline = spawn_pipeline(
+        pending,
+        Arc::new(embedder),
+        pinecone,
+        create_searchable_text,
+        moods,
+        history,
+        options.pipeline,
+    );
+    let total_batches = pipeline.total_batches;
+    let mut progress =
+        ProgressTracker::new(&options.catalog.input_path, pending_count, total_batches);
+    if let Some(path) = &options.status_file {
+        progress = progress.with_status_file(path);
+    }
+    // A failed report shouldn't cost a long run; the next one may get through
+    if let Err(e) = progress.report() {
+        warn!("Couldn't report indexing progress: {}", e);
+    }
+
+    let mut failed_books = 0;
+    let mut completed_batches = 0;
+    let mut new_books = Vec::new();
+
+    while let Some(outcome) = pipeline.outcomes.recv().await {
+        completed_batches += 1;
+        let batch = outcome.batch;
+
+        match outcome.result {
+            Ok(()) => {
+                for (book, hash) in &batch.items {
+                    if let Some(id) = &book.id {
+                        manifest.books.insert(id.clone(), hash.clone());
+                        if !previously_indexed.contains(id) {
+                            new_books.push(book.clone());
+                        }
+                    }
+                }
+                debug!(
+                    "Indexed batch {} ({} books)",
+                    batch.index + 1,
+                    batch.items.len()
+                );
+            }
+            Err(e) => {
+                error!("❌ Failed to index batch {}: {}", batch.index + 1, e);
+                failed_books += batch.items.len();
+                progress.record_error(format!("batch {}: {}", batch.index + 1, e));
+            }
+        }
+
+        progress.update(
+            pipeline.counters.embedded(),
+            pipeline.counters.upserted(),
+            failed_books,
+            completed_batches,
+        );
+        if let Err(e) = progress.report() {
+            warn!("Couldn't report indexing progress: {}", e);
+        }
+
+        // Persist progress so an interrupted incremental run resumes where it stopped
+        if completed_batches % MANIFEST_SAVE_INTERVAL == 0 {
+            manifest.save(&options.catalog.manifest_path)?;
+        }
+    }
+
+    let successfully_indexed = pipeline.counters.upserted();
+    progress.finish(if failed_books == 0 {
+        IndexRunState::Completed
+    } else {
+        IndexRunState::Failed
+    })?;
+
+    manifest
+        .save(&options.catalog.manifest_path)
+        .context("Failed to save index manifest")?;
+    info!(
+        "Manifest written to {}",
+        options.catalog.manifest_path.display()
+    );
+
+    if !new_books.is_empty() {
+        if let Some(publisher) = bootstrap::init_catalog_events(config).await {
+            publisher
+                .publish(CatalogEvent::BooksAdded {
+                    book_ids: new_books
+                        .iter()
+                        .filter_map(|book| book.id.clone())
+                        .collect(),
+                })
+                .await;
+        }
+    }
+
+    // Final statistics
+    info!("🎉 Indexing process completed!");
+    info!("  📚 Total processed: {}", pending_count);
+    info!("  ✅ Successfully indexed: {}", successfully_indexed);
+    info!(
+        "  ❌ Failed to index: {}",
+        pending_count - successfully_indexed
+    );
+
+    write_snapshots(&options, &unique_books, &hidden, &new_books)
+}
+
+/// Write the corpus statistics snapshot and the full-text index of the books
+/// that aren't hidden, listing `new_books` as recently indexed
+fn write_snapshots(
+    options: &IndexOptions,
+    unique_books: &[Book],
+    hidden: &HashSet<String>,
+    new_books: &[Book],
+) -> Result<()> {
+    let visible_books: Vec<Book> = unique_books
+        .iter()
+        .filter(|book| book.id.as_ref().is_none_or(|id| !hidden.contains(id)))
+        .cloned()
+        .collect();
+
+    // Generate some statistics about the indexed books
+    let mut stats = CorpusStats::build(&visible_books);
+    let avg_rating = unique_books
+        .iter()
+        .map(|b| b.rating)
+        .filter(|&r| r > 0.0)
+        .collect::<Vec<_>>();
+
+    info!("📊 Dataset statistics:");
+    info!("  Authors: {}", stats.authors);
+    info!("  Categories: {}", stats.categories);
+    if !avg_rating.is_empty() {
+        let avg = avg_rating.iter().sum::<f32>() / avg_rating.len() as f32;
+        info!("  Average rating: {:.2}", avg);
+    }
+
+    if let Some(path) = &options.corpus_stats_path {
+        let earlier = if path.exists() {
+            CorpusStats::load(path)
+                .map(|previous| previous.recently_indexed)
+                .unwrap_or_else(|e| {
+                    warn!("Previous corpus statistics are unreadable, starting a new list of indexed books: {}", e);
+                    Vec::new()
+                })
+        } else {
+            Vec::new()
+        };
+        stats.record_indexed(new_books, earlier);
+        info!("  New books: {}", new_books.len());
+        stats
+            .save(path)
+            .context("Failed to save corpus statistics")?;
+        info!(
+            "Corpus statistics written to {}; restart the API to load them",
+            path.display()
+        );
+    }
+
+    if let Some(path) = &options.fulltext_index_path {
+        let indexed = FullTextIndex::build(path, &visible_books)
+            .context("Failed to write the full-text index")?;
+        info!(
+            "Full-text index of {} books written to {}; restart the API to open it",
+            indexed,
+            path.display()
+        );
+    }
+
+    Ok(())
+}
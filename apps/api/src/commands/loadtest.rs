@@ -0,0 +1,382 @@
+//! `loadtest`: replay a query log against a running API server
+//!
+//! Unlike the maintenance commands this needs no configuration or upstream
+//! credentials: it only sends recommendation requests over HTTP, `concurrency`
+//! at a time, and reports their latency percentiles, cache hit rate and errors.
+//! Requests aren't retried, so throttling and overload show up as errors.
+
+use anyhow::{Context, Result};
+use log::{info, warn};
+use recommend_a_book_client::{Client, ClientError, RecommendationRequest, RetryPolicy};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Requests in flight at once when not set
+pub const DEFAULT_CONCURRENCY: usize = 8;
+
+/// Results asked for per query when the log doesn't say
+pub const DEFAULT_TOP_K: usize = 10;
+
+/// Options for the `loadtest` command
+#[derive(Debug, Clone)]
+pub struct LoadTestOptions {
+    /// Base URL of the API server, e.g. `http://localhost:8000`
+    pub api_url: String,
+    /// Query log to replay
+    pub query_log: PathBuf,
+    /// Requests in flight at once
+    pub concurrency: usize,
+    /// Requests to send, cycling through the log; one pass over it when unset
+    pub requests: Option<usize>,
+    /// Results asked for by queries that don't set their own
+    pub top_k: usize,
+    /// Timeout of each request
+    pub timeout: Duration,
+    /// Write the report here as JSON
+    pub output_path: Option<PathBuf>,
+}
+
+/// One query of a query log
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct LoggedQuery {
+    pub query: String,
+    #[serde(default)]
+    pub top_k: Option<usize>,
+}
+
+/// Read a query log: one query per line, either as plain text or as a JSON
+/// object with a `query` (and optionally `top_k`) field, like the entries of
+/// the slow query log. Blank lines and lines starting with `#` are skipped.
+pub fn parse_query_log(contents: &str) -> Result<Vec<LoggedQuery>> {
+    let mut queries = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let query = if line.starts_with('{') {
+            serde_json::from_str(line)
+                .with_context(|| format!("Invalid query on line {}", index + 1))?
+        } else {
+            LoggedQuery {
+                query: line.to_string(),
+                top_k: None,
+            }
+        };
+        if !query.query.trim().is_empty() {
+            queries.push(query);
+        }
+    }
+    Ok(queries)
+}
+
+/// Latency percentiles in milliseconds
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Percentiles {
+    pub p50: u64,
+    pub p90: u64,
+    pub p95: u64,
+    pub p99: u64,
+    pub max: u64,
+}
+
+impl Percentiles {
+    /// Nearest-rank percentiles of `samples`; zeros when there are none
+    pub fn of(samples: &mut [u64]) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+        samples.sort_unstable();
+        let at = |percentile: f64| {
+            let rank = (percentile / 100.0 * samples.len() as f64).ceil() as usize;
+            samples[rank.clamp(1, samples.len()) - 1]
+        };
+        Self {
+            p50: at(50.0),
+            p90: at(90.0),
+            p95: at(95.0),
+            p99: at(99.0),
+            max: samples[samples.len() - 1],
+        }
+    }
+}
+
+/// How one request went
+#[derive(Debug, Clone)]
+enum Outcome {
+    Answered {
+        latency_ms: u64,
+        server_ms: u64,
+        cache_hit: bool,
+        search_path: String,
+        degraded: bool,
+    },
+    /// Failed with this HTTP status, or without one when the request timed out
+    /// or couldn't be sent
+    Failed { status: Option<u16> },
+}
+
+/// Summary of a load test run
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LoadTestReport {
+    pub requests: usize,
+    pub concurrency: usize,
+    pub elapsed_ms: u64,
+    /// Requests completed per second
+    pub throughput: f64,
+    pub succeeded: usize,
+    /// Failed requests by HTTP status; `network` for timeouts and connection errors
+    pub errors: BTreeMap<String, usize>,
+    /// Share of answered requests served from the result cache
+    pub cache_hit_rate: f64,
+    /// Answered requests by the search that answered them
+    pub search_paths: BTreeMap<String, usize>,
+    /// Answered requests served by the fallback search
+    pub degraded: usize,
+    /// Latency seen by the client, of answered requests
+    pub latency_ms: Percentiles,
+    /// Latency reported by the server (`took_ms`), of answered requests
+    pub server_ms: Percentiles,
+}
+
+impl LoadTestReport {
+    fn from_outcomes(outcomes: Vec<Outcome>, concurrency: usize, elapsed: Duration) -> Self {
+        let mut report = Self {
+            requests: outcomes.len(),
+            concurrency,
+            elapsed_ms: elapsed.as_millis() as u64,
+            throughput: outcomes.len() as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+            ..Self::default()
+        };
+        let mut latencies = Vec::with_capacity(outcomes.len());
+        let mut server_latencies = Vec::with_capacity(outcomes.len());
+        let mut cache_hits = 0;
+        for outcome in outcomes {
+            match outcome {
+                Outcome::Answered {
+                    latency_ms,
+                    server_ms,
+                    cache_hit,
+                    search_path,
+                    degraded,
+                } => {
+                    report.succeeded += 1;
+                    latencies.push(latency_ms);
+                    server_latencies.push(server_ms);
+                    cache_hits += usize::from(cache_hit);
+                    report.degraded += usize::from(degraded);
+                    *report.search_paths.entry(search_path).or_default() += 1;
+                }
+                Outcome::Failed { status } => {
+                    let key = status.map_or_else(|| "network".to_string(), |s| s.to_string());
+                    *report.errors.entry(key).or_default() += 1;
+                }
+            }
+        }
+        if report.succeeded > 0 {
+            report.cache_hit_rate = cache_hits as f64 / report.succeeded as f64;
+        }
+        report.latency_ms = Percentiles::of(&mut latencies);
+        report.server_ms = Percentiles::of(&mut server_latencies);
+        report
+    }
+
+    fn log(&self) {
+        let failed: usize = self.errors.values().sum();
+        info!(
+            "{} requests at concurrency {} in {:.1}s ({:.1} req/s): {} ok, {} failed",
+            self.requests,
+            self.concurrency,
+            self.elapsed_ms as f64 / 1000.0,
+            self.throughput,
+            self.succeeded,
+            failed
+        );
+        for (label, latency) in [("client", &self.latency_ms), ("server", &self.server_ms)] {
+            info!(
+                "  {} latency ms: p50 {} | p90 {} | p95 {} | p99 {} | max {}",
+                label, latency.p50, latency.p90, latency.p95, latency.p99, latency.max
+            );
+        }
+        info!(
+            "  cache hit rate {:.1}%, {} degraded",
+            self.cache_hit_rate * 100.0,
+            self.degraded
+        );
+        for (path, count) in &self.search_paths {
+            info!("  search path {}: {}", path, count);
+        }
+        for (status, count) in &self.errors {
+            warn!("  errors {}: {}", status, count);
+        }
+    }
+}
+
+async fn send(client: &Client, query: &LoggedQuery, default_top_k: usize) -> Outcome {
+    let request = RecommendationRequest::new(query.query.clone())
+        .with_top_k(query.top_k.unwrap_or(default_top_k));
+    let started = Instant::now();
+    let result = client.recommendations(&request).await;
+    let latency_ms = started.elapsed().as_millis() as u64;
+    match result {
+        Ok(response) => Outcome::Answered {
+            latency_ms,
+            server_ms: response.took_ms,
+            cache_hit: response.cache == "hit",
+            search_path: response.search_path,
+            degraded: response.degraded,
+        },
+        Err(e) => Outcome::Failed {
+            status: match e {
+                ClientError::Api { status, .. } => Some(status),
+                ClientError::Http(e) => e.status().map(|status| status.as_u16()),
+            },
+        },
+    }
+}
+
+async fn read_query_log(path: &Path) -> Result<Vec<LoggedQuery>> {
+    let contents = tokio::fs::read_to_string(path)
+        .await
+        .with_context(|| format!("Failed to read query log {}", path.display()))?;
+    let queries = parse_query_log(&contents)?;
+    anyhow::ensure!(!queries.is_empty(), "{} has no queries", path.display());
+    Ok(queries)
+}
+
+pub async fn run(options: LoadTestOptions) -> Result<LoadTestReport> {
+    let queries = Arc::new(read_query_log(&options.query_log).await?);
+    let total = options.requests.unwrap_or(queries.len());
+    let concurrency = options.concurrency.max(1);
+    let client = Client::new(options.api_url)
+        .with_timeout(options.timeout)
+        .with_retry_policy(RetryPolicy::none());
+    info!(
+        "Replaying {} requests from {} queries against {} at concurrency {}",
+        total,
+        queries.len(),
+        client.base_url(),
+        concurrency
+    );
+
+    // Workers take the next request until all have been sent, so a slow
+    // request holds up only its own worker
+    let next = Arc::new(AtomicUsize::new(0));
+    let started = Instant::now();
+    let workers: Vec<_> = (0..concurrency)
+        .map(|_| {
+            let client = client.clone();
+            let queries = Arc::clone(&queries);
+            let next = Arc::clone(&next);
+            let top_k = options.top_k;
+            tokio::spawn(async move {
+                let mut outcomes = Vec::new();
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    if index >= total {
+                        break outcomes;
+                    }
+                    outcomes.push(send(&client, &queries[index % queries.len()], top_k).await);
+                }
+            })
+        })
+        .collect();
+
+    let mut outcomes = Vec::with_capacity(total);
+    for worker in workers {
+        outcomes.extend(worker.await.context("Load test worker panicked")?);
+    }
+    let report = LoadTestReport::from_outcomes(outcomes, concurrency, started.elapsed());
+    report.log();
+
+    if let Some(path) = &options.output_path {
+        let json = serde_json::to_string_pretty(&report)?;
+        tokio::fs::write(path, json)
+            .await
+            .with_context(|| format!("Failed to write report to {}", path.display()))?;
+        info!("Report written to {}", path.display());
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_log_accepts_plain_and_json_lines() {
+        let log = "# warm-up set\ncozy fantasy with dragons\n\n\
+                   {\"query\": \"books like dune\", \"top_k\": 5}\n\
+                   {\"query\": \"space opera\", \"top_k\": 20, \"total_ms\": 4210}\n";
+        let queries = parse_query_log(log).unwrap();
+        assert_eq!(
+            queries,
+            vec![
+                LoggedQuery {
+                    query: "cozy fantasy with dragons".to_string(),
+                    top_k: None,
+                },
+                LoggedQuery {
+                    query: "books like dune".to_string(),
+                    top_k: Some(5),
+                },
+                LoggedQuery {
+                    query: "space opera".to_string(),
+                    top_k: Some(20),
+                },
+            ]
+        );
+        assert!(parse_query_log("{\"top_k\": 5}").is_err());
+    }
+
+    #[test]
+    fn test_percentiles_use_nearest_rank() {
+        let mut samples: Vec<u64> = (1..=100).rev().collect();
+        let percentiles = Percentiles::of(&mut samples);
+        assert_eq!(
+            percentiles,
+            Percentiles {
+                p50: 50,
+                p90: 90,
+                p95: 95,
+                p99: 99,
+                max: 100,
+            }
+        );
+        assert_eq!(Percentiles::of(&mut [7]).p99, 7);
+        assert_eq!(Percentiles::of(&mut []), Percentiles::default());
+    }
+
+    #[test]
+    fn test_report_counts_cache_hits_and_errors() {
+        let answered = |cache_hit| Outcome::Answered {
+            latency_ms: 40,
+            server_ms: 30,
+            cache_hit,
+            search_path: "vector".to_string(),
+            degraded: false,
+        };
+        let outcomes = vec![
+            answered(true),
+            answered(false),
+            answered(true),
+            answered(true),
+            Outcome::Failed { status: Some(503) },
+            Outcome::Failed { status: None },
+        ];
+        let report = LoadTestReport::from_outcomes(outcomes, 2, Duration::from_secs(2));
+        assert_eq!(report.requests, 6);
+        assert_eq!(report.succeeded, 4);
+        assert_eq!(report.throughput, 3.0);
+        assert_eq!(report.cache_hit_rate, 0.75);
+        assert_eq!(report.search_paths["vector"], 4);
+        assert_eq!(report.errors["503"], 1);
+        assert_eq!(report.errors["network"], 1);
+        assert_eq!(report.latency_ms.max, 40);
+    }
+}
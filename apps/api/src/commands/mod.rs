@@ -0,0 +1,39 @@
+//! Maintenance commands run by the `rab-admin` CLI
+//!
+//! Each command takes the loaded [`Config`](crate::config::Config) plus its own
+//! options struct, and initializes services through
+//! [`bootstrap`](crate::services::bootstrap) so it talks to the same upstreams,
+//! with the same settings, as the API server. [`loadtest`] is the exception: it
+//! has its own binary and only talks to a running server over HTTP.
+
+pub mod check_config;
+pub mod clear_cache;
+pub mod co_shelving;
+pub mod digest;
+pub mod evaluate;
+pub mod graph;
+pub mod index;
+pub mod loadtest;
+pub mod prune;
+pub mod tag_moods;
+
+use anyhow::Result;
+use std::env;
+
+/// Fail with the names of any missing environment variables
+pub(crate) fn require_env(vars: &[&str]) -> Result<()> {
+    let missing: Vec<&str> = vars
+        .iter()
+        .copied()
+        .filter(|var| env::var(var).map_or(true, |value| value.trim().is_empty()))
+        .collect();
+
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!(
+            "Missing required environment variables: {}",
+            missing.join(", ")
+        ))
+    }
+}
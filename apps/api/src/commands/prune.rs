@@ -0,0 +1,202 @@
+//! `rab-admin prune`: delete vectors and graph nodes for books removed from the catalog
+
+use super::index::{load_catalog, CatalogOptions, IndexManifest};
+use super::require_env;
+use crate::{
+    config::Config,
+    ingest::open_source,
+    services::{bootstrap, catalog_events::CatalogEvent},
+};
+use anyhow::{Context, Result};
+use log::{info, warn};
+use std::collections::HashSet;
+
+/// Pinecone accepts at most this many ids per delete request
+const DELETE_BATCH_SIZE: usize = 1000;
+
+/// Options for the `prune` command
+#[derive(Debug, Clone)]
+pub struct PruneOptions {
+    pub catalog: CatalogOptions,
+    /// Actually delete; without it only the report is printed
+    pub confirm: bool,
+    /// Also remove stale book nodes from Neo4j
+    pub graph: bool,
+}
+
+/// Ids present in the index but missing from the catalog, sorted
+fn stale_ids(stored: impl IntoIterator<Item = String>, catalog_ids: &HashSet<&str>) -> Vec<String> {
+    let mut stale: Vec<String> = stored
+        .into_iter()
+        .filter(|id| !catalog_ids.contains(id.as_str()))
+        .collect();
+    stale.sort();
+    stale.dedup();
+    stale
+}
+
+/// Remove vectors and graph nodes for books that are no longer in the source catalog
+pub async fn run(config: &Config, options: PruneOptions) -> Result<()> {
+    require_env(&[
+        "APP_PINECONE_API_KEY",
+        "APP_PINECONE_ENV",
+        "APP_PINECONE_INDEX_NAME",
+    ])?;
+
+    info!("Starting prune of removed catalog entries...");
+    info!(
+        "Input file: {} ({:?})",
+        options.catalog.input_path.display(),
+        options.catalog.format
+    );
+
+    let mut source = open_source(
+        options.catalog.format,
+        &options.catalog.input_path,
+        options.catalog.authors_dump.clone(),
+    )?;
+    let catalog = load_catalog(source.as_mut(), &options.catalog)?;
+
+    // An empty or unreadable catalog would otherwise wipe the whole index
+    if catalog.books.is_empty() {
+        return Err(anyhow::anyhow!(
+            "No valid books found in input file, refusing to prune"
+        ));
+    }
+    let catalog_ids: HashSet<&str> = catalog
+        .books
+        .iter()
+        .filter_map(|book| book.id.as_deref())
+        .collect();
+
+    info!("Initializing Pinecone client...");
+    let pinecone = bootstrap::init_pinecone(config)
+        .await
+        .context("Failed to initialize Pinecone client")?;
+
+    let mut manifest = IndexManifest::load(&options.catalog.manifest_path)?;
+
+    info!("Listing vectors in Pinecone...");
+    let stored_ids = match pinecone.list_ids().await {
+        Ok(ids) => ids,
+        Err(e) => {
+            // Pod-based indexes can't list vectors; the manifest is the next best record
+            let Some(manifest) = &manifest else {
+                return Err(anyhow::anyhow!(
+                    "Failed to list Pinecone vectors ({}) and no manifest at {}",
+                    e,
+                    options.catalog.manifest_path.display()
+                ));
+            };
+            warn!(
+                "Failed to list Pinecone vectors ({}), using ids from {}",
+                e,
+                options.catalog.manifest_path.display()
+            );
+            manifest.books.keys().cloned().collect()
+        }
+    };
+    let stored_count = stored_ids.len();
+    let stale_vectors = stale_ids(stored_ids, &catalog_ids);
+
+    let graph = if options.graph {
+        let neo4j = bootstrap::init_neo4j(config)
+            .await
+            .context("Failed to initialize Neo4j client")?;
+        let node_ids = neo4j.list_book_ids().await?;
+        let node_count = node_ids.len();
+        let stale_nodes = stale_ids(node_ids, &catalog_ids);
+        Some((neo4j, node_count, stale_nodes))
+    } else {
+        None
+    };
+
+    println!();
+    println!("Prune report for {}", options.catalog.input_path.display());
+    println!("==================================================");
+    println!("  Books in catalog:     {}", catalog_ids.len());
+    println!("  Vectors in Pinecone:  {}", stored_count);
+    println!("  Stale vectors:        {}", stale_vectors.len());
+    if let Some((_, node_count, stale_nodes)) = &graph {
+        println!("  Book nodes in Neo4j:  {}", node_count);
+        println!("  Stale nodes:          {}", stale_nodes.len());
+    }
+    if !catalog.invalid_rows.is_empty() {
+        println!();
+        println!(
+            "Warning: {} rows could not be parsed; books on those rows will be pruned",
+            catalog.invalid_rows.len()
+        );
+    }
+
+    if !stale_vectors.is_empty() {
+        println!();
+        println!("Stale vectors");
+        for id in &stale_vectors {
+            println!("  {}", id);
+        }
+    }
+    if let Some((_, _, stale_nodes)) = &graph {
+        if !stale_nodes.is_empty() {
+            println!();
+            println!("Stale graph nodes");
+            for id in stale_nodes {
+                println!("  {}", id);
+            }
+        }
+    }
+
+    if !options.confirm {
+        println!();
+        println!("Nothing was deleted. Run again with --confirm to delete the entries above.");
+        return Ok(());
+    }
+
+    for (i, chunk) in stale_vectors.chunks(DELETE_BATCH_SIZE).enumerate() {
+        pinecone
+            .delete_vectors(chunk)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to delete vectors: {}", e))?;
+        info!(
+            "🗑️  Deleted vector batch {}/{}",
+            i + 1,
+            stale_vectors.len().div_ceil(DELETE_BATCH_SIZE)
+        );
+    }
+
+    if let Some((neo4j, _, stale_nodes)) = &graph {
+        if !stale_nodes.is_empty() {
+            neo4j.delete_books(stale_nodes).await?;
+        }
+    }
+
+    // Forget pruned books so the next incremental run doesn't think they're indexed
+    if let Some(manifest) = &mut manifest {
+        for id in &stale_vectors {
+            manifest.books.remove(id);
+        }
+        manifest.save(&options.catalog.manifest_path)?;
+    }
+
+    let mut removed: Vec<String> = stale_vectors.clone();
+    if let Some((_, _, stale_nodes)) = &graph {
+        removed.extend(stale_nodes.iter().cloned());
+    }
+    removed.sort();
+    removed.dedup();
+    if !removed.is_empty() {
+        if let Some(publisher) = bootstrap::init_catalog_events(config).await {
+            publisher
+                .publish(CatalogEvent::BooksRemoved { book_ids: removed })
+                .await;
+        }
+    }
+
+    info!("🎉 Prune completed!");
+    info!("  🗑️  Vectors deleted: {}", stale_vectors.len());
+    if let Some((_, _, stale_nodes)) = &graph {
+        info!("  🗑️  Graph nodes deleted: {}", stale_nodes.len());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,133 @@
+//! `rab-admin tag-moods`: tag already indexed books with moods
+//!
+//! New books are tagged while they are indexed; this backfills books indexed
+//! before moods existed, or re-tags the index after the vocabulary changes,
+//! without re-embedding anything.
+
+use super::require_env;
+use crate::{
+    config::Config,
+    ingest::moods::{tag_moods, MoodClassifier},
+    services::bootstrap,
+};
+use anyhow::{Context, Result};
+use log::{info, warn};
+use serde_json::json;
+use std::collections::BTreeMap;
+
+/// Books fetched from Pinecone per request
+const FETCH_BATCH_SIZE: usize = 100;
+
+/// Options for the `tag-moods` command
+#[derive(Debug, Clone, Default)]
+pub struct TagMoodsOptions {
+    /// Print the report without updating any metadata
+    pub dry_run: bool,
+}
+
+/// Tag every book in the index with its moods, updating only books whose moods changed
+pub async fn run(config: &Config, options: TagMoodsOptions) -> Result<()> {
+    require_env(&[
+        "APP_HUGGINGFACE_API_KEY",
+        "APP_PINECONE_API_KEY",
+        "APP_PINECONE_ENV",
+        "APP_PINECONE_INDEX_NAME",
+    ])?;
+
+    info!("Initializing Pinecone client...");
+    let pinecone = bootstrap::init_pinecone(config)
+        .await
+        .context("Failed to initialize Pinecone client")?;
+
+    info!("Initializing HuggingFace embedder...");
+    let embedder = bootstrap::init_embedder()
+        .await
+        .context("Failed to initialize HuggingFace embedder")?;
+    let classifier = match MoodClassifier::new(&embedder).await {
+        Ok(classifier) => Some(classifier),
+        Err(e) => {
+            warn!(
+                "Failed to embed mood prototypes ({}), tagging from descriptions only",
+                e
+            );
+            None
+        }
+    };
+
+    info!("Listing vectors in Pinecone...");
+    let ids = pinecone
+        .list_ids()
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to list Pinecone vectors: {}", e))?;
+    info!("Found {} vectors", ids.len());
+
+    let mut tagged = 0;
+    let mut changed = 0;
+    let mut failed = 0;
+    let mut mood_counts: BTreeMap<String, usize> = BTreeMap::new();
+    for (i, chunk) in ids.chunks(FETCH_BATCH_SIZE).enumerate() {
+        let books = pinecone.fetch_books(chunk).await?;
+        let vectors = if classifier.is_some() {
+            pinecone.fetch_vectors(chunk).await?
+        } else {
+            Default::default()
+        };
+
+        for (id, book) in &books {
+            let moods = tag_moods(
+                book,
+                vectors.get(id).map(Vec::as_slice),
+                classifier.as_ref(),
+            );
+            if !moods.is_empty() {
+                tagged += 1;
+            }
+            for mood in &moods {
+                *mood_counts.entry(mood.clone()).or_default() += 1;
+            }
+            if moods == book.moods {
+                continue;
+            }
+
+            changed += 1;
+            if options.dry_run {
+                continue;
+            }
+            if let Err(e) = pinecone
+                .update_metadata(id, &json!({ "moods": moods }))
+                .await
+            {
+                warn!("Failed to update moods of {}: {}", id, e);
+                failed += 1;
+            }
+        }
+        info!(
+            "Processed batch {}/{}",
+            i + 1,
+            ids.len().div_ceil(FETCH_BATCH_SIZE)
+        );
+    }
+
+    println!();
+    println!("Mood tagging report");
+    println!("==================================================");
+    println!("  Books in index:     {}", ids.len());
+    println!("  Books with moods:   {}", tagged);
+    println!("  Changed tags:       {}", changed);
+    if failed > 0 {
+        println!("  Failed updates:     {}", failed);
+    }
+    if !mood_counts.is_empty() {
+        println!();
+        println!("Books per mood");
+        for (mood, count) in &mood_counts {
+            println!("  {:<14} {}", mood, count);
+        }
+    }
+
+    if options.dry_run {
+        println!();
+        println!("Nothing was updated. Run again without --dry-run to store the tags.");
+    }
+    Ok(())
+}
@@ -1,7 +1,69 @@
+use crate::cache::DEFAULT_DISK_CACHE_MEMORY_MB;
+use crate::services::event_buffer::DEFAULT_EVENTS_FLUSH_INTERVAL_SECS;
+use crate::services::feature_flags::{FeatureFlagsConfig, Flag};
+use crate::services::load_shedding::DEFAULT_MAX_QUEUED_RECOMMENDATIONS;
+use crate::services::maintenance::{MaintenanceConfig, MaintenanceTask};
+use crate::services::post_filters::PostFilterConfig;
+use crate::services::prewarm::{
+    DEFAULT_IDLE_TIMEOUT_MINUTES, DEFAULT_PREWARM_INTERVAL_MINUTES, DEFAULT_WARMUP_QUERIES,
+};
+use crate::services::query_preloader::{DEFAULT_PRELOAD_QUERY_COUNT, DEFAULT_PRELOAD_WINDOW_HOURS};
+use crate::services::ranking::Ranker;
+use crate::services::recommendation::DEFAULT_MAX_CONCURRENT_RECOMMENDATIONS;
+use crate::services::retrieval::RetrievalConfig;
+use crate::services::session_store::DEFAULT_SESSION_TTL_MINUTES;
+use crate::services::shadow::ShadowConfig;
+use crate::services::slow_query_log::{
+    DEFAULT_SLOW_QUERY_LOG_CAPACITY, DEFAULT_SLOW_QUERY_THRESHOLD_MS,
+};
+use crate::services::usage::TenantConfig;
 use anyhow::Result;
 use config::{Config as ConfigFile, Environment, File, Source};
 use serde::Deserialize;
-use std::{env, path::PathBuf};
+use std::{env, path::PathBuf, time::Duration};
+
+fn default_slow_query_threshold_ms() -> u64 {
+    DEFAULT_SLOW_QUERY_THRESHOLD_MS
+}
+
+fn default_slow_query_log_capacity() -> usize {
+    DEFAULT_SLOW_QUERY_LOG_CAPACITY
+}
+
+fn default_session_ttl_minutes() -> u64 {
+    DEFAULT_SESSION_TTL_MINUTES
+}
+
+fn default_events_flush_interval_secs() -> u64 {
+    DEFAULT_EVENTS_FLUSH_INTERVAL_SECS
+}
+
+fn default_cache_dir_memory_mb() -> u64 {
+    DEFAULT_DISK_CACHE_MEMORY_MB
+}
+
+fn default_warmup_queries() -> Vec<String> {
+    DEFAULT_WARMUP_QUERIES
+        .iter()
+        .map(|query| query.to_string())
+        .collect()
+}
+
+fn default_prewarm_interval_minutes() -> u64 {
+    DEFAULT_PREWARM_INTERVAL_MINUTES
+}
+
+fn default_idle_timeout_minutes() -> u64 {
+    DEFAULT_IDLE_TIMEOUT_MINUTES
+}
+
+fn default_preload_query_count() -> usize {
+    DEFAULT_PRELOAD_QUERY_COUNT
+}
+
+fn default_preload_window_hours() -> u64 {
+    DEFAULT_PRELOAD_WINDOW_HOURS
+}
 
 #[allow(dead_code)]
 #[derive(Debug, Deserialize, Clone)]
@@ -11,9 +73,214 @@ pub struct Config {
     pub pinecone_api_key: String,
     pub pinecone_environment: String,
     pub pinecone_index: String,
+    /// Whether the Pinecone index holds keyword vectors for the fallback search;
+    /// needs the dotproduct metric and an index built with this set
+    #[serde(default)]
+    pub pinecone_sparse_keywords: bool,
     pub neo4j_uri: Option<String>,
     pub neo4j_user: Option<String>,
     pub neo4j_password: Option<String>,
+    /// Supabase (Postgres) connection string, used for user data and the admin audit log
+    #[serde(default)]
+    pub database_url: Option<String>,
+    /// Shared secret for /api/admin endpoints; admin endpoints are disabled when unset
+    #[serde(default)]
+    pub admin_api_key: Option<String>,
+    /// Secret the auth provider (Supabase) signs user access tokens with; endpoints
+    /// under /api/users only accept the admin key when unset
+    #[serde(default)]
+    pub user_jwt_secret: Option<String>,
+    /// Recommendation queries slower than this are recorded in the slow query log
+    #[serde(default = "default_slow_query_threshold_ms")]
+    pub slow_query_threshold_ms: u64,
+    /// Number of slow queries kept in memory
+    #[serde(default = "default_slow_query_log_capacity")]
+    pub slow_query_log_capacity: usize,
+    /// Progress file written by the indexer, served at /api/admin/indexer/status
+    #[serde(default)]
+    pub indexer_status_file: Option<String>,
+    /// Corpus statistics snapshot written by the indexer, loaded at startup
+    #[serde(default)]
+    pub corpus_stats_file: Option<String>,
+    /// Full-text index directory written by the indexer, opened at startup
+    #[serde(default)]
+    pub fulltext_index_dir: Option<String>,
+    /// YAML alias map of author spellings, applied when indexing and when matching author queries
+    #[serde(default)]
+    pub author_aliases_file: Option<String>,
+    /// YAML taxonomy mapping publisher categories onto the controlled genre vocabulary
+    #[serde(default)]
+    pub category_taxonomy_file: Option<String>,
+    /// CSV of Hugo, Booker and Pulitzer winners, credited to books when indexing
+    #[serde(default)]
+    pub awards_file: Option<String>,
+    /// YAML definitions of the A/B experiments recommendation requests are bucketed into
+    #[serde(default)]
+    pub experiments_file: Option<String>,
+    /// TOML genre and theme synonyms merged into the query keyword tables
+    #[serde(default)]
+    pub synonyms_file: Option<String>,
+    /// Minutes without activity after which an anonymous session's dismissed books are forgotten
+    #[serde(default = "default_session_ttl_minutes")]
+    pub session_ttl_minutes: u64,
+    /// Seconds between writes of buffered analytics events to the database
+    #[serde(default = "default_events_flush_interval_secs")]
+    pub events_flush_interval_secs: u64,
+    /// Queries run at startup and by the prewarm scheduler to fill the result cache
+    #[serde(default = "default_warmup_queries")]
+    pub warmup_queries: Vec<String>,
+    /// Minutes between scheduled cache warmups; 0 disables them
+    #[serde(default = "default_prewarm_interval_minutes")]
+    pub prewarm_interval_minutes: u64,
+    /// Inactivity after which the host stops the instance; caches are re-warmed just before. 0 disables
+    #[serde(default = "default_idle_timeout_minutes")]
+    pub idle_timeout_minutes: u64,
+    /// Public base URL of this API, requested before the idle timeout to keep the instance running
+    #[serde(default)]
+    pub keepalive_url: Option<String>,
+    /// Public URL of the web frontend, whose book pages at `/books/{id}` are listed in the sitemap
+    #[serde(default)]
+    pub site_url: Option<String>,
+    /// Most frequent recent queries whose results are preloaded after each scheduled warmup; 0 disables
+    #[serde(default = "default_preload_query_count")]
+    pub preload_query_count: usize,
+    /// Hours of analytics events the most frequent queries are counted over
+    #[serde(default = "default_preload_window_hours")]
+    pub preload_window_hours: u64,
+    /// Redis URL for a result cache shared between instances; needs the `redis` feature
+    #[serde(default)]
+    pub redis_url: Option<String>,
+    /// Directory results and embeddings are also cached in, so they survive restarts on a
+    /// persistent disk; needs the `sled` feature
+    #[serde(default)]
+    pub cache_dir: Option<String>,
+    /// Megabytes of the `cache_dir` cache kept in memory as well
+    #[serde(default = "default_cache_dir_memory_mb")]
+    pub cache_dir_memory_mb: u64,
+    /// URLs catalog events (books added or removed, graph rebuilt) are POSTed to by `rab-admin`
+    #[serde(default)]
+    pub webhook_urls: Vec<String>,
+    /// Secret webhook bodies are signed with, sent as an HMAC-SHA256 in `X-Catalog-Signature`
+    #[serde(default)]
+    pub webhook_secret: Option<String>,
+    /// Redis stream catalog events are also appended to; needs the `redis` feature and `redis_url`
+    #[serde(default)]
+    pub catalog_events_stream: Option<String>,
+    /// Text generation backend writing explanations of the top results and reading
+    /// query constraints for `/api/query/parse`, `openai` or `huggingface`; both
+    /// use templates when unset
+    #[serde(default)]
+    pub explanation_backend: Option<String>,
+    /// Model of the explanation backend; each backend has a default
+    #[serde(default)]
+    pub explanation_model: Option<String>,
+    /// API key of the `openai` explanation backend; `huggingface` uses the embedder's key
+    #[serde(default)]
+    pub openai_api_key: Option<String>,
+    /// Google Books API key for metadata enrichment jobs; lookups are anonymous without one
+    #[serde(default)]
+    pub google_books_api_key: Option<String>,
+    /// HTTP server and concurrency tuning
+    #[serde(default)]
+    pub server: ServerConfig,
+    /// Budgets of the retrieval and ranking stages
+    #[serde(default)]
+    pub retrieval: RetrievalConfig,
+    /// Schedules of the recurring maintenance tasks
+    #[serde(default)]
+    pub maintenance: MaintenanceConfig,
+    /// Clients calling with an X-Api-Key, whose usage is metered against monthly quotas
+    #[serde(default)]
+    pub tenants: Vec<TenantConfig>,
+    /// Rules of the feature flags and their remote provider
+    #[serde(default)]
+    pub feature_flags: FeatureFlagsConfig,
+    /// Candidate ranker evaluated in shadow on a sample of queries
+    #[serde(default)]
+    pub shadow: ShadowConfig,
+    /// Filters applied after ranking to requests without a tenant, and to tenants
+    /// without their own
+    #[serde(default)]
+    pub post_filters: PostFilterConfig,
+    /// Seed of exploration and shadow sampling, so the same query gets the same
+    /// results on every run; random when unset
+    #[serde(default)]
+    pub random_seed: Option<u64>,
+}
+
+/// HTTP server and concurrency tuning, the `[server]` table
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ServerConfig {
+    /// Worker threads; defaults to the number of CPUs, and at least 2
+    pub workers: Option<usize>,
+    /// Connections the OS queues while all workers are busy
+    pub backlog: u32,
+    /// Connections each worker accepts concurrently while their TLS/HTTP handshakes finish
+    pub max_connection_rate: usize,
+    /// Seconds an idle keep-alive connection stays open
+    pub keep_alive_secs: u64,
+    /// Seconds a client has to send the request head before getting a 408
+    pub client_request_timeout_secs: u64,
+    /// Seconds in-flight requests get to finish on shutdown
+    pub shutdown_timeout_secs: u64,
+    /// Recommendation queries computed at once; cached results don't count. 0 disables the limit
+    pub max_concurrent_recommendations: usize,
+    /// Approximate megabytes the in-process caches may hold together. 0 disables the limit
+    pub cache_memory_budget_mb: u64,
+    /// Seconds a request's upstream calls, retries and queueing may take together. 0 disables the limit
+    pub request_deadline_secs: u64,
+    /// Path prefixes of the requests the deadline applies to, the ones serving recommendations
+    pub deadline_paths: Vec<String>,
+    /// Queries waiting for a turn past which new requests are answered 503 at once. 0 disables shedding
+    pub max_queued_recommendations: usize,
+    /// Path prefixes of the requests never shed, such as health checks and prewarm
+    pub shed_exempt_paths: Vec<String>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            workers: None,
+            backlog: 2048,
+            max_connection_rate: 256,
+            keep_alive_secs: 75,
+            client_request_timeout_secs: 60,
+            shutdown_timeout_secs: 5,
+            max_concurrent_recommendations: DEFAULT_MAX_CONCURRENT_RECOMMENDATIONS,
+            cache_memory_budget_mb: 128,
+            request_deadline_secs: 25,
+            deadline_paths: [
+                "/api/recommendations",
+                "/api/explanations",
+                "/api/tools",
+                "/opds/search",
+            ]
+            .map(str::to_string)
+            .to_vec(),
+            max_queued_recommendations: DEFAULT_MAX_QUEUED_RECOMMENDATIONS,
+            shed_exempt_paths: vec!["/api/health".to_string(), "/api/prewarm".to_string()],
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Configured worker count, or the number of CPUs (at least 2)
+    pub fn worker_count(&self) -> usize {
+        self.workers
+            .filter(|&workers| workers > 0)
+            .unwrap_or_else(|| std::cmp::max(2, num_cpus::get()))
+    }
+
+    /// Cache memory budget in bytes; 0 for no limit
+    pub fn cache_memory_budget_bytes(&self) -> u64 {
+        self.cache_memory_budget_mb.saturating_mul(1024 * 1024)
+    }
+
+    /// Deadline of each request, or None without one
+    pub fn request_deadline(&self) -> Option<Duration> {
+        (self.request_deadline_secs > 0).then(|| Duration::from_secs(self.request_deadline_secs))
+    }
 }
 
 impl Config {
@@ -130,6 +397,13 @@ impl Config {
             );
         }
 
+        if let Ok(value) = env::var("APP_PINECONE_SPARSE_KEYWORDS") {
+            match value.parse::<bool>() {
+                Ok(enabled) => config.pinecone_sparse_keywords = enabled,
+                Err(_) => warn!("Invalid APP_PINECONE_SPARSE_KEYWORDS value: {}", value),
+            }
+        }
+
         // Neo4j configuration
         if let Ok(value) = env::var("APP_NEO4J_URI") {
             info!("Using Neo4j URI from environment variable: '{}'", value);
@@ -146,6 +420,567 @@ impl Config {
             config.neo4j_password = Some(value);
         }
 
+        // Database configuration
+        if let Ok(value) = env::var("APP_DATABASE_URL") {
+            info!("Using database URL from environment variable (redacted)");
+            config.database_url = Some(value);
+        }
+
+        if config
+            .database_url
+            .as_ref()
+            .is_some_and(|url| url.trim().is_empty())
+        {
+            config.database_url = None;
+        }
+
+        // Admin configuration
+        if let Ok(value) = env::var("APP_ADMIN_API_KEY") {
+            info!("Using admin API key from environment variable (redacted)");
+            config.admin_api_key = Some(value);
+        }
+
+        if config
+            .admin_api_key
+            .as_ref()
+            .is_some_and(|key| key.trim().is_empty())
+        {
+            config.admin_api_key = None;
+        }
+
+        if config.admin_api_key.is_none() {
+            info!("No admin API key configured, admin endpoints are disabled");
+        }
+
+        // User authentication
+        if let Ok(value) = env::var("APP_USER_JWT_SECRET") {
+            info!("Using user JWT secret from environment variable (redacted)");
+            config.user_jwt_secret = Some(value);
+        }
+
+        if config
+            .user_jwt_secret
+            .as_ref()
+            .is_some_and(|secret| secret.trim().is_empty())
+        {
+            config.user_jwt_secret = None;
+        }
+
+        // Slow query log configuration
+        if let Ok(value) = env::var("APP_SLOW_QUERY_THRESHOLD_MS") {
+            match value.parse::<u64>() {
+                Ok(threshold) => config.slow_query_threshold_ms = threshold,
+                Err(_) => warn!("Invalid APP_SLOW_QUERY_THRESHOLD_MS value: {}", value),
+            }
+        }
+
+        if let Ok(value) = env::var("APP_SLOW_QUERY_LOG_CAPACITY") {
+            match value.parse::<usize>() {
+                Ok(capacity) => config.slow_query_log_capacity = capacity,
+                Err(_) => warn!("Invalid APP_SLOW_QUERY_LOG_CAPACITY value: {}", value),
+            }
+        }
+
+        // Session store configuration
+        if let Ok(value) = env::var("APP_SESSION_TTL_MINUTES") {
+            match value.parse::<u64>() {
+                Ok(minutes) => config.session_ttl_minutes = minutes,
+                Err(_) => warn!("Invalid APP_SESSION_TTL_MINUTES value: {}", value),
+            }
+        }
+
+        // Analytics event buffer
+        if let Ok(value) = env::var("APP_EVENTS_FLUSH_INTERVAL_SECS") {
+            match value.parse::<u64>() {
+                Ok(secs) if secs > 0 => config.events_flush_interval_secs = secs,
+                _ => warn!("Invalid APP_EVENTS_FLUSH_INTERVAL_SECS value: {}", value),
+            }
+        }
+
+        // Prewarm scheduler
+        if let Ok(value) = env::var("APP_WARMUP_QUERIES") {
+            config.warmup_queries = value
+                .split(',')
+                .map(|query| query.trim().to_string())
+                .collect();
+        }
+
+        config
+            .warmup_queries
+            .retain(|query| query.trim().len() >= 3);
+
+        if let Ok(value) = env::var("APP_PREWARM_INTERVAL_MINUTES") {
+            match value.parse::<u64>() {
+                Ok(minutes) => config.prewarm_interval_minutes = minutes,
+                Err(_) => warn!("Invalid APP_PREWARM_INTERVAL_MINUTES value: {}", value),
+            }
+        }
+
+        if let Ok(value) = env::var("APP_IDLE_TIMEOUT_MINUTES") {
+            match value.parse::<u64>() {
+                Ok(minutes) => config.idle_timeout_minutes = minutes,
+                Err(_) => warn!("Invalid APP_IDLE_TIMEOUT_MINUTES value: {}", value),
+            }
+        }
+
+        if let Ok(value) = env::var("APP_PRELOAD_QUERY_COUNT") {
+            match value.parse::<usize>() {
+                Ok(count) => config.preload_query_count = count,
+                Err(_) => warn!("Invalid APP_PRELOAD_QUERY_COUNT value: {}", value),
+            }
+        }
+
+        if let Ok(value) = env::var("APP_PRELOAD_WINDOW_HOURS") {
+            match value.parse::<u64>() {
+                Ok(hours) if hours > 0 => config.preload_window_hours = hours,
+                _ => warn!("Invalid APP_PRELOAD_WINDOW_HOURS value: {}", value),
+            }
+        }
+
+        // Render sets RENDER_EXTERNAL_URL to the service's public URL
+        if let Ok(value) =
+            env::var("APP_KEEPALIVE_URL").or_else(|_| env::var("RENDER_EXTERNAL_URL"))
+        {
+            config.keepalive_url = Some(value);
+        }
+
+        if config
+            .keepalive_url
+            .as_ref()
+            .is_some_and(|url| url.trim().is_empty())
+        {
+            config.keepalive_url = None;
+        }
+
+        if let Ok(value) = env::var("APP_SITE_URL") {
+            config.site_url = Some(value);
+        }
+
+        config.site_url = config
+            .site_url
+            .take()
+            .map(|url| url.trim().trim_end_matches('/').to_string())
+            .filter(|url| !url.is_empty());
+
+        // Server tuning
+        if let Ok(value) = env::var("APP_WORKERS") {
+            match value.parse::<usize>() {
+                Ok(workers) if workers > 0 => config.server.workers = Some(workers),
+                _ => warn!("Invalid APP_WORKERS value: {}", value),
+            }
+        }
+
+        if let Ok(value) = env::var("APP_BACKLOG") {
+            match value.parse::<u32>() {
+                Ok(backlog) if backlog > 0 => config.server.backlog = backlog,
+                _ => warn!("Invalid APP_BACKLOG value: {}", value),
+            }
+        }
+
+        if let Ok(value) = env::var("APP_KEEP_ALIVE_SECS") {
+            match value.parse::<u64>() {
+                Ok(secs) => config.server.keep_alive_secs = secs,
+                Err(_) => warn!("Invalid APP_KEEP_ALIVE_SECS value: {}", value),
+            }
+        }
+
+        if let Ok(value) = env::var("APP_CLIENT_REQUEST_TIMEOUT_SECS") {
+            match value.parse::<u64>() {
+                Ok(secs) => config.server.client_request_timeout_secs = secs,
+                Err(_) => warn!("Invalid APP_CLIENT_REQUEST_TIMEOUT_SECS value: {}", value),
+            }
+        }
+
+        if let Ok(value) = env::var("APP_MAX_CONCURRENT_RECOMMENDATIONS") {
+            match value.parse::<usize>() {
+                Ok(max) => config.server.max_concurrent_recommendations = max,
+                Err(_) => warn!(
+                    "Invalid APP_MAX_CONCURRENT_RECOMMENDATIONS value: {}",
+                    value
+                ),
+            }
+        }
+
+        if let Ok(value) = env::var("APP_CACHE_MEMORY_BUDGET_MB") {
+            match value.parse::<u64>() {
+                Ok(mb) => config.server.cache_memory_budget_mb = mb,
+                Err(_) => warn!("Invalid APP_CACHE_MEMORY_BUDGET_MB value: {}", value),
+            }
+        }
+
+        if let Ok(value) = env::var("APP_MAX_QUEUED_RECOMMENDATIONS") {
+            match value.parse::<usize>() {
+                Ok(max) => config.server.max_queued_recommendations = max,
+                Err(_) => warn!("Invalid APP_MAX_QUEUED_RECOMMENDATIONS value: {}", value),
+            }
+        }
+
+        if let Ok(value) = env::var("APP_SHED_EXEMPT_PATHS") {
+            config.server.shed_exempt_paths = value
+                .split(',')
+                .map(str::trim)
+                .filter(|path| !path.is_empty())
+                .map(str::to_string)
+                .collect();
+        }
+
+        if let Ok(value) = env::var("APP_DEADLINE_PATHS") {
+            config.server.deadline_paths = value
+                .split(',')
+                .map(str::trim)
+                .filter(|path| !path.is_empty())
+                .map(str::to_string)
+                .collect();
+        }
+
+        if let Ok(value) = env::var("APP_REQUEST_DEADLINE_SECS") {
+            match value.parse::<u64>() {
+                Ok(secs) => config.server.request_deadline_secs = secs,
+                Err(_) => warn!("Invalid APP_REQUEST_DEADLINE_SECS value: {}", value),
+            }
+        }
+
+        // Retrieval budgets
+        if let Ok(value) = env::var("APP_CANDIDATES_PER_RESULT") {
+            match value.parse::<usize>() {
+                Ok(count) if count > 0 => config.retrieval.candidates_per_result = count,
+                _ => warn!("Invalid APP_CANDIDATES_PER_RESULT value: {}", value),
+            }
+        }
+
+        if let Ok(value) = env::var("APP_MATCHES_PER_CANDIDATE") {
+            match value.parse::<usize>() {
+                Ok(count) if count > 0 => config.retrieval.matches_per_candidate = count,
+                _ => warn!("Invalid APP_MATCHES_PER_CANDIDATE value: {}", value),
+            }
+        }
+
+        if let Ok(value) = env::var("APP_FALLBACK_RESULTS_PER_CANDIDATE") {
+            match value.parse::<usize>() {
+                Ok(count) if count > 0 => config.retrieval.fallback_results_per_candidate = count,
+                _ => warn!(
+                    "Invalid APP_FALLBACK_RESULTS_PER_CANDIDATE value: {}",
+                    value
+                ),
+            }
+        }
+
+        if let Ok(value) = env::var("APP_FALLBACK_TERMS") {
+            match value.parse::<usize>() {
+                Ok(count) => config.retrieval.fallback_terms = count,
+                Err(_) => warn!("Invalid APP_FALLBACK_TERMS value: {}", value),
+            }
+        }
+
+        if let Ok(value) = env::var("APP_TRUSTED_POSITIONS") {
+            match value.parse::<usize>() {
+                Ok(count) => config.retrieval.trusted_positions = count,
+                Err(_) => warn!("Invalid APP_TRUSTED_POSITIONS value: {}", value),
+            }
+        }
+
+        // Maintenance schedules, e.g. APP_MAINTENANCE_GRAPH_SYNC_ENABLED and
+        // APP_MAINTENANCE_GRAPH_SYNC_MINUTES
+        for task in MaintenanceTask::ALL {
+            let prefix = format!("APP_MAINTENANCE_{}", task.name().to_uppercase());
+            let schedule = config.maintenance.schedule_mut(task);
+            if let Ok(value) = env::var(format!("{}_ENABLED", prefix)) {
+                match value.parse::<bool>() {
+                    Ok(enabled) => schedule.enabled = enabled,
+                    Err(_) => warn!("Invalid {}_ENABLED value: {}", prefix, value),
+                }
+            }
+            if let Ok(value) = env::var(format!("{}_MINUTES", prefix)) {
+                match value.parse::<u64>() {
+                    Ok(minutes) => schedule.interval_minutes = minutes,
+                    Err(_) => warn!("Invalid {}_MINUTES value: {}", prefix, value),
+                }
+            }
+        }
+
+        if let Ok(value) = env::var("APP_MAINTENANCE_COVER_BATCH_SIZE") {
+            match value.parse::<usize>() {
+                Ok(size) if size > 0 => config.maintenance.cover_batch_size = size,
+                _ => warn!("Invalid APP_MAINTENANCE_COVER_BATCH_SIZE value: {}", value),
+            }
+        }
+
+        // Feature flags, e.g. APP_FLAG_FUSED_RANKING_ENABLED and
+        // APP_FLAG_FUSED_RANKING_ROLLOUT_PERCENT
+        for flag in Flag::ALL {
+            let prefix = format!("APP_FLAG_{}", flag.name().to_uppercase());
+            let rule = config.feature_flags.rule_mut(flag);
+            if let Ok(value) = env::var(format!("{}_ENABLED", prefix)) {
+                match value.parse::<bool>() {
+                    Ok(enabled) => rule.enabled = enabled,
+                    Err(_) => warn!("Invalid {}_ENABLED value: {}", prefix, value),
+                }
+            }
+            if let Ok(value) = env::var(format!("{}_ROLLOUT_PERCENT", prefix)) {
+                match value.parse::<u8>() {
+                    Ok(percent) if percent <= 100 => rule.rollout_percent = percent,
+                    _ => warn!("Invalid {}_ROLLOUT_PERCENT value: {}", prefix, value),
+                }
+            }
+        }
+
+        if let Ok(value) = env::var("APP_FEATURE_FLAGS_URL") {
+            config.feature_flags.remote_url = Some(value);
+        }
+        if config
+            .feature_flags
+            .remote_url
+            .as_ref()
+            .is_some_and(|url| url.trim().is_empty())
+        {
+            config.feature_flags.remote_url = None;
+        }
+
+        // Shadow ranking; an empty or "none" ranker turns it off
+        if let Ok(value) = env::var("APP_SHADOW_RANKER") {
+            match value.trim() {
+                "" | "none" => config.shadow.ranker = None,
+                name => match Ranker::from_name(name) {
+                    Some(ranker) => config.shadow.ranker = Some(ranker),
+                    None => warn!("Invalid APP_SHADOW_RANKER value: {}", value),
+                },
+            }
+        }
+
+        if let Ok(value) = env::var("APP_SHADOW_SAMPLE_PERCENT") {
+            match value.parse::<u8>() {
+                Ok(percent) if percent <= 100 => config.shadow.sample_percent = percent,
+                _ => warn!("Invalid APP_SHADOW_SAMPLE_PERCENT value: {}", value),
+            }
+        }
+
+        // Tenant keys as name=key pairs, so keys can stay out of the config files;
+        // a name without a [[tenants]] table is a tenant without quotas
+        if let Ok(value) = env::var("APP_TENANT_KEYS") {
+            for pair in value.split(',').filter(|pair| !pair.trim().is_empty()) {
+                let Some((name, key)) = pair.split_once('=') else {
+                    warn!("Invalid APP_TENANT_KEYS entry: {}", pair);
+                    continue;
+                };
+                let (name, key) = (name.trim(), key.trim().to_string());
+                match config.tenants.iter_mut().find(|tenant| tenant.name == name) {
+                    Some(tenant) => tenant.api_key = key,
+                    None => config.tenants.push(TenantConfig {
+                        name: name.to_string(),
+                        api_key: key,
+                        quota: Default::default(),
+                        post_filters: None,
+                    }),
+                }
+            }
+        }
+
+        // Shared result cache
+        if let Ok(value) = env::var("APP_CACHE_DIR") {
+            config.cache_dir = Some(value);
+        }
+
+        if config
+            .cache_dir
+            .as_ref()
+            .is_some_and(|dir| dir.trim().is_empty())
+        {
+            config.cache_dir = None;
+        }
+
+        if let Ok(value) = env::var("APP_CACHE_DIR_MEMORY_MB") {
+            match value.parse::<u64>() {
+                Ok(mb) => config.cache_dir_memory_mb = mb,
+                Err(_) => warn!("Invalid APP_CACHE_DIR_MEMORY_MB value: {}", value),
+            }
+        }
+
+        if let Ok(value) = env::var("APP_REDIS_URL") {
+            config.redis_url = Some(value);
+        }
+
+        if config
+            .redis_url
+            .as_ref()
+            .is_some_and(|url| url.trim().is_empty())
+        {
+            config.redis_url = None;
+        }
+
+        // Catalog events
+        if let Ok(value) = env::var("APP_WEBHOOK_URLS") {
+            config.webhook_urls = value.split(',').map(|url| url.trim().to_string()).collect();
+        }
+
+        config.webhook_urls.retain(|url| !url.is_empty());
+
+        if let Ok(value) = env::var("APP_WEBHOOK_SECRET") {
+            config.webhook_secret = Some(value);
+        }
+
+        if config
+            .webhook_secret
+            .as_ref()
+            .is_some_and(|secret| secret.trim().is_empty())
+        {
+            config.webhook_secret = None;
+        }
+
+        if let Ok(value) = env::var("APP_CATALOG_EVENTS_STREAM") {
+            config.catalog_events_stream = Some(value);
+        }
+
+        if config
+            .catalog_events_stream
+            .as_ref()
+            .is_some_and(|stream| stream.trim().is_empty())
+        {
+            config.catalog_events_stream = None;
+        }
+
+        // Indexer status file
+        if let Ok(value) = env::var("APP_INDEXER_STATUS_FILE") {
+            config.indexer_status_file = Some(value);
+        }
+
+        if config
+            .indexer_status_file
+            .as_ref()
+            .is_some_and(|path| path.trim().is_empty())
+        {
+            config.indexer_status_file = None;
+        }
+
+        // Corpus statistics snapshot
+        if let Ok(value) = env::var("APP_CORPUS_STATS_FILE") {
+            config.corpus_stats_file = Some(value);
+        }
+
+        if config
+            .corpus_stats_file
+            .as_ref()
+            .is_some_and(|path| path.trim().is_empty())
+        {
+            config.corpus_stats_file = None;
+        }
+
+        // Full-text index
+        if let Ok(value) = env::var("APP_FULLTEXT_INDEX_DIR") {
+            config.fulltext_index_dir = Some(value);
+        }
+
+        if config
+            .fulltext_index_dir
+            .as_ref()
+            .is_some_and(|path| path.trim().is_empty())
+        {
+            config.fulltext_index_dir = None;
+        }
+
+        // Author alias map
+        if let Ok(value) = env::var("APP_AUTHOR_ALIASES_FILE") {
+            config.author_aliases_file = Some(value);
+        }
+
+        if config
+            .author_aliases_file
+            .as_ref()
+            .is_some_and(|path| path.trim().is_empty())
+        {
+            config.author_aliases_file = None;
+        }
+
+        // Category taxonomy
+        if let Ok(value) = env::var("APP_CATEGORY_TAXONOMY_FILE") {
+            config.category_taxonomy_file = Some(value);
+        }
+
+        if config
+            .category_taxonomy_file
+            .as_ref()
+            .is_some_and(|path| path.trim().is_empty())
+        {
+            config.category_taxonomy_file = None;
+        }
+
+        // Award winners
+        if let Ok(value) = env::var("APP_AWARDS_FILE") {
+            config.awards_file = Some(value);
+        }
+
+        if config
+            .awards_file
+            .as_ref()
+            .is_some_and(|path| path.trim().is_empty())
+        {
+            config.awards_file = None;
+        }
+
+        // Ranking experiments
+        if let Ok(value) = env::var("APP_EXPERIMENTS_FILE") {
+            config.experiments_file = Some(value);
+        }
+
+        if config
+            .experiments_file
+            .as_ref()
+            .is_some_and(|path| path.trim().is_empty())
+        {
+            config.experiments_file = None;
+        }
+
+        // Query synonyms
+        if let Ok(value) = env::var("APP_SYNONYMS_FILE") {
+            config.synonyms_file = Some(value);
+        }
+
+        if config
+            .synonyms_file
+            .as_ref()
+            .is_some_and(|path| path.trim().is_empty())
+        {
+            config.synonyms_file = None;
+        }
+
+        // Explanations
+        if let Ok(value) = env::var("APP_EXPLANATION_BACKEND") {
+            config.explanation_backend = Some(value);
+        }
+
+        if let Ok(value) = env::var("APP_EXPLANATION_MODEL") {
+            config.explanation_model = Some(value);
+        }
+
+        if let Ok(value) = env::var("APP_OPENAI_API_KEY") {
+            config.openai_api_key = Some(value);
+        }
+
+        // Metadata enrichment
+        if let Ok(value) = env::var("APP_GOOGLE_BOOKS_API_KEY") {
+            config.google_books_api_key = Some(value);
+        }
+
+        if let Ok(value) = env::var("APP_RANDOM_SEED") {
+            match value.parse::<u64>() {
+                Ok(seed) => config.random_seed = Some(seed),
+                Err(_) => warn!("Invalid APP_RANDOM_SEED value: {}", value),
+            }
+        }
+
+        for setting in [
+            &mut config.explanation_backend,
+            &mut config.explanation_model,
+            &mut config.openai_api_key,
+            &mut config.google_books_api_key,
+        ] {
+            if setting
+                .as_ref()
+                .is_some_and(|value| value.trim().is_empty())
+            {
+                *setting = None;
+            }
+        }
+
         // Validate configuration values
         if config.pinecone_api_key.is_empty() || config.pinecone_api_key.contains("your") {
             warn!("Pinecone API key appears to be invalid or empty");
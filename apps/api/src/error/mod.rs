@@ -1,9 +1,21 @@
-use actix_web::{HttpResponse, ResponseError};
-use serde::Serialize;
+use crate::{models::ErrorResponse, telemetry};
+use actix_web::{
+    http::{header, StatusCode},
+    HttpResponse, ResponseError,
+};
 use thiserror::Error;
 
 pub type Result<T> = std::result::Result<T, ApiError>;
 
+/// Retry-After value (in seconds) used when an upstream throttles us without a hint
+pub const DEFAULT_RETRY_AFTER_SECONDS: u64 = 5;
+
+/// Upper bound for the Retry-After value we pass on to clients
+const MAX_RETRY_AFTER_SECONDS: u64 = 300;
+
+/// `X-RateLimit-Reset` values above this are Unix timestamps (September 2001 on), not delays
+const EPOCH_RESET_THRESHOLD: u64 = 1_000_000_000;
+
 #[derive(Error, Debug)]
 pub enum ApiError {
     #[error("Not found: {0}")]
@@ -36,25 +48,168 @@ pub enum ApiError {
 
     #[error("Pinecone error: {0}")]
     PineconeError(String),
+
+    #[error("Service unavailable: {message}")]
+    ServiceUnavailable {
+        message: String,
+        /// Seconds the client should wait before retrying, derived from upstream hints
+        retry_after: Option<u64>,
+    },
+
+    #[error("Quota exceeded: {message}")]
+    QuotaExceeded {
+        message: String,
+        /// Seconds until the quota resets
+        retry_after: u64,
+    },
+}
+
+impl ApiError {
+    /// Build a `ServiceUnavailable` error for a throttled or overloaded upstream
+    pub fn service_unavailable(message: impl Into<String>, retry_after: Option<u64>) -> Self {
+        ApiError::ServiceUnavailable {
+            message: message.into(),
+            retry_after,
+        }
+    }
 }
 
-#[derive(Serialize)]
-struct ErrorResponse {
-    error: String,
+/// Extract a retry hint (in seconds) from upstream response headers.
+///
+/// Understands `Retry-After` in both delta-seconds and HTTP-date form, and falls
+/// back to the common `X-RateLimit-Reset` header when no `Retry-After` is present.
+/// Some APIs send the latter as the Unix time the limit resets at rather than a
+/// delay, so values too large to be a delay are read that way.
+pub fn retry_after_from_headers(headers: &reqwest::header::HeaderMap) -> Option<u64> {
+    let parse_header = |name: &str| -> Option<u64> {
+        let value = headers.get(name)?.to_str().ok()?.trim();
+
+        if let Ok(seconds) = value.parse::<u64>() {
+            return Some(seconds);
+        }
+
+        if let Ok(seconds) = value.parse::<f64>() {
+            return Some(seconds.max(0.0).ceil() as u64);
+        }
+
+        chrono::DateTime::parse_from_rfc2822(value)
+            .ok()
+            .map(|date| (date.timestamp() - chrono::Utc::now().timestamp()).max(0) as u64)
+    };
+
+    let reset = || {
+        parse_header("x-ratelimit-reset").map(|seconds| {
+            if seconds > EPOCH_RESET_THRESHOLD {
+                seconds.saturating_sub(chrono::Utc::now().timestamp().max(0) as u64)
+            } else {
+                seconds
+            }
+        })
+    };
+
+    parse_header("retry-after")
+        .or_else(reset)
+        .map(|seconds| seconds.min(MAX_RETRY_AFTER_SECONDS))
+}
+
+/// Take the guard out of a lock result even if a previous holder panicked.
+///
+/// Our locks only guard caches, which stay internally consistent even if a writer
+/// panicked mid-update, so it's better to keep serving than to disable the cache
+/// for the lifetime of the process.
+pub fn recover_lock<G>(result: std::sync::LockResult<G>, lock_name: &str) -> G {
+    result.unwrap_or_else(|poisoned| {
+        log::error!("Recovered poisoned lock: {}", lock_name);
+        telemetry::record_poisoned_lock();
+        poisoned.into_inner()
+    })
+}
+
+impl ApiError {
+    /// Every error code, in declaration order, used to pre-register telemetry counters
+    pub const CODES: [&'static str; 12] = [
+        "not_found",
+        "invalid_input",
+        "database_error",
+        "external_service_error",
+        "model_load_error",
+        "model_inference_error",
+        "serialization_error",
+        "authentication_error",
+        "internal_error",
+        "pinecone_error",
+        "service_unavailable",
+        "quota_exceeded",
+    ];
+
+    /// Stable, machine-readable code for this error variant
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::NotFound(_) => "not_found",
+            ApiError::InvalidInput(_) => "invalid_input",
+            ApiError::DatabaseError(_) => "database_error",
+            ApiError::ExternalServiceError(_) => "external_service_error",
+            ApiError::ModelLoadError(_) => "model_load_error",
+            ApiError::ModelInferenceError(_) => "model_inference_error",
+            ApiError::SerializationError(_) => "serialization_error",
+            ApiError::AuthenticationError(_) => "authentication_error",
+            ApiError::InternalError(_) => "internal_error",
+            ApiError::PineconeError(_) => "pinecone_error",
+            ApiError::ServiceUnavailable { .. } => "service_unavailable",
+            ApiError::QuotaExceeded { .. } => "quota_exceeded",
+        }
+    }
 }
 
 impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::InvalidInput(_) => StatusCode::BAD_REQUEST,
+            ApiError::AuthenticationError(_) => StatusCode::UNAUTHORIZED,
+            // Upstream dependencies answered badly
+            ApiError::ExternalServiceError(_)
+            | ApiError::PineconeError(_)
+            | ApiError::ModelInferenceError(_) => StatusCode::BAD_GATEWAY,
+            // The model is not ready yet; retrying later can succeed
+            ApiError::ModelLoadError(_) | ApiError::ServiceUnavailable { .. } => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            ApiError::QuotaExceeded { .. } => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::DatabaseError(_)
+            | ApiError::SerializationError(_)
+            | ApiError::InternalError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
     fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+        telemetry::record_error(self.code());
+
         let error = ErrorResponse {
             error: self.to_string(),
+            code: self.code().to_string(),
+            status: status.as_u16(),
         };
 
-        match self {
-            ApiError::InvalidInput(_) => HttpResponse::BadRequest().json(error),
-            ApiError::AuthenticationError(_) => HttpResponse::Unauthorized().json(error),
-            ApiError::NotFound(_) => HttpResponse::NotFound().json(error),
-            _ => HttpResponse::InternalServerError().json(error),
+        let mut response = HttpResponse::build(status);
+        if let ApiError::QuotaExceeded { retry_after, .. } = self {
+            response.insert_header((header::RETRY_AFTER, retry_after.to_string()));
+        }
+        if status == StatusCode::SERVICE_UNAVAILABLE {
+            let retry_after = match self {
+                ApiError::ServiceUnavailable { retry_after, .. } => *retry_after,
+                _ => None,
+            };
+            response.insert_header((
+                header::RETRY_AFTER,
+                retry_after
+                    .unwrap_or(DEFAULT_RETRY_AFTER_SECONDS)
+                    .to_string(),
+            ));
         }
+
+        response.json(error)
     }
 }
 
@@ -93,3 +248,107 @@ impl From<anyhow::Error> for ApiError {
         ApiError::InternalError(err.to_string())
     }
 }
+
+impl From<neo4rs::Error> for ApiError {
+    fn from(err: neo4rs::Error) -> Self {
+        ApiError::ExternalServiceError(format!("Neo4j error: {}", err))
+    }
+}
+
+impl From<tokio::time::error::Elapsed> for ApiError {
+    fn from(_: tokio::time::error::Elapsed) -> Self {
+        ApiError::service_unavailable("Upstream request timed out", None)
+    }
+}
+
+impl<T> From<std::sync::PoisonError<T>> for ApiError {
+    fn from(err: std::sync::PoisonError<T>) -> Self {
+        ApiError::InternalError(format!("Lock poisoned: {}", err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_mapping() {
+        assert_eq!(
+            ApiError::InvalidInput("x".into()).status_code(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            ApiError::PineconeError("x".into()).status_code(),
+            StatusCode::BAD_GATEWAY
+        );
+        assert_eq!(
+            ApiError::ModelLoadError("x".into()).status_code(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert_eq!(
+            ApiError::InternalError("x".into()).status_code(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+
+    #[test]
+    fn test_codes_are_registered() {
+        let err = ApiError::service_unavailable("busy", Some(10));
+        assert!(ApiError::CODES.contains(&err.code()));
+
+        // Every code is documented in the OpenAPI schema
+        for code in ApiError::CODES {
+            assert!(
+                serde_json::from_value::<crate::models::ErrorCode>(serde_json::json!(code)).is_ok(),
+                "{} is missing from ErrorCode",
+                code
+            );
+        }
+    }
+
+    #[test]
+    fn test_service_unavailable_sets_retry_after() {
+        let response = ApiError::service_unavailable("busy", Some(12)).error_response();
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers().get(header::RETRY_AFTER).unwrap(), "12");
+
+        // Quotas reset too late for the cap on upstream hints to apply
+        let response = ApiError::QuotaExceeded {
+            message: "monthly requests used up".to_string(),
+            retry_after: 86_400,
+        }
+        .error_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(
+            response.headers().get(header::RETRY_AFTER).unwrap(),
+            "86400"
+        );
+    }
+
+    #[test]
+    fn test_retry_after_from_headers() {
+        let mut headers = reqwest::header::HeaderMap::new();
+        assert_eq!(retry_after_from_headers(&headers), None);
+
+        headers.insert("x-ratelimit-reset", "7".parse().unwrap());
+        assert_eq!(retry_after_from_headers(&headers), Some(7));
+
+        let reset_at = chrono::Utc::now().timestamp() + 20;
+        headers.insert("x-ratelimit-reset", reset_at.to_string().parse().unwrap());
+        let retry_after = retry_after_from_headers(&headers).unwrap();
+        assert!((19..=20).contains(&retry_after), "{}", retry_after);
+
+        headers.insert("x-ratelimit-reset", "1000000001".parse().unwrap());
+        assert_eq!(retry_after_from_headers(&headers), Some(0));
+
+        headers.insert("retry-after", "30".parse().unwrap());
+        assert_eq!(retry_after_from_headers(&headers), Some(30));
+
+        headers.insert("retry-after", "100000".parse().unwrap());
+        assert_eq!(
+            retry_after_from_headers(&headers),
+            Some(MAX_RETRY_AFTER_SECONDS)
+        );
+    }
+}
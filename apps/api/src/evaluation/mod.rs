@@ -0,0 +1,262 @@
+//! Offline ranking quality evaluation.
+//!
+//! A suite is a YAML file of queries, each with the books a good answer should
+//! contain. The `evaluate` binary runs every query through the recommendation
+//! pipeline and scores the ranked results with recall@k, nDCG@k and MRR.
+
+use crate::error::{ApiError, Result};
+use crate::models::Book;
+use serde::{Deserialize, Serialize};
+use std::borrow::Borrow;
+use std::path::Path;
+
+/// Cutoff used when the suite doesn't set one
+pub const DEFAULT_K: usize = 10;
+
+/// A set of test queries loaded from YAML
+#[derive(Debug, Clone, Deserialize)]
+pub struct EvaluationSuite {
+    /// Number of results requested and scored per query
+    #[serde(default = "default_k")]
+    pub k: usize,
+    pub queries: Vec<EvaluationQuery>,
+}
+
+fn default_k() -> usize {
+    DEFAULT_K
+}
+
+/// One query and the books expected in its results
+#[derive(Debug, Clone, Deserialize)]
+pub struct EvaluationQuery {
+    pub query: String,
+    pub relevant: Vec<ExpectedBook>,
+}
+
+/// A relevant book, matched by id or by title (and optionally author)
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExpectedBook {
+    pub id: Option<String>,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    /// Graded relevance used by nDCG; plain binary relevance when omitted
+    #[serde(default = "default_grade")]
+    pub grade: u32,
+}
+
+fn default_grade() -> u32 {
+    1
+}
+
+impl ExpectedBook {
+    /// Whether a returned book is this expected book
+    pub fn matches(&self, book: &Book) -> bool {
+        if let Some(id) = &self.id {
+            return book.id.as_deref() == Some(id.as_str());
+        }
+
+        let Some(title) = &self.title else {
+            return false;
+        };
+        let same_title = book
+            .title
+            .as_deref()
+            .is_some_and(|t| t.trim().eq_ignore_ascii_case(title.trim()));
+        let same_author = self.author.as_ref().is_none_or(|author| {
+            book.author
+                .as_deref()
+                .is_some_and(|a| a.to_lowercase().contains(&author.trim().to_lowercase()))
+        });
+
+        same_title && same_author
+    }
+
+    fn label(&self) -> String {
+        match (&self.id, &self.title) {
+            (Some(id), _) => id.clone(),
+            (None, Some(title)) => title.clone(),
+            (None, None) => "<unnamed>".to_string(),
+        }
+    }
+}
+
+impl EvaluationSuite {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path).map_err(|e| {
+            ApiError::InvalidInput(format!("Failed to read {}: {}", path.display(), e))
+        })?;
+        Self::from_yaml(&text)
+    }
+
+    pub fn from_yaml(text: &str) -> Result<Self> {
+        let suite: Self = serde_yaml::from_str(text)
+            .map_err(|e| ApiError::InvalidInput(format!("Invalid evaluation suite: {}", e)))?;
+
+        if suite.k == 0 {
+            return Err(ApiError::InvalidInput("k must be at least 1".to_string()));
+        }
+        for query in &suite.queries {
+            if query.relevant.is_empty() {
+                return Err(ApiError::InvalidInput(format!(
+                    "Query '{}' has no relevant books",
+                    query.query
+                )));
+            }
+            if query
+                .relevant
+                .iter()
+                .any(|book| book.id.is_none() && book.title.is_none())
+            {
+                return Err(ApiError::InvalidInput(format!(
+                    "Query '{}' has a relevant book without an id or title",
+                    query.query
+                )));
+            }
+        }
+
+        Ok(suite)
+    }
+}
+
+/// Scores for a single query
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryScore {
+    pub query: String,
+    pub recall: f64,
+    pub ndcg: f64,
+    pub reciprocal_rank: f64,
+    /// Expected books that didn't appear in the top k
+    pub missing: Vec<String>,
+}
+
+/// Scores for a whole suite, averaged over queries
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EvaluationReport {
+    pub k: usize,
+    pub recall: f64,
+    pub ndcg: f64,
+    pub mrr: f64,
+    pub queries: Vec<QueryScore>,
+}
+
+impl EvaluationReport {
+    pub fn from_scores(k: usize, queries: Vec<QueryScore>) -> Self {
+        let mean = |metric: fn(&QueryScore) -> f64| {
+            if queries.is_empty() {
+                0.0
+            } else {
+                queries.iter().map(metric).sum::<f64>() / queries.len() as f64
+            }
+        };
+
+        Self {
+            k,
+            recall: mean(|q| q.recall),
+            ndcg: mean(|q| q.ndcg),
+            mrr: mean(|q| q.reciprocal_rank),
+            queries,
+        }
+    }
+}
+
+/// Score a ranked result list against the expected books, looking at the first `k` results
+pub fn score_query<B: Borrow<Book>>(
+    query: &EvaluationQuery,
+    results: &[B],
+    k: usize,
+) -> QueryScore {
+    let results = &results[..results.len().min(k)];
+
+    // Grade of each ranked result; every expected book is credited at most once
+    let mut found = vec![false; query.relevant.len()];
+    let gains: Vec<u32> = results
+        .iter()
+        .map(|book| {
+            match query
+                .relevant
+                .iter()
+                .enumerate()
+                .find(|(i, expected)| !found[*i] && expected.matches(book.borrow()))
+            {
+                Some((i, expected)) => {
+                    found[i] = true;
+                    expected.grade
+                }
+                None => 0,
+            }
+        })
+        .collect();
+
+    let hits = found.iter().filter(|f| **f).count();
+    let recall = hits as f64 / query.relevant.len() as f64;
+
+    let mut ideal: Vec<u32> = query.relevant.iter().map(|b| b.grade).collect();
+    ideal.sort_unstable_by(|a, b| b.cmp(a));
+    ideal.truncate(k);
+    let ideal_dcg = dcg(&ideal);
+    let ndcg = if ideal_dcg > 0.0 {
+        dcg(&gains) / ideal_dcg
+    } else {
+        0.0
+    };
+
+    let reciprocal_rank = gains
+        .iter()
+        .position(|gain| *gain > 0)
+        .map_or(0.0, |rank| 1.0 / (rank + 1) as f64);
+
+    QueryScore {
+        query: query.query.clone(),
+        recall,
+        ndcg,
+        reciprocal_rank,
+        missing: query
+            .relevant
+            .iter()
+            .zip(&found)
+            .filter(|(_, found)| !**found)
+            .map(|(expected, _)| expected.label())
+            .collect(),
+    }
+}
+
+/// Discounted cumulative gain with exponential gain, `(2^grade - 1) / log2(rank + 1)`
+fn dcg(grades: &[u32]) -> f64 {
+    grades
+        .iter()
+        .enumerate()
+        .map(|(i, grade)| (2f64.powi(*grade as i32) - 1.0) / ((i + 2) as f64).log2())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book(title: &str) -> Book {
+        serde_json::from_value(serde_json::json!({ "title": title, "categories": [] })).unwrap()
+    }
+
+    #[test]
+    fn test_scores_ranked_results() {
+        let suite = EvaluationSuite::from_yaml(
+            "k: 3\nqueries:\n  - query: desert planet\n    relevant:\n      - title: Dune\n        grade: 2\n      - title: Arrakis\n",
+        )
+        .unwrap();
+        let query = &suite.queries[0];
+
+        let results = vec![book("Foundation"), book("dune"), book("Hyperion")];
+        let score = score_query(query, &results, suite.k);
+
+        assert_eq!(score.recall, 0.5);
+        assert_eq!(score.reciprocal_rank, 0.5);
+        assert_eq!(score.missing, vec!["Arrakis".to_string()]);
+        let expected_ndcg = (3.0 / 3f64.log2()) / (3.0 + 1.0 / 3f64.log2());
+        assert!((score.ndcg - expected_ndcg).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_rejects_queries_without_expectations() {
+        assert!(EvaluationSuite::from_yaml("queries:\n  - query: x\n    relevant: []\n").is_err());
+    }
+}
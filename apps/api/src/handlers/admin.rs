@@ -0,0 +1,1273 @@
+use crate::{
+    config::Config,
+    error::ApiError,
+    ingest::{
+        enrichment::MetadataEnricher,
+        progress::{read_status_file, IndexProgress},
+    },
+    middleware::AdminAuth,
+    models::{BadGateway, ErrorResponse, InternalServerError, Unauthorized},
+    services::{
+        audit_log::{AuditEntry, AuditLog, AuditQuery},
+        book_patch::{BookPatch, PatchResult, PatchStatus, MAX_PATCHES},
+        book_versions::BookHistory,
+        feature_flags::{FlagContext, FlagRule, FlagSet},
+        jobs::{Job, JobQueue, JobStatus},
+        query_preloader::{PreloadedQuery, QueryPreloader},
+        ranking::Ranker,
+        shadow::{ShadowComparison, ShadowSummary},
+        slow_query_log::SlowQueryEntry,
+        supabase::validate_id,
+        usage::{is_month, month_of, TenantUsage, UsageMeter},
+        RecommendationService,
+    },
+};
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+use utoipa::ToSchema;
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SlowQueryParams {
+    /// Maximum number of entries to return (default: 50)
+    #[serde(default = "default_slow_query_limit")]
+    #[schema(example = 50, minimum = 1)]
+    pub limit: usize,
+}
+
+fn default_slow_query_limit() -> usize {
+    50
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SlowQueriesResponse {
+    /// Latency threshold above which queries are recorded, in milliseconds
+    #[schema(example = 2000)]
+    pub threshold_ms: u64,
+    /// Slow queries, newest first
+    pub queries: Vec<SlowQueryEntry>,
+}
+
+/// List recent slow recommendation queries
+#[utoipa::path(
+    get,
+    path = "/api/admin/slow-queries",
+    tag = "Admin",
+    params(
+        ("limit" = Option<usize>, Query, description = "Maximum number of entries (default: 50)", example = 50),
+        ("X-Admin-Key" = String, Header, description = "Admin API key")
+    ),
+    responses(
+        (status = 200, description = "Recent slow queries, newest first", body = SlowQueriesResponse),
+        (status = 401, response = Unauthorized)
+    ),
+    summary = "Get slow recommendation queries",
+    description = "Returns recommendation queries whose end-to-end latency exceeded the configured threshold, \
+                   including their intent, search strategy, per-upstream timings and cache status."
+)]
+#[actix_web::get("/slow-queries")]
+pub async fn get_slow_queries(
+    _admin: AdminAuth,
+    params: web::Query<SlowQueryParams>,
+    recommendation_service: web::Data<RecommendationService>,
+) -> Result<HttpResponse, ApiError> {
+    let log = recommendation_service.slow_query_log();
+
+    Ok(HttpResponse::Ok().json(SlowQueriesResponse {
+        threshold_ms: log.threshold().as_millis() as u64,
+        queries: log.recent(params.limit.max(1)),
+    }))
+}
+
+/// Clear the slow query log
+#[utoipa::path(
+    delete,
+    path = "/api/admin/slow-queries",
+    tag = "Admin",
+    params(
+        ("X-Admin-Key" = String, Header, description = "Admin API key"),
+        ("X-Admin-Actor" = Option<String>, Header, description = "Who is performing the action, recorded in the audit log")
+    ),
+    responses(
+        (status = 200, description = "Slow query log cleared", body = AdminActionResponse),
+        (status = 401, response = Unauthorized),
+        (status = 500, response = InternalServerError)
+    ),
+    summary = "Clear slow query log"
+)]
+#[actix_web::delete("/slow-queries")]
+pub async fn clear_slow_queries(
+    admin: AdminAuth,
+    recommendation_service: web::Data<RecommendationService>,
+    audit_log: web::Data<AuditLog>,
+) -> Result<HttpResponse, ApiError> {
+    let audit_entry = audit_log
+        .record(&admin.actor, "slow_queries.clear", json!({}))
+        .await?;
+
+    recommendation_service.slow_query_log().clear();
+
+    Ok(HttpResponse::Ok().json(AdminActionResponse::from(audit_entry)))
+}
+
+/// Clear recommendation, query enhancement and Pinecone caches
+#[utoipa::path(
+    post,
+    path = "/api/admin/cache/clear",
+    tag = "Admin",
+    params(
+        ("X-Admin-Key" = String, Header, description = "Admin API key"),
+        ("X-Admin-Actor" = Option<String>, Header, description = "Who is performing the action, recorded in the audit log")
+    ),
+    responses(
+        (status = 200, description = "Caches cleared", body = AdminActionResponse),
+        (status = 401, response = Unauthorized),
+        (status = 500, response = InternalServerError)
+    ),
+    summary = "Clear caches",
+    description = "Drops all cached recommendation results, query enhancements and Pinecone query results. \
+                   Subsequent queries are served cold until the caches refill."
+)]
+#[actix_web::post("/cache/clear")]
+pub async fn clear_caches(
+    admin: AdminAuth,
+    recommendation_service: web::Data<RecommendationService>,
+    audit_log: web::Data<AuditLog>,
+) -> Result<HttpResponse, ApiError> {
+    let audit_entry = audit_log
+        .record(
+            &admin.actor,
+            "cache.clear",
+            json!({ "caches": ["recommendations", "query_enhancer", "pinecone"] }),
+        )
+        .await?;
+
+    recommendation_service.clear_caches().await;
+
+    Ok(HttpResponse::Ok().json(AdminActionResponse::from(audit_entry)))
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CacheStatusResponse {
+    /// Fresh entries in the recommendation result cache
+    #[schema(example = 37)]
+    pub cached_results: usize,
+    /// Top queries preloaded by the last run, most frequent first
+    pub preloaded: Vec<PreloadedQuery>,
+}
+
+/// Show the result cache and the preloaded top queries
+#[utoipa::path(
+    get,
+    path = "/api/admin/cache",
+    tag = "Admin",
+    params(
+        ("X-Admin-Key" = String, Header, description = "Admin API key")
+    ),
+    responses(
+        (status = 200, description = "Result cache size and preloaded queries", body = CacheStatusResponse),
+        (status = 401, response = Unauthorized)
+    ),
+    summary = "Get cache status",
+    description = "Returns the number of cached recommendation results and the most frequent recent queries \
+                   whose results were preloaded, with their frequency and when they were computed."
+)]
+#[actix_web::get("/cache")]
+pub async fn get_cache_status(
+    _admin: AdminAuth,
+    recommendation_service: web::Data<RecommendationService>,
+    preloader: web::Data<QueryPreloader>,
+) -> Result<HttpResponse, ApiError> {
+    Ok(HttpResponse::Ok().json(CacheStatusResponse {
+        cached_results: recommendation_service.cached_result_count(),
+        preloaded: preloader.preloaded(),
+    }))
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PreloadResponse {
+    #[serde(flatten)]
+    pub audit: AdminActionResponse,
+    /// Queries whose results were preloaded, most frequent first
+    pub preloaded: Vec<PreloadedQuery>,
+}
+
+/// Preload the most frequent recent queries now
+#[utoipa::path(
+    post,
+    path = "/api/admin/cache/preload",
+    tag = "Admin",
+    params(
+        ("X-Admin-Key" = String, Header, description = "Admin API key"),
+        ("X-Admin-Actor" = Option<String>, Header, description = "Who is performing the action, recorded in the audit log")
+    ),
+    responses(
+        (status = 200, description = "Top queries preloaded", body = PreloadResponse),
+        (status = 401, response = Unauthorized),
+        (status = 500, response = InternalServerError)
+    ),
+    summary = "Preload top queries",
+    description = "Computes and caches results for the most frequent queries in recent analytics events and \
+                   the slow query log, without waiting for the next scheduled warmup. Waits for a preload \
+                   that is already running to finish first."
+)]
+#[actix_web::post("/cache/preload")]
+pub async fn preload_queries(
+    admin: AdminAuth,
+    preloader: web::Data<QueryPreloader>,
+    audit_log: web::Data<AuditLog>,
+) -> Result<HttpResponse, ApiError> {
+    let audit_entry = audit_log
+        .record(&admin.actor, "cache.preload", json!({}))
+        .await?;
+
+    preloader.preload().await;
+
+    Ok(HttpResponse::Ok().json(PreloadResponse {
+        audit: audit_entry.into(),
+        preloaded: preloader.preloaded(),
+    }))
+}
+
+/// An admin action and the audit log entry recording it; the responses of
+/// actions that return more embed it
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AdminActionResponse {
+    /// The action that was performed
+    #[schema(example = "cache.clear")]
+    pub action: String,
+    /// Id of the audit log entry recording the action
+    #[schema(example = 42)]
+    pub audit_id: i64,
+}
+
+impl From<AuditEntry> for AdminActionResponse {
+    fn from(entry: AuditEntry) -> Self {
+        Self {
+            action: entry.action,
+            audit_id: entry.id,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct AuditLogParams {
+    /// Only entries by this actor
+    #[schema(example = "ops-oncall")]
+    pub actor: Option<String>,
+    /// Only entries for this action
+    #[schema(example = "cache.clear")]
+    pub action: Option<String>,
+    /// Only entries recorded at or after this RFC3339 timestamp
+    #[schema(value_type = Option<String>, format = DateTime, example = "2024-01-15T00:00:00Z")]
+    pub since: Option<DateTime<Utc>>,
+    /// Maximum number of entries to return (default: 50, max: 500)
+    #[serde(default = "default_audit_log_limit")]
+    #[schema(example = 50, minimum = 1, maximum = 500)]
+    pub limit: i64,
+}
+
+fn default_audit_log_limit() -> i64 {
+    50
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AuditLogResponse {
+    /// Whether entries are persisted to the database or only kept in memory
+    #[schema(example = true)]
+    pub persistent: bool,
+    /// Matching entries, newest first
+    pub entries: Vec<AuditEntry>,
+}
+
+/// Query the admin audit log
+#[utoipa::path(
+    get,
+    path = "/api/admin/audit-log",
+    tag = "Admin",
+    params(
+        ("actor" = Option<String>, Query, description = "Only entries by this actor"),
+        ("action" = Option<String>, Query, description = "Only entries for this action", example = "cache.clear"),
+        ("since" = Option<String>, Query, description = "Only entries at or after this RFC3339 timestamp"),
+        ("limit" = Option<i64>, Query, description = "Maximum number of entries (default: 50, max: 500)", example = 50),
+        ("X-Admin-Key" = String, Header, description = "Admin API key")
+    ),
+    responses(
+        (status = 200, description = "Audit entries, newest first", body = AuditLogResponse),
+        (status = 401, response = Unauthorized),
+        (status = 400, description = "Invalid filter, such as a malformed since timestamp (invalid_input)", body = ErrorResponse,
+            example = json!({
+                "error": "Invalid input: Query deserialize error: input contains invalid characters",
+                "code": "invalid_input",
+                "status": 400
+            })),
+        (status = 500, response = InternalServerError)
+    ),
+    summary = "Get admin audit log",
+    description = "Returns the append-only trail of admin actions with actor, action, parameters and timestamp."
+)]
+#[actix_web::get("/audit-log")]
+pub async fn get_audit_log(
+    _admin: AdminAuth,
+    params: web::Query<AuditLogParams>,
+    audit_log: web::Data<AuditLog>,
+) -> Result<HttpResponse, ApiError> {
+    let params = params.into_inner();
+    let entries = audit_log
+        .query(&AuditQuery {
+            actor: params.actor,
+            action: params.action,
+            since: params.since,
+            limit: params.limit,
+        })
+        .await?;
+
+    Ok(HttpResponse::Ok().json(AuditLogResponse {
+        persistent: audit_log.is_persistent(),
+        entries,
+    }))
+}
+
+/// Progress of the current or most recent indexer run
+#[utoipa::path(
+    get,
+    path = "/api/admin/indexer/status",
+    tag = "Admin",
+    params(
+        ("X-Admin-Key" = String, Header, description = "Admin API key")
+    ),
+    responses(
+        (status = 200, description = "Latest indexer progress snapshot", body = IndexProgress),
+        (status = 401, response = Unauthorized),
+        (status = 404, description = "No status file configured or no run has reported yet (not_found)", body = ErrorResponse,
+            examples(
+                ("Not configured" = (value = json!({
+                    "error": "Not found: No indexer status file is configured",
+                    "code": "not_found",
+                    "status": 404
+                }))),
+                ("No run yet" = (value = json!({
+                    "error": "Not found: The indexer has not reported progress yet",
+                    "code": "not_found",
+                    "status": 404
+                })))
+            )),
+        (status = 500, response = InternalServerError)
+    ),
+    summary = "Get indexer progress",
+    description = "Returns the progress snapshot the indexer writes to the configured status file: \
+                   processed/total books, embedding and upsert throughput, failures and ETA."
+)]
+#[actix_web::get("/indexer/status")]
+pub async fn get_indexer_status(
+    _admin: AdminAuth,
+    config: web::Data<Config>,
+) -> Result<HttpResponse, ApiError> {
+    let path = config
+        .indexer_status_file
+        .as_deref()
+        .ok_or_else(|| ApiError::NotFound("No indexer status file is configured".to_string()))?;
+
+    let progress = read_status_file(std::path::Path::new(path))?.ok_or_else(|| {
+        ApiError::NotFound("The indexer has not reported progress yet".to_string())
+    })?;
+
+    Ok(HttpResponse::Ok().json(progress))
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SynonymsReloadResponse {
+    #[serde(flatten)]
+    pub audit: AdminActionResponse,
+    /// Genres the file adds keywords to or defines
+    #[schema(example = 3)]
+    pub genres: usize,
+    /// Themes the file adds keywords to or defines
+    #[schema(example = 5)]
+    pub themes: usize,
+}
+
+/// Reload the query synonyms file
+#[utoipa::path(
+    post,
+    path = "/api/admin/synonyms/reload",
+    tag = "Admin",
+    params(
+        ("X-Admin-Key" = String, Header, description = "Admin API key"),
+        ("X-Admin-Actor" = Option<String>, Header, description = "Who is performing the action, recorded in the audit log")
+    ),
+    responses(
+        (status = 200, description = "Synonyms reloaded", body = SynonymsReloadResponse),
+        (status = 400, description = "The file isn't valid TOML synonyms (invalid_input)", body = ErrorResponse,
+            example = json!({
+                "error": "Invalid input: Invalid synonyms: invalid type: sequence, expected a map for key `genres` at line 1 column 10",
+                "code": "invalid_input",
+                "status": 400
+            })),
+        (status = 401, response = Unauthorized),
+        (status = 404, description = "No synonyms file is configured (not_found)", body = ErrorResponse,
+            example = json!({
+                "error": "Not found: No synonyms file is configured",
+                "code": "not_found",
+                "status": 404
+            })),
+        (status = 500, response = InternalServerError)
+    ),
+    summary = "Reload query synonyms",
+    description = "Re-reads the configured synonyms file and merges its genre and theme keywords into the \
+                   compiled-in ones queries are matched against, replacing the synonyms loaded before. Takes \
+                   effect for the next query; cached results of earlier readings expire as usual. An invalid \
+                   file is rejected and the synonyms in use are kept."
+)]
+#[actix_web::post("/synonyms/reload")]
+pub async fn reload_synonyms(
+    admin: AdminAuth,
+    config: web::Data<Config>,
+    recommendation_service: web::Data<RecommendationService>,
+    audit_log: web::Data<AuditLog>,
+) -> Result<HttpResponse, ApiError> {
+    let path = config
+        .synonyms_file
+        .as_deref()
+        .ok_or_else(|| ApiError::NotFound("No synonyms file is configured".to_string()))?;
+    let synonyms = recommendation_service.reload_synonyms(std::path::Path::new(path))?;
+
+    let audit_entry = audit_log
+        .record(
+            &admin.actor,
+            "synonyms.reload",
+            json!({ "file": path, "genres": synonyms.genres.len(), "themes": synonyms.themes.len() }),
+        )
+        .await?;
+
+    Ok(HttpResponse::Ok().json(SynonymsReloadResponse {
+        audit: audit_entry.into(),
+        genres: synonyms.genres.len(),
+        themes: synonyms.themes.len(),
+    }))
+}
+
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct HideBookRequest {
+    /// Why the book is hidden, recorded in the audit log
+    #[schema(example = "Takedown request #1234")]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BookVisibilityResponse {
+    #[serde(flatten)]
+    pub audit: AdminActionResponse,
+    #[schema(example = "book_12345")]
+    pub book_id: String,
+    /// Whether the book is now hidden
+    #[schema(example = true)]
+    pub hidden: bool,
+}
+
+/// Hide or restore a book and record it in the audit log
+async fn set_book_hidden(
+    admin: &AdminAuth,
+    book_id: String,
+    hidden: bool,
+    reason: Option<String>,
+    recommendation_service: &RecommendationService,
+    audit_log: &AuditLog,
+) -> Result<HttpResponse, ApiError> {
+    validate_id("book id", &book_id)?;
+    recommendation_service.set_hidden(&book_id, hidden).await?;
+
+    let action = if hidden {
+        "books.hide"
+    } else {
+        "books.restore"
+    };
+    let audit_entry = audit_log
+        .record(
+            &admin.actor,
+            action,
+            json!({ "book_id": book_id, "reason": reason }),
+        )
+        .await?;
+
+    Ok(HttpResponse::Ok().json(BookVisibilityResponse {
+        audit: audit_entry.into(),
+        book_id,
+        hidden,
+    }))
+}
+
+/// Hide a book
+#[utoipa::path(
+    post,
+    path = "/api/admin/books/{id}/hide",
+    tag = "Admin",
+    params(
+        ("id" = String, Path, description = "Book id", example = "book_12345"),
+        ("X-Admin-Key" = String, Header, description = "Admin API key"),
+        ("X-Admin-Actor" = Option<String>, Header, description = "Who is performing the action, recorded in the audit log")
+    ),
+    request_body(content = Option<HideBookRequest>, description = "Why the book is hidden"),
+    responses(
+        (status = 200, description = "Book hidden", body = BookVisibilityResponse),
+        (status = 400, description = "Invalid book id (invalid_input)", body = ErrorResponse,
+            example = json!({
+                "error": "Invalid input: Invalid book id 'book 1': expected 1-128 letters, digits, '-', '_' or '.'",
+                "code": "invalid_input",
+                "status": 400
+            })),
+        (status = 401, response = Unauthorized),
+        (status = 404, description = "No book with this id is indexed (not_found)", body = ErrorResponse,
+            example = json!({
+                "error": "Not found: Book book_12345 not found",
+                "code": "not_found",
+                "status": 404
+            })),
+        (status = 500, response = InternalServerError),
+        (status = 502, response = BadGateway)
+    ),
+    summary = "Hide a book",
+    description = "Flags the book as hidden in the index and the graph, leaving its vector in place. Hidden \
+                   books are left out of searches, recommendations, book lookups, feeds, title suggestions \
+                   and the graph endpoints until they're restored, and keep the flag when the catalog is \
+                   reindexed. Cached results are dropped so the book disappears right away."
+)]
+#[actix_web::post("/books/{id}/hide")]
+pub async fn hide_book(
+    admin: AdminAuth,
+    path: web::Path<String>,
+    body: Option<web::Json<HideBookRequest>>,
+    recommendation_service: web::Data<RecommendationService>,
+    audit_log: web::Data<AuditLog>,
+) -> Result<HttpResponse, ApiError> {
+    let reason = body.and_then(|body| body.into_inner().reason);
+    set_book_hidden(
+        &admin,
+        path.into_inner(),
+        true,
+        reason,
+        &recommendation_service,
+        &audit_log,
+    )
+    .await
+}
+
+/// Restore a hidden book
+#[utoipa::path(
+    post,
+    path = "/api/admin/books/{id}/restore",
+    tag = "Admin",
+    params(
+        ("id" = String, Path, description = "Book id", example = "book_12345"),
+        ("X-Admin-Key" = String, Header, description = "Admin API key"),
+        ("X-Admin-Actor" = Option<String>, Header, description = "Who is performing the action, recorded in the audit log")
+    ),
+    responses(
+        (status = 200, description = "Book restored", body = BookVisibilityResponse),
+        (status = 400, description = "Invalid book id (invalid_input)", body = ErrorResponse),
+        (status = 401, response = Unauthorized),
+        (status = 404, description = "No book with this id is indexed (not_found)", body = ErrorResponse),
+        (status = 500, response = InternalServerError),
+        (status = 502, response = BadGateway)
+    ),
+    summary = "Restore a hidden book",
+    description = "Clears the book's hidden flag in the index and the graph, so it's served again. Restoring a \
+                   book that isn't hidden does nothing but record the action."
+)]
+#[actix_web::post("/books/{id}/restore")]
+pub async fn restore_book(
+    admin: AdminAuth,
+    path: web::Path<String>,
+    recommendation_service: web::Data<RecommendationService>,
+    audit_log: web::Data<AuditLog>,
+) -> Result<HttpResponse, ApiError> {
+    set_book_hidden(
+        &admin,
+        path.into_inner(),
+        false,
+        None,
+        &recommendation_service,
+        &audit_log,
+    )
+    .await
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BookReembedResponse {
+    #[serde(flatten)]
+    pub audit: AdminActionResponse,
+    #[schema(example = "book_12345")]
+    pub book_id: String,
+    /// Version of the book's metadata after re-embedding
+    #[schema(example = 3)]
+    pub version: i32,
+}
+
+/// Re-embed a book
+#[utoipa::path(
+    post,
+    path = "/api/admin/books/{id}/reembed",
+    tag = "Admin",
+    params(
+        ("id" = String, Path, description = "Book id", example = "book_12345"),
+        ("X-Admin-Key" = String, Header, description = "Admin API key"),
+        ("X-Admin-Actor" = Option<String>, Header, description = "Who is performing the action, recorded in the audit log")
+    ),
+    responses(
+        (status = 200, description = "Book re-embedded", body = BookReembedResponse),
+        (status = 400, description = "Invalid book id (invalid_input)", body = ErrorResponse),
+        (status = 401, response = Unauthorized),
+        (status = 404, description = "No book with this id is indexed (not_found)", body = ErrorResponse),
+        (status = 500, response = InternalServerError),
+        (status = 502, response = BadGateway)
+    ),
+    summary = "Re-embed a book",
+    description = "Rebuilds the book's searchable text from its current metadata, embeds it with the current model \
+                   and replaces its vector, so search matches it on a patched description or enriched fields \
+                   without a full index run. Moods are tagged again and a hidden book stays hidden. The book \
+                   gets a new version in its history when its content hash changed."
+)]
+#[actix_web::post("/books/{id}/reembed")]
+pub async fn reembed_book(
+    admin: AdminAuth,
+    path: web::Path<String>,
+    recommendation_service: web::Data<RecommendationService>,
+    history: web::Data<BookHistory>,
+    audit_log: web::Data<AuditLog>,
+) -> Result<HttpResponse, ApiError> {
+    let book_id = path.into_inner();
+    validate_id("book id", &book_id)?;
+    let version = recommendation_service
+        .reembed_book(&book_id, &history)
+        .await?;
+
+    let audit_entry = audit_log
+        .record(
+            &admin.actor,
+            "books.reembed",
+            json!({ "book_id": book_id, "version": version }),
+        )
+        .await?;
+
+    Ok(HttpResponse::Ok().json(BookReembedResponse {
+        audit: audit_entry.into(),
+        book_id,
+        version,
+    }))
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct HiddenBooksResponse {
+    /// Ids of the hidden books, sorted
+    #[schema(example = json!(["book_12345"]))]
+    pub book_ids: Vec<String>,
+}
+
+/// List hidden books
+#[utoipa::path(
+    get,
+    path = "/api/admin/books/hidden",
+    tag = "Admin",
+    params(
+        ("X-Admin-Key" = String, Header, description = "Admin API key")
+    ),
+    responses(
+        (status = 200, description = "Ids of the hidden books", body = HiddenBooksResponse),
+        (status = 401, response = Unauthorized),
+        (status = 500, response = InternalServerError),
+        (status = 502, response = BadGateway)
+    ),
+    summary = "List hidden books",
+    description = "Returns the ids of the books flagged hidden in the index (up to 10,000). The audit log \
+                   records who hid each one and why."
+)]
+#[actix_web::get("/books/hidden")]
+pub async fn get_hidden_books(
+    _admin: AdminAuth,
+    recommendation_service: web::Data<RecommendationService>,
+) -> Result<HttpResponse, ApiError> {
+    Ok(HttpResponse::Ok().json(HiddenBooksResponse {
+        book_ids: recommendation_service.hidden_books().await?,
+    }))
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct BookPatchRequest {
+    /// Books to patch, at most 100
+    pub patches: Vec<BookPatch>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BookPatchResponse {
+    #[serde(flatten)]
+    pub audit: AdminActionResponse,
+    /// What became of each patch, in request order
+    pub results: Vec<PatchResult>,
+}
+
+/// Patch the metadata of several books
+#[utoipa::path(
+    post,
+    path = "/api/admin/books/patch",
+    tag = "Admin",
+    params(
+        ("X-Admin-Key" = String, Header, description = "Admin API key"),
+        ("X-Admin-Actor" = Option<String>, Header, description = "Who is performing the action, recorded in the audit log")
+    ),
+    request_body = BookPatchRequest,
+    responses(
+        (status = 200, description = "Outcome of each patch", body = BookPatchResponse),
+        (status = 400, description = "No patches, too many, or an invalid book id (invalid_input)", body = ErrorResponse,
+            example = json!({
+                "error": "Invalid input: At most 100 books can be patched at once",
+                "code": "invalid_input",
+                "status": 400
+            })),
+        (status = 401, response = Unauthorized),
+        (status = 500, response = InternalServerError),
+        (status = 502, response = BadGateway)
+    ),
+    summary = "Patch book metadata",
+    description = "Sets the given metadata fields of each book, such as a corrected title, year or categories, \
+                   in the index and the graph without re-embedding it. Each changed book gets a new version in \
+                   its history. Bookkeeping fields (`version`, `content_hash`, `hidden`, ...) can't be patched \
+                   and fields can't be cleared. Patches succeed or fail one by one: each result says whether \
+                   the book was `updated`, `unchanged`, `not_found`, `invalid` or `failed`. Re-embed a book \
+                   whose description or categories changed for search to match it on them."
+)]
+#[actix_web::post("/books/patch")]
+pub async fn patch_books(
+    admin: AdminAuth,
+    body: web::Json<BookPatchRequest>,
+    recommendation_service: web::Data<RecommendationService>,
+    history: web::Data<BookHistory>,
+    audit_log: web::Data<AuditLog>,
+) -> Result<HttpResponse, ApiError> {
+    let patches = body.into_inner().patches;
+    if patches.is_empty() {
+        return Err(ApiError::InvalidInput(
+            "patches must not be empty".to_string(),
+        ));
+    }
+    if patches.len() > MAX_PATCHES {
+        return Err(ApiError::InvalidInput(format!(
+            "At most {} books can be patched at once",
+            MAX_PATCHES
+        )));
+    }
+    for patch in &patches {
+        validate_id("book id", &patch.id)?;
+    }
+
+    let results = recommendation_service
+        .patch_books(&patches, "patch", &history)
+        .await?;
+
+    let updated: Vec<&str> = results
+        .iter()
+        .filter(|result| result.status == PatchStatus::Updated)
+        .map(|result| result.id.as_str())
+        .collect();
+    let audit_entry = audit_log
+        .record(
+            &admin.actor,
+            "books.patch",
+            json!({
+                "book_ids": patches.iter().map(|patch| &patch.id).collect::<Vec<_>>(),
+                "updated": updated,
+            }),
+        )
+        .await?;
+
+    Ok(HttpResponse::Ok().json(BookPatchResponse {
+        audit: audit_entry.into(),
+        results,
+    }))
+}
+
+/// Most books one enrichment job looks up
+pub const MAX_ENRICH_BOOKS: usize = 100;
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct JobSubmittedResponse {
+    #[serde(flatten)]
+    pub audit: AdminActionResponse,
+    /// The queued job; poll `/api/admin/jobs/{id}` for its progress
+    pub job: Job,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct EnrichBooksRequest {
+    /// Books to look up, at most 100
+    #[schema(example = json!(["9780441013593"]))]
+    pub book_ids: Vec<String>,
+}
+
+/// Enrich book metadata in the background
+#[utoipa::path(
+    post,
+    path = "/api/admin/books/enrich",
+    tag = "Admin",
+    params(
+        ("X-Admin-Key" = String, Header, description = "Admin API key"),
+        ("X-Admin-Actor" = Option<String>, Header, description = "Who is performing the action, recorded in the audit log")
+    ),
+    request_body = EnrichBooksRequest,
+    responses(
+        (status = 202, description = "Enrichment job queued", body = JobSubmittedResponse),
+        (status = 400, description = "No books, too many, or an invalid book id (invalid_input)", body = ErrorResponse,
+            example = json!({
+                "error": "Invalid input: At most 100 books can be enriched at once",
+                "code": "invalid_input",
+                "status": 400
+            })),
+        (status = 401, response = Unauthorized),
+        (status = 500, response = InternalServerError)
+    ),
+    summary = "Enrich book metadata",
+    description = "Queues a job that fills the missing descriptions, covers, page counts and categories of the \
+                   given books from Google Books and Open Library by ISBN, then re-embeds and versions the books \
+                   that changed. The job's result lists the enriched books and those not found or failed."
+)]
+#[actix_web::post("/books/enrich")]
+pub async fn enrich_books(
+    admin: AdminAuth,
+    body: web::Json<EnrichBooksRequest>,
+    config: web::Data<Config>,
+    recommendation_service: web::Data<RecommendationService>,
+    history: web::Data<BookHistory>,
+    jobs: web::Data<JobQueue>,
+    audit_log: web::Data<AuditLog>,
+) -> Result<HttpResponse, ApiError> {
+    let book_ids = body.into_inner().book_ids;
+    if book_ids.is_empty() {
+        return Err(ApiError::InvalidInput(
+            "book_ids must not be empty".to_string(),
+        ));
+    }
+    if book_ids.len() > MAX_ENRICH_BOOKS {
+        return Err(ApiError::InvalidInput(format!(
+            "At most {} books can be enriched at once",
+            MAX_ENRICH_BOOKS
+        )));
+    }
+    for book_id in &book_ids {
+        validate_id("book id", book_id)?;
+    }
+    let mut enricher = MetadataEnricher::new(config.google_books_api_key.clone())?;
+
+    let parameters = json!({ "book_ids": book_ids });
+    let service = recommendation_service.into_inner();
+    let history = history.get_ref().clone();
+    let job = jobs
+        .submit("books.enrich", parameters.clone(), move |job| async move {
+            service
+                .enrich_books(&book_ids, &mut enricher, &history, &job)
+                .await
+        })
+        .await?;
+
+    submitted(&admin, "books.enrich", parameters, job, &audit_log).await
+}
+
+/// Sync the graph with the index in the background
+#[utoipa::path(
+    post,
+    path = "/api/admin/graph/sync",
+    tag = "Admin",
+    params(
+        ("X-Admin-Key" = String, Header, description = "Admin API key"),
+        ("X-Admin-Actor" = Option<String>, Header, description = "Who is performing the action, recorded in the audit log")
+    ),
+    responses(
+        (status = 202, description = "Graph sync job queued", body = JobSubmittedResponse),
+        (status = 401, response = Unauthorized),
+        (status = 500, response = InternalServerError)
+    ),
+    summary = "Sync the graph",
+    description = "Queues a job that adds or updates a graph node for every indexed book, carries over hidden \
+                   flags and deletes the nodes of books no longer indexed. Relationships are left as they are; \
+                   `rab-admin rebuild-graph` recomputes them. Needs Neo4j and a serverless Pinecone index."
+)]
+#[actix_web::post("/graph/sync")]
+pub async fn sync_graph(
+    admin: AdminAuth,
+    recommendation_service: web::Data<RecommendationService>,
+    jobs: web::Data<JobQueue>,
+    audit_log: web::Data<AuditLog>,
+) -> Result<HttpResponse, ApiError> {
+    let service = recommendation_service.into_inner();
+    let job = jobs
+        .submit("graph.sync", json!({}), move |job| async move {
+            service.sync_graph(&job, false).await
+        })
+        .await?;
+
+    submitted(&admin, "graph.sync", json!({}), job, &audit_log).await
+}
+
+/// Record a queued job in the audit log and answer with it
+async fn submitted(
+    admin: &AdminAuth,
+    action: &str,
+    mut parameters: serde_json::Value,
+    job: Job,
+    audit_log: &AuditLog,
+) -> Result<HttpResponse, ApiError> {
+    parameters["job_id"] = json!(job.id);
+    let audit_entry = audit_log.record(&admin.actor, action, parameters).await?;
+
+    Ok(HttpResponse::Accepted().json(JobSubmittedResponse {
+        audit: audit_entry.into(),
+        job,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct JobListParams {
+    pub status: Option<JobStatus>,
+    #[serde(default = "default_job_list_limit")]
+    pub limit: i64,
+}
+
+fn default_job_list_limit() -> i64 {
+    50
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct JobsResponse {
+    /// Whether jobs are persisted to the database or only kept in memory
+    #[schema(example = true)]
+    pub persistent: bool,
+    /// Matching jobs, newest first
+    pub jobs: Vec<Job>,
+}
+
+/// List background jobs
+#[utoipa::path(
+    get,
+    path = "/api/admin/jobs",
+    tag = "Admin",
+    params(
+        ("status" = Option<JobStatus>, Query, description = "Only jobs with this status", example = "running"),
+        ("limit" = Option<i64>, Query, description = "Maximum number of jobs (default: 50, max: 200)", example = 50),
+        ("X-Admin-Key" = String, Header, description = "Admin API key")
+    ),
+    responses(
+        (status = 200, description = "Jobs, newest first", body = JobsResponse),
+        (status = 400, description = "Unknown status (invalid_input)", body = ErrorResponse),
+        (status = 401, response = Unauthorized),
+        (status = 500, response = InternalServerError)
+    ),
+    summary = "List jobs",
+    description = "Returns recent background jobs (prewarms, graph syncs, enrichment, ...) with their status and progress."
+)]
+#[actix_web::get("/jobs")]
+pub async fn list_jobs(
+    _admin: AdminAuth,
+    params: web::Query<JobListParams>,
+    jobs: web::Data<JobQueue>,
+) -> Result<HttpResponse, ApiError> {
+    Ok(HttpResponse::Ok().json(JobsResponse {
+        persistent: jobs.is_persistent(),
+        jobs: jobs.list(params.status, params.limit).await?,
+    }))
+}
+
+fn parse_job_id(id: &str) -> Result<i64, ApiError> {
+    id.parse()
+        .map_err(|_| ApiError::InvalidInput(format!("Invalid job id '{}'", id)))
+}
+
+/// Get a background job
+#[utoipa::path(
+    get,
+    path = "/api/admin/jobs/{id}",
+    tag = "Admin",
+    params(
+        ("id" = i64, Path, description = "Job id", example = 7),
+        ("X-Admin-Key" = String, Header, description = "Admin API key")
+    ),
+    responses(
+        (status = 200, description = "The job", body = Job),
+        (status = 400, description = "Invalid job id (invalid_input)", body = ErrorResponse),
+        (status = 401, response = Unauthorized),
+        (status = 404, description = "No job with this id (not_found)", body = ErrorResponse,
+            example = json!({
+                "error": "Not found: Job 7 not found",
+                "code": "not_found",
+                "status": 404
+            })),
+        (status = 500, response = InternalServerError)
+    ),
+    summary = "Get a job",
+    description = "Returns a background job's status, progress in percent and latest message, and once it's \
+                   finished, its result or error."
+)]
+#[actix_web::get("/jobs/{id}")]
+pub async fn get_job(
+    _admin: AdminAuth,
+    path: web::Path<String>,
+    jobs: web::Data<JobQueue>,
+) -> Result<HttpResponse, ApiError> {
+    let id = parse_job_id(&path)?;
+    let job = jobs
+        .get(id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("Job {} not found", id)))?;
+    Ok(HttpResponse::Ok().json(job))
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct JobCancelResponse {
+    #[serde(flatten)]
+    pub audit: AdminActionResponse,
+    pub job: Job,
+}
+
+/// Cancel a background job
+#[utoipa::path(
+    post,
+    path = "/api/admin/jobs/{id}/cancel",
+    tag = "Admin",
+    params(
+        ("id" = i64, Path, description = "Job id", example = 7),
+        ("X-Admin-Key" = String, Header, description = "Admin API key"),
+        ("X-Admin-Actor" = Option<String>, Header, description = "Who is performing the action, recorded in the audit log")
+    ),
+    responses(
+        (status = 200, description = "Cancellation requested", body = JobCancelResponse),
+        (status = 400, description = "Invalid job id, or the job already finished or runs on another instance (invalid_input)", body = ErrorResponse,
+            example = json!({
+                "error": "Invalid input: Job 7 already succeeded",
+                "code": "invalid_input",
+                "status": 400
+            })),
+        (status = 401, response = Unauthorized),
+        (status = 404, description = "No job with this id (not_found)", body = ErrorResponse),
+        (status = 500, response = InternalServerError)
+    ),
+    summary = "Cancel a job",
+    description = "Cancels a queued job at once. A running job stops at its next check and ends up `cancelled` \
+                   with what it got done as its result; poll the job to see when it stopped."
+)]
+#[actix_web::post("/jobs/{id}/cancel")]
+pub async fn cancel_job(
+    admin: AdminAuth,
+    path: web::Path<String>,
+    jobs: web::Data<JobQueue>,
+    audit_log: web::Data<AuditLog>,
+) -> Result<HttpResponse, ApiError> {
+    let id = parse_job_id(&path)?;
+    let job = jobs.cancel(id).await?;
+    let audit_entry = audit_log
+        .record(&admin.actor, "jobs.cancel", json!({ "job_id": id }))
+        .await?;
+
+    Ok(HttpResponse::Ok().json(JobCancelResponse {
+        audit: audit_entry.into(),
+        job,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UsageParams {
+    pub month: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UsageResponse {
+    #[schema(example = "2026-10")]
+    pub month: String,
+    /// Whether counts are shared between instances through the database, or
+    /// only this instance's are reported
+    #[schema(example = true)]
+    pub persistent: bool,
+    /// Usage of each tenant, configured tenants included when they made no requests
+    pub tenants: Vec<TenantUsage>,
+}
+
+/// Get tenants' metered usage
+#[utoipa::path(
+    get,
+    path = "/api/admin/usage",
+    tag = "Admin",
+    params(
+        ("month" = Option<String>, Query, description = "Month as YYYY-MM in UTC (default: the current month)", example = "2026-10"),
+        ("X-Admin-Key" = String, Header, description = "Admin API key")
+    ),
+    responses(
+        (status = 200, description = "Usage of each tenant in the month", body = UsageResponse),
+        (status = 400, description = "Month not in YYYY-MM form (invalid_input)", body = ErrorResponse),
+        (status = 401, response = Unauthorized),
+        (status = 500, response = InternalServerError)
+    ),
+    summary = "Get tenant usage",
+    description = "Returns the requests, embedding calls and Pinecone reads of each tenant calling with an X-Api-Key \
+                   in a month, with its monthly quotas, for billing or fairness. Cached results only count as a \
+                   request. Tenants that reach a quota get 429s until the month ends."
+)]
+#[actix_web::get("/usage")]
+pub async fn get_usage(
+    _admin: AdminAuth,
+    params: web::Query<UsageParams>,
+    meter: web::Data<UsageMeter>,
+) -> Result<HttpResponse, ApiError> {
+    let month = params
+        .into_inner()
+        .month
+        .unwrap_or_else(|| month_of(Utc::now()));
+    if !is_month(&month) {
+        return Err(ApiError::InvalidInput(format!(
+            "month must be in YYYY-MM form, got '{}'",
+            month
+        )));
+    }
+
+    Ok(HttpResponse::Ok().json(UsageResponse {
+        persistent: meter.is_persistent(),
+        tenants: meter.usage(&month).await?,
+        month,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FeatureFlagsParams {
+    pub tenant: Option<String>,
+    pub user_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct FeatureFlagsResponse {
+    /// Current rule of each flag, by name, remote overrides included
+    pub rules: BTreeMap<String, FlagRule>,
+    /// The flags for a request from the tenant and user id asked about
+    pub evaluated: FlagSet,
+}
+
+/// Get the feature flags
+#[utoipa::path(
+    get,
+    path = "/api/admin/feature-flags",
+    tag = "Admin",
+    params(
+        ("tenant" = Option<String>, Query, description = "Tenant to evaluate the flags for", example = "acme"),
+        ("user_id" = Option<String>, Query, description = "User or session id to evaluate the flags for", example = "reader-42"),
+        ("X-Admin-Key" = String, Header, description = "Admin API key")
+    ),
+    responses(
+        (status = 200, description = "Flag rules and their evaluation", body = FeatureFlagsResponse),
+        (status = 400, description = "Invalid user id (invalid_input)", body = ErrorResponse),
+        (status = 401, response = Unauthorized),
+        (status = 500, response = InternalServerError)
+    ),
+    summary = "Get feature flags",
+    description = "Returns the current rule of each feature flag (rerank, llm_explanations, graph_blending and \
+                   fused_ranking), with the remote provider's overrides, and which flags are on for a request \
+                   from the given tenant and user or session id."
+)]
+#[actix_web::get("/feature-flags")]
+pub async fn get_feature_flags(
+    _admin: AdminAuth,
+    params: web::Query<FeatureFlagsParams>,
+    recommendation_service: web::Data<RecommendationService>,
+) -> Result<HttpResponse, ApiError> {
+    let params = params.into_inner();
+    if let Some(user_id) = &params.user_id {
+        validate_id("user id", user_id)?;
+    }
+
+    let flags = recommendation_service.feature_flags();
+    Ok(HttpResponse::Ok().json(FeatureFlagsResponse {
+        rules: flags.rules(),
+        evaluated: flags.evaluate(&FlagContext {
+            tenant: params.tenant.as_deref(),
+            user: params.user_id.as_deref(),
+        }),
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ShadowParams {
+    /// Maximum number of comparisons to return (default: 50)
+    #[serde(default = "default_slow_query_limit")]
+    pub limit: usize,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ShadowReport {
+    /// Ranker evaluated in shadow; null when shadow ranking is off
+    pub ranker: Option<Ranker>,
+    /// Share of ranked queries ranked again in shadow
+    #[schema(example = 10)]
+    pub sample_percent: u8,
+    pub summary: ShadowSummary,
+    /// Most recent comparisons, newest first
+    pub comparisons: Vec<ShadowComparison>,
+}
+
+/// Get the shadow ranking comparisons
+#[utoipa::path(
+    get,
+    path = "/api/admin/shadow",
+    tag = "Admin",
+    params(
+        ("limit" = Option<usize>, Query, description = "Maximum number of comparisons to return (default: 50)"),
+        ("X-Admin-Key" = String, Header, description = "Admin API key")
+    ),
+    responses(
+        (status = 200, description = "Shadow ranker and its comparisons with the served rankings", body = ShadowReport),
+        (status = 401, response = Unauthorized),
+        (status = 500, response = InternalServerError)
+    ),
+    summary = "Get shadow ranking comparisons",
+    description = "With a shadow ranker configured, a sample of the queries that get ranked are ranked again by \
+                   it in the background and compared with the results that were served, which always come from \
+                   the production ranker. Each comparison has the Kendall's tau of the two orders, the pairs of \
+                   books they order differently, the share of served books the shadow ranker also returned, the \
+                   mean position shift, the difference in mean rating and the books that moved the most; the \
+                   summary averages every comparison since startup."
+)]
+#[actix_web::get("/shadow")]
+pub async fn get_shadow_report(
+    _admin: AdminAuth,
+    params: web::Query<ShadowParams>,
+    recommendation_service: web::Data<RecommendationService>,
+) -> Result<HttpResponse, ApiError> {
+    let shadow = recommendation_service.shadow();
+    Ok(HttpResponse::Ok().json(ShadowReport {
+        ranker: shadow.config().ranker,
+        sample_percent: shadow.config().sample_percent,
+        summary: shadow.summary(),
+        comparisons: shadow.recent(params.limit),
+    }))
+}
+
+pub fn admin_config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin")
+            .service(get_slow_queries)
+            .service(clear_slow_queries)
+            .service(clear_caches)
+            .service(get_cache_status)
+            .service(preload_queries)
+            .service(get_audit_log)
+            .service(get_indexer_status)
+            .service(reload_synonyms)
+            .service(get_hidden_books)
+            .service(patch_books)
+            .service(hide_book)
+            .service(restore_book)
+            .service(reembed_book)
+            .service(enrich_books)
+            .service(sync_graph)
+            .service(list_jobs)
+            .service(get_job)
+            .service(cancel_job)
+            .service(get_usage)
+            .service(get_feature_flags)
+            .service(get_shadow_report),
+    );
+}
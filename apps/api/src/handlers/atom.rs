@@ -0,0 +1,223 @@
+//! Atom 1.0 serialization for the feeds and the OPDS catalog
+//!
+//! OPDS 1.2 catalogs are Atom feeds with extra link relations and Dublin Core
+//! metadata, so both are written by the same serializer. Feeds are small enough
+//! to build as a string; text is escaped as it is written.
+
+use actix_web::{http::header, HttpRequest, HttpResponse};
+use std::borrow::Cow;
+use std::fmt::Write;
+
+/// Media type of plain Atom feeds
+pub const ATOM_MEDIA_TYPE: &str = "application/atom+xml; charset=utf-8";
+
+/// A link from a feed or entry
+pub struct AtomLink {
+    pub rel: String,
+    pub href: String,
+    pub media_type: String,
+    pub title: Option<String>,
+}
+
+impl AtomLink {
+    pub fn new(
+        rel: impl Into<String>,
+        href: impl Into<String>,
+        media_type: impl Into<String>,
+    ) -> Self {
+        Self {
+            rel: rel.into(),
+            href: href.into(),
+            media_type: media_type.into(),
+            title: None,
+        }
+    }
+
+    pub fn with_title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+}
+
+/// A feed entry
+#[derive(Default)]
+pub struct AtomEntry {
+    pub id: String,
+    pub title: String,
+    /// RFC3339 timestamp
+    pub updated: String,
+    pub authors: Vec<String>,
+    pub categories: Vec<String>,
+    pub summary: Option<String>,
+    /// Full text, written as plain text
+    pub content: Option<String>,
+    pub links: Vec<AtomLink>,
+    /// Dublin Core terms such as `identifier` or `issued`, written as `dc:<term>`
+    pub dublin_core: Vec<(&'static str, String)>,
+}
+
+/// An Atom feed
+pub struct AtomFeed {
+    pub id: String,
+    pub title: String,
+    pub subtitle: Option<String>,
+    /// RFC3339 timestamp
+    pub updated: String,
+    pub links: Vec<AtomLink>,
+    pub entries: Vec<AtomEntry>,
+}
+
+impl AtomFeed {
+    pub fn render(&self) -> String {
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+        xml.push_str(
+            "<feed xmlns=\"http://www.w3.org/2005/Atom\" xmlns:dc=\"http://purl.org/dc/terms/\" \
+             xmlns:opds=\"http://opds-spec.org/2010/catalog\">\n",
+        );
+        let _ = writeln!(xml, "  <id>{}</id>", escape(&self.id));
+        let _ = writeln!(xml, "  <title>{}</title>", escape(&self.title));
+        if let Some(subtitle) = &self.subtitle {
+            let _ = writeln!(xml, "  <subtitle>{}</subtitle>", escape(subtitle));
+        }
+        let _ = writeln!(xml, "  <updated>{}</updated>", escape(&self.updated));
+        for link in &self.links {
+            write_link(&mut xml, "  ", link);
+        }
+        xml.push_str("  <author><name>Recommend a Book</name></author>\n");
+
+        for entry in &self.entries {
+            xml.push_str("  <entry>\n");
+            let _ = writeln!(xml, "    <id>{}</id>", escape(&entry.id));
+            let _ = writeln!(xml, "    <title>{}</title>", escape(&entry.title));
+            let _ = writeln!(xml, "    <updated>{}</updated>", escape(&entry.updated));
+            for author in &entry.authors {
+                let _ = writeln!(xml, "    <author><name>{}</name></author>", escape(author));
+            }
+            for category in &entry.categories {
+                let _ = writeln!(
+                    xml,
+                    "    <category term=\"{}\" label=\"{}\"/>",
+                    escape(category),
+                    escape(category)
+                );
+            }
+            for (term, value) in &entry.dublin_core {
+                let _ = writeln!(xml, "    <dc:{}>{}</dc:{}>", term, escape(value), term);
+            }
+            if let Some(summary) = &entry.summary {
+                let _ = writeln!(xml, "    <summary>{}</summary>", escape(summary));
+            }
+            if let Some(content) = &entry.content {
+                let _ = writeln!(
+                    xml,
+                    "    <content type=\"text\">{}</content>",
+                    escape(content)
+                );
+            }
+            for link in &entry.links {
+                write_link(&mut xml, "    ", link);
+            }
+            xml.push_str("  </entry>\n");
+        }
+
+        xml.push_str("</feed>\n");
+        xml
+    }
+}
+
+fn write_link(xml: &mut String, indent: &str, link: &AtomLink) {
+    let _ = write!(
+        xml,
+        "{}<link rel=\"{}\" href=\"{}\" type=\"{}\"",
+        indent,
+        escape(&link.rel),
+        escape(&link.href),
+        escape(&link.media_type)
+    );
+    if let Some(title) = &link.title {
+        let _ = write!(xml, " title=\"{}\"", escape(title));
+    }
+    xml.push_str("/>\n");
+}
+
+/// Scheme and host the request was made to, e.g. `https://api.example.com`
+pub fn base_url(request: &HttpRequest) -> String {
+    let connection = request.connection_info();
+    format!("{}://{}", connection.scheme(), connection.host())
+}
+
+/// Respond with `xml`, letting clients cache it for `max_age_secs`
+pub fn xml_response(media_type: &str, max_age_secs: u32, xml: String) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(media_type)
+        .insert_header((
+            header::CACHE_CONTROL,
+            format!("public, max-age={}", max_age_secs),
+        ))
+        .body(xml)
+}
+
+/// Escape text for XML content and attribute values, dropping characters XML can't carry
+pub fn escape(text: &str) -> Cow<'_, str> {
+    let is_invalid = |c: char| c.is_control() && !matches!(c, '\t' | '\n' | '\r');
+    if !text.contains(|c: char| matches!(c, '&' | '<' | '>' | '"' | '\'') || is_invalid(c)) {
+        return Cow::Borrowed(text);
+    }
+
+    let mut escaped = String::with_capacity(text.len() + 16);
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c if is_invalid(c) => {}
+            c => escaped.push(c),
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_feed_escapes_book_text() {
+        let feed = AtomFeed {
+            id: "http://localhost:8000/feeds/new.xml".to_string(),
+            title: "New books".to_string(),
+            subtitle: None,
+            updated: "2024-01-15T10:30:00+00:00".to_string(),
+            links: vec![AtomLink::new(
+                "self",
+                "http://localhost:8000/feeds/new.xml?a=1&b=2",
+                "application/atom+xml",
+            )],
+            entries: vec![AtomEntry {
+                id: "urn:recommend-a-book:book:book-1".to_string(),
+                title: "Pride & Prejudice".to_string(),
+                updated: "2024-01-15T10:30:00+00:00".to_string(),
+                authors: vec!["Jane Austen".to_string()],
+                categories: vec!["Romance <Classic>".to_string()],
+                summary: Some("New in the catalog. \"It is a truth\u{1}…\"".to_string()),
+                content: Some("It is a truth universally acknowledged".to_string()),
+                dublin_core: vec![("issued", "1813".to_string())],
+                ..Default::default()
+            }],
+        };
+
+        let xml = feed.render();
+        assert!(xml.contains("<title>Pride &amp; Prejudice</title>"));
+        assert!(xml.contains("term=\"Romance &lt;Classic&gt;\""));
+        assert!(xml.contains("<summary>New in the catalog. &quot;It is a truth…&quot;</summary>"));
+        assert!(xml.contains("href=\"http://localhost:8000/feeds/new.xml?a=1&amp;b=2\""));
+        assert!(xml.contains("<dc:issued>1813</dc:issued>"));
+        assert!(
+            xml.contains("<content type=\"text\">It is a truth universally acknowledged</content>")
+        );
+        assert_eq!(xml.matches("<entry>").count(), 1);
+        assert!(xml.ends_with("</feed>\n"));
+    }
+}
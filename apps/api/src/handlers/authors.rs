@@ -0,0 +1,63 @@
+//! Author profiles built from the corpus statistics snapshot
+//!
+//! A profile lists an author's books with their aggregate rating, genres and
+//! co-authors, and the authors whose books read most alike by embedding.
+
+use crate::{
+    error::ApiError,
+    handlers::recommendations::request_tenant,
+    models::{BadGateway, ErrorResponse, InternalServerError},
+    services::{author_profile::AuthorProfile, RecommendationService},
+};
+use actix_web::{web, HttpRequest, HttpResponse};
+
+/// Get an author's profile
+#[utoipa::path(
+    get,
+    path = "/api/authors/{name}",
+    tag = "Catalog",
+    params(
+        ("name" = String, Path, description = "Author name, matched ignoring case and punctuation; a bare surname matches the most prolific author with it", example = "Ursula K. Le Guin")
+    ),
+    responses(
+        (status = 200, description = "The author's books, average rating, dominant genres and themes, co-authors and similar authors", body = AuthorProfile),
+        (status = 404, description = "No corpus statistics snapshot is loaded, or the author has no books in it (not_found)", body = ErrorResponse,
+            example = json!({
+                "error": "Not found: Author Jane Doe has no indexed books",
+                "code": "not_found",
+                "status": 404
+            })),
+        (status = 500, response = InternalServerError),
+        (status = 502, response = BadGateway)
+    ),
+    summary = "Get an author profile",
+    description = "Lists up to 100 of the author's most rated books from the corpus statistics snapshot, with their \
+                   average rating, the genres, moods and settings of the most books and the people the author wrote \
+                   with. Similar authors are those whose books' average embedding is closest to the author's, \
+                   with a cosine similarity of at least 0.6; the list is empty when embeddings can't be loaded."
+)]
+#[actix_web::get("/{name}")]
+pub async fn get_author_profile(
+    http_request: HttpRequest,
+    path: web::Path<String>,
+    recommendation_service: web::Data<RecommendationService>,
+) -> Result<HttpResponse, ApiError> {
+    let name = path.into_inner();
+    if name.trim().is_empty() {
+        return Err(ApiError::InvalidInput(
+            "Author name cannot be empty".to_string(),
+        ));
+    }
+
+    let mut profile = recommendation_service
+        .author_profile(&name)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("Author {} has no indexed books", name)))?;
+    recommendation_service
+        .retain_allowed(&mut profile.books, request_tenant(&http_request).as_deref());
+    Ok(HttpResponse::Ok().json(profile))
+}
+
+pub fn authors_config(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::scope("/authors").service(get_author_profile));
+}
@@ -0,0 +1,143 @@
+//! JSON responses carrying a long list of books
+//!
+//! A top_k=200 response is mostly its book list. Rather than serializing the
+//! whole response into one buffer before sending anything, long lists are
+//! streamed: the response's other fields are written around the list, and books
+//! are serialized a chunk at a time as the client reads them. Books are shared
+//! with the result cache; only those whose descriptions get cut are copied.
+
+use crate::{
+    error::ApiError,
+    models::{Book, BookProjection},
+};
+use actix_web::{http::header::ContentType, web::Bytes, HttpResponse};
+use futures::{stream, StreamExt};
+use serde::Serialize;
+use std::sync::Arc;
+
+/// Lists shorter than this are sent in one piece
+const STREAM_MIN_BOOKS: usize = 50;
+
+/// Books serialized per streamed chunk
+const BOOKS_PER_CHUNK: usize = 25;
+
+/// Respond with `envelope`, whose `key` list (left empty) is filled with `books`
+///
+/// Descriptions are truncated and fields dropped as `projection` asks.
+pub fn book_list_response<T: Serialize>(
+    envelope: &T,
+    key: &str,
+    mut books: Vec<Arc<Book>>,
+    projection: BookProjection,
+) -> Result<HttpResponse, ApiError> {
+    let envelope = serde_json::to_string(envelope)?;
+    // Keys are the only place an unescaped `"key":[]` can appear
+    let marker = format!("\"{}\":[]", key);
+    let split = envelope
+        .find(&marker)
+        .map(|start| start + marker.len() - 1)
+        .ok_or_else(|| ApiError::InternalError(format!("Response has no empty '{}' list", key)))?;
+    let (head, tail) = envelope.split_at(split);
+
+    if books.len() < STREAM_MIN_BOOKS {
+        let mut body = head.as_bytes().to_vec();
+        write_books(&mut body, &mut books, &projection, true)?;
+        body.extend_from_slice(tail.as_bytes());
+        return Ok(HttpResponse::Ok()
+            .content_type(ContentType::json())
+            .body(body));
+    }
+
+    let head = Bytes::copy_from_slice(head.as_bytes());
+    let tail = Bytes::copy_from_slice(tail.as_bytes());
+    let mut remaining = books.into_iter();
+    let mut first = true;
+    let chunks = std::iter::from_fn(move || {
+        let mut chunk: Vec<Arc<Book>> = remaining.by_ref().take(BOOKS_PER_CHUNK).collect();
+        if chunk.is_empty() {
+            return None;
+        }
+        let mut out = Vec::new();
+        let written = write_books(&mut out, &mut chunk, &projection, first);
+        first = false;
+        Some(written.map(|_| Bytes::from(out)))
+    });
+
+    let body = stream::iter(std::iter::once(Ok::<_, ApiError>(head)))
+        .chain(stream::iter(chunks))
+        .chain(stream::iter(std::iter::once(Ok(tail))));
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .streaming(body))
+}
+
+/// Append comma-separated books, with a leading comma unless they start the list
+fn write_books(
+    out: &mut Vec<u8>,
+    books: &mut [Arc<Book>],
+    projection: &BookProjection,
+    starts_list: bool,
+) -> Result<(), ApiError> {
+    projection.truncate_descriptions(books);
+    for (i, book) in books.iter().enumerate() {
+        if i > 0 || !starts_list {
+            out.push(b',');
+        }
+        projection.write_book(book, out)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{CacheStatus, RecommendationResponse, SearchPath};
+    use serde_json::{json, Value};
+
+    #[actix_web::test]
+    async fn test_streamed_list_is_complete_json() {
+        let books: Vec<Arc<Book>> = (0..STREAM_MIN_BOOKS + BOOKS_PER_CHUNK + 1)
+            .map(|i| {
+                serde_json::from_value(json!({ "id": i.to_string(), "categories": [] }))
+                    .map(Arc::new)
+                    .expect("valid book")
+            })
+            .collect();
+        let envelope = RecommendationResponse {
+            recommendations: Vec::new(),
+            semantic_tags: vec!["Fantasy".to_string()],
+            tags: Vec::new(),
+            refinements: Vec::new(),
+            relaxed: Vec::new(),
+            experiments: Vec::new(),
+            cache: CacheStatus::Hit,
+            took_ms: 12,
+            degraded: false,
+            model_used: None,
+            search_path: SearchPath::Vector,
+            conversation: None,
+        };
+
+        let response = book_list_response(
+            &envelope,
+            "recommendations",
+            books,
+            BookProjection::default(),
+        )
+        .expect("response");
+        let body = actix_web::body::to_bytes(response.into_body())
+            .await
+            .expect("body");
+        let body: Value = serde_json::from_slice(&body).expect("valid JSON");
+
+        let recommendations = body["recommendations"].as_array().expect("list");
+        assert_eq!(
+            recommendations.len(),
+            STREAM_MIN_BOOKS + BOOKS_PER_CHUNK + 1
+        );
+        assert_eq!(recommendations[0]["id"], "0");
+        assert_eq!(body["semantic_tags"], json!(["Fantasy"]));
+        assert_eq!(body["cache"], "hit");
+        assert_eq!(body["took_ms"], 12);
+    }
+}
@@ -0,0 +1,453 @@
+//! Structured data for server-rendered book pages
+//!
+//! The web frontend embeds the JSON-LD of a book in its page so search engines
+//! can show it as a rich result, and proxies the sitemap so they find the pages
+//! in the first place. Book pages live at `<site_url>/books/<id>`. Its compare
+//! view gets a few books side by side from `/api/books/compare`, and a book's
+//! metadata changes are listed by `/api/books/{id}/history`.
+
+use super::atom::{escape, xml_response};
+use crate::{
+    config::Config,
+    error::ApiError,
+    ingest::corpus_stats::TitleEntry,
+    models::{BadGateway, Book, ErrorResponse, InternalServerError},
+    services::{
+        book_versions::{BookHistory, BookVersion},
+        compare::{BookComparison, MAX_COMPARED_BOOKS, MIN_COMPARED_BOOKS},
+        supabase::validate_id,
+        RecommendationService,
+    },
+};
+use actix_web::{http::header, web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::fmt::Write;
+use utoipa::ToSchema;
+
+/// Media type of JSON-LD documents
+const JSON_LD_MEDIA_TYPE: &str = "application/ld+json";
+
+/// Most URLs a single sitemap file may list
+const MAX_SITEMAP_URLS: usize = 50_000;
+
+/// How long crawlers and the frontend may cache the sitemap and JSON-LD
+const MAX_AGE_SECS: u32 = 60 * 60;
+
+/// Versions listed by the history when no limit is given
+const DEFAULT_HISTORY_LIMIT: i64 = 50;
+
+/// URL of a book's page on the web frontend
+pub fn book_page_url(site_url: &str, book_id: &str) -> Option<String> {
+    let mut url = reqwest::Url::parse(site_url).ok()?;
+    url.path_segments_mut()
+        .ok()?
+        .pop_if_empty()
+        .extend(["books", book_id]);
+    Some(url.to_string())
+}
+
+/// schema.org `Book` markup, linked to its page when the frontend's URL is known
+pub fn book_json_ld(book: &Book, page_url: Option<&str>) -> Value {
+    let mut markup = Map::new();
+    markup.insert("@context".into(), json!("https://schema.org"));
+    markup.insert("@type".into(), json!("Book"));
+    match page_url {
+        Some(url) => {
+            markup.insert("@id".into(), json!(url));
+            markup.insert("url".into(), json!(url));
+        }
+        None => {
+            if let Some(id) = &book.id {
+                markup.insert(
+                    "@id".into(),
+                    json!(format!("urn:recommend-a-book:book:{}", id)),
+                );
+            }
+        }
+    }
+
+    let mut insert = |key: &str, value: Option<Value>| {
+        if let Some(value) = value {
+            markup.insert(key.into(), value);
+        }
+    };
+    insert("name", book.title.as_ref().map(|title| json!(title)));
+    insert(
+        "author",
+        book.author
+            .as_ref()
+            .map(|author| json!({ "@type": "Person", "name": author })),
+    );
+    insert(
+        "description",
+        book.description
+            .as_ref()
+            .filter(|description| !description.trim().is_empty())
+            .map(|description| json!(description)),
+    );
+    insert(
+        "genre",
+        (!book.categories.is_empty()).then(|| json!(book.categories)),
+    );
+    insert("image", book.thumbnail.as_ref().map(|url| json!(url)));
+    insert("isbn", book.isbn.as_ref().map(|isbn| json!(isbn)));
+    insert(
+        "numberOfPages",
+        book.page_count
+            .filter(|&pages| pages > 0)
+            .map(|pages| json!(pages)),
+    );
+    insert(
+        "inLanguage",
+        book.language.as_ref().map(|language| json!(language)),
+    );
+    insert(
+        "publisher",
+        book.publisher
+            .as_ref()
+            .map(|publisher| json!({ "@type": "Organization", "name": publisher })),
+    );
+    insert(
+        "datePublished",
+        book.year.map(|year| json!(year.to_string())),
+    );
+    // Rich results require a rating count alongside the value
+    insert(
+        "aggregateRating",
+        book.ratings_count
+            .filter(|&count| count > 0 && book.rating > 0.0)
+            .map(|count| {
+                json!({
+                    "@type": "AggregateRating",
+                    "ratingValue": book.rating,
+                    "ratingCount": count,
+                    "bestRating": 5,
+                    "worstRating": 0
+                })
+            }),
+    );
+
+    Value::Object(markup)
+}
+
+/// Sitemap of `urls`, each with its last modification date
+pub fn render_sitemap<'a>(urls: impl IntoIterator<Item = (String, &'a str)>) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    xml.push_str("<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n");
+    for (url, last_modified) in urls {
+        let _ = writeln!(
+            xml,
+            "  <url><loc>{}</loc><lastmod>{}</lastmod></url>",
+            escape(&url),
+            escape(last_modified)
+        );
+    }
+    xml.push_str("</urlset>\n");
+    xml
+}
+
+/// Get a book's JSON-LD markup
+#[utoipa::path(
+    get,
+    path = "/api/books/{id}/jsonld",
+    tag = "Books",
+    params(
+        ("id" = String, Path, description = "Book id", example = "book_12345")
+    ),
+    responses(
+        (status = 200, description = "schema.org Book markup for the book's page", content_type = "application/ld+json", body = Object,
+            example = json!({
+                "@context": "https://schema.org",
+                "@type": "Book",
+                "@id": "https://recommend-a-book.example.com/books/book_12345",
+                "url": "https://recommend-a-book.example.com/books/book_12345",
+                "name": "The Hobbit",
+                "author": { "@type": "Person", "name": "J.R.R. Tolkien" },
+                "genre": ["Fantasy", "Adventure"],
+                "isbn": "978-0547928227",
+                "numberOfPages": 310,
+                "datePublished": "1937",
+                "aggregateRating": {
+                    "@type": "AggregateRating",
+                    "ratingValue": 4.5,
+                    "ratingCount": 1500,
+                    "bestRating": 5,
+                    "worstRating": 0
+                }
+            })),
+        (status = 404, description = "No book with this id is indexed (not_found)", body = ErrorResponse,
+            example = json!({
+                "error": "Not found: Book book_12345 not found",
+                "code": "not_found",
+                "status": 404
+            })),
+        (status = 500, response = InternalServerError),
+        (status = 502, response = BadGateway)
+    ),
+    summary = "Get book JSON-LD",
+    description = "Returns the book as schema.org `Book` markup for embedding in a server-rendered page. \
+                   Fields the catalog lacks are left out; `url` is set when a site URL is configured."
+)]
+#[actix_web::get("/{id}/jsonld")]
+pub async fn get_book_json_ld(
+    path: web::Path<String>,
+    recommendation_service: web::Data<RecommendationService>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse, ApiError> {
+    let book_id = path.into_inner();
+    let book = recommendation_service
+        .get_book(&book_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("Book {} not found", book_id)))?;
+
+    let page_url = config
+        .site_url
+        .as_deref()
+        .and_then(|site_url| book_page_url(site_url, &book_id));
+
+    Ok(HttpResponse::Ok()
+        .content_type(JSON_LD_MEDIA_TYPE)
+        .insert_header((
+            header::CACHE_CONTROL,
+            format!("public, max-age={}", MAX_AGE_SECS),
+        ))
+        .json(book_json_ld(&book, page_url.as_deref())))
+}
+
+/// Sitemap of the frontend's book pages
+#[utoipa::path(
+    get,
+    path = "/api/books/sitemap.xml",
+    tag = "Books",
+    responses(
+        (status = 200, description = "Sitemap of the site's home page and book pages", content_type = "application/xml", body = String),
+        (status = 404, description = "No site URL is configured (not_found)", body = ErrorResponse,
+            example = json!({
+                "error": "Not found: No site URL is configured",
+                "code": "not_found",
+                "status": 404
+            }))
+    ),
+    summary = "Get the sitemap",
+    description = "Lists the page of every book in the corpus statistics snapshot, up to 50,000 of the most \
+                   rated, dated by the snapshot. Meant to be served by the frontend as its `/sitemap.xml`."
+)]
+#[actix_web::get("/sitemap.xml")]
+pub async fn get_sitemap(
+    recommendation_service: web::Data<RecommendationService>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse, ApiError> {
+    let site_url = config
+        .site_url
+        .as_deref()
+        .ok_or_else(|| ApiError::NotFound("No site URL is configured".to_string()))?;
+
+    let stats = recommendation_service.corpus_stats();
+    let generated_at = stats.map_or("", |stats| stats.generated_at.as_str());
+    // Sitemaps take W3C dates; the date part of the snapshot's timestamp is one
+    let last_modified = generated_at.get(..10).unwrap_or_default();
+
+    let home = (format!("{}/", site_url), last_modified);
+    // Titles are kept in title order; stable, so equally rated books stay in it
+    let mut entries: Vec<&TitleEntry> = stats
+        .map(|stats| stats.titles())
+        .unwrap_or_default()
+        .iter()
+        .filter(|entry| !recommendation_service.is_hidden(entry.id.as_deref()))
+        .collect();
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.ratings_count));
+    let books = entries
+        .into_iter()
+        .filter_map(|entry| book_page_url(site_url, entry.id.as_deref()?))
+        .map(|url| (url, last_modified));
+    let urls: Vec<(String, &str)> = std::iter::once(home)
+        .chain(books)
+        .take(MAX_SITEMAP_URLS)
+        .collect();
+
+    Ok(xml_response(
+        "application/xml; charset=utf-8",
+        MAX_AGE_SECS,
+        render_sitemap(urls),
+    ))
+}
+
+/// Book ids of a comparison, from a comma-separated list; duplicates are dropped
+pub fn parse_compare_ids(ids: &str) -> Result<Vec<String>, ApiError> {
+    let mut parsed: Vec<String> = Vec::new();
+    for id in ids.split(',').map(str::trim).filter(|id| !id.is_empty()) {
+        validate_id("book id", id)?;
+        if !parsed.iter().any(|known| known == id) {
+            parsed.push(id.to_string());
+        }
+    }
+    if !(MIN_COMPARED_BOOKS..=MAX_COMPARED_BOOKS).contains(&parsed.len()) {
+        return Err(ApiError::InvalidInput(format!(
+            "Compare between {} and {} different books",
+            MIN_COMPARED_BOOKS, MAX_COMPARED_BOOKS
+        )));
+    }
+    Ok(parsed)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CompareParams {
+    pub ids: String,
+}
+
+/// Compare books side by side
+#[utoipa::path(
+    get,
+    path = "/api/books/compare",
+    tag = "Books",
+    params(
+        ("ids" = String, Query, description = "Comma-separated ids of 2 to 5 books", example = "9780547928227,9780441013593")
+    ),
+    responses(
+        (status = 200, description = "What the books share and how they differ", body = BookComparison),
+        (status = 400, description = "Fewer than 2 or more than 5 books, or an invalid id (invalid_input)", body = ErrorResponse,
+            example = json!({
+                "error": "Invalid input: Compare between 2 and 5 different books",
+                "code": "invalid_input",
+                "status": 400
+            })),
+        (status = 404, description = "Some of the books aren't indexed (not_found)", body = ErrorResponse,
+            example = json!({
+                "error": "Not found: Books not indexed: book_12345",
+                "code": "not_found",
+                "status": 404
+            })),
+        (status = 500, response = InternalServerError),
+        (status = 502, response = BadGateway)
+    ),
+    summary = "Compare books",
+    description = "Returns the books with the genres and themes (moods, settings, awards) they all share, the \
+                   rating, page count and publication year differences of every pair, the embedding similarity \
+                   of each book to each other and the graph relationships between them. Similarities are null \
+                   for books without a stored embedding, and relationships are empty when the graph isn't configured."
+)]
+#[actix_web::get("/compare")]
+pub async fn compare_books(
+    params: web::Query<CompareParams>,
+    recommendation_service: web::Data<RecommendationService>,
+) -> Result<HttpResponse, ApiError> {
+    let ids = parse_compare_ids(&params.ids)?;
+    let comparison = recommendation_service.compare_books(&ids).await?;
+    Ok(HttpResponse::Ok().json(comparison))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryParams {
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BookHistoryResponse {
+    #[schema(example = "book_12345")]
+    pub book_id: String,
+    /// Whether versions are persisted to the database or only the recent ones kept in memory
+    #[schema(example = true)]
+    pub persistent: bool,
+    /// Versions of the book's metadata, newest first
+    pub versions: Vec<BookVersion>,
+}
+
+/// Get the versions of a book's metadata
+#[utoipa::path(
+    get,
+    path = "/api/books/{id}/history",
+    tag = "Books",
+    params(
+        ("id" = String, Path, description = "Book id", example = "book_12345"),
+        ("limit" = Option<i64>, Query, description = "Maximum number of versions (default: 50, max: 200)", example = 50)
+    ),
+    responses(
+        (status = 200, description = "The book's metadata versions, newest first", body = BookHistoryResponse),
+        (status = 400, description = "Invalid book id (invalid_input)", body = ErrorResponse,
+            example = json!({
+                "error": "Invalid input: Invalid book id 'book 1': expected 1-128 letters, digits, '-', '_' or '.'",
+                "code": "invalid_input",
+                "status": 400
+            })),
+        (status = 500, response = InternalServerError)
+    ),
+    summary = "Get book history",
+    description = "Lists the versions of the book's stored metadata with the fields each one changed and what \
+                   wrote it, such as an indexer run. A book's current version and when it was written are in \
+                   its `version` and `updated_at` fields. Empty for books that haven't changed since versions \
+                   were introduced."
+)]
+#[actix_web::get("/{id}/history")]
+pub async fn get_book_history(
+    path: web::Path<String>,
+    params: web::Query<HistoryParams>,
+    history: web::Data<BookHistory>,
+) -> Result<HttpResponse, ApiError> {
+    let book_id = path.into_inner();
+    validate_id("book id", &book_id)?;
+    let versions = history
+        .history(&book_id, params.limit.unwrap_or(DEFAULT_HISTORY_LIMIT))
+        .await?;
+
+    Ok(HttpResponse::Ok().json(BookHistoryResponse {
+        book_id,
+        persistent: history.is_persistent(),
+        versions,
+    }))
+}
+
+pub fn books_config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/books")
+            .service(get_sitemap)
+            .service(compare_books)
+            .service(get_book_json_ld)
+            .service(get_book_history),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_ld_describes_the_book_page() {
+        let book: Book = serde_json::from_value(json!({
+            "id": "book 1",
+            "title": "The Hobbit",
+            "author": "J.R.R. Tolkien",
+            "description": "",
+            "categories": ["Fantasy"],
+            "rating": 4.5,
+            "ratings_count": 1500,
+            "year": 1937
+        }))
+        .unwrap();
+
+        let url = book_page_url("https://example.com/app", "book 1").unwrap();
+        assert_eq!(url, "https://example.com/app/books/book%201");
+
+        let markup = book_json_ld(&book, Some(&url));
+        assert_eq!(markup["@type"], "Book");
+        assert_eq!(markup["url"], url);
+        assert_eq!(markup["author"]["name"], "J.R.R. Tolkien");
+        assert_eq!(markup["datePublished"], "1937");
+        assert_eq!(markup["aggregateRating"]["ratingCount"], 1500);
+        assert!(markup.get("description").is_none());
+        assert!(markup.get("isbn").is_none());
+
+        let sitemap = render_sitemap([(url, "2024-01-15")]);
+        assert!(sitemap.contains(
+            "<url><loc>https://example.com/app/books/book%201</loc><lastmod>2024-01-15</lastmod></url>"
+        ));
+    }
+
+    #[test]
+    fn test_compare_ids_are_deduplicated_and_counted() {
+        assert_eq!(parse_compare_ids("a, b,a,,c").unwrap(), vec!["a", "b", "c"]);
+        assert!(parse_compare_ids("a,a").is_err());
+        assert!(parse_compare_ids("a,b,c,d,e,f").is_err());
+        assert!(parse_compare_ids("a,b c").is_err());
+    }
+}
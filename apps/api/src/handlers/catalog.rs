@@ -0,0 +1,163 @@
+use crate::{
+    error::ApiError,
+    ingest::corpus_stats::{CorpusStats, NameCount, TitleEntry},
+    models::ErrorResponse,
+    services::RecommendationService,
+};
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Most title suggestions returned for one prefix
+const MAX_TITLE_SUGGESTIONS: usize = 50;
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CorpusSummary {
+    /// When the indexer wrote the snapshot, in RFC3339 format
+    #[schema(example = "2024-01-15T10:42:10Z")]
+    pub generated_at: String,
+    #[schema(example = 52000)]
+    pub books: usize,
+    #[schema(example = 18000)]
+    pub authors: usize,
+    #[schema(example = 900)]
+    pub categories: usize,
+    /// Authors with the most books
+    pub top_authors: Vec<NameCount>,
+    /// Categories with the most books
+    pub top_categories: Vec<NameCount>,
+    /// Rated books per half-star bucket, from [0, 0.5) up to [4.5, 5]
+    #[schema(example = json!([0, 2, 5, 40, 310, 1200, 5100, 14000, 21000, 3100]))]
+    pub rating_histogram: Vec<usize>,
+    /// Books without a rating
+    #[schema(example = 7232)]
+    pub unrated: usize,
+}
+
+impl From<&CorpusStats> for CorpusSummary {
+    fn from(stats: &CorpusStats) -> Self {
+        Self {
+            generated_at: stats.generated_at.clone(),
+            books: stats.books,
+            authors: stats.authors,
+            categories: stats.categories,
+            top_authors: stats.top_authors.clone(),
+            top_categories: stats.top_categories.clone(),
+            rating_histogram: stats.rating_histogram.clone(),
+            unrated: stats.unrated,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct TitleSuggestionParams {
+    /// Start of the title, ignoring case and punctuation
+    #[schema(example = "the left h")]
+    pub prefix: String,
+    /// Maximum number of titles (default: 10)
+    #[serde(default = "default_title_limit")]
+    #[schema(example = 10, minimum = 1, maximum = 50)]
+    pub limit: usize,
+}
+
+fn default_title_limit() -> usize {
+    10
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TitleSuggestionsResponse {
+    /// Matching titles, most rated first
+    pub titles: Vec<TitleEntry>,
+}
+
+fn loaded_stats(recommendation_service: &RecommendationService) -> Result<&CorpusStats, ApiError> {
+    recommendation_service
+        .corpus_stats()
+        .ok_or_else(|| ApiError::NotFound("No corpus statistics snapshot is loaded".to_string()))
+}
+
+/// Summarize the indexed catalog
+#[utoipa::path(
+    get,
+    path = "/api/catalog/stats",
+    tag = "Catalog",
+    responses(
+        (status = 200, description = "Catalog statistics from the indexer's snapshot", body = CorpusSummary),
+        (status = 404, description = "No snapshot is configured or it couldn't be loaded (not_found)", body = ErrorResponse,
+            example = json!({
+                "error": "Not found: No corpus statistics snapshot is loaded",
+                "code": "not_found",
+                "status": 404
+            }))
+    ),
+    summary = "Get catalog statistics",
+    description = "Returns the statistics the indexer computed for the catalog: book, author and category \
+                   counts, the authors and categories with the most books, and the rating distribution."
+)]
+#[actix_web::get("/stats")]
+pub async fn get_catalog_stats(
+    recommendation_service: web::Data<RecommendationService>,
+) -> Result<HttpResponse, ApiError> {
+    let stats = loaded_stats(&recommendation_service)?;
+    Ok(HttpResponse::Ok().json(CorpusSummary::from(stats)))
+}
+
+/// Suggest titles for autocomplete
+#[utoipa::path(
+    get,
+    path = "/api/catalog/titles",
+    tag = "Catalog",
+    params(
+        ("prefix" = String, Query, description = "Start of the title, ignoring case and punctuation", example = "the left h"),
+        ("limit" = Option<usize>, Query, description = "Maximum number of titles, 1-50 (default: 10)", example = 10)
+    ),
+    responses(
+        (status = 200, description = "Titles matching the prefix, most rated first", body = TitleSuggestionsResponse),
+        (status = 400, description = "Missing prefix or limit out of range (invalid_input)", body = ErrorResponse,
+            example = json!({
+                "error": "Invalid input: limit must be between 1 and 50",
+                "code": "invalid_input",
+                "status": 400
+            })),
+        (status = 404, description = "Neither a snapshot nor a full-text index is configured and loaded (not_found)", body = ErrorResponse,
+            example = json!({
+                "error": "Not found: No corpus statistics snapshot or full-text index is loaded",
+                "code": "not_found",
+                "status": 404
+            }))
+    ),
+    summary = "Suggest titles",
+    description = "Completes a title prefix from the indexer's full-text index of the whole catalog, matching \
+                   the prefix's words anywhere in a title, or else from the indexer's snapshot, which keeps the \
+                   most rated books of large catalogs and matches the start of a title."
+)]
+#[actix_web::get("/titles")]
+pub async fn suggest_titles(
+    params: web::Query<TitleSuggestionParams>,
+    recommendation_service: web::Data<RecommendationService>,
+) -> Result<HttpResponse, ApiError> {
+    if params.limit == 0 || params.limit > MAX_TITLE_SUGGESTIONS {
+        return Err(ApiError::InvalidInput(format!(
+            "limit must be between 1 and {}",
+            MAX_TITLE_SUGGESTIONS
+        )));
+    }
+
+    let titles = recommendation_service
+        .suggest_titles(&params.prefix, params.limit)
+        .ok_or_else(|| {
+            ApiError::NotFound(
+                "No corpus statistics snapshot or full-text index is loaded".to_string(),
+            )
+        })?;
+
+    Ok(HttpResponse::Ok().json(TitleSuggestionsResponse { titles }))
+}
+
+pub fn catalog_config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/catalog")
+            .service(get_catalog_stats)
+            .service(suggest_titles),
+    );
+}
@@ -0,0 +1,99 @@
+//! Book covers proxied over https
+//!
+//! The frontend loads covers from `/api/covers/{id}` instead of the catalog's
+//! thumbnail URLs, so it never hits a broken or http-only image host.
+
+use crate::{
+    error::ApiError,
+    models::{BadGateway, ErrorResponse, InternalServerError},
+    services::{
+        covers::{CoverProxy, COVER_CACHE_TTL, MAX_COVER_WIDTH, MIN_COVER_WIDTH},
+        supabase::validate_id,
+        RecommendationService,
+    },
+};
+use actix_web::{http::header, web, HttpResponse};
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct CoverParams {
+    pub width: Option<u32>,
+}
+
+/// Get a book's cover image
+#[utoipa::path(
+    get,
+    path = "/api/covers/{id}",
+    tag = "Books",
+    params(
+        ("id" = String, Path, description = "Book id", example = "book_12345"),
+        ("width" = Option<u32>, Query, description = "Scale the cover down to at most this many pixels wide (16 to 1024)", example = 200)
+    ),
+    responses(
+        (status = 200, description = "The cover image", content_type = "image/*", body = Vec<u8>),
+        (status = 400, description = "Width out of range or an invalid id (invalid_input)", body = ErrorResponse,
+            example = json!({
+                "error": "Invalid input: width must be between 16 and 1024",
+                "code": "invalid_input",
+                "status": 400
+            })),
+        (status = 404, description = "No book with this id is indexed, or it has no working cover (not_found)", body = ErrorResponse,
+            example = json!({
+                "error": "Not found: Book book_12345 has no cover",
+                "code": "not_found",
+                "status": 404
+            })),
+        (status = 500, response = InternalServerError),
+        (status = 502, response = BadGateway)
+    ),
+    summary = "Get a book cover",
+    description = "Serves the book's thumbnail over https, or Open Library's cover for its ISBN when the thumbnail \
+                   is broken. With `width`, wider covers are scaled down and returned as JPEG, to the widest of \
+                   16, 32, 64, 128, 256, 512 and 1024 pixels that fits. Covers, and books known to have none, are \
+                   cached for a day, here and by clients; a cover host that fails is tried again on the next \
+                   request."
+)]
+#[actix_web::get("/{id}")]
+pub async fn get_cover(
+    path: web::Path<String>,
+    params: web::Query<CoverParams>,
+    recommendation_service: web::Data<RecommendationService>,
+    covers: web::Data<CoverProxy>,
+) -> Result<HttpResponse, ApiError> {
+    let book_id = path.into_inner();
+    validate_id("book id", &book_id)?;
+    if let Some(width) = params.width {
+        if !(MIN_COVER_WIDTH..=MAX_COVER_WIDTH).contains(&width) {
+            return Err(ApiError::InvalidInput(format!(
+                "width must be between {} and {}",
+                MIN_COVER_WIDTH, MAX_COVER_WIDTH
+            )));
+        }
+    }
+
+    // Cached covers are served without looking the book up again
+    let cover = match covers.cached(&book_id, params.width) {
+        Some(cover) => cover,
+        None => {
+            let book = recommendation_service
+                .get_book(&book_id)
+                .await?
+                .ok_or_else(|| ApiError::NotFound(format!("Book {} not found", book_id)))?;
+            covers.cover(&book, params.width).await?
+        }
+    };
+    let cover =
+        cover.ok_or_else(|| ApiError::NotFound(format!("Book {} has no cover", book_id)))?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(cover.content_type)
+        .insert_header((
+            header::CACHE_CONTROL,
+            format!("public, max-age={}", COVER_CACHE_TTL.as_secs()),
+        ))
+        .body(cover.bytes))
+}
+
+pub fn covers_config(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::scope("/covers").service(get_cover));
+}
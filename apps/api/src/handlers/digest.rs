@@ -0,0 +1,143 @@
+use crate::{
+    error::ApiError,
+    middleware::{AdminAuth, UserAuth},
+    models::{BadGateway, ErrorResponse, InternalServerError, Unauthorized, UserUnauthorized},
+    services::{
+        digest::{Digest, DEFAULT_DIGEST_SIZE, MAX_DIGEST_SIZE},
+        supabase::validate_id,
+        RecommendationService,
+    },
+};
+use actix_web::{web, HttpResponse};
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+fn default_digest_size() -> usize {
+    DEFAULT_DIGEST_SIZE
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct DigestRequest {
+    /// Number of picks, 1-20
+    #[serde(default = "default_digest_size")]
+    #[schema(example = 5, default = 5, minimum = 1, maximum = 20)]
+    pub count: usize,
+    /// Generate the digest without recording it, so its picks can still appear in the next one
+    #[serde(default)]
+    #[schema(example = false, default = false)]
+    pub preview: bool,
+}
+
+/// Generate a user's weekly digest
+#[utoipa::path(
+    post,
+    path = "/api/users/{user_id}/digest",
+    tag = "Digest",
+    params(
+        ("user_id" = String, Path, description = "User id"),
+        ("X-Admin-Key" = String, Header, description = "Admin API key")
+    ),
+    request_body = DigestRequest,
+    responses(
+        (status = 200, description = "Personalized picks with explanations", body = Digest),
+        (status = 400, description = "Invalid user id or count (invalid_input)", body = ErrorResponse,
+            examples(
+                ("Invalid user id" = (value = json!({
+                    "error": "Invalid input: Invalid user id 'jane doe': expected 1-128 letters, digits, '-', '_' or '.'",
+                    "code": "invalid_input",
+                    "status": 400
+                }))),
+                ("Count out of range" = (value = json!({
+                    "error": "Invalid input: count must be between 1 and 20",
+                    "code": "invalid_input",
+                    "status": 400
+                })))
+            )),
+        (status = 401, response = Unauthorized),
+        (status = 500, response = InternalServerError),
+        (status = 502, response = BadGateway)
+    ),
+    summary = "Generate a digest",
+    description = "Picks books close to the ones on the user's shelves and the ones they rated \
+                   highly, each explained by the shelved book it is most similar to. Books the \
+                   user shelved, rated or received in an earlier digest are left out. Unless \
+                   preview is set, the digest is recorded and its books won't be picked again; \
+                   a digest already recorded this week (ISO, UTC) is returned as is, so retries \
+                   don't send new picks. The payload is meant to be rendered by an external \
+                   mailer, which calls this with the admin key."
+)]
+#[actix_web::post("")]
+pub async fn generate_digest(
+    path: web::Path<String>,
+    _admin: AdminAuth,
+    request: web::Json<DigestRequest>,
+    recommendation_service: web::Data<RecommendationService>,
+) -> Result<HttpResponse, ApiError> {
+    let user_id = path.into_inner();
+    validate_id("user id", &user_id)?;
+    if request.count == 0 || request.count > MAX_DIGEST_SIZE {
+        return Err(ApiError::InvalidInput(format!(
+            "count must be between 1 and {}",
+            MAX_DIGEST_SIZE
+        )));
+    }
+
+    let digest = recommendation_service
+        .weekly_digest(&user_id, request.count, !request.preview)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(digest))
+}
+
+/// Get the last digest recorded for a user
+#[utoipa::path(
+    get,
+    path = "/api/users/{user_id}/digest/latest",
+    tag = "Digest",
+    params(
+        ("user_id" = String, Path, description = "User id")
+    ),
+    responses(
+        (status = 200, description = "The last recorded digest", body = Digest),
+        (status = 400, description = "Invalid user id (invalid_input)", body = ErrorResponse,
+            example = json!({
+                "error": "Invalid input: Invalid user id 'jane doe': expected 1-128 letters, digits, '-', '_' or '.'",
+                "code": "invalid_input",
+                "status": 400
+            })),
+        (status = 404, description = "No digest was recorded for the user (not_found)", body = ErrorResponse,
+            example = json!({
+                "error": "Not found: User 42 has no digest",
+                "code": "not_found",
+                "status": 404
+            })),
+        (status = 401, response = UserUnauthorized),
+        (status = 500, response = InternalServerError)
+    ),
+    summary = "Get the latest digest"
+)]
+#[actix_web::get("/latest")]
+pub async fn latest_digest(
+    path: web::Path<String>,
+    auth: UserAuth,
+    recommendation_service: web::Data<RecommendationService>,
+) -> Result<HttpResponse, ApiError> {
+    let user_id = path.into_inner();
+    validate_id("user id", &user_id)?;
+    auth.authorize(&user_id)?;
+
+    let digest = recommendation_service
+        .latest_digest(&user_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("User {} has no digest", user_id)))?;
+
+    Ok(HttpResponse::Ok().json(digest))
+}
+
+pub fn digest_config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/users/{user_id}/digest")
+            .service(generate_digest)
+            .service(latest_digest),
+    );
+}
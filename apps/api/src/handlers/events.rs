@@ -0,0 +1,164 @@
+use crate::{
+    error::ApiError,
+    models::ErrorResponse,
+    services::{
+        event_buffer::EventBuffer,
+        supabase::{validate_id, AnalyticsEvent, EventType},
+    },
+};
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use utoipa::ToSchema;
+
+/// Most events accepted in one request
+const MAX_EVENTS_PER_BATCH: usize = 100;
+
+/// Longest query stored with an event
+const MAX_EVENT_QUERY_LENGTH: usize = 200;
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct EventPayload {
+    #[serde(rename = "type")]
+    pub event_type: EventType,
+    #[schema(example = "9780547928227")]
+    pub book_id: String,
+    /// Anonymous session id
+    #[serde(default)]
+    #[schema(example = "session-6b1f0c")]
+    pub session_id: Option<String>,
+    /// Query whose results the book was in
+    #[serde(default)]
+    #[schema(example = "cozy fantasy with dragons")]
+    pub query: Option<String>,
+    /// Zero-based position of the book in those results
+    #[serde(default)]
+    #[schema(example = 3)]
+    pub position: Option<u32>,
+    /// When the event happened, in RFC3339 format; defaults to when it was received
+    #[serde(default)]
+    #[schema(example = "2024-01-15T10:30:00Z")]
+    pub timestamp: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct EventBatch {
+    /// Up to 100 events
+    pub events: Vec<EventPayload>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct EventsAccepted {
+    /// Events buffered for storage
+    #[schema(example = 12)]
+    pub accepted: usize,
+    /// Events dropped because the buffer was full
+    #[schema(example = 0)]
+    pub dropped: usize,
+}
+
+impl EventPayload {
+    fn into_event(self, received_at: DateTime<Utc>) -> Result<AnalyticsEvent, ApiError> {
+        validate_id("book id", &self.book_id)?;
+        if let Some(session_id) = &self.session_id {
+            validate_id("session id", session_id)?;
+        }
+
+        let occurred_at = match &self.timestamp {
+            Some(timestamp) => DateTime::parse_from_rfc3339(timestamp)
+                .map_err(|e| {
+                    ApiError::InvalidInput(format!("Invalid timestamp '{}': {}", timestamp, e))
+                })?
+                .with_timezone(&Utc)
+                // Clock skew shouldn't put events in the future
+                .min(received_at),
+            None => received_at,
+        };
+
+        Ok(AnalyticsEvent {
+            event_type: self.event_type,
+            book_id: self.book_id,
+            session_id: self.session_id,
+            query: self
+                .query
+                .map(|query| query.trim().chars().take(MAX_EVENT_QUERY_LENGTH).collect())
+                .filter(|query: &String| !query.is_empty()),
+            position: self.position,
+            occurred_at: occurred_at.to_rfc3339(),
+        })
+    }
+}
+
+/// Record analytics events
+#[utoipa::path(
+    post,
+    path = "/api/events",
+    tag = "Events",
+    request_body = EventBatch,
+    responses(
+        (status = 202, description = "Events were buffered for storage", body = EventsAccepted),
+        (status = 400, description = "Invalid event or too many events (invalid_input)", body = ErrorResponse,
+            examples(
+                ("Too many events" = (value = json!({
+                    "error": "Invalid input: At most 100 events per request, got 250",
+                    "code": "invalid_input",
+                    "status": 400
+                }))),
+                ("Invalid timestamp" = (value = json!({
+                    "error": "Invalid input: Invalid timestamp 'yesterday': input contains invalid characters",
+                    "code": "invalid_input",
+                    "status": 400
+                }))),
+                ("Invalid book id" = (value = json!({
+                    "error": "Invalid input: Invalid book id 'the hobbit': expected 1-128 letters, digits, '-', '_' or '.'",
+                    "code": "invalid_input",
+                    "status": 400
+                })))
+            ))
+    ),
+    summary = "Record impressions, clicks and shelvings",
+    description = "Accepts a batch of up to 100 anonymous impression, click and add_to_shelf events. \
+                   Events are buffered in memory and written to the database in the background, \
+                   so a 202 doesn't guarantee they were stored. A batch with any invalid event is \
+                   rejected as a whole."
+)]
+#[actix_web::post("/events")]
+pub async fn record_events(
+    request: web::Json<EventBatch>,
+    buffer: web::Data<EventBuffer>,
+) -> Result<HttpResponse, ApiError> {
+    let batch = request.into_inner();
+    if batch.events.len() > MAX_EVENTS_PER_BATCH {
+        return Err(ApiError::InvalidInput(format!(
+            "At most {} events per request, got {}",
+            MAX_EVENTS_PER_BATCH,
+            batch.events.len()
+        )));
+    }
+
+    let received_at = Utc::now();
+    let events = batch
+        .events
+        .into_iter()
+        .map(|payload| payload.into_event(received_at))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let total = events.len();
+    let accepted = buffer.push(events);
+    if accepted < total {
+        warn!(
+            "Event buffer is full, dropped {} analytics events",
+            total - accepted
+        );
+    }
+
+    Ok(HttpResponse::Accepted().json(EventsAccepted {
+        accepted,
+        dropped: total - accepted,
+    }))
+}
+
+pub fn events_config(cfg: &mut web::ServiceConfig) {
+    cfg.service(record_events);
+}
@@ -0,0 +1,105 @@
+use super::recommendations::accept_language;
+use crate::{
+    error::ApiError,
+    middleware::user_auth::UserAuth,
+    models::{
+        BadGateway, ErrorResponse, ExplanationsRequest, ExplanationsResponse, InternalServerError,
+    },
+    services::{
+        explanations::MAX_EXPLAINED_BOOKS, i18n::Locale, supabase::validate_id,
+        RecommendationService,
+    },
+};
+use actix_web::{
+    web::{self, Json},
+    HttpRequest, HttpResponse,
+};
+
+/// The requested book ids, trimmed and without repeats, or an error if there are none,
+/// too many or an invalid one
+fn parse_book_ids(book_ids: &[String]) -> Result<Vec<String>, ApiError> {
+    let mut ids: Vec<String> = Vec::with_capacity(book_ids.len());
+    for id in book_ids.iter().map(|id| id.trim()) {
+        if !id.is_empty() && !ids.iter().any(|seen| seen == id) {
+            validate_id("book id", id)?;
+            ids.push(id.to_string());
+        }
+    }
+    if ids.is_empty() {
+        return Err(ApiError::InvalidInput(
+            "book_ids must list at least one book".to_string(),
+        ));
+    }
+    if ids.len() > MAX_EXPLAINED_BOOKS {
+        return Err(ApiError::InvalidInput(format!(
+            "At most {} books can be explained at once",
+            MAX_EXPLAINED_BOOKS
+        )));
+    }
+    Ok(ids)
+}
+
+/// Explain why books fit a query
+#[utoipa::path(
+    post,
+    path = "/api/explanations",
+    tag = "Recommendations",
+    request_body = ExplanationsRequest,
+    responses(
+        (status = 200, description = "One explanation per requested book, in the order asked for", body = ExplanationsResponse),
+        (status = 400, description = "Empty, too short or too long query, no book ids or more than 10, an invalid book id, or unsupported lang (invalid_input)", body = ErrorResponse,
+            example = json!({
+                "error": "Invalid input: At most 10 books can be explained at once",
+                "code": "invalid_input",
+                "status": 400
+            })),
+        (status = 404, description = "A requested book isn't indexed (not_found)", body = ErrorResponse,
+            example = json!({
+                "error": "Not found: Books not indexed: book_999",
+                "code": "not_found",
+                "status": 404
+            })),
+        (status = 500, response = InternalServerError),
+        (status = 502, response = BadGateway),
+    ),
+    summary = "Explain recommended books",
+    description = "Returns a one-sentence explanation of why each book fits the query, the same one a recommendation \
+                   request with explain would give it, so explanations can be fetched only for the books a reader \
+                   scrolls to. Explanations are written by a language model when one is configured and the request \
+                   carries a bearer token or the admin key, for at most 5 books per request, and from the book's \
+                   matches with the query otherwise. They are in English, German or Spanish, picked by lang or else \
+                   the Accept-Language header."
+)]
+pub async fn get_explanations(
+    auth: Option<UserAuth>,
+    http_request: HttpRequest,
+    request: Json<ExplanationsRequest>,
+    recommendation_service: web::Data<RecommendationService>,
+) -> Result<HttpResponse, ApiError> {
+    let book_ids = parse_book_ids(&request.book_ids)?;
+    let locale = Locale::negotiate(request.lang.as_deref(), accept_language(&http_request))?;
+
+    let explanations = recommendation_service
+        .explain_books(&request.query, &book_ids, locale, auth.is_some())
+        .await?;
+    Ok(HttpResponse::Ok().json(ExplanationsResponse { explanations }))
+}
+
+pub fn explanations_config(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/explanations").route(web::post().to(get_explanations)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_book_ids_are_trimmed_deduplicated_and_bounded() {
+        let ids = parse_book_ids(&[" a ".to_string(), "b".to_string(), "a".to_string()]).unwrap();
+        assert_eq!(ids, vec!["a", "b"]);
+        assert!(parse_book_ids(&[" ".to_string()]).is_err());
+        assert!(parse_book_ids(&["a/../b".to_string()]).is_err());
+        let too_many: Vec<String> = (0..=MAX_EXPLAINED_BOOKS).map(|i| i.to_string()).collect();
+        assert!(parse_book_ids(&too_many).is_err());
+    }
+}
@@ -0,0 +1,219 @@
+//! Atom feeds of trending and newly indexed books
+//!
+//! Feed readers poll these rather than the JSON API, so both feeds are cached
+//! by clients for a while and fall back to an empty feed rather than an error
+//! when their source isn't configured. Each entry links to the book's page on
+//! the frontend, or to its JSON-LD when no site URL is configured, and carries
+//! the book's full description as its content.
+
+use super::atom::{base_url, xml_response, AtomEntry, AtomFeed, AtomLink, ATOM_MEDIA_TYPE};
+use crate::{
+    config::Config,
+    error::ApiError,
+    handlers::{books::book_page_url, recommendations::request_tenant},
+    ingest::corpus_stats::IndexedBook,
+    models::{truncate_description, BadGateway, InternalServerError, DESCRIPTION_PREVIEW_CHARS},
+    services::RecommendationService,
+};
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::Utc;
+
+/// Entries in each feed
+const FEED_ENTRIES: usize = 50;
+
+/// Days of clicks and shelvings the trending feed is computed from
+const TRENDING_WINDOW_DAYS: i64 = 7;
+
+/// How long feed readers may cache a feed
+pub(super) const FEED_MAX_AGE_SECS: u32 = 15 * 60;
+
+/// Books clicked and shelved most over the last week
+#[utoipa::path(
+    get,
+    path = "/feeds/trending.xml",
+    tag = "Feeds",
+    responses(
+        (status = 200, description = "Atom feed of trending books, most popular first", content_type = "application/atom+xml", body = String),
+        (status = 500, response = InternalServerError),
+        (status = 502, response = BadGateway)
+    ),
+    summary = "Trending books feed",
+    description = "Atom feed of the 50 books readers clicked and shelved most over the last 7 days, a shelving \
+                   counting as much as three clicks. Empty when no analytics events have been recorded. The list is \
+                   cached, and recomputed by scheduled maintenance."
+)]
+#[actix_web::get("/trending.xml")]
+pub async fn trending_feed(
+    request: HttpRequest,
+    recommendation_service: web::Data<RecommendationService>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse, ApiError> {
+    let now = Utc::now();
+    let tenant = request_tenant(&request);
+    let mut trending = recommendation_service
+        .cached_trending(TRENDING_WINDOW_DAYS, FEED_ENTRIES)
+        .await?;
+    trending.retain(|trending| recommendation_service.allows(&trending.book, tenant.as_deref()));
+
+    let updated = now.to_rfc3339();
+    let entries = trending
+        .into_iter()
+        .filter_map(|trending| {
+            let book = trending.book;
+            let activity = format!(
+                "{} clicks and {} shelvings in the last {} days.",
+                trending.clicks, trending.shelvings, TRENDING_WINDOW_DAYS
+            );
+            let id = book.id?;
+            Some(AtomEntry {
+                id: book_entry_id(&id),
+                title: book.title.unwrap_or_else(|| "Untitled".to_string()),
+                updated: updated.clone(),
+                authors: book.author.into_iter().collect(),
+                categories: book.categories,
+                summary: Some(with_description(activity, book.description.clone())),
+                content: book.description,
+                links: book_link(&request, &config, &id).into_iter().collect(),
+                ..Default::default()
+            })
+        })
+        .collect();
+
+    Ok(feed_response(
+        &request,
+        "trending.xml",
+        "Trending books",
+        "Books readers are clicking and shelving this week",
+        updated,
+        entries,
+    ))
+}
+
+/// Books added by recent indexing runs
+#[utoipa::path(
+    get,
+    path = "/feeds/new.xml",
+    tag = "Feeds",
+    responses(
+        (status = 200, description = "Atom feed of newly indexed books, newest first", content_type = "application/atom+xml", body = String),
+        (status = 500, response = InternalServerError),
+        (status = 502, response = BadGateway)
+    ),
+    summary = "Newly indexed books feed",
+    description = "Atom feed of the 50 books most recently added to the index, from the corpus statistics \
+                   snapshot the indexer writes. Empty when no snapshot is loaded."
+)]
+#[actix_web::get("/new.xml")]
+pub async fn new_books_feed(
+    request: HttpRequest,
+    recommendation_service: web::Data<RecommendationService>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse, ApiError> {
+    let mut recently_indexed: Vec<&IndexedBook> = recommendation_service
+        .corpus_stats()
+        .map(|stats| stats.recently_indexed.as_slice())
+        .unwrap_or_default()
+        .iter()
+        .filter(|book| !recommendation_service.is_hidden(Some(&book.id)))
+        .take(FEED_ENTRIES)
+        .collect();
+    recommendation_service
+        .retain_allowed_ids(
+            &mut recently_indexed,
+            request_tenant(&request).as_deref(),
+            |book| &book.id,
+        )
+        .await?;
+
+    let entries: Vec<AtomEntry> = recently_indexed
+        .into_iter()
+        .map(|book| AtomEntry {
+            id: book_entry_id(&book.id),
+            title: book.title.clone().unwrap_or_else(|| "Untitled".to_string()),
+            updated: book.indexed_at.clone(),
+            authors: book.author.iter().cloned().collect(),
+            categories: book.categories.clone(),
+            summary: Some(with_description(
+                "New in the catalog.".to_string(),
+                book.description.clone(),
+            )),
+            content: book.description.clone(),
+            links: book_link(&request, &config, &book.id).into_iter().collect(),
+            ..Default::default()
+        })
+        .collect();
+    let updated = entries
+        .first()
+        .map(|entry| entry.updated.clone())
+        .unwrap_or_else(|| Utc::now().to_rfc3339());
+
+    Ok(feed_response(
+        &request,
+        "new.xml",
+        "New books",
+        "Books recently added to the catalog",
+        updated,
+        entries,
+    ))
+}
+
+/// Summary of a feed entry: what put the book in the feed, then a description preview
+fn with_description(lead: String, description: Option<String>) -> String {
+    match description {
+        Some(mut description) if !description.trim().is_empty() => {
+            truncate_description(&mut description, DESCRIPTION_PREVIEW_CHARS);
+            format!("{} {}", lead, description)
+        }
+        _ => lead,
+    }
+}
+
+/// Where a reader opens a book: its page on the frontend, or else its JSON-LD here
+fn book_link(request: &HttpRequest, config: &Config, book_id: &str) -> Option<AtomLink> {
+    if let Some(url) = config
+        .site_url
+        .as_deref()
+        .and_then(|site_url| book_page_url(site_url, book_id))
+    {
+        return Some(AtomLink::new("alternate", url, "text/html"));
+    }
+    let mut url = reqwest::Url::parse(&base_url(request)).ok()?;
+    url.path_segments_mut()
+        .ok()?
+        .pop_if_empty()
+        .extend(["api", "books", book_id, "jsonld"]);
+    Some(AtomLink::new(
+        "alternate",
+        url.to_string(),
+        "application/ld+json",
+    ))
+}
+
+fn feed_response(
+    request: &HttpRequest,
+    file: &str,
+    title: &str,
+    subtitle: &str,
+    updated: String,
+    entries: Vec<AtomEntry>,
+) -> HttpResponse {
+    let self_url = format!("{}/feeds/{}", base_url(request), file);
+    let feed = AtomFeed {
+        id: self_url.clone(),
+        title: title.to_string(),
+        subtitle: Some(subtitle.to_string()),
+        updated,
+        links: vec![AtomLink::new("self", self_url, "application/atom+xml")],
+        entries,
+    };
+    xml_response(ATOM_MEDIA_TYPE, FEED_MAX_AGE_SECS, feed.render())
+}
+
+/// Entry id of a book, the same in every feed
+pub(super) fn book_entry_id(book_id: &str) -> String {
+    format!("urn:recommend-a-book:book:{}", book_id)
+}
+
+pub fn feeds_config(cfg: &mut web::ServiceConfig) {
+    cfg.service(trending_feed).service(new_books_feed);
+}
@@ -1,9 +1,15 @@
 use crate::{
     error::ApiError,
-    services::neo4j::{GraphResponse, GraphStats, Neo4jClient},
+    handlers::recommendations::request_tenant,
+    models::{BadGateway, ErrorResponse, InternalServerError},
+    services::{
+        neo4j::{GraphResponse, GraphStats, Neo4jClient},
+        RecommendationService,
+    },
 };
-use actix_web::{web, HttpResponse};
+use actix_web::{web, HttpRequest, HttpResponse};
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use utoipa::ToSchema;
 
 #[derive(Debug, Deserialize, ToSchema)]
@@ -75,8 +81,14 @@ pub struct SimilarBooksResponse {
                 ]
             })
         ),
-        (status = 404, description = "Book not found"),
-        (status = 500, description = "Internal server error")
+        (status = 404, description = "Book not found (not_found)", body = ErrorResponse,
+            example = json!({
+                "error": "Not found: Book with ID book-123 not found",
+                "code": "not_found",
+                "status": 404
+            })),
+        (status = 500, response = InternalServerError),
+        (status = 502, response = BadGateway)
     ),
     summary = "Get book relationship graph",
     description = "Returns a graph of related books including nodes and relationships up to the specified depth. \
@@ -85,11 +97,25 @@ pub struct SimilarBooksResponse {
 )]
 #[actix_web::get("/book")]
 pub async fn get_book_graph(
+    http_request: HttpRequest,
     params: web::Query<GraphQueryParams>,
     neo4j: web::Data<Neo4jClient>,
+    recommendation_service: web::Data<RecommendationService>,
 ) -> Result<HttpResponse, ApiError> {
     let depth = params.depth.min(5); // Cap at 5 for performance
-    let graph = neo4j.get_book_graph(&params.book_id, depth).await?;
+    let mut graph = neo4j.get_book_graph(&params.book_id, depth).await?;
+    recommendation_service
+        .retain_allowed_ids(
+            &mut graph.nodes,
+            request_tenant(&http_request).as_deref(),
+            |node| &node.id,
+        )
+        .await?;
+    let node_ids: HashSet<&str> = graph.nodes.iter().map(|node| node.id.as_str()).collect();
+    graph.relationships.retain(|relationship| {
+        node_ids.contains(relationship.from_id.as_str())
+            && node_ids.contains(relationship.to_id.as_str())
+    });
 
     if graph.nodes.is_empty() {
         return Err(ApiError::NotFound(format!(
@@ -126,7 +152,8 @@ pub async fn get_book_graph(
                 ]
             })
         ),
-        (status = 500, description = "Internal server error")
+        (status = 500, response = InternalServerError),
+        (status = 502, response = BadGateway)
     ),
     summary = "Get similar books",
     description = "Returns books that are semantically similar to the specified book based on embeddings, \
@@ -134,11 +161,20 @@ pub async fn get_book_graph(
 )]
 #[actix_web::get("/similar")]
 pub async fn get_similar_books(
+    http_request: HttpRequest,
     params: web::Query<SearchQueryParams>,
     neo4j: web::Data<Neo4jClient>,
+    recommendation_service: web::Data<RecommendationService>,
 ) -> Result<HttpResponse, ApiError> {
     let limit = params.limit.min(100); // Cap at 100 for performance
-    let books = neo4j.get_similar_books(&params.query, limit).await?;
+    let mut books = neo4j.get_similar_books(&params.query, limit).await?;
+    recommendation_service
+        .retain_allowed_ids(
+            &mut books,
+            request_tenant(&http_request).as_deref(),
+            |book| &book.id,
+        )
+        .await?;
     Ok(HttpResponse::Ok().json(SimilarBooksResponse { books }))
 }
 
@@ -167,7 +203,8 @@ pub async fn get_similar_books(
                 ]
             })
         ),
-        (status = 500, description = "Internal server error")
+        (status = 500, response = InternalServerError),
+        (status = 502, response = BadGateway)
     ),
     summary = "Search books by title",
     description = "Search for books by title pattern using case-insensitive matching. \
@@ -175,11 +212,20 @@ pub async fn get_similar_books(
 )]
 #[actix_web::get("/search")]
 pub async fn search_books(
+    http_request: HttpRequest,
     params: web::Query<SearchQueryParams>,
     neo4j: web::Data<Neo4jClient>,
+    recommendation_service: web::Data<RecommendationService>,
 ) -> Result<HttpResponse, ApiError> {
     let limit = params.limit.min(100); // Cap at 100 for performance
-    let books = neo4j.search_books(&params.query, limit).await?;
+    let mut books = neo4j.search_books(&params.query, limit).await?;
+    recommendation_service
+        .retain_allowed_ids(
+            &mut books,
+            request_tenant(&http_request).as_deref(),
+            |book| &book.id,
+        )
+        .await?;
     Ok(HttpResponse::Ok().json(SimilarBooksResponse { books }))
 }
 
@@ -195,7 +241,8 @@ pub async fn search_books(
                 "total_relationships": 5000
             })
         ),
-        (status = 500, description = "Internal server error")
+        (status = 500, response = InternalServerError),
+        (status = 502, response = BadGateway)
     ),
     summary = "Get graph statistics",
     description = "Returns statistics about the book graph including total number of nodes (books) \
@@ -1,7 +1,30 @@
-use crate::models::HealthResponse;
-use crate::services::RecommendationService;
+use crate::models::{EndpointProbe, HealthResponse, UpstreamStatus};
+use crate::services::{
+    bootstrap,
+    jobs::JobQueue,
+    maintenance::{MaintenanceStatus, MaintenanceTaskStatus},
+    RecommendationService,
+};
 use actix_web::{get, options, web, HttpResponse};
-use log::debug;
+use log::warn;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// Whether the instance is ready for traffic, and how its maintenance is going
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ReadinessResponse {
+    /// "ready" once the startup prewarm has finished, "starting" before
+    #[schema(example = "ready")]
+    pub status: String,
+    #[schema(example = "2024-01-15T10:30:00Z")]
+    pub timestamp: String,
+    /// Health of the upstreams the search depends on, as its router sees it
+    pub upstreams: Vec<UpstreamStatus>,
+    /// Last probe of each upstream endpoint, with what to fix when it failed
+    pub endpoints: Vec<EndpointProbe>,
+    /// Schedule and last run of each maintenance task
+    pub maintenance: Vec<MaintenanceTaskStatus>,
+}
 
 /// Health check endpoint
 #[utoipa::path(
@@ -12,32 +35,65 @@ use log::debug;
         (status = 200, description = "Service is healthy and background prewarming has been triggered", body = HealthResponse),
     ),
     summary = "Check service health and trigger background prewarming",
-    description = "Returns the current status and timestamp of the service. This endpoint also initiates a background prewarming process to reduce cold start latency for subsequent requests."
+    description = "Returns the current status and timestamp of the service, and the health of the embedding service and Pinecone as the search router sees it: an upstream whose circuit breaker is open is skipped until its cooldown ends. This endpoint also queues a background prewarm job, listed with the admin jobs, to reduce cold start latency for subsequent requests, unless the service is already warm or a prewarm is under way."
 )]
 #[get("/health")]
 pub async fn health_check(
     recommendation_service: web::Data<RecommendationService>,
+    jobs: web::Data<JobQueue>,
 ) -> HttpResponse {
-    // Trigger background prewarming without waiting for it to complete
-    // This helps mitigate cold starts by initializing services when the health check is called
-    tokio::spawn(async move {
-        if let Err(e) = recommendation_service.prewarm().await {
-            debug!(
-                "Background prewarming during health check encountered an issue: {}",
-                e
-            );
-        } else {
-            debug!("Background prewarming during health check completed successfully");
-        }
-    });
+    // Queue a prewarm job without waiting for it to complete, unless the
+    // service is warm or one is already running; this mitigates cold starts
+    let upstreams = recommendation_service.upstream_statuses();
+    if let Err(e) = bootstrap::queue_prewarm(recommendation_service.into_inner(), &jobs).await {
+        warn!("Failed to queue prewarming during health check: {}", e);
+    }
 
     HttpResponse::Ok().json(serde_json::json!({
         "status": "ok",
         "timestamp": chrono::Utc::now().to_rfc3339(),
-        "prewarm": "background"
+        "prewarm": "background",
+        "upstreams": upstreams
     }))
 }
 
+/// Readiness check endpoint
+#[utoipa::path(
+    get,
+    path = "/api/health/ready",
+    tag = "Health",
+    responses(
+        (status = 200, description = "The instance is prewarmed and ready for traffic", body = ReadinessResponse),
+        (status = 503, description = "The startup prewarm hasn't finished yet", body = ReadinessResponse),
+    ),
+    summary = "Check whether the instance is ready for traffic",
+    description = "Answers 503 until the startup prewarm has finished, so a load balancer can hold traffic back \
+                   from a cold instance, and 200 after. Also reports the upstreams' health, the result of the \
+                   Pinecone endpoint probe run during prewarm (its resolved addresses, or what failed and \
+                   which setting to check) and, for each \
+                   scheduled maintenance task, whether it's enabled, when it last ran, how that went and when \
+                   it runs next. Unlike `/api/health`, this doesn't trigger prewarming."
+)]
+#[get("/health/ready")]
+pub async fn readiness_check(
+    recommendation_service: web::Data<RecommendationService>,
+    maintenance: web::Data<MaintenanceStatus>,
+) -> HttpResponse {
+    let ready = recommendation_service.is_prewarmed();
+    let response = ReadinessResponse {
+        status: if ready { "ready" } else { "starting" }.to_string(),
+        timestamp: chrono::Utc::now().to_rfc3339(),
+        upstreams: recommendation_service.upstream_statuses(),
+        endpoints: recommendation_service.endpoint_probes(),
+        maintenance: maintenance.tasks(),
+    };
+    if ready {
+        HttpResponse::Ok().json(response)
+    } else {
+        HttpResponse::ServiceUnavailable().json(response)
+    }
+}
+
 /// OPTIONS handler for the health endpoint to handle CORS preflight requests
 #[options("/health")]
 pub async fn health_options() -> HttpResponse {
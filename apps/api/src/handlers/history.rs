@@ -0,0 +1,136 @@
+use crate::{
+    error::ApiError,
+    middleware::UserAuth,
+    models::{ErrorResponse, InternalServerError, UserUnauthorized},
+    services::supabase::{
+        validate_id, HistoryOwner, QueryHistoryEntry, SupabaseClient, MAX_HISTORY_ENTRIES,
+    },
+};
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct HistoryQueryParams {
+    /// Maximum number of queries to return (default: 20)
+    #[serde(default = "default_history_limit")]
+    #[schema(example = 20, minimum = 1, maximum = 100)]
+    pub limit: usize,
+}
+
+fn default_history_limit() -> usize {
+    20
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct QueryHistoryResponse {
+    /// User or session id
+    #[schema(example = "3f1c2a9e-8d4b-4c47-9a51-0f6a7b2d1e55")]
+    pub user_id: String,
+    /// Recent queries, newest first
+    pub queries: Vec<QueryHistoryEntry>,
+}
+
+/// List a user's recent searches
+#[utoipa::path(
+    get,
+    path = "/api/users/{user_id}/history",
+    tag = "History",
+    params(
+        ("user_id" = String, Path, description = "User id"),
+        ("limit" = Option<usize>, Query, description = "Maximum number of queries (default: 20, at most 100)", example = 20)
+    ),
+    responses(
+        (status = 200, description = "Recent queries, newest first", body = QueryHistoryResponse),
+        (status = 400, description = "Invalid user id or limit (invalid_input)", body = ErrorResponse,
+            examples(
+                ("Invalid user id" = (value = json!({
+                    "error": "Invalid input: Invalid user id 'jane doe': expected 1-128 letters, digits, '-', '_' or '.'",
+                    "code": "invalid_input",
+                    "status": 400
+                }))),
+                ("Limit out of range" = (value = json!({
+                    "error": "Invalid input: limit must be between 1 and 100",
+                    "code": "invalid_input",
+                    "status": 400
+                })))
+            )),
+        (status = 401, response = UserUnauthorized),
+        (status = 500, response = InternalServerError)
+    ),
+    summary = "Get a user's recent searches",
+    description = "Returns the recommendation queries the user made while signed in, \
+                   with the semantic tags extracted from each. Only the latest 100 are kept."
+)]
+#[actix_web::get("/users/{user_id}/history")]
+pub async fn get_history(
+    path: web::Path<String>,
+    auth: UserAuth,
+    params: web::Query<HistoryQueryParams>,
+    supabase: web::Data<SupabaseClient>,
+) -> Result<HttpResponse, ApiError> {
+    let user_id = path.into_inner();
+    validate_id("user id", &user_id)?;
+    auth.authorize(&user_id)?;
+
+    history_response(HistoryOwner::User(user_id), params.limit, &supabase).await
+}
+
+/// List an anonymous session's recent searches
+#[utoipa::path(
+    get,
+    path = "/api/sessions/{session_id}/history",
+    tag = "History",
+    params(
+        ("session_id" = String, Path, description = "Session id used for anonymous queries"),
+        ("limit" = Option<usize>, Query, description = "Maximum number of queries (default: 20, at most 100)", example = 20)
+    ),
+    responses(
+        (status = 200, description = "Recent queries, newest first", body = QueryHistoryResponse),
+        (status = 400, description = "Invalid session id or limit (invalid_input)", body = ErrorResponse,
+            example = json!({
+                "error": "Invalid input: limit must be between 1 and 100",
+                "code": "invalid_input",
+                "status": 400
+            })),
+        (status = 500, response = InternalServerError)
+    ),
+    summary = "Get a session's recent searches",
+    description = "Returns the recommendation queries made with this session_id and no signed-in \
+                   user, with the semantic tags extracted from each. Only the latest 100 are kept."
+)]
+#[actix_web::get("/sessions/{session_id}/history")]
+pub async fn get_session_history(
+    path: web::Path<String>,
+    params: web::Query<HistoryQueryParams>,
+    supabase: web::Data<SupabaseClient>,
+) -> Result<HttpResponse, ApiError> {
+    let session_id = path.into_inner();
+    validate_id("session id", &session_id)?;
+
+    history_response(HistoryOwner::Session(session_id), params.limit, &supabase).await
+}
+
+async fn history_response(
+    owner: HistoryOwner,
+    limit: usize,
+    supabase: &SupabaseClient,
+) -> Result<HttpResponse, ApiError> {
+    if limit == 0 || limit > MAX_HISTORY_ENTRIES {
+        return Err(ApiError::InvalidInput(format!(
+            "limit must be between 1 and {}",
+            MAX_HISTORY_ENTRIES
+        )));
+    }
+
+    let queries = supabase.query_history(&owner, limit).await?;
+
+    Ok(HttpResponse::Ok().json(QueryHistoryResponse {
+        user_id: owner.id().to_string(),
+        queries,
+    }))
+}
+
+pub fn history_config(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_history).service(get_session_history);
+}
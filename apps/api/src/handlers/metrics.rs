@@ -0,0 +1,90 @@
+use crate::{
+    error::ApiError,
+    models::ErrorResponse,
+    telemetry::{self, QualityDay, TelemetrySnapshot, QUALITY_ROLLUP_DAYS},
+};
+use actix_web::{get, web, HttpResponse};
+use serde::Deserialize;
+
+/// Telemetry counters endpoint
+#[utoipa::path(
+    get,
+    path = "/api/metrics",
+    tag = "System",
+    responses(
+        (status = 200, description = "Current telemetry counters", body = TelemetrySnapshot),
+    ),
+    summary = "Get in-process telemetry counters",
+    description = "Returns counters collected since the process started, including the number of error responses returned per error code and recommendation quality per query pattern: requests, requests any semantic tag was read from, and explanations, generic and not. Counters reset on restart."
+)]
+#[get("/metrics")]
+pub async fn metrics() -> HttpResponse {
+    HttpResponse::Ok().json(telemetry::snapshot())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QualityParams {
+    #[serde(default = "default_quality_days")]
+    pub days: usize,
+}
+
+fn default_quality_days() -> usize {
+    7
+}
+
+/// Daily recommendation quality rollup endpoint
+#[utoipa::path(
+    get,
+    path = "/api/metrics/quality",
+    tag = "System",
+    params(
+        ("days" = Option<usize>, Query, description = "Days to return, 1-30 (default: 7)", example = 7)
+    ),
+    responses(
+        (status = 200, description = "Quality counters per UTC day, most recent first", body = Vec<QualityDay>),
+        (status = 400, description = "days out of range (invalid_input)", body = ErrorResponse,
+            example = json!({
+                "error": "Invalid input: days must be between 1 and 30",
+                "code": "invalid_input",
+                "status": 400
+            })),
+    ),
+    summary = "Get the daily recommendation quality rollup",
+    description = "Returns recommendation quality per UTC day with requests, most recent first, so regressions in query understanding show over time: per query pattern, the requests, the requests any semantic tag was read from, the results explained and the explanations that are generic, naming nothing the book and query share. Each day also has the totals with its tag coverage and generic explanation rate. The last 30 calendar days are kept in memory and reset on restart."
+)]
+#[get("/metrics/quality")]
+pub async fn quality_metrics(params: web::Query<QualityParams>) -> Result<HttpResponse, ApiError> {
+    if params.days == 0 || params.days > QUALITY_ROLLUP_DAYS {
+        return Err(ApiError::InvalidInput(format!(
+            "days must be between 1 and {}",
+            QUALITY_ROLLUP_DAYS
+        )));
+    }
+    Ok(HttpResponse::Ok().json(telemetry::quality_rollup(params.days)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::StatusCode, test, App};
+
+    #[actix_web::test]
+    async fn test_quality_rollup_validates_its_days() {
+        let app = test::init_service(App::new().service(quality_metrics)).await;
+        let get = |uri: &str| test::TestRequest::get().uri(uri).to_request();
+
+        for uri in ["/metrics/quality?days=0", "/metrics/quality?days=31"] {
+            let response = test::call_service(&app, get(uri)).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", uri);
+        }
+
+        telemetry::record_quality("TestEndpointPattern", true, 0, 0);
+        let days: serde_json::Value =
+            test::call_and_read_body_json(&app, get("/metrics/quality")).await;
+        let today = &days.as_array().unwrap()[0];
+        assert_eq!(
+            today["patterns"]["TestEndpointPattern"]["requests"],
+            serde_json::json!(1)
+        );
+    }
+}
@@ -1,9 +1,47 @@
+pub mod admin;
+mod atom;
+pub mod authors;
+mod book_list;
+pub mod books;
+pub mod catalog;
+pub mod covers;
+pub mod digest;
+pub mod events;
+pub mod explanations;
+pub mod feeds;
 pub mod graph;
 pub mod health;
+pub mod history;
+pub mod metrics;
+pub mod opds;
+pub mod preferences;
 pub mod prewarm;
+pub mod query;
+pub mod ratings;
 pub mod recommendations;
+pub mod sessions;
+pub mod shelves;
+pub mod tools;
 
+pub use admin::admin_config;
+pub use authors::authors_config;
+pub use books::books_config;
+pub use catalog::catalog_config;
+pub use covers::covers_config;
+pub use digest::digest_config;
+pub use events::events_config;
+pub use explanations::explanations_config;
+pub use feeds::feeds_config;
 pub use graph::graph_config;
-pub use health::{health_check, health_options};
+pub use health::{health_check, health_options, readiness_check};
+pub use history::history_config;
+pub use metrics::{metrics as metrics_endpoint, quality_metrics};
+pub use opds::opds_config;
+pub use preferences::preferences_config;
 pub use prewarm::{prewarm as prewarm_endpoint, prewarm_options};
+pub use query::query_config;
+pub use ratings::ratings_config;
 pub use recommendations::recommendations_config;
+pub use sessions::sessions_config;
+pub use shelves::shelves_config;
+pub use tools::tools_config;
@@ -0,0 +1,593 @@
+//! OPDS 1.2 catalog for e-reader apps
+//!
+//! The catalog root links to trending and newly indexed books, to browsing by
+//! the catalog's top authors and categories, and to search, which runs the
+//! query through the recommendation pipeline. Books aren't hosted here, so
+//! each book's acquisition link borrows it from Open Library.
+
+use super::atom::{base_url, xml_response, AtomEntry, AtomFeed, AtomLink};
+use super::feeds::{book_entry_id, FEED_MAX_AGE_SECS};
+use crate::{
+    error::ApiError,
+    handlers::recommendations::request_tenant,
+    ingest::corpus_stats::{IndexedBook, NameCount},
+    models::{
+        truncate_description, BadGateway, Book, ErrorResponse, InternalServerError,
+        ServiceUnavailable, DESCRIPTION_PREVIEW_CHARS,
+    },
+    services::RecommendationService,
+};
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use reqwest::Url;
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+const NAVIGATION_TYPE: &str = "application/atom+xml;profile=opds-catalog;kind=navigation";
+const ACQUISITION_TYPE: &str = "application/atom+xml;profile=opds-catalog;kind=acquisition";
+const OPENSEARCH_TYPE: &str = "application/opensearchdescription+xml";
+
+/// Books in each acquisition feed
+const OPDS_ENTRIES: usize = 50;
+
+/// Authors and categories listed for browsing
+const OPDS_BROWSE_ENTRIES: usize = 100;
+
+/// Days of clicks and shelvings the popular feed is computed from
+const POPULAR_WINDOW_DAYS: i64 = 7;
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct OpdsSearchParams {
+    /// What the reader is looking for
+    #[schema(example = "cozy fantasy with dragons")]
+    pub q: String,
+}
+
+/// Catalog root
+#[utoipa::path(
+    get,
+    path = "/opds",
+    tag = "OPDS",
+    responses(
+        (status = 200, description = "OPDS navigation feed", content_type = "application/atom+xml;profile=opds-catalog;kind=navigation", body = String)
+    ),
+    summary = "OPDS catalog root",
+    description = "Entry point of the OPDS 1.2 catalog: links to popular and new books, browsing by author and \
+                   category (when the corpus snapshot is loaded), and OpenSearch-based search."
+)]
+#[actix_web::get("")]
+pub async fn catalog_root(
+    request: HttpRequest,
+    recommendation_service: web::Data<RecommendationService>,
+) -> HttpResponse {
+    let base = base_url(&request);
+    let updated = Utc::now().to_rfc3339();
+    let mut entries = vec![
+        navigation_entry(
+            &base,
+            "popular",
+            "Popular",
+            "Books readers are opening and shelving this week",
+            "http://opds-spec.org/sort/popular",
+            ACQUISITION_TYPE,
+            &updated,
+        ),
+        navigation_entry(
+            &base,
+            "new",
+            "New",
+            "Books recently added to the catalog",
+            "http://opds-spec.org/sort/new",
+            ACQUISITION_TYPE,
+            &updated,
+        ),
+    ];
+    if recommendation_service.corpus_stats().is_some() {
+        entries.push(navigation_entry(
+            &base,
+            "authors",
+            "Authors",
+            "The authors with the most books in the catalog",
+            "subsection",
+            NAVIGATION_TYPE,
+            &updated,
+        ));
+        entries.push(navigation_entry(
+            &base,
+            "categories",
+            "Categories",
+            "The largest categories in the catalog",
+            "subsection",
+            NAVIGATION_TYPE,
+            &updated,
+        ));
+    }
+
+    catalog_response(
+        &base,
+        "",
+        "Recommend a Book",
+        NAVIGATION_TYPE,
+        updated,
+        entries,
+    )
+}
+
+/// Popular books
+#[utoipa::path(
+    get,
+    path = "/opds/popular",
+    tag = "OPDS",
+    responses(
+        (status = 200, description = "OPDS acquisition feed of trending books", content_type = "application/atom+xml;profile=opds-catalog;kind=acquisition", body = String),
+        (status = 500, response = InternalServerError),
+        (status = 502, response = BadGateway)
+    ),
+    summary = "OPDS popular books",
+    description = "The 50 books readers clicked and shelved most over the last 7 days. The list is cached, and \
+                   recomputed by scheduled maintenance."
+)]
+#[actix_web::get("/popular")]
+pub async fn popular_books(
+    request: HttpRequest,
+    recommendation_service: web::Data<RecommendationService>,
+) -> Result<HttpResponse, ApiError> {
+    let now = Utc::now();
+    let updated = now.to_rfc3339();
+    let tenant = request_tenant(&request);
+    let entries = recommendation_service
+        .cached_trending(POPULAR_WINDOW_DAYS, OPDS_ENTRIES)
+        .await?
+        .iter()
+        .filter(|trending| recommendation_service.allows(&trending.book, tenant.as_deref()))
+        .filter_map(|trending| book_entry(&trending.book, &updated))
+        .collect();
+
+    Ok(catalog_response(
+        &base_url(&request),
+        "/popular",
+        "Popular",
+        ACQUISITION_TYPE,
+        updated,
+        entries,
+    ))
+}
+
+/// Newly indexed books
+#[utoipa::path(
+    get,
+    path = "/opds/new",
+    tag = "OPDS",
+    responses(
+        (status = 200, description = "OPDS acquisition feed of newly indexed books", content_type = "application/atom+xml;profile=opds-catalog;kind=acquisition", body = String),
+        (status = 500, response = InternalServerError),
+        (status = 502, response = BadGateway)
+    ),
+    summary = "OPDS new books",
+    description = "The 50 books most recently added to the index. Empty when no corpus snapshot is loaded."
+)]
+#[actix_web::get("/new")]
+pub async fn new_books(
+    request: HttpRequest,
+    recommendation_service: web::Data<RecommendationService>,
+) -> Result<HttpResponse, ApiError> {
+    let mut recently_indexed: Vec<&IndexedBook> = recommendation_service
+        .corpus_stats()
+        .map(|stats| stats.recently_indexed.as_slice())
+        .unwrap_or_default()
+        .iter()
+        .filter(|book| !recommendation_service.is_hidden(Some(&book.id)))
+        .take(OPDS_ENTRIES)
+        .collect();
+    recommendation_service
+        .retain_allowed_ids(
+            &mut recently_indexed,
+            request_tenant(&request).as_deref(),
+            |book| &book.id,
+        )
+        .await?;
+    let entries: Vec<AtomEntry> = recently_indexed
+        .into_iter()
+        .map(indexed_book_entry)
+        .collect();
+    let updated = entries
+        .first()
+        .map(|entry| entry.updated.clone())
+        .unwrap_or_else(|| Utc::now().to_rfc3339());
+
+    Ok(catalog_response(
+        &base_url(&request),
+        "/new",
+        "New",
+        ACQUISITION_TYPE,
+        updated,
+        entries,
+    ))
+}
+
+/// Browse by author
+#[utoipa::path(
+    get,
+    path = "/opds/authors",
+    tag = "OPDS",
+    responses(
+        (status = 200, description = "OPDS navigation feed of top authors", content_type = "application/atom+xml;profile=opds-catalog;kind=navigation", body = String),
+        (status = 404, description = "No corpus snapshot is loaded (not_found)", body = ErrorResponse,
+            example = json!({
+                "error": "Not found: No corpus statistics snapshot is loaded",
+                "code": "not_found",
+                "status": 404
+            }))
+    ),
+    summary = "OPDS authors",
+    description = "The 100 authors with the most books in the catalog, each linking to a search for their books."
+)]
+#[actix_web::get("/authors")]
+pub async fn browse_authors(
+    request: HttpRequest,
+    recommendation_service: web::Data<RecommendationService>,
+) -> Result<HttpResponse, ApiError> {
+    let stats = recommendation_service
+        .corpus_stats()
+        .ok_or_else(no_snapshot)?;
+    Ok(browse_response(
+        &base_url(&request),
+        "/authors",
+        "Authors",
+        &stats.top_authors,
+        &stats.generated_at,
+        |author| format!("books by {}", author),
+    ))
+}
+
+/// Browse by category
+#[utoipa::path(
+    get,
+    path = "/opds/categories",
+    tag = "OPDS",
+    responses(
+        (status = 200, description = "OPDS navigation feed of top categories", content_type = "application/atom+xml;profile=opds-catalog;kind=navigation", body = String),
+        (status = 404, description = "No corpus snapshot is loaded (not_found)", body = ErrorResponse,
+            example = json!({
+                "error": "Not found: No corpus statistics snapshot is loaded",
+                "code": "not_found",
+                "status": 404
+            }))
+    ),
+    summary = "OPDS categories",
+    description = "The 100 categories with the most books in the catalog, each linking to a search for them."
+)]
+#[actix_web::get("/categories")]
+pub async fn browse_categories(
+    request: HttpRequest,
+    recommendation_service: web::Data<RecommendationService>,
+) -> Result<HttpResponse, ApiError> {
+    let stats = recommendation_service
+        .corpus_stats()
+        .ok_or_else(no_snapshot)?;
+    Ok(browse_response(
+        &base_url(&request),
+        "/categories",
+        "Categories",
+        &stats.top_categories,
+        &stats.generated_at,
+        |category| format!("{} books", category),
+    ))
+}
+
+/// Search
+#[utoipa::path(
+    get,
+    path = "/opds/search",
+    tag = "OPDS",
+    params(
+        ("q" = String, Query, description = "What the reader is looking for", example = "cozy fantasy with dragons")
+    ),
+    responses(
+        (status = 200, description = "OPDS acquisition feed of recommendations", content_type = "application/atom+xml;profile=opds-catalog;kind=acquisition", body = String),
+        (status = 400, description = "Missing, too short or too long query (invalid_input)", body = ErrorResponse,
+            example = json!({
+                "error": "Invalid input: Query too short (minimum 3 characters)",
+                "code": "invalid_input",
+                "status": 400
+            })),
+        (status = 500, response = InternalServerError),
+        (status = 502, response = BadGateway),
+        (status = 503, response = ServiceUnavailable)
+    ),
+    summary = "OPDS search",
+    description = "The top 50 recommendations for a free-text query, as the OpenSearch description at \
+                   /opds/opensearch.xml advertises."
+)]
+#[actix_web::get("/search")]
+pub async fn search(
+    request: HttpRequest,
+    params: web::Query<OpdsSearchParams>,
+    recommendation_service: web::Data<RecommendationService>,
+) -> Result<HttpResponse, ApiError> {
+    let query = params.q.trim();
+    if query.is_empty() {
+        return Err(ApiError::InvalidInput("Query cannot be empty".to_string()));
+    }
+
+    let (mut books, _) = recommendation_service
+        .get_recommendations(query, OPDS_ENTRIES)
+        .await?;
+    recommendation_service.retain_allowed(&mut books, request_tenant(&request).as_deref());
+    let updated = Utc::now().to_rfc3339();
+    let entries = books
+        .iter()
+        .filter_map(|book| book_entry(book, &updated))
+        .collect();
+
+    Ok(catalog_response(
+        &base_url(&request),
+        &search_path(query),
+        &format!("Recommendations for \"{}\"", query),
+        ACQUISITION_TYPE,
+        updated,
+        entries,
+    ))
+}
+
+/// OpenSearch description of the search endpoint
+#[utoipa::path(
+    get,
+    path = "/opds/opensearch.xml",
+    tag = "OPDS",
+    responses(
+        (status = 200, description = "OpenSearch description document", content_type = "application/opensearchdescription+xml", body = String)
+    ),
+    summary = "OPDS OpenSearch description",
+    description = "Tells OPDS clients how to build search URLs for the catalog."
+)]
+#[actix_web::get("/opensearch.xml")]
+pub async fn opensearch_description(request: HttpRequest) -> HttpResponse {
+    let template = format!("{}/opds/search?q={{searchTerms}}", base_url(&request));
+    let xml = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <OpenSearchDescription xmlns=\"http://a9.com/-/spec/opensearch/1.1/\">\n  \
+         <ShortName>Recommend a Book</ShortName>\n  \
+         <Description>Describe the book you want to read next</Description>\n  \
+         <InputEncoding>UTF-8</InputEncoding>\n  \
+         <OutputEncoding>UTF-8</OutputEncoding>\n  \
+         <Url type=\"{}\" template=\"{}\"/>\n\
+         </OpenSearchDescription>\n",
+        ACQUISITION_TYPE,
+        super::atom::escape(&template)
+    );
+    xml_response(OPENSEARCH_TYPE, FEED_MAX_AGE_SECS, xml)
+}
+
+fn no_snapshot() -> ApiError {
+    ApiError::NotFound("No corpus statistics snapshot is loaded".to_string())
+}
+
+/// Path of a search, relative to `/opds`
+fn search_path(query: &str) -> String {
+    let mut url = Url::parse("http://localhost/search").expect("valid URL");
+    url.query_pairs_mut().append_pair("q", query);
+    format!("/search?{}", url.query().unwrap_or_default())
+}
+
+/// A catalog feed at `/opds<path>` with the links every OPDS feed carries
+fn catalog_response(
+    base: &str,
+    path: &str,
+    title: &str,
+    kind: &str,
+    updated: String,
+    entries: Vec<AtomEntry>,
+) -> HttpResponse {
+    let self_url = format!("{}/opds{}", base, path);
+    let feed = AtomFeed {
+        id: self_url.clone(),
+        title: title.to_string(),
+        subtitle: None,
+        updated,
+        links: vec![
+            AtomLink::new("self", self_url, kind),
+            AtomLink::new("start", format!("{}/opds", base), NAVIGATION_TYPE),
+            AtomLink::new(
+                "search",
+                format!("{}/opds/opensearch.xml", base),
+                OPENSEARCH_TYPE,
+            ),
+        ],
+        entries,
+    };
+    xml_response(
+        "application/atom+xml; charset=utf-8",
+        FEED_MAX_AGE_SECS,
+        feed.render(),
+    )
+}
+
+/// Navigation entry linking to `/opds/<path>`
+fn navigation_entry(
+    base: &str,
+    path: &str,
+    title: &str,
+    summary: &str,
+    rel: &str,
+    kind: &str,
+    updated: &str,
+) -> AtomEntry {
+    AtomEntry {
+        id: format!("urn:recommend-a-book:opds:{}", path),
+        title: title.to_string(),
+        updated: updated.to_string(),
+        summary: Some(summary.to_string()),
+        links: vec![AtomLink::new(rel, format!("{}/opds/{}", base, path), kind)],
+        ..Default::default()
+    }
+}
+
+/// Navigation feed of authors or categories, each linking to the search `query_for` builds
+fn browse_response(
+    base: &str,
+    path: &str,
+    title: &str,
+    names: &[NameCount],
+    updated: &str,
+    query_for: impl Fn(&str) -> String,
+) -> HttpResponse {
+    let entries = names
+        .iter()
+        .take(OPDS_BROWSE_ENTRIES)
+        .map(|name| AtomEntry {
+            id: format!(
+                "urn:recommend-a-book:opds{}:{}",
+                path.replace('/', ":"),
+                name.name
+            ),
+            title: name.name.clone(),
+            updated: updated.to_string(),
+            summary: Some(format!("{} books", name.books)),
+            links: vec![AtomLink::new(
+                "subsection",
+                format!("{}/opds{}", base, search_path(&query_for(&name.name))),
+                ACQUISITION_TYPE,
+            )],
+            ..Default::default()
+        })
+        .collect();
+    catalog_response(
+        base,
+        path,
+        title,
+        NAVIGATION_TYPE,
+        updated.to_string(),
+        entries,
+    )
+}
+
+/// Acquisition entry of a book, borrowed from Open Library
+fn book_entry(book: &Book, updated: &str) -> Option<AtomEntry> {
+    let id = book.id.as_deref()?;
+    let title = book.title.clone().unwrap_or_else(|| "Untitled".to_string());
+    let mut entry = AtomEntry {
+        id: book_entry_id(id),
+        updated: updated.to_string(),
+        authors: book.author.iter().cloned().collect(),
+        categories: book.categories.clone(),
+        summary: book.description.clone().map(|mut description| {
+            truncate_description(&mut description, DESCRIPTION_PREVIEW_CHARS);
+            description
+        }),
+        links: vec![borrow_link(
+            book.isbn.as_deref(),
+            &title,
+            book.author.as_deref(),
+        )],
+        title,
+        ..Default::default()
+    };
+
+    if let Some(isbn) = &book.isbn {
+        entry
+            .dublin_core
+            .push(("identifier", format!("urn:isbn:{}", isbn)));
+    }
+    if let Some(year) = book.year {
+        entry.dublin_core.push(("issued", year.to_string()));
+    }
+    if let Some(language) = &book.language {
+        entry.dublin_core.push(("language", language.clone()));
+    }
+    if let Some(publisher) = &book.publisher {
+        entry.dublin_core.push(("publisher", publisher.clone()));
+    }
+    if let Some(thumbnail) = &book.thumbnail {
+        for rel in [
+            "http://opds-spec.org/image",
+            "http://opds-spec.org/image/thumbnail",
+        ] {
+            entry
+                .links
+                .push(AtomLink::new(rel, thumbnail.clone(), "image/jpeg"));
+        }
+    }
+    Some(entry)
+}
+
+fn indexed_book_entry(book: &IndexedBook) -> AtomEntry {
+    let title = book.title.clone().unwrap_or_else(|| "Untitled".to_string());
+    AtomEntry {
+        id: book_entry_id(&book.id),
+        updated: book.indexed_at.clone(),
+        authors: book.author.iter().cloned().collect(),
+        categories: book.categories.clone(),
+        summary: book.description.clone(),
+        links: vec![borrow_link(None, &title, book.author.as_deref())],
+        title,
+        ..Default::default()
+    }
+}
+
+/// Link to the book on Open Library, by ISBN when known and by title otherwise
+fn borrow_link(isbn: Option<&str>, title: &str, author: Option<&str>) -> AtomLink {
+    let href = match isbn.map(str::trim).filter(|isbn| !isbn.is_empty()) {
+        Some(isbn) => format!("https://openlibrary.org/isbn/{}", isbn.replace('-', "")),
+        None => {
+            let mut url = Url::parse("https://openlibrary.org/search").expect("valid URL");
+            url.query_pairs_mut().append_pair("title", title);
+            if let Some(author) = author {
+                url.query_pairs_mut().append_pair("author", author);
+            }
+            url.to_string()
+        }
+    };
+    AtomLink::new("http://opds-spec.org/acquisition/borrow", href, "text/html")
+        .with_title("Borrow from Open Library")
+}
+
+pub fn opds_config(cfg: &mut web::ServiceConfig) {
+    cfg.service(catalog_root)
+        .service(popular_books)
+        .service(new_books)
+        .service(browse_authors)
+        .service(browse_categories)
+        .service(search)
+        .service(opensearch_description);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_book_entries_link_to_open_library() {
+        let book: Book = serde_json::from_value(json!({
+            "id": "book-1",
+            "title": "The Hobbit",
+            "author": "J.R.R. Tolkien",
+            "isbn": "978-0547928227",
+            "year": 1937,
+            "thumbnail": "https://covers.example.com/hobbit.jpg",
+            "categories": ["Fantasy"],
+        }))
+        .expect("valid book");
+
+        let entry = book_entry(&book, "2024-01-15T10:30:00+00:00").expect("entry");
+        assert_eq!(
+            entry.links[0].href,
+            "https://openlibrary.org/isbn/9780547928227"
+        );
+        assert_eq!(entry.links.len(), 3);
+        assert!(entry
+            .dublin_core
+            .contains(&("identifier", "urn:isbn:978-0547928227".to_string())));
+
+        let link = borrow_link(None, "Pride & Prejudice", Some("Jane Austen"));
+        assert_eq!(
+            link.href,
+            "https://openlibrary.org/search?title=Pride+%26+Prejudice&author=Jane+Austen"
+        );
+        assert_eq!(
+            search_path("books by Le Guin"),
+            "/search?q=books+by+Le+Guin"
+        );
+    }
+}
@@ -0,0 +1,162 @@
+use crate::{
+    error::ApiError,
+    middleware::UserAuth,
+    models::{ErrorResponse, InternalServerError, UserUnauthorized},
+    services::{
+        personalization::ContentPreferences,
+        supabase::{validate_id, SupabaseClient, UserPreferences},
+    },
+};
+use actix_web::{web, HttpResponse};
+
+/// Get a user's content preferences
+#[utoipa::path(
+    get,
+    path = "/api/users/{user_id}/preferences",
+    tag = "Preferences",
+    params(
+        ("user_id" = String, Path, description = "User id")
+    ),
+    responses(
+        (status = 200, description = "The user's content preferences", body = UserPreferences),
+        (status = 400, description = "Invalid user id (invalid_input)", body = ErrorResponse,
+            example = json!({
+                "error": "Invalid input: Invalid user id 'jane doe': expected 1-128 letters, digits, '-', '_' or '.'",
+                "code": "invalid_input",
+                "status": 400
+            })),
+        (status = 401, response = UserUnauthorized),
+        (status = 404, description = "The user hasn't saved any preferences (not_found)", body = ErrorResponse,
+            example = json!({
+                "error": "Not found: User 42 has no preferences",
+                "code": "not_found",
+                "status": 404
+            })),
+        (status = 500, response = InternalServerError)
+    ),
+    summary = "Get preferences"
+)]
+#[actix_web::get("")]
+pub async fn get_preferences(
+    path: web::Path<String>,
+    auth: UserAuth,
+    supabase: web::Data<SupabaseClient>,
+) -> Result<HttpResponse, ApiError> {
+    let user_id = path.into_inner();
+    validate_id("user id", &user_id)?;
+    auth.authorize(&user_id)?;
+
+    let preferences = supabase
+        .preferences(&user_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("User {} has no preferences", user_id)))?;
+
+    Ok(HttpResponse::Ok().json(preferences))
+}
+
+/// Save a user's content preferences
+#[utoipa::path(
+    put,
+    path = "/api/users/{user_id}/preferences",
+    tag = "Preferences",
+    params(
+        ("user_id" = String, Path, description = "User id")
+    ),
+    request_body = ContentPreferences,
+    responses(
+        (status = 200, description = "The saved preferences", body = UserPreferences),
+        (status = 400, description = "Invalid user id or preferences (invalid_input)", body = ErrorResponse,
+            examples(
+                ("Invalid user id" = (value = json!({
+                    "error": "Invalid input: Invalid user id 'jane doe': expected 1-128 letters, digits, '-', '_' or '.'",
+                    "code": "invalid_input",
+                    "status": 400
+                }))),
+                ("Invalid preferences" = (value = json!({
+                    "error": "Invalid input: Json deserialize error: invalid type: string \"long\", expected u32 at line 1 column 25",
+                    "code": "invalid_input",
+                    "status": 400
+                })))
+            )),
+        (status = 401, response = UserUnauthorized),
+        (status = 500, response = InternalServerError)
+    ),
+    summary = "Save preferences",
+    description = "Replaces the user's content preferences. Recommendation requests that include \
+                   the user id leave out books in other languages, longer than max_page_count, \
+                   or matching a blocked genre or content warning. Languages match by name or \
+                   ISO 639-3 code. Books with an unknown language or no page count are kept."
+)]
+#[actix_web::put("")]
+pub async fn set_preferences(
+    path: web::Path<String>,
+    auth: UserAuth,
+    request: web::Json<ContentPreferences>,
+    supabase: web::Data<SupabaseClient>,
+) -> Result<HttpResponse, ApiError> {
+    let user_id = path.into_inner();
+    validate_id("user id", &user_id)?;
+    auth.authorize(&user_id)?;
+
+    let preferences = supabase
+        .set_preferences(&user_id, request.into_inner())
+        .await?;
+
+    Ok(HttpResponse::Ok().json(preferences))
+}
+
+/// Delete a user's content preferences
+#[utoipa::path(
+    delete,
+    path = "/api/users/{user_id}/preferences",
+    tag = "Preferences",
+    params(
+        ("user_id" = String, Path, description = "User id")
+    ),
+    responses(
+        (status = 204, description = "The preferences were deleted"),
+        (status = 400, description = "Invalid user id (invalid_input)", body = ErrorResponse,
+            example = json!({
+                "error": "Invalid input: Invalid user id 'jane doe': expected 1-128 letters, digits, '-', '_' or '.'",
+                "code": "invalid_input",
+                "status": 400
+            })),
+        (status = 401, response = UserUnauthorized),
+        (status = 404, description = "The user hasn't saved any preferences (not_found)", body = ErrorResponse,
+            example = json!({
+                "error": "Not found: User 42 has no preferences",
+                "code": "not_found",
+                "status": 404
+            })),
+        (status = 500, response = InternalServerError)
+    ),
+    summary = "Delete preferences"
+)]
+#[actix_web::delete("")]
+pub async fn remove_preferences(
+    path: web::Path<String>,
+    auth: UserAuth,
+    supabase: web::Data<SupabaseClient>,
+) -> Result<HttpResponse, ApiError> {
+    let user_id = path.into_inner();
+    validate_id("user id", &user_id)?;
+    auth.authorize(&user_id)?;
+
+    if !supabase.remove_preferences(&user_id).await? {
+        return Err(ApiError::NotFound(format!(
+            "User {} has no preferences",
+            user_id
+        )));
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub fn preferences_config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/users/{user_id}/preferences")
+            .service(get_preferences)
+            .service(set_preferences)
+            .service(remove_preferences),
+    );
+}
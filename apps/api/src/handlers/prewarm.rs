@@ -10,7 +10,7 @@ use serde_json::json;
 /// This endpoint performs several operations to ensure the application is fully initialized:
 /// 1. Initializes the ML embedder
 /// 2. Tests connection to Pinecone
-/// 3. Executes a sample query to prepare the entire pipeline
+/// 3. Runs the configured warmup queries to prepare the pipeline and fill the result cache
 ///
 /// # Returns
 ///
@@ -0,0 +1,118 @@
+use crate::{
+    error::ApiError,
+    middleware::user_auth::UserAuth,
+    models::{ErrorResponse, InternalServerError, UserUnauthorized},
+    services::{
+        query_parser::merge_filters,
+        templates::{QueryFilters, QueryPattern},
+        RecommendationService,
+    },
+};
+use actix_web::{
+    web::{self, Json},
+    HttpResponse,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Request to read the constraints of a query before searching
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct QueryParseRequest {
+    /// The free-text query
+    #[schema(example = "short cozy mysteries set in scotland from the 90s")]
+    pub query: String,
+    /// Let a language model fill in constraints the templates miss, when one is
+    /// configured; needs a user's bearer token or the admin key
+    #[serde(default)]
+    pub llm: bool,
+}
+
+/// What read a query's constraints
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryParsedBy {
+    /// The query templates, which are what the search applies
+    Templates,
+    /// The templates, with a language model filling in what they missed; the
+    /// model's constraints are advisory, since the search doesn't apply them
+    TemplatesAndLlm,
+}
+
+/// The constraints read from a query
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct QueryParseResponse {
+    #[schema(example = "short cozy mysteries set in scotland from the 90s")]
+    pub query: String,
+    /// What the query mostly asks for
+    pub pattern: QueryPattern,
+    /// The constraints the templates read, which a search for the query applies,
+    /// and with parsed_by templates_and_llm the model's additions, which it doesn't
+    pub filters: QueryFilters,
+    /// Terms of the query the constraints were read from
+    #[schema(example = json!(["mystery", "scotland"]))]
+    pub extracted_terms: Vec<String>,
+    pub parsed_by: QueryParsedBy,
+}
+
+/// Read the constraints of a query
+#[utoipa::path(
+    post,
+    path = "/api/query/parse",
+    tag = "Recommendations",
+    request_body = QueryParseRequest,
+    responses(
+        (status = 200, description = "The structured constraints of the query", body = QueryParseResponse),
+        (status = 400, description = "Empty, too short or too long query (invalid_input)", body = ErrorResponse,
+            example = json!({
+                "error": "Invalid input: Query too short (minimum 3 characters)",
+                "code": "invalid_input",
+                "status": 400
+            })),
+        (status = 401, response = UserUnauthorized),
+        (status = 500, response = InternalServerError),
+    ),
+    summary = "Parse a query into filters",
+    description = "Reads a free-text query into the structured filters a search for it applies: author, genres, \
+                   themes, minimum rating, page and publication year bounds, audience and settings, with the \
+                   query's pattern and the terms they were read from, so a client can show the reader the parsed \
+                   constraints to confirm before searching. The query templates read them; with llm set and a \
+                   language model configured, the model fills in constraints the templates found nothing for, and \
+                   parsed_by says so. Where both read a constraint the templates' reading is kept. The model's \
+                   additions are advisory: a recommendation request for the query doesn't apply them, so a client \
+                   wanting them applied has to put them into the query's wording. A failing model leaves the \
+                   templates' reading. Since llm spends language-model calls it needs a user's bearer token or the \
+                   admin key; parsing with the templates alone is open to anyone."
+)]
+pub async fn parse_query(
+    auth: Option<UserAuth>,
+    request: Json<QueryParseRequest>,
+    recommendation_service: web::Data<RecommendationService>,
+) -> Result<HttpResponse, ApiError> {
+    if request.llm && auth.is_none() {
+        return Err(ApiError::AuthenticationError(
+            "Parsing with a language model needs a bearer token or the admin key".to_string(),
+        ));
+    }
+    let (enhanced, llm_filters) = recommendation_service
+        .parse_query(&request.query, request.llm)
+        .await?;
+
+    let (filters, parsed_by) = match llm_filters {
+        Some(llm_filters) => (
+            merge_filters(enhanced.filters, llm_filters),
+            QueryParsedBy::TemplatesAndLlm,
+        ),
+        None => (enhanced.filters, QueryParsedBy::Templates),
+    };
+    Ok(HttpResponse::Ok().json(QueryParseResponse {
+        query: enhanced.original_query,
+        pattern: enhanced.pattern,
+        filters,
+        extracted_terms: enhanced.extracted_terms,
+        parsed_by,
+    }))
+}
+
+pub fn query_config(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/query/parse").route(web::post().to(parse_query)));
+}
@@ -0,0 +1,228 @@
+use crate::{
+    error::ApiError,
+    middleware::UserAuth,
+    models::{ErrorResponse, InternalServerError, UserUnauthorized},
+    services::supabase::{validate_id, SupabaseClient, UserRating},
+};
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SetRatingRequest {
+    /// Stars from 1 to 5
+    #[schema(example = 4, minimum = 1, maximum = 5)]
+    pub rating: u8,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RatingsResponse {
+    #[schema(example = "3f1c2a9e-8d4b-4c47-9a51-0f6a7b2d1e55")]
+    pub user_id: String,
+    /// The user's ratings, most recently updated first
+    pub ratings: Vec<UserRating>,
+}
+
+/// List a user's book ratings
+#[utoipa::path(
+    get,
+    path = "/api/users/{user_id}/ratings",
+    tag = "Ratings",
+    params(
+        ("user_id" = String, Path, description = "User id")
+    ),
+    responses(
+        (status = 200, description = "The user's ratings, most recently updated first", body = RatingsResponse),
+        (status = 400, description = "Invalid user id (invalid_input)", body = ErrorResponse,
+            example = json!({
+                "error": "Invalid input: Invalid user id 'jane doe': expected 1-128 letters, digits, '-', '_' or '.'",
+                "code": "invalid_input",
+                "status": 400
+            })),
+        (status = 401, response = UserUnauthorized),
+        (status = 500, response = InternalServerError)
+    ),
+    summary = "List ratings"
+)]
+#[actix_web::get("")]
+pub async fn get_ratings(
+    path: web::Path<String>,
+    auth: UserAuth,
+    supabase: web::Data<SupabaseClient>,
+) -> Result<HttpResponse, ApiError> {
+    let user_id = path.into_inner();
+    validate_id("user id", &user_id)?;
+    auth.authorize(&user_id)?;
+
+    let ratings = supabase.ratings(&user_id, None).await?;
+
+    Ok(HttpResponse::Ok().json(RatingsResponse { user_id, ratings }))
+}
+
+/// Get a user's rating of a book
+#[utoipa::path(
+    get,
+    path = "/api/users/{user_id}/ratings/{book_id}",
+    tag = "Ratings",
+    params(
+        ("user_id" = String, Path, description = "User id"),
+        ("book_id" = String, Path, description = "Book id", example = "9780547928227")
+    ),
+    responses(
+        (status = 200, description = "The user's rating of the book", body = UserRating),
+        (status = 400, description = "Invalid user or book id (invalid_input)", body = ErrorResponse,
+            examples(
+                ("Invalid user id" = (value = json!({
+                    "error": "Invalid input: Invalid user id 'jane doe': expected 1-128 letters, digits, '-', '_' or '.'",
+                    "code": "invalid_input",
+                    "status": 400
+                }))),
+                ("Invalid book id" = (value = json!({
+                    "error": "Invalid input: Invalid book id 'the hobbit': expected 1-128 letters, digits, '-', '_' or '.'",
+                    "code": "invalid_input",
+                    "status": 400
+                })))
+            )),
+        (status = 401, response = UserUnauthorized),
+        (status = 404, description = "The user hasn't rated the book (not_found)", body = ErrorResponse,
+            example = json!({
+                "error": "Not found: Book 9780547928227 is not rated",
+                "code": "not_found",
+                "status": 404
+            })),
+        (status = 500, response = InternalServerError)
+    ),
+    summary = "Get a rating"
+)]
+#[actix_web::get("/{book_id}")]
+pub async fn get_rating(
+    path: web::Path<(String, String)>,
+    auth: UserAuth,
+    supabase: web::Data<SupabaseClient>,
+) -> Result<HttpResponse, ApiError> {
+    let (user_id, book_id) = path.into_inner();
+    validate_id("user id", &user_id)?;
+    auth.authorize(&user_id)?;
+    validate_id("book id", &book_id)?;
+
+    let rating = supabase
+        .rating(&user_id, &book_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("Book {} is not rated", book_id)))?;
+
+    Ok(HttpResponse::Ok().json(rating))
+}
+
+/// Rate a book
+#[utoipa::path(
+    put,
+    path = "/api/users/{user_id}/ratings/{book_id}",
+    tag = "Ratings",
+    params(
+        ("user_id" = String, Path, description = "User id"),
+        ("book_id" = String, Path, description = "Book id", example = "9780547928227")
+    ),
+    request_body = SetRatingRequest,
+    responses(
+        (status = 200, description = "The saved rating", body = UserRating),
+        (status = 400, description = "Invalid user id, book id or rating (invalid_input)", body = ErrorResponse,
+            examples(
+                ("Invalid user id" = (value = json!({
+                    "error": "Invalid input: Invalid user id 'jane doe': expected 1-128 letters, digits, '-', '_' or '.'",
+                    "code": "invalid_input",
+                    "status": 400
+                }))),
+                ("Rating out of range" = (value = json!({
+                    "error": "Invalid input: Rating must be between 1 and 5, got 7",
+                    "code": "invalid_input",
+                    "status": 400
+                })))
+            )),
+        (status = 401, response = UserUnauthorized),
+        (status = 500, response = InternalServerError)
+    ),
+    summary = "Rate a book",
+    description = "Stores the user's 1-5 star rating of the book, replacing any earlier rating. \
+                   Ratings adjust the ranking of recommendation requests that include the user id."
+)]
+#[actix_web::put("/{book_id}")]
+pub async fn set_rating(
+    path: web::Path<(String, String)>,
+    auth: UserAuth,
+    request: web::Json<SetRatingRequest>,
+    supabase: web::Data<SupabaseClient>,
+) -> Result<HttpResponse, ApiError> {
+    let (user_id, book_id) = path.into_inner();
+    validate_id("user id", &user_id)?;
+    auth.authorize(&user_id)?;
+    validate_id("book id", &book_id)?;
+
+    let rating = supabase
+        .set_rating(&user_id, &book_id, request.rating)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(rating))
+}
+
+/// Delete a user's rating of a book
+#[utoipa::path(
+    delete,
+    path = "/api/users/{user_id}/ratings/{book_id}",
+    tag = "Ratings",
+    params(
+        ("user_id" = String, Path, description = "User id"),
+        ("book_id" = String, Path, description = "Book id", example = "9780547928227")
+    ),
+    responses(
+        (status = 204, description = "The rating was deleted"),
+        (status = 400, description = "Invalid user or book id (invalid_input)", body = ErrorResponse,
+            examples(
+                ("Invalid user id" = (value = json!({
+                    "error": "Invalid input: Invalid user id 'jane doe': expected 1-128 letters, digits, '-', '_' or '.'",
+                    "code": "invalid_input",
+                    "status": 400
+                }))),
+                ("Invalid book id" = (value = json!({
+                    "error": "Invalid input: Invalid book id 'the hobbit': expected 1-128 letters, digits, '-', '_' or '.'",
+                    "code": "invalid_input",
+                    "status": 400
+                })))
+            )),
+        (status = 401, response = UserUnauthorized),
+        (status = 404, description = "The user hasn't rated the book (not_found)", body = ErrorResponse,
+            example = json!({
+                "error": "Not found: Book 9780547928227 is not rated",
+                "code": "not_found",
+                "status": 404
+            })),
+        (status = 500, response = InternalServerError)
+    ),
+    summary = "Delete a rating"
+)]
+#[actix_web::delete("/{book_id}")]
+pub async fn remove_rating(
+    path: web::Path<(String, String)>,
+    auth: UserAuth,
+    supabase: web::Data<SupabaseClient>,
+) -> Result<HttpResponse, ApiError> {
+    let (user_id, book_id) = path.into_inner();
+    validate_id("user id", &user_id)?;
+    auth.authorize(&user_id)?;
+    validate_id("book id", &book_id)?;
+
+    if !supabase.remove_rating(&user_id, &book_id).await? {
+        return Err(ApiError::NotFound(format!("Book {} is not rated", book_id)));
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub fn ratings_config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/users/{user_id}/ratings")
+            .service(get_ratings)
+            .service(get_rating)
+            .service(set_rating)
+            .service(remove_rating),
+    );
+}
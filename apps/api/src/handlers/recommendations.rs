@@ -1,18 +1,93 @@
+use super::book_list::book_list_response;
 use crate::{
     error::ApiError,
-    models::{ErrorResponse, RecommendationRequest, RecommendationResponse},
-    services::RecommendationService,
+    ingest::regions::{available_in, parse_region},
+    middleware::UserAuth,
+    models::{
+        BadGateway, Book, BookProjection, CacheStatus, ConversationState, ErrorResponse,
+        ForYouRequest, ForYouResponse, InternalServerError, RecommendationRequest,
+        RecommendationResponse, SemanticTag, ServiceUnavailable, SimilarToRequest,
+        SimilarToResponse, TagKind, TooManyRequests, UserUnauthorized, WhyNotOutcome,
+        WhyNotResponse, DEFAULT_TOP_K,
+    },
+    services::{
+        conversation::Conversation,
+        explanations::EXPLAINED_RESULTS,
+        exploration::{exploration_pool_size, explore},
+        feature_flags::{FlagContext, FlagSet},
+        i18n::Locale,
+        personalization::ContentPreferences,
+        post_filters::{FilterContext, PostFilterChain},
+        ranking::ResultPlacement,
+        recommendation::{QueryServing, RankingExplanation, RankingParams},
+        session_store::SessionStore,
+        similar_to::MAX_SEED_BOOKS,
+        supabase::{validate_id, HistoryOwner},
+        usage::Tenant,
+        RecommendationService,
+    },
+    telemetry,
 };
 use actix_web::{
+    http::header,
     web::{self, Json},
-    HttpResponse,
+    HttpMessage, HttpRequest, HttpResponse,
 };
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::info;
+
+/// How many times top_k to fetch when content preferences may filter out results
+const PREFERENCE_OVERFETCH: usize = 2;
 
 pub fn recommendations_config(cfg: &mut web::ServiceConfig) {
-    cfg.service(web::resource("/recommendations").route(web::post().to(get_recommendations)));
+    cfg.service(web::resource("/recommendations").route(web::post().to(get_recommendations)))
+        .service(web::resource("/recommendations/for-you").route(web::post().to(get_for_you)))
+        .service(web::resource("/recommendations/similar-to").route(web::post().to(get_similar_to)))
+        .service(web::resource("/recommendations/why-not").route(web::get().to(why_not)));
 }
 
 /// Get book recommendations based on query
+///
+/// With a user_id and that user's bearer token, books the user rated, and books related to them,
+/// move up or down with those ratings, the user's saved content preferences filter the results and
+/// set the safe_search default, and the query is kept in the user's history; otherwise it's kept
+/// in the session_id's. Results lean slightly toward the genres of earlier queries in the history.
+/// The user_id or session_id is also bucketed into the variants of running ranking experiments,
+/// listed in the response, and with the tenant of the X-Api-Key decides the feature flags that
+/// switch off or roll out re-ranking, graph blending, language-model explanations and the fused
+/// ranker.
+///
+/// Books dismissed in the session are left out, as are books failing the tenant's post-filters
+/// (age appropriateness, licensed publishers and blocklists), books whose publisher data shows
+/// they aren't sold in the region (books without regional data are kept) and, with safe_search,
+/// books with content flags. safe_search is on by default for queries for children or young
+/// adults.
+///
+/// An exploration above 0 swaps up to half the results, in proportion, for well-rated books from
+/// lower down the ranking, favouring genres the rest don't cover; the top 3 results never change.
+/// When fewer than 3 results meet the query's year range, length, award or setting, those
+/// constraints are dropped in that order until enough do, and `relaxed` lists the ones dropped.
+///
+/// `tags` lists what the query was read as, each with its kind, a confidence and the filter it
+/// names. Up to 5 `refinements` suggest narrower queries along the genre, era, length, mood and
+/// setting the query leaves open, each keeping between 15% and 85% of the results, the most even
+/// split first. With explain, each of the top 5 results gets a one-sentence explanation, written
+/// by a language model when one is configured, which for a signed-in user may relate it to the
+/// books the user rated highest. Explanations and semantic tags are in English, German or
+/// Spanish, picked by lang or else the Accept-Language header.
+///
+/// A context lists the conversation's earlier queries and selections, oldest first. What earlier
+/// turns asked for and the query leaves open is added to it, and negations such as "not YA" in any
+/// turn leave out books of that genre or term until a later turn asks for it again; a query that
+/// only rules something out searches the turn before it.
+///
+/// The response says whether the results came from the cache, how long the server took, which
+/// search answered (`search_path`: vector, sparse while the embedding service is down, or cached),
+/// whether that was a fallback (`degraded`) and which embedding model encoded the query.
+/// Upstreams that keep failing are skipped for a cooldown rather than waited on.
 #[utoipa::path(
     post,
     path = "/api/recommendations",
@@ -20,29 +95,830 @@ pub fn recommendations_config(cfg: &mut web::ServiceConfig) {
     request_body = RecommendationRequest,
     responses(
         (status = 200, description = "Successfully retrieved book recommendations", body = RecommendationResponse),
-        (status = 400, description = "Invalid input parameters", body = ErrorResponse),
-        (status = 500, description = "Internal server error", body = ErrorResponse),
+        (status = 400, description = "Empty, too short or too long query, invalid user or session id, exploration outside 0-1, unsupported lang, region not a two-letter country code, context of more than 20 turns or with a turn over 200 characters, or unknown field (invalid_input)", body = ErrorResponse,
+            examples(
+                ("Query too short" = (value = json!({
+                    "error": "Invalid input: Query too short (minimum 3 characters)",
+                    "code": "invalid_input",
+                    "status": 400
+                }))),
+                ("Unknown field" = (value = json!({
+                    "error": "Invalid input: Unknown book field 'blurb'. Valid fields: id, title, author, description, categories, genres, moods, thumbnail, rating, year, isbn, page_count, ratings_count, language, publisher, relevance_indicators, confidence_score, explanation",
+                    "code": "invalid_input",
+                    "status": 400
+                })))
+            )),
+        (status = 429, response = TooManyRequests),
+        (status = 500, response = InternalServerError),
+        (status = 502, response = BadGateway),
+        (status = 503, response = ServiceUnavailable),
     ),
     summary = "Get book recommendations",
-    description = "Returns a list of book recommendations based on the provided search query. Uses machine learning to find semantically similar books. Each recommendation includes the book details and a similarity score."
+    description = "Returns books matching the search query by semantic similarity, each with its details and a \
+                   similarity score. With a user_id and that user's bearer token, the user's ratings, saved content \
+                   preferences and earlier queries shape the results; without, the session_id's earlier queries do. \
+                   Books dismissed in the session are left out. Results can be limited to a region and by safe_search, varied by exploration, \
+                   explained with explain and narrowed through the returned tags and refinements. A context of \
+                   earlier turns carries what they asked for and ruled out into the query. The response says which \
+                   search answered and whether from the cache; when none can run, the query's last good results \
+                   stand in, or a 503 with Retry-After is returned."
 )]
 // Use a function without the post macro since we're using the route configuration above
 pub async fn get_recommendations(
+    http_request: HttpRequest,
+    auth: Option<UserAuth>,
     request: Json<RecommendationRequest>,
     recommendation_service: web::Data<RecommendationService>,
+    sessions: web::Data<SessionStore>,
 ) -> Result<HttpResponse, ApiError> {
     let top_k = request.top_k;
 
     if request.query.trim().is_empty() {
         return Err(ApiError::InvalidInput("Query cannot be empty".to_string()));
     }
+    if let Some(user_id) = &request.user_id {
+        validate_id("user id", user_id)?;
+    }
+    if let Some(session_id) = &request.session_id {
+        validate_id("session id", session_id)?;
+    }
+    if !(0.0..=1.0).contains(&request.exploration) {
+        return Err(ApiError::InvalidInput(
+            "exploration must be between 0 and 1".to_string(),
+        ));
+    }
+    let projection = BookProjection::new(request.fields.as_deref(), request.description_full)?;
+    let locale = Locale::negotiate(request.lang.as_deref(), accept_language(&http_request))?;
+    let region = request.region.as_deref().map(parse_region).transpose()?;
+    // Without context the query is searched as written
+    let conversation = if request.context.is_empty() {
+        None
+    } else {
+        Some(Conversation::new(&request.context, &request.query)?)
+    };
+    let query = conversation.as_ref().map_or_else(
+        || request.query.clone(),
+        |conversation| conversation.query.clone(),
+    );
+
+    let (experiments, mut params) = match request.unit_id() {
+        Some(unit_id) => recommendation_service.assign_experiments(unit_id),
+        None => (Vec::new(), RankingParams::default()),
+    };
+    let tenant = request_tenant(&http_request);
+    let flags = request_flags(
+        tenant.as_deref(),
+        request.unit_id(),
+        &recommendation_service,
+    );
+    flags.apply(&mut params);
+    if !experiments.is_empty() {
+        let labels: Vec<String> = experiments.iter().map(|a| a.label()).collect();
+        info!(
+            "Recommendation request assigned to experiment variants: {}",
+            labels.join(", ")
+        );
+    }
+
+    let started = Instant::now();
+    let dismissed: HashSet<String> = match &request.session_id {
+        Some(session_id) => sessions.dismissed(session_id).into_iter().collect(),
+        None => HashSet::new(),
+    };
+    let owner = history_owner(&request, auth.as_ref());
+    // Only a signed-in user's preferences filter, and ratings show up in explanations
+    let signed_in_user = match &owner {
+        Some(HistoryOwner::User(user_id)) => Some(user_id.clone()),
+        _ => None,
+    };
+    let preferences = recommendation_service
+        .query_preferences(&query, signed_in_user.as_deref(), request.safe_search)
+        .await;
+    let filters = ResultFilters {
+        dismissed,
+        preferences,
+        post_filters: recommendation_service
+            .post_filters(tenant.as_deref())
+            .clone(),
+        tenant,
+        region,
+        conversation,
+    };
+    let result = ranked_recommendations(
+        &request,
+        owner,
+        &query,
+        top_k,
+        &params,
+        &filters,
+        &recommendation_service,
+    )
+    .await;
+    for assignment in &experiments {
+        telemetry::record_variant_request(
+            &assignment.label(),
+            started.elapsed(),
+            result.as_ref().ok().map(|(books, _, _)| books.len()),
+        );
+    }
+    let (mut recommendations, semantic_tags, serving) = result?;
+    let (explained, generic) = if request.explain {
+        let generic = recommendation_service
+            .explain_top(
+                &query,
+                locale,
+                signed_in_user.as_deref(),
+                &mut recommendations,
+                flags.llm_explanations,
+            )
+            .await;
+        (recommendations.len().min(EXPLAINED_RESULTS), generic)
+    } else {
+        (0, 0)
+    };
+    telemetry::record_quality(
+        &format!("{:?}", serving.pattern),
+        !serving.tags.is_empty(),
+        explained,
+        generic,
+    );
+
+    book_list_response(
+        &RecommendationResponse {
+            recommendations: Vec::new(),
+            semantic_tags: semantic_tags.iter().map(|tag| locale.theme(tag)).collect(),
+            tags: serving
+                .tags
+                .into_iter()
+                .map(|tag| match tag.kind {
+                    TagKind::Theme | TagKind::Genre | TagKind::Mood => SemanticTag {
+                        label: locale.theme(&tag.label),
+                        ..tag
+                    },
+                    TagKind::Author | TagKind::Era => tag,
+                })
+                .collect(),
+            refinements: serving.refinements,
+            relaxed: serving.relaxed,
+            experiments,
+            cache: if serving.cache_hit {
+                CacheStatus::Hit
+            } else {
+                CacheStatus::Miss
+            },
+            took_ms: started.elapsed().as_millis() as u64,
+            degraded: serving.degraded,
+            model_used: serving.model_used,
+            search_path: serving.search_path,
+            conversation: filters
+                .conversation
+                .as_ref()
+                .map(|conversation| ConversationState {
+                    searched_query: conversation.query.clone(),
+                    excluded: conversation.excluded(),
+                }),
+        },
+        "recommendations",
+        recommendations,
+        projection,
+    )
+}
+
+/// Name of the tenant of the request's X-Api-Key, if it has one
+pub(crate) fn request_tenant(http_request: &HttpRequest) -> Option<String> {
+    http_request
+        .extensions()
+        .get::<Tenant>()
+        .map(|tenant| tenant.name.clone())
+}
+
+/// Feature flags of a request from `tenant` and `unit_id`, the user or session id
+fn request_flags(
+    tenant: Option<&str>,
+    unit_id: Option<&str>,
+    recommendation_service: &RecommendationService,
+) -> FlagSet {
+    recommendation_service
+        .feature_flags()
+        .evaluate(&FlagContext {
+            tenant,
+            user: unit_id,
+        })
+}
+
+/// The request's `Accept-Language` header, if it has a readable one
+pub(crate) fn accept_language(request: &HttpRequest) -> Option<&str> {
+    request
+        .headers()
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|value| value.to_str().ok())
+}
+
+/// Books the requester doesn't want to see
+struct ResultFilters {
+    /// Dismissed in the request's session
+    dismissed: HashSet<String>,
+    /// The user's saved content preferences
+    preferences: Option<ContentPreferences>,
+    /// Business rules of the request's tenant
+    post_filters: PostFilterChain,
+    /// Tenant of the request's X-Api-Key
+    tenant: Option<String>,
+    /// Market the request comes from
+    region: Option<String>,
+    /// Terms the request's conversation ruled out
+    conversation: Option<Conversation>,
+}
+
+impl ResultFilters {
+    fn is_empty(&self) -> bool {
+        self.dismissed.is_empty()
+            && self.preferences.is_none()
+            && self.post_filters.is_empty()
+            && self.region.is_none()
+            && self
+                .conversation
+                .as_ref()
+                .is_none_or(|conversation| conversation.is_empty())
+    }
+
+    fn available(&self, book: &Book) -> bool {
+        self.region
+            .as_deref()
+            .is_none_or(|region| available_in(book, region))
+    }
+
+    fn context(&self) -> FilterContext<'_> {
+        FilterContext {
+            tenant: self.tenant.as_deref(),
+        }
+    }
+
+    fn allows(&self, book: &Book) -> bool {
+        book.id
+            .as_ref()
+            .is_none_or(|id| !self.dismissed.contains(id))
+            && self
+                .preferences
+                .as_ref()
+                .is_none_or(|preferences| preferences.allows(book))
+            && self.post_filters.allows(book, &self.context())
+            && self.available(book)
+            && self
+                .conversation
+                .as_ref()
+                .is_none_or(|conversation| conversation.allows(book))
+    }
+
+    /// Results to ask for so that `top_k` are likely left after filtering
+    fn fetch_k(&self, top_k: usize) -> usize {
+        let top_k = top_k + self.dismissed.len();
+        let excludes = self
+            .conversation
+            .as_ref()
+            .is_some_and(|conversation| !conversation.is_empty());
+        if self.preferences.is_some()
+            || !self.post_filters.is_empty()
+            || self.region.is_some()
+            || excludes
+        {
+            top_k * PREFERENCE_OVERFETCH
+        } else {
+            top_k
+        }
+    }
+}
+
+/// History the request's query is kept in: the user's when the caller is signed in as
+/// that user, or else the session's
+fn history_owner(request: &RecommendationRequest, auth: Option<&UserAuth>) -> Option<HistoryOwner> {
+    let signed_in = |user_id: &&String| auth.is_some_and(|auth| auth.authorize(user_id).is_ok());
+    match (
+        request.user_id.as_ref().filter(signed_in),
+        &request.session_id,
+    ) {
+        (Some(user_id), _) => Some(HistoryOwner::User(user_id.clone())),
+        (None, Some(session_id)) => Some(HistoryOwner::Session(session_id.clone())),
+        (None, None) => None,
+    }
+}
+
+/// Results of `query`, the request's query read with its context, without filtered books,
+/// re-ranked by the signed-in user's ratings and `history`
+async fn ranked_recommendations(
+    request: &RecommendationRequest,
+    history: Option<HistoryOwner>,
+    query: &str,
+    top_k: usize,
+    params: &RankingParams,
+    filters: &ResultFilters,
+    recommendation_service: &RecommendationService,
+) -> Result<(Vec<Arc<Book>>, Vec<String>, QueryServing), ApiError> {
+    let pool_size = exploration_pool_size(top_k, request.exploration);
+    let (mut recommendations, semantic_tags, serving) = recommendation_service
+        .get_recommendations_served(query, filters.fetch_k(pool_size), params)
+        .await?;
+    if !filters.is_empty() {
+        recommendations.retain(|book| filters.allows(book));
+    }
+    let mut rng = recommendation_service.rng(query);
+    recommendations = explore(recommendations, top_k, request.exploration, &mut rng);
+
+    // Ratings only apply to a user the caller is authorized as
+    if let Some(HistoryOwner::User(user_id)) = &history {
+        recommendations = recommendation_service
+            .apply_user_ratings(recommendations, user_id, params)
+            .await;
+    }
+    if let Some(owner) = history {
+        // Bias by earlier queries before recording this one
+        recommendations = recommendation_service
+            .apply_query_history(recommendations, &owner, params)
+            .await;
+        recommendation_service.remember_query(
+            owner,
+            &request.query,
+            &semantic_tags,
+            recommendations.len(),
+        );
+    }
+
+    Ok((recommendations, semantic_tags, serving))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WhyNotParams {
+    pub query: String,
+    pub book_id: String,
+    #[serde(default = "default_why_not_top_k")]
+    pub top_k: usize,
+    pub user_id: Option<String>,
+    pub session_id: Option<String>,
+    pub safe_search: Option<bool>,
+    pub region: Option<String>,
+}
+
+fn default_why_not_top_k() -> usize {
+    DEFAULT_TOP_K
+}
+
+/// Explain why a book is missing from a query's recommendations
+#[utoipa::path(
+    get,
+    path = "/api/recommendations/why-not",
+    tag = "Recommendations",
+    params(
+        ("query" = String, Query, description = "The search query", example = "fantasy books with dragons"),
+        ("book_id" = String, Query, description = "The book expected among the results", example = "book_12345"),
+        ("top_k" = Option<usize>, Query, description = "Number of results asked for, 1-200 (default: 100)", example = 100),
+        ("user_id" = Option<String>, Query, description = "User whose experiment variants apply, and whose content preferences apply with the user's bearer token"),
+        ("session_id" = Option<String>, Query, description = "Session whose dismissed books are left out"),
+        ("safe_search" = Option<bool>, Query, description = "Leave out books with content flags, overriding the user's saved preference"),
+        ("region" = Option<String>, Query, description = "Two-letter country code of the market the books must be available in", example = "GB")
+    ),
+    responses(
+        (status = 200, description = "Where the book fell out of the pipeline, or its position if it's included", body = WhyNotResponse),
+        (status = 400, description = "Empty, too short or too long query, invalid id or region, or top_k out of range (invalid_input)", body = ErrorResponse,
+            example = json!({
+                "error": "Invalid input: top_k must be between 1 and 200",
+                "code": "invalid_input",
+                "status": 400
+            })),
+        (status = 500, response = InternalServerError),
+        (status = 502, response = BadGateway),
+        (status = 503, response = ServiceUnavailable),
+    ),
+    summary = "Explain a missing recommendation",
+    description = "Runs the query through retrieval, ranking, deduplication and the requester's filters without \
+                   using the result cache, and reports the first stage that dropped the book: not indexed, not \
+                   retrieved as a candidate, deduplicated against another edition, ranked below top_k, dismissed \
+                   in the session, filtered by the user's content preferences or safe search, left out by a \
+                   post-filter of the tenant, or not available in the region. Re-ranking by the user's ratings \
+                   and query history only reorders the results, so it can't drop a book and isn't replayed."
+)]
+pub async fn why_not(
+    http_request: HttpRequest,
+    auth: Option<UserAuth>,
+    params: web::Query<WhyNotParams>,
+    recommendation_service: web::Data<RecommendationService>,
+    sessions: web::Data<SessionStore>,
+) -> Result<HttpResponse, ApiError> {
+    let params = params.into_inner();
+    validate_id("book id", &params.book_id)?;
+    if let Some(user_id) = &params.user_id {
+        validate_id("user id", user_id)?;
+    }
+    if let Some(session_id) = &params.session_id {
+        validate_id("session id", session_id)?;
+    }
+    if params.top_k == 0 || params.top_k > 200 {
+        return Err(ApiError::InvalidInput(
+            "top_k must be between 1 and 200".to_string(),
+        ));
+    }
+    let region = params.region.as_deref().map(parse_region).transpose()?;
+    let signed_in_user = params.user_id.as_deref().filter(|user_id| {
+        auth.as_ref()
+            .is_some_and(|auth| auth.authorize(user_id).is_ok())
+    });
+
+    let unit_id = params.user_id.as_deref().or(params.session_id.as_deref());
+    let mut ranking_params = match unit_id {
+        Some(unit_id) => recommendation_service.assign_experiments(unit_id).1,
+        None => RankingParams::default(),
+    };
+    let tenant = request_tenant(&http_request);
+    request_flags(tenant.as_deref(), unit_id, &recommendation_service).apply(&mut ranking_params);
+    let filters = ResultFilters {
+        dismissed: match &params.session_id {
+            Some(session_id) => sessions.dismissed(session_id).into_iter().collect(),
+            None => HashSet::new(),
+        },
+        preferences: recommendation_service
+            .query_preferences(&params.query, signed_in_user, params.safe_search)
+            .await,
+        post_filters: recommendation_service
+            .post_filters(tenant.as_deref())
+            .clone(),
+        tenant,
+        region,
+        conversation: None,
+    };
+
+    let explanation = recommendation_service
+        .explain_ranking(
+            &params.query,
+            &params.book_id,
+            filters.fetch_k(params.top_k),
+            &ranking_params,
+        )
+        .await?;
+
+    Ok(HttpResponse::Ok().json(why_not_response(params, explanation, &filters)))
+}
+
+/// Report the first stage that dropped the book
+fn why_not_response(
+    params: WhyNotParams,
+    explanation: RankingExplanation,
+    filters: &ResultFilters,
+) -> WhyNotResponse {
+    let top_k = params.top_k;
+    let mut ranked_position = None;
+    let mut position = None;
+    let mut duplicate_of = None;
+
+    let (outcome, reason) = match (&explanation.book, &explanation.placement) {
+        (None, _) => (
+            WhyNotOutcome::NotIndexed,
+            "No book with this id is in the index.".to_string(),
+        ),
+        (Some(_), ResultPlacement::Missing) => {
+            let mut reason = format!(
+                "Not among the {} candidates retrieved for the query, read as {} intent.",
+                explanation.candidate_count, explanation.intent
+            );
+            if let (Some(filter), Some(false)) = (
+                &explanation.metadata_filter,
+                explanation.matches_metadata_filter,
+            ) {
+                reason.push_str(&format!(
+                    " The search started from the metadata filter {}, which the book doesn't match.",
+                    filter
+                ));
+            }
+            (WhyNotOutcome::NotRetrieved, reason)
+        }
+        (Some(_), ResultPlacement::DuplicateOf(first)) => {
+            duplicate_of = first.clone();
+            (
+                WhyNotOutcome::Deduplicated,
+                format!(
+                    "Dropped as a duplicate of {}, a book with the same title and author ranked above it.",
+                    first.as_deref().unwrap_or("a book without an id")
+                ),
+            )
+        }
+        (Some(book), &ResultPlacement::Ranked(rank)) => {
+            ranked_position = Some(rank + 1);
+            if rank >= explanation.results.len() {
+                (
+                    WhyNotOutcome::BelowCutoff,
+                    format!(
+                        "Ranked at position {}, below the {} results asked for.",
+                        rank + 1,
+                        top_k
+                    ),
+                )
+            } else if book
+                .id
+                .as_ref()
+                .is_some_and(|id| filters.dismissed.contains(id))
+            {
+                (
+                    WhyNotOutcome::Dismissed,
+                    "Dismissed with \"don't show again\" in this session.".to_string(),
+                )
+            } else if let Some(preferences) = filters
+                .preferences
+                .as_ref()
+                .filter(|preferences| !preferences.allows(book))
+            {
+                let reason = if preferences.safe_search && !book.content_flags.is_empty() {
+                    format!(
+                        "Left out by safe search, flagged for {}.",
+                        book.content_flags.join(", ")
+                    )
+                } else {
+                    "Filtered by the user's content preferences.".to_string()
+                };
+                (WhyNotOutcome::FilteredByPreferences, reason)
+            } else if let Some(name) = filters.post_filters.rejecting(book, &filters.context()) {
+                (
+                    WhyNotOutcome::FilteredByPostFilter,
+                    format!("Left out by the {} post-filter.", name),
+                )
+            } else if !filters.available(book) {
+                (
+                    WhyNotOutcome::UnavailableInRegion,
+                    format!(
+                        "Not published or sold in {}; it's available in {}.",
+                        filters.region.as_deref().unwrap_or_default(),
+                        book.available_regions.join(", ")
+                    ),
+                )
+            } else {
+                let kept_above = explanation.results[..rank]
+                    .iter()
+                    .filter(|other| filters.allows(other))
+                    .count();
+                if kept_above >= top_k {
+                    (
+                        WhyNotOutcome::BelowCutoff,
+                        format!(
+                            "Ranked at position {}, and {} once filtered books are left out, \
+                             below the {} results asked for.",
+                            rank + 1,
+                            kept_above + 1,
+                            top_k
+                        ),
+                    )
+                } else {
+                    position = Some(kept_above + 1);
+                    (
+                        WhyNotOutcome::Included,
+                        format!(
+                            "Included at position {}, before re-ranking by the user's ratings and history.",
+                            kept_above + 1
+                        ),
+                    )
+                }
+            }
+        }
+    };
+
+    WhyNotResponse {
+        query: params.query,
+        book_id: params.book_id,
+        title: explanation
+            .book
+            .as_ref()
+            .and_then(|book| book.title.clone()),
+        author: explanation
+            .book
+            .as_ref()
+            .and_then(|book| book.author.clone()),
+        outcome,
+        reason,
+        intent: explanation.intent,
+        intent_confidence: explanation.intent_confidence,
+        metadata_filter: explanation.metadata_filter,
+        matches_metadata_filter: explanation.matches_metadata_filter,
+        used_fallback: explanation.used_fallback,
+        candidates: explanation.candidate_count,
+        candidate_position: explanation.candidate_position.map(|position| position + 1),
+        ranked_position,
+        position,
+        duplicate_of,
+        top_k,
+    }
+}
 
-    let (recommendations, semantic_tags) = recommendation_service
-        .get_recommendations(&request.query, top_k)
+/// Get recommendations from a user's reading history
+#[utoipa::path(
+    post,
+    path = "/api/recommendations/for-you",
+    tag = "Recommendations",
+    request_body = ForYouRequest,
+    responses(
+        (status = 200, description = "Books matching the user's taste profile", body = ForYouResponse),
+        (status = 400, description = "Invalid user id or region, top_k out of range or unknown field (invalid_input)", body = ErrorResponse,
+            example = json!({
+                "error": "Invalid input: top_k must be between 1 and 200",
+                "code": "invalid_input",
+                "status": 400
+            })),
+        (status = 401, response = UserUnauthorized),
+        (status = 500, response = InternalServerError),
+        (status = 502, response = BadGateway),
+    ),
+    summary = "Get personalized recommendations",
+    description = "Needs the user's bearer token or the admin key. Builds a taste profile from the books the user rated 4 stars or higher, weighting 5-star books more, and returns the closest books in the index. Books the user has rated or shelved as read are left out, as are books the tenant's post-filters leave out and, with a region, books known not to be published or sold in that market. Returns an empty list with a profile_size of 0 until the user has rated a book highly. Descriptions are cut to 300-character previews unless description_full is set, and fields limits each book to the listed fields."
+)]
+pub async fn get_for_you(
+    http_request: HttpRequest,
+    auth: UserAuth,
+    request: Json<ForYouRequest>,
+    recommendation_service: web::Data<RecommendationService>,
+) -> Result<HttpResponse, ApiError> {
+    validate_id("user id", &request.user_id)?;
+    auth.authorize(&request.user_id)?;
+    if request.top_k == 0 || request.top_k > 200 {
+        return Err(ApiError::InvalidInput(
+            "top_k must be between 1 and 200".to_string(),
+        ));
+    }
+    let projection = BookProjection::new(request.fields.as_deref(), request.description_full)?;
+    let region = request.region.as_deref().map(parse_region).transpose()?;
+
+    let (mut recommendations, profile_size) = recommendation_service
+        .recommend_for_user(&request.user_id, request.top_k)
         .await?;
+    recommendation_service.retain_allowed(
+        &mut recommendations,
+        request_tenant(&http_request).as_deref(),
+    );
+    recommendations.retain(|book| {
+        region
+            .as_deref()
+            .is_none_or(|region| available_in(book, region))
+    });
 
-    Ok(HttpResponse::Ok().json(serde_json::json!({
-        "recommendations": recommendations,
-        "semantic_tags": semantic_tags,
-    })))
+    book_list_response(
+        &ForYouResponse {
+            recommendations: Vec::new(),
+            profile_size,
+        },
+        "recommendations",
+        recommendations.into_iter().map(Arc::new).collect(),
+        projection,
+    )
+}
+
+/// The ids listed in `field`, trimmed and without repeats, or an error if there are too many
+fn parse_seed_ids(field: &str, ids: &[String]) -> Result<Vec<String>, ApiError> {
+    let mut parsed: Vec<String> = Vec::with_capacity(ids.len());
+    for id in ids.iter().map(|id| id.trim()) {
+        if !id.is_empty() && !parsed.iter().any(|seen| seen == id) {
+            validate_id("book id", id)?;
+            parsed.push(id.to_string());
+        }
+    }
+    if parsed.len() > MAX_SEED_BOOKS {
+        return Err(ApiError::InvalidInput(format!(
+            "{} may list at most {} books",
+            field, MAX_SEED_BOOKS
+        )));
+    }
+    Ok(parsed)
+}
+
+/// Get books similar to several seed books at once
+#[utoipa::path(
+    post,
+    path = "/api/recommendations/similar-to",
+    tag = "Recommendations",
+    request_body = SimilarToRequest,
+    responses(
+        (status = 200, description = "Books similar to the seed books", body = SimilarToResponse),
+        (status = 400, description = "No book ids, more than 10 seeds or books to avoid, a book both a seed and avoided, invalid book id or region, top_k out of range or unknown field (invalid_input)", body = ErrorResponse,
+            example = json!({
+                "error": "Invalid input: book_ids may list at most 10 books",
+                "code": "invalid_input",
+                "status": 400
+            })),
+        (status = 404, description = "None of the seed books is indexed (not_found)", body = ErrorResponse,
+            example = json!({
+                "error": "Not found: Books not indexed: book_999",
+                "code": "not_found",
+                "status": 404
+            })),
+        (status = 500, response = InternalServerError),
+        (status = 502, response = BadGateway),
+    ),
+    summary = "Get more books like these",
+    description = "Returns books similar to up to 10 seed books, for \"more like these\" without phrasing a query. The seeds' stored embeddings are grouped so that seeds with little in common are searched apart: each group's centroid is searched separately and the results are interleaved, groups with more seeds contributing more books, and seed_groups lists the groups. With avoid_ids, books the reader disliked, a scaled mean of their embeddings is subtracted from each centroid, steering the results away from them without leaving the seeds' neighbourhood; the avoided books are never returned. Seeds and books to avoid that aren't indexed are left out and listed in missing_seeds and missing_avoided. The seeds themselves are left out of the results, as are books the tenant's post-filters leave out and, with a region, books known not to be published or sold in that market. Descriptions are cut to 300-character previews unless description_full is set, and fields limits each book to the listed fields."
+)]
+pub async fn get_similar_to(
+    http_request: HttpRequest,
+    request: Json<SimilarToRequest>,
+    recommendation_service: web::Data<RecommendationService>,
+) -> Result<HttpResponse, ApiError> {
+    let book_ids = parse_seed_ids("book_ids", &request.book_ids)?;
+    if book_ids.is_empty() {
+        return Err(ApiError::InvalidInput(
+            "book_ids must list at least one book".to_string(),
+        ));
+    }
+    let avoid_ids = parse_seed_ids("avoid_ids", &request.avoid_ids)?;
+    if let Some(id) = avoid_ids.iter().find(|id| book_ids.contains(id)) {
+        return Err(ApiError::InvalidInput(format!(
+            "Book {} can't be both a seed and avoided",
+            id
+        )));
+    }
+    if request.top_k == 0 || request.top_k > 200 {
+        return Err(ApiError::InvalidInput(
+            "top_k must be between 1 and 200".to_string(),
+        ));
+    }
+    let projection = BookProjection::new(request.fields.as_deref(), request.description_full)?;
+    let region = request.region.as_deref().map(parse_region).transpose()?;
+
+    let mut similar = recommendation_service
+        .recommend_similar_to(&book_ids, &avoid_ids, request.top_k)
+        .await?;
+    recommendation_service
+        .retain_allowed(&mut similar.books, request_tenant(&http_request).as_deref());
+    similar.books.retain(|book| {
+        region
+            .as_deref()
+            .is_none_or(|region| available_in(book, region))
+    });
+
+    book_list_response(
+        &SimilarToResponse {
+            recommendations: Vec::new(),
+            seed_groups: similar.clusters,
+            missing_seeds: similar.missing,
+            missing_avoided: similar.missing_avoided,
+        },
+        "recommendations",
+        similar.books.into_iter().map(Arc::new).collect(),
+        projection,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::middleware::user_auth::tests::token;
+    use crate::test_support::{sample_catalog, TestServices};
+    use actix_web::{http::StatusCode, test, App};
+
+    const SECRET: &str = "test-secret";
+
+    #[actix_web::test]
+    async fn test_for_you_is_only_served_to_its_user() {
+        let config: Config = serde_json::from_value(serde_json::json!({
+            "host": "127.0.0.1",
+            "port": 8000,
+            "pinecone_api_key": "",
+            "pinecone_environment": "",
+            "pinecone_index": "",
+            "user_jwt_secret": SECRET,
+        }))
+        .unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(TestServices::new(&sample_catalog()).service))
+                .configure(recommendations_config),
+        )
+        .await;
+        let user_1 = token(
+            serde_json::json!({"sub": "user-1", "exp": i64::MAX}),
+            SECRET,
+        );
+        let for_you = |bearer: Option<&str>| {
+            let request = test::TestRequest::post()
+                .uri("/recommendations/for-you")
+                .set_json(serde_json::json!({"user_id": "user-2"}));
+            match bearer {
+                Some(bearer) => {
+                    request.insert_header((header::AUTHORIZATION, format!("Bearer {}", bearer)))
+                }
+                None => request,
+            }
+            .to_request()
+        };
+
+        for request in [for_you(Some(&user_1)), for_you(None)] {
+            let response = test::call_service(&app, request).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+        // The owner's token gets past authorization to the request checks.
+        let user_2 = token(
+            serde_json::json!({"sub": "user-2", "exp": i64::MAX}),
+            SECRET,
+        );
+        let request = test::TestRequest::post()
+            .uri("/recommendations/for-you")
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", user_2)))
+            .set_json(serde_json::json!({"user_id": "user-2", "top_k": 0}))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
 }
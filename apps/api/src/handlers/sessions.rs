@@ -0,0 +1,156 @@
+use crate::{
+    error::ApiError,
+    models::ErrorResponse,
+    services::{session_store::SessionStore, supabase::validate_id},
+};
+use actix_web::{web, HttpResponse};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DismissedBooksResponse {
+    #[schema(example = "session-6b1f0c")]
+    pub session_id: String,
+    /// Dismissed book ids, most recently dismissed first
+    #[schema(example = json!(["9780547928227"]))]
+    pub book_ids: Vec<String>,
+}
+
+/// List the books dismissed in a session
+#[utoipa::path(
+    get,
+    path = "/api/sessions/{session_id}/dismissed",
+    tag = "Sessions",
+    params(
+        ("session_id" = String, Path, description = "Session id")
+    ),
+    responses(
+        (status = 200, description = "Dismissed books, most recently dismissed first", body = DismissedBooksResponse),
+        (status = 400, description = "Invalid session id (invalid_input)", body = ErrorResponse,
+            example = json!({
+                "error": "Invalid input: Invalid session id 'session 1': expected 1-128 letters, digits, '-', '_' or '.'",
+                "code": "invalid_input",
+                "status": 400
+            }))
+    ),
+    summary = "List dismissed books"
+)]
+#[actix_web::get("")]
+pub async fn get_dismissed(
+    path: web::Path<String>,
+    sessions: web::Data<SessionStore>,
+) -> Result<HttpResponse, ApiError> {
+    let session_id = path.into_inner();
+    validate_id("session id", &session_id)?;
+
+    let book_ids = sessions.dismissed(&session_id);
+
+    Ok(HttpResponse::Ok().json(DismissedBooksResponse {
+        session_id,
+        book_ids,
+    }))
+}
+
+/// Stop recommending a book in a session
+#[utoipa::path(
+    put,
+    path = "/api/sessions/{session_id}/dismissed/{book_id}",
+    tag = "Sessions",
+    params(
+        ("session_id" = String, Path, description = "Session id"),
+        ("book_id" = String, Path, description = "Book id", example = "9780547928227")
+    ),
+    responses(
+        (status = 204, description = "The book was dismissed"),
+        (status = 400, description = "Invalid session or book id (invalid_input)", body = ErrorResponse,
+            examples(
+                ("Invalid session id" = (value = json!({
+                    "error": "Invalid input: Invalid session id 'session 1': expected 1-128 letters, digits, '-', '_' or '.'",
+                    "code": "invalid_input",
+                    "status": 400
+                }))),
+                ("Invalid book id" = (value = json!({
+                    "error": "Invalid input: Invalid book id 'the hobbit': expected 1-128 letters, digits, '-', '_' or '.'",
+                    "code": "invalid_input",
+                    "status": 400
+                })))
+            ))
+    ),
+    summary = "Dismiss a book",
+    description = "Leaves the book out of recommendation requests made with this session_id. \
+                   Sessions are kept in memory and forgotten after a day without activity."
+)]
+#[actix_web::put("/{book_id}")]
+pub async fn dismiss_book(
+    path: web::Path<(String, String)>,
+    sessions: web::Data<SessionStore>,
+) -> Result<HttpResponse, ApiError> {
+    let (session_id, book_id) = path.into_inner();
+    validate_id("session id", &session_id)?;
+    validate_id("book id", &book_id)?;
+
+    sessions.dismiss(&session_id, &book_id);
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Recommend a dismissed book again
+#[utoipa::path(
+    delete,
+    path = "/api/sessions/{session_id}/dismissed/{book_id}",
+    tag = "Sessions",
+    params(
+        ("session_id" = String, Path, description = "Session id"),
+        ("book_id" = String, Path, description = "Book id", example = "9780547928227")
+    ),
+    responses(
+        (status = 204, description = "The book is no longer dismissed"),
+        (status = 400, description = "Invalid session or book id (invalid_input)", body = ErrorResponse,
+            examples(
+                ("Invalid session id" = (value = json!({
+                    "error": "Invalid input: Invalid session id 'session 1': expected 1-128 letters, digits, '-', '_' or '.'",
+                    "code": "invalid_input",
+                    "status": 400
+                }))),
+                ("Invalid book id" = (value = json!({
+                    "error": "Invalid input: Invalid book id 'the hobbit': expected 1-128 letters, digits, '-', '_' or '.'",
+                    "code": "invalid_input",
+                    "status": 400
+                })))
+            )),
+        (status = 404, description = "The book isn't dismissed in this session (not_found)", body = ErrorResponse,
+            example = json!({
+                "error": "Not found: Book 9780547928227 is not dismissed",
+                "code": "not_found",
+                "status": 404
+            }))
+    ),
+    summary = "Undo a dismissal"
+)]
+#[actix_web::delete("/{book_id}")]
+pub async fn undismiss_book(
+    path: web::Path<(String, String)>,
+    sessions: web::Data<SessionStore>,
+) -> Result<HttpResponse, ApiError> {
+    let (session_id, book_id) = path.into_inner();
+    validate_id("session id", &session_id)?;
+    validate_id("book id", &book_id)?;
+
+    if !sessions.undismiss(&session_id, &book_id) {
+        return Err(ApiError::NotFound(format!(
+            "Book {} is not dismissed",
+            book_id
+        )));
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub fn sessions_config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/sessions/{session_id}/dismissed")
+            .service(get_dismissed)
+            .service(dismiss_book)
+            .service(undismiss_book),
+    );
+}
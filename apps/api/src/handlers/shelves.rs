@@ -0,0 +1,244 @@
+use crate::{
+    error::ApiError,
+    middleware::UserAuth,
+    models::{ErrorResponse, InternalServerError, UserUnauthorized},
+    services::supabase::{validate_id, ShelfEntry, ShelfStatus, SupabaseClient},
+};
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ShelfQueryParams {
+    /// Only return books on this shelf
+    pub status: Option<ShelfStatus>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SetShelfRequest {
+    /// Shelf to put the book on
+    pub status: ShelfStatus,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ShelfResponse {
+    #[schema(example = "3f1c2a9e-8d4b-4c47-9a51-0f6a7b2d1e55")]
+    pub user_id: String,
+    /// Shelved books, most recently updated first
+    pub entries: Vec<ShelfEntry>,
+}
+
+/// List the books on a user's shelves
+#[utoipa::path(
+    get,
+    path = "/api/users/{user_id}/shelves",
+    tag = "Shelves",
+    params(
+        ("user_id" = String, Path, description = "User id"),
+        ("status" = Option<ShelfStatus>, Query, description = "Only return books on this shelf")
+    ),
+    responses(
+        (status = 200, description = "Shelved books, most recently updated first", body = ShelfResponse),
+        (status = 400, description = "Invalid user id or shelf (invalid_input)", body = ErrorResponse,
+            examples(
+                ("Invalid user id" = (value = json!({
+                    "error": "Invalid input: Invalid user id 'jane doe': expected 1-128 letters, digits, '-', '_' or '.'",
+                    "code": "invalid_input",
+                    "status": 400
+                }))),
+                ("Unknown shelf" = (value = json!({
+                    "error": "Invalid input: Unknown shelf 'finished', expected want_to_read, reading or read",
+                    "code": "invalid_input",
+                    "status": 400
+                })))
+            )),
+        (status = 401, response = UserUnauthorized),
+        (status = 500, response = InternalServerError)
+    ),
+    summary = "List shelved books"
+)]
+#[actix_web::get("")]
+pub async fn get_shelves(
+    path: web::Path<String>,
+    auth: UserAuth,
+    params: web::Query<ShelfQueryParams>,
+    supabase: web::Data<SupabaseClient>,
+) -> Result<HttpResponse, ApiError> {
+    let user_id = path.into_inner();
+    validate_id("user id", &user_id)?;
+    auth.authorize(&user_id)?;
+
+    let entries = supabase.shelf(&user_id, params.status).await?;
+
+    Ok(HttpResponse::Ok().json(ShelfResponse { user_id, entries }))
+}
+
+/// Get the shelf a book is on
+#[utoipa::path(
+    get,
+    path = "/api/users/{user_id}/shelves/{book_id}",
+    tag = "Shelves",
+    params(
+        ("user_id" = String, Path, description = "User id"),
+        ("book_id" = String, Path, description = "Book id", example = "9780547928227")
+    ),
+    responses(
+        (status = 200, description = "The book's shelf entry", body = ShelfEntry),
+        (status = 400, description = "Invalid user or book id (invalid_input)", body = ErrorResponse,
+            examples(
+                ("Invalid user id" = (value = json!({
+                    "error": "Invalid input: Invalid user id 'jane doe': expected 1-128 letters, digits, '-', '_' or '.'",
+                    "code": "invalid_input",
+                    "status": 400
+                }))),
+                ("Invalid book id" = (value = json!({
+                    "error": "Invalid input: Invalid book id 'the hobbit': expected 1-128 letters, digits, '-', '_' or '.'",
+                    "code": "invalid_input",
+                    "status": 400
+                })))
+            )),
+        (status = 401, response = UserUnauthorized),
+        (status = 404, description = "The book is not on any of the user's shelves (not_found)", body = ErrorResponse,
+            example = json!({
+                "error": "Not found: Book 9780547928227 is not shelved",
+                "code": "not_found",
+                "status": 404
+            })),
+        (status = 500, response = InternalServerError)
+    ),
+    summary = "Get a shelved book"
+)]
+#[actix_web::get("/{book_id}")]
+pub async fn get_shelf_entry(
+    path: web::Path<(String, String)>,
+    auth: UserAuth,
+    supabase: web::Data<SupabaseClient>,
+) -> Result<HttpResponse, ApiError> {
+    let (user_id, book_id) = path.into_inner();
+    validate_id("user id", &user_id)?;
+    auth.authorize(&user_id)?;
+    validate_id("book id", &book_id)?;
+
+    let entry = supabase
+        .shelf_entry(&user_id, &book_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("Book {} is not shelved", book_id)))?;
+
+    Ok(HttpResponse::Ok().json(entry))
+}
+
+/// Put a book on a shelf
+#[utoipa::path(
+    put,
+    path = "/api/users/{user_id}/shelves/{book_id}",
+    tag = "Shelves",
+    params(
+        ("user_id" = String, Path, description = "User id"),
+        ("book_id" = String, Path, description = "Book id", example = "9780547928227")
+    ),
+    request_body = SetShelfRequest,
+    responses(
+        (status = 200, description = "The book's updated shelf entry", body = ShelfEntry),
+        (status = 400, description = "Invalid user id, book id or shelf (invalid_input)", body = ErrorResponse,
+            examples(
+                ("Invalid book id" = (value = json!({
+                    "error": "Invalid input: Invalid book id 'the hobbit': expected 1-128 letters, digits, '-', '_' or '.'",
+                    "code": "invalid_input",
+                    "status": 400
+                }))),
+                ("Unknown shelf" = (value = json!({
+                    "error": "Invalid input: Unknown shelf 'finished', expected want_to_read, reading or read",
+                    "code": "invalid_input",
+                    "status": 400
+                })))
+            )),
+        (status = 401, response = UserUnauthorized),
+        (status = 500, response = InternalServerError)
+    ),
+    summary = "Shelve a book",
+    description = "Adds the book to the given shelf, or moves it there if it is already on another one."
+)]
+#[actix_web::put("/{book_id}")]
+pub async fn set_shelf(
+    path: web::Path<(String, String)>,
+    auth: UserAuth,
+    request: web::Json<SetShelfRequest>,
+    supabase: web::Data<SupabaseClient>,
+) -> Result<HttpResponse, ApiError> {
+    let (user_id, book_id) = path.into_inner();
+    validate_id("user id", &user_id)?;
+    auth.authorize(&user_id)?;
+    validate_id("book id", &book_id)?;
+
+    let entry = supabase
+        .set_shelf(&user_id, &book_id, request.status)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(entry))
+}
+
+/// Take a book off the user's shelves
+#[utoipa::path(
+    delete,
+    path = "/api/users/{user_id}/shelves/{book_id}",
+    tag = "Shelves",
+    params(
+        ("user_id" = String, Path, description = "User id"),
+        ("book_id" = String, Path, description = "Book id", example = "9780547928227")
+    ),
+    responses(
+        (status = 204, description = "The book was removed"),
+        (status = 400, description = "Invalid user or book id (invalid_input)", body = ErrorResponse,
+            examples(
+                ("Invalid user id" = (value = json!({
+                    "error": "Invalid input: Invalid user id 'jane doe': expected 1-128 letters, digits, '-', '_' or '.'",
+                    "code": "invalid_input",
+                    "status": 400
+                }))),
+                ("Invalid book id" = (value = json!({
+                    "error": "Invalid input: Invalid book id 'the hobbit': expected 1-128 letters, digits, '-', '_' or '.'",
+                    "code": "invalid_input",
+                    "status": 400
+                })))
+            )),
+        (status = 401, response = UserUnauthorized),
+        (status = 404, description = "The book is not on any of the user's shelves (not_found)", body = ErrorResponse,
+            example = json!({
+                "error": "Not found: Book 9780547928227 is not shelved",
+                "code": "not_found",
+                "status": 404
+            })),
+        (status = 500, response = InternalServerError)
+    ),
+    summary = "Unshelve a book"
+)]
+#[actix_web::delete("/{book_id}")]
+pub async fn remove_from_shelf(
+    path: web::Path<(String, String)>,
+    auth: UserAuth,
+    supabase: web::Data<SupabaseClient>,
+) -> Result<HttpResponse, ApiError> {
+    let (user_id, book_id) = path.into_inner();
+    validate_id("user id", &user_id)?;
+    auth.authorize(&user_id)?;
+    validate_id("book id", &book_id)?;
+
+    if !supabase.remove_from_shelf(&user_id, &book_id).await? {
+        return Err(ApiError::NotFound(format!(
+            "Book {} is not shelved",
+            book_id
+        )));
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub fn shelves_config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/users/{user_id}/shelves")
+            .service(get_shelves)
+            .service(get_shelf_entry)
+            .service(set_shelf)
+            .service(remove_from_shelf),
+    );
+}
@@ -0,0 +1,402 @@
+//! Tool endpoints for LLM agents
+//!
+//! Agents discover what they can call with `GET /api/tools`, which lists each
+//! tool with a JSON Schema of its arguments, and call one with
+//! `POST /api/tools/{name}`. The argument schemas are generated from the same
+//! structs the arguments are parsed into, so the contract can't drift. Graph and
+//! title tools are only listed when Neo4j or the corpus snapshot is available.
+
+use crate::{
+    error::ApiError,
+    handlers::recommendations::request_tenant,
+    models::{
+        truncate_description, BadGateway, Book, ErrorResponse, InternalServerError,
+        ServiceUnavailable, DESCRIPTION_PREVIEW_CHARS,
+    },
+    services::{neo4j::Neo4jClient, RecommendationService},
+};
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use utoipa::{PartialSchema, ToSchema};
+
+/// Most books a tool returns; agents rarely need more and each costs context
+const MAX_TOOL_RESULTS: usize = 50;
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RecommendBooksArgs {
+    /// What the reader is looking for, in their own words
+    #[schema(
+        example = "cozy fantasy with dragons",
+        min_length = 3,
+        max_length = 500
+    )]
+    pub query: String,
+    /// Number of books to return (default: 10)
+    #[serde(default = "default_tool_limit")]
+    #[schema(example = 10, minimum = 1, maximum = 50)]
+    pub top_k: usize,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SuggestTitlesArgs {
+    /// Start of a title, ignoring case and punctuation
+    #[schema(example = "the left h")]
+    pub prefix: String,
+    /// Number of titles to return (default: 10)
+    #[serde(default = "default_tool_limit")]
+    #[schema(example = 10, minimum = 1, maximum = 50)]
+    pub limit: usize,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SearchBooksArgs {
+    /// Words from the title
+    #[schema(example = "Hobbit")]
+    pub title: String,
+    /// Number of books to return (default: 10)
+    #[serde(default = "default_tool_limit")]
+    #[schema(example = 10, minimum = 1, maximum = 50)]
+    pub limit: usize,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SimilarBooksArgs {
+    /// Id of a book returned by another tool
+    #[schema(example = "book-123")]
+    pub book_id: String,
+    /// Number of books to return (default: 10)
+    #[serde(default = "default_tool_limit")]
+    #[schema(example = 10, minimum = 1, maximum = 50)]
+    pub limit: usize,
+}
+
+fn default_tool_limit() -> usize {
+    10
+}
+
+/// The tools agents can call
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tool {
+    RecommendBooks,
+    SuggestTitles,
+    SearchBooks,
+    SimilarBooks,
+}
+
+impl Tool {
+    const ALL: [Tool; 4] = [
+        Tool::RecommendBooks,
+        Tool::SuggestTitles,
+        Tool::SearchBooks,
+        Tool::SimilarBooks,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Tool::RecommendBooks => "recommend_books",
+            Tool::SuggestTitles => "suggest_titles",
+            Tool::SearchBooks => "search_books",
+            Tool::SimilarBooks => "similar_books",
+        }
+    }
+
+    fn named(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|tool| tool.name() == name)
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            Tool::RecommendBooks => {
+                "Recommend books matching a free-text description of what the reader wants, such as \
+                 themes, genres, moods, authors or books they liked. Returns books ranked by relevance \
+                 with the themes detected in the query and why each book matched."
+            }
+            Tool::SuggestTitles => {
+                "Complete the start of a book title to the exact titles in the catalog, most rated first. \
+                 Use it to find the catalog's spelling of a title the reader mentions."
+            }
+            Tool::SearchBooks => {
+                "Find books whose title contains the given words, highest rated first. Returns book ids \
+                 that similar_books accepts."
+            }
+            Tool::SimilarBooks => {
+                "List books related to a book in the catalog, through shared authors, genres and \
+                 similar content, most similar first."
+            }
+        }
+    }
+
+    fn input_schema(self) -> Value {
+        let schema = match self {
+            Tool::RecommendBooks => RecommendBooksArgs::schema(),
+            Tool::SuggestTitles => SuggestTitlesArgs::schema(),
+            Tool::SearchBooks => SearchBooksArgs::schema(),
+            Tool::SimilarBooks => SimilarBooksArgs::schema(),
+        };
+        serde_json::to_value(schema).unwrap_or_else(|_| json!({ "type": "object" }))
+    }
+
+    /// Whether the services the tool needs are running
+    fn is_available(self, recommendation_service: &RecommendationService, graph: bool) -> bool {
+        match self {
+            Tool::RecommendBooks => true,
+            Tool::SuggestTitles => recommendation_service.can_suggest_titles(),
+            Tool::SearchBooks | Tool::SimilarBooks => graph,
+        }
+    }
+
+    fn definition(self) -> ToolDefinition {
+        ToolDefinition {
+            name: self.name().to_string(),
+            description: self.description().to_string(),
+            input_schema: self.input_schema(),
+        }
+    }
+}
+
+/// A tool and the contract of its arguments
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ToolDefinition {
+    #[schema(example = "recommend_books")]
+    pub name: String,
+    /// What the tool does and when to use it, written for the calling model
+    pub description: String,
+    /// JSON Schema of the arguments object
+    #[schema(value_type = Object, example = json!({
+        "type": "object",
+        "required": ["query"],
+        "properties": {
+            "query": { "type": "string", "minLength": 3, "maxLength": 500 },
+            "top_k": { "type": "integer", "minimum": 1, "maximum": 50 }
+        }
+    }))]
+    pub input_schema: Value,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ToolsResponse {
+    pub tools: Vec<ToolDefinition>,
+}
+
+/// Arguments of a tool call
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ToolCallRequest {
+    /// Matches the tool's input_schema
+    #[serde(default)]
+    #[schema(value_type = Object, example = json!({ "query": "cozy fantasy with dragons", "top_k": 5 }))]
+    pub arguments: Value,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ToolCallResponse {
+    #[schema(example = "recommend_books")]
+    pub tool: String,
+    /// The tool's output
+    #[schema(value_type = Object)]
+    pub result: Value,
+}
+
+/// List the tools agents can call
+#[utoipa::path(
+    get,
+    path = "/api/tools",
+    tag = "Tools",
+    responses(
+        (status = 200, description = "Tools available on this server with their argument schemas", body = ToolsResponse)
+    ),
+    summary = "List tools",
+    description = "Lists the tools an LLM agent can call, each with a description written for the model and a \
+                   JSON Schema of its arguments. search_books and similar_books are only listed when the graph \
+                   database is connected, and suggest_titles when the corpus snapshot is loaded."
+)]
+#[actix_web::get("")]
+pub async fn list_tools(
+    recommendation_service: web::Data<RecommendationService>,
+    neo4j: Option<web::Data<Neo4jClient>>,
+) -> HttpResponse {
+    let tools = Tool::ALL
+        .into_iter()
+        .filter(|tool| tool.is_available(&recommendation_service, neo4j.is_some()))
+        .map(Tool::definition)
+        .collect();
+    HttpResponse::Ok().json(ToolsResponse { tools })
+}
+
+/// Call a tool
+#[utoipa::path(
+    post,
+    path = "/api/tools/{name}",
+    tag = "Tools",
+    params(
+        ("name" = String, Path, description = "Name of a listed tool", example = "recommend_books")
+    ),
+    request_body = ToolCallRequest,
+    responses(
+        (status = 200, description = "The tool's output", body = ToolCallResponse,
+            example = json!({
+                "tool": "recommend_books",
+                "result": {
+                    "books": [{ "id": "book_12345", "title": "The Hobbit", "author": "J.R.R. Tolkien" }],
+                    "semantic_tags": ["Fantasy", "Dragons"]
+                }
+            })),
+        (status = 400, description = "Arguments don't match the tool's input_schema (invalid_input)", body = ErrorResponse,
+            example = json!({
+                "error": "Invalid input: Invalid arguments for recommend_books: missing field `query`",
+                "code": "invalid_input",
+                "status": 400
+            })),
+        (status = 404, description = "No tool with this name is available (not_found)", body = ErrorResponse,
+            example = json!({
+                "error": "Not found: No tool named 'lookup_isbn'",
+                "code": "not_found",
+                "status": 404
+            })),
+        (status = 500, response = InternalServerError),
+        (status = 502, response = BadGateway),
+        (status = 503, response = ServiceUnavailable)
+    ),
+    summary = "Call a tool",
+    description = "Runs a listed tool with arguments matching its input_schema. Errors use the same error \
+                   response as the rest of the API, so an agent can show the message to the model and retry."
+)]
+#[actix_web::post("/{name}")]
+pub async fn call_tool(
+    http_request: HttpRequest,
+    name: web::Path<String>,
+    request: web::Json<ToolCallRequest>,
+    recommendation_service: web::Data<RecommendationService>,
+    neo4j: Option<web::Data<Neo4jClient>>,
+) -> Result<HttpResponse, ApiError> {
+    let tool = Tool::named(&name)
+        .filter(|tool| tool.is_available(&recommendation_service, neo4j.is_some()))
+        .ok_or_else(|| ApiError::NotFound(format!("No tool named '{}'", name)))?;
+    let arguments = request.into_inner().arguments;
+    let tenant = request_tenant(&http_request);
+    let tenant = tenant.as_deref();
+
+    let result = match tool {
+        Tool::RecommendBooks => {
+            let args: RecommendBooksArgs = parse_arguments(tool, arguments)?;
+            check_limit("top_k", args.top_k)?;
+            let (mut books, semantic_tags) = recommendation_service
+                .get_recommendations(&args.query, args.top_k)
+                .await?;
+            recommendation_service.retain_allowed(&mut books, tenant);
+            let books: Vec<Value> = books.iter().map(|book| tool_book(book)).collect();
+            json!({ "books": books, "semantic_tags": semantic_tags })
+        }
+        Tool::SuggestTitles => {
+            let args: SuggestTitlesArgs = parse_arguments(tool, arguments)?;
+            check_limit("limit", args.limit)?;
+            let titles = recommendation_service
+                .suggest_titles(&args.prefix, args.limit)
+                .unwrap_or_default();
+            json!({ "titles": titles })
+        }
+        Tool::SearchBooks => {
+            let args: SearchBooksArgs = parse_arguments(tool, arguments)?;
+            check_limit("limit", args.limit)?;
+            let neo4j = neo4j.ok_or_else(graph_unavailable)?;
+            let mut books = neo4j.search_books(&args.title, args.limit).await?;
+            recommendation_service
+                .retain_allowed_ids(&mut books, tenant, |book| &book.id)
+                .await?;
+            json!({ "books": books })
+        }
+        Tool::SimilarBooks => {
+            let args: SimilarBooksArgs = parse_arguments(tool, arguments)?;
+            check_limit("limit", args.limit)?;
+            let neo4j = neo4j.ok_or_else(graph_unavailable)?;
+            let mut books = neo4j.get_similar_books(&args.book_id, args.limit).await?;
+            recommendation_service
+                .retain_allowed_ids(&mut books, tenant, |book| &book.id)
+                .await?;
+            json!({ "books": books })
+        }
+    };
+
+    Ok(HttpResponse::Ok().json(ToolCallResponse {
+        tool: tool.name().to_string(),
+        result,
+    }))
+}
+
+fn parse_arguments<T: DeserializeOwned>(tool: Tool, arguments: Value) -> Result<T, ApiError> {
+    // Agents often send no arguments object at all for tools without required fields
+    let arguments = if arguments.is_null() {
+        json!({})
+    } else {
+        arguments
+    };
+    serde_json::from_value(arguments).map_err(|e| {
+        ApiError::InvalidInput(format!("Invalid arguments for {}: {}", tool.name(), e))
+    })
+}
+
+fn check_limit(field: &str, value: usize) -> Result<(), ApiError> {
+    if value == 0 || value > MAX_TOOL_RESULTS {
+        return Err(ApiError::InvalidInput(format!(
+            "{} must be between 1 and {}",
+            field, MAX_TOOL_RESULTS
+        )));
+    }
+    Ok(())
+}
+
+fn graph_unavailable() -> ApiError {
+    ApiError::NotFound("The graph database is not connected".to_string())
+}
+
+/// The fields of a book an agent needs, with the description cut to a preview
+fn tool_book(book: &Book) -> Value {
+    let mut description = book.description.clone();
+    if let Some(description) = &mut description {
+        truncate_description(description, DESCRIPTION_PREVIEW_CHARS);
+    }
+    json!({
+        "id": book.id,
+        "title": book.title,
+        "author": book.author,
+        "year": book.year,
+        "rating": book.rating,
+        "categories": book.categories,
+        "description": description,
+        "why": book.relevance_indicators,
+    })
+}
+
+pub fn tools_config(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::scope("/tools").service(list_tools).service(call_tool));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tool_schemas_describe_their_arguments() {
+        let schema = Tool::RecommendBooks.input_schema();
+        assert_eq!(schema["type"], "object");
+        assert_eq!(schema["required"], json!(["query"]));
+        assert_eq!(schema["properties"]["top_k"]["maximum"], json!(50));
+
+        for tool in Tool::ALL {
+            assert_eq!(Tool::named(tool.name()), Some(tool));
+        }
+
+        let args: RecommendBooksArgs =
+            parse_arguments(Tool::RecommendBooks, json!({ "query": "dragons" })).expect("valid");
+        assert_eq!(args.top_k, 10);
+        assert!(parse_arguments::<RecommendBooksArgs>(
+            Tool::RecommendBooks,
+            json!({ "query": "dragons", "k": 5 })
+        )
+        .is_err());
+    }
+}
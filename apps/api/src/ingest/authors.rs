@@ -0,0 +1,248 @@
+//! Author name canonicalization
+//!
+//! Catalogs spell the same author many ways: "Rowling, J. K.", "J.K. Rowling",
+//! "Joanne Rowling". Names are first formatted consistently (initials dotted and
+//! spaced, "Last, First" flipped) and then resolved through an optional alias map.
+//! The indexer and query-time author matching both go through here, so a name
+//! typed in a query lands on the same canonical author that was indexed.
+
+use crate::error::{ApiError, Result};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Name suffixes that follow a comma without flipping the name, as in "King, Jr."
+const NAME_SUFFIXES: &[&str] = &["jr", "sr", "ii", "iii", "iv", "phd"];
+
+/// Alias map from alternative spellings to a canonical author name
+///
+/// Loaded from a YAML file mapping each canonical name to its aliases:
+///
+/// ```yaml
+/// J. K. Rowling:
+///   - Joanne Rowling
+///   - Joanne K. Rowling
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct AuthorAliases {
+    /// Keyed by [`author_key`] of the formatted alias
+    canonical: HashMap<String, String>,
+}
+
+impl AuthorAliases {
+    /// Load the alias map from a YAML file
+    pub fn load(path: &Path) -> Result<Self> {
+        let yaml = std::fs::read_to_string(path).map_err(|e| {
+            ApiError::InternalError(format!(
+                "Failed to read author aliases {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+        Self::from_yaml(&yaml)
+    }
+
+    pub fn from_yaml(yaml: &str) -> Result<Self> {
+        let entries: HashMap<String, Vec<String>> =
+            serde_yaml::from_str::<Option<HashMap<String, Vec<String>>>>(yaml)
+                .map_err(|e| ApiError::InvalidInput(format!("Invalid author aliases: {}", e)))?
+                .unwrap_or_default();
+
+        let mut aliases = Self::default();
+        for (canonical, names) in entries {
+            let canonical = format_name(&canonical);
+            if canonical.is_empty() {
+                continue;
+            }
+            for name in names.iter().map(String::as_str).chain([canonical.as_str()]) {
+                let key = author_key(&format_name(name));
+                if let Some(existing) = aliases.canonical.get(&key) {
+                    if *existing != canonical {
+                        return Err(ApiError::InvalidInput(format!(
+                            "Author alias '{}' maps to both '{}' and '{}'",
+                            name, existing, canonical
+                        )));
+                    }
+                }
+                aliases.canonical.insert(key, canonical.clone());
+            }
+        }
+        Ok(aliases)
+    }
+
+    pub fn len(&self) -> usize {
+        self.canonical.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.canonical.is_empty()
+    }
+
+    /// Canonical form of a single author name
+    pub fn canonicalize_name(&self, name: &str) -> String {
+        let formatted = format_name(name);
+        self.canonical
+            .get(&author_key(&formatted))
+            .cloned()
+            .unwrap_or(formatted)
+    }
+
+    /// Canonical form of an author field that may list several authors, joined with ", "
+    pub fn canonicalize(&self, authors: &str) -> String {
+        let mut names: Vec<String> = Vec::new();
+        for name in split_authors(authors) {
+            let name = self.canonicalize_name(&name);
+            if !name.is_empty() && !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        names.join(", ")
+    }
+}
+
+/// Comparison key for an author name: lowercase alphanumeric words separated by spaces
+pub fn author_key(name: &str) -> String {
+    name.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Split an author field into individual names, flipping "Last, First" where it's unambiguous
+pub fn split_authors(authors: &str) -> Vec<String> {
+    let mut names = Vec::new();
+
+    for group in authors.split(&[';', '|', '&'][..]) {
+        let parts: Vec<&str> = group
+            .split(',')
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+            .collect();
+
+        match parts.as_slice() {
+            [name, suffix] if is_suffix(suffix) => names.push(format!("{} {}", name, suffix)),
+            [last, first] if looks_inverted(last, first) => {
+                names.push(format!("{} {}", first, last))
+            }
+            parts => names.extend(parts.iter().map(|part| part.to_string())),
+        }
+    }
+
+    names
+}
+
+/// Format a single name: collapse whitespace, dot and space initials, and fix all-caps or
+/// all-lowercase names
+pub fn format_name(name: &str) -> String {
+    let tokens: Vec<String> = name.split_whitespace().flat_map(expand_initials).collect();
+
+    // Initials are always uppercase, so only the other words decide the case
+    let letters = || {
+        tokens
+            .iter()
+            .filter(|t| !is_initial(t))
+            .flat_map(|t| t.chars())
+            .filter(|c| c.is_alphabetic())
+    };
+    let fix_case = letters().all(char::is_lowercase) || letters().all(char::is_uppercase);
+
+    tokens
+        .into_iter()
+        .map(|token| if fix_case { title_case(&token) } else { token })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// "J.K." -> ["J.", "K."], "J" -> ["J."], other words unchanged
+fn expand_initials(token: &str) -> Vec<String> {
+    let letters: Vec<char> = token.chars().filter(|c| *c != '.').collect();
+    let is_initials = !letters.is_empty()
+        && letters.iter().all(|c| c.is_alphabetic())
+        && (letters.len() == 1 || token.matches('.').count() == letters.len());
+
+    if is_initials {
+        letters
+            .into_iter()
+            .map(|c| format!("{}.", c.to_uppercase()))
+            .collect()
+    } else {
+        vec![token.to_string()]
+    }
+}
+
+fn is_initial(token: &str) -> bool {
+    token.len() <= 3
+        && token.ends_with('.')
+        && token.chars().filter(|c| c.is_alphabetic()).count() == 1
+}
+
+fn title_case(word: &str) -> String {
+    let mut result = String::with_capacity(word.len());
+    let mut capitalize = true;
+    for c in word.chars() {
+        if capitalize {
+            result.extend(c.to_uppercase());
+        } else {
+            result.extend(c.to_lowercase());
+        }
+        capitalize = matches!(c, '-' | '\'' | '.');
+    }
+    result
+}
+
+fn is_suffix(part: &str) -> bool {
+    NAME_SUFFIXES.contains(&author_key(part).as_str())
+}
+
+/// Whether "A, B" is one inverted name rather than two authors
+///
+/// A short surname before the comma and given names after it read as inverted;
+/// "Neil Gaiman, Terry Pratchett" does not, since both sides are full names
+/// without initials.
+fn looks_inverted(last: &str, first: &str) -> bool {
+    let last_words = last.split_whitespace().count();
+    let first_words: Vec<&str> = first.split_whitespace().collect();
+    let has_initial = first_words
+        .iter()
+        .any(|word| word.trim_end_matches('.').chars().count() == 1 || word.contains('.'));
+
+    (1..=3).contains(&first_words.len()) && (last_words == 1 || (last_words == 2 && has_initial))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spellings_resolve_to_one_author() {
+        let aliases =
+            AuthorAliases::from_yaml("J. K. Rowling:\n  - Joanne Rowling\nUrsula K. Le Guin: []\n")
+                .unwrap();
+
+        for spelling in [
+            "J.K. Rowling",
+            "Rowling, J. K.",
+            "Joanne Rowling",
+            "j k rowling",
+        ] {
+            assert_eq!(
+                aliases.canonicalize(spelling),
+                "J. K. Rowling",
+                "{}",
+                spelling
+            );
+        }
+        assert_eq!(
+            aliases.canonicalize("Le Guin, Ursula K."),
+            "Ursula K. Le Guin"
+        );
+        assert_eq!(
+            aliases.canonicalize("Neil Gaiman, Terry Pratchett"),
+            "Neil Gaiman, Terry Pratchett"
+        );
+        assert_eq!(
+            aliases.canonicalize("King, Jr.; TOLKIEN, J.R.R."),
+            "King Jr., J. R. R. Tolkien"
+        );
+    }
+}
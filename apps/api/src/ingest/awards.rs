@@ -0,0 +1,260 @@
+//! Literary awards: which books won a Hugo, Booker or Pulitzer
+//!
+//! Winners come from a CSV bundled with the repo, one row per win:
+//!
+//! ```csv
+//! award,year,title,author
+//! Hugo Award,1985,Neuromancer,William Gibson
+//! ```
+//!
+//! A book is credited with an award when its title and one of its authors
+//! match a row, ignoring case, punctuation and a leading "The". Award names must
+//! come from [`AWARDS`], the vocabulary queries are matched against.
+
+use super::authors::{author_key, split_authors};
+use crate::error::{ApiError, Result};
+use crate::models::Book;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::Path;
+
+/// Awards of the vocabulary, with the words naming each in queries
+pub const AWARDS: &[(&str, &[&str])] = &[
+    ("Hugo Award", &["hugo", "hugos"]),
+    ("Booker Prize", &["booker", "bookers"]),
+    ("Pulitzer Prize", &["pulitzer", "pulitzers"]),
+];
+
+/// Words that make an award's name in a query mean the award, so "books by
+/// Victor Hugo" isn't read as asking for Hugo winners
+const AWARD_CONTEXT_WORDS: &[&str] = &[
+    "award", "awards", "prize", "prizes", "winner", "winners", "winning", "won",
+];
+
+lazy_static! {
+    static ref ANY_AWARD_PATTERN: Regex = Regex::new(
+        r"(?i)\b(award|prize)[\s-]?(winning|winners?|winner's)\b|\bprizewinning\b|\bwon (an?|the) (award|prize)\b"
+    )
+    .unwrap();
+}
+
+/// The award a query asks for
+#[derive(Debug, Clone, PartialEq)]
+pub enum AwardQuery {
+    /// Any award, e.g. "award-winning science fiction"
+    Any,
+    /// One award of the vocabulary, e.g. "hugo winners"
+    Named(&'static str),
+}
+
+impl AwardQuery {
+    /// Names of the awards that satisfy the query
+    pub fn award_names(&self) -> Vec<&'static str> {
+        match self {
+            AwardQuery::Any => AWARDS.iter().map(|(name, _)| *name).collect(),
+            AwardQuery::Named(name) => vec![name],
+        }
+    }
+
+    /// Awards of `book` that satisfy the query
+    pub fn matches<'a>(&self, book: &'a Book) -> Vec<&'a str> {
+        let names = self.award_names();
+        book.awards
+            .iter()
+            .filter(|award| names.contains(&award.as_str()))
+            .map(String::as_str)
+            .collect()
+    }
+}
+
+/// The award a query asks for, a named one taking precedence over "award-winning"
+pub fn award_in_query(query: &str) -> Option<AwardQuery> {
+    let query_lower = query.to_lowercase();
+    let words: Vec<&str> = query_lower
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect();
+    if !words.iter().any(|word| AWARD_CONTEXT_WORDS.contains(word)) {
+        return None;
+    }
+
+    let named = words.iter().find_map(|word| {
+        AWARDS
+            .iter()
+            .find(|(_, names)| names.contains(word))
+            .map(|(name, _)| *name)
+    });
+    match named {
+        Some(name) => Some(AwardQuery::Named(name)),
+        None => ANY_AWARD_PATTERN
+            .is_match(&query_lower)
+            .then_some(AwardQuery::Any),
+    }
+}
+
+/// Comparison key for a title, e.g. "the left hand of darkness" -> "left hand of darkness"
+fn title_key(title: &str) -> String {
+    let key = author_key(title);
+    match key.strip_prefix("the ") {
+        Some(rest) => rest.to_string(),
+        None => key,
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct WinnerRow {
+    award: String,
+    year: i32,
+    title: String,
+    author: String,
+}
+
+#[derive(Debug, Clone)]
+struct Winner {
+    award: &'static str,
+    year: i32,
+    author_key: String,
+}
+
+/// Award winners by title
+#[derive(Debug, Clone, Default)]
+pub struct AwardList {
+    winners: HashMap<String, Vec<Winner>>,
+}
+
+impl AwardList {
+    /// Load the winners from a CSV file
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = std::fs::File::open(path).map_err(|e| {
+            ApiError::InternalError(format!(
+                "Failed to read award list {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+        Self::from_reader(file)
+    }
+
+    pub fn from_reader(reader: impl Read) -> Result<Self> {
+        let mut winners: HashMap<String, Vec<Winner>> = HashMap::new();
+        for (row_index, result) in csv::Reader::from_reader(reader)
+            .deserialize::<WinnerRow>()
+            .enumerate()
+        {
+            let row = result.map_err(|e| {
+                ApiError::InvalidInput(format!("Invalid award list row {}: {}", row_index + 1, e))
+            })?;
+            let award = AWARDS
+                .iter()
+                .map(|(name, _)| *name)
+                .find(|name| name.eq_ignore_ascii_case(row.award.trim()))
+                .ok_or_else(|| {
+                    ApiError::InvalidInput(format!(
+                        "Unknown award '{}' on award list row {}",
+                        row.award,
+                        row_index + 1
+                    ))
+                })?;
+            winners
+                .entry(title_key(&row.title))
+                .or_default()
+                .push(Winner {
+                    award,
+                    year: row.year,
+                    author_key: author_key(&row.author),
+                });
+        }
+        Ok(Self { winners })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.winners.is_empty()
+    }
+
+    /// Number of wins on the list
+    pub fn len(&self) -> usize {
+        self.winners.values().map(Vec::len).sum()
+    }
+
+    /// Awards won by `book`, earliest win first
+    pub fn awards_for(&self, book: &Book) -> Vec<String> {
+        let (Some(title), Some(author)) = (&book.title, &book.author) else {
+            return Vec::new();
+        };
+        let Some(winners) = self.winners.get(&title_key(title)) else {
+            return Vec::new();
+        };
+        let author_keys: Vec<String> = split_authors(author)
+            .iter()
+            .map(|name| author_key(name))
+            .collect();
+
+        let mut wins: Vec<&Winner> = winners
+            .iter()
+            .filter(|winner| author_keys.contains(&winner.author_key))
+            .collect();
+        wins.sort_by_key(|winner| winner.year);
+        let mut awards: Vec<String> = Vec::new();
+        for winner in wins {
+            if !awards.iter().any(|award| award == winner.award) {
+                awards.push(winner.award.to_string());
+            }
+        }
+        awards
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_winners_match_by_title_and_author() {
+        let list = AwardList::from_reader(
+            "award,year,title,author\n\
+             Hugo Award,1985,Neuromancer,William Gibson\n\
+             Pulitzer Prize,1988,Beloved,Toni Morrison\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(list.len(), 2);
+
+        let book = |title: &str, author: &str| -> Book {
+            serde_json::from_value(serde_json::json!({
+                "title": title,
+                "author": author,
+                "categories": [],
+            }))
+            .unwrap()
+        };
+        assert_eq!(
+            list.awards_for(&book("neuromancer", "William Gibson")),
+            vec!["Hugo Award"]
+        );
+        assert!(list
+            .awards_for(&book("Neuromancer", "Someone Else"))
+            .is_empty());
+
+        assert!(
+            AwardList::from_reader("award,year,title,author\nOscar,2000,X,Y\n".as_bytes()).is_err()
+        );
+
+        assert_eq!(
+            award_in_query("award-winning science fiction"),
+            Some(AwardQuery::Any)
+        );
+        assert_eq!(
+            award_in_query("hugo award winners"),
+            Some(AwardQuery::Named("Hugo Award"))
+        );
+        assert_eq!(
+            award_in_query("hugo-winning space opera"),
+            Some(AwardQuery::Named("Hugo Award"))
+        );
+        assert_eq!(award_in_query("books by victor hugo"), None);
+        assert_eq!(award_in_query("books about rewards"), None);
+    }
+}
@@ -0,0 +1,187 @@
+//! Content flags: material some readers want to be warned about
+//!
+//! Books are flagged from a small fixed vocabulary (violence, sexual content,
+//! ...) so safe search and the "children"/"young adult" audience filters can
+//! leave them out. A flag needs a flagged category ("erotica", "true crime") or
+//! two cue words in the description; a single "murder" in a cozy mystery's
+//! blurb isn't enough. Google Books' maturity rating, looked up during
+//! enrichment, flags sexual content on its own.
+
+use crate::models::Book;
+
+/// A flag of the vocabulary
+pub struct ContentFlag {
+    pub name: &'static str,
+    /// Words and phrases in a description that suggest the flag
+    cues: &'static [&'static str],
+    /// Category words that carry the flag on their own
+    categories: &'static [&'static str],
+}
+
+pub const CONTENT_FLAGS: &[ContentFlag] = &[
+    ContentFlag {
+        name: "violence",
+        cues: &[
+            "violence",
+            "violent",
+            "brutal",
+            "brutally",
+            "massacre",
+            "torture",
+            "tortured",
+            "gore",
+            "gory",
+            "bloodshed",
+            "slaughter",
+            "serial killer",
+        ],
+        categories: &["true crime"],
+    },
+    ContentFlag {
+        name: "sexual content",
+        cues: &[
+            "erotic",
+            "erotica",
+            "explicit",
+            "sexual",
+            "sex",
+            "steamy",
+            "sensual",
+            "seduction",
+        ],
+        categories: &["erotica", "erotic"],
+    },
+    ContentFlag {
+        name: "strong language",
+        cues: &[
+            "profanity",
+            "profane",
+            "obscene",
+            "obscenities",
+            "vulgar",
+            "foul-mouthed",
+        ],
+        categories: &[],
+    },
+    ContentFlag {
+        name: "drug use",
+        cues: &[
+            "drugs",
+            "addiction",
+            "addict",
+            "heroin",
+            "cocaine",
+            "meth",
+            "overdose",
+            "junkie",
+        ],
+        categories: &["drug abuse", "substance abuse"],
+    },
+    ContentFlag {
+        name: "self-harm",
+        cues: &["suicide", "suicidal", "self-harm", "self-destruction"],
+        categories: &["suicide"],
+    },
+    ContentFlag {
+        name: "abuse",
+        cues: &[
+            "abuse", "abused", "abusive", "rape", "raped", "incest", "molested",
+        ],
+        categories: &["child abuse", "sexual abuse"],
+    },
+];
+
+/// Flag set by a "MATURE" maturity rating
+pub const MATURE_FLAG: &str = "sexual content";
+
+/// Cue matches that flag a book
+const CUE_MATCHES: usize = 2;
+
+/// Flags of a book from its description and categories, in vocabulary order
+pub fn content_flags(book: &Book) -> Vec<String> {
+    let text = book.description.as_deref().map(words).unwrap_or_default();
+    let labels: Vec<String> = book
+        .categories
+        .iter()
+        .chain(&book.genres)
+        .map(|label| words(label))
+        .collect();
+
+    CONTENT_FLAGS
+        .iter()
+        .filter(|flag| {
+            let matches = flag
+                .cues
+                .iter()
+                .map(|cue| text.matches(&format!(" {} ", cue)).count())
+                .sum::<usize>();
+            matches >= CUE_MATCHES
+                || flag.categories.iter().any(|category| {
+                    let category = format!(" {} ", category);
+                    labels.iter().any(|label| label.contains(&category))
+                })
+        })
+        .map(|flag| flag.name.to_string())
+        .collect()
+}
+
+/// Lowercase words of `text` joined by single spaces, with a space at each end,
+/// so whole words and phrases can be found by searching for `" word "`
+fn words(text: &str) -> String {
+    format!(
+        " {} ",
+        text.to_lowercase()
+            .split(|c: char| !c.is_alphanumeric() && c != '-')
+            .filter(|word| !word.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ")
+    )
+}
+
+/// Add the flags of `book`'s description and categories to the ones it already has
+pub fn flag_book(book: &mut Book) {
+    for flag in content_flags(book) {
+        if !book.content_flags.contains(&flag) {
+            book.content_flags.push(flag);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book(description: &str, categories: &[&str]) -> Book {
+        serde_json::from_value(serde_json::json!({
+            "description": description,
+            "categories": categories,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_flags_need_two_cues_or_a_category() {
+        assert!(content_flags(&book(
+            "A cozy mystery: a murder at the village fete, and the vicar knows more than he says.",
+            &["Mystery"]
+        ))
+        .is_empty());
+        assert_eq!(
+            content_flags(&book(
+                "A brutal tale of war, told through the violence that follows one family.",
+                &["Fiction"]
+            )),
+            vec!["violence"]
+        );
+        assert_eq!(
+            content_flags(&book("", &["Fiction / Erotica"])),
+            vec!["sexual content"]
+        );
+        assert!(content_flags(&book("", &["Psychology / Neurotic Disorders"])).is_empty());
+
+        let mut flagged = book("", &["True Crime"]);
+        flagged.content_flags = vec![MATURE_FLAG.to_string()];
+        flag_book(&mut flagged);
+        assert_eq!(flagged.content_flags, vec!["sexual content", "violence"]);
+    }
+}
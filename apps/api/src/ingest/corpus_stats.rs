@@ -0,0 +1,445 @@
+//! Corpus-wide statistics snapshot
+//!
+//! Autocomplete, popular books and author matching need data about the whole
+//! catalog, which the vector index can't list cheaply. The indexer writes a small
+//! JSON snapshot (top authors and categories, a title prefix index, the rating
+//! distribution and the books added by recent runs) and the API loads it at
+//! startup instead of scanning online.
+
+use super::authors::author_key;
+use crate::error::{ApiError, Result};
+use crate::models::{truncate_description, Book, DESCRIPTION_PREVIEW_CHARS};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use utoipa::ToSchema;
+
+/// Authors kept in the snapshot, most books first
+const TOP_AUTHORS: usize = 500;
+
+/// Categories kept in the snapshot, most books first
+const TOP_CATEGORIES: usize = 200;
+
+/// Titles kept in the prefix index, most rated first
+const MAX_TITLES: usize = 50_000;
+
+/// Rating histogram buckets, each half a star wide
+const RATING_BUCKETS: usize = 10;
+
+/// Newly indexed books kept across runs, newest first
+const MAX_RECENTLY_INDEXED: usize = 200;
+
+/// An author or category and the books filed under it
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct NameCount {
+    #[schema(example = "Ursula K. Le Guin")]
+    pub name: String,
+    #[schema(example = 24)]
+    pub books: usize,
+    /// Average of the rated books' ratings, 0 if none are rated
+    #[schema(example = 4.1)]
+    pub average_rating: f32,
+}
+
+/// A title in the prefix index
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TitleEntry {
+    #[schema(example = "book_12345")]
+    pub id: Option<String>,
+    #[schema(example = "The Left Hand of Darkness")]
+    pub title: String,
+    #[schema(example = "Ursula K. Le Guin")]
+    pub author: Option<String>,
+    #[schema(example = 4.1)]
+    pub rating: f32,
+    #[schema(example = 1500)]
+    pub ratings_count: i32,
+}
+
+/// A book added to the index by a recent run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexedBook {
+    pub id: String,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    /// Preview of the description
+    pub description: Option<String>,
+    pub categories: Vec<String>,
+    /// When the run that added the book finished, in RFC3339 format
+    pub indexed_at: String,
+}
+
+/// Snapshot of the indexed catalog, written by `rab-admin index`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CorpusStats {
+    /// When the snapshot was written, in RFC3339 format
+    pub generated_at: String,
+    pub books: usize,
+    /// Distinct authors and categories in the whole catalog
+    pub authors: usize,
+    pub categories: usize,
+    pub top_authors: Vec<NameCount>,
+    pub top_categories: Vec<NameCount>,
+    /// Rated books per half-star bucket, from [0, 0.5) up to [4.5, 5]
+    pub rating_histogram: Vec<usize>,
+    /// Books without a rating
+    pub unrated: usize,
+    /// Books added by recent runs, newest first
+    #[serde(default)]
+    pub recently_indexed: Vec<IndexedBook>,
+    /// Titles sorted by [`title_key`], so a prefix is a contiguous range
+    titles: Vec<TitleEntry>,
+    /// Index into `top_authors` by [`author_key`]
+    #[serde(skip)]
+    authors_by_key: HashMap<String, usize>,
+}
+
+impl CorpusStats {
+    /// Compute the snapshot of `books`
+    pub fn build(books: &[Book]) -> Self {
+        let mut authors: HashMap<&str, RatingTally> = HashMap::new();
+        let mut categories: HashMap<String, RatingTally> = HashMap::new();
+        let mut rating_histogram = vec![0; RATING_BUCKETS];
+        let mut unrated = 0;
+
+        for book in books {
+            // The indexer stores canonical names joined with ", "
+            for author in book.author.iter().flat_map(|authors| authors.split(", ")) {
+                authors.entry(author).or_default().add(book.rating);
+            }
+            for category in &book.categories {
+                categories
+                    .entry(category.trim().to_string())
+                    .or_default()
+                    .add(book.rating);
+            }
+            if book.rating > 0.0 {
+                let bucket = ((book.rating * 2.0) as usize).min(RATING_BUCKETS - 1);
+                rating_histogram[bucket] += 1;
+            } else {
+                unrated += 1;
+            }
+        }
+
+        let mut titles: Vec<TitleEntry> = books
+            .iter()
+            .filter_map(|book| {
+                Some(TitleEntry {
+                    id: book.id.clone(),
+                    title: book
+                        .title
+                        .clone()
+                        .filter(|title| !title.trim().is_empty())?,
+                    author: book.author.clone(),
+                    rating: book.rating,
+                    ratings_count: book.ratings_count.unwrap_or(0),
+                })
+            })
+            .collect();
+        titles.sort_by(|a, b| {
+            b.ratings_count
+                .cmp(&a.ratings_count)
+                .then(b.rating.total_cmp(&a.rating))
+        });
+        titles.truncate(MAX_TITLES);
+        titles.sort_by_cached_key(|entry| title_key(&entry.title));
+
+        let mut stats = Self {
+            generated_at: Utc::now().to_rfc3339(),
+            books: books.len(),
+            authors: authors.len(),
+            categories: categories.len(),
+            top_authors: top_counts(authors, TOP_AUTHORS),
+            top_categories: top_counts(categories, TOP_CATEGORIES),
+            rating_histogram,
+            unrated,
+            recently_indexed: Vec::new(),
+            titles,
+            authors_by_key: HashMap::new(),
+        };
+        stats.index_authors();
+        stats
+    }
+
+    /// Load a snapshot written by [`CorpusStats::save`]
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = std::fs::read(path).map_err(|e| {
+            ApiError::InternalError(format!(
+                "Failed to read corpus statistics {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+        let mut stats: Self = serde_json::from_slice(&json).map_err(|e| {
+            ApiError::InvalidInput(format!(
+                "Invalid corpus statistics {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+        stats.index_authors();
+        Ok(stats)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let write_error = |e: std::io::Error| {
+            ApiError::InternalError(format!(
+                "Failed to write corpus statistics {}: {}",
+                path.display(),
+                e
+            ))
+        };
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(write_error)?;
+        }
+        // Replace the file in one step so the API never loads a partial snapshot
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, serde_json::to_vec(self)?).map_err(write_error)?;
+        std::fs::rename(&tmp_path, path).map_err(write_error)
+    }
+
+    /// Add the books a run indexed for the first time ahead of those of earlier runs
+    ///
+    /// `earlier` is the list from the previous snapshot; books indexed again keep
+    /// their newest entry.
+    pub fn record_indexed(&mut self, new_books: &[Book], earlier: Vec<IndexedBook>) {
+        let indexed_at = self.generated_at.clone();
+        let mut seen = std::collections::HashSet::new();
+        self.recently_indexed = new_books
+            .iter()
+            .filter_map(|book| {
+                let mut description = book.description.clone();
+                if let Some(description) = &mut description {
+                    truncate_description(description, DESCRIPTION_PREVIEW_CHARS);
+                }
+                Some(IndexedBook {
+                    id: book.id.clone()?,
+                    title: book.title.clone(),
+                    author: book.author.clone(),
+                    description,
+                    categories: book.categories.clone(),
+                    indexed_at: indexed_at.clone(),
+                })
+            })
+            .chain(earlier)
+            .filter(|book| seen.insert(book.id.clone()))
+            .take(MAX_RECENTLY_INDEXED)
+            .collect();
+    }
+
+    /// Titles in the prefix index, in title order
+    pub fn titles(&self) -> &[TitleEntry] {
+        &self.titles
+    }
+
+    /// Titles in the prefix index
+    pub fn title_count(&self) -> usize {
+        self.titles.len()
+    }
+
+    /// Up to `limit` titles starting with `prefix`, most rated first
+    ///
+    /// Case, punctuation and spacing are ignored.
+    pub fn titles_with_prefix(&self, prefix: &str, limit: usize) -> Vec<&TitleEntry> {
+        let prefix = title_key(prefix);
+        if prefix.is_empty() {
+            return Vec::new();
+        }
+
+        let start = self
+            .titles
+            .partition_point(|entry| title_key(&entry.title) < prefix);
+        let mut matches: Vec<&TitleEntry> = self.titles[start..]
+            .iter()
+            .take_while(|entry| title_key(&entry.title).starts_with(&prefix))
+            .collect();
+        matches.sort_by(|a, b| {
+            b.ratings_count
+                .cmp(&a.ratings_count)
+                .then(b.rating.total_cmp(&a.rating))
+        });
+        matches.truncate(limit);
+        matches
+    }
+
+    /// Titles in the prefix index by `author`, alone or with co-authors, most rated first
+    pub fn titles_by(&self, author: &str) -> Vec<&TitleEntry> {
+        let key = author_key(author);
+        if key.is_empty() {
+            return Vec::new();
+        }
+        let mut titles: Vec<&TitleEntry> = self
+            .titles
+            .iter()
+            .filter(|entry| {
+                entry
+                    .author
+                    .iter()
+                    .flat_map(|authors| authors.split(", "))
+                    .any(|name| author_key(name) == key)
+            })
+            .collect();
+        titles.sort_by(|a, b| {
+            b.ratings_count
+                .cmp(&a.ratings_count)
+                .then(b.rating.total_cmp(&a.rating))
+        });
+        titles
+    }
+
+    /// The catalog's spelling of an author named in a query
+    ///
+    /// Matches the full name ignoring case and punctuation, or a bare surname
+    /// against the most prolific author with that surname.
+    pub fn author_named(&self, name: &str) -> Option<&str> {
+        let key = author_key(name);
+        if key.is_empty() {
+            return None;
+        }
+        if let Some(&index) = self.authors_by_key.get(&key) {
+            return Some(&self.top_authors[index].name);
+        }
+        if key.contains(' ') {
+            return None;
+        }
+        // `top_authors` is sorted by book count, so the first surname match is the most prolific
+        self.top_authors
+            .iter()
+            .find(|author| author_key(&author.name).rsplit(' ').next() == Some(key.as_str()))
+            .map(|author| author.name.as_str())
+    }
+
+    fn index_authors(&mut self) {
+        self.authors_by_key = self
+            .top_authors
+            .iter()
+            .enumerate()
+            .map(|(index, author)| (author_key(&author.name), index))
+            .collect();
+    }
+}
+
+/// Book count and rating sum of an author or category
+#[derive(Default)]
+pub(crate) struct RatingTally {
+    books: usize,
+    rated: usize,
+    rating_sum: f32,
+}
+
+impl RatingTally {
+    pub(crate) fn add(&mut self, rating: f32) {
+        self.books += 1;
+        if rating > 0.0 {
+            self.rated += 1;
+            self.rating_sum += rating;
+        }
+    }
+
+    /// Average of the rated books' ratings, 0 if none are rated
+    pub(crate) fn average_rating(&self) -> f32 {
+        if self.rated > 0 {
+            self.rating_sum / self.rated as f32
+        } else {
+            0.0
+        }
+    }
+}
+
+/// The `limit` names with the most books, ties broken alphabetically
+pub(crate) fn top_counts<K: Into<String>>(
+    tallies: HashMap<K, RatingTally>,
+    limit: usize,
+) -> Vec<NameCount> {
+    let mut counts: Vec<NameCount> = tallies
+        .into_iter()
+        .map(|(name, tally)| NameCount {
+            name: name.into(),
+            books: tally.books,
+            average_rating: tally.average_rating(),
+        })
+        .filter(|count| !count.name.is_empty())
+        .collect();
+    counts.sort_by(|a, b| b.books.cmp(&a.books).then_with(|| a.name.cmp(&b.name)));
+    counts.truncate(limit);
+    counts
+}
+
+/// Comparison key for titles: lowercase alphanumeric words separated by spaces
+fn title_key(title: &str) -> String {
+    author_key(title)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn book(title: &str, author: &str, rating: f32, ratings_count: i32) -> Book {
+        serde_json::from_value(json!({
+            "id": title,
+            "title": title,
+            "author": author,
+            "rating": rating,
+            "ratings_count": ratings_count,
+            "categories": ["Fiction"],
+        }))
+        .expect("valid book")
+    }
+
+    #[test]
+    fn test_snapshot_suggests_titles_and_resolves_authors() {
+        let books = vec![
+            book("The Hobbit", "J. R. R. Tolkien", 4.3, 3000),
+            book("The Silmarillion", "J. R. R. Tolkien", 3.9, 800),
+            book("The Hunger Games", "Suzanne Collins", 4.3, 6000),
+            book("Dune", "Frank Herbert", 0.0, 10),
+        ];
+        let stats = CorpusStats::build(&books);
+
+        let titles: Vec<&str> = stats
+            .titles_with_prefix("the h", 10)
+            .iter()
+            .map(|entry| entry.title.as_str())
+            .collect();
+        assert_eq!(titles, vec!["The Hunger Games", "The Hobbit"]);
+        assert!(stats.titles_with_prefix("  ", 10).is_empty());
+
+        assert_eq!(stats.top_authors[0].name, "J. R. R. Tolkien");
+        assert_eq!(stats.author_named("tolkien"), Some("J. R. R. Tolkien"));
+        assert_eq!(
+            stats.author_named("SUZANNE COLLINS"),
+            Some("Suzanne Collins")
+        );
+        assert_eq!(stats.author_named("Anne Collins"), None);
+        let tolkien: Vec<&str> = stats
+            .titles_by("j.r.r. tolkien")
+            .iter()
+            .map(|entry| entry.title.as_str())
+            .collect();
+        assert_eq!(tolkien, vec!["The Hobbit", "The Silmarillion"]);
+
+        assert_eq!(stats.unrated, 1);
+        assert_eq!(stats.rating_histogram[8], 2);
+        assert_eq!(stats.rating_histogram[7], 1);
+
+        let mut next = CorpusStats::build(&books);
+        let earlier = vec![IndexedBook {
+            id: "Dune".to_string(),
+            title: Some("Dune".to_string()),
+            author: None,
+            description: None,
+            categories: Vec::new(),
+            indexed_at: "2024-01-01T00:00:00Z".to_string(),
+        }];
+        next.record_indexed(&books[2..], earlier);
+        let ids: Vec<&str> = next
+            .recently_indexed
+            .iter()
+            .map(|book| book.id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["The Hunger Games", "Dune"]);
+        assert_eq!(next.recently_indexed[1].indexed_at, next.generated_at);
+    }
+}
@@ -0,0 +1,92 @@
+//! Check cover thumbnails before indexing and replace the broken ones
+//!
+//! Every book's thumbnail is requested over https; books whose thumbnail is
+//! missing or doesn't answer with an image get Open Library's cover for their
+//! ISBN instead, and no thumbnail at all when that fails too, so the frontend
+//! shows its placeholder rather than a broken image.
+
+use crate::error::Result;
+use crate::models::Book;
+use crate::services::covers::{cover_candidates, cover_client, is_image, secure_url};
+use futures::stream::{self, StreamExt};
+use reqwest::Client;
+use serde::Serialize;
+
+/// Cover checks in flight at once
+pub const DEFAULT_COVER_CONCURRENCY: usize = 8;
+
+/// Counts reported after resolving covers
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct CoverStats {
+    pub checked: usize,
+    /// Thumbnails that worked as they were
+    pub kept: usize,
+    /// http thumbnails that work over https
+    pub upgraded: usize,
+    /// Books given Open Library's cover
+    pub replaced: usize,
+    /// Books left without a cover
+    pub missing: usize,
+}
+
+/// Validates thumbnails and finds replacements for broken ones
+pub struct CoverResolver {
+    client: Client,
+    concurrency: usize,
+}
+
+impl CoverResolver {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            client: cover_client()?,
+            concurrency: DEFAULT_COVER_CONCURRENCY,
+        })
+    }
+
+    /// Check up to `concurrency` covers at once
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Point every book's thumbnail at a working https cover, or at none
+    pub async fn resolve_all(&self, books: &mut [Book]) -> CoverStats {
+        let client = &self.client;
+        // Candidates are listed up front so the checks don't borrow the books and
+        // the future can be spawned, as scheduled maintenance does
+        let candidates: Vec<Vec<String>> = books.iter().map(cover_candidates).collect();
+        let resolved: Vec<(usize, Option<String>)> =
+            stream::iter(candidates.into_iter().enumerate())
+                .map(|(index, candidates)| async move {
+                    for url in candidates {
+                        if is_image(client, &url).await {
+                            return (index, Some(url));
+                        }
+                    }
+                    (index, None)
+                })
+                .buffer_unordered(self.concurrency)
+                .collect()
+                .await;
+
+        let mut stats = CoverStats::default();
+        for (index, cover) in resolved {
+            let book = &mut books[index];
+            stats.checked += 1;
+            let thumbnail = book.thumbnail.as_deref().map(str::trim);
+            match &cover {
+                None => stats.missing += 1,
+                Some(url) if thumbnail.and_then(secure_url).as_ref() == Some(url) => {
+                    if thumbnail.is_some_and(|thumbnail| thumbnail.starts_with("https://")) {
+                        stats.kept += 1;
+                    } else {
+                        stats.upgraded += 1;
+                    }
+                }
+                Some(_) => stats.replaced += 1,
+            }
+            book.thumbnail = cover;
+        }
+        stats
+    }
+}
@@ -0,0 +1,33 @@
+use super::{open_file, IngestSource, RawBookRecord, RecordIter};
+use crate::error::Result;
+use std::path::{Path, PathBuf};
+
+/// CSV export with a header row; columns are matched by name (see [`RawBookRecord`])
+pub struct CsvSource {
+    path: PathBuf,
+}
+
+impl CsvSource {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+}
+
+impl IngestSource for CsvSource {
+    fn name(&self) -> &'static str {
+        "csv"
+    }
+
+    fn records(&mut self) -> Result<RecordIter<'_>> {
+        let reader = csv::ReaderBuilder::new()
+            .flexible(true)
+            .trim(csv::Trim::All)
+            .from_reader(open_file(&self.path)?);
+
+        Ok(Box::new(reader.into_deserialize::<RawBookRecord>().map(
+            |result| result.map_err(|e| format!("could not be parsed: {}", e)),
+        )))
+    }
+}
@@ -0,0 +1,324 @@
+//! Fuzzy duplicate detection for ingested books
+//!
+//! Reprints and edition variants rarely share an exact title and author string,
+//! so books are compared on a normalized title (Levenshtein), a token-sorted
+//! author name (Jaro-Winkler) and publication year, and merged into the first
+//! occurrence when the combined similarity reaches the configured threshold.
+
+use crate::models::Book;
+use serde::Serialize;
+use std::collections::HashMap;
+use strsim::{jaro_winkler, normalized_levenshtein};
+
+/// Similarity at or above which two books are treated as the same work
+pub const DEFAULT_DEDUP_THRESHOLD: f64 = 0.85;
+
+/// Weight of each signal in the combined similarity
+const TITLE_WEIGHT: f64 = 0.6;
+const AUTHOR_WEIGHT: f64 = 0.3;
+const YEAR_WEIGHT: f64 = 0.1;
+
+/// Year gap at which the year signal stops contributing; reprints are often decades apart
+const MAX_YEAR_GAP: i32 = 20;
+
+/// Subtitle and bracket words that mark an edition rather than a different work
+const EDITION_MARKERS: &[&str] = &[
+    "edition",
+    "reprint",
+    "classics",
+    "illustrated",
+    "unabridged",
+    "abridged",
+    "paperback",
+    "hardcover",
+    "annotated",
+    "anniversary",
+    "deluxe",
+    "collector",
+];
+
+const LEADING_ARTICLES: &[&str] = &["the", "a", "an"];
+
+#[derive(Debug, Clone, Copy)]
+pub struct DedupConfig {
+    /// Combined similarity (0-1) needed to treat two books as duplicates
+    pub threshold: f64,
+    /// Fill gaps in the kept book from its duplicates
+    pub merge: bool,
+}
+
+impl Default for DedupConfig {
+    fn default() -> Self {
+        Self {
+            threshold: DEFAULT_DEDUP_THRESHOLD,
+            merge: false,
+        }
+    }
+}
+
+/// Input rows that were judged to be the same book; only the first row is kept
+#[derive(Debug, Clone, Serialize)]
+pub struct DuplicateCluster {
+    /// Normalized `title|author` of the kept book
+    pub key: String,
+    pub rows: Vec<usize>,
+    /// Original titles, aligned with `rows`
+    pub titles: Vec<String>,
+    /// Lowest similarity between the kept book and any of its duplicates
+    pub min_score: f64,
+}
+
+/// Unique books in input order, plus the clusters that were collapsed
+#[derive(Debug, Default)]
+pub struct DedupOutcome {
+    pub books: Vec<Book>,
+    pub clusters: Vec<DuplicateCluster>,
+}
+
+/// Comparable form of a book
+struct Fingerprint {
+    title: String,
+    author: String,
+    year: Option<i32>,
+}
+
+impl Fingerprint {
+    fn of(book: &Book) -> Self {
+        Self {
+            title: normalize_title(book.title.as_deref().unwrap_or_default()),
+            author: normalize_author_name(book.author.as_deref().unwrap_or_default()),
+            year: book.year.filter(|y| *y > 0),
+        }
+    }
+
+    /// Books are only compared within the same block, keyed by the first title word
+    fn block(&self) -> &str {
+        self.title.split(' ').next().unwrap_or_default()
+    }
+
+    fn similarity(&self, other: &Self) -> f64 {
+        // Edit distance rather than Jaro-Winkler, which rates "dune" close to "dune messiah"
+        let title = normalized_levenshtein(&self.title, &other.title);
+        let author = match (self.author.is_empty(), other.author.is_empty()) {
+            (true, true) => 1.0,
+            (false, false) => jaro_winkler(&self.author, &other.author),
+            _ => 0.5,
+        };
+        let year = match (self.year, other.year) {
+            (Some(a), Some(b)) => {
+                1.0 - (a - b).abs().min(MAX_YEAR_GAP) as f64 / MAX_YEAR_GAP as f64
+            }
+            _ => 1.0,
+        };
+
+        TITLE_WEIGHT * title + AUTHOR_WEIGHT * author + YEAR_WEIGHT * year
+    }
+}
+
+/// Collapse duplicate books, keeping the first occurrence of each.
+///
+/// `books` carries the input row of each book so the report can point back at it.
+pub fn deduplicate(books: Vec<(usize, Book)>, config: &DedupConfig) -> DedupOutcome {
+    let mut outcome = DedupOutcome::default();
+    let mut kept: Vec<(Fingerprint, usize)> = Vec::new();
+    let mut blocks: HashMap<String, Vec<usize>> = HashMap::new();
+    // Index into `outcome.clusters` for each kept book that has duplicates
+    let mut cluster_of: HashMap<usize, usize> = HashMap::new();
+
+    for (row, book) in books {
+        let fingerprint = Fingerprint::of(&book);
+
+        let best = blocks
+            .get(fingerprint.block())
+            .into_iter()
+            .flatten()
+            .map(|&k| (k, kept[k].0.similarity(&fingerprint)))
+            // Tolerate rounding so a threshold of exactly 0.9 accepts a 0.6 + 0.3 score
+            .filter(|(_, score)| *score + 1e-9 >= config.threshold)
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+
+        let Some((k, score)) = best else {
+            let index = kept.len();
+            blocks
+                .entry(fingerprint.block().to_string())
+                .or_default()
+                .push(index);
+            kept.push((fingerprint, row));
+            outcome.books.push(book);
+            continue;
+        };
+
+        let cluster = *cluster_of.entry(k).or_insert_with(|| {
+            let (fingerprint, first_row) = &kept[k];
+            outcome.clusters.push(DuplicateCluster {
+                key: format!("{}|{}", fingerprint.title, fingerprint.author),
+                rows: vec![*first_row],
+                titles: vec![outcome.books[k].title.clone().unwrap_or_default()],
+                min_score: 1.0,
+            });
+            outcome.clusters.len() - 1
+        });
+        let cluster = &mut outcome.clusters[cluster];
+        cluster.rows.push(row);
+        cluster.titles.push(book.title.clone().unwrap_or_default());
+        cluster.min_score = cluster.min_score.min(score);
+
+        if config.merge {
+            merge_into(&mut outcome.books[k], book);
+        }
+    }
+
+    outcome
+}
+
+/// Fill missing or weaker fields of `kept` from a duplicate
+fn merge_into(kept: &mut Book, duplicate: Book) {
+    let longer = |a: &Option<String>, b: &Option<String>| {
+        b.as_deref().map_or(0, |s| s.trim().len()) > a.as_deref().map_or(0, |s| s.trim().len())
+    };
+
+    if longer(&kept.description, &duplicate.description) {
+        kept.description = duplicate.description;
+    }
+    if kept.thumbnail.is_none() {
+        kept.thumbnail = duplicate.thumbnail;
+    }
+    if kept.isbn.is_none() {
+        kept.isbn = duplicate.isbn;
+    }
+    if kept.page_count.unwrap_or(0) <= 0 {
+        kept.page_count = duplicate.page_count;
+    }
+    // The earliest year is the closest to the original publication
+    if let Some(year) = duplicate.year.filter(|y| *y > 0) {
+        if kept
+            .year
+            .is_none_or(|kept_year| kept_year <= 0 || year < kept_year)
+        {
+            kept.year = Some(year);
+        }
+    }
+    if duplicate.ratings_count.unwrap_or(0) > kept.ratings_count.unwrap_or(0) {
+        kept.ratings_count = duplicate.ratings_count;
+        kept.rating = duplicate.rating;
+    }
+
+    let only_general = |categories: &[String]| {
+        categories.is_empty() || categories.iter().all(|c| c.eq_ignore_ascii_case("general"))
+    };
+    if only_general(&kept.categories) {
+        kept.categories = duplicate.categories;
+    } else if !only_general(&duplicate.categories) {
+        for category in duplicate.categories {
+            if !kept.categories.contains(&category) {
+                kept.categories.push(category);
+            }
+        }
+    }
+}
+
+/// Lowercase title without edition markers, bracketed notes or a leading article
+pub fn normalize_title(title: &str) -> String {
+    // Drop "(Anniversary Edition)", "[Paperback]" and the like
+    let mut stripped = String::with_capacity(title.len());
+    let mut depth = 0usize;
+    for c in title.chars() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth = depth.saturating_sub(1),
+            _ if depth == 0 => stripped.push(c),
+            _ => {}
+        }
+    }
+
+    // Keep subtitles unless they only describe the edition
+    let mut segments = stripped.split(':');
+    let mut kept = segments.next().unwrap_or_default().to_string();
+    for segment in segments {
+        let lower = segment.to_lowercase();
+        if !EDITION_MARKERS.iter().any(|marker| lower.contains(marker)) {
+            kept.push(' ');
+            kept.push_str(segment);
+        }
+    }
+
+    let words: Vec<String> = kept
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    let start = usize::from(
+        words.len() > 1
+            && words
+                .first()
+                .is_some_and(|w| LEADING_ARTICLES.contains(&w.as_str())),
+    );
+
+    words[start..].join(" ")
+}
+
+/// Author name with initials and name order folded, so "Tolkien, J.R.R." matches "J. R. R. Tolkien"
+fn normalize_author_name(author: &str) -> String {
+    let mut tokens: Vec<String> = author
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    tokens.sort();
+    tokens.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book(title: &str, author: &str, year: i32) -> Book {
+        serde_json::from_value(serde_json::json!({
+            "title": title,
+            "author": author,
+            "year": year,
+            "categories": ["General"],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_edition_variants_are_collapsed() {
+        let mut with_description = book("Dune (40th Anniversary Edition)", "Herbert, Frank", 2005);
+        with_description.description = Some("Desert planet epic.".to_string());
+
+        let outcome = deduplicate(
+            vec![
+                (1, book("Dune", "Frank Herbert", 1965)),
+                (2, book("Dune Messiah", "Frank Herbert", 1969)),
+                (3, with_description),
+                (4, book("The Dune: Deluxe Edition", "Frank Herbert", 2019)),
+            ],
+            &DedupConfig {
+                merge: true,
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(outcome.books.len(), 2);
+        assert_eq!(outcome.clusters.len(), 1);
+        assert_eq!(outcome.clusters[0].rows, vec![1, 3, 4]);
+        assert_eq!(outcome.books[0].year, Some(1965));
+        assert_eq!(
+            outcome.books[0].description.as_deref(),
+            Some("Desert planet epic.")
+        );
+    }
+
+    #[test]
+    fn test_different_authors_are_kept_apart() {
+        let outcome = deduplicate(
+            vec![
+                (1, book("Emma", "Jane Austen", 1815)),
+                (2, book("Emma", "Amy Smith", 2015)),
+            ],
+            &DedupConfig::default(),
+        );
+        assert_eq!(outcome.books.len(), 2);
+    }
+}
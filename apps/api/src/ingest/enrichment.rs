@@ -0,0 +1,560 @@
+//! Fill gaps in catalog metadata from Google Books and Open Library before embedding
+//!
+//! Lookups are keyed by ISBN, rate limited per provider and cached on disk (including
+//! misses) so re-running the indexer doesn't query the same books again.
+//!
+//! Google Books' maturity rating is kept too, as a content flag, and the country
+//! Open Library records the edition as published in as its available region.
+//! Regions alone don't make a book a candidate, since few books have them.
+
+use super::content_flags::MATURE_FLAG;
+use super::isbn::normalize_isbn;
+use super::normalize_categories;
+use super::regions::marc_country_region;
+use crate::error::{retry_after_from_headers, ApiError, Result};
+use crate::models::Book;
+use log::{debug, info, warn};
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const GOOGLE_BOOKS_URL: &str = "https://www.googleapis.com/books/v1/volumes";
+const OPEN_LIBRARY_URL: &str = "https://openlibrary.org/api/books";
+
+/// Minimum spacing between requests to each provider
+const DEFAULT_REQUEST_INTERVAL: Duration = Duration::from_millis(1000);
+
+/// How long to back off when a provider throttles us without a Retry-After header
+const DEFAULT_THROTTLE_BACKOFF_SECONDS: u64 = 30;
+
+/// Descriptions shorter than this are dropped from the embedding text, so treat them as missing
+const MIN_DESCRIPTION_LENGTH: usize = 50;
+
+/// Metadata found for one ISBN; all fields empty means nothing was found
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EnrichedMetadata {
+    pub description: Option<String>,
+    pub thumbnail: Option<String>,
+    pub page_count: Option<i32>,
+    #[serde(default)]
+    pub categories: Vec<String>,
+    /// Rated "MATURE" by Google Books
+    #[serde(default)]
+    pub mature: bool,
+    /// ISO codes of the countries the book is published in
+    #[serde(default)]
+    pub available_regions: Vec<String>,
+}
+
+impl EnrichedMetadata {
+    fn merge(&mut self, other: EnrichedMetadata) {
+        self.description = self.description.take().or(other.description);
+        self.thumbnail = self.thumbnail.take().or(other.thumbnail);
+        self.page_count = self.page_count.or(other.page_count);
+        if self.categories.is_empty() {
+            self.categories = other.categories;
+        }
+        self.mature |= other.mature;
+        for region in other.available_regions {
+            if !self.available_regions.contains(&region) {
+                self.available_regions.push(region);
+            }
+        }
+    }
+
+    fn is_complete(&self) -> bool {
+        self.description.is_some()
+            && self.thumbnail.is_some()
+            && self.page_count.is_some()
+            && !self.categories.is_empty()
+    }
+}
+
+/// Counts reported after an enrichment pass
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct EnrichmentStats {
+    /// Books with missing fields and an ISBN
+    pub candidates: usize,
+    pub cache_hits: usize,
+    pub lookups: usize,
+    /// Books that had at least one field filled in
+    pub enriched: usize,
+    pub descriptions: usize,
+    pub thumbnails: usize,
+    pub page_counts: usize,
+    pub categories: usize,
+    /// Books flagged by their maturity rating
+    pub mature: usize,
+    pub available_regions: usize,
+    pub failures: usize,
+}
+
+/// Whether a book is missing any field enrichment can fill
+pub fn needs_enrichment(book: &Book) -> bool {
+    book.isbn
+        .as_deref()
+        .is_some_and(|isbn| !isbn.trim().is_empty())
+        && (missing_description(book)
+            || book.thumbnail.is_none()
+            || book.page_count.unwrap_or(0) <= 0
+            || missing_categories(book))
+}
+
+fn missing_description(book: &Book) -> bool {
+    book.description
+        .as_deref()
+        .is_none_or(|d| d.trim().len() < MIN_DESCRIPTION_LENGTH)
+}
+
+// Rows without categories are normalized to ["General"]
+fn missing_categories(book: &Book) -> bool {
+    book.categories.is_empty()
+        || (book.categories.len() == 1 && book.categories[0].eq_ignore_ascii_case("general"))
+}
+
+/// Looks up missing metadata by ISBN
+pub struct MetadataEnricher {
+    client: Client,
+    google_api_key: Option<String>,
+    request_interval: Duration,
+    last_google_request: Option<Instant>,
+    last_open_library_request: Option<Instant>,
+    cache: HashMap<String, EnrichedMetadata>,
+    cache_path: Option<PathBuf>,
+}
+
+impl MetadataEnricher {
+    pub fn new(google_api_key: Option<String>) -> Result<Self> {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(15))
+            .user_agent("recommend-a-book-indexer/1.0")
+            .build()
+            .map_err(|e| ApiError::InternalError(format!("Failed to create HTTP client: {}", e)))?;
+
+        Ok(Self {
+            client,
+            google_api_key: google_api_key.filter(|key| !key.trim().is_empty()),
+            request_interval: DEFAULT_REQUEST_INTERVAL,
+            last_google_request: None,
+            last_open_library_request: None,
+            cache: HashMap::new(),
+            cache_path: None,
+        })
+    }
+
+    /// Load and persist lookups in the given file
+    pub fn with_cache_file(mut self, path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if path.exists() {
+            let file = File::open(&path)?;
+            self.cache = serde_json::from_reader(file)?;
+            info!(
+                "Loaded {} cached enrichment lookups from {}",
+                self.cache.len(),
+                path.display()
+            );
+        }
+        self.cache_path = Some(path);
+        Ok(self)
+    }
+
+    /// Minimum spacing between requests to each provider
+    pub fn with_request_interval(mut self, interval: Duration) -> Self {
+        self.request_interval = interval;
+        self
+    }
+
+    /// Fill missing descriptions, thumbnails, page counts and categories in place, and
+    /// regions where a lookup finds them
+    pub async fn enrich_all(&mut self, books: &mut [Book]) -> Result<EnrichmentStats> {
+        let mut stats = EnrichmentStats::default();
+        let total = books.iter().filter(|book| needs_enrichment(book)).count();
+        info!("{} books are missing metadata and have an ISBN", total);
+
+        for book in books.iter_mut().filter(|book| needs_enrichment(book)) {
+            self.enrich(book, &mut stats).await;
+
+            if stats.candidates % 100 == 0 {
+                info!(
+                    "Enrichment progress: {}/{} candidates, {} enriched",
+                    stats.candidates, total, stats.enriched
+                );
+                self.save_cache()?;
+            }
+        }
+
+        self.save_cache()?;
+        Ok(stats)
+    }
+
+    /// Fill the missing fields of one book in place, counting it in `stats`;
+    /// returns whether any field was filled
+    pub async fn enrich(&mut self, book: &mut Book, stats: &mut EnrichmentStats) -> bool {
+        if !needs_enrichment(book) {
+            return false;
+        }
+        stats.candidates += 1;
+        let isbn = normalize_isbn_key(book.isbn.as_deref().unwrap_or_default());
+
+        let metadata = match self.cache.get(&isbn) {
+            Some(metadata) => {
+                stats.cache_hits += 1;
+                metadata.clone()
+            }
+            None => {
+                stats.lookups += 1;
+                match self.lookup(&isbn).await {
+                    Ok((metadata, answered)) => {
+                        // A provider that failed is asked again on the next run
+                        if answered {
+                            self.cache.insert(isbn.clone(), metadata.clone());
+                        }
+                        metadata
+                    }
+                    Err(e) => {
+                        // Not cached, so the next run tries again
+                        warn!("Enrichment lookup for ISBN {} failed: {}", isbn, e);
+                        stats.failures += 1;
+                        return false;
+                    }
+                }
+            }
+        };
+
+        let enriched = apply(book, metadata, stats);
+        if enriched {
+            stats.enriched += 1;
+        }
+        enriched
+    }
+
+    /// Query Google Books first, then Open Library for whatever is still missing
+    ///
+    /// Also returns whether every provider queried answered. When Google Books
+    /// fails, Open Library is still asked.
+    async fn lookup(&mut self, isbn: &str) -> Result<(EnrichedMetadata, bool)> {
+        let (mut metadata, answered) = match self.lookup_google_books(isbn).await {
+            Ok(metadata) => (metadata, true),
+            Err(e) => {
+                warn!(
+                    "Google Books lookup for ISBN {} failed, trying Open Library: {}",
+                    isbn, e
+                );
+                (EnrichedMetadata::default(), false)
+            }
+        };
+        if !metadata.is_complete() {
+            metadata.merge(self.lookup_open_library(isbn).await?);
+        }
+        debug!("Enrichment for ISBN {}: {:?}", isbn, metadata);
+        Ok((metadata, answered))
+    }
+
+    async fn lookup_google_books(&mut self, isbn: &str) -> Result<EnrichedMetadata> {
+        let mut query = vec![("q", format!("isbn:{}", isbn))];
+        if let Some(key) = &self.google_api_key {
+            query.push(("key", key.clone()));
+        }
+
+        let interval = self.request_interval;
+        let body = fetch_json(
+            &self.client,
+            GOOGLE_BOOKS_URL,
+            &query,
+            &mut self.last_google_request,
+            interval,
+        )
+        .await?;
+
+        let Some(item) = body
+            .get("items")
+            .and_then(Value::as_array)
+            .and_then(|items| items.first())
+        else {
+            return Ok(EnrichedMetadata::default());
+        };
+        let info = item.get("volumeInfo").unwrap_or(&Value::Null);
+
+        let thumbnail = info.get("imageLinks").and_then(|links| {
+            links
+                .get("thumbnail")
+                .or_else(|| links.get("smallThumbnail"))
+                .and_then(Value::as_str)
+                // Google returns http links, which browsers block as mixed content
+                .map(|url| url.replacen("http://", "https://", 1))
+        });
+
+        Ok(EnrichedMetadata {
+            description: info
+                .get("description")
+                .and_then(Value::as_str)
+                .map(str::to_string),
+            thumbnail,
+            page_count: info
+                .get("pageCount")
+                .and_then(Value::as_i64)
+                .and_then(|pages| i32::try_from(pages).ok())
+                .filter(|pages| *pages > 0),
+            categories: string_list(info.get("categories")),
+            mature: info.get("maturityRating").and_then(Value::as_str) == Some("MATURE"),
+            // saleInfo.country is the caller's country, not the book's market
+            available_regions: Vec::new(),
+        })
+    }
+
+    async fn lookup_open_library(&mut self, isbn: &str) -> Result<EnrichedMetadata> {
+        let bibkey = format!("ISBN:{}", isbn);
+        let query = vec![
+            ("bibkeys", bibkey.clone()),
+            ("format", "json".to_string()),
+            ("jscmd", "details".to_string()),
+        ];
+
+        let interval = self.request_interval;
+        let body = fetch_json(
+            &self.client,
+            OPEN_LIBRARY_URL,
+            &query,
+            &mut self.last_open_library_request,
+            interval,
+        )
+        .await?;
+
+        let Some(entry) = body.get(&bibkey) else {
+            return Ok(EnrichedMetadata::default());
+        };
+        let details = entry.get("details").unwrap_or(&Value::Null);
+
+        let description = details.get("description").and_then(|d| {
+            d.as_str()
+                .or_else(|| d.get("value").and_then(Value::as_str))
+                .map(str::to_string)
+        });
+
+        // thumbnail_url ends in -S.jpg; the large cover is the same id with -L
+        let thumbnail = entry
+            .get("thumbnail_url")
+            .and_then(Value::as_str)
+            .map(|url| url.replace("-S.jpg", "-L.jpg"));
+
+        Ok(EnrichedMetadata {
+            description,
+            thumbnail,
+            page_count: details
+                .get("number_of_pages")
+                .and_then(Value::as_i64)
+                .and_then(|pages| i32::try_from(pages).ok())
+                .filter(|pages| *pages > 0),
+            categories: string_list(details.get("subjects")),
+            mature: false,
+            available_regions: details
+                .get("publish_country")
+                .and_then(Value::as_str)
+                .and_then(marc_country_region)
+                .map(|region| vec![region.to_string()])
+                .unwrap_or_default(),
+        })
+    }
+
+    fn save_cache(&self) -> Result<()> {
+        let Some(path) = &self.cache_path else {
+            return Ok(());
+        };
+        let tmp_path = path.with_extension("json.tmp");
+        serde_json::to_writer(File::create(&tmp_path)?, &self.cache)?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+}
+
+/// GET a JSON document, spacing requests and retrying once after a 429
+async fn fetch_json(
+    client: &Client,
+    url: &str,
+    query: &[(&str, String)],
+    last_request: &mut Option<Instant>,
+    interval: Duration,
+) -> Result<Value> {
+    for attempt in 0..2 {
+        if let Some(last) = last_request {
+            let elapsed = last.elapsed();
+            if elapsed < interval {
+                tokio::time::sleep(interval - elapsed).await;
+            }
+        }
+        *last_request = Some(Instant::now());
+
+        let response = client.get(url).query(query).send().await?;
+        let status = response.status();
+
+        if status == StatusCode::TOO_MANY_REQUESTS && attempt == 0 {
+            let wait = retry_after_from_headers(response.headers())
+                .unwrap_or(DEFAULT_THROTTLE_BACKOFF_SECONDS);
+            warn!(
+                "{} is throttling enrichment lookups, waiting {}s",
+                url, wait
+            );
+            tokio::time::sleep(Duration::from_secs(wait)).await;
+            continue;
+        }
+        if status == StatusCode::NOT_FOUND {
+            return Ok(Value::Null);
+        }
+        if !status.is_success() {
+            return Err(ApiError::ExternalServiceError(format!(
+                "{} returned {}",
+                url, status
+            )));
+        }
+
+        return Ok(response.json().await?);
+    }
+
+    Err(ApiError::service_unavailable(
+        format!("{} is throttling enrichment lookups", url),
+        None,
+    ))
+}
+
+/// Fill only the fields that are missing; returns whether anything changed
+fn apply(book: &mut Book, metadata: EnrichedMetadata, stats: &mut EnrichmentStats) -> bool {
+    let mut changed = false;
+
+    if missing_description(book) {
+        if let Some(description) = metadata.description.filter(|d| !d.trim().is_empty()) {
+            book.description = Some(description);
+            stats.descriptions += 1;
+            changed = true;
+        }
+    }
+    if book.thumbnail.is_none() {
+        if let Some(thumbnail) = metadata.thumbnail {
+            book.thumbnail = Some(thumbnail);
+            stats.thumbnails += 1;
+            changed = true;
+        }
+    }
+    if book.page_count.unwrap_or(0) <= 0 {
+        if let Some(pages) = metadata.page_count {
+            book.page_count = Some(pages);
+            stats.page_counts += 1;
+            changed = true;
+        }
+    }
+    if missing_categories(book) {
+        let categories = normalize_categories(&metadata.categories.join(","));
+        if !categories.is_empty() {
+            book.categories = categories;
+            stats.categories += 1;
+            changed = true;
+        }
+    }
+    if metadata.mature && !book.content_flags.iter().any(|flag| flag == MATURE_FLAG) {
+        book.content_flags.push(MATURE_FLAG.to_string());
+        stats.mature += 1;
+        changed = true;
+    }
+    if book.available_regions.is_empty() && !metadata.available_regions.is_empty() {
+        book.available_regions = metadata.available_regions;
+        stats.available_regions += 1;
+        changed = true;
+    }
+
+    changed
+}
+
+fn string_list(value: Option<&Value>) -> Vec<String> {
+    value
+        .and_then(Value::as_array)
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(|item| {
+                    item.as_str()
+                        .or_else(|| item.get("name").and_then(Value::as_str))
+                        .map(str::to_string)
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Cache key for an ISBN: the ISBN-13 when valid, so ISBN-10 and ISBN-13 share an entry
+fn normalize_isbn_key(isbn: &str) -> String {
+    normalize_isbn(isbn).unwrap_or_else(|_| {
+        isbn.chars()
+            .filter(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_uppercase()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book() -> Book {
+        serde_json::from_value(serde_json::json!({
+            "id": "9780441013593",
+            "title": "Dune",
+            "isbn": "978-0-441-01359-3",
+            "categories": ["General"],
+            "page_count": 0,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_only_missing_fields_are_filled() {
+        let mut book = book();
+        book.thumbnail = Some("https://example.com/existing.jpg".to_string());
+        assert!(needs_enrichment(&book));
+
+        let mut stats = EnrichmentStats::default();
+        let changed = apply(
+            &mut book,
+            EnrichedMetadata {
+                description: Some("A".repeat(80)),
+                thumbnail: Some("https://example.com/new.jpg".to_string()),
+                page_count: Some(412),
+                categories: vec!["Science Fiction".to_string()],
+                mature: false,
+                available_regions: vec!["US".to_string()],
+            },
+            &mut stats,
+        );
+
+        assert!(changed);
+        assert_eq!(
+            book.thumbnail.as_deref(),
+            Some("https://example.com/existing.jpg")
+        );
+        assert_eq!(book.page_count, Some(412));
+        assert_eq!(book.categories, vec!["science fiction"]);
+        assert_eq!(book.available_regions, vec!["US"]);
+        assert_eq!(stats.thumbnails, 0);
+        assert!(!needs_enrichment(&book));
+    }
+
+    #[test]
+    fn test_missing_regions_alone_dont_need_enrichment() {
+        let mut book = book();
+        book.description = Some("A".repeat(80));
+        book.thumbnail = Some("https://example.com/cover.jpg".to_string());
+        book.page_count = Some(412);
+        book.categories = vec!["science fiction".to_string()];
+        assert!(book.available_regions.is_empty());
+        assert!(!needs_enrichment(&book));
+    }
+
+    #[test]
+    fn test_isbn_cache_key_ignores_formatting() {
+        assert_eq!(normalize_isbn_key("978-0-441-01359-3"), "9780441013593");
+        assert_eq!(normalize_isbn_key("0 441 01359 7"), "9780441013593");
+        assert_eq!(normalize_isbn_key("0 441 01359 x"), "044101359X");
+    }
+}
@@ -0,0 +1,369 @@
+//! Local full-text index of the catalog
+//!
+//! The indexer writes a tantivy index of every book's title, author,
+//! categories and description next to its other snapshots, and the API opens
+//! it at startup. It answers title autocomplete, quoted phrases and the
+//! fallback search from local files, so the API still returns books when
+//! Pinecone and the embedding service are both unavailable.
+
+use crate::error::{ApiError, Result};
+use crate::ingest::corpus_stats::TitleEntry;
+use crate::models::Book;
+use crate::services::templates::STOP_WORDS;
+use std::path::Path;
+use tantivy::collector::TopDocs;
+use tantivy::query::{BooleanQuery, Occur, Query, QueryParser, RegexQuery, TermQuery};
+use tantivy::schema::{Field, IndexRecordOption, Schema, Value, STORED, STRING, TEXT};
+use tantivy::{doc, Index, IndexReader, TantivyDocument, Term};
+
+/// Memory the writer buffers documents in before flushing a segment
+const WRITER_MEMORY_BYTES: usize = 64 * 1024 * 1024;
+
+/// How much a word in each field counts, relative to one in the description
+const TITLE_BOOST: f32 = 3.0;
+const AUTHOR_BOOST: f32 = 3.0;
+const CATEGORY_BOOST: f32 = 2.0;
+
+/// Titles containing a prefix's words considered before the most rated are kept
+const TITLE_CANDIDATES: usize = 200;
+
+fn index_error(e: impl std::fmt::Display) -> ApiError {
+    ApiError::InternalError(format!("Full-text index: {}", e))
+}
+
+#[derive(Clone, Copy)]
+struct Fields {
+    id: Field,
+    title: Field,
+    author: Field,
+    categories: Field,
+    description: Field,
+    /// The book's metadata as JSON, returned with each hit
+    book: Field,
+}
+
+impl Fields {
+    fn schema() -> (Schema, Self) {
+        let mut builder = Schema::builder();
+        let fields = Self {
+            id: builder.add_text_field("id", STRING | STORED),
+            title: builder.add_text_field("title", TEXT),
+            author: builder.add_text_field("author", TEXT),
+            categories: builder.add_text_field("categories", TEXT),
+            description: builder.add_text_field("description", TEXT),
+            book: builder.add_text_field("book", STORED),
+        };
+        (builder.build(), fields)
+    }
+
+    fn of(schema: &Schema) -> Result<Self> {
+        let field = |name: &str| schema.get_field(name).map_err(index_error);
+        Ok(Self {
+            id: field("id")?,
+            title: field("title")?,
+            author: field("author")?,
+            categories: field("categories")?,
+            description: field("description")?,
+            book: field("book")?,
+        })
+    }
+}
+
+/// Phrases of a query in double quotes, e.g. "the left hand of darkness"
+pub fn quoted_phrases(query: &str) -> Vec<&str> {
+    query
+        .split('"')
+        .skip(1)
+        .step_by(2)
+        .map(str::trim)
+        .filter(|phrase| !phrase.is_empty())
+        .collect()
+}
+
+/// Lowercased words of `text`, split the way the index's tokenizer splits them
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+}
+
+/// A searchable index of the catalog
+pub struct FullTextIndex {
+    index: Index,
+    reader: IndexReader,
+    fields: Fields,
+}
+
+impl FullTextIndex {
+    /// Write an index of `books` to `dir`, replacing any index there
+    ///
+    /// The index is written next to `dir` and moved into place once complete,
+    /// so an API starting meanwhile opens the previous index, not half of one.
+    pub fn build(dir: &Path, books: &[Book]) -> Result<usize> {
+        let write_error = |e: std::io::Error| {
+            ApiError::InternalError(format!(
+                "Failed to write full-text index {}: {}",
+                dir.display(),
+                e
+            ))
+        };
+        let staging = dir.with_extension("tmp");
+        if staging.exists() {
+            std::fs::remove_dir_all(&staging).map_err(write_error)?;
+        }
+        std::fs::create_dir_all(&staging).map_err(write_error)?;
+
+        let (schema, fields) = Fields::schema();
+        let index = Index::create_in_dir(&staging, schema).map_err(index_error)?;
+        let written = Self::write(&index, fields, books)?;
+
+        if dir.exists() {
+            std::fs::remove_dir_all(dir).map_err(write_error)?;
+        }
+        std::fs::rename(&staging, dir).map_err(write_error)?;
+        Ok(written)
+    }
+
+    fn write(index: &Index, fields: Fields, books: &[Book]) -> Result<usize> {
+        let mut writer = index.writer(WRITER_MEMORY_BYTES).map_err(index_error)?;
+        let mut written = 0;
+        for book in books {
+            let Some(id) = &book.id else {
+                continue;
+            };
+            let json = serde_json::to_string(book)?;
+            let mut document = doc!(
+                fields.id => id.as_str(),
+                fields.title => book.title.as_deref().unwrap_or_default(),
+                fields.author => book.author.as_deref().unwrap_or_default(),
+                fields.description => book.description.as_deref().unwrap_or_default(),
+                fields.book => json,
+            );
+            for category in book.categories.iter().chain(&book.genres) {
+                document.add_text(fields.categories, category);
+            }
+            writer.add_document(document).map_err(index_error)?;
+            written += 1;
+        }
+        writer.commit().map_err(index_error)?;
+        writer.wait_merging_threads().map_err(index_error)?;
+        Ok(written)
+    }
+
+    /// Open the index the indexer wrote to `dir`
+    pub fn open(dir: &Path) -> Result<Self> {
+        let index = Index::open_in_dir(dir).map_err(|e| {
+            ApiError::InternalError(format!(
+                "Failed to open full-text index {}: {}",
+                dir.display(),
+                e
+            ))
+        })?;
+        Self::from_index(index)
+    }
+
+    fn from_index(index: Index) -> Result<Self> {
+        let fields = Fields::of(&index.schema())?;
+        let reader = index.reader().map_err(index_error)?;
+        Ok(Self {
+            index,
+            reader,
+            fields,
+        })
+    }
+
+    /// Books in the index
+    pub fn len(&self) -> u64 {
+        self.reader.searcher().num_docs()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Up to `limit` books best matching the words of `query`, title and author
+    /// words counting most; phrases in double quotes have to match as a whole
+    pub fn search(&self, query: &str, limit: usize) -> Result<Vec<Book>> {
+        let mut text = String::new();
+        for (index, part) in query.split('"').enumerate() {
+            if index % 2 == 1 {
+                text.push_str(&format!(
+                    " \"{}\" ",
+                    words(part).collect::<Vec<_>>().join(" ")
+                ));
+            } else {
+                for word in words(part).filter(|word| !STOP_WORDS.contains(&word.as_str())) {
+                    text.push(' ');
+                    text.push_str(&word);
+                }
+            }
+        }
+
+        let fields = self.fields;
+        let mut parser = QueryParser::for_index(
+            &self.index,
+            vec![
+                fields.title,
+                fields.author,
+                fields.categories,
+                fields.description,
+            ],
+        );
+        parser.set_field_boost(fields.title, TITLE_BOOST);
+        parser.set_field_boost(fields.author, AUTHOR_BOOST);
+        parser.set_field_boost(fields.categories, CATEGORY_BOOST);
+        // The text is only words and quotes, so there's nothing to misparse
+        let (parsed, _) = parser.parse_query_lenient(&text);
+        self.books(&*parsed, limit)
+    }
+
+    /// Up to `limit` books with quoted `phrases` in their title, author or description
+    pub fn phrase_matches(&self, phrases: &[&str], limit: usize) -> Result<Vec<Book>> {
+        if phrases.is_empty() {
+            return Ok(Vec::new());
+        }
+        let query: Vec<String> = phrases
+            .iter()
+            .map(|phrase| format!("\"{}\"", phrase))
+            .collect();
+        self.search(&query.join(" "), limit)
+    }
+
+    /// Up to `limit` titles containing the words of `prefix`, its last word
+    /// possibly unfinished, most rated first
+    pub fn titles_with_prefix(&self, prefix: &str, limit: usize) -> Result<Vec<TitleEntry>> {
+        let mut words: Vec<String> = words(prefix).collect();
+        let unfinished = match words.pop() {
+            Some(word) if !prefix.ends_with(|c: char| !c.is_alphanumeric()) => word,
+            Some(word) => {
+                words.push(word);
+                String::new()
+            }
+            None => return Ok(Vec::new()),
+        };
+
+        let title = self.fields.title;
+        let mut clauses: Vec<(Occur, Box<dyn Query>)> = words
+            .iter()
+            .map(|word| {
+                let term = Term::from_field_text(title, word);
+                let query: Box<dyn Query> =
+                    Box::new(TermQuery::new(term, IndexRecordOption::Basic));
+                (Occur::Must, query)
+            })
+            .collect();
+        if !unfinished.is_empty() {
+            let pattern = format!("{}.*", regex::escape(&unfinished));
+            let query = RegexQuery::from_pattern(&pattern, title).map_err(index_error)?;
+            clauses.push((Occur::Must, Box::new(query)));
+        }
+
+        let mut titles: Vec<TitleEntry> = self
+            .books(&BooleanQuery::new(clauses), TITLE_CANDIDATES)?
+            .into_iter()
+            .filter_map(|book| {
+                Some(TitleEntry {
+                    title: book.title?,
+                    id: book.id,
+                    author: book.author,
+                    rating: book.rating,
+                    ratings_count: book.ratings_count.unwrap_or_default(),
+                })
+            })
+            .collect();
+        titles.sort_by(|a, b| {
+            b.ratings_count
+                .cmp(&a.ratings_count)
+                .then(b.rating.total_cmp(&a.rating))
+        });
+        titles.truncate(limit);
+        Ok(titles)
+    }
+
+    fn books(&self, query: &dyn Query, limit: usize) -> Result<Vec<Book>> {
+        if limit == 0 {
+            return Ok(Vec::new());
+        }
+        let searcher = self.reader.searcher();
+        let hits = searcher
+            .search(query, &TopDocs::with_limit(limit))
+            .map_err(index_error)?;
+        let mut books = Vec::with_capacity(hits.len());
+        for (_, address) in hits {
+            let document: TantivyDocument = searcher.doc(address).map_err(index_error)?;
+            if let Some(json) = document
+                .get_first(self.fields.book)
+                .and_then(|v| v.as_str())
+            {
+                books.push(serde_json::from_str(json)?);
+            }
+        }
+        Ok(books)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book(id: &str, title: &str, description: &str, ratings_count: i32) -> Book {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "title": title,
+            "author": "Ursula K. Le Guin",
+            "description": description,
+            "categories": ["Science Fiction"],
+            "ratings_count": ratings_count,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_index_answers_keywords_phrases_and_title_prefixes() {
+        let (schema, fields) = Fields::schema();
+        let index = Index::create_in_ram(schema);
+        let books = vec![
+            book(
+                "1",
+                "The Left Hand of Darkness",
+                "Winter on the planet Gethen.",
+                900,
+            ),
+            book(
+                "2",
+                "The Lathe of Heaven",
+                "Dreams that change the world.",
+                400,
+            ),
+            book(
+                "3",
+                "The Dispossessed",
+                "An ambiguous utopia, left behind.",
+                700,
+            ),
+        ];
+        assert_eq!(FullTextIndex::write(&index, fields, &books).unwrap(), 3);
+        let index = FullTextIndex::from_index(index).unwrap();
+
+        let ids = |books: Vec<Book>| -> Vec<String> {
+            books.into_iter().filter_map(|book| book.id).collect()
+        };
+        assert_eq!(ids(index.search("books about dreams", 10).unwrap()), ["2"]);
+        assert_eq!(ids(index.search("left", 10).unwrap()), ["1", "3"]);
+        assert_eq!(
+            ids(index
+                .phrase_matches(&quoted_phrases(r#"the "left hand""#), 10)
+                .unwrap()),
+            ["1"]
+        );
+
+        let titles: Vec<String> = index
+            .titles_with_prefix("the l", 10)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.title)
+            .collect();
+        assert_eq!(titles, ["The Left Hand of Darkness", "The Lathe of Heaven"]);
+        assert!(index.titles_with_prefix("  ", 10).unwrap().is_empty());
+    }
+}
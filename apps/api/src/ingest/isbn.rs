@@ -0,0 +1,167 @@
+//! ISBN-10 / ISBN-13 parsing, checksum validation and normalization to ISBN-13
+
+use std::fmt;
+
+/// Why a value couldn't be read as an ISBN
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IsbnError {
+    /// Not 10 or 13 digits once separators are removed
+    InvalidLength(usize),
+    /// Contains something other than digits, separators or a trailing ISBN-10 `X`
+    InvalidCharacter(char),
+    /// Digits are well-formed but the check digit doesn't match
+    InvalidChecksum,
+    /// ISBN-13s must start with the 978 or 979 Bookland prefix
+    InvalidPrefix,
+}
+
+impl fmt::Display for IsbnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IsbnError::InvalidLength(len) => write!(f, "expected 10 or 13 digits, found {}", len),
+            IsbnError::InvalidCharacter(c) => write!(f, "unexpected character '{}'", c),
+            IsbnError::InvalidChecksum => write!(f, "check digit does not match"),
+            IsbnError::InvalidPrefix => write!(f, "ISBN-13 must start with 978 or 979"),
+        }
+    }
+}
+
+impl std::error::Error for IsbnError {}
+
+/// Parse an ISBN-10 or ISBN-13 in any common formatting and return it as a bare ISBN-13.
+///
+/// Hyphens, spaces and an `ISBN`/`ISBN-13:` label are ignored, so "0-441-01359-7",
+/// "ISBN 978-0-441-01359-3" and "9780441013593" all normalize to "9780441013593".
+pub fn normalize_isbn(raw: &str) -> Result<String, IsbnError> {
+    let mut chars = Vec::with_capacity(13);
+    for c in strip_label(raw.trim()).chars() {
+        match c {
+            '-' | ' ' | '\u{2010}' | '\u{2011}' | '\u{2013}' => continue,
+            '0'..='9' => chars.push(c),
+            'x' | 'X' => chars.push('X'),
+            other => return Err(IsbnError::InvalidCharacter(other)),
+        }
+    }
+
+    match chars.len() {
+        10 => isbn10_to_isbn13(&chars),
+        13 => {
+            if chars.contains(&'X') {
+                return Err(IsbnError::InvalidCharacter('X'));
+            }
+            let digits: Vec<u32> = chars.iter().filter_map(|c| c.to_digit(10)).collect();
+            if !(digits[..3] == [9, 7, 8] || digits[..3] == [9, 7, 9]) {
+                return Err(IsbnError::InvalidPrefix);
+            }
+            if isbn13_check_digit(&digits[..12]) != digits[12] {
+                return Err(IsbnError::InvalidChecksum);
+            }
+            Ok(chars.into_iter().collect())
+        }
+        len => Err(IsbnError::InvalidLength(len)),
+    }
+}
+
+/// Drop a leading "ISBN", "ISBN-10:", "ISBN13" etc. label
+fn strip_label(value: &str) -> &str {
+    let Some(rest) = value
+        .get(..4)
+        .filter(|label| label.eq_ignore_ascii_case("isbn"))
+        .map(|_| &value[4..])
+    else {
+        return value;
+    };
+
+    let rest = rest.strip_prefix('-').unwrap_or(rest);
+    let rest = rest
+        .strip_prefix("13")
+        .or_else(|| rest.strip_prefix("10"))
+        .unwrap_or(rest);
+    rest.trim_start().strip_prefix(':').unwrap_or(rest)
+}
+
+fn isbn10_to_isbn13(chars: &[char]) -> Result<String, IsbnError> {
+    // Only the check digit may be an X
+    if chars[..9].contains(&'X') {
+        return Err(IsbnError::InvalidCharacter('X'));
+    }
+
+    let digits: Vec<u32> = chars[..9].iter().filter_map(|c| c.to_digit(10)).collect();
+    let check = match chars[9] {
+        'X' => 10,
+        c => c.to_digit(10).unwrap_or_default(),
+    };
+
+    let weighted: u32 = digits
+        .iter()
+        .enumerate()
+        .map(|(i, d)| d * (10 - i as u32))
+        .sum::<u32>()
+        + check;
+    if !weighted.is_multiple_of(11) {
+        return Err(IsbnError::InvalidChecksum);
+    }
+
+    let mut isbn13: Vec<u32> = vec![9, 7, 8];
+    isbn13.extend(digits);
+    let check13 = isbn13_check_digit(&isbn13);
+    isbn13.push(check13);
+
+    Ok(isbn13
+        .into_iter()
+        .filter_map(|d| char::from_digit(d, 10))
+        .collect())
+}
+
+/// Check digit for the first 12 digits of an ISBN-13 (alternating weights 1 and 3)
+fn isbn13_check_digit(digits: &[u32]) -> u32 {
+    let sum: u32 = digits
+        .iter()
+        .enumerate()
+        .map(|(i, d)| if i % 2 == 0 { *d } else { d * 3 })
+        .sum();
+    (10 - sum % 10) % 10
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_isbn10_and_isbn13_normalize_to_the_same_value() {
+        assert_eq!(normalize_isbn("0-441-01359-7").unwrap(), "9780441013593");
+        assert_eq!(
+            normalize_isbn("978-0-441-01359-3").unwrap(),
+            "9780441013593"
+        );
+        assert_eq!(
+            normalize_isbn("ISBN 0 8044 2957 X").unwrap(),
+            "9780804429573"
+        );
+        assert_eq!(
+            normalize_isbn("isbn-13: 9780804429573").unwrap(),
+            "9780804429573"
+        );
+    }
+
+    #[test]
+    fn test_invalid_isbns_are_rejected() {
+        assert_eq!(
+            normalize_isbn("0-441-01359-8"),
+            Err(IsbnError::InvalidChecksum)
+        );
+        assert_eq!(
+            normalize_isbn("9780441013594"),
+            Err(IsbnError::InvalidChecksum)
+        );
+        assert_eq!(
+            normalize_isbn("1234567890123"),
+            Err(IsbnError::InvalidPrefix)
+        );
+        assert_eq!(normalize_isbn("12345"), Err(IsbnError::InvalidLength(5)));
+        assert_eq!(
+            normalize_isbn("B00ABC1234"),
+            Err(IsbnError::InvalidCharacter('B'))
+        );
+    }
+}
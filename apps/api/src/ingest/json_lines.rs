@@ -0,0 +1,118 @@
+use super::{open_file, record_from_json, IngestSource, RecordIter};
+use crate::error::{ApiError, Result};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+/// One JSON object per line, using the same field names as the CSV columns.
+///
+/// Numbers are accepted for numeric fields and arrays for authors/categories.
+/// Blank lines are skipped.
+pub struct JsonLinesSource {
+    path: PathBuf,
+}
+
+impl JsonLinesSource {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+}
+
+impl IngestSource for JsonLinesSource {
+    fn name(&self) -> &'static str {
+        "jsonl"
+    }
+
+    fn records(&mut self) -> Result<RecordIter<'_>> {
+        let reader = BufReader::new(open_file(&self.path)?);
+
+        Ok(Box::new(
+            reader
+                .lines()
+                .filter(|line| line.as_ref().map_or(true, |l| !l.trim().is_empty()))
+                .map(|line| {
+                    let line = line.map_err(|e| format!("could not be read: {}", e))?;
+                    let value =
+                        serde_json::from_str(&line).map_err(|e| format!("invalid JSON: {}", e))?;
+                    record_from_json(value).map_err(|e| format!("could not be parsed: {}", e))
+                }),
+        ))
+    }
+}
+
+/// A JSON array of the objects [`JsonLinesSource`] reads one per line.
+///
+/// The whole array is parsed up front, so a malformed file fails before any
+/// record is indexed; an element that isn't a book object fails on its own.
+pub struct JsonArraySource {
+    path: PathBuf,
+}
+
+impl JsonArraySource {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+}
+
+impl IngestSource for JsonArraySource {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn records(&mut self) -> Result<RecordIter<'_>> {
+        let reader = BufReader::new(open_file(&self.path)?);
+        let values: Vec<serde_json::Value> = serde_json::from_reader(reader).map_err(|e| {
+            ApiError::InvalidInput(format!(
+                "{} is not a JSON array of books ({}); use a .jsonl file or --format jsonl for one object per line",
+                self.path.display(),
+                e
+            ))
+        })?;
+
+        Ok(Box::new(values.into_iter().map(|value| {
+            record_from_json(value).map_err(|e| format!("could not be parsed: {}", e))
+        })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn titles(source: &mut dyn IngestSource) -> Vec<std::result::Result<Option<String>, String>> {
+        source
+            .records()
+            .unwrap()
+            .map(|record| record.map(|record| record.title))
+            .collect()
+    }
+
+    #[test]
+    fn test_json_arrays_are_read_element_by_element() {
+        let path = std::env::temp_dir().join(format!("rab-books-{}.json", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"[
+                {"title": "Dune", "authors": ["Frank Herbert"], "pageCount": 412},
+                "not a book"
+            ]"#,
+        )
+        .unwrap();
+
+        let records = titles(&mut JsonArraySource::new(&path));
+        assert_eq!(records[0], Ok(Some("Dune".to_string())));
+        assert!(records[1].is_err());
+
+        // One object per line is not an array
+        std::fs::write(&path, "{\"title\": \"Dune\"}\n{\"title\": \"Emma\"}\n").unwrap();
+        assert!(matches!(
+            JsonArraySource::new(&path).records(),
+            Err(ApiError::InvalidInput(_))
+        ));
+        assert_eq!(titles(&mut JsonLinesSource::new(&path)).len(), 2);
+        std::fs::remove_file(&path).ok();
+    }
+}
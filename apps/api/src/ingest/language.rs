@@ -0,0 +1,249 @@
+//! Language detection for book records
+//!
+//! Many catalog rows leave the language out, and ingestion marks those
+//! "unknown", which no language preference matches. Their language is detected
+//! from the title and description instead and stored as an ISO 639-3 code
+//! ("eng", "spa"). Sources use other codes for the same languages: Open Library
+//! dumps have MARC codes ("fre", "ger"), which mostly match ISO 639-2/B, and
+//! CSV exports often have ISO 639-1 ones ("fr"). Ingestion stores all of them
+//! as the ISO 639-3 code, so one language never ends up under several codes.
+
+use crate::models::Book;
+use whatlang::Lang;
+
+/// Stored for books whose language couldn't be detected
+pub const UNKNOWN_LANGUAGE: &str = "unknown";
+
+/// Letters below which a text says too little to tell languages apart
+const MIN_DETECTION_LETTERS: usize = 40;
+
+/// Codes of the detectable languages other than their ISO 639-3 one: ISO 639-1,
+/// ISO 639-2/B and MARC codes, and macrolanguages detected as one of their
+/// languages (Chinese as Mandarin, Persian as Iranian Persian)
+const CODE_ALIASES: &[(&str, &str)] = &[
+    ("af", "afr"),
+    ("ak", "aka"),
+    ("am", "amh"),
+    ("ar", "ara"),
+    ("az", "aze"),
+    ("be", "bel"),
+    ("bg", "bul"),
+    ("bn", "ben"),
+    ("ca", "cat"),
+    ("cs", "ces"),
+    ("da", "dan"),
+    ("de", "deu"),
+    ("el", "ell"),
+    ("en", "eng"),
+    ("eo", "epo"),
+    ("es", "spa"),
+    ("et", "est"),
+    ("fa", "pes"),
+    ("fi", "fin"),
+    ("fr", "fra"),
+    ("gu", "guj"),
+    ("he", "heb"),
+    ("hi", "hin"),
+    ("hr", "hrv"),
+    ("hu", "hun"),
+    ("hy", "hye"),
+    ("id", "ind"),
+    ("it", "ita"),
+    ("ja", "jpn"),
+    ("jv", "jav"),
+    ("ka", "kat"),
+    ("km", "khm"),
+    ("kn", "kan"),
+    ("ko", "kor"),
+    ("la", "lat"),
+    ("lt", "lit"),
+    ("lv", "lav"),
+    ("mk", "mkd"),
+    ("ml", "mal"),
+    ("mr", "mar"),
+    ("my", "mya"),
+    ("nb", "nob"),
+    ("ne", "nep"),
+    ("nl", "nld"),
+    ("no", "nob"),
+    ("or", "ori"),
+    ("pa", "pan"),
+    ("pl", "pol"),
+    ("pt", "por"),
+    ("ro", "ron"),
+    ("ru", "rus"),
+    ("si", "sin"),
+    ("sk", "slk"),
+    ("sl", "slv"),
+    ("sn", "sna"),
+    ("sr", "srp"),
+    ("sv", "swe"),
+    ("ta", "tam"),
+    ("te", "tel"),
+    ("th", "tha"),
+    ("tk", "tuk"),
+    ("tl", "tgl"),
+    ("tr", "tur"),
+    ("uk", "ukr"),
+    ("ur", "urd"),
+    ("uz", "uzb"),
+    ("vi", "vie"),
+    ("yi", "yid"),
+    ("zh", "cmn"),
+    ("zu", "zul"),
+    // ISO 639-2/B and MARC
+    ("arm", "hye"),
+    ("bur", "mya"),
+    ("chi", "cmn"),
+    ("cze", "ces"),
+    ("dut", "nld"),
+    ("esp", "epo"),
+    ("fil", "tgl"),
+    ("fre", "fra"),
+    ("geo", "kat"),
+    ("ger", "deu"),
+    ("gre", "ell"),
+    ("mac", "mkd"),
+    ("nor", "nob"),
+    ("per", "pes"),
+    ("rum", "ron"),
+    ("scc", "srp"),
+    ("scr", "hrv"),
+    ("slo", "slk"),
+    ("tag", "tgl"),
+    // Macrolanguages
+    ("fas", "pes"),
+    ("zho", "cmn"),
+];
+
+/// Whether a stored language is missing
+pub fn is_unknown(language: Option<&str>) -> bool {
+    language.map(str::trim).is_none_or(|language| {
+        language.is_empty() || language.eq_ignore_ascii_case(UNKNOWN_LANGUAGE)
+    })
+}
+
+/// ISO 639-3 code of the language the book's title and description are in,
+/// when it's detected reliably
+pub fn detect_language(book: &Book) -> Option<&'static str> {
+    let text = [book.title.as_deref(), book.description.as_deref()]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(". ");
+    if text.chars().filter(|c| c.is_alphabetic()).count() < MIN_DETECTION_LETTERS {
+        return None;
+    }
+
+    let info = whatlang::detect(&text)?;
+    info.is_reliable().then(|| info.lang().code())
+}
+
+/// Detect the language of a book that has none; returns whether one was found
+pub fn tag_language(book: &mut Book) -> bool {
+    if !is_unknown(book.language.as_deref()) {
+        return false;
+    }
+    match detect_language(book) {
+        Some(code) => {
+            book.language = Some(code.to_string());
+            true
+        }
+        None => false,
+    }
+}
+
+/// The detectable language a code or English name stands for
+fn known_language(language: &str) -> Option<Lang> {
+    let code = CODE_ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == language)
+        .map_or(language, |(_, code)| code);
+    Lang::from_code(code).or_else(|| {
+        Lang::all()
+            .iter()
+            .copied()
+            .find(|lang| lang.eng_name().eq_ignore_ascii_case(language))
+    })
+}
+
+/// A language as ingestion stores it: the ISO 639-3 code of a known language,
+/// whichever code or name it came as, else the lowercased value
+pub fn normalize_language(language: &str) -> String {
+    let language = language.trim().to_lowercase();
+    match known_language(&language) {
+        Some(lang) => lang.code().to_string(),
+        None => language,
+    }
+}
+
+/// Lowercased names a stored language goes by: the value itself and, for a
+/// known language, its ISO 639-3 code and English name
+pub fn language_names(language: &str) -> Vec<String> {
+    let language = language.trim().to_lowercase();
+    let known = known_language(&language);
+
+    let mut names = vec![language];
+    if let Some(lang) = known {
+        for name in [lang.code().to_string(), lang.eng_name().to_lowercase()] {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book(title: &str, description: &str, language: Option<&str>) -> Book {
+        serde_json::from_value(serde_json::json!({
+            "id": "b1",
+            "title": title,
+            "description": description,
+            "categories": [],
+            "language": language,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_unknown_languages_are_detected_from_the_text() {
+        let mut spanish = book(
+            "Cien años de soledad",
+            "La historia de la familia Buendía a lo largo de siete generaciones en el pueblo de Macondo, \
+             desde su fundación hasta su desaparición.",
+            Some("unknown"),
+        );
+        assert!(tag_language(&mut spanish));
+        assert_eq!(spanish.language.as_deref(), Some("spa"));
+
+        // A stored language is kept, and a bare title is too short to go on
+        let mut tagged = book("Dune", "", Some("eng"));
+        assert!(!tag_language(&mut tagged));
+        let mut short = book("Dune", "", None);
+        assert!(!tag_language(&mut short));
+        assert_eq!(short.language, None);
+    }
+
+    #[test]
+    fn test_language_names_cover_codes_and_english_names() {
+        assert_eq!(language_names("ENG"), vec!["eng", "english"]);
+        assert_eq!(language_names(" Spanish "), vec!["spanish", "spa"]);
+        assert_eq!(language_names("fre"), vec!["fre", "fra", "french"]);
+        assert_eq!(language_names("fr"), vec!["fr", "fra", "french"]);
+        assert_eq!(language_names("klingon"), vec!["klingon"]);
+    }
+
+    #[test]
+    fn test_codes_of_one_language_normalize_to_its_iso_639_3_code() {
+        for code in ["fre", "FR", "fra", "French"] {
+            assert_eq!(normalize_language(code), "fra");
+        }
+        assert_eq!(normalize_language("ger"), "deu");
+        assert_eq!(normalize_language("chi"), "cmn");
+        assert_eq!(normalize_language(" Klingon "), "klingon");
+    }
+}
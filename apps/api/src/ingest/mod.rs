@@ -0,0 +1,432 @@
+//! Catalog ingestion: reading book records from the supported dump formats
+//!
+//! Every source yields [`RawBookRecord`]s, which are normalized into the common
+//! [`Book`] model before deduplication and embedding.
+
+pub mod authors;
+pub mod awards;
+pub mod content_flags;
+pub mod corpus_stats;
+pub mod covers;
+mod csv_file;
+pub mod dedup;
+pub mod enrichment;
+pub mod fulltext;
+pub mod isbn;
+mod json_lines;
+pub mod language;
+pub mod moods;
+mod open_library;
+#[cfg(feature = "parquet")]
+mod parquet_file;
+pub mod pipeline;
+pub mod progress;
+pub mod regions;
+pub mod searchable_text;
+pub mod settings;
+pub mod taxonomy;
+
+pub use csv_file::CsvSource;
+pub use json_lines::{JsonArraySource, JsonLinesSource};
+pub use open_library::OpenLibraryDumpSource;
+#[cfg(feature = "parquet")]
+pub use parquet_file::ParquetSource;
+
+use crate::error::{ApiError, Result};
+use crate::models::Book;
+use authors::AuthorAliases;
+use isbn::normalize_isbn;
+use language::{normalize_language, UNKNOWN_LANGUAGE};
+use log::warn;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Records read from a source, with per-row failures kept so they can be reported
+pub type RecordIter<'a> = Box<dyn Iterator<Item = std::result::Result<RawBookRecord, String>> + 'a>;
+
+/// A catalog dump the indexer can read books from
+pub trait IngestSource {
+    /// Short name of the format, used in logs
+    fn name(&self) -> &'static str;
+
+    /// Read every record in the source, in order
+    fn records(&mut self) -> Result<RecordIter<'_>>;
+}
+
+/// Supported input formats, selected with `--format`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IngestFormat {
+    Csv,
+    /// One JSON object per line
+    JsonLines,
+    /// A JSON array of objects
+    Json,
+    Parquet,
+    OpenLibrary,
+}
+
+impl FromStr for IngestFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(Self::Csv),
+            "jsonl" | "ndjson" => Ok(Self::JsonLines),
+            "json" => Ok(Self::Json),
+            "parquet" => Ok(Self::Parquet),
+            "openlibrary" | "open-library" | "ol" => Ok(Self::OpenLibrary),
+            other => Err(format!(
+                "Unknown format '{}' (expected csv, jsonl, json, parquet or openlibrary)",
+                other
+            )),
+        }
+    }
+}
+
+impl IngestFormat {
+    /// Guess the format from the file name, defaulting to CSV
+    pub fn from_path(path: &Path) -> Self {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        if name.ends_with(".jsonl") || name.ends_with(".ndjson") {
+            Self::JsonLines
+        } else if name.ends_with(".json") {
+            Self::Json
+        } else if name.ends_with(".parquet") {
+            Self::Parquet
+        } else if name.starts_with("ol_dump") || name.ends_with(".txt.gz") {
+            Self::OpenLibrary
+        } else {
+            Self::Csv
+        }
+    }
+}
+
+/// Open the source for the given format.
+///
+/// `authors_dump` is only used by Open Library edition/work dumps, which reference
+/// authors by key rather than by name.
+pub fn open_source(
+    format: IngestFormat,
+    path: &Path,
+    authors_dump: Option<PathBuf>,
+) -> Result<Box<dyn IngestSource>> {
+    match format {
+        IngestFormat::Csv => Ok(Box::new(CsvSource::new(path))),
+        IngestFormat::JsonLines => Ok(Box::new(JsonLinesSource::new(path))),
+        IngestFormat::Json => Ok(Box::new(JsonArraySource::new(path))),
+        IngestFormat::OpenLibrary => {
+            let mut source = OpenLibraryDumpSource::new(path);
+            if let Some(authors_dump) = authors_dump {
+                source = source.with_authors_dump(authors_dump);
+            }
+            Ok(Box::new(source))
+        }
+        #[cfg(feature = "parquet")]
+        IngestFormat::Parquet => Ok(Box::new(ParquetSource::new(path))),
+        #[cfg(not(feature = "parquet"))]
+        IngestFormat::Parquet => Err(ApiError::InvalidInput(
+            "Parquet support is not compiled in; rebuild with `--features parquet`".to_string(),
+        )),
+    }
+}
+
+/// A catalog row before normalization, in the shape every ingest source maps into.
+///
+/// Field aliases cover the column names seen in the CSV exports we index from.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RawBookRecord {
+    #[serde(alias = "Title", alias = "title")]
+    pub title: Option<String>,
+    #[serde(
+        alias = "Authors",
+        alias = "Author",
+        alias = "authors",
+        alias = "author"
+    )]
+    pub authors: Option<String>,
+    #[serde(alias = "Description", alias = "description")]
+    pub description: Option<String>,
+    #[serde(alias = "Categories", alias = "categories")]
+    pub categories: Option<String>,
+    #[serde(alias = "isbn13", alias = "ISBN13", alias = "ISBN", alias = "isbn")]
+    pub isbn: Option<String>,
+    #[serde(alias = "published_year", alias = "publishedYear", alias = "year")]
+    pub published_year: Option<String>,
+    #[serde(alias = "ratings_count", alias = "ratingsCount")]
+    pub ratings_count: Option<String>,
+    #[serde(alias = "average_rating", alias = "rating")]
+    pub rating: Option<String>,
+    #[serde(alias = "image_url", alias = "thumbnail", alias = "imageLinks")]
+    pub thumbnail: Option<String>,
+    #[serde(alias = "page_count", alias = "pageCount")]
+    pub page_count: Option<String>,
+    #[serde(alias = "language")]
+    pub language: Option<String>,
+    #[serde(alias = "publisher")]
+    pub publisher: Option<String>,
+}
+
+fn open_file(path: &Path) -> Result<File> {
+    File::open(path)
+        .map_err(|e| ApiError::InvalidInput(format!("Failed to open {}: {}", path.display(), e)))
+}
+
+/// Build a record from a JSON object, stringifying scalar values and joining arrays.
+///
+/// JSON Lines and Parquet rows carry typed values, while the record keeps the raw
+/// text so normalization is identical for every source.
+pub(crate) fn record_from_json(
+    value: serde_json::Value,
+) -> std::result::Result<RawBookRecord, String> {
+    let serde_json::Value::Object(object) = value else {
+        return Err("expected a JSON object".to_string());
+    };
+
+    let fields: serde_json::Map<String, serde_json::Value> = object
+        .into_iter()
+        .filter_map(|(key, value)| {
+            json_to_text(value).map(|text| (key, serde_json::Value::String(text)))
+        })
+        .collect();
+
+    serde_json::from_value(serde_json::Value::Object(fields)).map_err(|e| e.to_string())
+}
+
+fn json_to_text(value: serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::Null => None,
+        serde_json::Value::String(s) => Some(s),
+        serde_json::Value::Number(n) => Some(n.to_string()),
+        serde_json::Value::Bool(b) => Some(b.to_string()),
+        serde_json::Value::Array(items) => {
+            let parts: Vec<String> = items.into_iter().filter_map(json_to_text).collect();
+            (!parts.is_empty()).then(|| parts.join(", "))
+        }
+        serde_json::Value::Object(_) => None,
+    }
+}
+
+/// Stable id for books without a valid ISBN, derived from the normalized title and author.
+///
+/// Case, punctuation and whitespace differences don't change the id, so re-ingesting
+/// the same book from another export keeps its vector.
+pub fn fallback_id(title: &str, author: Option<&str>) -> String {
+    fn fold(text: &str) -> String {
+        text.split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(str::to_lowercase)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    let mut hasher = Sha256::new();
+    hasher.update(fold(title).as_bytes());
+    hasher.update(b"|");
+    hasher.update(fold(author.unwrap_or_default()).as_bytes());
+    let digest = hasher.finalize();
+
+    format!("book-{:x}", digest)[..21].to_string()
+}
+
+impl RawBookRecord {
+    /// Normalize into a `Book`, or `None` when the row has no title
+    pub fn into_book(self, row_index: usize) -> Option<Book> {
+        // Require at least title
+        let title = self.title?.trim().to_string();
+        if title.is_empty() {
+            return None;
+        }
+
+        // Clean and validate author
+        let author = self.authors.as_ref().map(|a| normalize_author(a));
+        if author.as_ref().is_none_or(|a| a.is_empty()) {
+            warn!("Row {}: Book '{}' has no valid author", row_index, title);
+        }
+
+        // Process categories
+        let categories = self
+            .categories
+            .as_ref()
+            .map(|c| normalize_categories(c))
+            .unwrap_or_else(|| vec!["General".to_string()]);
+
+        // Books are keyed by ISBN-13 so ISBN-10 and ISBN-13 rows of the same edition collide
+        let isbn = self
+            .isbn
+            .as_deref()
+            .map(str::trim)
+            .filter(|isbn| !isbn.is_empty())
+            .and_then(|raw| match normalize_isbn(raw) {
+                Ok(isbn) => Some(isbn),
+                Err(e) => {
+                    warn!("Row {}: Ignoring invalid ISBN '{}': {}", row_index, raw, e);
+                    None
+                }
+            });
+        let id = isbn
+            .clone()
+            .unwrap_or_else(|| fallback_id(&title, author.as_deref()));
+
+        Some(Book {
+            id: Some(id),
+            title: Some(title),
+            author,
+            description: self.description.filter(|d| !d.trim().is_empty()),
+            categories,
+            genres: vec![],
+            moods: vec![],
+            awards: vec![],
+            settings: vec![],
+            content_flags: vec![],
+            available_regions: vec![],
+            thumbnail: self.thumbnail.filter(|t| !t.trim().is_empty()),
+            rating: self.rating.and_then(|r| r.parse().ok()).unwrap_or(0.0),
+            year: self.published_year.and_then(|y| y.parse().ok()),
+            isbn,
+            page_count: self.page_count.and_then(|p| p.parse().ok()).or(Some(0)),
+            ratings_count: self.ratings_count.and_then(|r| r.parse().ok()),
+            language: self
+                .language
+                .filter(|l| !l.trim().is_empty())
+                .map(|l| normalize_language(&l))
+                .or(Some(UNKNOWN_LANGUAGE.to_string())),
+            publisher: self
+                .publisher
+                .filter(|p| !p.trim().is_empty())
+                .or(Some("unknown".to_string())),
+            version: None,
+            updated_at: None,
+            relevance_indicators: vec![],
+            confidence_score: 0.0,
+            explanation: None,
+        })
+    }
+
+    /// Non-fatal problems with a row that will still be indexed
+    pub fn problems(&self) -> Vec<String> {
+        fn unparsable<T: std::str::FromStr>(value: &Option<String>) -> bool {
+            value
+                .as_deref()
+                .map(str::trim)
+                .is_some_and(|v| !v.is_empty() && v.parse::<T>().is_err())
+        }
+
+        let mut problems = Vec::new();
+
+        if self
+            .authors
+            .as_deref()
+            .is_none_or(|a| normalize_author(a).is_empty())
+        {
+            problems.push("no valid author".to_string());
+        }
+        if self
+            .description
+            .as_deref()
+            .is_none_or(|d| d.trim().is_empty())
+        {
+            problems.push("no description".to_string());
+        }
+        if let Some(raw) = self.isbn.as_deref().filter(|i| !i.trim().is_empty()) {
+            if let Err(e) = normalize_isbn(raw) {
+                problems.push(format!("invalid ISBN '{}': {}", raw.trim(), e));
+            }
+        }
+        if unparsable::<i32>(&self.published_year) {
+            problems.push(format!(
+                "unparsable year '{}'",
+                self.published_year.as_deref().unwrap_or_default()
+            ));
+        }
+        if unparsable::<f32>(&self.rating) {
+            problems.push(format!(
+                "unparsable rating '{}'",
+                self.rating.as_deref().unwrap_or_default()
+            ));
+        } else if let Some(rating) = self
+            .rating
+            .as_deref()
+            .and_then(|r| r.trim().parse::<f32>().ok())
+        {
+            if !(0.0..=5.0).contains(&rating) {
+                problems.push(format!("rating {} outside 0-5", rating));
+            }
+        }
+        if unparsable::<i32>(&self.page_count) {
+            problems.push(format!(
+                "unparsable page count '{}'",
+                self.page_count.as_deref().unwrap_or_default()
+            ));
+        }
+        if unparsable::<i32>(&self.ratings_count) {
+            problems.push(format!(
+                "unparsable ratings count '{}'",
+                self.ratings_count.as_deref().unwrap_or_default()
+            ));
+        }
+
+        problems
+    }
+}
+
+/// Clean and normalize author names for better matching
+///
+/// Only formats the names; aliases are resolved later with [`AuthorAliases`] so
+/// fallback ids don't change when the alias map does.
+pub fn normalize_author(author: &str) -> String {
+    AuthorAliases::default().canonicalize(author)
+}
+
+/// Extract and clean categories
+pub fn normalize_categories(categories: &str) -> Vec<String> {
+    categories
+        .trim()
+        .to_lowercase()
+        .split(&['&', '|', ';', ','][..])
+        .map(|cat| {
+            cat.trim()
+                .chars()
+                .filter(|c| c.is_alphanumeric() || c.is_whitespace() || *c == '-')
+                .collect::<String>()
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .filter(|cat| !cat.is_empty() && cat.len() > 1)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(isbn: &str, title: &str) -> RawBookRecord {
+        serde_json::from_value(serde_json::json!({
+            "title": title,
+            "authors": "Frank Herbert",
+            "isbn": isbn,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_ids_are_stable_across_isbn_formats_and_reingestion() {
+        let isbn10 = record("0-441-01359-7", "Dune").into_book(1).unwrap();
+        let isbn13 = record("9780441013593", "Dune").into_book(2).unwrap();
+        assert_eq!(isbn10.id.as_deref(), Some("9780441013593"));
+        assert_eq!(isbn10.id, isbn13.id);
+
+        let invalid = record("0-441-01359-8", "Dune");
+        assert_eq!(invalid.problems().len(), 2);
+        let book = invalid.into_book(3).unwrap();
+        assert_eq!(book.isbn, None);
+        assert_eq!(book.id, Some(fallback_id("DUNE ", Some("frank  herbert"))));
+    }
+}
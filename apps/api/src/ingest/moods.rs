@@ -0,0 +1,275 @@
+//! Mood tags: how a book feels to read, from its description
+//!
+//! Books are tagged with moods from a small fixed vocabulary so queries like
+//! "cozy uplifting reads" can prefer books that actually are. Two signals are
+//! combined: cue words in the description, and how close the book's embedding
+//! is to a short prototype description of each mood. Either is enough when it
+//! is strong; a weaker match needs both.
+//!
+//! Queries are matched against the same vocabulary, with common synonyms
+//! ("humorous", "feel-good") mapped onto it.
+
+use crate::error::Result;
+use crate::ml::embedder::Embedder;
+use crate::models::Book;
+use crate::services::personalization::cosine_similarity;
+use std::collections::HashMap;
+
+/// A mood of the vocabulary
+pub struct Mood {
+    pub name: &'static str,
+    /// Words and phrases in a description that suggest the mood
+    cues: &'static [&'static str],
+    /// Query words meaning the mood, besides its name
+    synonyms: &'static [&'static str],
+    /// Description of a typical book with the mood, embedded for the classifier
+    prototype: &'static str,
+}
+
+pub const MOODS: &[Mood] = &[
+    Mood {
+        name: "cozy",
+        cues: &["cozy", "cosy", "comforting", "tea", "bakery", "village", "small town", "snug", "charming"],
+        synonyms: &["comfy", "comforting", "snug"],
+        prototype: "A cozy, comforting story set in a charming small town, full of warmth, tea and friendship.",
+    },
+    Mood {
+        name: "uplifting",
+        cues: &["uplifting", "hopeful", "inspiring", "inspirational", "feel-good", "joyful", "triumph", "optimistic"],
+        synonyms: &["feel-good", "feelgood", "hopeful", "happy", "optimistic", "inspiring"],
+        prototype: "An uplifting, hopeful and inspiring story that leaves the reader happy and optimistic.",
+    },
+    Mood {
+        name: "heartwarming",
+        cues: &["heartwarming", "heart-warming", "tender", "touching", "kindness", "found family", "sweet"],
+        synonyms: &["touching", "wholesome", "sweet"],
+        prototype: "A heartwarming, tender story about kindness, love and found family.",
+    },
+    Mood {
+        name: "funny",
+        cues: &["funny", "hilarious", "humor", "humour", "comic", "comedy", "witty", "laugh"],
+        synonyms: &["humorous", "hilarious", "comic", "comedic", "witty", "fun"],
+        prototype: "A hilarious, witty comedy full of jokes that will make you laugh out loud.",
+    },
+    Mood {
+        name: "whimsical",
+        cues: &["whimsical", "quirky", "playful", "fanciful", "magical", "fairy tale", "enchanting"],
+        synonyms: &["playful", "quirky", "fanciful"],
+        prototype: "A whimsical, playful and quirky fairy tale full of magic and wonder.",
+    },
+    Mood {
+        name: "dark",
+        cues: &["dark", "sinister", "disturbing", "haunting", "chilling", "macabre", "bleak", "menacing"],
+        synonyms: &["sinister", "disturbing", "bleak", "creepy"],
+        prototype: "A dark, disturbing and sinister story of menace, death and haunting dread.",
+    },
+    Mood {
+        name: "gritty",
+        cues: &["gritty", "brutal", "violent", "raw", "unflinching", "harsh", "underworld"],
+        synonyms: &["brutal", "violent", "raw"],
+        prototype: "A gritty, brutal and unflinching story of violence in a harsh criminal underworld.",
+    },
+    Mood {
+        name: "melancholic",
+        cues: &["melancholy", "melancholic", "grief", "loss", "mourning", "lonely", "sorrow", "elegiac"],
+        synonyms: &["melancholy", "sad", "depressing", "gloomy", "somber", "sombre", "pessimistic"],
+        prototype: "A melancholy, sorrowful meditation on grief, loss and loneliness.",
+    },
+    Mood {
+        name: "bittersweet",
+        cues: &["bittersweet", "poignant", "nostalgic", "wistful", "coming-of-age"],
+        synonyms: &["poignant", "nostalgic", "wistful"],
+        prototype: "A bittersweet, poignant and nostalgic story of love and loss, joy mixed with sadness.",
+    },
+    Mood {
+        name: "suspenseful",
+        cues: &["suspense", "suspenseful", "thrilling", "gripping", "tense", "page-turner", "twist", "race against time"],
+        synonyms: &["tense", "intense", "thrilling", "gripping", "edge-of-your-seat"],
+        prototype: "A gripping, suspenseful thriller full of tension and twists, a race against time.",
+    },
+    Mood {
+        name: "peaceful",
+        cues: &["peaceful", "quiet", "gentle", "serene", "meditative", "calm", "slow-paced"],
+        synonyms: &["relaxing", "calm", "gentle", "serene", "quiet"],
+        prototype: "A gentle, peaceful and meditative story with a calm, quiet and serene pace.",
+    },
+];
+
+/// Cue matches that tag a mood on their own
+const KEYWORD_ONLY_MATCHES: usize = 2;
+
+/// Prototype similarity that tags a mood on its own
+const CLASSIFIER_ONLY_SIMILARITY: f32 = 0.5;
+
+/// Prototype similarity that tags a mood together with a single cue match
+const CLASSIFIER_WITH_KEYWORD_SIMILARITY: f32 = 0.35;
+
+/// Most moods tagged on one book, strongest first
+pub const MAX_MOODS_PER_BOOK: usize = 3;
+
+/// Every query word naming a mood: the names of the vocabulary and their synonyms
+pub fn mood_words() -> impl Iterator<Item = &'static str> {
+    MOODS
+        .iter()
+        .flat_map(|mood| std::iter::once(mood.name).chain(mood.synonyms.iter().copied()))
+}
+
+/// The mood a query word names, e.g. "humorous" -> "funny"
+pub fn mood_named(word: &str) -> Option<&'static str> {
+    let word = word.trim().to_lowercase();
+    MOODS
+        .iter()
+        .find(|mood| mood.name == word || mood.synonyms.contains(&word.as_str()))
+        .map(|mood| mood.name)
+}
+
+/// Moods named in a query, in query order
+pub fn moods_in_query(query: &str) -> Vec<String> {
+    let mut moods: Vec<String> = Vec::new();
+    for word in query
+        .split(|c: char| !c.is_alphanumeric() && c != '-')
+        .filter(|word| !word.is_empty())
+    {
+        if let Some(mood) = mood_named(word) {
+            if !moods.iter().any(|m| m == mood) {
+                moods.push(mood.to_string());
+            }
+        }
+    }
+    moods
+}
+
+/// Cue matches of each mood in a description
+pub fn keyword_matches(description: &str) -> HashMap<&'static str, usize> {
+    let text = format!(
+        " {} ",
+        description
+            .to_lowercase()
+            .split(|c: char| !c.is_alphanumeric() && c != '-')
+            .filter(|word| !word.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ")
+    );
+
+    MOODS
+        .iter()
+        .filter_map(|mood| {
+            let matches = mood
+                .cues
+                .iter()
+                .map(|cue| text.matches(&format!(" {} ", cue)).count())
+                .sum::<usize>();
+            (matches > 0).then_some((mood.name, matches))
+        })
+        .collect()
+}
+
+/// Moods of a book from its cue matches and, when available, prototype similarities
+pub fn combine_signals(
+    keyword_matches: &HashMap<&'static str, usize>,
+    similarities: &HashMap<&'static str, f32>,
+) -> Vec<String> {
+    let mut tagged: Vec<(&'static str, f32)> = MOODS
+        .iter()
+        .filter_map(|mood| {
+            let matches = keyword_matches.get(mood.name).copied().unwrap_or(0);
+            let similarity = similarities.get(mood.name).copied().unwrap_or(0.0);
+            let tagged = matches >= KEYWORD_ONLY_MATCHES
+                || similarity >= CLASSIFIER_ONLY_SIMILARITY
+                || (matches >= 1 && similarity >= CLASSIFIER_WITH_KEYWORD_SIMILARITY);
+            tagged.then_some((mood.name, matches as f32 * 0.1 + similarity))
+        })
+        .collect();
+    tagged.sort_by(|a, b| b.1.total_cmp(&a.1));
+    tagged
+        .into_iter()
+        .take(MAX_MOODS_PER_BOOK)
+        .map(|(mood, _)| mood.to_string())
+        .collect()
+}
+
+/// Compares book embeddings against an embedded prototype of each mood
+pub struct MoodClassifier {
+    prototypes: Vec<(&'static str, Vec<f32>)>,
+}
+
+impl MoodClassifier {
+    /// Embed the mood prototypes with the model the books are embedded with
+    pub async fn new(embedder: &dyn Embedder) -> Result<Self> {
+        let texts: Vec<String> = MOODS
+            .iter()
+            .map(|mood| mood.prototype.to_string())
+            .collect();
+        let embeddings = embedder.encode_batch(&texts).await?;
+        Ok(Self {
+            prototypes: MOODS.iter().map(|mood| mood.name).zip(embeddings).collect(),
+        })
+    }
+
+    /// Similarity of a book's embedding to each mood's prototype
+    pub fn similarities(&self, embedding: &[f32]) -> HashMap<&'static str, f32> {
+        self.prototypes
+            .iter()
+            .map(|(mood, prototype)| (*mood, cosine_similarity(embedding, prototype)))
+            .collect()
+    }
+}
+
+/// Moods of a book, from its description and, with a classifier, its embedding
+pub fn tag_moods(
+    book: &Book,
+    embedding: Option<&[f32]>,
+    classifier: Option<&MoodClassifier>,
+) -> Vec<String> {
+    let matches = book
+        .description
+        .as_deref()
+        .map(keyword_matches)
+        .unwrap_or_default();
+    let similarities = match (embedding, classifier) {
+        (Some(embedding), Some(classifier)) => classifier.similarities(embedding),
+        _ => HashMap::new(),
+    };
+    combine_signals(&matches, &similarities)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_queries_and_descriptions_share_the_vocabulary() {
+        assert_eq!(
+            moods_in_query("cozy, uplifting reads"),
+            vec!["cozy", "uplifting"]
+        );
+        assert_eq!(
+            moods_in_query("something humorous and feel-good"),
+            vec!["funny", "uplifting"]
+        );
+        assert!(moods_in_query("books about dragons").is_empty());
+        // "light" is about how easy a book reads, not how it feels
+        assert!(moods_in_query("light reads").is_empty());
+
+        let matches = keyword_matches(
+            "A cozy mystery in a small town bakery, where tea is served and murder is solved.",
+        );
+        assert_eq!(matches.get("cozy"), Some(&4));
+
+        // Two cue matches are enough; one needs the classifier to agree
+        let gripping = HashMap::from([("suspenseful", 1)]);
+        assert_eq!(combine_signals(&matches, &HashMap::new()), vec!["cozy"]);
+        assert!(combine_signals(&gripping, &HashMap::new()).is_empty());
+        assert_eq!(
+            combine_signals(&gripping, &HashMap::from([("suspenseful", 0.4)])),
+            vec!["suspenseful"]
+        );
+        assert_eq!(
+            combine_signals(
+                &HashMap::new(),
+                &HashMap::from([("dark", 0.6), ("funny", 0.2)])
+            ),
+            vec!["dark"]
+        );
+    }
+}
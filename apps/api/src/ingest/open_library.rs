@@ -0,0 +1,244 @@
+use super::{open_file, IngestSource, RawBookRecord, RecordIter};
+use crate::error::Result;
+use flate2::read::MultiGzDecoder;
+use log::info;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+const COVER_URL_PREFIX: &str = "https://covers.openlibrary.org/b/id/";
+
+/// Open Library bulk dump (`ol_dump_editions_*.txt.gz` or `ol_dump_works_*.txt.gz`).
+///
+/// Each line is `type \t key \t revision \t last_modified \t json`. Editions and works
+/// are mapped into records; every other record type is skipped. Dumps only reference
+/// authors by key, so names are resolved from an optional authors dump.
+pub struct OpenLibraryDumpSource {
+    path: PathBuf,
+    authors_dump: Option<PathBuf>,
+}
+
+impl OpenLibraryDumpSource {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            authors_dump: None,
+        }
+    }
+
+    /// Resolve author keys to names using an `ol_dump_authors_*` file
+    pub fn with_authors_dump(mut self, path: impl AsRef<Path>) -> Self {
+        self.authors_dump = Some(path.as_ref().to_path_buf());
+        self
+    }
+}
+
+impl IngestSource for OpenLibraryDumpSource {
+    fn name(&self) -> &'static str {
+        "openlibrary"
+    }
+
+    fn records(&mut self) -> Result<RecordIter<'_>> {
+        let authors = match &self.authors_dump {
+            Some(path) => load_author_names(path)?,
+            None => HashMap::new(),
+        };
+
+        Ok(Box::new(dump_lines(&self.path)?.filter_map(move |line| {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => return Some(Err(format!("could not be read: {}", e))),
+            };
+            parse_dump_line(&line, &authors).transpose()
+        })))
+    }
+}
+
+/// Lines of a dump file, transparently decompressing `.gz` files
+fn dump_lines(path: &Path) -> Result<std::io::Lines<BufReader<Box<dyn Read>>>> {
+    let file = open_file(path)?;
+    let reader: Box<dyn Read> = if path.extension().is_some_and(|ext| ext == "gz") {
+        Box::new(MultiGzDecoder::new(file))
+    } else {
+        Box::new(file)
+    };
+    Ok(BufReader::new(reader).lines())
+}
+
+fn load_author_names(path: &Path) -> Result<HashMap<String, String>> {
+    info!("Loading Open Library author names from {}", path.display());
+
+    let mut names = HashMap::new();
+    for line in dump_lines(path)?.map_while(std::result::Result::ok) {
+        let mut columns = line.splitn(5, '\t');
+        if columns.next() != Some("/type/author") {
+            continue;
+        }
+        let (Some(key), Some(json)) = (columns.next(), columns.nth(2)) else {
+            continue;
+        };
+        if let Some(name) = serde_json::from_str::<Value>(json)
+            .ok()
+            .and_then(|author| author.get("name")?.as_str().map(str::to_string))
+        {
+            names.insert(key.to_string(), name);
+        }
+    }
+
+    info!("Loaded {} Open Library author names", names.len());
+    Ok(names)
+}
+
+/// Parse one dump line; `Ok(None)` for record types that aren't books
+fn parse_dump_line(
+    line: &str,
+    authors: &HashMap<String, String>,
+) -> std::result::Result<Option<RawBookRecord>, String> {
+    let mut columns = line.splitn(5, '\t');
+    let record_type = columns.next().unwrap_or_default();
+    if record_type != "/type/edition" && record_type != "/type/work" {
+        return Ok(None);
+    }
+
+    let json = columns
+        .nth(3)
+        .ok_or_else(|| "expected 5 tab-separated columns".to_string())?;
+    let doc: Value = serde_json::from_str(json).map_err(|e| format!("invalid JSON: {}", e))?;
+
+    Ok(Some(record_from_document(&doc, authors)))
+}
+
+fn record_from_document(doc: &Value, authors: &HashMap<String, String>) -> RawBookRecord {
+    let text = |field: &str| doc.get(field).and_then(Value::as_str).map(str::to_string);
+    let first_text = |field: &str| {
+        doc.get(field)
+            .and_then(Value::as_array)
+            .and_then(|items| items.first())
+            .and_then(Value::as_str)
+            .map(str::to_string)
+    };
+
+    let title = text("title").map(|title| match text("subtitle") {
+        Some(subtitle) => format!("{}: {}", title, subtitle),
+        None => title,
+    });
+
+    // Editions list `{"key": ...}`, works list `{"author": {"key": ...}}`
+    let author_names: Vec<&str> = doc
+        .get("authors")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|author| {
+            author
+                .get("key")
+                .or_else(|| author.get("author")?.get("key"))
+                .and_then(Value::as_str)
+        })
+        .filter_map(|key| authors.get(key).map(String::as_str))
+        .collect();
+    let authors = if author_names.is_empty() {
+        text("by_statement")
+    } else {
+        Some(author_names.join(", "))
+    };
+
+    // Descriptions are either a plain string or `{"type": "/type/text", "value": ...}`
+    let description = doc.get("description").and_then(|d| {
+        d.as_str()
+            .or_else(|| d.get("value").and_then(Value::as_str))
+            .map(str::to_string)
+    });
+
+    let categories = doc
+        .get("subjects")
+        .and_then(Value::as_array)
+        .map(|subjects| {
+            subjects
+                .iter()
+                .filter_map(Value::as_str)
+                .collect::<Vec<_>>()
+                .join(", ")
+        })
+        .filter(|subjects| !subjects.is_empty());
+
+    let published_year = text("publish_date")
+        .or_else(|| text("first_publish_date"))
+        .and_then(|date| extract_year(&date));
+
+    let thumbnail = doc
+        .get("covers")
+        .and_then(Value::as_array)
+        .and_then(|covers| covers.iter().filter_map(Value::as_i64).find(|id| *id > 0))
+        .map(|id| format!("{}{}-L.jpg", COVER_URL_PREFIX, id));
+
+    let language = doc
+        .get("languages")
+        .and_then(Value::as_array)
+        .and_then(|languages| languages.first())
+        .and_then(|language| language.get("key"))
+        .and_then(Value::as_str)
+        .map(|key| key.trim_start_matches("/languages/").to_string());
+
+    RawBookRecord {
+        title,
+        authors,
+        description,
+        categories,
+        isbn: first_text("isbn_13").or_else(|| first_text("isbn_10")),
+        published_year,
+        ratings_count: None,
+        rating: None,
+        thumbnail,
+        page_count: doc
+            .get("number_of_pages")
+            .and_then(Value::as_i64)
+            .map(|pages| pages.to_string()),
+        language,
+        publisher: first_text("publishers"),
+    }
+}
+
+/// First four-digit run in a free-form date such as "June 1965" or "1965-06-01"
+fn extract_year(date: &str) -> Option<String> {
+    date.as_bytes()
+        .windows(4)
+        .position(|window| window.iter().all(u8::is_ascii_digit))
+        .map(|start| date[start..start + 4].to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edition_line_maps_to_record() {
+        let authors = HashMap::from([("/authors/OL1A".to_string(), "Frank Herbert".to_string())]);
+        let line = "/type/edition\t/books/OL1M\t3\t2020-01-01T00:00:00\t\
+                    {\"title\": \"Dune\", \"authors\": [{\"key\": \"/authors/OL1A\"}], \
+                    \"publish_date\": \"August 1965\", \"isbn_13\": [\"9780441013593\"], \
+                    \"number_of_pages\": 412, \"covers\": [-1, 42], \
+                    \"languages\": [{\"key\": \"/languages/eng\"}], \
+                    \"description\": {\"type\": \"/type/text\", \"value\": \"Desert planet.\"}}";
+
+        let record = parse_dump_line(line, &authors).unwrap().unwrap();
+        assert_eq!(record.title.as_deref(), Some("Dune"));
+        assert_eq!(record.authors.as_deref(), Some("Frank Herbert"));
+        assert_eq!(record.published_year.as_deref(), Some("1965"));
+        assert_eq!(record.isbn.as_deref(), Some("9780441013593"));
+        assert_eq!(record.page_count.as_deref(), Some("412"));
+        assert_eq!(record.language.as_deref(), Some("eng"));
+        assert_eq!(record.description.as_deref(), Some("Desert planet."));
+        assert_eq!(
+            record.thumbnail.as_deref(),
+            Some("https://covers.openlibrary.org/b/id/42-L.jpg")
+        );
+    }
+
+    #[test]
+    fn test_non_book_records_are_skipped() {
+        let line = "/type/redirect\t/books/OL2M\t1\t2020-01-01T00:00:00\t{}";
+        assert!(parse_dump_line(line, &HashMap::new()).unwrap().is_none());
+    }
+}
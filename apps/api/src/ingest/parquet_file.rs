@@ -0,0 +1,44 @@
+use super::{open_file, record_from_json, IngestSource, RecordIter};
+use crate::error::{ApiError, Result};
+use parquet::file::reader::SerializedFileReader;
+use parquet::record::reader::RowIter;
+use std::path::{Path, PathBuf};
+
+/// Parquet file whose column names match the CSV columns.
+///
+/// Only built with the `parquet` feature, since the parquet crate is a heavy dependency.
+pub struct ParquetSource {
+    path: PathBuf,
+}
+
+impl ParquetSource {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+}
+
+impl IngestSource for ParquetSource {
+    fn name(&self) -> &'static str {
+        "parquet"
+    }
+
+    fn records(&mut self) -> Result<RecordIter<'_>> {
+        let reader = SerializedFileReader::new(open_file(&self.path)?).map_err(|e| {
+            ApiError::InvalidInput(format!(
+                "Failed to read Parquet file {}: {}",
+                self.path.display(),
+                e
+            ))
+        })?;
+
+        Ok(Box::new(RowIter::from_file_into(Box::new(reader)).map(
+            |row| {
+                let row = row.map_err(|e| format!("could not be read: {}", e))?;
+                record_from_json(row.to_json_value())
+                    .map_err(|e| format!("could not be parsed: {}", e))
+            },
+        )))
+    }
+}
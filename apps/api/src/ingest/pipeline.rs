@@ -0,0 +1,452 @@
+//! Concurrent embed → upsert pipeline used by the indexer
+//!
+//! Batches flow through bounded channels: a producer splits the books into batches,
+//! a pool of embedding workers turns them into vectors and a pool of upsert workers
+//! writes them to Pinecone. Each upstream has its own [`AdaptiveThrottle`] that slows
+//! every worker down when that upstream starts returning 429s. Books are tagged
+//! with moods as they are embedded, since the classifier needs their embeddings.
+//! Upserts replace a book's metadata, so each batch's stored metadata is read
+//! first: hidden books keep their flag and changed books get the next version.
+
+use crate::error::{ApiError, Result};
+use crate::ingest::moods::{tag_moods, MoodClassifier};
+use crate::ml::huggingface_embedder::HuggingFaceEmbedder;
+use crate::ml::sparse_encoder::encode_book;
+use crate::models::Book;
+use crate::services::book_versions::{stamp_version, BookHistory, VersionChange};
+use crate::services::pinecone::{is_hidden, Pinecone, UpsertVector, HIDDEN_FIELD};
+use log::{debug, info, warn};
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, Mutex};
+
+pub const DEFAULT_BATCH_SIZE: usize = 25;
+pub const DEFAULT_EMBED_CONCURRENCY: usize = 2;
+pub const DEFAULT_UPSERT_CONCURRENCY: usize = 4;
+
+/// Attempts per batch and stage before the batch is reported as failed
+const MAX_ATTEMPTS: u32 = 5;
+
+/// Delay applied after the first 429 when the upstream gives no Retry-After
+const MIN_THROTTLE_DELAY_MS: u64 = 500;
+const MAX_THROTTLE_DELAY_MS: u64 = 60_000;
+
+/// Pipeline sizing, set from the indexer's command line
+#[derive(Debug, Clone, Copy)]
+pub struct PipelineConfig {
+    pub batch_size: usize,
+    pub embed_concurrency: usize,
+    pub upsert_concurrency: usize,
+    /// Store a keyword vector with each book for the fallback search; the index
+    /// must use the dotproduct metric
+    pub sparse_keywords: bool,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        Self {
+            batch_size: DEFAULT_BATCH_SIZE,
+            embed_concurrency: DEFAULT_EMBED_CONCURRENCY,
+            upsert_concurrency: DEFAULT_UPSERT_CONCURRENCY,
+            sparse_keywords: false,
+        }
+    }
+}
+
+/// Delay shared by all workers talking to one upstream.
+///
+/// It doubles (or jumps to Retry-After) whenever the upstream throttles and
+/// decays back to zero as requests succeed.
+pub struct AdaptiveThrottle {
+    name: &'static str,
+    delay_ms: AtomicU64,
+}
+
+impl AdaptiveThrottle {
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            delay_ms: AtomicU64::new(0),
+        }
+    }
+
+    pub fn current_delay(&self) -> Duration {
+        Duration::from_millis(self.delay_ms.load(Ordering::Relaxed))
+    }
+
+    async fn wait(&self) {
+        let delay = self.current_delay();
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    fn on_success(&self) {
+        let _ = self
+            .delay_ms
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |delay| {
+                // Decay by a quarter per success, snapping to zero once it's negligible
+                let next = delay * 3 / 4;
+                Some(if next < 50 { 0 } else { next })
+            });
+    }
+
+    fn on_throttled(&self, retry_after: Option<u64>) {
+        let requested = retry_after.unwrap_or(0) * 1000;
+        let previous = self
+            .delay_ms
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |delay| {
+                Some(
+                    (delay * 2)
+                        .max(MIN_THROTTLE_DELAY_MS)
+                        .max(requested)
+                        .min(MAX_THROTTLE_DELAY_MS),
+                )
+            })
+            .unwrap_or_default();
+        warn!(
+            "{} is throttling, slowing down ({} ms -> {:?} between requests)",
+            self.name,
+            previous,
+            self.current_delay()
+        );
+    }
+}
+
+/// A batch of books with their content hashes, in input order
+pub struct PipelineBatch {
+    pub index: usize,
+    pub items: Vec<(Book, String)>,
+}
+
+struct EmbeddedBatch {
+    batch: PipelineBatch,
+    vectors: Vec<UpsertVector>,
+}
+
+/// Result of pushing one batch through the pipeline
+pub struct BatchOutcome {
+    pub batch: PipelineBatch,
+    pub result: Result<()>,
+}
+
+/// Books that made it through each stage, readable while the pipeline runs
+#[derive(Debug, Default)]
+pub struct PipelineCounters {
+    embedded: AtomicUsize,
+    upserted: AtomicUsize,
+}
+
+impl PipelineCounters {
+    pub fn embedded(&self) -> usize {
+        self.embedded.load(Ordering::Relaxed)
+    }
+
+    pub fn upserted(&self) -> usize {
+        self.upserted.load(Ordering::Relaxed)
+    }
+}
+
+/// A running pipeline
+pub struct PipelineHandle {
+    pub total_batches: usize,
+    /// Outcomes in completion order; closes once every batch is done
+    pub outcomes: mpsc::Receiver<BatchOutcome>,
+    pub counters: Arc<PipelineCounters>,
+}
+
+/// Start the pipeline
+pub fn spawn_pipeline(
+    items: Vec<(Book, String)>,
+    embedder: Arc<HuggingFaceEmbedder>,
+    pinecone: Pinecone,
+    searchable_text: fn(&Book) -> String,
+    moods: Option<Arc<MoodClassifier>>,
+    history: Option<BookHistory>,
+    config: PipelineConfig,
+) -> PipelineHandle {
+    let batch_size = config.batch_size.max(1);
+    let embed_workers = config.embed_concurrency.max(1);
+    let upsert_workers = config.upsert_concurrency.max(1);
+    let sparse_keywords = config.sparse_keywords;
+    let total_batches = items.len().div_ceil(batch_size);
+
+    info!(
+        "Starting pipeline: {} batches of up to {}, {} embedding workers, {} upsert workers",
+        total_batches, batch_size, embed_workers, upsert_workers
+    );
+
+    let (batch_tx, batch_rx) = mpsc::channel::<PipelineBatch>(embed_workers * 2);
+    let (embedded_tx, embedded_rx) = mpsc::channel::<EmbeddedBatch>(upsert_workers * 2);
+    let (outcome_tx, outcome_rx) = mpsc::channel::<BatchOutcome>(total_batches.max(1));
+    let counters = Arc::new(PipelineCounters::default());
+
+    // Producer: split into batches
+    tokio::spawn(async move {
+        let mut items = items.into_iter();
+        for index in 0..total_batches {
+            let batch = PipelineBatch {
+                index,
+                items: items.by_ref().take(batch_size).collect(),
+            };
+            if batch_tx.send(batch).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    // Embedding workers
+    let batch_rx = Arc::new(Mutex::new(batch_rx));
+    let embed_throttle = Arc::new(AdaptiveThrottle::new("HuggingFace"));
+    for _ in 0..embed_workers {
+        let batch_rx = batch_rx.clone();
+        let embedded_tx = embedded_tx.clone();
+        let outcome_tx = outcome_tx.clone();
+        let embedder = embedder.clone();
+        let throttle = embed_throttle.clone();
+        let counters = counters.clone();
+        let moods = moods.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let Some(batch) = batch_rx.lock().await.recv().await else {
+                    break;
+                };
+                match embed_batch(
+                    &batch,
+                    &embedder,
+                    &throttle,
+                    searchable_text,
+                    moods.as_deref(),
+                    sparse_keywords,
+                )
+                .await
+                {
+                    Ok(vectors) => {
+                        counters
+                            .embedded
+                            .fetch_add(batch.items.len(), Ordering::Relaxed);
+                        if embedded_tx
+                            .send(EmbeddedBatch { batch, vectors })
+                            .await
+                            .is_err()
+                        {
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        let _ = outcome_tx
+                            .send(BatchOutcome {
+                                batch,
+                                result: Err(e),
+                            })
+                            .await;
+                    }
+                }
+            }
+        });
+    }
+    drop(embedded_tx);
+
+    // Upsert workers
+    let embedded_rx = Arc::new(Mutex::new(embedded_rx));
+    let upsert_throttle = Arc::new(AdaptiveThrottle::new("Pinecone"));
+    for _ in 0..upsert_workers {
+        let embedded_rx = embedded_rx.clone();
+        let outcome_tx = outcome_tx.clone();
+        let pinecone = pinecone.clone();
+        let throttle = upsert_throttle.clone();
+        let counters = counters.clone();
+        let history = history.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let Some(mut embedded) = embedded_rx.lock().await.recv().await else {
+                    break;
+                };
+                let result: Result<()> = async {
+                    let changes =
+                        carry_over_metadata(&pinecone, &throttle, &mut embedded.vectors).await?;
+                    with_retries(&throttle, "Pinecone upsert", || {
+                        pinecone.upsert_vectors(&embedded.vectors)
+                    })
+                    .await?;
+                    if let Some(history) = &history {
+                        // The vectors are written; a missing history entry shouldn't fail them
+                        if let Err(e) = history.record("index", &changes).await {
+                            warn!("Failed to record {} book versions: {}", changes.len(), e);
+                        }
+                    }
+                    Ok(())
+                }
+                .await;
+                if result.is_ok() {
+                    counters
+                        .upserted
+                        .fetch_add(embedded.batch.items.len(), Ordering::Relaxed);
+                }
+                let _ = outcome_tx
+                    .send(BatchOutcome {
+                        batch: embedded.batch,
+                        result,
+                    })
+                    .await;
+            }
+        });
+    }
+
+    // Workers hold the remaining senders, so the channel closes when they finish
+    drop(outcome_tx);
+
+    PipelineHandle {
+        total_batches,
+        outcomes: outcome_rx,
+        counters,
+    }
+}
+
+async fn embed_batch(
+    batch: &PipelineBatch,
+    embedder: &HuggingFaceEmbedder,
+    throttle: &AdaptiveThrottle,
+    searchable_text: fn(&Book) -> String,
+    moods: Option<&MoodClassifier>,
+    sparse_keywords: bool,
+) -> Result<Vec<UpsertVector>> {
+    let texts: Vec<String> = batch
+        .items
+        .iter()
+        .map(|(book, _)| searchable_text(book))
+        .collect();
+
+    let embeddings = with_retries(throttle, "HuggingFace embedding", || {
+        embedder.encode_batch(&texts)
+    })
+    .await?;
+    debug!(
+        "Generated embeddings shape: {}x{} for batch {}",
+        embeddings.len(),
+        embeddings.first().map(|v| v.len()).unwrap_or(0),
+        batch.index + 1
+    );
+
+    if embeddings.len() != batch.items.len() {
+        return Err(ApiError::ModelInferenceError(format!(
+            "Expected {} embeddings, got {}",
+            batch.items.len(),
+            embeddings.len()
+        )));
+    }
+
+    batch
+        .items
+        .iter()
+        .zip(embeddings)
+        .map(|((book, hash), values)| {
+            let mut metadata = serde_json::to_value(book)?;
+            metadata["content_hash"] = serde_json::Value::String(hash.clone());
+            metadata["moods"] = serde_json::json!(tag_moods(book, Some(&values), moods));
+            Ok(UpsertVector {
+                id: book.id.clone().unwrap_or_default(),
+                values,
+                sparse_values: sparse_keywords.then(|| encode_book(book)).flatten(),
+                metadata,
+            })
+        })
+        .collect()
+}
+
+/// Keep the hidden flag and version of the stored metadata the vectors replace,
+/// bumping the version of the changed books; returns their changes
+async fn carry_over_metadata(
+    pinecone: &Pinecone,
+    throttle: &AdaptiveThrottle,
+    vectors: &mut [UpsertVector],
+) -> Result<Vec<VersionChange>> {
+    let ids: Vec<String> = vectors.iter().map(|vector| vector.id.clone()).collect();
+    let stored = with_retries(throttle, "Pinecone fetch", || pinecone.fetch_metadata(&ids)).await?;
+    let now = chrono::Utc::now().to_rfc3339();
+
+    let mut changes = Vec::new();
+    for vector in vectors {
+        let stored = stored.get(&vector.id);
+        if stored.is_some_and(is_hidden) {
+            vector.metadata[HIDDEN_FIELD] = serde_json::Value::Bool(true);
+        }
+        changes.extend(stamp_version(
+            &vector.id,
+            stored,
+            &mut vector.metadata,
+            &now,
+        ));
+    }
+    Ok(changes)
+}
+
+/// Run `operation`, backing off through the throttle on 429s and retrying transient errors
+async fn with_retries<T, F, Fut>(
+    throttle: &AdaptiveThrottle,
+    operation_name: &str,
+    mut operation: F,
+) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut attempt = 1;
+    loop {
+        throttle.wait().await;
+
+        match operation().await {
+            Ok(value) => {
+                throttle.on_success();
+                return Ok(value);
+            }
+            Err(e) if attempt >= MAX_ATTEMPTS || !is_retryable(&e) => return Err(e),
+            Err(ApiError::ServiceUnavailable { retry_after, .. }) => {
+                throttle.on_throttled(retry_after);
+            }
+            Err(e) => {
+                let delay = Duration::from_millis(500 * 2u64.pow(attempt - 1));
+                warn!(
+                    "{} attempt {} failed, retrying in {:?}: {}",
+                    operation_name, attempt, delay, e
+                );
+                tokio::time::sleep(delay).await;
+            }
+        }
+        attempt += 1;
+    }
+}
+
+fn is_retryable(error: &ApiError) -> bool {
+    !matches!(
+        error,
+        ApiError::InvalidInput(_)
+            | ApiError::SerializationError(_)
+            | ApiError::AuthenticationError(_)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_throttle_backs_off_and_recovers() {
+        let throttle = AdaptiveThrottle::new("test");
+        throttle.on_throttled(None);
+        assert_eq!(throttle.current_delay(), Duration::from_millis(500));
+        throttle.on_throttled(None);
+        assert_eq!(throttle.current_delay(), Duration::from_millis(1000));
+        throttle.on_throttled(Some(10));
+        assert_eq!(throttle.current_delay(), Duration::from_secs(10));
+
+        for _ in 0..30 {
+            throttle.on_success();
+        }
+        assert!(throttle.current_delay().is_zero());
+    }
+}
@@ -0,0 +1,242 @@
+//! Indexer progress tracking
+//!
+//! The indexer periodically logs a one-line summary and, when configured, writes
+//! the same snapshot to a JSON status file that the API serves at
+//! `/api/admin/indexer/status`.
+
+use crate::error::{ApiError, Result};
+use chrono::Utc;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use utoipa::ToSchema;
+
+/// Minimum time between progress log lines and status file writes
+const REPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum IndexRunState {
+    Running,
+    Completed,
+    Failed,
+}
+
+/// Snapshot of an indexing run
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct IndexProgress {
+    pub state: IndexRunState,
+    /// Input file being indexed
+    #[schema(example = "./data/books.csv")]
+    pub input: String,
+    /// When the run started, in RFC3339 format
+    #[schema(example = "2024-01-15T10:30:00Z")]
+    pub started_at: String,
+    /// When this snapshot was taken, in RFC3339 format
+    #[schema(example = "2024-01-15T10:42:10Z")]
+    pub updated_at: String,
+    /// Books queued for embedding in this run
+    #[schema(example = 5000)]
+    pub total_books: usize,
+    /// Books embedded so far
+    #[schema(example = 3100)]
+    pub embedded_books: usize,
+    /// Books embedded and written to Pinecone
+    #[schema(example = 3000)]
+    pub processed_books: usize,
+    /// Books in batches that failed
+    #[schema(example = 25)]
+    pub failed_books: usize,
+    #[schema(example = 200)]
+    pub total_batches: usize,
+    #[schema(example = 121)]
+    pub completed_batches: usize,
+    #[schema(example = 4.2)]
+    pub embeddings_per_sec: f64,
+    #[schema(example = 4.1)]
+    pub upserts_per_sec: f64,
+    #[schema(example = 730)]
+    pub elapsed_seconds: u64,
+    /// Estimated seconds until the run finishes, once a rate is known
+    #[schema(example = 482)]
+    pub eta_seconds: Option<u64>,
+    /// Most recent batch failure
+    pub last_error: Option<String>,
+}
+
+/// Tracks throughput for an indexing run and reports it
+pub struct ProgressTracker {
+    progress: IndexProgress,
+    started: Instant,
+    last_report: Option<Instant>,
+    status_file: Option<PathBuf>,
+}
+
+impl ProgressTracker {
+    pub fn new(input: &Path, total_books: usize, total_batches: usize) -> Self {
+        let now = Utc::now().to_rfc3339();
+        Self {
+            progress: IndexProgress {
+                state: IndexRunState::Running,
+                input: input.display().to_string(),
+                started_at: now.clone(),
+                updated_at: now,
+                total_books,
+                embedded_books: 0,
+                processed_books: 0,
+                failed_books: 0,
+                total_batches,
+                completed_batches: 0,
+                embeddings_per_sec: 0.0,
+                upserts_per_sec: 0.0,
+                elapsed_seconds: 0,
+                eta_seconds: None,
+                last_error: None,
+            },
+            started: Instant::now(),
+            last_report: None,
+            status_file: None,
+        }
+    }
+
+    /// Also write every report to this JSON file
+    pub fn with_status_file(mut self, path: impl AsRef<Path>) -> Self {
+        self.status_file = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn progress(&self) -> &IndexProgress {
+        &self.progress
+    }
+
+    /// Update the counters from the pipeline
+    pub fn update(&mut self, embedded: usize, processed: usize, failed: usize, batches: usize) {
+        let elapsed = self.started.elapsed();
+        let seconds = elapsed.as_secs_f64().max(f64::EPSILON);
+        let progress = &mut self.progress;
+
+        progress.embedded_books = embedded;
+        progress.processed_books = processed;
+        progress.failed_books = failed;
+        progress.completed_batches = batches;
+        progress.elapsed_seconds = elapsed.as_secs();
+        progress.embeddings_per_sec = embedded as f64 / seconds;
+        progress.upserts_per_sec = processed as f64 / seconds;
+
+        let remaining = progress.total_books.saturating_sub(processed + failed);
+        progress.eta_seconds = (progress.upserts_per_sec > 0.0)
+            .then(|| (remaining as f64 / progress.upserts_per_sec).round() as u64);
+    }
+
+    pub fn record_error(&mut self, error: impl Into<String>) {
+        self.progress.last_error = Some(error.into());
+    }
+
+    /// Log and persist the current snapshot, at most once per report interval
+    pub fn report(&mut self) -> Result<()> {
+        if self
+            .last_report
+            .is_some_and(|last| last.elapsed() < REPORT_INTERVAL)
+        {
+            return Ok(());
+        }
+        self.report_now()
+    }
+
+    /// Mark the run as finished and report immediately
+    pub fn finish(&mut self, state: IndexRunState) -> Result<()> {
+        self.progress.state = state;
+        if state == IndexRunState::Completed {
+            self.progress.eta_seconds = Some(0);
+        }
+        self.report_now()
+    }
+
+    fn report_now(&mut self) -> Result<()> {
+        self.last_report = Some(Instant::now());
+        self.progress.updated_at = Utc::now().to_rfc3339();
+
+        let p = &self.progress;
+        info!(
+            "📈 Progress: {}/{} books ({:.1}%), {}/{} batches, {:.1} embeddings/s, {:.1} upserts/s, {} failed, ETA {}",
+            p.processed_books,
+            p.total_books,
+            if p.total_books == 0 {
+                100.0
+            } else {
+                p.processed_books as f64 * 100.0 / p.total_books as f64
+            },
+            p.completed_batches,
+            p.total_batches,
+            p.embeddings_per_sec,
+            p.upserts_per_sec,
+            p.failed_books,
+            p.eta_seconds
+                .map(format_duration)
+                .unwrap_or_else(|| "unknown".to_string())
+        );
+
+        if let Some(path) = &self.status_file {
+            write_status_file(path, &self.progress)?;
+        }
+        Ok(())
+    }
+}
+
+/// Write a snapshot atomically so readers never see a partial file
+pub fn write_status_file(path: &Path, progress: &IndexProgress) -> Result<()> {
+    let tmp_path = path.with_extension("json.tmp");
+    let json = serde_json::to_vec_pretty(progress)?;
+    std::fs::write(&tmp_path, json).map_err(|e| {
+        ApiError::InternalError(format!(
+            "Failed to write status file {}: {}",
+            tmp_path.display(),
+            e
+        ))
+    })?;
+    std::fs::rename(&tmp_path, path).map_err(|e| {
+        ApiError::InternalError(format!(
+            "Failed to replace status file {}: {}",
+            path.display(),
+            e
+        ))
+    })
+}
+
+/// Read the last snapshot, or `None` if no run has written one yet
+pub fn read_status_file(path: &Path) -> Result<Option<IndexProgress>> {
+    match std::fs::read(path) {
+        Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(ApiError::InternalError(format!(
+            "Failed to read status file {}: {}",
+            path.display(),
+            e
+        ))),
+    }
+}
+
+fn format_duration(seconds: u64) -> String {
+    match seconds {
+        s if s >= 3600 => format!("{}h {}m", s / 3600, s % 3600 / 60),
+        s if s >= 60 => format!("{}m {}s", s / 60, s % 60),
+        s => format!("{}s", s),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eta_uses_upsert_rate() {
+        let mut tracker = ProgressTracker::new(Path::new("books.csv"), 100, 4);
+        tracker.started = Instant::now() - Duration::from_secs(10);
+        tracker.update(60, 50, 10, 2);
+
+        let progress = tracker.progress();
+        assert!((progress.upserts_per_sec - 5.0).abs() < 0.1);
+        assert_eq!(progress.eta_seconds, Some(8));
+    }
+}
@@ -0,0 +1,132 @@
+//! Markets a book is available in, as ISO 3166-1 alpha-2 country codes
+//!
+//! Enrichment fills a book's `available_regions` from publisher data: the
+//! country Open Library records the edition as published in (a MARC country
+//! code, where "nyu" is New York and "enk" England). Requests with a `region`
+//! leave out books known to be unavailable there; books without regions are
+//! kept, since most of the catalog has no publisher data.
+
+use crate::error::{ApiError, Result};
+use crate::models::Book;
+
+/// MARC codes of countries without subdivisions, and the ISO code of each
+const MARC_COUNTRIES: &[(&str, &str)] = &[
+    ("ag", "AR"),
+    ("au", "AT"),
+    ("be", "BE"),
+    ("bl", "BR"),
+    ("cc", "CN"),
+    ("ch", "TW"),
+    ("ck", "CO"),
+    ("cl", "CL"),
+    ("dk", "DK"),
+    ("fi", "FI"),
+    ("fr", "FR"),
+    ("gr", "GR"),
+    ("gw", "DE"),
+    ("hu", "HU"),
+    ("ie", "IE"),
+    ("ii", "IN"),
+    ("is", "IL"),
+    ("it", "IT"),
+    ("ja", "JP"),
+    ("ko", "KR"),
+    ("mx", "MX"),
+    ("ne", "NL"),
+    ("no", "NO"),
+    ("nz", "NZ"),
+    ("pl", "PL"),
+    ("po", "PT"),
+    ("ru", "RU"),
+    ("sa", "ZA"),
+    ("si", "SG"),
+    ("sp", "ES"),
+    ("sw", "SE"),
+    ("sz", "CH"),
+    ("tu", "TR"),
+    ("xr", "CZ"),
+];
+
+/// Codes of Australian states, which unlike US states and Canadian provinces
+/// don't share a suffix
+const MARC_AUSTRALIA: &[&str] = &["at", "aca", "qea", "tma", "vra", "wea", "xna", "xoa", "xra"];
+
+/// ISO code of a MARC country code, e.g. "nyu" is "US"; None for codes not known
+pub fn marc_country_region(code: &str) -> Option<&'static str> {
+    let code = code.trim().to_ascii_lowercase();
+    if code.len() == 3 {
+        if code.ends_with('u') {
+            return Some("US");
+        }
+        if code.ends_with('c') {
+            return Some("CA");
+        }
+        if matches!(code.as_str(), "xxk" | "enk" | "stk" | "wlk" | "nik") {
+            return Some("GB");
+        }
+    }
+    if MARC_AUSTRALIA.contains(&code.as_str()) {
+        return Some("AU");
+    }
+    MARC_COUNTRIES
+        .iter()
+        .find(|(marc, _)| *marc == code)
+        .map(|(_, iso)| *iso)
+}
+
+/// A request's region as an uppercase ISO code; "UK" is read as "GB"
+pub fn parse_region(region: &str) -> Result<String> {
+    let region = region.trim().to_ascii_uppercase();
+    if region.len() != 2 || !region.chars().all(|c| c.is_ascii_alphabetic()) {
+        return Err(ApiError::InvalidInput(format!(
+            "region must be a two-letter country code, e.g. US or GB, not '{}'",
+            region
+        )));
+    }
+    Ok(if region == "UK" {
+        "GB".to_string()
+    } else {
+        region
+    })
+}
+
+/// Whether `book` may be shown in `region`: it's listed, or the book's regions are unknown
+pub fn available_in(book: &Book, region: &str) -> bool {
+    book.available_regions.is_empty()
+        || book
+            .available_regions
+            .iter()
+            .any(|available| available.eq_ignore_ascii_case(region))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_marc_codes_map_to_countries() {
+        assert_eq!(marc_country_region("nyu"), Some("US"));
+        assert_eq!(marc_country_region("xxu"), Some("US"));
+        assert_eq!(marc_country_region("onc"), Some("CA"));
+        assert_eq!(marc_country_region("enk"), Some("GB"));
+        assert_eq!(marc_country_region("vra"), Some("AU"));
+        assert_eq!(marc_country_region("gw "), Some("DE"));
+        assert_eq!(marc_country_region("xr"), Some("CZ"));
+        assert_eq!(marc_country_region("xx"), None);
+    }
+
+    #[test]
+    fn test_books_without_regions_are_available_everywhere() {
+        let mut book: Book = serde_json::from_value(serde_json::json!({
+            "title": "Dune",
+            "categories": ["Science Fiction"],
+        }))
+        .unwrap();
+        assert!(available_in(&book, "FR"));
+
+        book.available_regions = vec!["US".to_string(), "GB".to_string()];
+        assert!(available_in(&book, &parse_region(" uk").unwrap()));
+        assert!(!available_in(&book, "FR"));
+        assert!(parse_region("USA").is_err());
+    }
+}
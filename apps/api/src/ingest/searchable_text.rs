@@ -0,0 +1,140 @@
+//! The text a book is embedded from, and the hash that decides when to re-embed it
+//!
+//! The indexer and the admin re-embed endpoint both build a book's vector from
+//! [`create_searchable_text`], so a book re-embedded on its own ends up exactly
+//! where a full index run would have put it.
+
+use crate::error::Result;
+use crate::models::Book;
+use log::debug;
+use sha2::{Digest, Sha256};
+
+/// Fields left out of the content hash: derived while embedding, or bookkeeping
+const UNHASHED_FIELDS: [&str; 3] = ["moods", "version", "updated_at"];
+
+/// Enhanced text preprocessing for better semantic understanding
+fn preprocess_text(text: &str) -> String {
+    text.trim()
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .filter(|c| !c.is_control())
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Create a comprehensive searchable text representation
+pub fn create_searchable_text(book: &Book) -> String {
+    let mut parts = Vec::new();
+
+    // Add title with emphasis
+    if let Some(title) = &book.title {
+        parts.push(format!("Title: {}", title));
+        parts.push(title.clone()); // Add title again for emphasis
+    }
+
+    // Add author information
+    if let Some(author) = &book.author {
+        parts.push(format!("Author: {}", author));
+        parts.push(format!("Written by {}", author));
+    }
+
+    // Add categories/genres
+    if !book.categories.is_empty() {
+        let categories_str = book.categories.join(", ");
+        let genres_str = if book.genres.is_empty() {
+            categories_str.clone()
+        } else {
+            book.genres.join(", ")
+        };
+        parts.push(format!("Genre: {}", genres_str));
+        parts.push(format!("Categories: {}", categories_str));
+    }
+
+    // Add description if available
+    if let Some(description) = &book.description {
+        if !description.trim().is_empty() {
+            let cleaned_desc = preprocess_text(description);
+            if cleaned_desc.len() > 50 {
+                // Only add substantial descriptions
+                parts.push(format!("Description: {}", cleaned_desc));
+            }
+        }
+    }
+
+    // Add publisher and year for context
+    if let Some(publisher) = &book.publisher {
+        parts.push(format!("Publisher: {}", publisher));
+    }
+
+    if let Some(year) = book.year {
+        parts.push(format!("Published: {}", year));
+    }
+
+    let result = parts.join(". ");
+    debug!(
+        "Created searchable text for '{}': {} chars",
+        book.title.as_deref().unwrap_or("Unknown"),
+        result.len()
+    );
+    result
+}
+
+/// Hash of everything that ends up in a book's vector or metadata.
+///
+/// The model name is included so switching models re-embeds the whole catalog.
+/// Moods are left out: they're derived from the rest while embedding. So are the
+/// version and update time, which only change because the rest did.
+pub fn content_hash(book: &Book, model_name: &str) -> Result<String> {
+    let mut metadata = serde_json::to_value(book)?;
+    if let Some(fields) = metadata.as_object_mut() {
+        for field in UNHASHED_FIELDS {
+            fields.shift_remove(field);
+        }
+    }
+
+    let mut hasher = Sha256::new();
+    hasher.update(model_name.as_bytes());
+    hasher.update(b"\n");
+    hasher.update(serde_json::to_vec(&metadata)?);
+    hasher.update(b"\n");
+    hasher.update(create_searchable_text(book).as_bytes());
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book(description: &str) -> Book {
+        serde_json::from_value(serde_json::json!({
+            "id": "b1",
+            "title": "Dune",
+            "author": "Frank Herbert",
+            "description": description,
+            "categories": ["Science Fiction"],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_hash_follows_content_not_bookkeeping() {
+        let original = book("A desert planet and the spice that only grows there.");
+        let hash = content_hash(&original, "model").unwrap();
+
+        let mut versioned = original.clone();
+        versioned.version = Some(3);
+        versioned.updated_at = Some("2024-01-15T10:30:00Z".to_string());
+        versioned.moods = vec!["epic".to_string()];
+        assert_eq!(content_hash(&versioned, "model").unwrap(), hash);
+
+        let fixed = book("A desert planet, the spice that grows only there, and a messiah.");
+        assert_ne!(content_hash(&fixed, "model").unwrap(), hash);
+        assert_ne!(content_hash(&original, "other-model").unwrap(), hash);
+    }
+}
@@ -0,0 +1,324 @@
+//! Story settings: where a book takes place, from its description
+//!
+//! Places come from a small gazetteer of countries, regions and cities. A place
+//! counts as a setting when the description mentions it, capitalized, right
+//! after a preposition ("in Tokyo", "across the Netherlands"), which keeps names
+//! like "Jack London" and words like "china" out. Books are tagged with the
+//! places and everything containing them, so a book set in Kyoto matches
+//! "set in Japan" and "set in Asia" as well.
+
+use lazy_static::lazy_static;
+use regex::{Regex, RegexBuilder};
+use std::collections::HashMap;
+
+/// A place of the gazetteer
+pub struct Place {
+    pub name: &'static str,
+    /// Names the place goes by, as written in descriptions
+    aliases: &'static [&'static str],
+    /// The place containing this one
+    within: Option<&'static str>,
+}
+
+const fn place(
+    name: &'static str,
+    aliases: &'static [&'static str],
+    within: Option<&'static str>,
+) -> Place {
+    Place {
+        name,
+        aliases,
+        within,
+    }
+}
+
+pub const PLACES: &[Place] = &[
+    // Continents and regions
+    place("Europe", &["Europe"], None),
+    place("Asia", &["Asia"], None),
+    place("Africa", &["Africa"], None),
+    place("North America", &["North America"], None),
+    place("South America", &["South America"], None),
+    place("Middle East", &["Middle East"], None),
+    place("Antarctica", &["Antarctica"], None),
+    // Countries
+    place("Japan", &["Japan"], Some("Asia")),
+    place("China", &["China"], Some("Asia")),
+    place("India", &["India"], Some("Asia")),
+    place(
+        "Korea",
+        &["Korea", "South Korea", "North Korea"],
+        Some("Asia"),
+    ),
+    place("Vietnam", &["Vietnam"], Some("Asia")),
+    place("Thailand", &["Thailand", "Siam"], Some("Asia")),
+    place("Afghanistan", &["Afghanistan"], Some("Middle East")),
+    place("Iran", &["Iran", "Persia"], Some("Middle East")),
+    place("Iraq", &["Iraq"], Some("Middle East")),
+    place("Israel", &["Israel"], Some("Middle East")),
+    place("Egypt", &["Egypt"], Some("Africa")),
+    place("Nigeria", &["Nigeria"], Some("Africa")),
+    place("Kenya", &["Kenya"], Some("Africa")),
+    place("Ethiopia", &["Ethiopia"], Some("Africa")),
+    place("Morocco", &["Morocco"], Some("Africa")),
+    place("South Africa", &["South Africa"], Some("Africa")),
+    place(
+        "Russia",
+        &["Russia", "Soviet Union", "USSR"],
+        Some("Europe"),
+    ),
+    place("France", &["France"], Some("Europe")),
+    place("Germany", &["Germany"], Some("Europe")),
+    place("Italy", &["Italy"], Some("Europe")),
+    place("Spain", &["Spain"], Some("Europe")),
+    place("Portugal", &["Portugal"], Some("Europe")),
+    place("Greece", &["Greece"], Some("Europe")),
+    place("Ireland", &["Ireland"], Some("Europe")),
+    place(
+        "United Kingdom",
+        &["United Kingdom", "Britain", "Great Britain"],
+        Some("Europe"),
+    ),
+    place("England", &["England"], Some("United Kingdom")),
+    place("Scotland", &["Scotland"], Some("United Kingdom")),
+    place("Wales", &["Wales"], Some("United Kingdom")),
+    place("Netherlands", &["Netherlands", "Holland"], Some("Europe")),
+    place("Sweden", &["Sweden"], Some("Europe")),
+    place("Norway", &["Norway"], Some("Europe")),
+    place("Denmark", &["Denmark"], Some("Europe")),
+    place("Iceland", &["Iceland"], Some("Europe")),
+    place("Poland", &["Poland"], Some("Europe")),
+    place("Austria", &["Austria"], Some("Europe")),
+    place("Switzerland", &["Switzerland"], Some("Europe")),
+    place(
+        "Czech Republic",
+        &["Czech Republic", "Czechoslovakia", "Bohemia"],
+        Some("Europe"),
+    ),
+    place("Hungary", &["Hungary"], Some("Europe")),
+    place("Turkey", &["Turkey", "Ottoman Empire"], Some("Europe")),
+    place(
+        "United States",
+        &["United States", "America", "USA"],
+        Some("North America"),
+    ),
+    place("Canada", &["Canada"], Some("North America")),
+    place("Mexico", &["Mexico"], Some("North America")),
+    place("Cuba", &["Cuba"], Some("North America")),
+    place("Brazil", &["Brazil"], Some("South America")),
+    place("Argentina", &["Argentina"], Some("South America")),
+    place("Chile", &["Chile"], Some("South America")),
+    place("Colombia", &["Colombia"], Some("South America")),
+    place("Peru", &["Peru"], Some("South America")),
+    place("Australia", &["Australia"], None),
+    place("New Zealand", &["New Zealand"], None),
+    // US states
+    place("California", &["California"], Some("United States")),
+    place("Texas", &["Texas"], Some("United States")),
+    place("Alaska", &["Alaska"], Some("United States")),
+    place("Mississippi", &["Mississippi"], Some("United States")),
+    // Cities
+    place("Tokyo", &["Tokyo", "Edo"], Some("Japan")),
+    place("Kyoto", &["Kyoto"], Some("Japan")),
+    place("Beijing", &["Beijing", "Peking"], Some("China")),
+    place("Shanghai", &["Shanghai"], Some("China")),
+    place("Hong Kong", &["Hong Kong"], Some("China")),
+    place("Mumbai", &["Mumbai", "Bombay"], Some("India")),
+    place("Delhi", &["Delhi", "New Delhi"], Some("India")),
+    place("Kolkata", &["Kolkata", "Calcutta"], Some("India")),
+    place("Seoul", &["Seoul"], Some("Korea")),
+    place("Kabul", &["Kabul"], Some("Afghanistan")),
+    place("Tehran", &["Tehran"], Some("Iran")),
+    place("Jerusalem", &["Jerusalem"], Some("Israel")),
+    place("Cairo", &["Cairo"], Some("Egypt")),
+    place("Lagos", &["Lagos"], Some("Nigeria")),
+    place("Cape Town", &["Cape Town"], Some("South Africa")),
+    place("Johannesburg", &["Johannesburg"], Some("South Africa")),
+    place("Moscow", &["Moscow"], Some("Russia")),
+    place(
+        "St. Petersburg",
+        &["St. Petersburg", "Saint Petersburg", "Leningrad"],
+        Some("Russia"),
+    ),
+    place("Paris", &["Paris"], Some("France")),
+    place("Berlin", &["Berlin"], Some("Germany")),
+    place("Munich", &["Munich"], Some("Germany")),
+    place("Rome", &["Rome"], Some("Italy")),
+    place("Venice", &["Venice"], Some("Italy")),
+    place("Florence", &["Florence"], Some("Italy")),
+    place("Naples", &["Naples"], Some("Italy")),
+    place("Sicily", &["Sicily"], Some("Italy")),
+    place("Madrid", &["Madrid"], Some("Spain")),
+    place("Barcelona", &["Barcelona"], Some("Spain")),
+    place("Lisbon", &["Lisbon"], Some("Portugal")),
+    place("Athens", &["Athens"], Some("Greece")),
+    place("Dublin", &["Dublin"], Some("Ireland")),
+    place("London", &["London"], Some("England")),
+    place("Oxford", &["Oxford"], Some("England")),
+    place("Edinburgh", &["Edinburgh"], Some("Scotland")),
+    place("Amsterdam", &["Amsterdam"], Some("Netherlands")),
+    place("Vienna", &["Vienna"], Some("Austria")),
+    place("Prague", &["Prague"], Some("Czech Republic")),
+    place("Istanbul", &["Istanbul", "Constantinople"], Some("Turkey")),
+    place(
+        "New York",
+        &["New York", "New York City", "Manhattan", "Brooklyn"],
+        Some("United States"),
+    ),
+    place(
+        "Los Angeles",
+        &["Los Angeles", "Hollywood"],
+        Some("California"),
+    ),
+    place("San Francisco", &["San Francisco"], Some("California")),
+    place("Chicago", &["Chicago"], Some("United States")),
+    place("Boston", &["Boston"], Some("United States")),
+    place("New Orleans", &["New Orleans"], Some("United States")),
+    place("Seattle", &["Seattle"], Some("United States")),
+    place("Toronto", &["Toronto"], Some("Canada")),
+    place("Montreal", &["Montreal"], Some("Canada")),
+    place("Mexico City", &["Mexico City"], Some("Mexico")),
+    place("Havana", &["Havana"], Some("Cuba")),
+    place("Rio de Janeiro", &["Rio de Janeiro", "Rio"], Some("Brazil")),
+    place("Buenos Aires", &["Buenos Aires"], Some("Argentina")),
+    place("Sydney", &["Sydney"], Some("Australia")),
+    place("Melbourne", &["Melbourne"], Some("Australia")),
+];
+
+/// Most places tagged on one book, most mentioned first, before adding what contains them
+pub const MAX_PLACES_PER_BOOK: usize = 3;
+
+/// Prepositions that introduce a setting in a description. Not "of", which
+/// names far more banks and universities than settings
+const SETTING_PREPOSITIONS: &str =
+    "in|to|from|across|around|through|throughout|within|near|outside";
+
+lazy_static! {
+    /// Place by each of its aliases, lowercased
+    static ref PLACE_BY_ALIAS: HashMap<String, &'static Place> = PLACES
+        .iter()
+        .flat_map(|place| place.aliases.iter().map(move |alias| (alias.to_lowercase(), place)))
+        .collect();
+
+    /// Every alias, longest first so "New York City" wins over "New York"
+    static ref ALIAS_ALTERNATION: String = {
+        let mut aliases: Vec<&str> = PLACES.iter().flat_map(|place| place.aliases.iter().copied()).collect();
+        aliases.sort_by_key(|alias| std::cmp::Reverse(alias.len()));
+        aliases.iter().map(|alias| regex::escape(alias)).collect::<Vec<_>>().join("|")
+    };
+
+    /// A place right after a preposition, optionally with "the". The preposition
+    /// may be capitalized, the place must be written as a proper name
+    static ref DESCRIPTION_PATTERN: Regex = Regex::new(&format!(
+        r"\b(?i:{})\s+(?i:the\s+)?({})\b",
+        SETTING_PREPOSITIONS, *ALIAS_ALTERNATION
+    ))
+    .unwrap();
+
+    /// Any alias, in any case, for the setting phrases of a query
+    static ref QUERY_PATTERN: Regex = RegexBuilder::new(&format!(r"\b(?:{})\b", *ALIAS_ALTERNATION))
+        .case_insensitive(true)
+        .build()
+        .unwrap();
+}
+
+/// `name` followed by every place containing it, innermost first
+fn with_containing_places(name: &'static str) -> Vec<&'static str> {
+    let mut chain = vec![name];
+    let mut current = name;
+    while let Some(parent) = PLACES
+        .iter()
+        .find(|place| place.name == current)
+        .and_then(|place| place.within)
+    {
+        if chain.contains(&parent) {
+            break;
+        }
+        chain.push(parent);
+        current = parent;
+    }
+    chain
+}
+
+/// Settings of a book from its description: the most mentioned places and what contains them
+pub fn settings_in_description(description: &str) -> Vec<String> {
+    let mut mentions: Vec<(&'static str, usize)> = Vec::new();
+    for captures in DESCRIPTION_PATTERN.captures_iter(description) {
+        let Some(alias) = captures.get(1) else {
+            continue;
+        };
+        let Some(place) = PLACE_BY_ALIAS.get(&alias.as_str().to_lowercase()) else {
+            continue;
+        };
+        match mentions.iter_mut().find(|(name, _)| *name == place.name) {
+            Some((_, count)) => *count += 1,
+            None => mentions.push((place.name, 1)),
+        }
+    }
+    // Stable, so ties keep the order of first mention
+    mentions.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+
+    let mut settings: Vec<String> = Vec::new();
+    for (name, _) in mentions.into_iter().take(MAX_PLACES_PER_BOOK) {
+        for place in with_containing_places(name) {
+            if !settings.iter().any(|setting| setting == place) {
+                settings.push(place.to_string());
+            }
+        }
+    }
+    settings
+}
+
+/// Places named in the setting phrases of a query, e.g. "japan" from "set in japan"
+pub fn settings_in_query<S: AsRef<str>>(phrases: &[S]) -> Vec<String> {
+    let mut settings: Vec<String> = Vec::new();
+    for phrase in phrases {
+        for alias in QUERY_PATTERN.find_iter(phrase.as_ref()) {
+            if let Some(place) = PLACE_BY_ALIAS.get(&alias.as_str().to_lowercase()) {
+                if !settings.iter().any(|setting| setting == place.name) {
+                    settings.push(place.name.to_string());
+                }
+            }
+        }
+    }
+    settings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_descriptions_and_queries_resolve_to_the_gazetteer() {
+        assert_eq!(
+            settings_in_description(
+                "A young woman in London falls in with thieves, then flees to Paris. \
+                 A tribute to Jack London."
+            ),
+            vec![
+                "London",
+                "England",
+                "United Kingdom",
+                "Europe",
+                "Paris",
+                "France"
+            ]
+        );
+        assert_eq!(
+            settings_in_description("Growing up in South Africa under apartheid."),
+            vec!["South Africa", "Africa"]
+        );
+        assert!(settings_in_description("Mostly about china and turkey dinners.").is_empty());
+        assert_eq!(
+            settings_in_description("In Tokyo, a clerk from the Bank of America disappears."),
+            vec!["Tokyo", "Japan", "Asia"]
+        );
+
+        assert_eq!(settings_in_query(&["japan"]), vec!["Japan"]);
+        assert_eq!(
+            settings_in_query(&["new york city in the twenties"]),
+            vec!["New York"]
+        );
+        assert!(settings_in_query(&["a distant galaxy"]).is_empty());
+    }
+}
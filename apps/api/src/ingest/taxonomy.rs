@@ -0,0 +1,169 @@
+//! Category taxonomy: maps noisy publisher categories onto a controlled genre vocabulary
+//!
+//! The taxonomy file maps each genre to the phrases that indicate it:
+//!
+//! ```yaml
+//! fantasy:
+//!   - fantasy
+//!   - sword and sorcery
+//! science fiction:
+//!   - science fiction
+//!   - sci-fi
+//! ```
+//!
+//! A phrase matches a category when its words appear in the category in order, so
+//! "fiction / fantasy / general" maps to `fantasy`. When phrases overlap, the
+//! longest wins: "science fiction" keeps "fiction" from also matching.
+
+use crate::error::{ApiError, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+#[derive(Debug, Clone)]
+struct Phrase {
+    words: Vec<String>,
+    genre: String,
+}
+
+/// Controlled genre vocabulary and the phrases mapping categories onto it
+#[derive(Debug, Clone, Default)]
+pub struct CategoryTaxonomy {
+    /// Longest phrases first, so they claim their words before shorter ones
+    phrases: Vec<Phrase>,
+}
+
+impl CategoryTaxonomy {
+    /// Load the taxonomy from a YAML file
+    pub fn load(path: &Path) -> Result<Self> {
+        let yaml = std::fs::read_to_string(path).map_err(|e| {
+            ApiError::InternalError(format!(
+                "Failed to read category taxonomy {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+        Self::from_yaml(&yaml)
+    }
+
+    pub fn from_yaml(yaml: &str) -> Result<Self> {
+        let entries: BTreeMap<String, Vec<String>> =
+            serde_yaml::from_str::<Option<BTreeMap<String, Vec<String>>>>(yaml)
+                .map_err(|e| ApiError::InvalidInput(format!("Invalid category taxonomy: {}", e)))?
+                .unwrap_or_default();
+
+        let mut seen: HashMap<Vec<String>, String> = HashMap::new();
+        let mut phrases = Vec::new();
+        for (genre, patterns) in entries {
+            let genre = genre.trim().to_string();
+            if genre.is_empty() {
+                continue;
+            }
+            for pattern in patterns.iter().map(String::as_str).chain([genre.as_str()]) {
+                let words = words(pattern);
+                if words.is_empty() {
+                    continue;
+                }
+                match seen.get(&words) {
+                    Some(existing) if *existing == genre => continue,
+                    Some(existing) => {
+                        return Err(ApiError::InvalidInput(format!(
+                            "Taxonomy phrase '{}' maps to both '{}' and '{}'",
+                            pattern, existing, genre
+                        )))
+                    }
+                    None => {}
+                }
+                seen.insert(words.clone(), genre.clone());
+                phrases.push(Phrase {
+                    words,
+                    genre: genre.clone(),
+                });
+            }
+        }
+        phrases.sort_by_key(|phrase| std::cmp::Reverse(phrase.words.len()));
+
+        Ok(Self { phrases })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.phrases.is_empty()
+    }
+
+    /// Genres for a single category, in the order they appear in it
+    pub fn classify_category(&self, category: &str) -> Vec<String> {
+        let category_words = words(category);
+        let mut claimed = vec![false; category_words.len()];
+        let mut matches: Vec<(usize, &str)> = Vec::new();
+
+        for phrase in &self.phrases {
+            let len = phrase.words.len();
+            if len > category_words.len() {
+                continue;
+            }
+            for start in 0..=category_words.len() - len {
+                let span = start..start + len;
+                if claimed[span.clone()].iter().any(|c| *c)
+                    || category_words[span.clone()] != phrase.words[..]
+                {
+                    continue;
+                }
+                claimed[span].iter_mut().for_each(|c| *c = true);
+                matches.push((start, phrase.genre.as_str()));
+            }
+        }
+
+        matches.sort_by_key(|(start, _)| *start);
+        let mut genres: Vec<String> = Vec::new();
+        for (_, genre) in matches {
+            if !genres.iter().any(|g| g == genre) {
+                genres.push(genre.to_string());
+            }
+        }
+        genres
+    }
+
+    /// Genres for all of a book's categories, without duplicates
+    pub fn classify(&self, categories: &[String]) -> Vec<String> {
+        let mut genres: Vec<String> = Vec::new();
+        for genre in categories.iter().flat_map(|c| self.classify_category(c)) {
+            if !genres.contains(&genre) {
+                genres.push(genre);
+            }
+        }
+        genres
+    }
+}
+
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric() && c != '-')
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_longest_phrase_wins() {
+        let taxonomy = CategoryTaxonomy::from_yaml(
+            "fiction: []\nfantasy: []\nscience fiction:\n  - sci-fi\nchildren:\n  - juvenile fiction\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            taxonomy.classify_category("fiction / fantasy / general"),
+            vec!["fiction", "fantasy"]
+        );
+        assert_eq!(
+            taxonomy.classify_category("Fiction / Science Fiction / Space Opera"),
+            vec!["fiction", "science fiction"]
+        );
+        assert_eq!(
+            taxonomy.classify(&["juvenile fiction".to_string(), "sci-fi".to_string()]),
+            vec!["children", "science fiction"]
+        );
+        assert!(taxonomy.classify_category("cooking").is_empty());
+    }
+}
@@ -2,13 +2,21 @@
 #![allow(unused_imports)]
 
 pub mod app;
+pub mod cache;
+pub mod commands;
 pub mod config;
 pub mod error;
+pub mod evaluation;
 pub mod handlers;
+pub mod ingest;
+pub mod middleware;
 pub mod ml;
 pub mod models;
 pub mod routes;
 pub mod services;
+pub mod telemetry;
+#[cfg(any(test, feature = "test_support"))]
+pub mod test_support;
 
 pub use config::Config;
 pub use error::{ApiError, Result};
@@ -1,16 +1,7 @@
-use crate::error::{ApiError, Result};
 use log::info;
+use recommend_a_book_api::{app, config, ApiError, Result};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-mod app;
-mod config;
-mod error;
-mod handlers;
-mod ml;
-mod models;
-mod routes;
-mod services;
-
 #[actix_web::main]
 async fn main() -> Result<()> {
     // Load configuration
@@ -0,0 +1,65 @@
+//! Shared-secret authentication for `/api/admin` endpoints
+
+use crate::{config::Config, error::ApiError};
+use actix_web::{dev::Payload, web, FromRequest, HttpRequest};
+use futures::future::{ready, Ready};
+
+/// Header carrying the admin API key
+pub const ADMIN_KEY_HEADER: &str = "X-Admin-Key";
+
+/// Optional header naming the person or system performing the admin action
+pub const ADMIN_ACTOR_HEADER: &str = "X-Admin-Actor";
+
+/// Extractor that only succeeds for requests carrying the configured admin key.
+///
+/// Add it as a handler argument to protect an endpoint. Admin endpoints are
+/// disabled entirely when no `admin_api_key` is configured.
+#[derive(Debug, Clone)]
+pub struct AdminAuth {
+    /// Who performed the request, from `X-Admin-Actor` (defaults to "admin")
+    pub actor: String,
+}
+
+impl FromRequest for AdminAuth {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(authenticate(req))
+    }
+}
+
+fn authenticate(req: &HttpRequest) -> Result<AdminAuth, ApiError> {
+    let expected = req
+        .app_data::<web::Data<Config>>()
+        .and_then(|config| config.admin_api_key.clone())
+        .ok_or_else(|| ApiError::AuthenticationError("Admin endpoints are disabled".to_string()))?;
+
+    let provided = req
+        .headers()
+        .get(ADMIN_KEY_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+
+    if !constant_time_eq(provided.as_bytes(), expected.as_bytes()) {
+        return Err(ApiError::AuthenticationError(
+            "Missing or invalid admin key".to_string(),
+        ));
+    }
+
+    let actor = req
+        .headers()
+        .get(ADMIN_ACTOR_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|actor| !actor.is_empty())
+        .unwrap_or("admin")
+        .to_string();
+
+    Ok(AdminAuth { actor })
+}
+
+/// Compare secrets without short-circuiting on the first mismatching byte
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
@@ -0,0 +1,133 @@
+//! Middleware that turns handler panics into structured JSON 500 responses
+
+use crate::telemetry;
+use actix_web::{
+    body::{BoxBody, MessageBody},
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::StatusCode,
+    Error, HttpResponse,
+};
+use futures::future::{ready, LocalBoxFuture, Ready};
+use futures::FutureExt;
+use log::error;
+use std::panic::AssertUnwindSafe;
+
+/// Catches panics raised while a request is handled.
+///
+/// Without this, a panicking handler tears down the connection and the client sees
+/// an empty reply. With it, the panic is logged with the request that caused it,
+/// counted in telemetry, and answered with the same JSON error shape as `ApiError`.
+pub struct CatchPanic;
+
+impl<S, B> Transform<S, ServiceRequest> for CatchPanic
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Transform = CatchPanicMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(CatchPanicMiddleware { service }))
+    }
+}
+
+pub struct CatchPanicMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for CatchPanicMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        // Keep a handle on the request so we can still build a response after a panic
+        let http_request = req.request().clone();
+
+        // Calling the inner service can itself panic, before any future is returned
+        let future = match std::panic::catch_unwind(AssertUnwindSafe(|| self.service.call(req))) {
+            Ok(future) => future,
+            Err(payload) => {
+                let response = panic_response(&http_request, payload);
+                return Box::pin(async move { Ok(ServiceResponse::new(http_request, response)) });
+            }
+        };
+
+        Box::pin(async move {
+            match AssertUnwindSafe(future).catch_unwind().await {
+                Ok(result) => result.map(ServiceResponse::map_into_boxed_body),
+                Err(payload) => {
+                    let response = panic_response(&http_request, payload);
+                    Ok(ServiceResponse::new(http_request, response))
+                }
+            }
+        })
+    }
+}
+
+/// Log the panic with its request context and build the JSON 500 body
+fn panic_response(
+    request: &actix_web::HttpRequest,
+    payload: Box<dyn std::any::Any + Send>,
+) -> HttpResponse {
+    let message = payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic payload".to_string());
+
+    error!(
+        "Handler panicked while serving {} {} (user agent: {}): {}",
+        request.method(),
+        request.path(),
+        request
+            .headers()
+            .get("User-Agent")
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("-"),
+        message
+    );
+    telemetry::record_panic();
+
+    HttpResponse::InternalServerError().json(serde_json::json!({
+        "error": "Internal server error: the request handler panicked",
+        "code": "internal_error",
+        "status": StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test, web, App};
+
+    #[actix_web::test]
+    async fn test_panicking_handler_returns_json_500() {
+        let app = test::init_service(App::new().wrap(CatchPanic).route(
+            "/boom",
+            web::get().to(|| async {
+                panic!("boom");
+                #[allow(unreachable_code)]
+                HttpResponse::Ok().finish()
+            }),
+        ))
+        .await;
+
+        let response =
+            test::call_service(&app, test::TestRequest::get().uri("/boom").to_request()).await;
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        let body: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(body["code"], "internal_error");
+    }
+}
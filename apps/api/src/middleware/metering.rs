@@ -0,0 +1,142 @@
+//! Middleware that identifies tenants by their API key and meters their requests
+
+use crate::error::ApiError;
+use crate::services::usage::{metered, RequestUsage, UsageMeter};
+use actix_web::{
+    body::{BoxBody, MessageBody},
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    web, Error, HttpMessage,
+};
+use futures::future::{ready, LocalBoxFuture, Ready};
+use std::sync::Arc;
+
+/// Header carrying a tenant's API key
+pub const API_KEY_HEADER: &str = "X-Api-Key";
+
+/// Meters the requests that carry an `X-Api-Key`.
+///
+/// A key that belongs to no tenant is answered 401 and a tenant that used up
+/// a monthly quota 429; otherwise the tenant is put in the request extensions
+/// and the request, with the upstream calls it makes, is counted against it.
+/// Requests without a key, or served without a [`UsageMeter`], pass through.
+pub struct Metering;
+
+impl<S, B> Transform<S, ServiceRequest> for Metering
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Transform = MeteringMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(MeteringMiddleware { service }))
+    }
+}
+
+pub struct MeteringMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for MeteringMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let api_key = req
+            .headers()
+            .get(API_KEY_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+            .filter(|key| !key.is_empty())
+            .map(str::to_string);
+        let meter = req.app_data::<web::Data<UsageMeter>>().cloned();
+        let (Some(api_key), Some(meter)) = (api_key, meter) else {
+            let future = self.service.call(req);
+            return Box::pin(async move { future.await.map(ServiceResponse::map_into_boxed_body) });
+        };
+
+        let Some(tenant) = meter.tenant(&api_key) else {
+            let error = ApiError::AuthenticationError("Unknown API key".to_string());
+            return Box::pin(async move { Ok(req.error_response(error)) });
+        };
+        if let Err(error) = meter.check(&tenant) {
+            return Box::pin(async move { Ok(req.error_response(error)) });
+        }
+
+        req.extensions_mut().insert(tenant.clone());
+        let usage = Arc::new(RequestUsage::default());
+        let future = metered(usage.clone(), self.service.call(req));
+        Box::pin(async move {
+            let response = future.await;
+            meter.record(&tenant, &usage.counts());
+            response.map(ServiceResponse::map_into_boxed_body)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::usage::{month_of, record_pinecone_read, TenantConfig, UsageQuota};
+    use actix_web::{http::StatusCode, test, App, HttpResponse};
+
+    #[actix_web::test]
+    async fn test_tenants_are_metered_and_stopped_at_their_quota() {
+        let meter = UsageMeter::new(&[TenantConfig {
+            name: "acme".to_string(),
+            api_key: "key-acme".to_string(),
+            quota: UsageQuota {
+                requests: Some(1),
+                ..UsageQuota::default()
+            },
+            post_filters: None,
+        }]);
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(meter.clone()))
+                .wrap(Metering)
+                .route(
+                    "/search",
+                    web::get().to(|| async {
+                        record_pinecone_read();
+                        HttpResponse::Ok().finish()
+                    }),
+                ),
+        )
+        .await;
+        let call = |key: Option<&'static str>| {
+            let mut request = test::TestRequest::get().uri("/search");
+            if let Some(key) = key {
+                request = request.insert_header((API_KEY_HEADER, key));
+            }
+            request.to_request()
+        };
+
+        let anonymous = test::call_service(&app, call(None)).await;
+        assert_eq!(anonymous.status(), StatusCode::OK);
+        let unknown = test::call_service(&app, call(Some("key-other"))).await;
+        assert_eq!(unknown.status(), StatusCode::UNAUTHORIZED);
+
+        let first = test::call_service(&app, call(Some("key-acme"))).await;
+        assert_eq!(first.status(), StatusCode::OK);
+        let second = test::call_service(&app, call(Some("key-acme"))).await;
+        assert_eq!(second.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        let usage = meter.usage(&month_of(chrono::Utc::now())).await.unwrap();
+        assert_eq!(
+            (usage[0].usage.requests, usage[0].usage.pinecone_reads),
+            (1, 1)
+        );
+    }
+}
@@ -0,0 +1,9 @@
+pub mod admin_auth;
+pub mod catch_panic;
+pub mod metering;
+pub mod user_auth;
+
+pub use admin_auth::AdminAuth;
+pub use catch_panic::CatchPanic;
+pub use metering::Metering;
+pub use user_auth::UserAuth;
@@ -0,0 +1,184 @@
+//! Bearer token authentication for the per-user endpoints under `/api/users`
+
+use crate::middleware::admin_auth::{constant_time_eq, ADMIN_KEY_HEADER};
+use crate::services::catalog_events::hmac_sha256;
+use crate::{config::Config, error::ApiError};
+use actix_web::{dev::Payload, http::header, web, FromRequest, HttpRequest};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use futures::future::{ready, Ready};
+use serde::Deserialize;
+
+/// Extractor for the caller of a per-user endpoint.
+///
+/// Accepts an HS256 access token from the auth provider in
+/// `Authorization: Bearer`, verified against `user_jwt_secret`, or the admin
+/// key in `X-Admin-Key`. Handlers call [`UserAuth::authorize`] with the user id
+/// of the path, so a user can only reach their own data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UserAuth {
+    /// A user, identified by the token's `sub` claim
+    User(String),
+    /// The admin key, which may act on any user
+    Admin,
+}
+
+impl UserAuth {
+    /// Fail unless the caller may act on `user_id`'s data
+    pub fn authorize(&self, user_id: &str) -> Result<(), ApiError> {
+        match self {
+            UserAuth::Admin => Ok(()),
+            UserAuth::User(subject) if subject == user_id => Ok(()),
+            UserAuth::User(_) => Err(ApiError::AuthenticationError(format!(
+                "Not allowed to access the data of user {}",
+                user_id
+            ))),
+        }
+    }
+}
+
+impl FromRequest for UserAuth {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(authenticate(req))
+    }
+}
+
+fn authenticate(req: &HttpRequest) -> Result<UserAuth, ApiError> {
+    let config = req.app_data::<web::Data<Config>>();
+
+    if let Some(provided) = req
+        .headers()
+        .get(ADMIN_KEY_HEADER)
+        .and_then(|value| value.to_str().ok())
+    {
+        let admin_key = config.and_then(|config| config.admin_api_key.as_deref());
+        return match admin_key {
+            Some(expected) if constant_time_eq(provided.as_bytes(), expected.as_bytes()) => {
+                Ok(UserAuth::Admin)
+            }
+            _ => Err(ApiError::AuthenticationError(
+                "Missing or invalid admin key".to_string(),
+            )),
+        };
+    }
+
+    let token = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+        .ok_or_else(|| ApiError::AuthenticationError("Missing bearer token".to_string()))?;
+    let secret = config
+        .and_then(|config| config.user_jwt_secret.as_deref())
+        .ok_or_else(|| {
+            ApiError::AuthenticationError("User tokens aren't accepted by this server".to_string())
+        })?;
+
+    verify_token(token, secret, chrono::Utc::now().timestamp()).map(UserAuth::User)
+}
+
+#[derive(Deserialize)]
+struct TokenHeader {
+    alg: String,
+}
+
+#[derive(Deserialize)]
+struct TokenClaims {
+    sub: String,
+    exp: i64,
+}
+
+/// Check an HS256 token's signature and expiry, and return its subject
+fn verify_token(token: &str, secret: &str, now: i64) -> Result<String, ApiError> {
+    let invalid = || ApiError::AuthenticationError("Invalid bearer token".to_string());
+
+    let (signed, signature) = token.rsplit_once('.').ok_or_else(invalid)?;
+    let (header, claims) = signed.split_once('.').ok_or_else(invalid)?;
+
+    let header: TokenHeader = decode_part(header).ok_or_else(invalid)?;
+    if header.alg != "HS256" {
+        return Err(invalid());
+    }
+    let signature = URL_SAFE_NO_PAD.decode(signature).map_err(|_| invalid())?;
+    if !constant_time_eq(
+        &signature,
+        &hmac_sha256(secret.as_bytes(), signed.as_bytes()),
+    ) {
+        return Err(invalid());
+    }
+
+    let claims: TokenClaims = decode_part(claims).ok_or_else(invalid)?;
+    if claims.exp <= now {
+        return Err(ApiError::AuthenticationError(
+            "Bearer token has expired".to_string(),
+        ));
+    }
+    if claims.sub.is_empty() {
+        return Err(invalid());
+    }
+    Ok(claims.sub)
+}
+
+fn decode_part<T: serde::de::DeserializeOwned>(part: &str) -> Option<T> {
+    let bytes = URL_SAFE_NO_PAD.decode(part).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    const SECRET: &str = "test-secret";
+
+    /// An HS256 token with `claims`, signed with `secret`
+    pub(crate) fn token(claims: serde_json::Value, secret: &str) -> String {
+        let header = URL_SAFE_NO_PAD.encode(r#"{"alg":"HS256","typ":"JWT"}"#);
+        let claims = URL_SAFE_NO_PAD.encode(claims.to_string());
+        let signed = format!("{}.{}", header, claims);
+        let signature = URL_SAFE_NO_PAD.encode(hmac_sha256(secret.as_bytes(), signed.as_bytes()));
+        format!("{}.{}", signed, signature)
+    }
+
+    #[test]
+    fn test_valid_tokens_yield_their_subject() {
+        let token = token(serde_json::json!({"sub": "user-1", "exp": 2000}), SECRET);
+        assert_eq!(verify_token(&token, SECRET, 1000).unwrap(), "user-1");
+    }
+
+    #[test]
+    fn test_forged_expired_and_malformed_tokens_are_rejected() {
+        let claims = serde_json::json!({"sub": "user-1", "exp": 2000});
+        for (token, now) in [
+            (token(claims.clone(), "other-secret"), 1000),
+            (token(claims.clone(), SECRET), 2000),
+            (token(serde_json::json!({"exp": 2000}), SECRET), 1000),
+            ("not-a-token".to_string(), 1000),
+        ] {
+            assert!(matches!(
+                verify_token(&token, SECRET, now),
+                Err(ApiError::AuthenticationError(_))
+            ));
+        }
+
+        let unsigned = format!(
+            "{}.{}.",
+            URL_SAFE_NO_PAD.encode(r#"{"alg":"none"}"#),
+            URL_SAFE_NO_PAD.encode(claims.to_string())
+        );
+        assert!(verify_token(&unsigned, SECRET, 1000).is_err());
+    }
+
+    #[test]
+    fn test_users_are_only_authorized_for_themselves() {
+        let user = UserAuth::User("user-1".to_string());
+        assert!(user.authorize("user-1").is_ok());
+        assert!(matches!(
+            user.authorize("user-2"),
+            Err(ApiError::AuthenticationError(_))
+        ));
+        assert!(UserAuth::Admin.authorize("user-2").is_ok());
+    }
+}
@@ -0,0 +1,67 @@
+//! The text embedding model queries and books are embedded with
+//!
+//! [`HuggingFaceEmbedder`] calls the inference API in production. The trait
+//! lets tests, and users of the crate, swap in another model, such as the
+//! deterministic one of the `test_support` module.
+
+use crate::error::ApiError;
+use crate::ml::huggingface_embedder::HuggingFaceEmbedder;
+use futures::future::BoxFuture;
+
+pub trait Embedder: Send + Sync {
+    /// The configured model's name and the size of its embeddings
+    fn model_info(&self) -> (String, usize);
+
+    /// Embed `text` with another model embedding into the same space, or the
+    /// configured one for None
+    fn encode_with_model<'a>(
+        &'a self,
+        text: &'a str,
+        model: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<Vec<f32>, ApiError>>;
+
+    /// Embed any number of texts, in order
+    fn encode_batch<'a>(
+        &'a self,
+        texts: &'a [String],
+    ) -> BoxFuture<'a, Result<Vec<Vec<f32>>, ApiError>>;
+
+    /// Get the model ready to serve; true the first time it's done
+    fn prewarm(&self) -> BoxFuture<'_, Result<bool, ApiError>>;
+
+    fn encode<'a>(&'a self, text: &'a str) -> BoxFuture<'a, Result<Vec<f32>, ApiError>> {
+        self.encode_with_model(text, None)
+    }
+}
+
+impl Embedder for HuggingFaceEmbedder {
+    fn model_info(&self) -> (String, usize) {
+        HuggingFaceEmbedder::model_info(self)
+    }
+
+    fn encode_with_model<'a>(
+        &'a self,
+        text: &'a str,
+        model: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<Vec<f32>, ApiError>> {
+        Box::pin(HuggingFaceEmbedder::encode_with_model(self, text, model))
+    }
+
+    fn encode_batch<'a>(
+        &'a self,
+        texts: &'a [String],
+    ) -> BoxFuture<'a, Result<Vec<Vec<f32>>, ApiError>> {
+        Box::pin(async move {
+            let embeddings = self.encode_large_batch(texts, None).await?;
+            Ok(embeddings
+                .rows()
+                .into_iter()
+                .map(|row| row.to_vec())
+                .collect())
+        })
+    }
+
+    fn prewarm(&self) -> BoxFuture<'_, Result<bool, ApiError>> {
+        Box::pin(HuggingFaceEmbedder::prewarm(self))
+    }
+}
@@ -1,4 +1,4 @@
-use crate::error::ApiError;
+use crate::error::{retry_after_from_headers, ApiError};
 use ndarray::{Array1, Array2};
 use reqwest::Client;
 use serde_json::json;
@@ -19,24 +19,45 @@ const BATCH_SIZE_LIMIT: usize = 20; // Maximum number of texts to process in a s
 
 /// Text processing limits
 const MAX_TEXT_PREVIEW_LENGTH: usize = 100;
-// LRU cache size for embedding results
-#[allow(dead_code)]
-const EMBEDDING_CACHE_SIZE: usize = 100;
+// Embedding cache size and lifetime; embeddings only change with the model, which is part of the key
+const EMBEDDING_CACHE_SIZE: usize = 1000;
+const EMBEDDING_CACHE_TTL: std::time::Duration = std::time::Duration::from_secs(24 * 60 * 60);
 
+use crate::cache::{SharedCache, TtlCache};
+use crate::services::http_client::upstream_client;
+use crate::services::{deadline, usage};
 use lazy_static::lazy_static;
-use std::num::NonZeroUsize;
+use once_cell::sync::OnceCell;
 use std::sync::{Arc, RwLock};
 
 lazy_static! {
     // Global embedding cache to improve performance and reduce API calls
-    static ref EMBEDDING_CACHE: RwLock<lru::LruCache<String, Vec<f32>>> =
-        RwLock::new(lru::LruCache::new(NonZeroUsize::new(EMBEDDING_CACHE_SIZE).unwrap()));
+    static ref EMBEDDING_CACHE: TtlCache<String, Vec<f32>> =
+        TtlCache::new("embeddings", EMBEDDING_CACHE_SIZE, EMBEDDING_CACHE_TTL);
 
     // Flag to track if prewarming has been done
     static ref PREWARM_COMPLETED: std::sync::atomic::AtomicBool =
         std::sync::atomic::AtomicBool::new(false);
 }
 
+/// Where embeddings are also kept beyond the process, if anywhere
+static EMBEDDING_STORE: OnceCell<Arc<dyn SharedCache>> = OnceCell::new();
+
+/// Also keep embeddings in `store`, e.g. on disk so they survive restarts
+///
+/// Only the first store set takes effect.
+pub fn persist_embeddings(store: Arc<dyn SharedCache>) {
+    let _ = EMBEDDING_STORE.set(store);
+}
+
+/// The embedding cache, falling back to the persistent store when one is set
+fn embedding_cache() -> TtlCache<String, Vec<f32>> {
+    match EMBEDDING_STORE.get() {
+        Some(store) => EMBEDDING_CACHE.clone().with_shared(store.clone()),
+        None => EMBEDDING_CACHE.clone(),
+    }
+}
+
 #[derive(Clone)]
 
 pub struct HuggingFaceEmbedder {
@@ -47,6 +68,8 @@ pub struct HuggingFaceEmbedder {
     // Initialization status and parameters
     initialized: Arc<AtomicBool>,
     deferred_init: bool,
+    /// Attempts and delay between them in milliseconds, over the environment's
+    retry: Option<(u32, u64)>,
 }
 
 impl HuggingFaceEmbedder {
@@ -88,14 +111,7 @@ impl HuggingFaceEmbedder {
         let model_name = env::var("APP_HUGGINGFACE_MODEL_NAME")
             .unwrap_or_else(|_| DEFAULT_MODEL_NAME.to_string());
 
-        // Create client with optimized connection settings for better cold starts
-        let client = Client::builder()
-            .timeout(std::time::Duration::from_secs(timeout_seconds))
-            // Increase connection pool to handle concurrent requests better
-            .pool_max_idle_per_host(10)
-            // Add connection timeout separate from request timeout
-            .connect_timeout(std::time::Duration::from_secs(10))
-            .build()
+        let client = upstream_client(std::time::Duration::from_secs(timeout_seconds))
             .map_err(|e| ApiError::InternalError(format!("Failed to create HTTP client: {}", e)))?;
 
         let model_url = format!("{}/models/{}", base_url, model_name);
@@ -111,6 +127,7 @@ impl HuggingFaceEmbedder {
             model_name: Arc::new(RwLock::new(model_name)),
             initialized: Arc::new(std::sync::atomic::AtomicBool::new(false)),
             deferred_init: false,
+            retry: None,
         };
 
         info!("HuggingFace encoder created (not yet initialized)");
@@ -126,9 +143,7 @@ impl HuggingFaceEmbedder {
         info!("Creating HuggingFace encoder with deferred initialization");
 
         // Create a default client that will be replaced on first use
-        let client = Client::builder()
-            .timeout(std::time::Duration::from_secs(30))
-            .build()
+        let client = upstream_client(std::time::Duration::from_secs(30))
             .map_err(|e| ApiError::InternalError(format!("Failed to create HTTP client: {}", e)))?;
 
         let encoder = Self {
@@ -138,12 +153,45 @@ impl HuggingFaceEmbedder {
             model_name: Arc::new(RwLock::new(String::from(DEFAULT_MODEL_NAME))),
             initialized: Arc::new(std::sync::atomic::AtomicBool::new(false)),
             deferred_init: true,
+            retry: None,
         };
 
         info!("HuggingFace encoder created with deferred initialization");
         Ok(encoder)
     }
 
+    /// Create an encoder calling the inference API at `base_url` instead of the
+    /// one configured in the environment, such as a mock of it in tests
+    ///
+    /// The encoder counts as initialized, so its first request isn't preceded
+    /// by a connection probe.
+    pub fn with_endpoint(
+        base_url: &str,
+        model_name: &str,
+        api_key: &str,
+        timeout: std::time::Duration,
+    ) -> Result<Self, ApiError> {
+        let client = upstream_client(timeout)
+            .map_err(|e| ApiError::InternalError(format!("Failed to create HTTP client: {}", e)))?;
+
+        Ok(Self {
+            client: Arc::new(RwLock::new(client)),
+            api_key: Arc::new(RwLock::new(api_key.to_string())),
+            model_url: Arc::new(RwLock::new(format!("{}/models/{}", base_url, model_name))),
+            model_name: Arc::new(RwLock::new(model_name.to_string())),
+            initialized: Arc::new(std::sync::atomic::AtomicBool::new(true)),
+            deferred_init: false,
+            retry: None,
+        })
+    }
+
+    /// Make `attempts` attempts at each request, `delay` apart, instead of the
+    /// number configured in the environment
+    pub fn with_retries(mut self, attempts: u32, delay: std::time::Duration) -> Self {
+        self.retry = Some((attempts.max(1), delay.as_millis() as u64));
+        self
+    }
+
     /// Prewarm the encoder to avoid cold start delays
     ///
     /// This method:
@@ -248,11 +296,7 @@ impl HuggingFaceEmbedder {
                 let mut client_guard = self.client.write().map_err(|_| {
                     ApiError::InternalError("Failed to acquire write lock for client".to_string())
                 })?;
-                *client_guard = Client::builder()
-                    .timeout(std::time::Duration::from_secs(timeout_seconds))
-                    .pool_max_idle_per_host(10)
-                    .connect_timeout(std::time::Duration::from_secs(10))
-                    .build()
+                *client_guard = upstream_client(std::time::Duration::from_secs(timeout_seconds))
                     .map_err(|e| {
                         ApiError::InternalError(format!("Failed to create HTTP client: {}", e))
                     })?;
@@ -291,15 +335,27 @@ impl HuggingFaceEmbedder {
     }
 
     pub async fn encode(&self, text: &str) -> Result<Vec<f32>, ApiError> {
+        self.encode_with_model(text, None).await
+    }
+
+    /// Encode with another model on the same inference endpoint, or the configured one for None
+    ///
+    /// Only useful for models that embed into the same space as the configured one.
+    pub async fn encode_with_model(
+        &self,
+        text: &str,
+        model: Option<&str>,
+    ) -> Result<Vec<f32>, ApiError> {
         // Ensure the encoder is initialized
         self.ensure_initialized().await?;
-        let model_name = self
+        let configured_model = self
             .model_name
             .read()
             .map_err(|_| {
                 ApiError::InternalError("Failed to acquire read lock for model_name".to_string())
             })?
             .clone();
+        let model_name = model.map(str::to_string).unwrap_or(configured_model);
 
         info!(
             "Encoding text with {}: '{}' (text length: {})",
@@ -320,6 +376,13 @@ impl HuggingFaceEmbedder {
             ));
         }
 
+        let cache_key = format!("{}\n{}", model_name, processed_text);
+        let cache = embedding_cache();
+        if let Some(embedding) = cache.get_shared(&cache_key).await {
+            debug!("Embedding cache hit");
+            return Ok(embedding);
+        }
+
         // Load retry configuration
         let retry_config = self.get_retry_config();
         let retry_attempts = retry_config.0;
@@ -341,7 +404,7 @@ impl HuggingFaceEmbedder {
                 attempt, retry_attempts
             );
 
-            match self.make_api_request(&request_json).await {
+            match self.make_model_request(&request_json, model).await {
                 Ok(response) => {
                     // If successful, process the response
                     info!(
@@ -354,15 +417,26 @@ impl HuggingFaceEmbedder {
                         attempt,
                         response.status()
                     );
-                    return self.process_api_response(response).await;
+                    // Reading the body counts against the deadline as much as sending did
+                    let embedding =
+                        deadline::run("HuggingFace", self.process_api_response(response)).await?;
+                    cache
+                        .insert_shared(cache_key, embedding.clone(), cache.ttl())
+                        .await;
+                    return Ok(embedding);
                 }
                 Err(e) => {
                     // Store the error and retry if it's retryable
                     error!("Attempt {}/{} failed: {}", attempt, retry_attempts, e);
 
+                    let delay = std::time::Duration::from_millis(retry_delay_ms);
+                    if attempt < retry_attempts && !deadline::allows_retry(delay) {
+                        info!("No time left before the request deadline to retry");
+                        return Err(e);
+                    }
                     if attempt < retry_attempts {
                         info!("Waiting {}ms before retry...", retry_delay_ms);
-                        tokio::time::sleep(std::time::Duration::from_millis(retry_delay_ms)).await;
+                        tokio::time::sleep(delay).await;
                     }
                     last_error = Some(e);
                 }
@@ -379,6 +453,15 @@ impl HuggingFaceEmbedder {
     async fn make_api_request(
         &self,
         payload: &serde_json::Value,
+    ) -> Result<reqwest::Response, ApiError> {
+        self.make_model_request(payload, None).await
+    }
+
+    /// Make a single API request to the given model, or the configured one for None
+    async fn make_model_request(
+        &self,
+        payload: &serde_json::Value,
+        model: Option<&str>,
     ) -> Result<reqwest::Response, ApiError> {
         // Clone all needed values to avoid holding locks across await points
         let client_clone = {
@@ -392,7 +475,14 @@ impl HuggingFaceEmbedder {
             let model_url = self.model_url.read().map_err(|_| {
                 ApiError::InternalError("Failed to acquire read lock for model_url".to_string())
             })?;
-            model_url.clone()
+            match model {
+                // Model URLs are always "{base_url}/models/{model_name}"
+                Some(model) => match model_url.rfind("/models/") {
+                    Some(idx) => format!("{}/models/{}", &model_url[..idx], model),
+                    None => model_url.clone(),
+                },
+                None => model_url.clone(),
+            }
         };
 
         let api_key_clone = {
@@ -403,22 +493,28 @@ impl HuggingFaceEmbedder {
         };
 
         // Now we can safely use the cloned values across await points
-        client_clone
+        usage::record_embedding_call();
+        let request = client_clone
             .post(model_url_clone.as_str())
             .header("Authorization", format!("Bearer {}", api_key_clone))
             .header("Content-Type", "application/json")
             .json(payload)
-            .send()
-            .await
-            .map_err(|e| {
+            .send();
+        deadline::run("HuggingFace", async {
+            request.await.map_err(|e| {
                 error!("Failed to call HuggingFace API: {}", e);
                 ApiError::ExternalServiceError(format!("HuggingFace API request failed: {}", e))
             })
+        })
+        .await
     }
 
     /// Process the API response
     /// Get retry configuration from environment variables
     fn get_retry_config(&self) -> (u32, u64) {
+        if let Some(retry) = self.retry {
+            return retry;
+        }
         let retry_attempts = env::var("APP_HUGGINGFACE_RETRY_ATTEMPTS")
             .ok()
             .and_then(|s| s.parse().ok())
@@ -443,6 +539,7 @@ impl HuggingFaceEmbedder {
         );
 
         if !status.is_success() {
+            let retry_after = retry_after_from_headers(response.headers());
             let error_text = response.text().await.unwrap_or_default();
             error!("HuggingFace API error {}: {}", status, error_text);
 
@@ -453,11 +550,13 @@ impl HuggingFaceEmbedder {
                 403 => Err(ApiError::AuthenticationError(
                     "HuggingFace API access forbidden".to_string(),
                 )),
-                429 => Err(ApiError::ExternalServiceError(
-                    "HuggingFace API rate limit exceeded".to_string(),
+                429 => Err(ApiError::service_unavailable(
+                    "HuggingFace API rate limit exceeded",
+                    retry_after,
                 )),
-                503 => Err(ApiError::ExternalServiceError(
-                    "HuggingFace model is currently loading".to_string(),
+                503 => Err(ApiError::service_unavailable(
+                    "HuggingFace model is currently loading",
+                    retry_after.or_else(|| Self::estimated_load_time(&error_text)),
                 )),
                 _ => Err(ApiError::ExternalServiceError(format!(
                     "HuggingFace API error {}: {}",
@@ -550,16 +649,7 @@ impl HuggingFaceEmbedder {
             model_name
         );
 
-        // Get retry attempts from env or use default
-        let retry_attempts = env::var("APP_HUGGINGFACE_RETRY_ATTEMPTS")
-            .ok()
-            .and_then(|s| s.parse().ok())
-            .unwrap_or(DEFAULT_RETRY_ATTEMPTS);
-
-        let retry_delay_ms = env::var("APP_HUGGINGFACE_RETRY_DELAY_MS")
-            .ok()
-            .and_then(|s| s.parse().ok())
-            .unwrap_or(DEFAULT_RETRY_DELAY_MS);
+        let (retry_attempts, retry_delay_ms) = self.get_retry_config();
 
         // Prepare request payload
         let request_json = json!({
@@ -588,16 +678,25 @@ impl HuggingFaceEmbedder {
                     }
 
                     // Process successful response
-                    let result = self.process_batch_response(response, texts.len()).await?;
+                    let result = deadline::run(
+                        "HuggingFace",
+                        self.process_batch_response(response, texts.len()),
+                    )
+                    .await?;
                     return Ok(result.rows().into_iter().map(|r| r.to_vec()).collect());
                 }
                 Err(e) => {
                     error!("Batch attempt {}/{} failed: {}", attempt, retry_attempts, e);
 
+                    let backoff =
+                        std::time::Duration::from_millis(retry_delay_ms * (2_u64.pow(attempt - 1)));
+                    if attempt < retry_attempts && !deadline::allows_retry(backoff) {
+                        info!("No time left before the request deadline to retry");
+                        return Err(e);
+                    }
                     if attempt < retry_attempts {
-                        let backoff_ms = retry_delay_ms * (2_u64.pow(attempt - 1));
-                        info!("Waiting {}ms before retry...", backoff_ms);
-                        tokio::time::sleep(std::time::Duration::from_millis(backoff_ms)).await;
+                        info!("Waiting {}ms before retry...", backoff.as_millis());
+                        tokio::time::sleep(backoff).await;
                     }
                     last_error = Some(e);
                 }
@@ -618,6 +717,7 @@ impl HuggingFaceEmbedder {
     ) -> Result<Array2<f32>, ApiError> {
         let status = response.status();
         if !status.is_success() {
+            let retry_after = retry_after_from_headers(response.headers());
             let error_text = response.text().await.unwrap_or_default();
             error!("HuggingFace API batch error {}: {}", status, error_text);
 
@@ -628,11 +728,13 @@ impl HuggingFaceEmbedder {
                 403 => Err(ApiError::AuthenticationError(
                     "HuggingFace API access forbidden".to_string(),
                 )),
-                429 => Err(ApiError::ExternalServiceError(
-                    "HuggingFace API rate limit exceeded".to_string(),
+                429 => Err(ApiError::service_unavailable(
+                    "HuggingFace API rate limit exceeded",
+                    retry_after,
                 )),
-                503 => Err(ApiError::ExternalServiceError(
-                    "HuggingFace model is currently loading".to_string(),
+                503 => Err(ApiError::service_unavailable(
+                    "HuggingFace model is currently loading",
+                    retry_after.or_else(|| Self::estimated_load_time(&error_text)),
                 )),
                 _ => Err(ApiError::ExternalServiceError(format!(
                     "HuggingFace API batch error {}: {}",
@@ -691,6 +793,15 @@ impl HuggingFaceEmbedder {
         )
     }
 
+    /// Read the `estimated_time` hint HuggingFace includes while a model is loading
+    fn estimated_load_time(error_body: &str) -> Option<u64> {
+        serde_json::from_str::<serde_json::Value>(error_body)
+            .ok()?
+            .get("estimated_time")?
+            .as_f64()
+            .map(|seconds| seconds.max(0.0).ceil() as u64)
+    }
+
     /// Improved text preprocessing that preserves more semantic information
     fn preprocess_text(&self, text: &str) -> String {
         text.trim()
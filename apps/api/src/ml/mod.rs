@@ -1 +1,3 @@
+pub mod embedder;
 pub mod huggingface_embedder;
+pub mod sparse_encoder;
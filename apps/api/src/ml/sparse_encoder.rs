@@ -0,0 +1,144 @@
+//! Sparse keyword vectors for Pinecone
+//!
+//! When the embedding service is down a query can't be embedded, so the
+//! fallback search matches its words instead. The indexer stores a sparse
+//! vector of each book's words next to its embedding, and the query's words are
+//! scored against those with a sparse query, ranking books by the words they
+//! share with it rather than filtering on exact metadata values. Words are
+//! hashed into the index space, so the indexer and the API share no vocabulary
+//! beyond this code. Sparse vectors need an index with the dotproduct metric.
+
+use crate::models::Book;
+use crate::services::templates::STOP_WORDS;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// A sparse vector, as Pinecone takes it: the indices of its non-zero values and those values
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SparseValues {
+    pub indices: Vec<u32>,
+    pub values: Vec<f32>,
+}
+
+/// Weight of a word in each field of a book; a title or author word says most about it
+const TITLE_WEIGHT: f32 = 3.0;
+const AUTHOR_WEIGHT: f32 = 3.0;
+const CATEGORY_WEIGHT: f32 = 2.0;
+const DESCRIPTION_WEIGHT: f32 = 1.0;
+
+/// Saturation of repeated words, as BM25's k1: a word's value approaches 1 the
+/// more it's used, so a description repeating one word doesn't outweigh a title
+const SATURATION: f32 = 1.2;
+
+/// Shortest word worth matching on
+const MIN_WORD_CHARS: usize = 2;
+
+/// A word folded so its plural matches it, e.g. "dragon" for "dragons"
+fn fold(word: &str) -> String {
+    let word = word.to_lowercase();
+    match word.strip_suffix('s') {
+        Some(stem) if stem.chars().count() > 2 && !stem.ends_with('s') => stem.to_string(),
+        _ => word,
+    }
+}
+
+/// Folded words of `text` worth matching on
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() >= MIN_WORD_CHARS)
+        .filter(|word| !STOP_WORDS.contains(&word.to_lowercase().as_str()))
+        .map(fold)
+}
+
+/// Index of a word: its 32-bit FNV-1a hash, which is the same on every build and platform
+fn word_index(word: &str) -> u32 {
+    word.bytes().fold(0x811c_9dc5, |hash: u32, byte| {
+        (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+/// The sparse vector of weighted word counts, or None if there are no words
+fn sparse_vector(counts: BTreeMap<u32, f32>, value: impl Fn(f32) -> f32) -> Option<SparseValues> {
+    if counts.is_empty() {
+        return None;
+    }
+    let (indices, values) = counts
+        .into_iter()
+        .map(|(index, count)| (index, value(count)))
+        .unzip();
+    Some(SparseValues { indices, values })
+}
+
+/// Keyword vector of a book's title, author, categories, genres and description
+pub fn encode_book(book: &Book) -> Option<SparseValues> {
+    let fields = [
+        (book.title.as_deref(), TITLE_WEIGHT),
+        (book.author.as_deref(), AUTHOR_WEIGHT),
+        (book.description.as_deref(), DESCRIPTION_WEIGHT),
+    ]
+    .into_iter()
+    .filter_map(|(text, weight)| text.map(|text| (text, weight)))
+    .chain(
+        book.categories
+            .iter()
+            .chain(&book.genres)
+            .map(|category| (category.as_str(), CATEGORY_WEIGHT)),
+    );
+
+    let mut counts: BTreeMap<u32, f32> = BTreeMap::new();
+    for (text, weight) in fields {
+        for word in words(text) {
+            *counts.entry(word_index(&word)).or_default() += weight;
+        }
+    }
+    sparse_vector(counts, |count| count / (count + SATURATION))
+}
+
+/// Keyword vector of a query, each word counted once
+pub fn encode_query(query: &str) -> Option<SparseValues> {
+    let counts: BTreeMap<u32, f32> = words(query).map(|word| (word_index(&word), 1.0)).collect();
+    sparse_vector(counts, |count| count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dot(a: &SparseValues, b: &SparseValues) -> f32 {
+        a.indices
+            .iter()
+            .zip(&a.values)
+            .filter_map(|(index, value)| {
+                let position = b.indices.iter().position(|other| other == index)?;
+                Some(value * b.values[position])
+            })
+            .sum()
+    }
+
+    fn book(title: &str, description: &str) -> Book {
+        serde_json::from_value(serde_json::json!({
+            "title": title,
+            "description": description,
+            "categories": ["Fiction"],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_books_sharing_more_query_words_score_higher() {
+        let query = encode_query("Books about dragons and wizards").unwrap();
+        assert_eq!(query.indices.len(), 2);
+
+        let title_match = encode_book(&book("Dragon Wizards", "A quiet tale.")).unwrap();
+        let description_match =
+            encode_book(&book("The Long Road", "Dragons and more dragons.")).unwrap();
+        let no_match = encode_book(&book("Gardening", "Roses and tulips.")).unwrap();
+
+        assert!(dot(&query, &title_match) > dot(&query, &description_match));
+        assert!(dot(&query, &description_match) > 0.0);
+        assert_eq!(dot(&query, &no_match), 0.0);
+        assert!(title_match.values.iter().all(|value| *value < 1.0));
+
+        assert!(encode_query("the books").is_none());
+    }
+}
@@ -1,3 +1,4 @@
+use crate::cache::CacheWeight;
 use serde::{Deserialize, Deserializer, Serialize};
 use std::str::FromStr;
 use utoipa::ToSchema;
@@ -56,6 +57,8 @@ where
     enum StringOrInt {
         String(String),
         Int(i32),
+        // Pinecone stores every number as a float
+        Float(f64),
         Null,
     }
 
@@ -70,6 +73,11 @@ where
             }
         }
         StringOrInt::Int(i) => Ok(Some(i)),
+        StringOrInt::Float(f) if f.fract() == 0.0 => Ok(Some(f as i32)),
+        StringOrInt::Float(f) => Err(serde::de::Error::custom(format!(
+            "expected a whole number, got {}",
+            f
+        ))),
         StringOrInt::Null => Ok(None),
     }
 }
@@ -100,6 +108,37 @@ pub struct Book {
     #[schema(example = json!(["Fantasy", "Adventure", "Classic Literature"]))]
     pub categories: Vec<String>,
 
+    /// Genres from the controlled vocabulary, mapped from `categories` by the category taxonomy
+    #[serde(default)]
+    #[schema(example = json!(["fantasy", "adventure"]))]
+    pub genres: Vec<String>,
+
+    /// How the book feels to read, from the mood vocabulary; tagged when indexing
+    #[serde(default)]
+    #[schema(example = json!(["whimsical", "suspenseful"]))]
+    pub moods: Vec<String>,
+
+    /// Literary awards the book won, e.g. "Hugo Award"; tagged when indexing
+    #[serde(default)]
+    #[schema(example = json!(["Hugo Award"]))]
+    pub awards: Vec<String>,
+
+    /// Places the book is set in, with the regions containing them; tagged when indexing
+    #[serde(default)]
+    #[schema(example = json!(["London", "England", "United Kingdom", "Europe"]))]
+    pub settings: Vec<String>,
+
+    /// Content warnings from the flag vocabulary, e.g. "violence"; tagged when indexing
+    #[serde(default)]
+    #[schema(example = json!(["violence"]))]
+    pub content_flags: Vec<String>,
+
+    /// ISO 3166-1 alpha-2 codes of the markets the book is published in, from
+    /// publisher data found during enrichment; empty when unknown
+    #[serde(default)]
+    #[schema(example = json!(["GB", "US"]))]
+    pub available_regions: Vec<String>,
+
     /// Thumbnail image URL for the book cover
     #[serde(alias = "image_url")]
     #[schema(example = "https://example.com/book-cover.jpg")]
@@ -148,6 +187,20 @@ pub struct Book {
     #[schema(example = "Houghton Mifflin Harcourt")]
     pub publisher: Option<String>,
 
+    /// Revision of the stored metadata, bumped whenever it changes
+    #[serde(
+        default,
+        deserialize_with = "deserialize_optional_i32",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[schema(example = 3)]
+    pub version: Option<i32>,
+
+    /// When the stored metadata last changed, in RFC3339 format
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = "2024-01-15T10:30:00Z")]
+    pub updated_at: Option<String>,
+
     /// Relevance indicators showing why this book was recommended
     #[serde(default)]
     #[schema(example = json!(["Fantasy", "Adventure", "Magic"]))]
@@ -158,6 +211,45 @@ pub struct Book {
     #[schema(example = 0.95, minimum = 0.0, maximum = 1.0)]
     pub confidence_score: f32,
 
+    /// One sentence on why the book fits the query, for the top results when explanations are requested
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(
+        example = "A witty, fast-moving quest with dragons and a reluctant hero, just the light fantasy you asked for."
+    )]
+    pub explanation: Option<String>,
+}
+
+impl CacheWeight for Book {
+    fn heap_size(&self) -> usize {
+        [
+            &self.id,
+            &self.title,
+            &self.author,
+            &self.description,
+            &self.thumbnail,
+            &self.isbn,
+            &self.language,
+            &self.publisher,
+            &self.updated_at,
+            &self.explanation,
+        ]
+        .iter()
+        .map(|field| field.heap_size())
+        .sum::<usize>()
+            + [
+                &self.categories,
+                &self.genres,
+                &self.moods,
+                &self.awards,
+                &self.settings,
+                &self.content_flags,
+                &self.available_regions,
+                &self.relevance_indicators,
+            ]
+            .iter()
+            .map(|field| field.heap_size())
+            .sum::<usize>()
+    }
 }
 
 /// Book recommendation with similarity score
@@ -0,0 +1,171 @@
+//! Error bodies and the reusable error responses of the OpenAPI document
+//!
+//! Every error is returned as an [`ErrorResponse`] whose `code` is one of the
+//! [`ErrorCode`]s. Responses shared by many endpoints (admin authentication,
+//! upstream and server failures) are declared once here; endpoint-specific 400s
+//! and 404s are described next to their handlers.
+
+use serde::{Deserialize, Serialize};
+use utoipa::{ToResponse, ToSchema};
+
+/// Error response structure
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ErrorResponse {
+    /// Error message
+    #[schema(example = "Invalid input: Query cannot be empty")]
+    pub error: String,
+    /// Machine-readable error code
+    #[schema(value_type = ErrorCode, example = "invalid_input")]
+    pub code: String,
+    /// HTTP status code
+    #[schema(example = 400)]
+    pub status: u16,
+}
+
+/// Machine-readable error codes, each always returned with the same HTTP status
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// 404: the requested resource doesn't exist
+    NotFound,
+    /// 400: the request is malformed or out of range
+    InvalidInput,
+    /// 500: reading or writing user data failed
+    DatabaseError,
+    /// 502: an upstream service answered with an error
+    ExternalServiceError,
+    /// 503: the embedding model isn't loaded yet
+    ModelLoadError,
+    /// 502: the embedding model failed on the input
+    ModelInferenceError,
+    /// 500: a response couldn't be serialized
+    SerializationError,
+    /// 401: the admin key is missing or wrong, admin endpoints are disabled, or the X-Api-Key is unknown
+    AuthenticationError,
+    /// 500: an unexpected failure
+    InternalError,
+    /// 502: the vector search failed
+    PineconeError,
+    /// 503: overloaded or throttled; retry after the `Retry-After` header
+    ServiceUnavailable,
+    /// 429: the API key used up one of its monthly quotas; retry after the `Retry-After` header
+    QuotaExceeded,
+}
+
+/// An API key used up one of its monthly quotas
+#[derive(ToResponse)]
+#[response(
+    description = "The request's X-Api-Key used up one of its monthly quotas (quota_exceeded)",
+    headers(
+        ("Retry-After" = u64, description = "Seconds until the quotas reset at the start of next month")
+    ),
+    example = json!({
+        "error": "Quota exceeded: acme used its 10000 requests for 2026-10",
+        "code": "quota_exceeded",
+        "status": 429
+    })
+)]
+pub struct TooManyRequests(ErrorResponse);
+
+/// Missing or invalid admin key
+#[derive(ToResponse)]
+#[response(
+    description = "Missing or invalid X-Admin-Key header, or admin endpoints are disabled (authentication_error)",
+    examples(
+        ("Invalid key" = (value = json!({
+            "error": "Authentication error: Missing or invalid admin key",
+            "code": "authentication_error",
+            "status": 401
+        }))),
+        ("Disabled" = (value = json!({
+            "error": "Authentication error: Admin endpoints are disabled",
+            "code": "authentication_error",
+            "status": 401
+        })))
+    )
+)]
+pub struct Unauthorized(ErrorResponse);
+
+/// Missing or invalid user token, or a token of another user
+#[derive(ToResponse)]
+#[response(
+    description = "Missing or invalid bearer token or X-Admin-Key header, or a token of another user (authentication_error)",
+    examples(
+        ("Invalid token" = (value = json!({
+            "error": "Authentication error: Invalid bearer token",
+            "code": "authentication_error",
+            "status": 401
+        }))),
+        ("Other user" = (value = json!({
+            "error": "Authentication error: Not allowed to access the data of user 42",
+            "code": "authentication_error",
+            "status": 401
+        })))
+    )
+)]
+pub struct UserUnauthorized(ErrorResponse);
+
+/// Unexpected server-side failure
+#[derive(ToResponse)]
+#[response(
+    description = "Server-side failure (database_error, internal_error or serialization_error)",
+    examples(
+        ("Database" = (value = json!({
+            "error": "Database error: pool timed out while waiting for an open connection",
+            "code": "database_error",
+            "status": 500
+        }))),
+        ("Internal" = (value = json!({
+            "error": "Internal server error: Lock poisoned: poisoned lock: another task failed inside",
+            "code": "internal_error",
+            "status": 500
+        })))
+    )
+)]
+pub struct InternalServerError(ErrorResponse);
+
+/// An upstream service failed
+#[derive(ToResponse)]
+#[response(
+    description = "An upstream service failed (pinecone_error, external_service_error or model_inference_error)",
+    examples(
+        ("Vector search" = (value = json!({
+            "error": "Pinecone error: query failed with status 500 Internal Server Error",
+            "code": "pinecone_error",
+            "status": 502
+        }))),
+        ("Upstream" = (value = json!({
+            "error": "External service error: Neo4j error: connection refused",
+            "code": "external_service_error",
+            "status": 502
+        }))),
+        ("Embedding" = (value = json!({
+            "error": "Model inference failed: embedding has 0 dimensions, expected 384",
+            "code": "model_inference_error",
+            "status": 502
+        })))
+    )
+)]
+pub struct BadGateway(ErrorResponse);
+
+/// Overloaded or throttled; retrying later can succeed
+#[derive(ToResponse)]
+#[response(
+    description = "Overloaded, throttled or still starting (service_unavailable or model_load_error)",
+    headers(
+        ("Retry-After" = u64, description = "Seconds to wait before retrying")
+    ),
+    examples(
+        ("Overloaded" = (value = json!({
+            "error": "Service unavailable: Too many recommendation queries in progress",
+            "code": "service_unavailable",
+            "status": 503
+        }))),
+        ("Starting" = (value = json!({
+            "error": "Failed to load model: the embedding model is still loading",
+            "code": "model_load_error",
+            "status": 503
+        })))
+    )
+)]
+pub struct ServiceUnavailable(ErrorResponse);
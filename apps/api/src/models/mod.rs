@@ -3,8 +3,15 @@ use utoipa::ToSchema;
 
 // Re-export types from book.rs
 pub use book::Book;
+pub use error::{
+    BadGateway, ErrorCode, ErrorResponse, InternalServerError, ServiceUnavailable, TooManyRequests,
+    Unauthorized, UserUnauthorized,
+};
+pub use projection::{truncate_description, BookProjection, DESCRIPTION_PREVIEW_CHARS};
 
 mod book;
+mod error;
+mod projection;
 
 /// Request structure for book recommendations
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
@@ -16,6 +23,56 @@ pub struct RecommendationRequest {
     #[serde(default = "default_top_k")]
     #[schema(example = 50, minimum = 1, maximum = 200)]
     pub top_k: usize,
+    /// Optional user id; when set with the user's bearer token, the user's ratings of the same or
+    /// similar books adjust the ranking
+    #[serde(default)]
+    #[schema(example = "3f1c2a9e-8d4b-4c47-9a51-0f6a7b2d1e55")]
+    pub user_id: Option<String>,
+    /// Optional anonymous session id; recent queries are kept under it when no user id is given
+    #[serde(default)]
+    #[schema(example = "session-6b1f0c")]
+    pub session_id: Option<String>,
+    /// Book fields to include; all of them when omitted. `id` is always included
+    #[serde(default)]
+    #[schema(example = json!(["title", "author", "thumbnail"]))]
+    pub fields: Option<Vec<String>>,
+    /// Return full descriptions instead of previews cut at 300 characters
+    #[serde(default)]
+    pub description_full: bool,
+    /// Leave out books with content flags; overrides the user's saved preference, and is on by
+    /// default for queries for children or young adults
+    #[serde(default)]
+    pub safe_search: Option<bool>,
+    /// How much to mix in well-rated books from lower down the ranking, from 0 (none, the
+    /// default) to 1, so repeating a query gives a varied list
+    #[serde(default)]
+    #[schema(example = 0.3, minimum = 0.0, maximum = 1.0)]
+    pub exploration: f32,
+    /// Add a one-sentence explanation to each of the top 5 results
+    #[serde(default)]
+    pub explain: bool,
+    /// Language of explanations and semantic tags: en, de or es. Defaults to the
+    /// `Accept-Language` header, then English
+    #[serde(default)]
+    #[schema(example = "de")]
+    pub lang: Option<String>,
+    /// Two-letter country code of the caller's market; books known not to be published or
+    /// sold there are left out
+    #[serde(default)]
+    #[schema(example = "GB")]
+    pub region: Option<String>,
+    /// Earlier queries and selections of the conversation, oldest first; their constraints and
+    /// negations ("not YA") carry over to this query
+    #[serde(default)]
+    #[schema(example = json!(["cozy mysteries set in scotland", "no romance"]))]
+    pub context: Vec<String>,
+}
+
+impl RecommendationRequest {
+    /// Id experiment variants and flag rollouts are bucketed by: the user id, or else the session id
+    pub fn unit_id(&self) -> Option<&str> {
+        self.user_id.as_deref().or(self.session_id.as_deref())
+    }
 }
 
 /// Response structure for book recommendations
@@ -26,6 +83,381 @@ pub struct RecommendationResponse {
     /// Semantic tags extracted from the query
     #[schema(example = json!(["Fantasy", "Magic", "Adventure"]))]
     pub semantic_tags: Vec<String>,
+    /// The query's themes, genres, moods, author and era, with their kind, confidence and filter
+    #[serde(default)]
+    pub tags: Vec<SemanticTag>,
+    /// Up to 5 ways to narrow the query, most even split of the results first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub refinements: Vec<Refinement>,
+    /// Constraints of the query dropped because too few books met them, in the order dropped
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub relaxed: Vec<RelaxedConstraint>,
+    /// Experiment variants the request was bucketed into, when it carried a user or session id
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub experiments: Vec<ExperimentAssignment>,
+    /// Whether the ranked results came from the result cache
+    #[serde(default)]
+    pub cache: CacheStatus,
+    /// Time the server took to answer, in milliseconds
+    #[serde(default)]
+    #[schema(example = 412)]
+    pub took_ms: u64,
+    /// The vector search was unavailable and the results come from the metadata fallback
+    #[serde(default)]
+    pub degraded: bool,
+    /// Embedding model the query was encoded with; missing when the fallback answered
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = "BAAI/bge-large-en-v1.5")]
+    pub model_used: Option<String>,
+    /// Which search answered the query
+    #[serde(default)]
+    pub search_path: SearchPath,
+    /// How the request's context changed the query; missing without context
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conversation: Option<ConversationState>,
+}
+
+/// The query a conversation's turn searched, and what the conversation ruled out
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ConversationState {
+    /// The query with its negations taken out and earlier turns' constraints added
+    #[schema(example = "something short mystery cozy set in scotland")]
+    pub searched_query: String,
+    /// Genres and terms books were left out for
+    #[schema(example = json!(["romance"]))]
+    pub excluded: Vec<String>,
+}
+
+/// What a semantic tag says about the query
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TagKind {
+    Theme,
+    Genre,
+    Mood,
+    Author,
+    Era,
+}
+
+/// A part of the query the search understood, for rendering as a filter chip
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct SemanticTag {
+    #[schema(example = "fantasy")]
+    pub label: String,
+    pub kind: TagKind,
+    /// How sure the query analysis is of the tag, from 0 to 1
+    #[schema(example = 0.9, minimum = 0.0, maximum = 1.0)]
+    pub confidence: f32,
+    /// The constraint the tag names, untranslated, to refine the query by when its chip is selected
+    pub filter: TagFilter,
+}
+
+/// The constraint of a semantic tag; only the field of the tag's kind is set
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct TagFilter {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = "fantasy")]
+    pub genre: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = "dragons")]
+    pub theme: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = "cozy")]
+    pub mood: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = "Ursula K. Le Guin")]
+    pub author: Option<String>,
+    /// Earliest publication year of an era tag
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = 2015)]
+    pub min_year: Option<i32>,
+    /// Latest publication year of an era tag
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_year: Option<i32>,
+}
+
+impl TagFilter {
+    /// Filter on the `kind` of tag named `label`; era tags carry their years instead
+    pub fn named(kind: TagKind, label: &str) -> Self {
+        let label = Some(label.to_string());
+        match kind {
+            TagKind::Genre => Self {
+                genre: label,
+                ..Self::default()
+            },
+            TagKind::Theme => Self {
+                theme: label,
+                ..Self::default()
+            },
+            TagKind::Mood => Self {
+                mood: label,
+                ..Self::default()
+            },
+            TagKind::Author => Self {
+                author: label,
+                ..Self::default()
+            },
+            TagKind::Era => Self::default(),
+        }
+    }
+}
+
+/// What a refinement narrows a query by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RefinementKind {
+    Genre,
+    Era,
+    Length,
+    Mood,
+    Setting,
+}
+
+/// A suggested narrower version of the query
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Refinement {
+    #[schema(example = "published since 2015")]
+    pub label: String,
+    /// The query to send to apply the refinement
+    #[schema(example = "recent fantasy books with dragons")]
+    pub query: String,
+    pub kind: RefinementKind,
+    /// How many of the current results already fit the refinement
+    #[schema(example = 12)]
+    pub matches: usize,
+}
+
+/// A query constraint that can be dropped to find enough results
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RelaxedConstraint {
+    /// The publication years, e.g. of "recent"
+    Year,
+    /// The page count, e.g. of "short reads"
+    Length,
+    /// The award, e.g. of "award-winning"
+    Award,
+    /// The setting, e.g. of "set in japan"
+    Setting,
+}
+
+/// Whether a response was served from the result cache
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CacheStatus {
+    Hit,
+    #[default]
+    Miss,
+}
+
+/// Which search answered a query
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchPath {
+    /// The vector search of the query's embedding
+    #[default]
+    Vector,
+    /// The keyword search, while the embedding service is unavailable
+    Sparse,
+    /// Cached results: the result cache, or the query's last good candidates
+    /// while the upstreams are unavailable
+    Cached,
+}
+
+/// Where a book fell out of a query's results
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum WhyNotOutcome {
+    /// No book with this id is in the index
+    NotIndexed,
+    /// Neither the metadata nor the vector search returned it as a candidate
+    NotRetrieved,
+    /// Dropped as another edition of a book ranked above it
+    Deduplicated,
+    /// Ranked, but below the number of results asked for
+    BelowCutoff,
+    /// Dismissed in the session
+    Dismissed,
+    /// Filtered by the user's content preferences or safe search
+    FilteredByPreferences,
+    /// Left out by a post-filter of the tenant, e.g. its blocklist
+    FilteredByPostFilter,
+    /// Not published or sold in the request's region
+    UnavailableInRegion,
+    /// The book is in the results
+    Included,
+}
+
+/// Why a book is or isn't among a query's recommendations
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct WhyNotResponse {
+    #[schema(example = "fantasy books with dragons")]
+    pub query: String,
+    #[schema(example = "book_12345")]
+    pub book_id: String,
+    #[schema(example = "The Hobbit")]
+    pub title: Option<String>,
+    #[schema(example = "J.R.R. Tolkien")]
+    pub author: Option<String>,
+    pub outcome: WhyNotOutcome,
+    /// The outcome in a sentence
+    #[schema(example = "Ranked at position 143, below the 100 results asked for.")]
+    pub reason: String,
+    /// Intent the query was read as: Author, Genre, SimilarTo or General
+    #[schema(example = "Genre")]
+    pub intent: String,
+    /// Confidence in the query's author or "similar to" reading, 1 for other
+    /// queries; below 0.5 the query is read as General instead
+    #[schema(example = 1.0)]
+    pub intent_confidence: f32,
+    /// Metadata filter the search started with
+    #[schema(example = "genres ~ fantasy")]
+    pub metadata_filter: Option<String>,
+    /// Whether the book matches that filter
+    pub matches_metadata_filter: Option<bool>,
+    /// Whether the keyword fallback stood in for the vector search
+    pub used_fallback: bool,
+    /// Candidates retrieved before ranking
+    #[schema(example = 300)]
+    pub candidates: usize,
+    /// 1-based position of the book among the candidates
+    #[schema(example = 212)]
+    pub candidate_position: Option<usize>,
+    /// 1-based position after ranking and deduplication, before per-request filters
+    #[schema(example = 143)]
+    pub ranked_position: Option<usize>,
+    /// 1-based position in the returned results, when included
+    pub position: Option<usize>,
+    /// Id of the book it was deduplicated against
+    pub duplicate_of: Option<String>,
+    /// Number of results asked for
+    #[schema(example = 100)]
+    pub top_k: usize,
+}
+
+/// The variant of an A/B experiment a request was assigned to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ExperimentAssignment {
+    #[schema(example = "keyword-boost")]
+    pub experiment: String,
+    #[schema(example = "control")]
+    pub variant: String,
+}
+
+impl ExperimentAssignment {
+    /// `experiment/variant`, the key metrics are tagged with
+    pub fn label(&self) -> String {
+        format!("{}/{}", self.experiment, self.variant)
+    }
+}
+
+/// Request for recommendations from a user's reading history
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ForYouRequest {
+    /// User whose ratings make up the taste profile
+    #[schema(example = "3f1c2a9e-8d4b-4c47-9a51-0f6a7b2d1e55")]
+    pub user_id: String,
+    /// Optional number of recommendations to return (default: 100)
+    #[serde(default = "default_top_k")]
+    #[schema(example = 20, minimum = 1, maximum = 200)]
+    pub top_k: usize,
+    /// Book fields to include; all of them when omitted. `id` is always included
+    #[serde(default)]
+    #[schema(example = json!(["title", "author", "thumbnail"]))]
+    pub fields: Option<Vec<String>>,
+    /// Return full descriptions instead of previews cut at 300 characters
+    #[serde(default)]
+    pub description_full: bool,
+    /// Two-letter country code of the caller's market; books known not to be published or
+    /// sold there are left out
+    #[serde(default)]
+    #[schema(example = "GB")]
+    pub region: Option<String>,
+}
+
+/// Recommendations from a user's taste profile
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ForYouResponse {
+    /// Recommended books, excluding books the user has read or rated
+    pub recommendations: Vec<Book>,
+    /// Number of highly rated books the taste profile was built from (0 when there are none yet)
+    #[schema(example = 12)]
+    pub profile_size: usize,
+}
+
+/// Request for books similar to several seed books at once
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SimilarToRequest {
+    /// Ids of the seed books, at most 10
+    #[schema(example = json!(["9780441013593", "9780553283686"]))]
+    pub book_ids: Vec<String>,
+    /// Ids of books the reader disliked, at most 10; results are steered away from them
+    #[serde(default)]
+    #[schema(example = json!(["9780316015844"]))]
+    pub avoid_ids: Vec<String>,
+    /// Optional number of recommendations to return (default: 100)
+    #[serde(default = "default_top_k")]
+    #[schema(example = 20, minimum = 1, maximum = 200)]
+    pub top_k: usize,
+    /// Book fields to include; all of them when omitted. `id` is always included
+    #[serde(default)]
+    #[schema(example = json!(["title", "author", "thumbnail"]))]
+    pub fields: Option<Vec<String>>,
+    /// Return full descriptions instead of previews cut at 300 characters
+    #[serde(default)]
+    pub description_full: bool,
+    /// Two-letter country code of the caller's market; books known not to be published or
+    /// sold there are left out
+    #[serde(default)]
+    #[schema(example = "GB")]
+    pub region: Option<String>,
+}
+
+/// Books similar to a set of seed books
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SimilarToResponse {
+    /// Recommended books, excluding the seeds
+    pub recommendations: Vec<Book>,
+    /// The seeds searched together, one list per centroid
+    #[schema(example = json!([["9780441013593", "9780553283686"]]))]
+    pub seed_groups: Vec<Vec<String>>,
+    /// Seeds that aren't indexed and were left out
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub missing_seeds: Vec<String>,
+    /// Books to avoid that aren't indexed and were left out
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub missing_avoided: Vec<String>,
+}
+
+/// Request for explanations of books already shown for a query
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ExplanationsRequest {
+    /// The query the books were recommended for
+    #[schema(example = "fantasy books with dragons and magic")]
+    pub query: String,
+    /// Books to explain, at most 10
+    #[schema(example = json!(["9780547928227", "9780441013593"]))]
+    pub book_ids: Vec<String>,
+    /// Language of the explanations: en, de or es. Defaults to the `Accept-Language`
+    /// header, then English
+    #[serde(default)]
+    #[schema(example = "es")]
+    pub lang: Option<String>,
+}
+
+/// Why one book fits a query
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BookExplanation {
+    #[schema(example = "9780547928227")]
+    pub book_id: String,
+    #[schema(example = "Recommended because it has dragons and magic.")]
+    pub explanation: String,
+}
+
+/// Explanations of the requested books, in the order asked for
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ExplanationsResponse {
+    pub explanations: Vec<BookExplanation>,
 }
 
 /// Health check response structure
@@ -37,19 +469,144 @@ pub struct HealthResponse {
     /// Current timestamp in RFC3339 format
     #[schema(example = "2024-01-15T10:30:00Z")]
     pub timestamp: String,
+    /// Health of the upstreams the search depends on, as its router sees it
+    #[serde(default)]
+    pub upstreams: Vec<UpstreamStatus>,
+}
+
+/// State of an upstream's circuit breaker
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum UpstreamState {
+    /// Answering; requests go to it
+    Closed,
+    /// Failing; requests skip it until its cooldown ends
+    Open,
+    /// Cooldown over; the next request probes it
+    HalfOpen,
 }
 
-/// Error response structure
+/// Health of an upstream the search depends on
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
-pub struct ErrorResponse {
-    /// Error message
-    #[schema(example = "Query cannot be empty")]
-    pub error: String,
-    /// HTTP status code
-    #[schema(example = 400)]
-    pub status: u16,
+pub struct UpstreamStatus {
+    #[schema(example = "embeddings")]
+    pub upstream: String,
+    pub state: UpstreamState,
+    /// Failed calls since it last answered
+    #[schema(example = 0)]
+    pub consecutive_failures: u32,
+}
+
+/// Outcome of probing an upstream's endpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum EndpointCheck {
+    /// Resolved and answered
+    Ok,
+    /// The settings the host is built from are missing or placeholders
+    InvalidConfig,
+    /// The host didn't resolve
+    DnsFailed,
+    /// The host resolved, but the request to it failed
+    Unreachable,
+    /// The endpoint rejected the API key
+    Unauthorized,
+    /// Nothing answers at the host, usually a wrong index name
+    NotFound,
+    /// The endpoint answered with a server error
+    UpstreamError,
 }
 
+/// Result of resolving and probing an upstream's endpoint
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct EndpointProbe {
+    #[schema(example = "pinecone")]
+    pub upstream: String,
+    #[schema(example = "https://books-abc123.svc.us-east-1-aws.pinecone.io")]
+    pub host: String,
+    /// Addresses the host resolved to, used for its requests until the next probe
+    #[serde(default)]
+    pub addresses: Vec<String>,
+    pub check: EndpointCheck,
+    /// What failed and what to change, when the check didn't pass
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[schema(example = 84)]
+    pub latency_ms: u64,
+    /// When the probe ran, in RFC3339 format
+    #[schema(example = "2024-01-15T10:30:00Z")]
+    pub checked_at: String,
+}
+
+/// Results returned when a request doesn't set top_k
+pub const DEFAULT_TOP_K: usize = 100;
+
 fn default_top_k() -> usize {
-    100
+    DEFAULT_TOP_K
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_client_types_read_server_responses() {
+        let book: Book = serde_json::from_value(json!({
+            "id": "book_1",
+            "title": "The Hobbit",
+            "categories": ["Fantasy"],
+            "rating": 4.3,
+            "relevance_indicators": ["Fantasy"],
+        }))
+        .expect("valid book");
+        let response = RecommendationResponse {
+            recommendations: vec![book],
+            semantic_tags: vec!["Fantasy".to_string()],
+            tags: vec![SemanticTag {
+                label: "fantasy".to_string(),
+                kind: TagKind::Genre,
+                confidence: 0.8,
+                filter: TagFilter::named(TagKind::Genre, "fantasy"),
+            }],
+            refinements: vec![Refinement {
+                label: "published since 2015".to_string(),
+                query: "recent fantasy".to_string(),
+                kind: RefinementKind::Era,
+                matches: 1,
+            }],
+            relaxed: vec![RelaxedConstraint::Year],
+            experiments: vec![ExperimentAssignment {
+                experiment: "keyword-boost".to_string(),
+                variant: "control".to_string(),
+            }],
+            cache: CacheStatus::Hit,
+            took_ms: 12,
+            degraded: true,
+            model_used: None,
+            search_path: SearchPath::Sparse,
+            conversation: None,
+        };
+        let json = serde_json::to_string(&response).expect("serializable");
+        let read: recommend_a_book_client::RecommendationResponse =
+            serde_json::from_str(&json).expect("client reads the response");
+        assert_eq!(read.recommendations[0].title.as_deref(), Some("The Hobbit"));
+        assert_eq!(read.experiments[0].variant, "control");
+        assert_eq!(read.cache, "hit");
+        assert_eq!(read.tags[0].kind, "genre");
+        assert_eq!(read.tags[0].filter.genre.as_deref(), Some("fantasy"));
+        assert_eq!(read.refinements[0].query, "recent fantasy");
+        assert_eq!(read.relaxed, vec!["year"]);
+        assert!(read.degraded);
+        assert_eq!(read.search_path, "sparse");
+
+        let request = recommend_a_book_client::RecommendationRequest::new("cozy fantasy")
+            .with_top_k(10)
+            .with_fields(["title"]);
+        let request: RecommendationRequest =
+            serde_json::from_value(serde_json::to_value(&request).expect("serializable"))
+                .expect("server reads the request");
+        assert_eq!(request.top_k, 10);
+        assert!(!request.description_full);
+    }
 }
@@ -0,0 +1,145 @@
+//! Slimmer book payloads for recommendation responses
+//!
+//! Responses list up to 200 books, most of the bytes being descriptions. Clients
+//! can ask for just the fields they render with `fields`, and descriptions are
+//! cut to a preview unless `description_full` is set.
+
+use super::Book;
+use crate::error::ApiError;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// Characters of a description kept when the full description isn't requested
+pub const DESCRIPTION_PREVIEW_CHARS: usize = 300;
+
+lazy_static::lazy_static! {
+    /// Names of the fields of a serialized [`Book`]
+    static ref BOOK_FIELDS: Vec<String> = serde_json::from_value::<Book>(serde_json::json!({ "categories": [] }))
+        .and_then(serde_json::to_value)
+        .ok()
+        .and_then(|book| book.as_object().map(|fields| fields.keys().cloned().collect()))
+        .unwrap_or_default();
+}
+
+/// Which parts of each book a response includes
+#[derive(Debug, Clone, Default)]
+pub struct BookProjection {
+    /// Fields to keep; all of them when unset. Always includes `id`
+    fields: Option<HashSet<String>>,
+    description_full: bool,
+}
+
+impl BookProjection {
+    /// Projection onto `fields`, rejecting names a book doesn't have
+    pub fn new(fields: Option<&[String]>, description_full: bool) -> Result<Self, ApiError> {
+        let fields = match fields {
+            Some(fields) => {
+                let mut kept: HashSet<String> = HashSet::with_capacity(fields.len() + 1);
+                for field in fields {
+                    let field = field.trim();
+                    if !BOOK_FIELDS.iter().any(|known| known == field) {
+                        return Err(ApiError::InvalidInput(format!(
+                            "Unknown book field '{}'. Valid fields: {}",
+                            field,
+                            BOOK_FIELDS.join(", ")
+                        )));
+                    }
+                    kept.insert(field.to_string());
+                }
+                kept.insert("id".to_string());
+                Some(kept)
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            fields,
+            description_full,
+        })
+    }
+
+    /// Cut descriptions to a preview unless full descriptions were requested
+    ///
+    /// Only books with a description to cut are copied out of their `Arc`.
+    pub fn truncate_descriptions(&self, books: &mut [Arc<Book>]) {
+        if self.description_full {
+            return;
+        }
+        for book in books {
+            let too_long = book.description.as_ref().is_some_and(|description| {
+                description.chars().nth(DESCRIPTION_PREVIEW_CHARS).is_some()
+            });
+            if !too_long {
+                continue;
+            }
+            if let Some(description) = &mut Arc::make_mut(book).description {
+                truncate_description(description, DESCRIPTION_PREVIEW_CHARS);
+            }
+        }
+    }
+
+    /// Write `book` as JSON with only the requested fields
+    pub fn write_book(&self, book: &Book, out: &mut Vec<u8>) -> serde_json::Result<()> {
+        let Some(fields) = &self.fields else {
+            return serde_json::to_writer(out, book);
+        };
+        let mut value = serde_json::to_value(book)?;
+        if let Value::Object(book) = &mut value {
+            book.retain(|field, _| fields.contains(field));
+        }
+        serde_json::to_writer(out, &value)
+    }
+}
+
+/// Shorten `description` to at most `max_chars` characters plus an ellipsis, at a word boundary
+pub fn truncate_description(description: &mut String, max_chars: usize) {
+    let Some((cut, _)) = description.char_indices().nth(max_chars) else {
+        return;
+    };
+    let kept = &description[..cut];
+    let end = kept
+        .rfind(char::is_whitespace)
+        .filter(|&space| space > 0)
+        .unwrap_or(cut);
+    description.truncate(end);
+    let trimmed_len = description
+        .trim_end_matches(|c: char| c.is_whitespace() || c.is_ascii_punctuation())
+        .len();
+    description.truncate(trimmed_len);
+    description.push('…');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_descriptions_are_cut_at_a_word_and_fields_projected() {
+        let mut description = "A hobbit, a wizard, and thirteen dwarves.".to_string();
+        truncate_description(&mut description, 20);
+        assert_eq!(description, "A hobbit, a wizard…");
+
+        let mut short = "Short.".to_string();
+        truncate_description(&mut short, 20);
+        assert_eq!(short, "Short.");
+
+        let projection =
+            BookProjection::new(Some(&["title".to_string()]), false).expect("known field");
+        let book: Book = serde_json::from_value(
+            json!({ "id": "1", "title": "Dune", "rating": 4.5, "categories": [] }),
+        )
+        .expect("valid book");
+        let mut out = Vec::new();
+        projection
+            .write_book(&book, &mut out)
+            .expect("serializable");
+        assert_eq!(
+            serde_json::from_slice::<Value>(&out).expect("valid JSON"),
+            json!({ "id": "1", "title": "Dune" })
+        );
+
+        assert!(BookProjection::new(Some(&["blurb".to_string()]), false).is_err());
+    }
+}
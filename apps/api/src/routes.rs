@@ -4,8 +4,11 @@ use utoipa_swagger_ui::{Config as SwaggerConfig, SwaggerUi};
 
 use crate::app::ApiDoc;
 use crate::handlers::{
-    graph_config, health_check, health_options, prewarm_endpoint, prewarm_options,
-    recommendations_config,
+    admin_config, authors_config, books_config, catalog_config, covers_config, digest_config,
+    events_config, explanations_config, feeds_config, graph_config, health_check, health_options,
+    history_config, metrics_endpoint, opds_config, preferences_config, prewarm_endpoint,
+    prewarm_options, quality_metrics, query_config, ratings_config, readiness_check,
+    recommendations_config, sessions_config, shelves_config, tools_config,
 };
 
 /// Configure all routes for the API
@@ -13,10 +16,38 @@ pub fn api_routes() -> Scope {
     web::scope("/api")
         .service(health_check)
         .service(health_options)
+        .service(readiness_check)
         .service(prewarm_endpoint)
         .service(prewarm_options)
+        .service(metrics_endpoint)
+        .service(quality_metrics)
         .configure(recommendations_config)
+        .configure(explanations_config)
+        .configure(query_config)
         .configure(graph_config)
+        .configure(admin_config)
+        .configure(shelves_config)
+        .configure(ratings_config)
+        .configure(history_config)
+        .configure(sessions_config)
+        .configure(preferences_config)
+        .configure(events_config)
+        .configure(digest_config)
+        .configure(catalog_config)
+        .configure(books_config)
+        .configure(covers_config)
+        .configure(authors_config)
+        .configure(tools_config)
+}
+
+/// Configure the Atom feeds, outside the API scope so feed URLs stay short
+pub fn feed_routes() -> Scope {
+    web::scope("/feeds").configure(feeds_config)
+}
+
+/// Configure the OPDS catalog for e-reader apps
+pub fn opds_routes() -> Scope {
+    web::scope("/opds").configure(opds_config)
 }
 
 /// Configure Swagger UI routes
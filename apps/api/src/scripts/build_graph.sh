@@ -85,17 +85,17 @@ print_info "  User: $APP_NEO4J_USER"
 # Ask if user wants to clear existing graph
 echo ""
 read -p "Do you want to clear the existing graph before building? (y/N): " clear_graph
+GRAPH_ARGS=()
 if [[ $clear_graph =~ ^[Yy]$ ]]; then
-    export CLEAR_GRAPH=true
+    GRAPH_ARGS+=("--clear")
     print_warning "Existing graph will be cleared"
 else
-    export CLEAR_GRAPH=false
     print_info "Existing graph will be preserved, new relationships will be added"
 fi
 
 # Build the project
 print_info "Building the graph building binary..."
-cargo build --bin build_graph --release
+cargo build --bin rab-admin --release
 
 if [ $? -ne 0 ]; then
     print_error "Failed to build the project"
@@ -121,10 +121,10 @@ print_info "Starting graph building process..."
 echo "=================================================================================="
 
 # Set logging level
-export RUST_LOG="build_graph=info,recommend_a_book_api=info"
+export RUST_LOG="rab_admin=info,recommend_a_book_api=info"
 
 # Run the graph builder
-./target/release/build_graph
+./target/release/rab-admin rebuild-graph "${GRAPH_ARGS[@]}"
 
 # Check exit status
 if [ $? -eq 0 ]; then
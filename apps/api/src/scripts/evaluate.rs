@@ -0,0 +1,67 @@
+//! Score ranking quality against a YAML query suite
+//!
+//! Same as `rab-admin evaluate`, kept as its own binary for the scripts and CI
+//! jobs that call it directly.
+
+use anyhow::Context;
+use clap::Parser;
+use log::error;
+use recommend_a_book_api::{
+    commands::{self, evaluate::EvaluateOptions},
+    config::Config,
+};
+use std::path::PathBuf;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+
+/// Score retrieval quality against a YAML query suite
+#[derive(Debug, Parser)]
+#[command(name = "evaluate", version, about)]
+struct Cli {
+    /// Query suite, e.g. ./eval/queries.yaml
+    suite: PathBuf,
+    /// Override the cutoff set in the suite
+    #[arg(long)]
+    k: Option<usize>,
+    /// Write the full report as JSON, e.g. to use as a baseline later
+    #[arg(long)]
+    output: Option<PathBuf>,
+    /// Previous JSON report to compare against
+    #[arg(long)]
+    baseline: Option<PathBuf>,
+}
+
+#[tokio::main]
+async fn main() {
+    // Load environment variables
+    dotenv::dotenv().ok();
+    let cli = Cli::parse();
+
+    // Initialize logging
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| "evaluate=info,recommend_a_book_api=warn".into()),
+        )
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_target(false)
+                .with_level(true),
+        )
+        .init();
+
+    let options = EvaluateOptions {
+        suite_path: cli.suite,
+        k: cli.k,
+        output_path: cli.output,
+        baseline_path: cli.baseline,
+    };
+    let result = match Config::load().context("Failed to load configuration") {
+        Ok(config) => commands::evaluate::run(&config, options).await,
+        Err(e) => Err(e),
+    };
+
+    if let Err(e) = result {
+        error!("❌ Evaluation failed: {:#}", e);
+        std::process::exit(1);
+    }
+}
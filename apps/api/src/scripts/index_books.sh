@@ -26,24 +26,26 @@ print_error() {
     echo -e "${RED}[ERROR]${NC} $1"
 }
 
-# Check if CSV file argument is provided
+# Check if an input file argument is provided
 if [ $# -eq 0 ]; then
-    print_error "No CSV file provided!"
-    echo "Usage: $0 <path_to_csv_file>"
-    echo "Example: $0 ./data/books.csv"
+    print_error "No input file provided!"
+    echo "Usage: $0 <input_file> [--format csv|jsonl|json|parquet|openlibrary] [--authors-dump <path>] [--incremental] [--dry-run] [--manifest <path>] [--enrich] [--enrichment-cache <path>]"
+    echo "Example: $0 ./data/books.csv --incremental"
     exit 1
 fi
 
-CSV_FILE="$1"
+INPUT_FILE="$1"
+shift
+INDEXER_ARGS=("$@")
 
-# Check if CSV file exists
-if [ ! -f "$CSV_FILE" ]; then
-    print_error "CSV file does not exist: $CSV_FILE"
+# Check if the input file exists
+if [ ! -f "$INPUT_FILE" ]; then
+    print_error "Input file does not exist: $INPUT_FILE"
     exit 1
 fi
 
 print_info "Starting book indexing process..."
-print_info "CSV file: $CSV_FILE"
+print_info "Input file: $INPUT_FILE"
 
 # Check for required environment variables
 REQUIRED_VARS=(
@@ -89,7 +91,7 @@ fi
 
 # Build the project
 print_info "Building the indexing binary..."
-cargo build --bin index_books --release
+cargo build --bin rab-admin --release
 
 if [ $? -ne 0 ]; then
     print_error "Failed to build the project"
@@ -99,8 +101,8 @@ fi
 print_success "Build completed successfully!"
 
 # Get file info
-file_size=$(wc -l < "$CSV_FILE")
-print_info "CSV file contains approximately $file_size lines"
+file_size=$(wc -l < "$INPUT_FILE")
+print_info "Input file contains approximately $file_size lines"
 
 # Estimate processing time
 estimated_minutes=$((file_size / 1000))
@@ -123,10 +125,10 @@ print_info "Starting indexing process..."
 echo "=================================================================================="
 
 # Set logging level
-export RUST_LOG="index_books=info,recommend_a_book_api=info"
+export RUST_LOG="rab_admin=info,recommend_a_book_api=info"
 
 # Run the indexing
-./target/release/index_books "$CSV_FILE"
+./target/release/rab-admin index "$INPUT_FILE" "${INDEXER_ARGS[@]}"
 
 # Check exit status
 if [ $? -eq 0 ]; then
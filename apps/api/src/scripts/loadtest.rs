@@ -0,0 +1,75 @@
+use clap::Parser;
+use log::error;
+use recommend_a_book_api::commands::loadtest::{
+    self, LoadTestOptions, DEFAULT_CONCURRENCY, DEFAULT_TOP_K,
+};
+use std::path::PathBuf;
+use std::time::Duration;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+
+/// Replay a query log against a running API server and report latency
+/// percentiles, cache hit rate and errors
+#[derive(Debug, Parser)]
+#[command(name = "loadtest", version, about)]
+struct Cli {
+    /// Query log: one query per line, as text or as JSON with a `query` field
+    query_log: PathBuf,
+    /// Base URL of the API server
+    #[arg(long, env = "APP_API_URL", default_value = "http://localhost:10000")]
+    api_url: String,
+    /// Requests in flight at once
+    #[arg(long, default_value_t = DEFAULT_CONCURRENCY, value_parser = parse_count)]
+    concurrency: usize,
+    /// Requests to send, cycling through the log [default: one pass over it]
+    #[arg(long, value_parser = parse_count)]
+    requests: Option<usize>,
+    /// Results asked for by queries that don't set their own
+    #[arg(long, default_value_t = DEFAULT_TOP_K, value_parser = parse_count)]
+    top_k: usize,
+    /// Timeout of each request, in seconds
+    #[arg(long, default_value_t = 30, value_parser = parse_count)]
+    timeout_secs: usize,
+    /// Write the report as JSON
+    #[arg(long)]
+    output: Option<PathBuf>,
+}
+
+fn parse_count(value: &str) -> std::result::Result<usize, String> {
+    value
+        .parse::<usize>()
+        .ok()
+        .filter(|n| *n > 0)
+        .ok_or_else(|| "expected a positive number".to_string())
+}
+
+#[tokio::main]
+async fn main() {
+    dotenv::dotenv().ok();
+    let cli = Cli::parse();
+
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| "loadtest=info,recommend_a_book_api=info".into()),
+        )
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_target(false)
+                .with_level(true),
+        )
+        .init();
+
+    let options = LoadTestOptions {
+        api_url: cli.api_url,
+        query_log: cli.query_log,
+        concurrency: cli.concurrency,
+        requests: cli.requests,
+        top_k: cli.top_k,
+        timeout: Duration::from_secs(cli.timeout_secs as u64),
+        output_path: cli.output,
+    };
+    if let Err(e) = loadtest::run(options).await {
+        error!("❌ {:#}", e);
+        std::process::exit(1);
+    }
+}
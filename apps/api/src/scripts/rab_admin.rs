@@ -0,0 +1,386 @@
+use anyhow::{Context, Result};
+use clap::{Args, Parser, Subcommand};
+use log::{error, info};
+use recommend_a_book_api::{
+    commands::{
+        self,
+        check_config::CheckConfigOptions,
+        clear_cache::ClearCacheOptions,
+        co_shelving::CoShelvingOptions,
+        digest::DigestOptions,
+        evaluate::EvaluateOptions,
+        graph::GraphOptions,
+        index::{CatalogOptions, IndexOptions},
+        prune::PruneOptions,
+        tag_moods::TagMoodsOptions,
+    },
+    config::Config,
+    ingest::{
+        dedup::{DedupConfig, DEFAULT_DEDUP_THRESHOLD},
+        pipeline::{
+            PipelineConfig, DEFAULT_BATCH_SIZE, DEFAULT_EMBED_CONCURRENCY,
+            DEFAULT_UPSERT_CONCURRENCY,
+        },
+        IngestFormat,
+    },
+    services::{
+        collaborative::{CoShelvingConfig, DEFAULT_MAX_NEIGHBOURS, DEFAULT_MIN_SHARED_USERS},
+        digest::{DEFAULT_DIGEST_SIZE, MAX_DIGEST_SIZE},
+        supabase::validate_id,
+    },
+};
+use std::path::PathBuf;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+
+/// Maintenance tasks for the book recommendation API
+#[derive(Debug, Parser)]
+#[command(name = "rab-admin", version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: AdminCommand,
+}
+
+#[derive(Debug, Subcommand)]
+enum AdminCommand {
+    /// Embed a catalog and upsert it to Pinecone
+    Index(IndexArgs),
+    /// Delete vectors (and graph nodes) for books no longer in the catalog
+    Prune(PruneArgs),
+    /// Rebuild the Neo4j relationship graph from the indexed books
+    RebuildGraph {
+        /// Delete the existing graph before rebuilding
+        #[arg(long)]
+        clear: bool,
+    },
+    /// Tag indexed books with moods from their descriptions and embeddings
+    TagMoods {
+        /// Print the report without updating any metadata
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Score "users who shelved X also shelved Y" pairs and write them to the graph
+    CoShelving {
+        /// Ignore pairs shelved together by fewer users than this
+        #[arg(long, default_value_t = DEFAULT_MIN_SHARED_USERS, value_parser = parse_count)]
+        min_shared_users: usize,
+        /// Strongest co-shelved neighbours kept per book
+        #[arg(long, default_value_t = DEFAULT_MAX_NEIGHBOURS, value_parser = parse_count)]
+        max_neighbours: usize,
+        /// Print the report without updating the graph
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Generate a user's weekly digest of new picks as JSON
+    Digest {
+        user_id: String,
+        /// Number of picks
+        #[arg(long, default_value_t = DEFAULT_DIGEST_SIZE, value_parser = parse_digest_size)]
+        count: usize,
+        /// Print the digest without recording it, so its picks can appear in the next one
+        #[arg(long)]
+        dry_run: bool,
+        /// Write the digest to a file instead of stdout
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Score retrieval quality against a YAML query suite
+    Evaluate {
+        /// Query suite, e.g. ./eval/queries.yaml
+        suite: PathBuf,
+        /// Override the cutoff set in the suite
+        #[arg(long)]
+        k: Option<usize>,
+        /// Write the full report as JSON, e.g. to use as a baseline later
+        #[arg(long)]
+        output: Option<PathBuf>,
+        /// Previous JSON report to compare against
+        #[arg(long)]
+        baseline: Option<PathBuf>,
+    },
+    /// Print the effective configuration and report problems
+    CheckConfig {
+        /// Also connect to Pinecone, HuggingFace, Neo4j and the database
+        #[arg(long)]
+        connect: bool,
+    },
+    /// Clear the caches of a running API server through the admin API
+    ClearCache {
+        /// Base URL of the API server [default: http://localhost:<port>]
+        #[arg(long, env = "APP_API_URL")]
+        api_url: Option<String>,
+        /// Name recorded in the audit log
+        #[arg(long, default_value = "rab-admin")]
+        actor: String,
+    },
+}
+
+/// Catalog input shared by `index` and `prune`
+#[derive(Debug, Args)]
+struct CatalogArgs {
+    /// Catalog file (CSV, JSONL, a JSON array, Parquet or an Open Library editions dump)
+    input: PathBuf,
+    /// Input format [default: guessed from the file extension]
+    #[arg(long, value_parser = parse_format)]
+    format: Option<IngestFormat>,
+    /// Open Library authors dump used to resolve author keys to names
+    #[arg(long)]
+    authors_dump: Option<PathBuf>,
+    /// Content hash manifest [default: <input>.manifest.json]
+    #[arg(long)]
+    manifest: Option<PathBuf>,
+    /// Similarity above which two books are treated as duplicates
+    #[arg(long, default_value_t = DEFAULT_DEDUP_THRESHOLD, value_parser = parse_threshold)]
+    dedup_threshold: f64,
+    /// Merge duplicate metadata into the kept book instead of only dropping the rest
+    #[arg(long)]
+    merge_duplicates: bool,
+    /// Author alias map [default: author_aliases_file from the config]
+    #[arg(long)]
+    author_aliases: Option<PathBuf>,
+    /// Category taxonomy [default: category_taxonomy_file from the config]
+    #[arg(long)]
+    taxonomy: Option<PathBuf>,
+    /// Award winners [default: awards_file from the config]
+    #[arg(long)]
+    awards: Option<PathBuf>,
+}
+
+impl CatalogArgs {
+    fn into_options(self, config: &Config) -> CatalogOptions {
+        let mut options = CatalogOptions::new(self.input);
+        if let Some(format) = self.format {
+            options.format = format;
+        }
+        if let Some(manifest) = self.manifest {
+            options.manifest_path = manifest;
+        }
+        options.authors_dump = self.authors_dump;
+        options.author_aliases_path = self
+            .author_aliases
+            .or_else(|| config.author_aliases_file.as_ref().map(PathBuf::from));
+        options.taxonomy_path = self
+            .taxonomy
+            .or_else(|| config.category_taxonomy_file.as_ref().map(PathBuf::from));
+        options.awards_path = self
+            .awards
+            .or_else(|| config.awards_file.as_ref().map(PathBuf::from));
+        options.dedup = DedupConfig {
+            threshold: self.dedup_threshold,
+            merge: self.merge_duplicates,
+        };
+        options
+    }
+}
+
+#[derive(Debug, Args)]
+struct IndexArgs {
+    #[command(flatten)]
+    catalog: CatalogArgs,
+    /// Only re-embed and upsert books whose content hash changed
+    #[arg(long)]
+    incremental: bool,
+    /// Validate the input and print a report without calling any external API
+    #[arg(long)]
+    dry_run: bool,
+    /// Fill missing metadata from Google Books / Open Library before embedding
+    #[arg(long)]
+    enrich: bool,
+    /// Cache of enrichment lookups [default: <input>.enrichment-cache.json]
+    #[arg(long)]
+    enrichment_cache: Option<PathBuf>,
+    /// Check every cover thumbnail and replace broken ones with Open Library covers
+    #[arg(long)]
+    resolve_covers: bool,
+    #[arg(long, default_value_t = DEFAULT_BATCH_SIZE, value_parser = parse_count)]
+    batch_size: usize,
+    #[arg(long, default_value_t = DEFAULT_EMBED_CONCURRENCY, value_parser = parse_count)]
+    embed_concurrency: usize,
+    #[arg(long, default_value_t = DEFAULT_UPSERT_CONCURRENCY, value_parser = parse_count)]
+    upsert_concurrency: usize,
+    /// Write the duplicate clusters as JSON
+    #[arg(long)]
+    duplicates_report: Option<PathBuf>,
+    /// JSON progress snapshot, readable through /api/admin/indexer/status
+    #[arg(long, env = "APP_INDEXER_STATUS_FILE")]
+    status_file: Option<PathBuf>,
+    /// Corpus statistics snapshot for the API [default: corpus_stats_file from the config]
+    #[arg(long)]
+    corpus_stats: Option<PathBuf>,
+    /// Full-text index directory for the API [default: fulltext_index_dir from the config]
+    #[arg(long)]
+    fulltext_index: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+struct PruneArgs {
+    #[command(flatten)]
+    catalog: CatalogArgs,
+    /// Actually delete; without it only the report is printed
+    #[arg(long)]
+    confirm: bool,
+    /// Also remove stale book nodes from Neo4j
+    #[arg(long)]
+    graph: bool,
+}
+
+fn parse_format(value: &str) -> std::result::Result<IngestFormat, String> {
+    value.parse()
+}
+
+fn parse_count(value: &str) -> std::result::Result<usize, String> {
+    value
+        .parse::<usize>()
+        .ok()
+        .filter(|n| *n > 0)
+        .ok_or_else(|| "expected a positive number".to_string())
+}
+
+fn parse_digest_size(value: &str) -> std::result::Result<usize, String> {
+    value
+        .parse::<usize>()
+        .ok()
+        .filter(|n| (1..=MAX_DIGEST_SIZE).contains(n))
+        .ok_or_else(|| format!("expected a number between 1 and {}", MAX_DIGEST_SIZE))
+}
+
+fn parse_threshold(value: &str) -> std::result::Result<f64, String> {
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|t| (0.0..=1.0).contains(t))
+        .ok_or_else(|| "expected a number between 0 and 1".to_string())
+}
+
+async fn run(command: AdminCommand, config: &Config) -> Result<()> {
+    match command {
+        AdminCommand::Index(args) => {
+            let enrichment_cache_path = args
+                .enrichment_cache
+                .unwrap_or_else(|| args.catalog.input.with_extension("enrichment-cache.json"));
+            let dry_run = args.dry_run;
+            let options = IndexOptions {
+                catalog: args.catalog.into_options(config),
+                incremental: args.incremental,
+                dry_run,
+                enrich: args.enrich,
+                enrichment_cache_path,
+                resolve_covers: args.resolve_covers,
+                pipeline: PipelineConfig {
+                    batch_size: args.batch_size,
+                    embed_concurrency: args.embed_concurrency,
+                    upsert_concurrency: args.upsert_concurrency,
+                    sparse_keywords: config.pinecone_sparse_keywords,
+                },
+                duplicates_report_path: args.duplicates_report,
+                status_file: args.status_file.filter(|path| !path.as_os_str().is_empty()),
+                corpus_stats_path: args
+                    .corpus_stats
+                    .or_else(|| config.corpus_stats_file.as_ref().map(PathBuf::from)),
+                fulltext_index_path: args
+                    .fulltext_index
+                    .or_else(|| config.fulltext_index_dir.as_ref().map(PathBuf::from)),
+            };
+            commands::index::run(config, options).await?;
+            if !dry_run {
+                info!("✅ Indexing completed successfully!");
+            }
+        }
+        AdminCommand::Prune(args) => {
+            let options = PruneOptions {
+                catalog: args.catalog.into_options(config),
+                confirm: args.confirm,
+                graph: args.graph,
+            };
+            commands::prune::run(config, options).await?;
+        }
+        AdminCommand::RebuildGraph { clear } => {
+            commands::graph::run(config, GraphOptions { clear }).await?;
+        }
+        AdminCommand::TagMoods { dry_run } => {
+            commands::tag_moods::run(config, TagMoodsOptions { dry_run }).await?;
+        }
+        AdminCommand::CoShelving {
+            min_shared_users,
+            max_neighbours,
+            dry_run,
+        } => {
+            let options = CoShelvingOptions {
+                cf: CoShelvingConfig {
+                    min_shared_users,
+                    max_neighbours,
+                },
+                dry_run,
+            };
+            commands::co_shelving::run(config, options).await?;
+        }
+        AdminCommand::Digest {
+            user_id,
+            count,
+            dry_run,
+            output,
+        } => {
+            validate_id("user id", &user_id)?;
+            let options = DigestOptions {
+                user_id,
+                count,
+                dry_run,
+                output_path: output,
+            };
+            commands::digest::run(config, options).await?;
+        }
+        AdminCommand::Evaluate {
+            suite,
+            k,
+            output,
+            baseline,
+        } => {
+            let options = EvaluateOptions {
+                suite_path: suite,
+                k,
+                output_path: output,
+                baseline_path: baseline,
+            };
+            commands::evaluate::run(config, options).await?;
+        }
+        AdminCommand::CheckConfig { connect } => {
+            commands::check_config::run(config, CheckConfigOptions { connect }).await?;
+        }
+        AdminCommand::ClearCache { api_url, actor } => {
+            let api_url = api_url
+                .filter(|url| !url.trim().is_empty())
+                .unwrap_or_else(|| format!("http://localhost:{}", config.port));
+            commands::clear_cache::run(config, ClearCacheOptions { api_url, actor }).await?;
+        }
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() {
+    // Load environment variables before clap reads env-backed defaults
+    dotenv::dotenv().ok();
+    let cli = Cli::parse();
+
+    // Initialize logging
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| "rab_admin=info,recommend_a_book_api=info".into()),
+        )
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_target(false)
+                .with_level(true),
+        )
+        .init();
+
+    let result = match Config::load().context("Failed to load configuration") {
+        Ok(config) => run(cli.command, &config).await,
+        Err(e) => Err(e),
+    };
+
+    if let Err(e) = result {
+        error!("❌ {:#}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,248 @@
+use crate::error::{recover_lock, ApiError, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{postgres::PgPoolOptions, Executor, PgPool, Row};
+use std::collections::VecDeque;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+/// Schema for the audit table, applied on startup (safe to run repeatedly)
+const AUDIT_LOG_MIGRATION: &str = include_str!("../../migrations/0001_admin_audit_log.sql");
+
+/// Number of entries kept when no database is configured
+const IN_MEMORY_CAPACITY: usize = 1000;
+
+/// Upper bound on entries returned by a single query
+pub const MAX_AUDIT_QUERY_LIMIT: i64 = 500;
+
+/// A single admin action recorded in the audit trail
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct AuditEntry {
+    /// Sequential entry id
+    #[schema(example = 42)]
+    pub id: i64,
+    /// Who performed the action, from the X-Admin-Actor header
+    #[schema(example = "ops-oncall")]
+    pub actor: String,
+    /// What was done
+    #[schema(example = "cache.clear")]
+    pub action: String,
+    /// Parameters the action was invoked with
+    #[schema(example = json!({"caches": ["recommendations", "query_enhancer"]}))]
+    pub parameters: serde_json::Value,
+    /// When the action was recorded, in RFC3339 format
+    #[schema(example = "2024-01-15T10:30:00Z")]
+    pub created_at: String,
+}
+
+/// Filters for reading the audit trail
+#[derive(Debug, Clone, Default)]
+pub struct AuditQuery {
+    pub actor: Option<String>,
+    pub action: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub limit: i64,
+}
+
+impl AuditQuery {
+    fn matches(&self, entry: &AuditEntry) -> bool {
+        self.actor
+            .as_ref()
+            .is_none_or(|actor| &entry.actor == actor)
+            && self
+                .action
+                .as_ref()
+                .is_none_or(|action| &entry.action == action)
+            && self.since.is_none_or(|since| {
+                DateTime::parse_from_rfc3339(&entry.created_at)
+                    .is_ok_and(|created| created >= since)
+            })
+    }
+}
+
+/// Append-only trail of admin actions.
+///
+/// Entries are written to the `admin_audit_log` table in Supabase when a
+/// database is configured, and kept in a bounded in-memory buffer otherwise.
+#[derive(Clone)]
+pub struct AuditLog {
+    pool: Option<PgPool>,
+    memory: Arc<RwLock<VecDeque<AuditEntry>>>,
+}
+
+impl AuditLog {
+    /// Audit log that only keeps recent entries in memory
+    pub fn in_memory() -> Self {
+        Self {
+            pool: None,
+            memory: Arc::new(RwLock::new(VecDeque::new())),
+        }
+    }
+
+    /// Audit log backed by the given Postgres pool
+    pub fn with_pool(pool: PgPool) -> Self {
+        Self {
+            pool: Some(pool),
+            ..Self::in_memory()
+        }
+    }
+
+    /// Connect to the database and make sure the audit table exists
+    pub async fn connect(database_url: &str) -> Result<Self> {
+        let pool = PgPoolOptions::new()
+            .max_connections(5)
+            .acquire_timeout(Duration::from_secs(10))
+            .connect(database_url)
+            .await?;
+
+        Self::from_pool(pool).await
+    }
+
+    /// Audit log sharing an existing pool, making sure the audit table exists
+    pub async fn from_pool(pool: PgPool) -> Result<Self> {
+        pool.execute(AUDIT_LOG_MIGRATION).await?;
+        info!("Admin audit log table is ready");
+
+        Ok(Self::with_pool(pool))
+    }
+
+    /// Whether entries are persisted to the database
+    pub fn is_persistent(&self) -> bool {
+        self.pool.is_some()
+    }
+
+    /// Append an entry to the trail
+    pub async fn record(
+        &self,
+        actor: &str,
+        action: &str,
+        parameters: serde_json::Value,
+    ) -> Result<AuditEntry> {
+        info!(
+            "Admin action '{}' by '{}' with parameters {}",
+            action, actor, parameters
+        );
+
+        let Some(pool) = &self.pool else {
+            let mut memory = recover_lock(self.memory.write(), "audit log");
+            let entry = AuditEntry {
+                id: memory.back().map_or(1, |last| last.id + 1),
+                actor: actor.to_string(),
+                action: action.to_string(),
+                parameters,
+                created_at: Utc::now().to_rfc3339(),
+            };
+            if memory.len() >= IN_MEMORY_CAPACITY {
+                memory.pop_front();
+            }
+            memory.push_back(entry.clone());
+            return Ok(entry);
+        };
+
+        let row = sqlx::query(
+            "INSERT INTO admin_audit_log (actor, action, parameters) \
+             VALUES ($1, $2, $3) \
+             RETURNING id, actor, action, parameters, created_at",
+        )
+        .bind(actor)
+        .bind(action)
+        .bind(&parameters)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| {
+            warn!("Failed to write audit entry for '{}': {}", action, e);
+            ApiError::DatabaseError(format!("Failed to write audit entry: {}", e))
+        })?;
+
+        entry_from_row(&row)
+    }
+
+    /// Entries matching the filters, newest first
+    pub async fn query(&self, filter: &AuditQuery) -> Result<Vec<AuditEntry>> {
+        let limit = filter.limit.clamp(1, MAX_AUDIT_QUERY_LIMIT);
+
+        let Some(pool) = &self.pool else {
+            return Ok(recover_lock(self.memory.read(), "audit log")
+                .iter()
+                .rev()
+                .filter(|entry| filter.matches(entry))
+                .take(limit as usize)
+                .cloned()
+                .collect());
+        };
+
+        let rows = sqlx::query(
+            "SELECT id, actor, action, parameters, created_at FROM admin_audit_log \
+             WHERE ($1::text IS NULL OR actor = $1) \
+               AND ($2::text IS NULL OR action = $2) \
+               AND ($3::timestamptz IS NULL OR created_at >= $3) \
+             ORDER BY created_at DESC, id DESC \
+             LIMIT $4",
+        )
+        .bind(&filter.actor)
+        .bind(&filter.action)
+        .bind(filter.since)
+        .bind(limit)
+        .fetch_all(pool)
+        .await?;
+
+        rows.iter().map(entry_from_row).collect()
+    }
+}
+
+impl Default for AuditLog {
+    fn default() -> Self {
+        Self::in_memory()
+    }
+}
+
+fn entry_from_row(row: &sqlx::postgres::PgRow) -> Result<AuditEntry> {
+    Ok(AuditEntry {
+        id: row.try_get("id")?,
+        actor: row.try_get("actor")?,
+        action: row.try_get("action")?,
+        parameters: row.try_get("parameters")?,
+        created_at: row.try_get::<DateTime<Utc>, _>("created_at")?.to_rfc3339(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[actix_web::test]
+    async fn test_in_memory_entries_are_filtered_newest_first() {
+        let log = AuditLog::in_memory();
+        log.record("alice", "cache.clear", json!({})).await.unwrap();
+        log.record("bob", "slow_queries.clear", json!({}))
+            .await
+            .unwrap();
+        log.record("alice", "slow_queries.clear", json!({}))
+            .await
+            .unwrap();
+
+        let all = log
+            .query(&AuditQuery {
+                limit: 10,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(all.iter().map(|e| e.id).collect::<Vec<_>>(), vec![3, 2, 1]);
+
+        let alice = log
+            .query(&AuditQuery {
+                actor: Some("alice".to_string()),
+                action: Some("slow_queries.clear".to_string()),
+                limit: 10,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(alice.len(), 1);
+        assert_eq!(alice[0].id, 3);
+    }
+}
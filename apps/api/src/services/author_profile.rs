@@ -0,0 +1,218 @@
+//! Author profiles: an author's books and what they add up to
+//!
+//! The books come from the corpus statistics snapshot, which lists the most
+//! rated titles with their authors; their stored metadata gives the average
+//! rating, the genres and themes the author keeps coming back to and the people
+//! they wrote with. Similar authors are found by averaging the embeddings of the
+//! author's books, retrieving the books closest to that average and comparing
+//! it with the average of each other author's books among them.
+
+use crate::ingest::authors::author_key;
+use crate::ingest::corpus_stats::{top_counts, NameCount, RatingTally};
+use crate::models::Book;
+use crate::services::personalization::cosine_similarity;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use utoipa::ToSchema;
+
+/// Most of an author's books a profile covers, most rated first
+pub const MAX_PROFILE_BOOKS: usize = 100;
+
+/// Books retrieved near an author's average embedding to find similar authors
+pub const SIMILAR_AUTHOR_CANDIDATES: usize = 200;
+
+/// Similar authors listed in a profile
+const MAX_SIMILAR_AUTHORS: usize = 10;
+
+/// Least similarity of an author listed as similar; below it the nearest
+/// books of a small catalog are just the least unrelated ones
+pub const MIN_AUTHOR_SIMILARITY: f32 = 0.6;
+
+/// Genres, themes and co-authors listed in a profile
+const MAX_LISTED_LABELS: usize = 10;
+
+/// An author whose books read like another's
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct SimilarAuthor {
+    #[schema(example = "Ursula K. Le Guin")]
+    pub name: String,
+    /// Cosine similarity of the two authors' average book embeddings
+    #[schema(example = 0.81)]
+    pub similarity: f32,
+    /// Books of theirs the similarity is based on
+    #[schema(example = 4)]
+    pub books: usize,
+}
+
+/// An author's books and aggregate statistics
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AuthorProfile {
+    /// The catalog's spelling of the name
+    #[schema(example = "J. R. R. Tolkien")]
+    pub name: String,
+    /// The author's books, most rated first
+    pub books: Vec<Book>,
+    /// Average rating of the rated books, 0 if none are rated
+    #[schema(example = 4.2)]
+    pub average_rating: f32,
+    /// Genres of the most books, from the genre vocabulary
+    pub top_genres: Vec<NameCount>,
+    /// Moods and settings of the most books
+    pub top_themes: Vec<NameCount>,
+    /// People the author wrote books with, most shared books first
+    pub co_authors: Vec<NameCount>,
+    /// Authors whose books are closest in embedding space, most similar first
+    pub similar_authors: Vec<SimilarAuthor>,
+}
+
+/// Normalized average of `vectors`, or None when there are none of the same length
+pub fn average_embedding<'a>(vectors: impl IntoIterator<Item = &'a Vec<f32>>) -> Option<Vec<f32>> {
+    let mut total: Vec<f32> = Vec::new();
+    for vector in vectors {
+        if total.is_empty() {
+            total = vec![0.0; vector.len()];
+        } else if total.len() != vector.len() {
+            continue;
+        }
+        for (sum, value) in total.iter_mut().zip(vector) {
+            *sum += value;
+        }
+    }
+
+    let norm = total.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm == 0.0 {
+        return None;
+    }
+    Some(total.into_iter().map(|x| x / norm).collect())
+}
+
+/// Individual names of a book's authors, as the indexer joined them
+fn author_names(book: &Book) -> impl Iterator<Item = &str> {
+    book.author.iter().flat_map(|authors| authors.split(", "))
+}
+
+/// Profile of `name` from their books, without similar authors
+pub fn build_profile(name: &str, books: Vec<Book>) -> AuthorProfile {
+    let key = author_key(name);
+    let mut overall = RatingTally::default();
+    let mut genres: HashMap<&str, RatingTally> = HashMap::new();
+    let mut themes: HashMap<&str, RatingTally> = HashMap::new();
+    let mut co_authors: HashMap<&str, RatingTally> = HashMap::new();
+    for book in &books {
+        overall.add(book.rating);
+        for genre in &book.genres {
+            genres.entry(genre).or_default().add(book.rating);
+        }
+        for theme in book.moods.iter().chain(&book.settings) {
+            themes.entry(theme).or_default().add(book.rating);
+        }
+        for co_author in author_names(book).filter(|other| author_key(other) != key) {
+            co_authors.entry(co_author).or_default().add(book.rating);
+        }
+    }
+    let top_genres = top_counts(genres, MAX_LISTED_LABELS);
+    let top_themes = top_counts(themes, MAX_LISTED_LABELS);
+    let co_authors = top_counts(co_authors, MAX_LISTED_LABELS);
+
+    AuthorProfile {
+        name: name.to_string(),
+        books,
+        average_rating: overall.average_rating(),
+        top_genres,
+        top_themes,
+        co_authors,
+        similar_authors: Vec::new(),
+    }
+}
+
+/// Other authors of `candidates` ranked by how close the average embedding of
+/// their books is to `average`
+pub fn similar_authors(
+    name: &str,
+    average: &[f32],
+    candidates: &[Book],
+    vectors: &HashMap<String, Vec<f32>>,
+) -> Vec<SimilarAuthor> {
+    let key = author_key(name);
+    let mut books_by_author: HashMap<&str, Vec<&Vec<f32>>> = HashMap::new();
+    for book in candidates {
+        let Some(vector) = book.id.as_ref().and_then(|id| vectors.get(id)) else {
+            continue;
+        };
+        for author in author_names(book).filter(|author| author_key(author) != key) {
+            books_by_author.entry(author).or_default().push(vector);
+        }
+    }
+
+    let mut similar: Vec<SimilarAuthor> = books_by_author
+        .into_iter()
+        .filter_map(|(author, vectors)| {
+            let books = vectors.len();
+            Some(SimilarAuthor {
+                name: author.to_string(),
+                similarity: cosine_similarity(average, &average_embedding(vectors)?),
+                books,
+            })
+        })
+        .filter(|author| author.similarity >= MIN_AUTHOR_SIMILARITY)
+        .collect();
+    similar.sort_by(|a, b| {
+        b.similarity
+            .total_cmp(&a.similarity)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    similar.truncate(MAX_SIMILAR_AUTHORS);
+    similar
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book(id: &str, author: &str, rating: f32, genres: &[&str]) -> Book {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "author": author,
+            "rating": rating,
+            "categories": [],
+            "genres": genres,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_profile_aggregates_books_and_ranks_similar_authors() {
+        let profile = build_profile(
+            "Terry Pratchett",
+            vec![
+                book("a", "Terry Pratchett", 4.0, &["fantasy", "humor"]),
+                book("b", "Terry Pratchett, Neil Gaiman", 4.5, &["fantasy"]),
+                book("c", "Terry Pratchett", 0.0, &["fantasy"]),
+            ],
+        );
+        assert_eq!(profile.average_rating, 4.25);
+        assert_eq!(profile.top_genres[0].name, "fantasy");
+        assert_eq!(profile.top_genres[0].books, 3);
+        assert_eq!(profile.co_authors.len(), 1);
+        assert_eq!(profile.co_authors[0].name, "Neil Gaiman");
+
+        let average = average_embedding(&[vec![1.0, 0.0], vec![1.0, 0.2]]).unwrap();
+        let candidates = vec![
+            book("d", "Douglas Adams", 4.2, &[]),
+            book("e", "Stephen King", 4.0, &[]),
+            book("f", "Terry Pratchett", 4.0, &[]),
+        ];
+        let vectors = HashMap::from([
+            ("d".to_string(), vec![1.0, 0.1]),
+            ("e".to_string(), vec![0.0, 1.0]),
+            ("f".to_string(), vec![1.0, 0.1]),
+        ]);
+        let names: Vec<String> =
+            similar_authors("terry pratchett", &average, &candidates, &vectors)
+                .into_iter()
+                .map(|author| author.name)
+                .collect();
+        // Stephen King's books are nearly orthogonal, too far off to count as similar
+        assert_eq!(names, vec!["Douglas Adams"]);
+    }
+}
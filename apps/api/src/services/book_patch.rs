@@ -0,0 +1,225 @@
+//! Corrections to stored book metadata without re-embedding
+//!
+//! A patch sets some of a book's metadata fields, such as a fixed typo in the
+//! title or the right publication year. The new values are merged over the
+//! stored metadata as the book's next version; its vector is left as it was.
+
+use crate::error::{ApiError, Result};
+use crate::models::Book;
+use crate::services::book_versions::{
+    metadata_changes, stored_version, VersionChange, UPDATED_AT_FIELD, VERSION_FIELD,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use utoipa::ToSchema;
+
+/// Most books one request may patch
+pub const MAX_PATCHES: usize = 100;
+
+/// Fields a patch may set; the others are written by the indexer or other endpoints
+const PATCHABLE_FIELDS: &[&str] = &[
+    "title",
+    "author",
+    "description",
+    "categories",
+    "genres",
+    "moods",
+    "awards",
+    "settings",
+    "content_flags",
+    "available_regions",
+    "thumbnail",
+    "rating",
+    "year",
+    "isbn",
+    "page_count",
+    "ratings_count",
+    "language",
+    "publisher",
+];
+
+/// New values for some of a book's metadata fields
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct BookPatch {
+    #[schema(example = "book_12345")]
+    pub id: String,
+    /// Fields to set and their new values
+    #[schema(value_type = Object, example = json!({"year": 1937, "categories": ["Fantasy", "Classics"]}))]
+    pub fields: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PatchStatus {
+    /// The fields were written as a new version
+    Updated,
+    /// The book already had these values
+    Unchanged,
+    /// No book with this id is indexed
+    NotFound,
+    /// The patch was rejected; see `error`
+    Invalid,
+    /// Writing the new version failed; see `error`
+    Failed,
+}
+
+/// What became of one patch
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct PatchResult {
+    #[schema(example = "book_12345")]
+    pub id: String,
+    pub status: PatchStatus,
+    /// The book's version after the patch
+    #[schema(example = 4)]
+    pub version: Option<i32>,
+    #[schema(example = json!(null))]
+    pub error: Option<String>,
+}
+
+impl PatchResult {
+    pub fn new(id: &str, status: PatchStatus) -> Self {
+        Self {
+            id: id.to_string(),
+            status,
+            version: None,
+            error: None,
+        }
+    }
+
+    pub fn with_version(mut self, version: i32) -> Self {
+        self.version = Some(version);
+        self
+    }
+
+    pub fn with_error(mut self, error: impl ToString) -> Self {
+        self.error = Some(error.to_string());
+        self
+    }
+}
+
+/// A patch merged over a book's stored metadata
+#[derive(Debug, Clone)]
+pub struct PatchedBook {
+    /// Metadata fields to write: the changed ones and the new version
+    pub set_metadata: Value,
+    /// The book as it reads after the patch
+    pub book: Book,
+    pub change: VersionChange,
+}
+
+/// Reject patches that set nothing, set fields that can't be patched, or
+/// clear fields (the index can't store nulls)
+pub fn validate_patch(patch: &BookPatch) -> Result<()> {
+    if patch.fields.is_empty() {
+        return Err(ApiError::InvalidInput(
+            "fields must not be empty".to_string(),
+        ));
+    }
+    for (field, value) in &patch.fields {
+        if !PATCHABLE_FIELDS.contains(&field.as_str()) {
+            return Err(ApiError::InvalidInput(format!(
+                "{} can't be patched; patchable fields are {}",
+                field,
+                PATCHABLE_FIELDS.join(", ")
+            )));
+        }
+        if value.is_null() {
+            return Err(ApiError::InvalidInput(format!("{} can't be null", field)));
+        }
+    }
+    Ok(())
+}
+
+/// Merge a valid patch over the book's stored metadata as its next version,
+/// or None when it changes nothing
+pub fn apply_patch(patch: &BookPatch, stored: &Value, now: &str) -> Result<Option<PatchedBook>> {
+    let mut merged = stored.clone();
+    let Some(fields) = merged.as_object_mut() else {
+        return Err(ApiError::InvalidInput(format!(
+            "Book {} has no stored metadata to patch",
+            patch.id
+        )));
+    };
+    for (field, value) in &patch.fields {
+        fields.insert(field.clone(), value.clone());
+    }
+
+    let changes = metadata_changes(Some(stored), &merged);
+    if changes.is_empty() {
+        return Ok(None);
+    }
+
+    let version = stored_version(stored) + 1;
+    let mut set_metadata: Map<String, Value> = changes
+        .keys()
+        .map(|field| (field.clone(), merged[field].clone()))
+        .collect();
+    set_metadata.insert(VERSION_FIELD.to_string(), json!(version));
+    set_metadata.insert(UPDATED_AT_FIELD.to_string(), json!(now));
+
+    // The patched metadata must still read as a book
+    let mut readable = merged;
+    readable["id"] = json!(patch.id);
+    let book: Book = serde_json::from_value(readable)
+        .map_err(|e| ApiError::InvalidInput(format!("Invalid field value: {}", e)))?;
+
+    Ok(Some(PatchedBook {
+        set_metadata: Value::Object(set_metadata),
+        book,
+        change: VersionChange {
+            book_id: patch.id.clone(),
+            version,
+            changes,
+        },
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patch(fields: Value) -> BookPatch {
+        BookPatch {
+            id: "b1".to_string(),
+            fields: fields.as_object().unwrap().clone(),
+        }
+    }
+
+    #[test]
+    fn test_patches_only_write_changed_fields_as_the_next_version() {
+        let stored = json!({
+            "title": "The Hobit",
+            "year": 1937.0,
+            "categories": ["Fantasy"],
+            "version": 2.0
+        });
+
+        let fix = patch(json!({ "title": "The Hobbit", "year": 1937 }));
+        validate_patch(&fix).unwrap();
+        let patched = apply_patch(&fix, &stored, "2024-02-01T00:00:00Z")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            patched.set_metadata,
+            json!({ "title": "The Hobbit", "version": 3, "updated_at": "2024-02-01T00:00:00Z" })
+        );
+        assert_eq!(patched.book.title.as_deref(), Some("The Hobbit"));
+        assert_eq!(patched.change.version, 3);
+
+        let same = patch(json!({ "categories": ["Fantasy"] }));
+        assert!(apply_patch(&same, &stored, "2024-02-01T00:00:00Z")
+            .unwrap()
+            .is_none());
+
+        let wrong_type = patch(json!({ "categories": 7 }));
+        assert!(apply_patch(&wrong_type, &stored, "2024-02-01T00:00:00Z").is_err());
+    }
+
+    #[test]
+    fn test_bookkeeping_and_null_fields_are_rejected() {
+        assert!(validate_patch(&patch(json!({}))).is_err());
+        assert!(validate_patch(&patch(json!({ "version": 9 }))).is_err());
+        assert!(validate_patch(&patch(json!({ "hidden": false }))).is_err());
+        assert!(validate_patch(&patch(json!({ "description": null }))).is_err());
+    }
+}
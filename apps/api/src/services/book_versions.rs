@@ -0,0 +1,344 @@
+//! Versions of each book's stored metadata
+//!
+//! Every vector's metadata carries a `version`, bumped whenever its content
+//! changes, and the time of that change in `updated_at`. Each new version is
+//! recorded in the `book_versions` table with the fields that changed, so
+//! enrichment runs and manual corrections can be traced; without a database
+//! the most recent versions are kept in memory.
+
+use crate::error::{recover_lock, ApiError, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use sqlx::{postgres::PgPoolOptions, Executor, PgPool, Row};
+use std::collections::VecDeque;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tracing::info;
+use utoipa::ToSchema;
+
+/// Schema for the versions table, applied on startup (safe to run repeatedly)
+const BOOK_VERSIONS_MIGRATION: &str = include_str!("../../migrations/0009_book_versions.sql");
+
+/// Metadata field holding the version number
+pub const VERSION_FIELD: &str = "version";
+/// Metadata field holding when the version was written, in RFC3339 format
+pub const UPDATED_AT_FIELD: &str = "updated_at";
+/// Metadata field the indexer detects content changes with
+pub const CONTENT_HASH_FIELD: &str = "content_hash";
+
+/// Fields that change with every version rather than describing the book
+const BOOKKEEPING_FIELDS: &[&str] = &[VERSION_FIELD, UPDATED_AT_FIELD, CONTENT_HASH_FIELD];
+
+/// Number of versions kept when no database is configured
+const IN_MEMORY_CAPACITY: usize = 1000;
+
+/// Upper bound on versions returned for one book
+pub const MAX_HISTORY_LIMIT: i64 = 200;
+
+/// One recorded version of a book's metadata
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct BookVersion {
+    /// Sequential entry id
+    #[schema(example = 42)]
+    pub id: i64,
+    #[schema(example = "book_12345")]
+    pub book_id: String,
+    /// Version number, starting at 1
+    #[schema(example = 3)]
+    pub version: i32,
+    /// What wrote the version
+    #[schema(example = "index")]
+    pub source: String,
+    /// Fields that changed, each with its value before and after
+    #[schema(example = json!({"year": {"from": 1973, "to": 1937}}))]
+    pub changes: Value,
+    /// When the version was written, in RFC3339 format
+    #[schema(example = "2024-01-15T10:30:00Z")]
+    pub created_at: String,
+}
+
+/// A new version to record
+#[derive(Debug, Clone)]
+pub struct VersionChange {
+    pub book_id: String,
+    pub version: i32,
+    pub changes: Map<String, Value>,
+}
+
+/// Version number stored in metadata; Pinecone returns numbers as floats
+pub fn stored_version(metadata: &Value) -> i32 {
+    metadata
+        .get(VERSION_FIELD)
+        .and_then(Value::as_f64)
+        .map_or(0, |version| version as i32)
+}
+
+/// Fields of `after` that differ from `before`, each as `{"from": .., "to": ..}`
+///
+/// Fields only one side has count as changed from or to null.
+pub fn metadata_changes(before: Option<&Value>, after: &Value) -> Map<String, Value> {
+    let empty = Map::new();
+    let before = before.and_then(Value::as_object).unwrap_or(&empty);
+    let after = after.as_object().unwrap_or(&empty);
+
+    let mut changes = Map::new();
+    for field in before.keys().chain(after.keys()) {
+        if BOOKKEEPING_FIELDS.contains(&field.as_str()) || changes.contains_key(field) {
+            continue;
+        }
+        let from = before.get(field).unwrap_or(&Value::Null);
+        let to = after.get(field).unwrap_or(&Value::Null);
+        if !same_value(from, to) {
+            changes.insert(field.clone(), json!({ "from": from, "to": to }));
+        }
+    }
+    changes
+}
+
+/// Equality that treats 1937 and 1937.0 alike, as Pinecone stores every number as a float
+fn same_value(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64() == b.as_f64(),
+        (Value::Array(a), Value::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| same_value(a, b))
+        }
+        _ => a == b,
+    }
+}
+
+/// Give metadata about to replace `stored` its version
+///
+/// Metadata with the content hash of the stored one keeps its version;
+/// anything else gets the next version, stamped with `now`, and the returned
+/// change for the history.
+pub fn stamp_version(
+    book_id: &str,
+    stored: Option<&Value>,
+    metadata: &mut Value,
+    now: &str,
+) -> Option<VersionChange> {
+    let unchanged = stored.is_some_and(|stored| {
+        stored.get(CONTENT_HASH_FIELD).is_some()
+            && stored.get(CONTENT_HASH_FIELD) == metadata.get(CONTENT_HASH_FIELD)
+    });
+    if unchanged {
+        let stored = stored?;
+        for field in [VERSION_FIELD, UPDATED_AT_FIELD] {
+            if let Some(value) = stored.get(field) {
+                metadata[field] = value.clone();
+            }
+        }
+        return None;
+    }
+
+    let version = stored.map_or(0, stored_version) + 1;
+    metadata[VERSION_FIELD] = json!(version);
+    metadata[UPDATED_AT_FIELD] = json!(now);
+    Some(VersionChange {
+        book_id: book_id.to_string(),
+        version,
+        changes: metadata_changes(stored, metadata),
+    })
+}
+
+/// History of book metadata versions
+#[derive(Clone)]
+pub struct BookHistory {
+    pool: Option<PgPool>,
+    memory: Arc<RwLock<VecDeque<BookVersion>>>,
+}
+
+impl BookHistory {
+    /// History that only keeps recent versions in memory
+    pub fn in_memory() -> Self {
+        Self {
+            pool: None,
+            memory: Arc::new(RwLock::new(VecDeque::new())),
+        }
+    }
+
+    /// Connect to the database and make sure the versions table exists
+    pub async fn connect(database_url: &str) -> Result<Self> {
+        let pool = PgPoolOptions::new()
+            .max_connections(2)
+            .acquire_timeout(Duration::from_secs(10))
+            .connect(database_url)
+            .await?;
+
+        Self::from_pool(pool).await
+    }
+
+    /// History sharing an existing pool, making sure the versions table exists
+    pub async fn from_pool(pool: PgPool) -> Result<Self> {
+        pool.execute(BOOK_VERSIONS_MIGRATION).await?;
+        info!("Book versions table is ready");
+
+        Ok(Self {
+            pool: Some(pool),
+            ..Self::in_memory()
+        })
+    }
+
+    /// Whether versions are persisted to the database
+    pub fn is_persistent(&self) -> bool {
+        self.pool.is_some()
+    }
+
+    /// Record new versions written by `source`
+    pub async fn record(&self, source: &str, changes: &[VersionChange]) -> Result<()> {
+        if changes.is_empty() {
+            return Ok(());
+        }
+
+        let Some(pool) = &self.pool else {
+            let mut memory = recover_lock(self.memory.write(), "book history");
+            for change in changes {
+                let entry = BookVersion {
+                    id: memory.back().map_or(1, |last| last.id + 1),
+                    book_id: change.book_id.clone(),
+                    version: change.version,
+                    source: source.to_string(),
+                    changes: Value::Object(change.changes.clone()),
+                    created_at: Utc::now().to_rfc3339(),
+                };
+                if memory.len() >= IN_MEMORY_CAPACITY {
+                    memory.pop_front();
+                }
+                memory.push_back(entry);
+            }
+            return Ok(());
+        };
+
+        let book_ids: Vec<&str> = changes.iter().map(|c| c.book_id.as_str()).collect();
+        let versions: Vec<i32> = changes.iter().map(|c| c.version).collect();
+        let diffs: Vec<Value> = changes
+            .iter()
+            .map(|c| Value::Object(c.changes.clone()))
+            .collect();
+        sqlx::query(
+            "INSERT INTO book_versions (book_id, version, source, changes) \
+             SELECT book_id, version, $3, changes \
+             FROM UNNEST($1::text[], $2::int[], $4::jsonb[]) AS v(book_id, version, changes)",
+        )
+        .bind(&book_ids)
+        .bind(&versions)
+        .bind(source)
+        .bind(&diffs)
+        .execute(pool)
+        .await
+        .map_err(|e| ApiError::DatabaseError(format!("Failed to record book versions: {}", e)))?;
+
+        Ok(())
+    }
+
+    /// Up to `limit` versions of a book, newest first
+    pub async fn history(&self, book_id: &str, limit: i64) -> Result<Vec<BookVersion>> {
+        let limit = limit.clamp(1, MAX_HISTORY_LIMIT);
+
+        let Some(pool) = &self.pool else {
+            return Ok(recover_lock(self.memory.read(), "book history")
+                .iter()
+                .rev()
+                .filter(|entry| entry.book_id == book_id)
+                .take(limit as usize)
+                .cloned()
+                .collect());
+        };
+
+        let rows = sqlx::query(
+            "SELECT id, book_id, version, source, changes, created_at FROM book_versions \
+             WHERE book_id = $1 \
+             ORDER BY version DESC, id DESC \
+             LIMIT $2",
+        )
+        .bind(book_id)
+        .bind(limit)
+        .fetch_all(pool)
+        .await?;
+
+        rows.iter().map(version_from_row).collect()
+    }
+}
+
+impl Default for BookHistory {
+    fn default() -> Self {
+        Self::in_memory()
+    }
+}
+
+fn version_from_row(row: &sqlx::postgres::PgRow) -> Result<BookVersion> {
+    Ok(BookVersion {
+        id: row.try_get("id")?,
+        book_id: row.try_get("book_id")?,
+        version: row.try_get("version")?,
+        source: row.try_get("source")?,
+        changes: row.try_get("changes")?,
+        created_at: row.try_get::<DateTime<Utc>, _>("created_at")?.to_rfc3339(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changed_content_gets_the_next_version() {
+        let stored = json!({
+            "title": "The Hobbit",
+            "year": 1973.0,
+            "content_hash": "a",
+            "version": 2.0,
+            "updated_at": "2024-01-01T00:00:00Z"
+        });
+
+        let mut same = json!({ "title": "The Hobbit", "year": 1973, "content_hash": "a" });
+        assert!(stamp_version("b1", Some(&stored), &mut same, "2024-02-01T00:00:00Z").is_none());
+        assert_eq!(same["version"], json!(2.0));
+        assert_eq!(same["updated_at"], json!("2024-01-01T00:00:00Z"));
+
+        let mut fixed = json!({ "title": "The Hobbit", "year": 1937, "content_hash": "b" });
+        let change =
+            stamp_version("b1", Some(&stored), &mut fixed, "2024-02-01T00:00:00Z").unwrap();
+        assert_eq!(change.version, 3);
+        assert_eq!(fixed["version"], json!(3));
+        assert_eq!(fixed["updated_at"], json!("2024-02-01T00:00:00Z"));
+        assert_eq!(
+            Value::Object(change.changes),
+            json!({ "year": { "from": 1973.0, "to": 1937 } })
+        );
+
+        let mut new = json!({ "title": "Dune", "content_hash": "c" });
+        let change = stamp_version("b2", None, &mut new, "2024-02-01T00:00:00Z").unwrap();
+        assert_eq!(change.version, 1);
+        assert_eq!(
+            Value::Object(change.changes),
+            json!({ "title": { "from": null, "to": "Dune" } })
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_in_memory_history_is_newest_first() {
+        let history = BookHistory::in_memory();
+        for version in 1..=3 {
+            history
+                .record(
+                    "index",
+                    &[VersionChange {
+                        book_id: "b1".to_string(),
+                        version,
+                        changes: Map::new(),
+                    }],
+                )
+                .await
+                .unwrap();
+        }
+
+        let versions = history.history("b1", 2).await.unwrap();
+        assert_eq!(
+            versions.iter().map(|v| v.version).collect::<Vec<_>>(),
+            vec![3, 2]
+        );
+        assert!(history.history("b2", 10).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,547 @@
+//! Service initialization shared by the API server and the `rab-admin` CLI
+
+#[cfg(feature = "sled")]
+use crate::cache::disk::DiskCache;
+#[cfg(feature = "redis")]
+use crate::cache::redis::RedisCache;
+use crate::cache::{self, SharedCache};
+use crate::config::Config;
+use crate::error::{ApiError, Result};
+use crate::ingest::{
+    authors::AuthorAliases, awards::AwardList, corpus_stats::CorpusStats, fulltext::FullTextIndex,
+    taxonomy::CategoryTaxonomy,
+};
+use crate::ml::huggingface_embedder::HuggingFaceEmbedder;
+use crate::services::{
+    audit_log::AuditLog,
+    book_versions::BookHistory,
+    catalog_events::CatalogEventPublisher,
+    experiments::Experiments,
+    explanations::{Explainer, LlmBackend, LlmProvider},
+    feature_flags::FeatureFlags,
+    jobs::{Job, JobQueue},
+    neo4j::Neo4jClient,
+    post_filters::PostFilters,
+    query_parser::QueryParser,
+    shadow::ShadowRanking,
+    slow_query_log::SlowQueryLog,
+    supabase::SupabaseClient,
+    synonyms::SynonymDictionary,
+    templates::set_synonyms,
+    usage::UsageMeter,
+    Pinecone, RecommendationService,
+};
+use log::{info, warn};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How long to wait for an upstream before falling back to lazy initialization
+const INIT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Kind of the jobs prewarming the service
+const PREWARM_JOB: &str = "prewarm";
+
+/// Neo4j URI and user when only a password is configured
+const DEFAULT_NEO4J_URI: &str = "bolt://localhost:7687";
+const DEFAULT_NEO4J_USER: &str = "neo4j";
+
+/// Prefix of every key this API stores in the shared cache
+#[cfg(feature = "redis")]
+const SHARED_CACHE_NAMESPACE: &str = "rab";
+
+/// Connect to Pinecone, deferring the connection to the first request if it's slow to respond
+pub async fn init_pinecone(config: &Config) -> Result<Pinecone> {
+    let pinecone_future = Pinecone::new(
+        &config.pinecone_api_key,
+        &config.pinecone_environment,
+        &config.pinecone_index,
+    );
+
+    match tokio::time::timeout(INIT_TIMEOUT, pinecone_future).await {
+        Ok(result) => result,
+        Err(_) => {
+            warn!(
+                "Pinecone initialization timed out after {:?}. Will retry on first request",
+                INIT_TIMEOUT
+            );
+            Pinecone::new_with_lazy_init(
+                &config.pinecone_api_key,
+                &config.pinecone_environment,
+                &config.pinecone_index,
+            )
+        }
+    }
+}
+
+/// Create the HuggingFace embedder, deferring model checks if the API is slow to respond
+pub async fn init_embedder() -> Result<HuggingFaceEmbedder> {
+    match tokio::time::timeout(INIT_TIMEOUT, HuggingFaceEmbedder::new()).await {
+        Ok(result) => result,
+        Err(_) => {
+            warn!(
+                "HuggingFace initialization timed out after {:?}. Will retry on first request",
+                INIT_TIMEOUT
+            );
+            HuggingFaceEmbedder::new_with_deferred_init()
+        }
+    }
+}
+
+/// Connect to Neo4j when it is configured
+///
+/// Only the password is required; the URI and user default to a local
+/// instance's, so a local setup only needs `APP_NEO4J_PASSWORD`.
+pub async fn init_neo4j(config: &Config) -> Result<Neo4jClient> {
+    let Some(password) = &config.neo4j_password else {
+        return Err(ApiError::ExternalServiceError(
+            "Neo4j not configured".to_string(),
+        ));
+    };
+    let uri = config.neo4j_uri.as_deref().unwrap_or(DEFAULT_NEO4J_URI);
+    let user = config.neo4j_user.as_deref().unwrap_or(DEFAULT_NEO4J_USER);
+
+    info!("Initializing Neo4j client at {}...", uri);
+    Neo4jClient::new(uri, user, password).await
+}
+
+/// Shared result cache backend, if Redis is configured and reachable
+pub async fn init_shared_cache(config: &Config) -> Option<Arc<dyn SharedCache>> {
+    #[cfg(feature = "redis")]
+    {
+        let url = config.redis_url.as_deref()?;
+        match RedisCache::connect(url, SHARED_CACHE_NAMESPACE).await {
+            Ok(cache) => {
+                info!("Sharing cached results through Redis");
+                Some(Arc::new(cache))
+            }
+            Err(e) => {
+                warn!("{}. Results will be cached per instance only", e);
+                None
+            }
+        }
+    }
+
+    #[cfg(not(feature = "redis"))]
+    {
+        if config.redis_url.is_some() {
+            warn!("A Redis URL is configured but this build lacks the `redis` feature. Results will be cached per instance only");
+        }
+        None
+    }
+}
+
+/// On-disk cache backend, if a cache directory is configured and can be opened
+pub fn init_disk_cache(config: &Config) -> Option<Arc<dyn SharedCache>> {
+    let dir = config.cache_dir.as_deref()?;
+
+    #[cfg(feature = "sled")]
+    {
+        let memory_bytes = config.cache_dir_memory_mb.saturating_mul(1024 * 1024);
+        match DiskCache::open(Path::new(dir), memory_bytes) {
+            Ok(cache) => {
+                info!("Keeping cached results and embeddings in {}", dir);
+                Some(Arc::new(cache))
+            }
+            Err(e) => {
+                warn!("{}. Caches won't survive restarts", e);
+                None
+            }
+        }
+    }
+
+    #[cfg(not(feature = "sled"))]
+    {
+        warn!(
+            "cache_dir is set to {} but this build lacks the `sled` feature. Caches won't survive restarts",
+            dir
+        );
+        None
+    }
+}
+
+/// Publisher of catalog change events, if any webhook or stream is configured
+pub async fn init_catalog_events(config: &Config) -> Option<CatalogEventPublisher> {
+    let mut publisher = CatalogEventPublisher::new(config.webhook_urls.clone());
+    if let Some(secret) = &config.webhook_secret {
+        publisher = publisher.with_secret(secret);
+    }
+
+    if let Some(stream) = &config.catalog_events_stream {
+        #[cfg(feature = "redis")]
+        match config.redis_url.as_deref() {
+            Some(url) => match connect_redis(url).await {
+                Ok(connection) => publisher = publisher.with_redis_stream(connection, stream),
+                Err(e) => warn!("{}. Catalog events won't be appended to {}", e, stream),
+            },
+            None => warn!(
+                "catalog_events_stream is set without a Redis URL. Catalog events won't be appended to {}",
+                stream
+            ),
+        }
+
+        #[cfg(not(feature = "redis"))]
+        warn!(
+            "catalog_events_stream is set but this build lacks the `redis` feature. Catalog events won't be appended to {}",
+            stream
+        );
+    }
+
+    publisher.is_enabled().then_some(publisher)
+}
+
+#[cfg(feature = "redis")]
+async fn connect_redis(url: &str) -> Result<redis::aio::ConnectionManager> {
+    let client = redis::Client::open(url)
+        .map_err(|e| ApiError::InvalidInput(format!("Invalid Redis URL: {}", e)))?;
+    redis::aio::ConnectionManager::new(client)
+        .await
+        .map_err(|e| ApiError::ExternalServiceError(format!("Failed to connect to Redis: {}", e)))
+}
+
+/// Recommendation service configured from `config`
+pub fn recommendation_service(
+    config: &Config,
+    sentence_encoder: HuggingFaceEmbedder,
+    pinecone: Pinecone,
+) -> RecommendationService {
+    load_synonyms(config.synonyms_file.as_deref());
+    cache::set_memory_budget(config.server.cache_memory_budget_bytes());
+    let llm = llm_backend(config);
+    let service = RecommendationService::new(sentence_encoder, pinecone)
+        .with_slow_query_log(SlowQueryLog::new(
+            config.slow_query_threshold_ms,
+            config.slow_query_log_capacity,
+        ))
+        .with_author_aliases(load_author_aliases(config.author_aliases_file.as_deref()))
+        .with_experiments(load_experiments(config.experiments_file.as_deref()))
+        .with_feature_flags(FeatureFlags::new(config.feature_flags.clone()))
+        .with_shadow(ShadowRanking::new(config.shadow.clone()))
+        .with_post_filters(PostFilters::new(&config.post_filters, &config.tenants))
+        .with_warmup_queries(config.warmup_queries.clone())
+        .with_sparse_keywords(config.pinecone_sparse_keywords)
+        .with_explainer(explainer(llm.clone()))
+        .with_query_parser(QueryParser::new(llm))
+        .with_retrieval(config.retrieval.clone())
+        .with_max_concurrent_recommendations(config.server.max_concurrent_recommendations)
+        .with_max_queued_recommendations(config.server.max_queued_recommendations);
+    let service = match config.random_seed {
+        Some(seed) => service.with_random_seed(seed),
+        None => service,
+    };
+    let service = match load_fulltext_index(config.fulltext_index_dir.as_deref()) {
+        Some(fulltext) => service.with_fulltext(fulltext),
+        None => service,
+    };
+    match load_corpus_stats(config.corpus_stats_file.as_deref()) {
+        Some(corpus_stats) => service.with_corpus_stats(corpus_stats),
+        None => service,
+    }
+}
+
+/// Explainer using the text generation backend, or templates only without one
+pub fn explainer(llm: Option<Arc<LlmBackend>>) -> Explainer {
+    let Some(backend) = llm else {
+        return Explainer::default();
+    };
+    let explainer = Explainer::default().with_backend(backend);
+    info!("Explanations written by {}", explainer.backend_name());
+    explainer
+}
+
+/// The configured text generation backend, which writes explanations and reads
+/// query constraints; none when unset or misconfigured
+pub fn llm_backend(config: &Config) -> Option<Arc<LlmBackend>> {
+    let backend = config.explanation_backend.as_deref()?;
+
+    let (provider, api_key) = match backend.trim().to_lowercase().as_str() {
+        "openai" => (LlmProvider::OpenAi, config.openai_api_key.clone()),
+        "huggingface" => (
+            LlmProvider::HuggingFace,
+            std::env::var("APP_HUGGINGFACE_API_KEY").ok(),
+        ),
+        other => {
+            warn!(
+                "Unknown explanation backend '{}'. Explanations and query parsing use templates",
+                other
+            );
+            return None;
+        }
+    };
+    let Some(api_key) = api_key.filter(|key| !key.trim().is_empty()) else {
+        warn!(
+            "No API key for the {} explanation backend. Explanations and query parsing use templates",
+            backend
+        );
+        return None;
+    };
+
+    match LlmBackend::new(provider, api_key, config.explanation_model.clone()) {
+        Ok(backend) => Some(Arc::new(backend)),
+        Err(e) => {
+            warn!("{}. Explanations and query parsing use templates", e);
+            None
+        }
+    }
+}
+
+/// Corpus statistics snapshot from `path`, or none if it's unset or can't be loaded
+pub fn load_corpus_stats(path: Option<impl AsRef<Path>>) -> Option<CorpusStats> {
+    let path = path?;
+
+    match CorpusStats::load(path.as_ref()) {
+        Ok(stats) => {
+            info!(
+                "Loaded corpus statistics for {} books ({} titles) from {}",
+                stats.books,
+                stats.title_count(),
+                path.as_ref().display()
+            );
+            Some(stats)
+        }
+        Err(e) => {
+            warn!("{}. Title suggestions are unavailable", e);
+            None
+        }
+    }
+}
+
+/// The full-text index in `dir`, or none if it's unset or can't be opened
+pub fn load_fulltext_index(dir: Option<impl AsRef<Path>>) -> Option<FullTextIndex> {
+    let dir = dir?;
+
+    match FullTextIndex::open(dir.as_ref()) {
+        Ok(index) => {
+            info!(
+                "Opened full-text index of {} books from {}",
+                index.len(),
+                dir.as_ref().display()
+            );
+            Some(index)
+        }
+        Err(e) => {
+            warn!("{}. Searches need Pinecone and embeddings", e);
+            None
+        }
+    }
+}
+
+/// Merge the synonyms at `path` into the query keyword tables, if it's set and loads
+pub fn load_synonyms(path: Option<impl AsRef<Path>>) {
+    let Some(path) = path else {
+        return;
+    };
+
+    match SynonymDictionary::load(path.as_ref()) {
+        Ok(synonyms) => {
+            set_synonyms(&synonyms);
+            info!(
+                "Loaded synonyms for {} genres and {} themes from {}",
+                synonyms.genres.len(),
+                synonyms.themes.len(),
+                path.as_ref().display()
+            );
+        }
+        Err(e) => warn!("{}. Queries are matched with the compiled-in keywords", e),
+    }
+}
+
+/// Ranking experiments from `path`, or none if it's unset or can't be loaded
+pub fn load_experiments(path: Option<impl AsRef<Path>>) -> Experiments {
+    let Some(path) = path else {
+        return Experiments::default();
+    };
+
+    match Experiments::load(path.as_ref()) {
+        Ok(experiments) => {
+            info!(
+                "Loaded {} enabled experiments from {}",
+                experiments.len(),
+                path.as_ref().display()
+            );
+            experiments
+        }
+        Err(e) => {
+            warn!("{}. Every request gets the default ranking", e);
+            Experiments::default()
+        }
+    }
+}
+
+/// Author alias map from `path`, or an empty one if it's unset or can't be loaded
+pub fn load_author_aliases(path: Option<impl AsRef<Path>>) -> AuthorAliases {
+    let Some(path) = path else {
+        return AuthorAliases::default();
+    };
+
+    match AuthorAliases::load(path.as_ref()) {
+        Ok(aliases) => {
+            info!(
+                "Loaded {} author aliases from {}",
+                aliases.len(),
+                path.as_ref().display()
+            );
+            aliases
+        }
+        Err(e) => {
+            warn!("{}. Author names will only be formatted, not aliased", e);
+            AuthorAliases::default()
+        }
+    }
+}
+
+/// Category taxonomy from `path`, or an empty one (no genres) if it's unset or can't be loaded
+pub fn load_category_taxonomy(path: Option<impl AsRef<Path>>) -> CategoryTaxonomy {
+    let Some(path) = path else {
+        return CategoryTaxonomy::default();
+    };
+
+    match CategoryTaxonomy::load(path.as_ref()) {
+        Ok(taxonomy) => {
+            info!("Loaded category taxonomy from {}", path.as_ref().display());
+            taxonomy
+        }
+        Err(e) => {
+            warn!("{}. Books will be indexed without genres", e);
+            CategoryTaxonomy::default()
+        }
+    }
+}
+
+/// Award winners from `path`, or an empty list if it's unset or unreadable
+pub fn load_awards(path: Option<impl AsRef<Path>>) -> AwardList {
+    let Some(path) = path else {
+        return AwardList::default();
+    };
+
+    match AwardList::load(path.as_ref()) {
+        Ok(awards) => {
+            info!(
+                "Loaded {} award wins from {}",
+                awards.len(),
+                path.as_ref().display()
+            );
+            awards
+        }
+        Err(e) => {
+            warn!("{}. Books will be indexed without awards", e);
+            AwardList::default()
+        }
+    }
+}
+
+/// Supabase client for the configured database, or an in-memory one without it
+pub async fn init_supabase(config: &Config) -> SupabaseClient {
+    match &config.database_url {
+        Some(database_url) => match SupabaseClient::connect(database_url).await {
+            Ok(client) => client,
+            Err(e) => {
+                warn!(
+                    "Failed to connect to Supabase: {}. User data will be kept in memory only",
+                    e
+                );
+                SupabaseClient::in_memory()
+            }
+        },
+        None => {
+            info!("No database configured, user data will be kept in memory only");
+            SupabaseClient::in_memory()
+        }
+    }
+}
+
+/// Audit log sharing the Supabase pool, or kept in memory without one
+pub async fn init_audit_log(supabase: &SupabaseClient) -> AuditLog {
+    let Some(pool) = supabase.pool() else {
+        info!("No database configured, admin audit log will be kept in memory only");
+        return AuditLog::in_memory();
+    };
+
+    match AuditLog::from_pool(pool.clone()).await {
+        Ok(audit_log) => audit_log,
+        Err(e) => {
+            warn!(
+                "Failed to prepare audit log table: {}. Audit entries will be kept in memory only",
+                e
+            );
+            AuditLog::in_memory()
+        }
+    }
+}
+
+/// Book version history sharing the Supabase pool, or kept in memory without one
+pub async fn init_book_history(supabase: &SupabaseClient) -> BookHistory {
+    let Some(pool) = supabase.pool() else {
+        return BookHistory::in_memory();
+    };
+
+    match BookHistory::from_pool(pool.clone()).await {
+        Ok(history) => history,
+        Err(e) => {
+            warn!(
+                "Failed to prepare book versions table: {}. Book history will be kept in memory only",
+                e
+            );
+            BookHistory::in_memory()
+        }
+    }
+}
+
+/// Job queue recording jobs through the Supabase pool, or in memory without one
+pub async fn init_job_queue(supabase: &SupabaseClient) -> JobQueue {
+    let Some(pool) = supabase.pool() else {
+        return JobQueue::in_memory();
+    };
+
+    match JobQueue::from_pool(pool.clone()).await {
+        Ok(queue) => queue,
+        Err(e) => {
+            warn!(
+                "Failed to prepare jobs table: {}. Jobs will be kept in memory only",
+                e
+            );
+            JobQueue::in_memory()
+        }
+    }
+}
+
+/// Queue a prewarm of the service as a job, unless it's prewarmed or one is
+/// already under way on this instance; returns the queued job
+pub async fn queue_prewarm(
+    service: Arc<RecommendationService>,
+    jobs: &JobQueue,
+) -> Result<Option<Job>> {
+    if service.is_prewarmed() || jobs.has_unfinished(PREWARM_JOB) {
+        return Ok(None);
+    }
+    let job = jobs
+        .submit(PREWARM_JOB, serde_json::json!({}), move |_job| async move {
+            info!("Starting background prewarm process");
+            let first = service.prewarm().await.inspect_err(|e| {
+                warn!("Background prewarming encountered an error: {}", e);
+            })?;
+            info!("Background prewarming completed successfully");
+            Ok(serde_json::json!({ "first_prewarm": first }))
+        })
+        .await?;
+    Ok(Some(job))
+}
+
+/// Tenant usage meter, sharing counts through Supabase when it's configured
+pub async fn init_usage_meter(config: &Config, supabase: &SupabaseClient) -> UsageMeter {
+    let pool = supabase.pool().filter(|_| !config.tenants.is_empty());
+    let Some(pool) = pool else {
+        return UsageMeter::new(&config.tenants);
+    };
+
+    match UsageMeter::from_pool(&config.tenants, pool.clone()).await {
+        Ok(meter) => meter,
+        Err(e) => {
+            warn!(
+                "Failed to prepare tenant usage table: {}. Usage will be kept in memory only",
+                e
+            );
+            UsageMeter::new(&config.tenants)
+        }
+    }
+}
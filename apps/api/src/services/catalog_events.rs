@@ -0,0 +1,328 @@
+//! Catalog change notifications for downstream consumers
+//!
+//! The `rab-admin` commands that change the catalog publish a [`CatalogEvent`]
+//! when they finish, so caches and search UIs built on the catalog can
+//! invalidate instead of polling. Each event is POSTed as JSON to every
+//! configured webhook, signed when a secret is set, and appended to a Redis
+//! stream when one is configured. Delivery is best effort: failures are logged
+//! and never fail the command that changed the catalog.
+
+use chrono::Utc;
+use log::{info, warn};
+use recommend_a_book_client::RetryPolicy;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Book ids carried by one event; larger changes are split over several events
+pub const MAX_BOOK_IDS_PER_EVENT: usize = 500;
+
+/// Names the event type of a webhook delivery
+pub const EVENT_HEADER: &str = "X-Catalog-Event";
+
+/// Unique id of a webhook delivery, the same across its retries
+pub const DELIVERY_HEADER: &str = "X-Catalog-Delivery";
+
+/// `sha256=<hex>` HMAC of the request body under the webhook secret
+pub const SIGNATURE_HEADER: &str = "X-Catalog-Signature";
+
+/// How long a webhook may take to answer
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Entries kept in the Redis stream; older ones are trimmed as new ones arrive
+#[cfg(feature = "redis")]
+const STREAM_MAX_LEN: usize = 10_000;
+
+/// A change to the catalog
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CatalogEvent {
+    /// Books indexed for the first time
+    BooksAdded { book_ids: Vec<String> },
+    /// Books deleted from the index and the graph
+    BooksRemoved { book_ids: Vec<String> },
+    /// The book graph was rebuilt, so related-book results may have changed
+    GraphRebuilt { books: usize, relationships: usize },
+}
+
+impl CatalogEvent {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            CatalogEvent::BooksAdded { .. } => "books_added",
+            CatalogEvent::BooksRemoved { .. } => "books_removed",
+            CatalogEvent::GraphRebuilt { .. } => "graph_rebuilt",
+        }
+    }
+
+    /// Split into events of at most `max_ids` book ids each
+    pub fn split(self, max_ids: usize) -> Vec<CatalogEvent> {
+        let max_ids = max_ids.max(1);
+        match self {
+            CatalogEvent::BooksAdded { book_ids } => book_ids
+                .chunks(max_ids)
+                .map(|chunk| CatalogEvent::BooksAdded {
+                    book_ids: chunk.to_vec(),
+                })
+                .collect(),
+            CatalogEvent::BooksRemoved { book_ids } => book_ids
+                .chunks(max_ids)
+                .map(|chunk| CatalogEvent::BooksRemoved {
+                    book_ids: chunk.to_vec(),
+                })
+                .collect(),
+            event => vec![event],
+        }
+    }
+}
+
+/// An event as delivered, with the id and time consumers deduplicate and order by
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CatalogEventMessage {
+    pub id: String,
+    /// RFC3339 timestamp
+    pub occurred_at: String,
+    #[serde(flatten)]
+    pub event: CatalogEvent,
+}
+
+/// Delivers catalog events to webhooks and, with the `redis` feature, a Redis stream
+pub struct CatalogEventPublisher {
+    client: reqwest::Client,
+    webhook_urls: Vec<String>,
+    secret: Option<String>,
+    retry: RetryPolicy,
+    #[cfg(feature = "redis")]
+    stream: Option<(redis::aio::ConnectionManager, String)>,
+}
+
+impl CatalogEventPublisher {
+    pub fn new(webhook_urls: Vec<String>) -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(WEBHOOK_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+            webhook_urls,
+            secret: None,
+            retry: RetryPolicy::default(),
+            #[cfg(feature = "redis")]
+            stream: None,
+        }
+    }
+
+    /// Sign webhook bodies with `secret`
+    pub fn with_secret(mut self, secret: impl Into<String>) -> Self {
+        self.secret = Some(secret.into());
+        self
+    }
+
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Also append events to the Redis stream `stream`
+    #[cfg(feature = "redis")]
+    pub fn with_redis_stream(
+        mut self,
+        connection: redis::aio::ConnectionManager,
+        stream: impl Into<String>,
+    ) -> Self {
+        self.stream = Some((connection, stream.into()));
+        self
+    }
+
+    /// Whether any webhook or stream is configured
+    pub fn is_enabled(&self) -> bool {
+        #[cfg(feature = "redis")]
+        if self.stream.is_some() {
+            return true;
+        }
+        !self.webhook_urls.is_empty()
+    }
+
+    /// Deliver `event` to every destination, split if it names many books
+    pub async fn publish(&self, event: CatalogEvent) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        for event in event.split(MAX_BOOK_IDS_PER_EVENT) {
+            let message = CatalogEventMessage {
+                id: Uuid::new_v4().to_string(),
+                occurred_at: Utc::now().to_rfc3339(),
+                event,
+            };
+            let body = match serde_json::to_string(&message) {
+                Ok(body) => body,
+                Err(e) => {
+                    warn!("Failed to serialize catalog event: {}", e);
+                    continue;
+                }
+            };
+
+            for url in &self.webhook_urls {
+                self.deliver_webhook(url, &message, &body).await;
+            }
+
+            #[cfg(feature = "redis")]
+            if let Some((connection, stream)) = &self.stream {
+                let result: redis::RedisResult<String> = redis::cmd("XADD")
+                    .arg(stream)
+                    .arg("MAXLEN")
+                    .arg("~")
+                    .arg(STREAM_MAX_LEN)
+                    .arg("*")
+                    .arg("type")
+                    .arg(message.event.kind())
+                    .arg("event")
+                    .arg(&body)
+                    .query_async(&mut connection.clone())
+                    .await;
+                if let Err(e) = result {
+                    warn!(
+                        "Failed to append catalog event to Redis stream {}: {}",
+                        stream, e
+                    );
+                }
+            }
+        }
+    }
+
+    async fn deliver_webhook(&self, url: &str, message: &CatalogEventMessage, body: &str) {
+        let mut attempt = 0;
+        loop {
+            let mut request = self
+                .client
+                .post(url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header(EVENT_HEADER, message.event.kind())
+                .header(DELIVERY_HEADER, &message.id)
+                .body(body.to_string());
+            if let Some(secret) = &self.secret {
+                request = request.header(SIGNATURE_HEADER, signature(secret, body.as_bytes()));
+            }
+
+            let error = match request.send().await {
+                Ok(response) if response.status().is_success() => {
+                    info!(
+                        "Delivered {} event {} to {}",
+                        message.event.kind(),
+                        message.id,
+                        url
+                    );
+                    return;
+                }
+                Ok(response) if RetryPolicy::is_retryable_status(response.status()) => {
+                    format!("status {}", response.status())
+                }
+                Ok(response) => {
+                    warn!(
+                        "Webhook {} rejected {} event {} with status {}",
+                        url,
+                        message.event.kind(),
+                        message.id,
+                        response.status()
+                    );
+                    return;
+                }
+                Err(e) if e.is_connect() || e.is_timeout() => e.to_string(),
+                Err(e) => {
+                    warn!("Failed to deliver catalog event to {}: {}", url, e);
+                    return;
+                }
+            };
+
+            match self.retry.delay(attempt, None) {
+                Some(delay) => {
+                    attempt += 1;
+                    tokio::time::sleep(delay).await;
+                }
+                None => {
+                    warn!(
+                        "Gave up delivering {} event {} to {} after {} attempts: {}",
+                        message.event.kind(),
+                        message.id,
+                        url,
+                        attempt + 1,
+                        error
+                    );
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// HMAC-SHA256 of `body` under `secret`, as `sha256=<hex>`
+pub fn signature(secret: &str, body: &[u8]) -> String {
+    let mut hex = String::from("sha256=");
+    for byte in hmac_sha256(secret.as_bytes(), body) {
+        let _ = write!(hex, "{:02x}", byte);
+    }
+    hex
+}
+
+/// HMAC-SHA256 of `message` under `secret`
+pub(crate) fn hmac_sha256(secret: &[u8], message: &[u8]) -> [u8; 32] {
+    const BLOCK_SIZE: usize = 64;
+
+    let mut key = [0u8; BLOCK_SIZE];
+    if secret.len() > BLOCK_SIZE {
+        key[..32].copy_from_slice(&Sha256::digest(secret));
+    } else {
+        key[..secret.len()].copy_from_slice(secret);
+    }
+
+    let mut inner = Sha256::new();
+    inner.update(key.map(|byte| byte ^ 0x36));
+    inner.update(message);
+    let mut outer = Sha256::new();
+    outer.update(key.map(|byte| byte ^ 0x5c));
+    outer.update(inner.finalize());
+    outer.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_large_changes_are_split_and_messages_are_tagged() {
+        let book_ids: Vec<String> = (0..5).map(|i| format!("book-{}", i)).collect();
+        let events = CatalogEvent::BooksAdded { book_ids }.split(2);
+        assert_eq!(events.len(), 3);
+        assert_eq!(
+            events[2],
+            CatalogEvent::BooksAdded {
+                book_ids: vec!["book-4".to_string()]
+            }
+        );
+
+        let rebuilt = CatalogEvent::GraphRebuilt {
+            books: 10,
+            relationships: 40,
+        };
+        assert_eq!(rebuilt.clone().split(2), vec![rebuilt.clone()]);
+
+        let message = CatalogEventMessage {
+            id: "delivery-1".to_string(),
+            occurred_at: "2024-01-15T10:30:00+00:00".to_string(),
+            event: rebuilt,
+        };
+        let json = serde_json::to_value(&message).unwrap();
+        assert_eq!(json["type"], "graph_rebuilt");
+        assert_eq!(json["relationships"], 40);
+        assert_eq!(json["id"], "delivery-1");
+    }
+
+    #[test]
+    fn test_signature_is_hmac_sha256() {
+        assert_eq!(
+            signature("key", b"The quick brown fox jumps over the lazy dog"),
+            "sha256=f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+    }
+}
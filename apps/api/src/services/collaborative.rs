@@ -0,0 +1,176 @@
+//! Item-item collaborative filtering from co-shelving
+//!
+//! "Users who shelved X also shelved Y": two books score higher the more users
+//! have both on their shelves, normalized by how popular each book is
+//! (`shared / sqrt(users(X) * users(Y))`). When most of those users shelved X
+//! before Y, the pair also suggests reading Y next.
+
+use crate::services::supabase::ShelfEntry;
+use std::collections::HashMap;
+
+pub const DEFAULT_MIN_SHARED_USERS: usize = 2;
+pub const DEFAULT_MAX_NEIGHBOURS: usize = 20;
+
+/// Only a user's most recently shelved books are paired, to bound the work per user
+const MAX_BOOKS_PER_USER: usize = 200;
+
+#[derive(Debug, Clone)]
+pub struct CoShelvingConfig {
+    /// Pairs shelved together by fewer users than this are ignored
+    pub min_shared_users: usize,
+    /// Strongest neighbours kept per book
+    pub max_neighbours: usize,
+}
+
+impl Default for CoShelvingConfig {
+    fn default() -> Self {
+        Self {
+            min_shared_users: DEFAULT_MIN_SHARED_USERS,
+            max_neighbours: DEFAULT_MAX_NEIGHBOURS,
+        }
+    }
+}
+
+/// A directed pair of books that users tend to shelve together
+#[derive(Debug, Clone, PartialEq)]
+pub struct CoShelvedPair {
+    pub from_id: String,
+    pub to_id: String,
+    /// Users with both books on their shelves
+    pub shared_users: usize,
+    /// Co-occurrence normalized by popularity, in 0..=1
+    pub cf_score: f32,
+    /// More of the shared users shelved `from_id` before `to_id` than the other way round
+    pub read_next: bool,
+}
+
+#[derive(Default)]
+struct PairCounts {
+    shared: usize,
+    /// Users who shelved the lexically smaller id first
+    first_before_second: usize,
+    second_before_first: usize,
+}
+
+/// Directed co-shelving pairs, each book's strongest neighbours first
+pub fn co_shelving_pairs(entries: &[ShelfEntry], config: &CoShelvingConfig) -> Vec<CoShelvedPair> {
+    let mut shelves: HashMap<&str, Vec<&ShelfEntry>> = HashMap::new();
+    for entry in entries {
+        shelves.entry(&entry.user_id).or_default().push(entry);
+    }
+
+    let mut book_users: HashMap<&str, usize> = HashMap::new();
+    let mut pairs: HashMap<(&str, &str), PairCounts> = HashMap::new();
+    for books in shelves.values_mut() {
+        books.sort_by(|a, b| b.added_at.cmp(&a.added_at));
+        books.truncate(MAX_BOOKS_PER_USER);
+
+        for book in books.iter() {
+            *book_users.entry(&book.book_id).or_default() += 1;
+        }
+        for (i, a) in books.iter().enumerate() {
+            for b in &books[i + 1..] {
+                let (first, second) = if a.book_id < b.book_id {
+                    (a, b)
+                } else {
+                    (b, a)
+                };
+                let counts = pairs.entry((&first.book_id, &second.book_id)).or_default();
+                counts.shared += 1;
+                match first.added_at.cmp(&second.added_at) {
+                    std::cmp::Ordering::Less => counts.first_before_second += 1,
+                    std::cmp::Ordering::Greater => counts.second_before_first += 1,
+                    std::cmp::Ordering::Equal => {}
+                }
+            }
+        }
+    }
+
+    let mut neighbours: HashMap<&str, Vec<CoShelvedPair>> = HashMap::new();
+    for ((first, second), counts) in pairs {
+        if counts.shared < config.min_shared_users.max(1) {
+            continue;
+        }
+        let popularity = (book_users[first] * book_users[second]) as f32;
+        let cf_score = (counts.shared as f32 / popularity.sqrt()).min(1.0);
+
+        for (from, to, from_first, to_first) in [
+            (
+                first,
+                second,
+                counts.first_before_second,
+                counts.second_before_first,
+            ),
+            (
+                second,
+                first,
+                counts.second_before_first,
+                counts.first_before_second,
+            ),
+        ] {
+            neighbours.entry(from).or_default().push(CoShelvedPair {
+                from_id: from.to_string(),
+                to_id: to.to_string(),
+                shared_users: counts.shared,
+                cf_score,
+                read_next: from_first > to_first,
+            });
+        }
+    }
+
+    let mut books: Vec<&str> = neighbours.keys().copied().collect();
+    books.sort();
+    let mut result = Vec::new();
+    for book in books {
+        let mut pairs = neighbours.remove(book).unwrap_or_default();
+        pairs.sort_by(|a, b| {
+            b.cf_score
+                .partial_cmp(&a.cf_score)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| a.to_id.cmp(&b.to_id))
+        });
+        pairs.truncate(config.max_neighbours);
+        result.extend(pairs);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::supabase::ShelfStatus;
+
+    fn entry(user: &str, book: &str, day: u32) -> ShelfEntry {
+        ShelfEntry {
+            user_id: user.to_string(),
+            book_id: book.to_string(),
+            status: ShelfStatus::Read,
+            added_at: format!("2024-01-{:02}T00:00:00+00:00", day),
+            updated_at: format!("2024-01-{:02}T00:00:00+00:00", day),
+        }
+    }
+
+    #[test]
+    fn test_pairs_shelved_by_several_users() {
+        let entries = vec![
+            entry("ann", "hobbit", 1),
+            entry("ann", "rings", 2),
+            entry("bob", "hobbit", 3),
+            entry("bob", "rings", 4),
+            entry("bob", "dune", 5),
+            entry("cat", "dune", 1),
+        ];
+
+        let pairs = co_shelving_pairs(&entries, &CoShelvingConfig::default());
+        assert_eq!(pairs.len(), 2);
+
+        let hobbit = pairs.iter().find(|p| p.from_id == "hobbit").unwrap();
+        assert_eq!(hobbit.to_id, "rings");
+        assert_eq!(hobbit.shared_users, 2);
+        assert!((hobbit.cf_score - 1.0).abs() < 1e-6);
+        assert!(hobbit.read_next);
+
+        let rings = pairs.iter().find(|p| p.from_id == "rings").unwrap();
+        assert!(!rings.read_next);
+    }
+}
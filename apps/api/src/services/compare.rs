@@ -0,0 +1,213 @@
+//! Side-by-side comparison of a few books
+//!
+//! Backs the "compare these books" view: what the books have in common, how
+//! far apart they are on rating, length and publication year, how close their
+//! embeddings are and how the book graph links them. Fields a book lacks are
+//! left out of the comparison rather than counted as zero.
+
+use crate::models::Book;
+use crate::services::neo4j::GraphRelationshipResponse;
+use crate::services::personalization::cosine_similarity;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use utoipa::ToSchema;
+
+/// Fewest books a comparison takes
+pub const MIN_COMPARED_BOOKS: usize = 2;
+
+/// Most books a comparison takes
+pub const MAX_COMPARED_BOOKS: usize = 5;
+
+/// How two of the compared books differ; each delta is `second - first`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct PairComparison {
+    #[schema(example = "9780547928227")]
+    pub first_id: String,
+    #[schema(example = "9780441013593")]
+    pub second_id: String,
+    #[schema(example = -0.2)]
+    pub rating_delta: f32,
+    /// Missing when either page count is unknown
+    #[schema(example = 102)]
+    pub page_count_delta: Option<i32>,
+    /// Missing when either publication year is unknown
+    #[schema(example = 28)]
+    pub year_delta: Option<i32>,
+    /// Cosine similarity of the two embeddings; missing when either isn't stored
+    #[schema(example = 0.62)]
+    pub similarity: Option<f32>,
+}
+
+/// A structured comparison of two to five books
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BookComparison {
+    /// The books, in the order asked for
+    pub books: Vec<Book>,
+    /// Genres every book has, from the genre vocabulary or else the categories
+    #[schema(example = json!(["fantasy"]))]
+    pub shared_genres: Vec<String>,
+    /// Moods, settings and awards every book has
+    #[schema(example = json!(["whimsical"]))]
+    pub shared_themes: Vec<String>,
+    /// Every pair of books, in the order asked for
+    pub pairs: Vec<PairComparison>,
+    /// Embedding similarity of each book to each other, rows and columns in book
+    /// order; null where an embedding isn't stored
+    #[schema(example = json!([[1.0, 0.62], [0.62, 1.0]]))]
+    pub similarity_matrix: Vec<Vec<Option<f32>>>,
+    /// Graph relationships between the compared books
+    pub relationships: Vec<GraphRelationshipResponse>,
+}
+
+/// Genres of a book for comparison: its controlled genres, or its categories
+/// when the taxonomy mapped none
+fn comparable_genres(book: &Book) -> Vec<String> {
+    let labels = if book.genres.is_empty() {
+        &book.categories
+    } else {
+        &book.genres
+    };
+    labels.iter().map(|label| label.to_lowercase()).collect()
+}
+
+/// Labels every list has, in the order of the first
+fn shared(lists: &[Vec<String>]) -> Vec<String> {
+    let Some((first, rest)) = lists.split_first() else {
+        return Vec::new();
+    };
+    let mut common: Vec<String> = Vec::new();
+    for label in first {
+        if rest.iter().all(|list| list.contains(label)) && !common.contains(label) {
+            common.push(label.clone());
+        }
+    }
+    common
+}
+
+/// Compare `books` using their stored embeddings and the graph relationships among them
+pub fn compare_books(
+    books: Vec<Book>,
+    vectors: &HashMap<String, Vec<f32>>,
+    relationships: Vec<GraphRelationshipResponse>,
+) -> BookComparison {
+    let ids: Vec<&str> = books
+        .iter()
+        .map(|book| book.id.as_deref().unwrap_or_default())
+        .collect();
+    let similarity = |a: &str, b: &str| -> Option<f32> {
+        Some(cosine_similarity(vectors.get(a)?, vectors.get(b)?))
+    };
+
+    let similarity_matrix: Vec<Vec<Option<f32>>> = ids
+        .iter()
+        .map(|a| ids.iter().map(|b| similarity(a, b)).collect())
+        .collect();
+
+    // Unknown page counts are stored as 0
+    let known_pages = |book: &Book| book.page_count.filter(|&pages| pages > 0);
+    let mut pairs = Vec::new();
+    for (i, first) in books.iter().enumerate() {
+        for (j, second) in books.iter().enumerate().skip(i + 1) {
+            pairs.push(PairComparison {
+                first_id: ids[i].to_string(),
+                second_id: ids[j].to_string(),
+                rating_delta: second.rating - first.rating,
+                page_count_delta: known_pages(second)
+                    .zip(known_pages(first))
+                    .map(|(b, a)| b - a),
+                year_delta: second.year.zip(first.year).map(|(b, a)| b - a),
+                similarity: similarity_matrix[i][j],
+            });
+        }
+    }
+
+    let shared_genres = shared(&books.iter().map(comparable_genres).collect::<Vec<_>>());
+    let shared_themes = shared(
+        &books
+            .iter()
+            .map(|book| {
+                book.moods
+                    .iter()
+                    .chain(&book.settings)
+                    .chain(&book.awards)
+                    .cloned()
+                    .collect()
+            })
+            .collect::<Vec<_>>(),
+    );
+    let relationships = relationships
+        .into_iter()
+        .filter(|rel| ids.contains(&rel.from_id.as_str()) && ids.contains(&rel.to_id.as_str()))
+        .collect();
+
+    BookComparison {
+        books,
+        shared_genres,
+        shared_themes,
+        pairs,
+        similarity_matrix,
+        relationships,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_comparison_lists_pairs_and_shared_labels() {
+        let book = |id: &str, genres: &[&str], moods: &[&str], pages: i32, year: i32| -> Book {
+            serde_json::from_value(serde_json::json!({
+                "id": id,
+                "categories": ["Fiction"],
+                "genres": genres,
+                "moods": moods,
+                "rating": 4.0,
+                "page_count": pages,
+                "year": year,
+            }))
+            .unwrap()
+        };
+        let books = vec![
+            book("a", &["fantasy", "adventure"], &["whimsical"], 300, 1937),
+            book("b", &["fantasy"], &["whimsical", "dark"], 0, 1954),
+            book("c", &["fantasy", "horror"], &["whimsical"], 500, 2001),
+        ];
+        let vectors = HashMap::from([
+            ("a".to_string(), vec![1.0, 0.0]),
+            ("c".to_string(), vec![0.0, 1.0]),
+        ]);
+        let relationships = vec![
+            GraphRelationshipResponse {
+                from_id: "a".to_string(),
+                to_id: "b".to_string(),
+                relation_type: "SIMILAR_TO".to_string(),
+                weight: 0.8,
+            },
+            GraphRelationshipResponse {
+                from_id: "a".to_string(),
+                to_id: "z".to_string(),
+                relation_type: "SAME_AUTHOR".to_string(),
+                weight: 1.0,
+            },
+        ];
+
+        let comparison = compare_books(books, &vectors, relationships);
+        assert_eq!(comparison.shared_genres, vec!["fantasy"]);
+        assert_eq!(comparison.shared_themes, vec!["whimsical"]);
+        assert_eq!(comparison.relationships.len(), 1);
+
+        let ids: Vec<(&str, &str)> = comparison
+            .pairs
+            .iter()
+            .map(|pair| (pair.first_id.as_str(), pair.second_id.as_str()))
+            .collect();
+        assert_eq!(ids, vec![("a", "b"), ("a", "c"), ("b", "c")]);
+        assert_eq!(comparison.pairs[0].page_count_delta, None);
+        assert_eq!(comparison.pairs[1].page_count_delta, Some(200));
+        assert_eq!(comparison.pairs[1].year_delta, Some(64));
+        assert_eq!(comparison.pairs[1].similarity, Some(0.0));
+        assert_eq!(comparison.similarity_matrix[0][0], Some(1.0));
+        assert_eq!(comparison.similarity_matrix[1][0], None);
+    }
+}
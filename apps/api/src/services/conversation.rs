@@ -0,0 +1,367 @@
+//! Recommendations that follow a conversation instead of a single query
+//!
+//! A request's `context` lists the earlier queries and selections of the
+//! conversation, oldest first. Constraints of earlier turns the current query
+//! doesn't restate (genres, moods, length, era, setting and author) are carried
+//! into the query that is searched, so "something shorter" after "cozy
+//! mysteries set in scotland" still searches cozy mysteries set in Scotland.
+//! Negations such as "not YA" or "no romance" are taken out of the searched
+//! text, which would otherwise ask for what they rule out, and books matching
+//! them are left out for the rest of the conversation, until a later turn asks
+//! for the term again.
+
+use crate::error::{ApiError, Result};
+use crate::ingest::moods::moods_in_query;
+use crate::models::Book;
+use crate::services::recommendation::MAX_QUERY_LENGTH;
+use crate::services::templates::{
+    genre_keywords, genres_in, EnhancedQuery, HISTORICAL_PERIODS, LONG_READ_MIN_PAGES,
+    SHORT_READ_MAX_PAGES,
+};
+use lazy_static::lazy_static;
+use regex::Regex;
+
+/// Most earlier turns a request may carry
+pub const MAX_CONTEXT_TURNS: usize = 20;
+
+lazy_static! {
+    /// "not YA", "no romance, please", "without vampires and ..."
+    static ref NEGATION: Regex = Regex::new(
+        r"(?i)\b(?:not|no|without|except|avoid|skip)\s+(?:any\s+|more\s+)?([a-z][a-z' -]*?)\s*(?:[,;.!?]|\bbut\b|\band\b|\bor\b|\bplease\b|$)"
+    )
+    .unwrap();
+}
+
+/// Words after a negated term that aren't part of it, as in "no romance novels"
+const NEGATION_TRAILERS: &[&str] = &["books", "book", "novels", "novel", "stories", "anymore"];
+
+/// Connectives left dangling at the end once a negation is taken out
+const DANGLING_WORDS: &[&str] = &["but", "and", "or", "please", "with"];
+
+/// A term the conversation ruled out
+#[derive(Debug, Clone, PartialEq)]
+struct Exclusion {
+    /// The genre-table key the term names, e.g. "young adult" for "ya", or the
+    /// term itself when it names no genre
+    label: String,
+    /// Keywords matching the term in a book's genres, categories and moods
+    keywords: Vec<String>,
+}
+
+impl Exclusion {
+    fn new(term: &str) -> Self {
+        match genres_in(term).first() {
+            Some(genre) => {
+                let mut keywords: Vec<String> = genre_keywords(genre)
+                    .into_iter()
+                    .map(str::to_string)
+                    .collect();
+                keywords.push(genre.to_string());
+                Self {
+                    label: genre.to_string(),
+                    keywords,
+                }
+            }
+            None => Self {
+                label: term.to_string(),
+                keywords: vec![term.to_string()],
+            },
+        }
+    }
+
+    /// Whether a later turn asking for `text` asks for this term again
+    fn asked_for_in(&self, text: &str) -> bool {
+        genres_in(text).contains(&self.label.as_str()) || has_phrase(text, &self.label)
+    }
+
+    fn matches(&self, book: &Book) -> bool {
+        book.genres
+            .iter()
+            .chain(&book.categories)
+            .chain(&book.moods)
+            .any(|text| {
+                self.keywords
+                    .iter()
+                    .any(|keyword| has_phrase(text, keyword))
+            })
+    }
+}
+
+/// Whether `text` has `phrase` as whole words, ignoring case and punctuation
+fn has_phrase(text: &str, phrase: &str) -> bool {
+    let words = |text: &str| {
+        let words: Vec<String> = text
+            .to_lowercase()
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(str::to_string)
+            .collect();
+        format!(" {} ", words.join(" "))
+    };
+    let phrase = words(phrase);
+    phrase.trim() != "" && words(text).contains(&phrase)
+}
+
+/// A turn without its negations, and the terms they negate
+fn split_negations(turn: &str) -> (String, Vec<String>) {
+    let mut negated = Vec::new();
+    for captures in NEGATION.captures_iter(turn) {
+        let mut words: Vec<&str> = captures[1].split_whitespace().collect();
+        while words
+            .last()
+            .is_some_and(|word| NEGATION_TRAILERS.contains(&word.to_lowercase().as_str()))
+        {
+            words.pop();
+        }
+        if !words.is_empty() {
+            negated.push(words.join(" ").to_lowercase());
+        }
+    }
+
+    let positive = NEGATION.replace_all(turn, " ");
+    let mut words: Vec<&str> = positive.split_whitespace().collect();
+    while words.last().is_some_and(|word| {
+        DANGLING_WORDS.contains(&word.trim_matches(',').to_lowercase().as_str())
+    }) {
+        words.pop();
+    }
+    let positive = words.join(" ");
+    let positive = positive.trim_end_matches([',', ';']).to_string();
+    (positive, negated)
+}
+
+/// What a turn asks for, by the dimensions a later turn can leave open
+#[derive(Debug, Default)]
+struct TurnConstraints {
+    genres: Vec<&'static str>,
+    moods: Vec<String>,
+    /// "short" or "long"
+    length: Option<&'static str>,
+    /// "recent", "classic" or a historical period
+    era: Option<String>,
+    setting: Option<String>,
+    author: Option<String>,
+}
+
+impl TurnConstraints {
+    fn read(text: &str) -> Self {
+        let filters = EnhancedQuery::from_query(text).filters;
+        let period = filters
+            .settings
+            .iter()
+            .find(|setting| HISTORICAL_PERIODS.contains_key(setting.as_str()))
+            .cloned();
+        let era = period
+            .clone()
+            .or(match (filters.min_year, filters.max_year) {
+                (Some(_), None) => Some("recent".to_string()),
+                (None, Some(_)) => Some("classic".to_string()),
+                _ => None,
+            });
+        let length = match (filters.min_pages, filters.max_pages) {
+            (_, Some(pages)) if pages <= SHORT_READ_MAX_PAGES => Some("short"),
+            (Some(pages), _) if pages >= LONG_READ_MIN_PAGES => Some("long"),
+            _ => None,
+        };
+        Self {
+            genres: genres_in(text),
+            moods: moods_in_query(text),
+            length,
+            era,
+            setting: filters
+                .settings
+                .into_iter()
+                .find(|setting| Some(setting) != period.as_ref()),
+            author: filters.author,
+        }
+    }
+}
+
+/// The query a conversation's turn searches, and what the conversation ruled out
+#[derive(Debug, Clone, Default)]
+pub struct Conversation {
+    /// The current query without its negations, with the constraints of
+    /// earlier turns it leaves open
+    pub query: String,
+    exclusions: Vec<Exclusion>,
+}
+
+impl Conversation {
+    /// Read `query` in the light of the earlier turns `context`, oldest first
+    pub fn new(context: &[String], query: &str) -> Result<Self> {
+        if context.len() > MAX_CONTEXT_TURNS {
+            return Err(ApiError::InvalidInput(format!(
+                "context may list at most {} earlier turns",
+                MAX_CONTEXT_TURNS
+            )));
+        }
+        if context.iter().any(|turn| turn.len() > MAX_QUERY_LENGTH) {
+            return Err(ApiError::InvalidInput(format!(
+                "Each turn of the context may be at most {} characters",
+                MAX_QUERY_LENGTH
+            )));
+        }
+
+        let mut exclusions: Vec<Exclusion> = Vec::new();
+        let mut earlier: Vec<String> = Vec::new();
+        for turn in context
+            .iter()
+            .map(|turn| turn.trim())
+            .filter(|turn| !turn.is_empty())
+            .chain([query.trim()])
+        {
+            let (positive, negated) = split_negations(turn);
+            exclusions.retain(|exclusion| !exclusion.asked_for_in(&positive));
+            for term in negated {
+                let exclusion = Exclusion::new(&term);
+                if !exclusions.contains(&exclusion) {
+                    exclusions.push(exclusion);
+                }
+            }
+            earlier.push(positive);
+        }
+        let current = earlier.pop().unwrap_or_default();
+        earlier.retain(|turn| !turn.is_empty());
+
+        // A turn that only rules something out searches the turn before it
+        let base = if current.len() < 3 {
+            earlier.pop().unwrap_or(current)
+        } else {
+            current
+        };
+        let query = carry_constraints(&base, &earlier, &exclusions);
+        Ok(Self { query, exclusions })
+    }
+
+    /// Whether the conversation changed nothing about the current query
+    pub fn is_empty(&self) -> bool {
+        self.exclusions.is_empty()
+    }
+
+    /// Terms the conversation ruled out, as genres where they name one
+    pub fn excluded(&self) -> Vec<String> {
+        self.exclusions
+            .iter()
+            .map(|exclusion| exclusion.label.clone())
+            .collect()
+    }
+
+    /// Whether `book` matches nothing the conversation ruled out
+    pub fn allows(&self, book: &Book) -> bool {
+        !self
+            .exclusions
+            .iter()
+            .any(|exclusion| exclusion.matches(book))
+    }
+}
+
+/// `base` with the constraints of the most recent of `earlier` turns that set
+/// each dimension `base` leaves open, as long as the query stays searchable
+fn carry_constraints(base: &str, earlier: &[String], exclusions: &[Exclusion]) -> String {
+    let current = TurnConstraints::read(base);
+    let mut phrases: Vec<String> = Vec::new();
+    let carry = |open: bool, value: &dyn Fn(&TurnConstraints) -> Option<String>| {
+        if !open {
+            return None;
+        }
+        earlier
+            .iter()
+            .rev()
+            .find_map(|turn| value(&TurnConstraints::read(turn)))
+    };
+
+    let genres = carry(current.genres.is_empty(), &|turn| {
+        let genres: Vec<&str> = turn
+            .genres
+            .iter()
+            .copied()
+            .filter(|genre| !exclusions.iter().any(|exclusion| exclusion.label == *genre))
+            .collect();
+        (!genres.is_empty()).then(|| genres.join(" "))
+    });
+    phrases.extend(genres);
+    phrases.extend(carry(current.moods.is_empty(), &|turn| {
+        (!turn.moods.is_empty()).then(|| turn.moods.join(" "))
+    }));
+    phrases.extend(carry(current.length.is_none(), &|turn| {
+        turn.length.map(str::to_string)
+    }));
+    phrases.extend(carry(current.era.is_none(), &|turn| turn.era.clone()));
+    phrases.extend(carry(current.author.is_none(), &|turn| {
+        turn.author
+            .as_ref()
+            .map(|author| format!("by {} books", author))
+    }));
+    // Setting patterns read to the end of the query, so the setting goes last
+    phrases.extend(carry(current.setting.is_none(), &|turn| {
+        turn.setting
+            .as_ref()
+            .map(|setting| format!("set in {}", setting))
+    }));
+
+    let mut query = base.to_string();
+    for phrase in phrases {
+        if query.len() + phrase.len() < MAX_QUERY_LENGTH {
+            query = format!("{} {}", query, phrase).trim().to_string();
+        }
+    }
+    query
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book(categories: &[&str]) -> Book {
+        serde_json::from_value(serde_json::json!({
+            "id": "1",
+            "title": "A book",
+            "categories": categories,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_negations_are_taken_out_and_filter_books() {
+        let (positive, negated) = split_negations("fantasy with dragons but not YA books, please");
+        assert_eq!(positive, "fantasy with dragons");
+        assert_eq!(negated, vec!["ya"]);
+
+        let conversation =
+            Conversation::new(&["fantasy with dragons".to_string()], "no YA").unwrap();
+        assert_eq!(conversation.query, "fantasy with dragons");
+        assert_eq!(conversation.excluded(), vec!["young adult"]);
+        assert!(!conversation.allows(&book(&["Fantasy", "Teen"])));
+        assert!(conversation.allows(&book(&["Fantasy", "Mayan history"])));
+
+        // Asking for the term again lifts the exclusion
+        let conversation =
+            Conversation::new(&["fantasy".to_string(), "not YA".to_string()], "ya romance")
+                .unwrap();
+        assert!(conversation.is_empty());
+    }
+
+    #[test]
+    fn test_constraints_the_query_leaves_open_are_carried() {
+        let conversation = Conversation::new(
+            &[
+                "cozy mystery set in scotland".to_string(),
+                "no romance".to_string(),
+            ],
+            "something short",
+        )
+        .unwrap();
+        assert_eq!(
+            conversation.query,
+            "something short mystery cozy set in scotland"
+        );
+
+        // The query's own genre replaces the earlier one
+        let conversation =
+            Conversation::new(&["cozy mystery".to_string()], "science fiction").unwrap();
+        assert_eq!(conversation.query, "science fiction cozy");
+
+        let too_long = vec!["fantasy".to_string(); MAX_CONTEXT_TURNS + 1];
+        assert!(Conversation::new(&too_long, "dragons").is_err());
+    }
+}
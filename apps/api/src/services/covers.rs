@@ -0,0 +1,365 @@
+//! Reliable cover images for the frontend
+//!
+//! Catalog thumbnails are often broken or served over plain http, which browsers
+//! block as mixed content. [`cover_candidates`] lists the URLs worth trying for a
+//! book: its thumbnail upgraded to https, then Open Library's cover for its ISBN.
+//! The indexer keeps the first of them that answers with an image, and
+//! `/api/covers/{id}` proxies it over https, scaled down to the width asked for,
+//! snapped to one of [`COVER_WIDTHS`] so a handful of sizes per book are cached.
+
+use crate::cache::{CacheWeight, TtlCache};
+use crate::error::{ApiError, Result};
+use crate::ingest::isbn::normalize_isbn;
+use crate::models::Book;
+use actix_web::web::Bytes;
+use image::{imageops::FilterType, DynamicImage, ImageFormat, ImageReader, Limits};
+use log::{debug, warn};
+use reqwest::{header, Client, Url};
+use std::io::Cursor;
+use std::time::Duration;
+
+/// Open Library's covers by ISBN; `default=false` answers 404 instead of a blank image
+const OPEN_LIBRARY_COVERS_URL: &str = "https://covers.openlibrary.org/b/isbn";
+
+/// Narrowest and widest cover the proxy scales to
+pub const MIN_COVER_WIDTH: u32 = 16;
+pub const MAX_COVER_WIDTH: u32 = 1024;
+
+/// Widths covers are scaled to; a requested width is rounded down to one of these
+pub const COVER_WIDTHS: [u32; 7] = [16, 32, 64, 128, 256, 512, 1024];
+
+/// Largest cover the proxy downloads, so a bad URL can't exhaust memory
+const MAX_COVER_BYTES: usize = 5 * 1024 * 1024;
+
+/// Widest and tallest cover the proxy decodes, in pixels
+const MAX_COVER_DIMENSION: u32 = 8_000;
+
+/// Most memory decoding one cover may allocate
+const MAX_COVER_DECODE_BYTES: u64 = 128 * 1024 * 1024;
+
+/// Covers kept in memory, each size of each book counting once
+const COVER_CACHE_CAPACITY: usize = 1_000;
+
+/// How long a proxied cover, or the lack of one, is cached
+pub const COVER_CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Time allowed for a cover host to answer
+const COVER_FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Quality of the JPEGs written for scaled covers
+const JPEG_QUALITY: u8 = 85;
+
+/// A cover image ready to serve
+#[derive(Debug, Clone)]
+pub struct CoverImage {
+    pub content_type: String,
+    pub bytes: Bytes,
+}
+
+impl CacheWeight for CoverImage {
+    fn heap_size(&self) -> usize {
+        self.content_type.heap_size() + self.bytes.len()
+    }
+}
+
+/// `url` as an https URL, or None if it isn't an http(s) URL at all
+pub fn secure_url(url: &str) -> Option<String> {
+    let mut url = Url::parse(url.trim()).ok()?;
+    match url.scheme() {
+        "https" => {}
+        "http" => url.set_scheme("https").ok()?,
+        _ => return None,
+    }
+    Some(url.to_string())
+}
+
+/// Open Library's large cover for a book's ISBN
+pub fn open_library_cover(isbn: &str) -> Option<String> {
+    let isbn = normalize_isbn(isbn).ok()?;
+    Some(format!(
+        "{}/{}-L.jpg?default=false",
+        OPEN_LIBRARY_COVERS_URL, isbn
+    ))
+}
+
+/// Cover URLs to try for a book, best first
+pub fn cover_candidates(book: &Book) -> Vec<String> {
+    let mut candidates: Vec<String> = book
+        .thumbnail
+        .as_deref()
+        .and_then(secure_url)
+        .into_iter()
+        .collect();
+    if let Some(url) = book.isbn.as_deref().and_then(open_library_cover) {
+        if !candidates.contains(&url) {
+            candidates.push(url);
+        }
+    }
+    candidates
+}
+
+/// HTTP client for cover hosts
+pub fn cover_client() -> Result<Client> {
+    Client::builder()
+        .timeout(COVER_FETCH_TIMEOUT)
+        .user_agent("recommend-a-book/1.0")
+        .build()
+        .map_err(|e| ApiError::InternalError(format!("Failed to create HTTP client: {}", e)))
+}
+
+/// Whether `url` answers with an image
+pub async fn is_image(client: &Client, url: &str) -> bool {
+    match client.get(url).send().await {
+        Ok(response) => response.status().is_success() && image_content_type(&response).is_some(),
+        Err(e) => {
+            debug!("Cover {} is unreachable: {}", url, e);
+            false
+        }
+    }
+}
+
+fn image_content_type(response: &reqwest::Response) -> Option<String> {
+    response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| value.starts_with("image/"))
+        .map(str::to_string)
+}
+
+/// Download the image at `url`, or None if it isn't one; errors are the
+/// failures worth retrying, like timeouts and server errors
+async fn fetch_image(client: &Client, url: &str) -> Result<Option<CoverImage>> {
+    let mut response = client.get(url).send().await?;
+    let status = response.status();
+    if status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+        return Err(ApiError::ExternalServiceError(format!(
+            "Cover {} answered {}",
+            url, status
+        )));
+    }
+    if !status.is_success() {
+        debug!("Cover {} answered {}", url, response.status());
+        return Ok(None);
+    }
+    let Some(content_type) = image_content_type(&response) else {
+        return Ok(None);
+    };
+    if response
+        .content_length()
+        .is_some_and(|length| length as usize > MAX_COVER_BYTES)
+    {
+        warn!("Cover {} is too large to proxy", url);
+        return Ok(None);
+    }
+    // The length header may be missing or wrong, so stop reading once the body outgrows the cap
+    let mut bytes = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if bytes.len() + chunk.len() > MAX_COVER_BYTES {
+            warn!("Cover {} is too large to proxy", url);
+            return Ok(None);
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(Some(CoverImage {
+        content_type,
+        bytes: Bytes::from(bytes),
+    }))
+}
+
+/// The width of [`COVER_WIDTHS`] a cover asked to be at most `width` wide is scaled to
+pub fn snap_width(width: u32) -> u32 {
+    COVER_WIDTHS
+        .into_iter()
+        .rev()
+        .find(|&snapped| snapped <= width)
+        .unwrap_or(MIN_COVER_WIDTH)
+}
+
+/// Scale `cover` down to `width` pixels wide as a JPEG; narrower covers are left alone
+///
+/// Decoding and encoding are CPU-bound, so async callers run this on the
+/// blocking pool.
+pub fn scale_cover(cover: CoverImage, width: u32) -> Result<CoverImage> {
+    let unreadable = |e| ApiError::ExternalServiceError(format!("Unreadable cover image: {}", e));
+    let mut reader = ImageReader::new(Cursor::new(&cover.bytes))
+        .with_guessed_format()
+        .map_err(|e| unreadable(image::ImageError::IoError(e)))?;
+    // A small file can declare huge dimensions, so refuse them before allocating
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_COVER_DIMENSION);
+    limits.max_image_height = Some(MAX_COVER_DIMENSION);
+    limits.max_alloc = Some(MAX_COVER_DECODE_BYTES);
+    reader.limits(limits);
+    let image = reader.decode().map_err(unreadable)?;
+    if image.width() <= width {
+        return Ok(cover);
+    }
+
+    // JPEG has no alpha channel
+    let scaled = DynamicImage::ImageRgb8(
+        image
+            .resize(width, u32::MAX, FilterType::Triangle)
+            .to_rgb8(),
+    );
+    let mut bytes = Cursor::new(Vec::new());
+    let encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut bytes, JPEG_QUALITY);
+    scaled
+        .write_with_encoder(encoder)
+        .map_err(|e| ApiError::InternalError(format!("Failed to encode cover: {}", e)))?;
+    Ok(CoverImage {
+        content_type: ImageFormat::Jpeg.to_mime_type().to_string(),
+        bytes: Bytes::from(bytes.into_inner()),
+    })
+}
+
+/// Fetches, scales and caches book covers for `/api/covers/{id}`
+#[derive(Clone)]
+pub struct CoverProxy {
+    client: Client,
+    /// Covers by book id and width (0 for the original size); None for books without one
+    cache: TtlCache<(String, u32), Option<CoverImage>>,
+}
+
+impl CoverProxy {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            client: cover_client()?,
+            cache: TtlCache::new("covers", COVER_CACHE_CAPACITY, COVER_CACHE_TTL),
+        })
+    }
+
+    /// A cached cover of the book; `Some(None)` when it's known to have none
+    pub fn cached(&self, book_id: &str, width: Option<u32>) -> Option<Option<CoverImage>> {
+        self.cache
+            .get(&(book_id.to_string(), width.map_or(0, snap_width)))
+    }
+
+    /// The book's cover, at most `width` pixels wide when given
+    pub async fn cover(&self, book: &Book, width: Option<u32>) -> Result<Option<CoverImage>> {
+        let id = book.id.clone().unwrap_or_default();
+        let width = width.map(snap_width);
+        let key = (id.clone(), width.unwrap_or(0));
+        if let Some(cover) = self.cache.get(&key) {
+            return Ok(cover);
+        }
+
+        let original = match self.cache.get(&(id.clone(), 0)) {
+            Some(cover) => cover,
+            None => {
+                let cover = self.first_image(book).await?;
+                self.cache.insert((id, 0), cover.clone());
+                cover
+            }
+        };
+        let cover = match (original, width) {
+            (Some(cover), Some(width)) => Some(
+                tokio::task::spawn_blocking(move || scale_cover(cover, width))
+                    .await
+                    .map_err(|e| {
+                        ApiError::InternalError(format!("Cover scaling failed: {}", e))
+                    })??,
+            ),
+            (cover, _) => cover,
+        };
+        self.cache.insert(key, cover.clone());
+        Ok(cover)
+    }
+
+    /// The first of the book's cover candidates that downloads as an image
+    ///
+    /// `None` only when every candidate answered without an image, so a missing
+    /// cover is cached; when a host failed instead, its error is returned and
+    /// the next request tries again.
+    async fn first_image(&self, book: &Book) -> Result<Option<CoverImage>> {
+        let mut failure = None;
+        for url in cover_candidates(book) {
+            match fetch_image(&self.client, &url).await {
+                Ok(Some(cover)) => return Ok(Some(cover)),
+                Ok(None) => {}
+                Err(e) => {
+                    warn!("Failed to fetch cover {}: {}", url, e);
+                    failure = Some(e);
+                }
+            }
+        }
+        match failure {
+            Some(e) => Err(e),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_candidates_upgrade_the_thumbnail_and_fall_back_to_open_library() {
+        let book: Book = serde_json::from_value(serde_json::json!({
+            "id": "9780441013593",
+            "title": "Dune",
+            "categories": ["Science Fiction"],
+            "isbn": "0-441-01359-7",
+            "thumbnail": "http://books.google.com/books/content?id=B1hSG45JCX4C&zoom=1"
+        }))
+        .unwrap();
+
+        assert_eq!(
+            cover_candidates(&book),
+            vec![
+                "https://books.google.com/books/content?id=B1hSG45JCX4C&zoom=1",
+                "https://covers.openlibrary.org/b/isbn/9780441013593-L.jpg?default=false",
+            ]
+        );
+        assert_eq!(secure_url("ftp://example.com/cover.jpg"), None);
+        assert_eq!(secure_url("not a url"), None);
+        assert_eq!(open_library_cover("123"), None);
+    }
+
+    #[test]
+    fn test_covers_are_only_scaled_down() {
+        let mut png = Cursor::new(Vec::new());
+        DynamicImage::new_rgba8(40, 60)
+            .write_to(&mut png, ImageFormat::Png)
+            .unwrap();
+        let cover = CoverImage {
+            content_type: "image/png".to_string(),
+            bytes: Bytes::from(png.into_inner()),
+        };
+
+        let unchanged = scale_cover(cover.clone(), 100).unwrap();
+        assert_eq!(unchanged.content_type, "image/png");
+
+        let scaled = scale_cover(cover, 20).unwrap();
+        assert_eq!(scaled.content_type, "image/jpeg");
+        let scaled = image::load_from_memory(&scaled.bytes).unwrap();
+        assert_eq!((scaled.width(), scaled.height()), (20, 30));
+    }
+
+    #[test]
+    fn test_covers_beyond_the_decoding_limits_are_refused() {
+        let mut png = Cursor::new(Vec::new());
+        DynamicImage::new_luma8(MAX_COVER_DIMENSION + 1, 1)
+            .write_to(&mut png, ImageFormat::Png)
+            .unwrap();
+        let cover = CoverImage {
+            content_type: "image/png".to_string(),
+            bytes: Bytes::from(png.into_inner()),
+        };
+
+        assert!(matches!(
+            scale_cover(cover, 64),
+            Err(ApiError::ExternalServiceError(_))
+        ));
+    }
+
+    #[test]
+    fn test_widths_snap_down_to_the_cached_sizes() {
+        assert_eq!(snap_width(MIN_COVER_WIDTH), 16);
+        assert_eq!(snap_width(200), 128);
+        assert_eq!(snap_width(256), 256);
+        assert_eq!(snap_width(1000), 512);
+        assert_eq!(snap_width(MAX_COVER_WIDTH), 1024);
+    }
+}
@@ -0,0 +1,136 @@
+//! Deadlines shared by every upstream call a request makes
+//!
+//! The embedder and Pinecone each retry failed calls with their own attempts
+//! and backoff, and a cold embedder first probes its model. Left to themselves
+//! these stack up, so one request could wait minutes on slow upstreams. A
+//! request runs under a deadline instead: each call's timeout is cut to the
+//! time left, and a retry is only made while its backoff and a useful attempt
+//! still fit. Work outside a request, such as the startup prewarm and the
+//! ingest jobs, keeps its own retry settings.
+
+use crate::error::ApiError;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+/// Shortest time worth giving an attempt; retries that would leave less are skipped
+pub const MIN_ATTEMPT_TIME: Duration = Duration::from_millis(500);
+
+tokio::task_local! {
+    static DEADLINE: Instant;
+}
+
+/// Run `future` with the upstream calls it makes bounded by `budget`
+///
+/// Inside another deadline, the earlier of the two holds. Only calls made on
+/// the same task are bounded; work spawned onto other tasks isn't.
+pub async fn within<F: Future>(budget: Duration, future: F) -> F::Output {
+    let Some(expires_at) = Instant::now().checked_add(budget) else {
+        return future.await;
+    };
+    let expires_at = DEADLINE
+        .try_with(|outer| expires_at.min(*outer))
+        .unwrap_or(expires_at);
+    DEADLINE.scope(expires_at, future).await
+}
+
+/// Run `future` with the upstream calls it makes bounded by `share` of the time
+/// left, keeping the rest for what runs after it, such as a fallback
+pub async fn within_share<F: Future>(share: f32, future: F) -> F::Output {
+    match remaining() {
+        Some(left) => within(left.mul_f32(share), future).await,
+        None => future.await,
+    }
+}
+
+/// Time left before the current request's deadline; None outside a request
+pub fn remaining() -> Option<Duration> {
+    DEADLINE
+        .try_with(|expires_at| expires_at.saturating_duration_since(Instant::now()))
+        .ok()
+}
+
+/// `timeout` cut to the time left before the deadline
+pub fn bounded(timeout: Duration) -> Duration {
+    remaining().map_or(timeout, |left| timeout.min(left))
+}
+
+/// Whether a retry after waiting `backoff` still leaves time for an attempt
+pub fn allows_retry(backoff: Duration) -> bool {
+    remaining().is_none_or(|left| left >= backoff + MIN_ATTEMPT_TIME)
+}
+
+fn passed(upstream: &str) -> ApiError {
+    ApiError::service_unavailable(
+        format!("Request deadline passed while calling {}", upstream),
+        None,
+    )
+}
+
+/// An error once the deadline has passed, so no call to `upstream` starts after it
+pub fn check(upstream: &str) -> Result<(), ApiError> {
+    match remaining() {
+        Some(left) if left.is_zero() => Err(passed(upstream)),
+        _ => Ok(()),
+    }
+}
+
+/// Run `call` to `upstream`, giving up on it when the deadline passes
+pub async fn run<T, F>(upstream: &str, call: F) -> Result<T, ApiError>
+where
+    F: Future<Output = Result<T, ApiError>>,
+{
+    match remaining() {
+        None => call.await,
+        Some(left) if left.is_zero() => Err(passed(upstream)),
+        Some(left) => tokio::time::timeout(left, call)
+            .await
+            .unwrap_or_else(|_| Err(passed(upstream))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_calls_are_bounded_by_the_earliest_deadline() {
+        // Outside a request nothing is cut
+        assert_eq!(remaining(), None);
+        assert!(run("pinecone", async { Ok(()) }).await.is_ok());
+        assert_eq!(bounded(Duration::from_secs(30)), Duration::from_secs(30));
+        assert!(allows_retry(Duration::from_secs(60)));
+
+        within(Duration::from_secs(10), async {
+            assert!(bounded(Duration::from_secs(30)) <= Duration::from_secs(10));
+            assert_eq!(bounded(Duration::from_secs(1)), Duration::from_secs(1));
+            assert!(allows_retry(Duration::from_secs(1)));
+            assert!(!allows_retry(Duration::from_secs(10)));
+            assert!(check("pinecone").is_ok());
+
+            // A nested deadline can shorten the budget but not extend it
+            within(Duration::from_secs(60), async {
+                assert!(remaining().unwrap() <= Duration::from_secs(10));
+            })
+            .await;
+            within_share(0.5, async {
+                assert!(remaining().unwrap() <= Duration::from_secs(5));
+            })
+            .await;
+            assert!(remaining().unwrap() > Duration::from_secs(5));
+            within(Duration::from_millis(50), async {
+                let slow = run("pinecone", async {
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                    Ok(())
+                });
+                assert!(matches!(
+                    slow.await,
+                    Err(ApiError::ServiceUnavailable { .. })
+                ));
+                assert!(!allows_retry(Duration::ZERO));
+                assert!(check("pinecone").is_err());
+            })
+            .await;
+        })
+        .await;
+    }
+}
@@ -0,0 +1,152 @@
+//! Weekly digest of personalized picks, rendered by an external mailer
+//!
+//! A digest is built from the books on a user's shelves and the books they rated
+//! highly: their embeddings make up a taste profile, and the closest indexed books
+//! the user hasn't shelved, rated or been sent before become the picks. Each pick
+//! is explained by the seed book it is most similar to. Sent digests are kept as
+//! snapshots, and their books are remembered so later digests don't repeat them.
+
+use crate::models::Book;
+use crate::services::personalization::{cosine_similarity, MIN_PROFILE_RATING};
+use crate::services::supabase::ShelfStatus;
+use chrono::{DateTime, Datelike, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use utoipa::ToSchema;
+
+/// Picks in a digest unless the caller asks for another number
+pub const DEFAULT_DIGEST_SIZE: usize = 5;
+
+/// Most picks in one digest
+pub const MAX_DIGEST_SIZE: usize = 20;
+
+/// Snapshot kind digests are stored under
+pub const DIGEST_SNAPSHOT_KIND: &str = "weekly_digest";
+
+/// A book from the user's shelves or ratings that the picks are based on
+#[derive(Debug, Clone)]
+pub struct DigestSeed {
+    pub book_id: String,
+    pub title: Option<String>,
+    pub shelf: Option<ShelfStatus>,
+    pub rating: Option<u8>,
+}
+
+impl DigestSeed {
+    /// Rating-equivalent weight in the taste profile; shelved books count as liked
+    pub fn profile_rating(&self) -> u8 {
+        self.rating.unwrap_or(MIN_PROFILE_RATING)
+    }
+
+    /// Why a book similar to this one was picked
+    pub fn explanation(&self) -> String {
+        let title = self.title.as_deref().unwrap_or("a book on your shelves");
+        match (self.rating, self.shelf) {
+            (Some(rating), _) if rating >= MIN_PROFILE_RATING => {
+                format!("Because you rated {} {} stars", title, rating)
+            }
+            (_, Some(ShelfStatus::Reading)) => format!("Because you're reading {}", title),
+            (_, Some(ShelfStatus::WantToRead)) => {
+                format!("Because {} is on your want-to-read shelf", title)
+            }
+            _ => format!("Because you read {}", title),
+        }
+    }
+}
+
+/// One recommended book in a digest
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct DigestPick {
+    pub book: Book,
+    /// Human-readable reason for the pick
+    #[schema(example = "Because you rated The Hobbit 5 stars")]
+    pub explanation: String,
+    /// Id of the seed book the pick is most similar to
+    #[schema(example = "9780547928227")]
+    pub because_of: Option<String>,
+}
+
+/// A digest payload, ready to render
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Digest {
+    #[schema(example = "3f1c2a9e-8d4b-4c47-9a51-0f6a7b2d1e55")]
+    pub user_id: String,
+    /// When the digest was generated, in RFC3339 format
+    #[schema(example = "2024-01-15T10:30:00Z")]
+    pub generated_at: String,
+    /// Shelved and highly rated books the picks are based on
+    #[schema(example = 12)]
+    pub seed_count: usize,
+    pub picks: Vec<DigestPick>,
+}
+
+/// The liked seed most similar to a candidate, if any seed has an embedding
+pub fn closest_seed<'a>(
+    candidate: &[f32],
+    seeds: &'a [DigestSeed],
+    vectors: &HashMap<String, Vec<f32>>,
+) -> Option<&'a DigestSeed> {
+    seeds
+        .iter()
+        .filter(|seed| seed.profile_rating() >= MIN_PROFILE_RATING)
+        .filter_map(|seed| {
+            let vector = vectors.get(&seed.book_id)?;
+            Some((seed, cosine_similarity(candidate, vector)))
+        })
+        .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|(seed, _)| seed)
+}
+
+/// Whether a digest generated at `generated_at` (RFC3339) belongs to the ISO week of `now`
+pub fn same_week(generated_at: &str, now: DateTime<Utc>) -> bool {
+    DateTime::parse_from_rfc3339(generated_at)
+        .is_ok_and(|generated_at| generated_at.with_timezone(&Utc).iso_week() == now.iso_week())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seed(book_id: &str, shelf: Option<ShelfStatus>, rating: Option<u8>) -> DigestSeed {
+        DigestSeed {
+            book_id: book_id.to_string(),
+            title: Some(book_id.to_uppercase()),
+            shelf,
+            rating,
+        }
+    }
+
+    #[test]
+    fn test_picks_are_explained_by_the_closest_liked_seed() {
+        let seeds = vec![
+            seed("dune", Some(ShelfStatus::Read), None),
+            seed("emma", None, Some(5)),
+            seed("ulysses", Some(ShelfStatus::Read), Some(1)),
+        ];
+        let vectors = HashMap::from([
+            ("dune".to_string(), vec![1.0, 0.0]),
+            ("emma".to_string(), vec![0.0, 1.0]),
+            ("ulysses".to_string(), vec![0.1, 1.0]),
+        ]);
+
+        let closest = closest_seed(&[0.2, 1.0], &seeds, &vectors).unwrap();
+        assert_eq!(closest.book_id, "emma");
+        assert_eq!(closest.explanation(), "Because you rated EMMA 5 stars");
+
+        let closest = closest_seed(&[1.0, 0.1], &seeds, &vectors).unwrap();
+        assert_eq!(closest.explanation(), "Because you read DUNE");
+        assert_eq!(
+            seed("it", Some(ShelfStatus::WantToRead), None).explanation(),
+            "Because IT is on your want-to-read shelf"
+        );
+    }
+
+    #[test]
+    fn test_digests_of_the_same_iso_week_match() {
+        let now = "2026-10-18T20:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        assert!(same_week("2026-10-12T08:00:00Z", now));
+        assert!(!same_week("2026-10-11T23:59:00Z", now));
+        assert!(!same_week("2025-10-14T08:00:00Z", now));
+        assert!(!same_week("not a date", now));
+    }
+}
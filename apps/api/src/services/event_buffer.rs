@@ -0,0 +1,148 @@
+//! In-memory buffer for analytics events
+//!
+//! `POST /api/events` only appends to the buffer, so the frontend never waits on
+//! the database. A background task flushes it to Supabase on an interval, and a
+//! flush starts early once a full batch is waiting. Events are dropped, with a
+//! warning, when the buffer is full because the database can't keep up.
+
+use crate::error::recover_lock;
+use crate::services::supabase::{AnalyticsEvent, SupabaseClient};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// Default interval between background flushes
+pub const DEFAULT_EVENTS_FLUSH_INTERVAL_SECS: u64 = 10;
+
+/// Events buffered before a flush starts without waiting for the interval
+const FLUSH_BATCH_SIZE: usize = 500;
+
+/// Most events held in memory while the database is unavailable
+const MAX_BUFFERED_EVENTS: usize = 20_000;
+
+#[derive(Clone)]
+pub struct EventBuffer {
+    events: Arc<Mutex<Vec<AnalyticsEvent>>>,
+    /// Held while flushing so only one flush writes at a time
+    flushing: Arc<tokio::sync::Mutex<()>>,
+    supabase: SupabaseClient,
+}
+
+impl EventBuffer {
+    pub fn new(supabase: SupabaseClient) -> Self {
+        Self {
+            events: Arc::new(Mutex::new(Vec::new())),
+            flushing: Arc::new(tokio::sync::Mutex::new(())),
+            supabase,
+        }
+    }
+
+    /// Buffer events, returning how many were accepted before the buffer filled up
+    pub fn push(&self, events: Vec<AnalyticsEvent>) -> usize {
+        let (accepted, batch_ready) = {
+            let mut buffer = recover_lock(self.events.lock(), "event buffer");
+            let room = MAX_BUFFERED_EVENTS.saturating_sub(buffer.len());
+            let accepted = events.len().min(room);
+            buffer.extend(events.into_iter().take(accepted));
+            (accepted, buffer.len() >= FLUSH_BATCH_SIZE)
+        };
+
+        if batch_ready {
+            let buffer = self.clone();
+            tokio::spawn(async move { buffer.flush().await });
+        }
+        accepted
+    }
+
+    /// Number of events waiting to be written
+    pub fn len(&self) -> usize {
+        recover_lock(self.events.lock(), "event buffer").len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Write buffered events to Supabase, putting them back if the write fails
+    pub async fn flush(&self) {
+        let _flushing = self.flushing.lock().await;
+        loop {
+            let batch: Vec<AnalyticsEvent> = {
+                let mut buffer = recover_lock(self.events.lock(), "event buffer");
+                let size = buffer.len().min(FLUSH_BATCH_SIZE);
+                buffer.drain(..size).collect()
+            };
+            if batch.is_empty() {
+                return;
+            }
+
+            match self.supabase.insert_events(&batch).await {
+                Ok(()) => debug!("Flushed {} analytics events", batch.len()),
+                Err(e) => {
+                    warn!(
+                        "Failed to flush {} analytics events: {}. Will retry",
+                        batch.len(),
+                        e
+                    );
+                    let mut buffer = recover_lock(self.events.lock(), "event buffer");
+                    let room = MAX_BUFFERED_EVENTS.saturating_sub(buffer.len());
+                    if room < batch.len() {
+                        warn!(
+                            "Event buffer is full, dropping {} analytics events",
+                            batch.len() - room
+                        );
+                    }
+                    buffer.splice(0..0, batch.into_iter().take(room));
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Flush on an interval until the process exits
+    pub async fn run(self, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            self.flush().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::supabase::EventType;
+
+    #[actix_web::test]
+    async fn test_flush_writes_buffered_events() {
+        let supabase = SupabaseClient::in_memory();
+        let buffer = EventBuffer::new(supabase.clone());
+        let event = |book_id: &str| AnalyticsEvent {
+            event_type: EventType::Click,
+            book_id: book_id.to_string(),
+            session_id: None,
+            query: None,
+            position: Some(0),
+            occurred_at: chrono::Utc::now().to_rfc3339(),
+        };
+
+        assert_eq!(
+            buffer.push(vec![event("dune"), event("dune"), event("emma")]),
+            3
+        );
+        assert_eq!(buffer.len(), 3);
+
+        buffer.flush().await;
+        assert!(buffer.is_empty());
+        let counts = supabase
+            .event_counts(
+                EventType::Click,
+                chrono::Utc::now() - chrono::Duration::hours(1),
+            )
+            .await
+            .unwrap();
+        assert_eq!(counts["dune"], 2);
+        assert_eq!(counts["emma"], 1);
+    }
+}
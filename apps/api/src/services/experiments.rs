@@ -0,0 +1,291 @@
+//! A/B experiments for ranking changes
+//!
+//! Experiments are defined in a YAML file. Each one splits traffic between
+//! weighted variants, and each variant can override ranking parameters:
+//!
+//! ```yaml
+//! keyword-boost:
+//!   enabled: true
+//!   variants:
+//!     - name: control
+//!       weight: 50
+//!     - name: strong-keywords
+//!       weight: 50
+//!       params:
+//!         keyword_boost_cap: 3.0
+//! ```
+//!
+//! Requests are bucketed by user id, or by session id when there is none, with
+//! a stable hash, so an id stays in the same variant across requests and
+//! restarts. Requests without either id get the default ranking and aren't
+//! counted toward any variant. When several experiments override the same
+//! parameter, the experiment whose name sorts first wins.
+
+use crate::error::{ApiError, Result};
+use crate::models::ExperimentAssignment;
+use crate::services::recommendation::RankingParams;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Ranking parameters a variant can override; unset fields keep the default
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RankingOverrides {
+    pub rerank: Option<bool>,
+    pub keyword_boost_cap: Option<f32>,
+    pub rating_blend_weight: Option<f32>,
+    pub history_blend_weight: Option<f32>,
+    pub embedding_model: Option<String>,
+}
+
+impl RankingOverrides {
+    fn apply(&self, params: &mut RankingParams) {
+        if let Some(rerank) = self.rerank {
+            params.rerank = rerank;
+        }
+        if let Some(cap) = self.keyword_boost_cap {
+            params.keyword_boost_cap = cap;
+        }
+        if let Some(weight) = self.rating_blend_weight {
+            params.rating_blend_weight = weight;
+        }
+        if let Some(weight) = self.history_blend_weight {
+            params.history_blend_weight = weight;
+        }
+        if let Some(model) = &self.embedding_model {
+            params.embedding_model = Some(model.clone());
+        }
+    }
+
+    fn validate(&self) -> std::result::Result<(), String> {
+        for (name, value) in [
+            ("keyword_boost_cap", self.keyword_boost_cap),
+            ("rating_blend_weight", self.rating_blend_weight),
+            ("history_blend_weight", self.history_blend_weight),
+        ] {
+            if value.is_some_and(|v| !v.is_finite() || v < 0.0) {
+                return Err(format!("{} must be a non-negative number", name));
+            }
+        }
+        if self
+            .embedding_model
+            .as_ref()
+            .is_some_and(|model| model.trim().is_empty())
+        {
+            return Err("embedding_model cannot be empty".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Variant {
+    pub name: String,
+    /// Share of traffic relative to the other variants' weights
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    #[serde(default)]
+    pub params: RankingOverrides,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Experiment {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    pub variants: Vec<Variant>,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// The enabled experiments, ordered by name
+#[derive(Debug, Clone, Default)]
+pub struct Experiments {
+    experiments: BTreeMap<String, Experiment>,
+}
+
+impl Experiments {
+    /// Load experiment definitions from a YAML file
+    pub fn load(path: &Path) -> Result<Self> {
+        let yaml = std::fs::read_to_string(path).map_err(|e| {
+            ApiError::InternalError(format!(
+                "Failed to read experiments {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+        Self::from_yaml(&yaml)
+    }
+
+    pub fn from_yaml(yaml: &str) -> Result<Self> {
+        let experiments: BTreeMap<String, Experiment> =
+            serde_yaml::from_str::<Option<BTreeMap<String, Experiment>>>(yaml)
+                .map_err(|e| ApiError::InvalidInput(format!("Invalid experiments: {}", e)))?
+                .unwrap_or_default();
+
+        for (name, experiment) in &experiments {
+            let invalid = |reason: String| {
+                ApiError::InvalidInput(format!("Invalid experiment '{}': {}", name, reason))
+            };
+            if experiment.variants.is_empty() {
+                return Err(invalid("no variants".to_string()));
+            }
+            if experiment
+                .variants
+                .iter()
+                .map(|v| v.weight as u64)
+                .sum::<u64>()
+                == 0
+            {
+                return Err(invalid("variant weights add up to 0".to_string()));
+            }
+            for (i, variant) in experiment.variants.iter().enumerate() {
+                if variant.name.trim().is_empty() {
+                    return Err(invalid("variant without a name".to_string()));
+                }
+                if experiment.variants[..i]
+                    .iter()
+                    .any(|other| other.name == variant.name)
+                {
+                    return Err(invalid(format!("duplicate variant '{}'", variant.name)));
+                }
+                variant
+                    .params
+                    .validate()
+                    .map_err(|reason| invalid(format!("variant '{}': {}", variant.name, reason)))?;
+            }
+        }
+
+        Ok(Self {
+            experiments: experiments
+                .into_iter()
+                .filter(|(_, experiment)| experiment.enabled)
+                .collect(),
+        })
+    }
+
+    /// Number of enabled experiments
+    pub fn len(&self) -> usize {
+        self.experiments.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.experiments.is_empty()
+    }
+
+    /// Variant of every enabled experiment for this user or session id, and the
+    /// ranking parameters those variants add up to
+    pub fn assign(&self, unit_id: &str) -> (Vec<ExperimentAssignment>, RankingParams) {
+        let variants: Vec<(&String, &Variant)> = self
+            .experiments
+            .iter()
+            .map(|(name, experiment)| (name, experiment.variant_for(unit_id, name)))
+            .collect();
+
+        // Later experiments go first so the one whose name sorts first has the last word
+        let mut params = RankingParams::default();
+        for (_, variant) in variants.iter().rev() {
+            variant.params.apply(&mut params);
+        }
+
+        let assignments = variants
+            .into_iter()
+            .map(|(name, variant)| ExperimentAssignment {
+                experiment: name.clone(),
+                variant: variant.name.clone(),
+            })
+            .collect();
+        (assignments, params)
+    }
+}
+
+impl Experiment {
+    /// Pick a variant by where the id's hash falls among the cumulative weights
+    fn variant_for(&self, unit_id: &str, name: &str) -> &Variant {
+        let total: u64 = self.variants.iter().map(|v| v.weight as u64).sum();
+        let mut bucket = stable_hash(&format!("{}:{}", name, unit_id)) % total;
+        for variant in &self.variants {
+            if bucket < variant.weight as u64 {
+                return variant;
+            }
+            bucket -= variant.weight as u64;
+        }
+        unreachable!("bucket is below the total weight")
+    }
+}
+
+/// 64-bit FNV-1a, which unlike the std hasher is the same in every process
+pub(crate) fn stable_hash(value: &str) -> u64 {
+    value.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXPERIMENTS: &str = r#"
+keyword-boost:
+  variants:
+    - name: control
+      weight: 1
+    - name: strong-keywords
+      weight: 3
+      params:
+        keyword_boost_cap: 3.0
+        rerank: true
+no-rerank:
+  variants:
+    - name: treatment
+      params:
+        rerank: false
+        rating_blend_weight: 0.8
+retired:
+  enabled: false
+  variants:
+    - name: control
+"#;
+
+    #[test]
+    fn test_assignment_is_stable_and_follows_weights() {
+        let experiments = Experiments::from_yaml(EXPERIMENTS).unwrap();
+        assert_eq!(experiments.len(), 2);
+
+        let (first, params) = experiments.assign("user-1");
+        assert_eq!(first, experiments.assign("user-1").0);
+        assert_eq!(first[1].variant, "treatment");
+        assert_eq!(params.rating_blend_weight, 0.8);
+        // keyword-boost sorts first, so its variant decides rerank when it sets it
+        if first[0].variant == "strong-keywords" {
+            assert!(params.rerank);
+            assert_eq!(params.keyword_boost_cap, 3.0);
+        } else {
+            assert!(!params.rerank);
+            assert_eq!(
+                params.keyword_boost_cap,
+                RankingParams::default().keyword_boost_cap
+            );
+        }
+
+        let strong = (0..1000)
+            .filter(|i| experiments.assign(&format!("user-{}", i)).0[0].variant != "control")
+            .count();
+        assert!((650..850).contains(&strong), "{} of 1000", strong);
+
+        assert!(Experiments::from_yaml("broken:\n  variants: []\n").is_err());
+        assert!(Experiments::from_yaml(
+            "typo:\n  variants:\n    - name: a\n      params:\n        rerankk: false\n"
+        )
+        .is_err());
+        assert!(Experiments::from_yaml("").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,608 @@
+//! One-sentence explanations of why a book was recommended
+//!
+//! The template backend writes the sentence from the relevance indicators
+//! ranking attached to the book. An LLM backend (OpenAI chat completions or
+//! Hugging Face text generation) can write them instead for the top few
+//! results, personalized with the books a signed-in reader rated highly. Its
+//! sentences are cached per backend, query, reader and book for a day so a
+//! popular query costs a handful of generations, and a failed or slow
+//! generation falls back to the template sentence. Failures are cached for a
+//! few minutes so an outage doesn't cost a timeout per book, and no request
+//! makes more than [`MAX_GENERATIONS`] calls. Both write in the request's
+//! [`Locale`].
+
+use crate::cache::TtlCache;
+use crate::error::{ApiError, Result};
+use crate::models::Book;
+use crate::services::deadline;
+use crate::services::i18n::Locale;
+use futures::future::{join_all, BoxFuture};
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// Results at the top of a list that get an explanation
+pub const EXPLAINED_RESULTS: usize = 5;
+
+/// Most books one explanations request takes
+pub const MAX_EXPLAINED_BOOKS: usize = 10;
+
+/// Most calls to an LLM backend one request makes; other books get the template
+pub const MAX_GENERATIONS: usize = EXPLAINED_RESULTS;
+
+/// Highly rated books of a reader an explanation may mention
+pub const MAX_READER_TITLES: usize = 3;
+
+const EXPLANATION_CACHE_SIZE: usize = 5000;
+const EXPLANATION_CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Time a failed generation is remembered, so it isn't retried for every request
+const FAILED_GENERATION_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Time an LLM backend gets for a sentence before the template stands in
+const GENERATION_TIMEOUT: Duration = Duration::from_secs(8);
+
+/// Tokens an LLM backend may generate for a sentence
+const MAX_NEW_TOKENS: u32 = 60;
+
+/// Characters of a description included in the prompt
+const PROMPT_DESCRIPTION_CHARS: usize = 500;
+
+/// Longest generated explanation kept
+const MAX_EXPLANATION_CHARS: usize = 280;
+
+const OPENAI_URL: &str = "https://api.openai.com/v1/chat/completions";
+const DEFAULT_OPENAI_MODEL: &str = "gpt-4o-mini";
+const HUGGINGFACE_BASE_URL: &str = "https://router.huggingface.co/hf-inference";
+const DEFAULT_HUGGINGFACE_MODEL: &str = "mistralai/Mistral-7B-Instruct-v0.3";
+
+/// The signed-in reader explanations are written for
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Reader {
+    /// Titles of the books the reader rated highest, at most [`MAX_READER_TITLES`]
+    pub liked_titles: Vec<String>,
+}
+
+impl Reader {
+    fn cache_key(&self) -> String {
+        self.liked_titles.join("|").to_lowercase()
+    }
+}
+
+/// Writes the explanation of one book for a query
+pub trait ExplanationBackend: Send + Sync {
+    /// Name of the backend and model, logged and part of the cache key
+    fn name(&self) -> &str;
+    fn explain<'a>(
+        &'a self,
+        query: &'a str,
+        locale: Locale,
+        reader: Option<&'a Reader>,
+        book: &'a Book,
+    ) -> BoxFuture<'a, Result<String>>;
+}
+
+/// Explanations built from a book's relevance indicators
+pub struct TemplateBackend;
+
+impl ExplanationBackend for TemplateBackend {
+    fn name(&self) -> &str {
+        "template"
+    }
+
+    fn explain<'a>(
+        &'a self,
+        query: &'a str,
+        locale: Locale,
+        _reader: Option<&'a Reader>,
+        book: &'a Book,
+    ) -> BoxFuture<'a, Result<String>> {
+        Box::pin(async move { Ok(template_explanation(query, locale, book)) })
+    }
+}
+
+/// Whether the template sentence for `book` is the generic one, naming nothing
+/// the book and query share because ranking gave it no indicators
+pub fn is_generic_template(book: &Book) -> bool {
+    book.relevance_indicators.is_empty()
+}
+
+/// The template sentence for `book`, from the indicators ranking gave it
+pub fn template_explanation(query: &str, locale: Locale, book: &Book) -> String {
+    let mut clauses = Vec::new();
+    let mut themes = Vec::new();
+    for indicator in &book.relevance_indicators {
+        if let Some(author) = indicator.strip_prefix("Author: ") {
+            clauses.push(locale.by_author(author));
+        } else if let Some(place) = indicator.strip_prefix("Set in ") {
+            clauses.push(locale.set_in(place));
+        } else {
+            let theme = locale.theme(indicator);
+            // German capitalizes nouns, which the translations already do
+            themes.push(if locale == Locale::De {
+                theme
+            } else {
+                theme.to_lowercase()
+            });
+        }
+    }
+    if !themes.is_empty() {
+        clauses.push(locale.has_themes(&locale.join_and(&themes)));
+    }
+
+    if clauses.is_empty() {
+        locale.close_match(query.trim())
+    } else {
+        locale.because(&locale.join_and(&clauses))
+    }
+}
+
+/// Where an LLM backend sends its prompts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LlmProvider {
+    /// OpenAI chat completions
+    OpenAi,
+    /// Hugging Face text generation
+    HuggingFace,
+}
+
+/// Explanations written by a text generation model
+pub struct LlmBackend {
+    provider: LlmProvider,
+    client: Client,
+    api_key: String,
+    model: String,
+    name: String,
+}
+
+impl LlmBackend {
+    /// Backend for `provider`, with its default model unless `model` is set
+    pub fn new(provider: LlmProvider, api_key: String, model: Option<String>) -> Result<Self> {
+        let model = model.unwrap_or_else(|| {
+            match provider {
+                LlmProvider::OpenAi => DEFAULT_OPENAI_MODEL,
+                LlmProvider::HuggingFace => DEFAULT_HUGGINGFACE_MODEL,
+            }
+            .to_string()
+        });
+        let client = Client::builder()
+            .timeout(GENERATION_TIMEOUT)
+            .build()
+            .map_err(|e| ApiError::InternalError(format!("Failed to create HTTP client: {}", e)))?;
+        let name = match provider {
+            LlmProvider::OpenAi => format!("openai:{}", model),
+            LlmProvider::HuggingFace => format!("huggingface:{}", model),
+        };
+        Ok(Self {
+            provider,
+            client,
+            api_key,
+            model,
+            name,
+        })
+    }
+
+    /// The model's text for `prompt`, at most `max_tokens` long
+    pub async fn complete(&self, prompt: String, max_tokens: u32) -> Result<String> {
+        let (url, body) = match self.provider {
+            LlmProvider::OpenAi => (
+                OPENAI_URL.to_string(),
+                json!({
+                    "model": self.model,
+                    "messages": [{ "role": "user", "content": prompt }],
+                    "max_tokens": max_tokens,
+                    "temperature": 0.3,
+                }),
+            ),
+            LlmProvider::HuggingFace => (
+                format!("{}/models/{}", HUGGINGFACE_BASE_URL, self.model),
+                json!({
+                    "inputs": prompt,
+                    "parameters": {
+                        "max_new_tokens": max_tokens,
+                        "temperature": 0.3,
+                        "return_full_text": false,
+                    },
+                }),
+            ),
+        };
+
+        let response = self
+            .client
+            .post(&url)
+            .bearer_auth(&self.api_key)
+            .timeout(deadline::bounded(GENERATION_TIMEOUT))
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| {
+                ApiError::ExternalServiceError(format!("{} request failed: {}", self.name, e))
+            })?;
+        if !response.status().is_success() {
+            return Err(ApiError::ExternalServiceError(format!(
+                "{} returned {}",
+                self.name,
+                response.status()
+            )));
+        }
+        let body: Value = response.json().await.map_err(|e| {
+            ApiError::ExternalServiceError(format!("{} returned invalid JSON: {}", self.name, e))
+        })?;
+
+        let text = match self.provider {
+            LlmProvider::OpenAi => body["choices"][0]["message"]["content"].as_str(),
+            LlmProvider::HuggingFace => body[0]["generated_text"].as_str(),
+        };
+        text.map(str::to_string).ok_or_else(|| {
+            ApiError::ExternalServiceError(format!("{} returned no text", self.name))
+        })
+    }
+
+    async fn generate(&self, prompt: String) -> Result<String> {
+        let text = self.complete(prompt, MAX_NEW_TOKENS).await?;
+        first_sentence(&text).ok_or_else(|| {
+            ApiError::ExternalServiceError(format!("{} returned no explanation", self.name))
+        })
+    }
+}
+
+impl ExplanationBackend for LlmBackend {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn explain<'a>(
+        &'a self,
+        query: &'a str,
+        locale: Locale,
+        reader: Option<&'a Reader>,
+        book: &'a Book,
+    ) -> BoxFuture<'a, Result<String>> {
+        Box::pin(self.generate(prompt(query, locale, reader, book)))
+    }
+}
+
+/// Prompt asking for one sentence in `locale` on why `book` fits `query`, and
+/// `reader`'s taste when they have highly rated books
+fn prompt(query: &str, locale: Locale, reader: Option<&Reader>, book: &Book) -> String {
+    let mut details = vec![format!(
+        "Title: {}",
+        book.title.as_deref().unwrap_or("Untitled")
+    )];
+    if let Some(author) = &book.author {
+        details.push(format!("Author: {}", author));
+    }
+    let genres = if book.genres.is_empty() {
+        &book.categories
+    } else {
+        &book.genres
+    };
+    if !genres.is_empty() {
+        details.push(format!("Genres: {}", genres.join(", ")));
+    }
+    if !book.moods.is_empty() {
+        details.push(format!("Moods: {}", book.moods.join(", ")));
+    }
+    if !book.relevance_indicators.is_empty() {
+        details.push(format!(
+            "Matched the search on: {}",
+            book.relevance_indicators.join(", ")
+        ));
+    }
+    if let Some(description) = &book.description {
+        let description: String = description.chars().take(PROMPT_DESCRIPTION_CHARS).collect();
+        details.push(format!("Description: {}", description));
+    }
+
+    let taste = match reader {
+        Some(reader) if !reader.liked_titles.is_empty() => format!(
+            " They loved {}; if this book shares something with one of those, say what.",
+            reader
+                .liked_titles
+                .iter()
+                .map(|title| format!("\"{}\"", title))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        _ => String::new(),
+    };
+
+    format!(
+        "A reader searched a book catalog for \"{}\".{} In one sentence of at most 30 words, \
+         addressed to the reader, say in {} why this book fits their search. Don't repeat the title.\n\n{}\n\nSentence:",
+        query.trim(),
+        taste,
+        locale.language_name(),
+        details.join("\n")
+    )
+}
+
+/// The first sentence of generated text, without surrounding quotes
+fn first_sentence(text: &str) -> Option<String> {
+    let text = text.trim().trim_matches('"').trim();
+    let end = text
+        .char_indices()
+        .find(|&(i, c)| {
+            matches!(c, '.' | '!' | '?')
+                && text[i + c.len_utf8()..]
+                    .chars()
+                    .next()
+                    .is_none_or(char::is_whitespace)
+        })
+        .map(|(i, c)| i + c.len_utf8())
+        .unwrap_or(text.len());
+    let sentence: String = text[..end].chars().take(MAX_EXPLANATION_CHARS).collect();
+    (!sentence.is_empty()).then_some(sentence)
+}
+
+/// Adds explanations to the top results, from an LLM backend when one is configured
+#[derive(Clone)]
+pub struct Explainer {
+    backend: Option<Arc<dyn ExplanationBackend>>,
+    /// Generated sentences, and `None` for generations that recently failed
+    cache: TtlCache<String, Option<String>>,
+}
+
+impl Default for Explainer {
+    fn default() -> Self {
+        Self {
+            backend: None,
+            cache: TtlCache::new(
+                "explanations",
+                EXPLANATION_CACHE_SIZE,
+                EXPLANATION_CACHE_TTL,
+            ),
+        }
+    }
+}
+
+impl Explainer {
+    /// Write explanations with `backend`, falling back to the template
+    pub fn with_backend(mut self, backend: Arc<dyn ExplanationBackend>) -> Self {
+        self.backend = Some(backend);
+        self
+    }
+
+    /// This explainer without its backend, writing every explanation from the
+    /// template; the cache is shared
+    pub fn templates_only(&self) -> Self {
+        Self {
+            backend: None,
+            cache: self.cache.clone(),
+        }
+    }
+
+    /// Whether explanations come from a backend rather than the template
+    pub fn has_backend(&self) -> bool {
+        self.backend.is_some()
+    }
+
+    /// Name of the backend explanations come from
+    pub fn backend_name(&self) -> &str {
+        self.backend
+            .as_ref()
+            .map_or("template", |backend| backend.name())
+    }
+
+    /// Set the explanation of the first [`EXPLAINED_RESULTS`] books for `query`, in `locale`
+    ///
+    /// Returns how many of the explanations are generic.
+    pub async fn explain_top(
+        &self,
+        query: &str,
+        locale: Locale,
+        reader: Option<&Reader>,
+        books: &mut [Arc<Book>],
+    ) -> usize {
+        let count = books.len().min(EXPLAINED_RESULTS);
+        self.explain(query, locale, reader, &mut books[..count])
+            .await
+    }
+
+    /// Set the explanation of each of `books` for `query`, in `locale`
+    ///
+    /// Only the first [`MAX_GENERATIONS`] books without a cached sentence are
+    /// sent to the backend. Returns how many of the explanations are generic:
+    /// template sentences for books ranking gave no indicators.
+    pub async fn explain(
+        &self,
+        query: &str,
+        locale: Locale,
+        reader: Option<&Reader>,
+        books: &mut [Arc<Book>],
+    ) -> usize {
+        let explanations: Vec<(String, bool)> = match &self.backend {
+            Some(backend) => {
+                let backend = backend.as_ref();
+                let mut generations = 0;
+                join_all(books.iter().map(|book| {
+                    let key = cache_key(backend, query, locale, reader, book);
+                    let cached = self.cache.get(&key);
+                    let generate = cached.is_none() && generations < MAX_GENERATIONS;
+                    generations += usize::from(generate);
+                    async move {
+                        match cached {
+                            Some(Some(explanation)) => (explanation, false),
+                            _ if !generate => (template_explanation(query, locale, book), true),
+                            _ => {
+                                self.generate(backend, query, locale, reader, book, key)
+                                    .await
+                            }
+                        }
+                    }
+                }))
+                .await
+            }
+            None => books
+                .iter()
+                .map(|book| (template_explanation(query, locale, book), true))
+                .collect(),
+        };
+        let mut generic = 0;
+        for (book, (explanation, from_template)) in books.iter_mut().zip(explanations) {
+            if from_template && is_generic_template(book) {
+                generic += 1;
+            }
+            Arc::make_mut(book).explanation = Some(explanation);
+        }
+        generic
+    }
+
+    /// The backend's explanation of `book`, cached under `key`, or the
+    /// template's if it fails, and whether it's the template's
+    async fn generate(
+        &self,
+        backend: &dyn ExplanationBackend,
+        query: &str,
+        locale: Locale,
+        reader: Option<&Reader>,
+        book: &Book,
+        key: String,
+    ) -> (String, bool) {
+        let template = || (template_explanation(query, locale, book), true);
+        // A failure is only remembered when the backend had its full time, not
+        // when the request's deadline cut it short
+        let timeout = deadline::bounded(GENERATION_TIMEOUT);
+        let remember_failure = || {
+            if timeout == GENERATION_TIMEOUT {
+                self.cache
+                    .insert_with_ttl(key.clone(), None, FAILED_GENERATION_TTL);
+            }
+        };
+        match tokio::time::timeout(timeout, backend.explain(query, locale, reader, book)).await {
+            Ok(Ok(explanation)) => {
+                debug!("Generated explanation with {}", backend.name());
+                self.cache.insert(key.clone(), Some(explanation.clone()));
+                (explanation, false)
+            }
+            Ok(Err(e)) => {
+                warn!("Explanation backend failed, using the template: {}", e);
+                remember_failure();
+                template()
+            }
+            Err(_) => {
+                warn!(
+                    "Explanation backend {} timed out, using the template",
+                    backend.name()
+                );
+                remember_failure();
+                template()
+            }
+        }
+    }
+}
+
+/// Cache key of `backend`'s explanation of `book` for `query`, in `locale`, for `reader`
+fn cache_key(
+    backend: &dyn ExplanationBackend,
+    query: &str,
+    locale: Locale,
+    reader: Option<&Reader>,
+    book: &Book,
+) -> String {
+    format!(
+        "{}|{:?}|{}|{}|{}",
+        backend.name(),
+        locale,
+        query.trim().to_lowercase(),
+        reader.map(Reader::cache_key).unwrap_or_default(),
+        book.id.as_deref().unwrap_or_default()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_template_and_generated_sentences() {
+        let book: Book = serde_json::from_value(json!({
+            "id": "1",
+            "categories": [],
+            "relevance_indicators": ["Author: Ursula K. Le Guin", "Dragons", "Magic"],
+        }))
+        .unwrap();
+        assert_eq!(
+            template_explanation("le guin dragons", Locale::En, &book),
+            "Recommended because it is by Ursula K. Le Guin and has dragons and magic."
+        );
+        assert_eq!(
+            template_explanation("le guin dragons", Locale::Es, &book),
+            "Recomendado porque es de Ursula K. Le Guin y tiene dragones y magia."
+        );
+
+        assert_eq!(
+            first_sentence(" \"A quiet, wise fantasy about power. It also has dragons.\"\n"),
+            Some("A quiet, wise fantasy about power.".to_string())
+        );
+        assert_eq!(first_sentence("  "), None);
+    }
+
+    /// Fails every call, counting them
+    #[derive(Default)]
+    struct FailingBackend {
+        calls: std::sync::atomic::AtomicUsize,
+    }
+
+    impl ExplanationBackend for FailingBackend {
+        fn name(&self) -> &str {
+            "failing"
+        }
+
+        fn explain<'a>(
+            &'a self,
+            _query: &'a str,
+            _locale: Locale,
+            _reader: Option<&'a Reader>,
+            _book: &'a Book,
+        ) -> BoxFuture<'a, Result<String>> {
+            self.calls
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            Box::pin(async { Err(ApiError::ExternalServiceError("down".to_string())) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generations_are_capped_and_failures_cached() {
+        let backend = Arc::new(FailingBackend::default());
+        let explainer = Explainer::default().with_backend(backend.clone());
+        let mut books: Vec<Arc<Book>> = (0..MAX_GENERATIONS + 3)
+            .map(|i| {
+                Arc::new(
+                    serde_json::from_value(
+                        json!({ "id": format!("capped-{}", i), "categories": [] }),
+                    )
+                    .unwrap(),
+                )
+            })
+            .collect();
+
+        explainer
+            .explain("cozy mysteries", Locale::En, None, &mut books)
+            .await;
+        let calls = || backend.calls.load(std::sync::atomic::Ordering::Relaxed);
+        assert_eq!(calls(), MAX_GENERATIONS);
+        assert!(books.iter().all(|book| book.explanation.is_some()));
+
+        // The failed books aren't retried; the rest get their turn
+        explainer
+            .explain("cozy mysteries", Locale::En, None, &mut books)
+            .await;
+        assert_eq!(calls(), MAX_GENERATIONS + 3);
+    }
+
+    #[test]
+    fn test_prompts_mention_the_readers_favourites() {
+        let book: Book =
+            serde_json::from_value(json!({ "id": "1", "title": "Piranesi", "categories": [] }))
+                .unwrap();
+        let reader = Reader {
+            liked_titles: vec!["Jonathan Strange & Mr Norrell".to_string()],
+        };
+        assert!(prompt("strange houses", Locale::En, Some(&reader), &book)
+            .contains("They loved \"Jonathan Strange & Mr Norrell\""));
+        assert!(!prompt("strange houses", Locale::En, None, &book).contains("They loved"));
+    }
+}
@@ -0,0 +1,146 @@
+//! "Surprise me": controlled randomness in a result list
+//!
+//! With exploration above 0, part of a query's results is swapped for books
+//! sampled from further down the ranking, so someone repeating a query doesn't
+//! get the identical list every time. Only well-rated books are sampled, and
+//! books whose genres the kept results don't cover yet are favoured. The top
+//! few results never move, and exploration 0 leaves the ranking untouched.
+
+use crate::models::Book;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// Share of the results exploration 1.0 swaps for sampled books
+const MAX_SWAPPED_SHARE: f32 = 0.5;
+
+/// Results at the top that exploration never replaces or moves
+const STABLE_RESULTS: usize = 3;
+
+/// Lowest rating a book may have to be sampled
+const MIN_SAMPLED_RATING: f32 = 3.5;
+
+/// Extra sampling weight of books with a genre the kept results don't have
+const NEW_GENRE_WEIGHT: f32 = 1.0;
+
+/// How many times top_k candidates exploration 1.0 samples from
+const MAX_POOL_FACTOR: f32 = 3.0;
+
+/// Candidates to retrieve so that `top_k` results can be explored at `exploration`
+pub fn exploration_pool_size(top_k: usize, exploration: f32) -> usize {
+    let factor = 1.0 + (MAX_POOL_FACTOR - 1.0) * exploration.clamp(0.0, 1.0);
+    (top_k as f32 * factor).ceil() as usize
+}
+
+/// The first `top_k` of `ranked`, with a share set by `exploration` (0 to 1)
+/// replaced by books sampled from the rest
+pub fn explore(
+    mut ranked: Vec<Arc<Book>>,
+    top_k: usize,
+    exploration: f32,
+    rng: &mut fastrand::Rng,
+) -> Vec<Arc<Book>> {
+    let exploration = exploration.clamp(0.0, 1.0);
+    let swapped = ((top_k as f32 * exploration * MAX_SWAPPED_SHARE).round() as usize)
+        .min(top_k.saturating_sub(STABLE_RESULTS));
+    if swapped == 0 || ranked.len() <= top_k {
+        ranked.truncate(top_k);
+        return ranked;
+    }
+
+    let rest = ranked.split_off(top_k - swapped);
+    let mut results = ranked;
+    let mut genres: HashSet<&str> = HashSet::new();
+    for book in &results {
+        genres.extend(book.genres.iter().map(String::as_str));
+    }
+
+    let mut pool: Vec<(Arc<Book>, f32)> = rest
+        .iter()
+        .filter(|book| book.rating >= MIN_SAMPLED_RATING)
+        .map(|book| {
+            let new_genre = book
+                .genres
+                .iter()
+                .any(|genre| !genres.contains(genre.as_str()));
+            let weight = book.rating / 5.0 + if new_genre { NEW_GENRE_WEIGHT } else { 0.0 };
+            (book.clone(), weight)
+        })
+        .collect();
+
+    let mut sampled: Vec<Arc<Book>> = Vec::new();
+    while sampled.len() < swapped && !pool.is_empty() {
+        let total: f32 = pool.iter().map(|(_, weight)| weight).sum();
+        let mut target = rng.f32() * total;
+        let index = pool
+            .iter()
+            .position(|(_, weight)| {
+                target -= weight;
+                target <= 0.0
+            })
+            .unwrap_or(pool.len() - 1);
+        sampled.push(pool.swap_remove(index).0);
+    }
+    // Too few well-rated books further down: fill up in ranking order
+    for book in &rest {
+        if sampled.len() >= swapped {
+            break;
+        }
+        if !sampled.iter().any(|picked| Arc::ptr_eq(picked, book)) {
+            sampled.push(book.clone());
+        }
+    }
+
+    // Mix the sampled books in below the stable top instead of piling them at the end
+    for book in sampled {
+        let position = rng.usize(STABLE_RESULTS.min(results.len())..=results.len());
+        results.insert(position, book);
+    }
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn books(count: usize) -> Vec<Arc<Book>> {
+        (0..count)
+            .map(|i| {
+                Arc::new(
+                    serde_json::from_value(serde_json::json!({
+                        "id": i.to_string(),
+                        "categories": [],
+                        "genres": [if i < 10 { "fantasy" } else { "mystery" }],
+                        "rating": if i % 2 == 0 { 4.5 } else { 2.0 },
+                    }))
+                    .unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    fn ids(results: &[Arc<Book>]) -> Vec<usize> {
+        results
+            .iter()
+            .map(|book| book.id.as_deref().unwrap().parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_exploration_swaps_well_rated_books_from_lower_down() {
+        let mut rng = fastrand::Rng::with_seed(7);
+        assert_eq!(
+            ids(&explore(books(30), 10, 0.0, &mut rng)),
+            (0..10).collect::<Vec<_>>()
+        );
+
+        let explored = ids(&explore(books(30), 10, 1.0, &mut rng));
+        assert_eq!(explored.len(), 10);
+        assert_eq!(explored[..3], [0, 1, 2]);
+        let sampled: Vec<usize> = explored.iter().copied().filter(|&id| id >= 5).collect();
+        assert_eq!(sampled.len(), 5);
+        assert!(sampled.iter().all(|id| id % 2 == 0));
+
+        assert_eq!(exploration_pool_size(10, 0.0), 10);
+        assert_eq!(exploration_pool_size(10, 1.0), 30);
+    }
+}
@@ -0,0 +1,428 @@
+//! Feature flags for rolling out risky features gradually
+//!
+//! Each flag has a rule in the `[feature_flags]` table:
+//!
+//! ```toml
+//! [feature_flags.fused_ranking]
+//! enabled = true
+//! rollout_percent = 10
+//! tenants = ["acme"]
+//! users = ["reader-42"]
+//! ```
+//!
+//! A disabled flag is off for everyone. An enabled one is on for the listed
+//! tenants and users, and for `rollout_percent` of the rest, bucketed by user
+//! (or session) id, else by tenant, with the stable hash experiments use, so an
+//! id that has a feature keeps it as the rollout grows. Requests with neither
+//! id only get a feature at 100%.
+//!
+//! With `remote_url` set, a JSON object of flag rules keyed by flag name is
+//! fetched every `refresh_seconds` and updates the rules of the flags it lists,
+//! so flags can be flipped without a redeploy. A remote rule only changes the
+//! fields it sets, so `{"enabled": true}` keeps the flag's rollout. A failed
+//! fetch keeps the last rules.
+
+use crate::error::{recover_lock, ApiError, Result};
+use crate::services::experiments::stable_hash;
+use crate::services::recommendation::RankingParams;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+/// Time a fetch of the remote flag rules gets
+const REMOTE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A feature that can be switched per tenant and user
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Flag {
+    /// Re-rank retrieved books by intent, rating and keyword matches
+    Rerank,
+    /// Have a language model write explanations when one is configured
+    LlmExplanations,
+    /// Find books adjacent to a user's rated ones through the graph
+    GraphBlending,
+    /// The new ranker, fusing retrieval and intent order
+    FusedRanking,
+}
+
+impl Flag {
+    pub const ALL: [Flag; 4] = [
+        Flag::Rerank,
+        Flag::LlmExplanations,
+        Flag::GraphBlending,
+        Flag::FusedRanking,
+    ];
+
+    /// Name of the flag in the `[feature_flags]` table, env variables and remote rules
+    pub fn name(self) -> &'static str {
+        match self {
+            Flag::Rerank => "rerank",
+            Flag::LlmExplanations => "llm_explanations",
+            Flag::GraphBlending => "graph_blending",
+            Flag::FusedRanking => "fused_ranking",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|flag| flag.name() == name)
+    }
+}
+
+/// Who a flag is on for
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(default)]
+pub struct FlagRule {
+    /// Off for everyone when false
+    #[schema(example = true)]
+    pub enabled: bool,
+    /// Share of user ids, from 0 to 100, the flag is on for
+    #[schema(example = 10)]
+    pub rollout_percent: u8,
+    /// Tenants the flag is always on for
+    pub tenants: Vec<String>,
+    /// User or session ids the flag is always on for
+    pub users: Vec<String>,
+}
+
+impl FlagRule {
+    fn everyone() -> Self {
+        Self {
+            enabled: true,
+            rollout_percent: 100,
+            tenants: Vec::new(),
+            users: Vec::new(),
+        }
+    }
+
+    fn nobody() -> Self {
+        Self {
+            enabled: false,
+            rollout_percent: 0,
+            ..Self::everyone()
+        }
+    }
+
+    /// Whether `flag`, which this is the rule of, is on for a request from `context`
+    fn allows(&self, flag: Flag, context: &FlagContext) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        if context
+            .tenant
+            .is_some_and(|tenant| self.tenants.iter().any(|t| t == tenant))
+            || context
+                .user
+                .is_some_and(|user| self.users.iter().any(|u| u == user))
+        {
+            return true;
+        }
+        if self.rollout_percent >= 100 {
+            return true;
+        }
+        match context.user.or(context.tenant) {
+            Some(unit_id) => {
+                stable_hash(&format!("{}:{}", flag.name(), unit_id)) % 100
+                    < self.rollout_percent as u64
+            }
+            None => false,
+        }
+    }
+}
+
+impl Default for FlagRule {
+    fn default() -> Self {
+        Self::everyone()
+    }
+}
+
+/// A flag rule from the remote provider, which may leave fields out
+#[derive(Debug, Default, Deserialize)]
+struct RemoteFlagRule {
+    enabled: Option<bool>,
+    rollout_percent: Option<u8>,
+    tenants: Option<Vec<String>>,
+    users: Option<Vec<String>>,
+}
+
+impl RemoteFlagRule {
+    /// Set the fields of `rule` this sets, keeping the rest
+    fn merge_into(self, rule: &mut FlagRule) {
+        if let Some(enabled) = self.enabled {
+            rule.enabled = enabled;
+        }
+        if let Some(rollout_percent) = self.rollout_percent {
+            rule.rollout_percent = rollout_percent;
+        }
+        if let Some(tenants) = self.tenants {
+            rule.tenants = tenants;
+        }
+        if let Some(users) = self.users {
+            rule.users = users;
+        }
+    }
+}
+
+/// Flag rules and the remote provider, the `[feature_flags]` table
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct FeatureFlagsConfig {
+    pub rerank: FlagRule,
+    pub llm_explanations: FlagRule,
+    pub graph_blending: FlagRule,
+    /// Off by default while it's rolled out
+    pub fused_ranking: FlagRule,
+    /// URL of a JSON object of flag rules by name that override these
+    pub remote_url: Option<String>,
+    /// Seconds between fetches of the remote rules
+    pub refresh_seconds: u64,
+}
+
+impl FeatureFlagsConfig {
+    pub fn rule(&self, flag: Flag) -> &FlagRule {
+        match flag {
+            Flag::Rerank => &self.rerank,
+            Flag::LlmExplanations => &self.llm_explanations,
+            Flag::GraphBlending => &self.graph_blending,
+            Flag::FusedRanking => &self.fused_ranking,
+        }
+    }
+
+    pub fn rule_mut(&mut self, flag: Flag) -> &mut FlagRule {
+        match flag {
+            Flag::Rerank => &mut self.rerank,
+            Flag::LlmExplanations => &mut self.llm_explanations,
+            Flag::GraphBlending => &mut self.graph_blending,
+            Flag::FusedRanking => &mut self.fused_ranking,
+        }
+    }
+}
+
+impl Default for FeatureFlagsConfig {
+    fn default() -> Self {
+        Self {
+            rerank: FlagRule::everyone(),
+            llm_explanations: FlagRule::everyone(),
+            graph_blending: FlagRule::everyone(),
+            fused_ranking: FlagRule::nobody(),
+            remote_url: None,
+            refresh_seconds: 60,
+        }
+    }
+}
+
+/// Whom a request comes from, for evaluating flags
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FlagContext<'a> {
+    /// Tenant of the request's X-Api-Key
+    pub tenant: Option<&'a str>,
+    /// User id, or session id when there is none
+    pub user: Option<&'a str>,
+}
+
+/// The flags evaluated for one request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+pub struct FlagSet {
+    pub rerank: bool,
+    pub llm_explanations: bool,
+    pub graph_blending: bool,
+    pub fused_ranking: bool,
+}
+
+impl FlagSet {
+    pub fn is_enabled(&self, flag: Flag) -> bool {
+        match flag {
+            Flag::Rerank => self.rerank,
+            Flag::LlmExplanations => self.llm_explanations,
+            Flag::GraphBlending => self.graph_blending,
+            Flag::FusedRanking => self.fused_ranking,
+        }
+    }
+
+    /// Switch off the ranking features these flags turn off, and on the new ranker
+    pub fn apply(&self, params: &mut RankingParams) {
+        params.rerank &= self.rerank;
+        params.graph_blending &= self.graph_blending;
+        params.fused_ranking = self.fused_ranking;
+    }
+}
+
+/// The current flag rules, refreshed from the remote provider when one is configured
+#[derive(Clone)]
+pub struct FeatureFlags {
+    rules: Arc<RwLock<FeatureFlagsConfig>>,
+    client: reqwest::Client,
+}
+
+impl Default for FeatureFlags {
+    fn default() -> Self {
+        Self::new(FeatureFlagsConfig::default())
+    }
+}
+
+impl FeatureFlags {
+    pub fn new(config: FeatureFlagsConfig) -> Self {
+        Self {
+            rules: Arc::new(RwLock::new(config)),
+            client: reqwest::Client::builder()
+                .timeout(REMOTE_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+        }
+    }
+
+    /// The flags for a request from `context`
+    pub fn evaluate(&self, context: &FlagContext) -> FlagSet {
+        let rules = recover_lock(self.rules.read(), "feature flags");
+        let on = |flag: Flag| rules.rule(flag).allows(flag, context);
+        FlagSet {
+            rerank: on(Flag::Rerank),
+            llm_explanations: on(Flag::LlmExplanations),
+            graph_blending: on(Flag::GraphBlending),
+            fused_ranking: on(Flag::FusedRanking),
+        }
+    }
+
+    /// Current rule of every flag, by name
+    pub fn rules(&self) -> BTreeMap<String, FlagRule> {
+        let rules = recover_lock(self.rules.read(), "feature flags");
+        Flag::ALL
+            .into_iter()
+            .map(|flag| (flag.name().to_string(), rules.rule(flag).clone()))
+            .collect()
+    }
+
+    /// Update the rules of the flags in `json`, an object of rules by flag name,
+    /// with the fields each sets; returns how many were updated
+    pub fn apply_remote(&self, json: &str) -> Result<usize> {
+        let remote: BTreeMap<String, RemoteFlagRule> = serde_json::from_str(json)
+            .map_err(|e| ApiError::InvalidInput(format!("Invalid feature flags: {}", e)))?;
+        let mut rules = recover_lock(self.rules.write(), "feature flags");
+        let mut replaced = 0;
+        for (name, rule) in remote {
+            match Flag::from_name(&name) {
+                Some(flag) => {
+                    rule.merge_into(rules.rule_mut(flag));
+                    replaced += 1;
+                }
+                None => warn!("Ignoring unknown feature flag '{}'", name),
+            }
+        }
+        Ok(replaced)
+    }
+
+    /// Fetch the remote rules and apply them
+    pub async fn refresh(&self) -> Result<usize> {
+        let Some(url) = recover_lock(self.rules.read(), "feature flags")
+            .remote_url
+            .clone()
+        else {
+            return Ok(0);
+        };
+        let response = self
+            .client
+            .get(&url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| {
+                ApiError::ExternalServiceError(format!("Failed to fetch feature flags: {}", e))
+            })?;
+        let body = response.text().await.map_err(|e| {
+            ApiError::ExternalServiceError(format!("Failed to read feature flags: {}", e))
+        })?;
+        self.apply_remote(&body)
+    }
+
+    /// Refresh the remote rules every `refresh_seconds`; returns at once without a remote provider
+    pub async fn run(self) {
+        let (has_remote, refresh_seconds) = {
+            let rules = recover_lock(self.rules.read(), "feature flags");
+            (rules.remote_url.is_some(), rules.refresh_seconds)
+        };
+        if !has_remote {
+            return;
+        }
+        let mut ticker = tokio::time::interval(Duration::from_secs(refresh_seconds.max(1)));
+        loop {
+            ticker.tick().await;
+            match self.refresh().await {
+                Ok(replaced) => info!("Refreshed {} remote feature flags", replaced),
+                Err(e) => warn!("Keeping the current feature flags: {}", e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rules_switch_flags_by_tenant_user_and_rollout() {
+        let mut config = FeatureFlagsConfig::default();
+        config.rerank.enabled = false;
+        config.fused_ranking = FlagRule {
+            enabled: true,
+            rollout_percent: 50,
+            tenants: vec!["acme".to_string()],
+            users: vec!["reader-42".to_string()],
+        };
+        let flags = FeatureFlags::new(config);
+        let evaluate = |tenant: Option<&str>, user: Option<&str>| {
+            flags.evaluate(&FlagContext { tenant, user })
+        };
+
+        let anonymous = evaluate(None, None);
+        assert!(!anonymous.rerank && !anonymous.fused_ranking);
+        assert!(anonymous.llm_explanations && anonymous.graph_blending);
+        assert!(evaluate(Some("acme"), None).fused_ranking);
+        assert!(evaluate(Some("globex"), Some("reader-42")).fused_ranking);
+
+        // Half the users get the new ranker, each the same way every time
+        let enabled = (0..1000)
+            .filter(|i| evaluate(None, Some(&format!("user-{}", i))).fused_ranking)
+            .count();
+        assert!((400..600).contains(&enabled), "{} of 1000 enabled", enabled);
+        assert_eq!(
+            evaluate(None, Some("user-7")),
+            evaluate(Some("globex"), Some("user-7"))
+        );
+    }
+
+    #[test]
+    fn test_remote_rules_replace_the_flags_they_list() {
+        let flags = FeatureFlags::default();
+        let replaced = flags
+            .apply_remote(
+                r#"{"fused_ranking": {"enabled": true, "rollout_percent": 100}, "unknown": {}}"#,
+            )
+            .unwrap();
+        assert_eq!(replaced, 1);
+
+        let set = flags.evaluate(&FlagContext::default());
+        assert!(set.fused_ranking && set.rerank);
+        assert!(flags.apply_remote("[]").is_err());
+        assert!(flags.evaluate(&FlagContext::default()).fused_ranking);
+    }
+
+    #[test]
+    fn test_partial_remote_rules_keep_the_fields_they_leave_out() {
+        let mut config = FeatureFlagsConfig::default();
+        config.rerank.rollout_percent = 10;
+        config.rerank.users = vec!["reader-42".to_string()];
+        let flags = FeatureFlags::new(config);
+        flags
+            .apply_remote(r#"{"rerank": {"enabled": true}, "fused_ranking": {"enabled": true}}"#)
+            .unwrap();
+
+        let rules = flags.rules();
+        assert_eq!(rules["rerank"].rollout_percent, 10);
+        assert_eq!(rules["rerank"].users, vec!["reader-42".to_string()]);
+        // A default-off flag switched on without a rollout is only on for its listed ids
+        assert_eq!(rules["fused_ranking"].rollout_percent, 0);
+        assert!(!flags.evaluate(&FlagContext::default()).fused_ranking);
+    }
+}
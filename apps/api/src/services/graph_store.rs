@@ -0,0 +1,78 @@
+//! The book graph the recommendation service keeps in line with the index
+//!
+//! [`Neo4jClient`] is the graph in production. The trait lets tests, and users
+//! of the crate, swap in another one, such as the in-memory graph of the
+//! `test_support` module.
+
+use crate::error::Result;
+use crate::models::Book;
+use crate::services::neo4j::{GraphRelationshipResponse, Neo4jClient};
+use futures::future::BoxFuture;
+
+/// Book nodes and the relationships between them
+///
+/// Relationship lookups leave out hidden books.
+pub trait GraphStore: Send + Sync {
+    fn add_book<'a>(&'a self, book: &'a Book) -> BoxFuture<'a, Result<()>>;
+
+    /// Update a book's node; false when the graph has none
+    fn update_book<'a>(&'a self, book: &'a Book) -> BoxFuture<'a, Result<bool>>;
+
+    /// Hide or restore a book's node; false when the graph has none
+    fn set_hidden<'a>(&'a self, book_id: &'a str, hidden: bool) -> BoxFuture<'a, Result<bool>>;
+
+    fn list_book_ids(&self) -> BoxFuture<'_, Result<Vec<String>>>;
+
+    /// Delete the nodes of `ids` with their relationships; the number deleted
+    fn delete_books<'a>(&'a self, ids: &'a [String]) -> BoxFuture<'a, Result<usize>>;
+
+    /// Direct relationships from any of `book_ids`, strongest first
+    fn get_relationships_from<'a>(
+        &'a self,
+        book_ids: &'a [String],
+        limit: usize,
+    ) -> BoxFuture<'a, Result<Vec<GraphRelationshipResponse>>>;
+
+    /// Direct relationships between any two of `book_ids`, strongest first
+    fn get_relationships_among<'a>(
+        &'a self,
+        book_ids: &'a [String],
+    ) -> BoxFuture<'a, Result<Vec<GraphRelationshipResponse>>>;
+}
+
+impl GraphStore for Neo4jClient {
+    fn add_book<'a>(&'a self, book: &'a Book) -> BoxFuture<'a, Result<()>> {
+        Box::pin(Neo4jClient::add_book(self, book))
+    }
+
+    fn update_book<'a>(&'a self, book: &'a Book) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(Neo4jClient::update_book(self, book))
+    }
+
+    fn set_hidden<'a>(&'a self, book_id: &'a str, hidden: bool) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(Neo4jClient::set_hidden(self, book_id, hidden))
+    }
+
+    fn list_book_ids(&self) -> BoxFuture<'_, Result<Vec<String>>> {
+        Box::pin(Neo4jClient::list_book_ids(self))
+    }
+
+    fn delete_books<'a>(&'a self, ids: &'a [String]) -> BoxFuture<'a, Result<usize>> {
+        Box::pin(Neo4jClient::delete_books(self, ids))
+    }
+
+    fn get_relationships_from<'a>(
+        &'a self,
+        book_ids: &'a [String],
+        limit: usize,
+    ) -> BoxFuture<'a, Result<Vec<GraphRelationshipResponse>>> {
+        Box::pin(Neo4jClient::get_relationships_from(self, book_ids, limit))
+    }
+
+    fn get_relationships_among<'a>(
+        &'a self,
+        book_ids: &'a [String],
+    ) -> BoxFuture<'a, Result<Vec<GraphRelationshipResponse>>> {
+        Box::pin(Neo4jClient::get_relationships_among(self, book_ids))
+    }
+}
@@ -0,0 +1,46 @@
+//! HTTP clients for the upstream APIs
+//!
+//! Pinecone and HuggingFace clients are built here so they share one set of
+//! connection settings: HTTP/2 where the server negotiates it, with pings that
+//! keep its connections alive; pooled connections reused between bursts of
+//! traffic; TCP keepalive so idle connections aren't silently dropped by NATs;
+//! and hostnames resolved by hickory, which caches answers for their TTL rather
+//! than asking the system resolver for every new connection.
+
+use reqwest::{Client, ClientBuilder};
+use std::time::Duration;
+
+/// Longest wait for a connection to be established
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Idle connections kept per host for the next burst of requests
+pub const POOL_MAX_IDLE_PER_HOST: usize = 10;
+/// How long an idle pooled connection is kept; under the 60 second idle timeout
+/// common to cloud load balancers, so a pooled connection is never one they've closed
+pub const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(55);
+/// Interval of TCP keepalive probes on open connections
+pub const TCP_KEEPALIVE: Duration = Duration::from_secs(30);
+/// Interval of HTTP/2 pings on open connections, idle ones included
+pub const HTTP2_KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(30);
+/// How long a ping may go unanswered before its connection is closed
+pub const HTTP2_KEEP_ALIVE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Builder of an upstream client with the shared connection settings
+pub fn upstream_client_builder() -> ClientBuilder {
+    Client::builder()
+        .use_rustls_tls()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .pool_max_idle_per_host(POOL_MAX_IDLE_PER_HOST)
+        .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+        .tcp_keepalive(TCP_KEEPALIVE)
+        .tcp_nodelay(true)
+        .http2_adaptive_window(true)
+        .http2_keep_alive_interval(HTTP2_KEEP_ALIVE_INTERVAL)
+        .http2_keep_alive_timeout(HTTP2_KEEP_ALIVE_TIMEOUT)
+        .http2_keep_alive_while_idle(true)
+        .hickory_dns(true)
+}
+
+/// Upstream client whose requests time out after `timeout`
+pub fn upstream_client(timeout: Duration) -> reqwest::Result<Client> {
+    upstream_client_builder().timeout(timeout).build()
+}
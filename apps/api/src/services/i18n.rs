@@ -0,0 +1,259 @@
+//! Localized explanation templates and theme names
+//!
+//! Responses are English unless the request asks for another language with
+//! `lang` or `Accept-Language`. The explanation sentence is assembled from the
+//! phrases here, and semantic tags and explained themes that name a known
+//! genre, mood or common theme are translated; other words (titles, author
+//! names, places) are left as they are.
+
+use crate::error::ApiError;
+use serde::{Deserialize, Serialize};
+
+/// A language explanations and tags can be written in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Locale {
+    #[default]
+    En,
+    De,
+    Es,
+}
+
+/// Theme names and their German and Spanish translations
+const THEMES: &[(&str, &str, &str)] = &[
+    // Genres
+    ("fiction", "Belletristik", "ficción"),
+    ("literary", "literarisch", "literaria"),
+    ("fantasy", "Fantasy", "fantasía"),
+    ("science fiction", "Science-Fiction", "ciencia ficción"),
+    ("sci-fi", "Science-Fiction", "ciencia ficción"),
+    ("mystery", "Krimi", "misterio"),
+    ("thriller", "Thriller", "suspense"),
+    ("horror", "Horror", "terror"),
+    ("romance", "Liebesroman", "romántica"),
+    ("adventure", "Abenteuer", "aventura"),
+    (
+        "historical fiction",
+        "historischer Roman",
+        "novela histórica",
+    ),
+    ("children", "Kinderbuch", "infantil"),
+    ("young adult", "Jugendbuch", "juvenil"),
+    ("graphic novel", "Graphic Novel", "novela gráfica"),
+    ("humor", "Humor", "humor"),
+    ("poetry", "Lyrik", "poesía"),
+    ("drama", "Drama", "drama"),
+    ("biography", "Biografie", "biografía"),
+    ("history", "Geschichte", "historia"),
+    ("philosophy", "Philosophie", "filosofía"),
+    ("religion", "Religion", "religión"),
+    ("self-help", "Ratgeber", "autoayuda"),
+    ("business", "Wirtschaft", "negocios"),
+    ("science", "Wissenschaft", "ciencia"),
+    ("social science", "Sozialwissenschaft", "ciencias sociales"),
+    ("art", "Kunst", "arte"),
+    ("cooking", "Kochen", "cocina"),
+    ("health", "Gesundheit", "salud"),
+    ("travel", "Reisen", "viajes"),
+    ("sports", "Sport", "deportes"),
+    ("historical", "historisch", "histórica"),
+    ("western", "Western", "wéstern"),
+    ("satire", "Satire", "sátira"),
+    ("true crime", "True Crime", "crónica negra"),
+    ("cookbook", "Kochbuch", "libro de cocina"),
+    ("spirituality", "Spiritualität", "espiritualidad"),
+    ("politics", "Politik", "política"),
+    // Moods
+    ("cozy", "gemütlich", "acogedor"),
+    ("uplifting", "ermutigend", "inspirador"),
+    ("heartwarming", "herzerwärmend", "conmovedor"),
+    ("funny", "lustig", "divertido"),
+    ("whimsical", "verspielt", "caprichoso"),
+    ("dark", "düster", "oscuro"),
+    ("gritty", "schonungslos", "crudo"),
+    ("melancholic", "melancholisch", "melancólico"),
+    ("bittersweet", "bittersüß", "agridulce"),
+    ("suspenseful", "spannend", "intrigante"),
+    ("peaceful", "ruhig", "sereno"),
+    // Common query themes
+    ("dragons", "Drachen", "dragones"),
+    ("dragon", "Drache", "dragón"),
+    ("magic", "Magie", "magia"),
+    ("love", "Liebe", "amor"),
+    ("war", "Krieg", "guerra"),
+    ("space", "Weltraum", "espacio"),
+    ("detective", "Detektiv", "detective"),
+    ("murder", "Mord", "asesinato"),
+    ("family", "Familie", "familia"),
+    ("friendship", "Freundschaft", "amistad"),
+    ("crime", "Verbrechen", "crimen"),
+    ("ghosts", "Geister", "fantasmas"),
+    ("vampires", "Vampire", "vampiros"),
+    ("witches", "Hexen", "brujas"),
+    ("robots", "Roboter", "robots"),
+    ("time travel", "Zeitreise", "viajes en el tiempo"),
+    ("dystopian", "dystopisch", "distópico"),
+    ("survival", "Überleben", "supervivencia"),
+    ("mythology", "Mythologie", "mitología"),
+    ("coming of age", "Erwachsenwerden", "crecimiento personal"),
+];
+
+impl Locale {
+    /// Locale of a language tag such as `de` or `es-MX`, or None if unsupported
+    pub fn from_tag(tag: &str) -> Option<Self> {
+        let language = tag.trim().split(['-', '_']).next()?.to_lowercase();
+        match language.as_str() {
+            "en" => Some(Locale::En),
+            "de" => Some(Locale::De),
+            "es" => Some(Locale::Es),
+            _ => None,
+        }
+    }
+
+    /// Locale of the `lang` parameter, rejecting unsupported languages
+    pub fn from_param(lang: &str) -> Result<Self, ApiError> {
+        Self::from_tag(lang).ok_or_else(|| {
+            ApiError::InvalidInput(format!(
+                "Unsupported language '{}'. Supported: en, de, es",
+                lang.trim()
+            ))
+        })
+    }
+
+    /// The supported language an `Accept-Language` header prefers most, English if none
+    pub fn from_accept_language(header: &str) -> Self {
+        let mut best: Option<(Locale, f32)> = None;
+        for entry in header.split(',') {
+            let mut parts = entry.split(';');
+            let Some(locale) = parts.next().and_then(Self::from_tag) else {
+                continue;
+            };
+            let quality = parts
+                .find_map(|part| part.trim().strip_prefix("q="))
+                .and_then(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            if quality > 0.0 && best.is_none_or(|(_, best_quality)| quality > best_quality) {
+                best = Some((locale, quality));
+            }
+        }
+        best.map(|(locale, _)| locale).unwrap_or_default()
+    }
+
+    /// Locale of a request: its `lang` parameter, else its `Accept-Language` header, else English
+    pub fn negotiate(lang: Option<&str>, accept_language: Option<&str>) -> Result<Self, ApiError> {
+        match lang {
+            Some(lang) => Self::from_param(lang),
+            None => Ok(accept_language
+                .map(Self::from_accept_language)
+                .unwrap_or_default()),
+        }
+    }
+
+    /// A theme name in this language; names without a translation are returned as given
+    pub fn theme(&self, name: &str) -> String {
+        if *self == Locale::En {
+            return name.to_string();
+        }
+        let key = name.trim().to_lowercase();
+        THEMES
+            .iter()
+            .find(|(english, _, _)| *english == key)
+            .map(|(_, german, spanish)| match self {
+                Locale::De => german.to_string(),
+                _ => spanish.to_string(),
+            })
+            .unwrap_or_else(|| name.to_string())
+    }
+
+    /// Name of the language, as asked for in a prompt
+    pub fn language_name(&self) -> &'static str {
+        match self {
+            Locale::En => "English",
+            Locale::De => "German",
+            Locale::Es => "Spanish",
+        }
+    }
+
+    /// "a", "a and b", "a, b and c"
+    pub fn join_and(&self, items: &[String]) -> String {
+        let and = match self {
+            Locale::En => "and",
+            Locale::De => "und",
+            Locale::Es => "y",
+        };
+        match items.split_last() {
+            None => String::new(),
+            Some((last, [])) => last.clone(),
+            Some((last, rest)) => format!("{} {} {}", rest.join(", "), and, last),
+        }
+    }
+
+    /// Clause for a book by `author`
+    pub fn by_author(&self, author: &str) -> String {
+        match self {
+            Locale::En => format!("is by {}", author),
+            Locale::De => format!("von {} ist", author),
+            Locale::Es => format!("es de {}", author),
+        }
+    }
+
+    /// Clause for a book set in `place`
+    pub fn set_in(&self, place: &str) -> String {
+        match self {
+            Locale::En => format!("is set in {}", place),
+            Locale::De => format!("in {} spielt", place),
+            Locale::Es => format!("transcurre en {}", place),
+        }
+    }
+
+    /// Clause for a book with `themes`, already joined
+    pub fn has_themes(&self, themes: &str) -> String {
+        match self {
+            Locale::En => format!("has {}", themes),
+            Locale::De => format!("{} bietet", themes),
+            Locale::Es => format!("tiene {}", themes),
+        }
+    }
+
+    /// The explanation sentence from its clauses, already joined
+    pub fn because(&self, clauses: &str) -> String {
+        match self {
+            Locale::En => format!("Recommended because it {}.", clauses),
+            Locale::De => format!("Empfohlen, weil es {}.", clauses),
+            Locale::Es => format!("Recomendado porque {}.", clauses),
+        }
+    }
+
+    /// The explanation sentence of a book without indicators
+    pub fn close_match(&self, query: &str) -> String {
+        match self {
+            Locale::En => format!("Recommended as a close match for \"{}\".", query),
+            Locale::De => format!("Empfohlen als passender Treffer für „{}“.", query),
+            Locale::Es => format!("Recomendado por su cercanía a «{}».", query),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locale_negotiation_and_theme_names() {
+        assert_eq!(
+            Locale::from_accept_language("fr-FR, es;q=0.8, de;q=0.9"),
+            Locale::De
+        );
+        assert_eq!(Locale::from_accept_language("fr, it;q=0.5"), Locale::En);
+        assert_eq!(Locale::from_accept_language("de;q=0, es-MX"), Locale::Es);
+        assert!(Locale::from_param("fr").is_err());
+
+        assert_eq!(Locale::De.theme("Science Fiction"), "Science-Fiction");
+        assert_eq!(Locale::Es.theme("dragons"), "dragones");
+        assert_eq!(Locale::Es.theme("Middle-earth"), "Middle-earth");
+        assert_eq!(
+            Locale::Es.join_and(&["a".into(), "b".into(), "c".into()]),
+            "a, b y c"
+        );
+    }
+}
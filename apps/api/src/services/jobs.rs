@@ -0,0 +1,698 @@
+//! Background jobs for work that outlasts a request
+//!
+//! The startup prewarm, graph syncs and metadata enrichment run through the
+//! [`JobQueue`]: admin endpoints submit the work and answer with its job id at
+//! once. A few jobs run at a time as tokio tasks, reporting progress and
+//! checking for cancellation through their [`JobHandle`], and
+//! `GET /api/admin/jobs/{id}` shows how far they got.
+//!
+//! Jobs are recorded in the `jobs` table when a database is configured, so
+//! their outcome outlives the instance that ran them, and kept in memory
+//! otherwise. Only the instance running a job can cancel it. That instance
+//! touches its unfinished jobs every [`HEARTBEAT_INTERVAL`]; a job nobody has
+//! touched for a few intervals was cut short by a restart or crash, and is
+//! marked failed when an instance starts or someone tries to cancel it.
+
+use crate::error::{recover_lock, ApiError, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{Executor, PgPool, Row};
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::sync::{Notify, Semaphore};
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+/// Schema for the jobs table, applied on startup (safe to run repeatedly)
+const JOBS_MIGRATIONS: &[&str] = &[
+    include_str!("../../migrations/0010_jobs.sql"),
+    include_str!("../../migrations/0013_job_heartbeats.sql"),
+];
+
+/// How often an instance touches the unfinished jobs it holds
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Missed heartbeats after which an unfinished job is taken to be orphaned
+const ORPHANED_AFTER_HEARTBEATS: u32 = 4;
+
+/// Jobs running at once; later ones wait in the queue
+pub const MAX_CONCURRENT_JOBS: usize = 2;
+
+/// Jobs kept in memory; the oldest finished ones are dropped first
+const IN_MEMORY_CAPACITY: usize = 500;
+
+/// Upper bound on jobs returned by a single listing
+pub const MAX_JOB_LIST_LIMIT: i64 = 200;
+
+/// Where a job is in its life
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    /// Waiting for a free slot
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    /// Stopped on request; the result holds whatever was done by then
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            JobStatus::Queued => "queued",
+            JobStatus::Running => "running",
+            JobStatus::Succeeded => "succeeded",
+            JobStatus::Failed => "failed",
+            JobStatus::Cancelled => "cancelled",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "queued" => Some(JobStatus::Queued),
+            "running" => Some(JobStatus::Running),
+            "succeeded" => Some(JobStatus::Succeeded),
+            "failed" => Some(JobStatus::Failed),
+            "cancelled" => Some(JobStatus::Cancelled),
+            _ => None,
+        }
+    }
+
+    /// Whether the job is done, one way or another
+    pub fn is_finished(self) -> bool {
+        matches!(
+            self,
+            JobStatus::Succeeded | JobStatus::Failed | JobStatus::Cancelled
+        )
+    }
+}
+
+/// A background job and how far it got
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Job {
+    /// Sequential job id
+    #[schema(example = 7)]
+    pub id: i64,
+    /// What the job does
+    #[schema(example = "graph.sync")]
+    pub kind: String,
+    pub status: JobStatus,
+    /// Share of the work done, in percent
+    #[schema(example = 42.5)]
+    pub progress: f32,
+    /// What the job is doing, or what stopped it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = "Synced 400 of 940 books")]
+    pub message: Option<String>,
+    /// Parameters the job was submitted with
+    #[schema(example = json!({}))]
+    pub parameters: Value,
+    /// What the job produced; for a cancelled job, what it got done
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = json!({"synced": 940, "removed": 3}))]
+    pub result: Option<Value>,
+    /// Why the job failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// When the job was submitted, in RFC3339 format
+    #[schema(example = "2024-01-15T10:30:00Z")]
+    pub created_at: String,
+    /// When the job started running
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<String>,
+    /// When the job finished
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<String>,
+}
+
+/// What a running job reports progress and checks for cancellation through
+#[derive(Clone)]
+pub struct JobHandle {
+    id: i64,
+    queue: JobQueue,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl JobHandle {
+    pub fn id(&self) -> i64 {
+        self.id
+    }
+
+    /// Whether the job was asked to stop; jobs check between units of work and
+    /// return what they got done
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Report that `done` of `total` units of work are complete
+    pub async fn progress(&self, done: usize, total: usize, message: impl Into<String>) {
+        let progress = if total == 0 {
+            100.0
+        } else {
+            (done as f32 / total as f32 * 100.0).min(100.0)
+        };
+        self.queue
+            .report_progress(self.id, progress, message.into())
+            .await;
+    }
+}
+
+/// Runs background jobs and keeps track of them
+#[derive(Clone)]
+pub struct JobQueue {
+    pool: Option<PgPool>,
+    jobs: Arc<RwLock<BTreeMap<i64, Job>>>,
+    /// Cancellation flags of the jobs this instance hasn't finished
+    active: Arc<RwLock<HashMap<i64, Arc<AtomicBool>>>>,
+    slots: Arc<Semaphore>,
+    next_id: Arc<AtomicI64>,
+    /// Woken whenever one of this instance's jobs finishes
+    finished: Arc<Notify>,
+}
+
+impl JobQueue {
+    /// Queue that only keeps its jobs in memory
+    pub fn in_memory() -> Self {
+        Self {
+            pool: None,
+            jobs: Arc::new(RwLock::new(BTreeMap::new())),
+            active: Arc::new(RwLock::new(HashMap::new())),
+            slots: Arc::new(Semaphore::new(MAX_CONCURRENT_JOBS)),
+            next_id: Arc::new(AtomicI64::new(1)),
+            finished: Arc::new(Notify::new()),
+        }
+    }
+
+    /// Queue recording its jobs through an existing pool, making sure the jobs
+    /// table exists and failing the jobs no instance holds any more
+    pub async fn from_pool(pool: PgPool) -> Result<Self> {
+        for migration in JOBS_MIGRATIONS {
+            pool.execute(*migration).await?;
+        }
+        info!("Jobs table is ready");
+
+        let queue = Self {
+            pool: Some(pool),
+            ..Self::in_memory()
+        };
+        queue.fail_orphaned().await?;
+        tokio::spawn(queue.clone().heartbeat());
+        Ok(queue)
+    }
+
+    /// Mark the unfinished jobs that no instance has touched for a while as
+    /// failed; returns how many there were
+    async fn fail_orphaned(&self) -> Result<u64> {
+        let Some(pool) = &self.pool else {
+            return Ok(0);
+        };
+        let orphaned_after = (HEARTBEAT_INTERVAL * ORPHANED_AFTER_HEARTBEATS).as_secs() as f64;
+        let failed = sqlx::query(
+            "UPDATE jobs SET status = 'failed', finished_at = now(), \
+                             error = 'Interrupted: the instance running it stopped' \
+             WHERE status IN ('queued', 'running') \
+               AND COALESCE(heartbeat_at, started_at, created_at) \
+                   < now() - make_interval(secs => $1)",
+        )
+        .bind(orphaned_after)
+        .execute(pool)
+        .await?
+        .rows_affected();
+        if failed > 0 {
+            warn!("Marked {} interrupted jobs as failed", failed);
+        }
+        Ok(failed)
+    }
+
+    /// Touch this instance's unfinished jobs every [`HEARTBEAT_INTERVAL`], so
+    /// other instances don't take them for orphaned
+    async fn heartbeat(self) {
+        let Some(pool) = self.pool.clone() else {
+            return;
+        };
+        let mut ticker = tokio::time::interval(HEARTBEAT_INTERVAL);
+        loop {
+            ticker.tick().await;
+            let ids: Vec<i64> = recover_lock(self.active.read(), "active jobs")
+                .keys()
+                .copied()
+                .collect();
+            if ids.is_empty() {
+                continue;
+            }
+            let result = sqlx::query("UPDATE jobs SET heartbeat_at = now() WHERE id = ANY($1)")
+                .bind(&ids)
+                .execute(&pool)
+                .await;
+            if let Err(e) = result {
+                warn!(
+                    "Failed to record the heartbeat of {} jobs: {}",
+                    ids.len(),
+                    e
+                );
+            }
+        }
+    }
+
+    /// Whether one of this instance's jobs of the given kind hasn't finished yet
+    pub fn has_unfinished(&self, kind: &str) -> bool {
+        let active = recover_lock(self.active.read(), "active jobs");
+        recover_lock(self.jobs.read(), "jobs")
+            .values()
+            .any(|job| job.kind == kind && active.contains_key(&job.id))
+    }
+
+    /// Whether jobs are persisted to the database
+    pub fn is_persistent(&self) -> bool {
+        self.pool.is_some()
+    }
+
+    /// Queue `task` as a job of the given kind; it starts once a slot is free
+    pub async fn submit<F, Fut>(&self, kind: &str, parameters: Value, task: F) -> Result<Job>
+    where
+        F: FnOnce(JobHandle) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<Value>> + Send + 'static,
+    {
+        let job = match &self.pool {
+            Some(pool) => {
+                let row = sqlx::query(
+                    "INSERT INTO jobs (kind, parameters, heartbeat_at) VALUES ($1, $2, now()) \
+                     RETURNING id, kind, status, progress, message, parameters, result, error, \
+                               created_at, started_at, finished_at",
+                )
+                .bind(kind)
+                .bind(&parameters)
+                .fetch_one(pool)
+                .await
+                .map_err(|e| ApiError::DatabaseError(format!("Failed to record job: {}", e)))?;
+                job_from_row(&row)?
+            }
+            None => Job {
+                id: self.next_id.fetch_add(1, Ordering::Relaxed),
+                kind: kind.to_string(),
+                status: JobStatus::Queued,
+                progress: 0.0,
+                message: None,
+                parameters,
+                result: None,
+                error: None,
+                created_at: Utc::now().to_rfc3339(),
+                started_at: None,
+                finished_at: None,
+            },
+        };
+        info!("Queued job {} ({})", job.id, job.kind);
+
+        let cancelled = Arc::new(AtomicBool::new(false));
+        recover_lock(self.active.write(), "active jobs").insert(job.id, cancelled.clone());
+        {
+            let mut jobs = recover_lock(self.jobs.write(), "jobs");
+            jobs.insert(job.id, job.clone());
+            trim(&mut jobs);
+        }
+
+        let handle = JobHandle {
+            id: job.id,
+            queue: self.clone(),
+            cancelled,
+        };
+        tokio::spawn(self.clone().run(handle, task));
+        Ok(job)
+    }
+
+    async fn run<F, Fut>(self, handle: JobHandle, task: F)
+    where
+        F: FnOnce(JobHandle) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<Value>> + Send + 'static,
+    {
+        let id = handle.id;
+        // The semaphore is never closed
+        let _slot = self.slots.clone().acquire_owned().await;
+
+        let started = self
+            .update(id, |job| {
+                if job.status == JobStatus::Queued {
+                    job.status = JobStatus::Running;
+                    job.started_at = Some(Utc::now().to_rfc3339());
+                }
+            })
+            .await;
+        // Jobs cancelled while queued were finished by `cancel`
+        if started.is_none_or(|job| job.status != JobStatus::Running) {
+            self.forget(id);
+            return;
+        }
+
+        // Run the task on its own so a panic fails the job instead of leaving it running
+        let outcome = tokio::spawn(task(handle.clone())).await;
+        let cancelled = handle.is_cancelled();
+        let finished = self
+            .update(id, |job| {
+                match outcome {
+                    Ok(Ok(result)) => {
+                        job.status = if cancelled {
+                            JobStatus::Cancelled
+                        } else {
+                            job.progress = 100.0;
+                            JobStatus::Succeeded
+                        };
+                        job.result = Some(result);
+                    }
+                    Ok(Err(e)) => {
+                        job.status = if cancelled {
+                            JobStatus::Cancelled
+                        } else {
+                            JobStatus::Failed
+                        };
+                        job.error = Some(e.to_string());
+                    }
+                    Err(e) => {
+                        job.status = JobStatus::Failed;
+                        job.error = Some(format!("Job stopped unexpectedly: {}", e));
+                    }
+                }
+                job.finished_at = Some(Utc::now().to_rfc3339());
+            })
+            .await;
+        self.forget(id);
+
+        if let Some(job) = finished {
+            match &job.error {
+                Some(error) => warn!(
+                    "Job {} ({}) {}: {}",
+                    id,
+                    job.kind,
+                    job.status.as_str(),
+                    error
+                ),
+                None => info!("Job {} ({}) {}", id, job.kind, job.status.as_str()),
+            }
+        }
+    }
+
+    /// A job by id, from memory or, for other instances' jobs, the database
+    pub async fn get(&self, id: i64) -> Result<Option<Job>> {
+        if let Some(job) = recover_lock(self.jobs.read(), "jobs").get(&id) {
+            return Ok(Some(job.clone()));
+        }
+        let Some(pool) = &self.pool else {
+            return Ok(None);
+        };
+
+        let row = sqlx::query(
+            "SELECT id, kind, status, progress, message, parameters, result, error, \
+                    created_at, started_at, finished_at \
+             FROM jobs WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
+        row.as_ref().map(job_from_row).transpose()
+    }
+
+    /// Up to `limit` jobs, optionally only those with the given status, newest first
+    pub async fn list(&self, status: Option<JobStatus>, limit: i64) -> Result<Vec<Job>> {
+        let limit = limit.clamp(1, MAX_JOB_LIST_LIMIT);
+
+        let Some(pool) = &self.pool else {
+            return Ok(recover_lock(self.jobs.read(), "jobs")
+                .values()
+                .rev()
+                .filter(|job| status.is_none_or(|status| job.status == status))
+                .take(limit as usize)
+                .cloned()
+                .collect());
+        };
+
+        let rows = sqlx::query(
+            "SELECT id, kind, status, progress, message, parameters, result, error, \
+                    created_at, started_at, finished_at \
+             FROM jobs \
+             WHERE ($1::text IS NULL OR status = $1) \
+             ORDER BY id DESC \
+             LIMIT $2",
+        )
+        .bind(status.map(JobStatus::as_str))
+        .bind(limit)
+        .fetch_all(pool)
+        .await?;
+
+        rows.iter().map(job_from_row).collect()
+    }
+
+    /// Wait for one of this instance's jobs to finish
+    pub async fn wait(&self, id: i64) -> Result<Job> {
+        loop {
+            // Registered before checking, so a job finishing in between still wakes us
+            let finished = self.finished.notified();
+            tokio::pin!(finished);
+            finished.as_mut().enable();
+
+            match self.get(id).await? {
+                None => return Err(ApiError::NotFound(format!("Job {} not found", id))),
+                Some(job) if job.status.is_finished() => return Ok(job),
+                Some(_) => finished.await,
+            }
+        }
+    }
+
+    /// Ask a job to stop; a queued job is cancelled at once, a running one at
+    /// its next check
+    pub async fn cancel(&self, id: i64) -> Result<Job> {
+        let flag = recover_lock(self.active.read(), "active jobs")
+            .get(&id)
+            .cloned();
+        let Some(flag) = flag else {
+            // A job cut short elsewhere has nobody left to cancel it
+            self.fail_orphaned().await?;
+            return match self.get(id).await? {
+                None => Err(ApiError::NotFound(format!("Job {} not found", id))),
+                Some(job) if job.status.is_finished() => Err(ApiError::InvalidInput(format!(
+                    "Job {} already {}",
+                    id,
+                    job.status.as_str()
+                ))),
+                Some(_) => Err(ApiError::InvalidInput(format!(
+                    "Job {} runs on another instance",
+                    id
+                ))),
+            };
+        };
+
+        flag.store(true, Ordering::Relaxed);
+        let job = self
+            .update(id, |job| {
+                if job.status == JobStatus::Queued {
+                    job.status = JobStatus::Cancelled;
+                    job.finished_at = Some(Utc::now().to_rfc3339());
+                } else if !job.status.is_finished() {
+                    job.message = Some("Cancellation requested".to_string());
+                }
+            })
+            .await
+            .ok_or_else(|| ApiError::NotFound(format!("Job {} not found", id)))?;
+        info!("Cancellation of job {} ({}) requested", id, job.kind);
+        Ok(job)
+    }
+
+    async fn report_progress(&self, id: i64, progress: f32, message: String) {
+        let (job, moved) = {
+            let mut jobs = recover_lock(self.jobs.write(), "jobs");
+            let Some(job) = jobs.get_mut(&id) else {
+                return;
+            };
+            // Whole percents are written through, so a chatty job doesn't flood the database
+            let moved = job.progress.floor() != progress.floor();
+            job.progress = progress;
+            job.message = Some(message);
+            (job.clone(), moved)
+        };
+        if moved {
+            self.persist(&job).await;
+        }
+    }
+
+    /// Change a job in memory and write it through; None if it isn't in memory
+    async fn update(&self, id: i64, change: impl FnOnce(&mut Job)) -> Option<Job> {
+        let job = {
+            let mut jobs = recover_lock(self.jobs.write(), "jobs");
+            let job = jobs.get_mut(&id)?;
+            change(job);
+            job.clone()
+        };
+        self.persist(&job).await;
+        if job.status.is_finished() {
+            self.finished.notify_waiters();
+        }
+        Some(job)
+    }
+
+    async fn persist(&self, job: &Job) {
+        let Some(pool) = &self.pool else {
+            return;
+        };
+        let result = sqlx::query(
+            "UPDATE jobs SET status = $2, progress = $3, message = $4, result = $5, error = $6, \
+                             started_at = $7, finished_at = $8 \
+             WHERE id = $1",
+        )
+        .bind(job.id)
+        .bind(job.status.as_str())
+        .bind(job.progress)
+        .bind(&job.message)
+        .bind(&job.result)
+        .bind(&job.error)
+        .bind(parse_time(job.started_at.as_deref()))
+        .bind(parse_time(job.finished_at.as_deref()))
+        .execute(pool)
+        .await;
+        if let Err(e) = result {
+            warn!("Failed to record job {}: {}", job.id, e);
+        }
+    }
+
+    fn forget(&self, id: i64) {
+        recover_lock(self.active.write(), "active jobs").remove(&id);
+    }
+}
+
+impl Default for JobQueue {
+    fn default() -> Self {
+        Self::in_memory()
+    }
+}
+
+/// Drop the oldest finished jobs beyond the in-memory capacity
+fn trim(jobs: &mut BTreeMap<i64, Job>) {
+    while jobs.len() > IN_MEMORY_CAPACITY {
+        let Some(id) = jobs
+            .values()
+            .find(|job| job.status.is_finished())
+            .map(|job| job.id)
+        else {
+            break;
+        };
+        jobs.remove(&id);
+    }
+}
+
+fn parse_time(value: Option<&str>) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value?)
+        .ok()
+        .map(|time| time.with_timezone(&Utc))
+}
+
+fn job_from_row(row: &sqlx::postgres::PgRow) -> Result<Job> {
+    let status: String = row.try_get("status")?;
+    let time = |column: &str| -> Result<Option<String>> {
+        Ok(row
+            .try_get::<Option<DateTime<Utc>>, _>(column)?
+            .map(|time| time.to_rfc3339()))
+    };
+    Ok(Job {
+        id: row.try_get("id")?,
+        kind: row.try_get("kind")?,
+        status: JobStatus::parse(&status)
+            .ok_or_else(|| ApiError::DatabaseError(format!("Unknown job status '{}'", status)))?,
+        progress: row.try_get("progress")?,
+        message: row.try_get("message")?,
+        parameters: row.try_get("parameters")?,
+        result: row.try_get("result")?,
+        error: row.try_get("error")?,
+        created_at: row.try_get::<DateTime<Utc>, _>("created_at")?.to_rfc3339(),
+        started_at: time("started_at")?,
+        finished_at: time("finished_at")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::time::Duration;
+
+    async fn wait_until_finished(queue: &JobQueue, id: i64) -> Job {
+        for _ in 0..200 {
+            let job = queue.get(id).await.unwrap().unwrap();
+            if job.status.is_finished() {
+                return job;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("job {} didn't finish", id);
+    }
+
+    #[actix_web::test]
+    async fn test_jobs_report_progress_and_results() {
+        let queue = JobQueue::in_memory();
+        let job = queue
+            .submit("test.count", json!({ "to": 4 }), |job| async move {
+                for done in 1..=4 {
+                    job.progress(done, 4, format!("Counted to {}", done)).await;
+                }
+                Ok(json!({ "counted": 4 }))
+            })
+            .await
+            .unwrap();
+        assert_eq!(job.status, JobStatus::Queued);
+
+        let job = wait_until_finished(&queue, job.id).await;
+        assert_eq!(job.status, JobStatus::Succeeded);
+        assert_eq!(job.progress, 100.0);
+        assert_eq!(job.message.as_deref(), Some("Counted to 4"));
+        assert_eq!(job.result, Some(json!({ "counted": 4 })));
+
+        let failing = queue
+            .submit("test.fail", json!({}), |_job| async move {
+                Err(ApiError::InternalError("boom".to_string()))
+            })
+            .await
+            .unwrap();
+        let failing = queue.wait(failing.id).await.unwrap();
+        assert_eq!(failing.status, JobStatus::Failed);
+        assert_eq!(
+            failing.error.as_deref(),
+            Some("Internal server error: boom")
+        );
+
+        let listed = queue.list(Some(JobStatus::Failed), 10).await.unwrap();
+        assert_eq!(
+            listed.iter().map(|job| job.id).collect::<Vec<_>>(),
+            vec![failing.id]
+        );
+        assert!(queue.cancel(failing.id).await.is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_cancelled_jobs_stop_at_their_next_check() {
+        let queue = JobQueue::in_memory();
+        let job = queue
+            .submit("test.wait", json!({}), |job| async move {
+                let mut checks = 0;
+                while !job.is_cancelled() {
+                    checks += 1;
+                    tokio::time::sleep(Duration::from_millis(5)).await;
+                }
+                Ok(json!({ "checks": checks }))
+            })
+            .await
+            .unwrap();
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(queue.has_unfinished("test.wait"));
+        queue.cancel(job.id).await.unwrap();
+        let job = wait_until_finished(&queue, job.id).await;
+        assert_eq!(job.status, JobStatus::Cancelled);
+        assert!(!queue.has_unfinished("test.wait"));
+        assert!(job.result.is_some());
+        assert!(matches!(
+            queue.cancel(999).await,
+            Err(ApiError::NotFound(_))
+        ));
+    }
+}
@@ -0,0 +1,153 @@
+//! Turns of the uncached recommendation queries, and shedding past a queue depth
+//!
+//! At most a configured number of queries are computed at once and the rest
+//! wait their turn. Under a load spike that queue grows until everything in it
+//! times out together, so once `max_queued` queries are already waiting, new
+//! sheddable requests are answered 503 with `Retry-After` straight away. The
+//! server marks every request sheddable except those on its exempt paths, the
+//! health checks and prewarm by default; work outside a request, such as the
+//! scheduled cache warming, is never shed.
+
+use crate::error::{ApiError, Result};
+use crate::telemetry;
+use log::warn;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Default number of queries that may wait for a turn before new requests are shed
+pub const DEFAULT_MAX_QUEUED_RECOMMENDATIONS: usize = 32;
+/// Retry-After sent when a request is shed or its wait for a turn times out
+pub const RETRY_AFTER_SECS: u64 = 5;
+
+tokio::task_local! {
+    static SHEDDABLE: ();
+}
+
+/// Run `future` as a request that may be shed when the queue is too deep
+pub async fn sheddable<F: Future>(future: F) -> F::Output {
+    SHEDDABLE.scope((), future).await
+}
+
+/// Whether the current task is a sheddable request
+pub fn is_sheddable() -> bool {
+    SHEDDABLE.try_with(|_| ()).is_ok()
+}
+
+/// Counts a query as waiting until it gets a turn or gives up
+struct Waiting(Arc<AtomicUsize>);
+
+impl Drop for Waiting {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Turns of the queries computed at once, and the queries waiting for one
+#[derive(Clone)]
+pub struct ComputeQueue {
+    permits: Arc<Semaphore>,
+    waiting: Arc<AtomicUsize>,
+    max_queued: usize,
+}
+
+impl ComputeQueue {
+    /// Queue of `max_concurrent` turns shedding past `max_queued` waiting queries, or never if 0
+    pub fn new(max_concurrent: usize, max_queued: usize) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(max_concurrent)),
+            waiting: Arc::default(),
+            max_queued,
+        }
+    }
+
+    /// The same turns, shedding past `max_queued` waiting queries instead
+    pub fn with_max_queued(self, max_queued: usize) -> Self {
+        Self { max_queued, ..self }
+    }
+
+    pub fn max_queued(&self) -> usize {
+        self.max_queued
+    }
+
+    /// Queries waiting for a turn
+    pub fn waiting(&self) -> usize {
+        self.waiting.load(Ordering::Relaxed)
+    }
+
+    /// A turn, waited for up to `timeout` unless the request is shed
+    pub async fn turn(&self, timeout: Duration) -> Result<OwnedSemaphorePermit> {
+        if let Ok(permit) = self.permits.clone().try_acquire_owned() {
+            return Ok(permit);
+        }
+
+        let waiting = self.waiting.fetch_add(1, Ordering::Relaxed);
+        let _waiting = Waiting(self.waiting.clone());
+        if self.max_queued > 0 && waiting >= self.max_queued && is_sheddable() {
+            warn!(
+                "Shedding a recommendation request with {} queries already queued",
+                waiting
+            );
+            telemetry::record_shed_request();
+            return Err(ApiError::service_unavailable(
+                "Too many recommendation queries queued",
+                Some(RETRY_AFTER_SECS),
+            ));
+        }
+
+        tokio::time::timeout(timeout, self.permits.clone().acquire_owned())
+            .await
+            .map_err(|_| {
+                ApiError::service_unavailable(
+                    "Too many recommendation queries in progress",
+                    Some(RETRY_AFTER_SECS),
+                )
+            })?
+            .map_err(|_| ApiError::InternalError("Recommendation queue closed".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sheddable_requests_are_turned_away_past_the_queue_depth() {
+        let queue = ComputeQueue::new(1, 1);
+        let timeout = Duration::from_secs(5);
+        let busy = queue.turn(timeout).await.unwrap();
+
+        // One query may wait; the next sheddable one is turned away at once
+        let first = tokio::spawn({
+            let queue = queue.clone();
+            async move { sheddable(queue.turn(timeout)).await.map(drop) }
+        });
+        while queue.waiting() == 0 {
+            tokio::task::yield_now().await;
+        }
+        let shed = sheddable(queue.turn(timeout)).await.unwrap_err();
+        assert!(matches!(
+            shed,
+            ApiError::ServiceUnavailable {
+                retry_after: Some(RETRY_AFTER_SECS),
+                ..
+            }
+        ));
+        assert_eq!(queue.waiting(), 1);
+
+        // Exempt requests and work outside a request still queue
+        let exempt = tokio::spawn({
+            let queue = queue.clone();
+            async move { queue.turn(timeout).await.map(drop) }
+        });
+        while queue.waiting() < 2 {
+            tokio::task::yield_now().await;
+        }
+        drop(busy);
+        first.await.unwrap().unwrap();
+        exempt.await.unwrap().unwrap();
+        assert_eq!(queue.waiting(), 0);
+    }
+}
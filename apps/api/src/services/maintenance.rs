@@ -0,0 +1,548 @@
+//! Scheduled maintenance inside the API process
+//!
+//! A long-running instance has housekeeping to do besides serving requests:
+//! expired cache entries and idle sessions hold memory until something sweeps
+//! them, trending lists go stale, the hidden books and preloaded queries drift
+//! as other instances and readers change them, the graph misses books indexed
+//! since its last sync, and cover hosts take images down. The
+//! [`MaintenanceScheduler`] runs each of these tasks on its own interval from
+//! the `[maintenance]` table and keeps the outcome of each task's last run for
+//! `/api/health/ready`.
+
+use crate::cache;
+use crate::error::{recover_lock, ApiError, Result};
+use crate::ingest::covers::CoverResolver;
+use crate::services::{
+    book_versions::BookHistory,
+    jobs::{JobQueue, JobStatus},
+    query_preloader::QueryPreloader,
+    session_store::SessionStore,
+    RecommendationService,
+};
+use chrono::Utc;
+use futures::FutureExt;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+/// How often the scheduler checks whether a task is due
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// A recurring maintenance task
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MaintenanceTask {
+    /// Drop expired cache entries and idle sessions
+    CacheCleanup,
+    /// Recompute the cached trending lists
+    Trending,
+    /// Reload the hidden books and preloaded queries, and finish a failed startup prewarm
+    PrewarmRefresh,
+    /// Add books indexed since the last sync to the graph and drop removed ones
+    GraphSync,
+    /// Check a batch of cover thumbnails and patch working ones over broken ones
+    CoverRevalidation,
+}
+
+impl MaintenanceTask {
+    pub const ALL: [MaintenanceTask; 5] = [
+        MaintenanceTask::CacheCleanup,
+        MaintenanceTask::Trending,
+        MaintenanceTask::PrewarmRefresh,
+        MaintenanceTask::GraphSync,
+        MaintenanceTask::CoverRevalidation,
+    ];
+
+    /// Name of the task in the `[maintenance]` table, env variables and status
+    pub fn name(self) -> &'static str {
+        match self {
+            MaintenanceTask::CacheCleanup => "cache_cleanup",
+            MaintenanceTask::Trending => "trending",
+            MaintenanceTask::PrewarmRefresh => "prewarm_refresh",
+            MaintenanceTask::GraphSync => "graph_sync",
+            MaintenanceTask::CoverRevalidation => "cover_revalidation",
+        }
+    }
+}
+
+/// Whether and how often a task runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TaskSchedule {
+    pub enabled: bool,
+    /// Minutes between runs; the first run is one interval after startup. 0 disables the task
+    pub interval_minutes: u64,
+}
+
+impl TaskSchedule {
+    const fn every(interval_minutes: u64) -> Self {
+        Self {
+            enabled: true,
+            interval_minutes,
+        }
+    }
+
+    const fn disabled(interval_minutes: u64) -> Self {
+        Self {
+            enabled: false,
+            interval_minutes,
+        }
+    }
+
+    /// Time between runs, or None when the task doesn't run
+    pub fn interval(&self) -> Option<Duration> {
+        (self.enabled && self.interval_minutes > 0)
+            .then(|| Duration::from_secs(self.interval_minutes * 60))
+    }
+}
+
+/// A `[maintenance.<task>]` table; the fields it leaves out keep the task's defaults
+#[derive(Debug, Default, Deserialize)]
+struct ScheduleTable {
+    enabled: Option<bool>,
+    interval_minutes: Option<u64>,
+}
+
+impl ScheduleTable {
+    fn merge_into(self, schedule: &mut TaskSchedule) {
+        if let Some(enabled) = self.enabled {
+            schedule.enabled = enabled;
+        }
+        if let Some(interval_minutes) = self.interval_minutes {
+            schedule.interval_minutes = interval_minutes;
+        }
+    }
+}
+
+/// The `[maintenance]` table as written, merged over [`MaintenanceConfig::default`]
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct MaintenanceTable {
+    cache_cleanup: ScheduleTable,
+    trending: ScheduleTable,
+    prewarm_refresh: ScheduleTable,
+    graph_sync: ScheduleTable,
+    cover_revalidation: ScheduleTable,
+    cover_batch_size: Option<usize>,
+}
+
+impl From<MaintenanceTable> for MaintenanceConfig {
+    fn from(table: MaintenanceTable) -> Self {
+        let mut config = MaintenanceConfig::default();
+        table.cache_cleanup.merge_into(&mut config.cache_cleanup);
+        table.trending.merge_into(&mut config.trending);
+        table
+            .prewarm_refresh
+            .merge_into(&mut config.prewarm_refresh);
+        table.graph_sync.merge_into(&mut config.graph_sync);
+        table
+            .cover_revalidation
+            .merge_into(&mut config.cover_revalidation);
+        if let Some(size) = table.cover_batch_size {
+            config.cover_batch_size = size;
+        }
+        config
+    }
+}
+
+/// Schedules of the maintenance tasks, the `[maintenance]` table
+///
+/// A task's table only needs the fields it changes: `enabled = true` alone
+/// turns the graph sync on at its default interval of six hours.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(from = "MaintenanceTable")]
+pub struct MaintenanceConfig {
+    pub cache_cleanup: TaskSchedule,
+    pub trending: TaskSchedule,
+    pub prewarm_refresh: TaskSchedule,
+    /// Needs Neo4j and a serverless Pinecone index
+    pub graph_sync: TaskSchedule,
+    /// Needs a serverless Pinecone index
+    pub cover_revalidation: TaskSchedule,
+    /// Books whose covers each cover revalidation checks; later runs go on
+    /// where the last one stopped
+    pub cover_batch_size: usize,
+}
+
+impl Default for MaintenanceConfig {
+    fn default() -> Self {
+        Self {
+            cache_cleanup: TaskSchedule::every(10),
+            trending: TaskSchedule::every(15),
+            prewarm_refresh: TaskSchedule::every(60),
+            graph_sync: TaskSchedule::disabled(6 * 60),
+            cover_revalidation: TaskSchedule::disabled(24 * 60),
+            cover_batch_size: 200,
+        }
+    }
+}
+
+impl MaintenanceConfig {
+    pub fn schedule(&self, task: MaintenanceTask) -> TaskSchedule {
+        match task {
+            MaintenanceTask::CacheCleanup => self.cache_cleanup,
+            MaintenanceTask::Trending => self.trending,
+            MaintenanceTask::PrewarmRefresh => self.prewarm_refresh,
+            MaintenanceTask::GraphSync => self.graph_sync,
+            MaintenanceTask::CoverRevalidation => self.cover_revalidation,
+        }
+    }
+
+    pub fn schedule_mut(&mut self, task: MaintenanceTask) -> &mut TaskSchedule {
+        match task {
+            MaintenanceTask::CacheCleanup => &mut self.cache_cleanup,
+            MaintenanceTask::Trending => &mut self.trending,
+            MaintenanceTask::PrewarmRefresh => &mut self.prewarm_refresh,
+            MaintenanceTask::GraphSync => &mut self.graph_sync,
+            MaintenanceTask::CoverRevalidation => &mut self.cover_revalidation,
+        }
+    }
+}
+
+/// Schedule and last run of a maintenance task
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct MaintenanceTaskStatus {
+    #[schema(example = "trending")]
+    pub task: String,
+    pub enabled: bool,
+    #[schema(example = 15)]
+    pub interval_minutes: u64,
+    /// Whether the task is running right now
+    pub running: bool,
+    /// When the last run started
+    #[schema(example = "2024-01-15T10:30:00Z")]
+    pub last_run_at: Option<String>,
+    #[schema(example = 840)]
+    pub last_duration_ms: Option<u64>,
+    /// Whether the last run succeeded
+    #[schema(example = true)]
+    pub last_succeeded: Option<bool>,
+    #[schema(example = json!(null))]
+    pub last_error: Option<String>,
+    /// What the last successful run did
+    #[schema(value_type = Object, example = json!({"refreshed": 2}))]
+    pub last_result: Option<Value>,
+    /// When the task is due next
+    #[schema(example = "2024-01-15T10:45:00Z")]
+    pub next_run_at: Option<String>,
+}
+
+/// Statuses of the maintenance tasks, shared between the scheduler and `/api/health/ready`
+#[derive(Clone)]
+pub struct MaintenanceStatus {
+    tasks: Arc<RwLock<Vec<MaintenanceTaskStatus>>>,
+}
+
+impl MaintenanceStatus {
+    pub fn new(config: &MaintenanceConfig) -> Self {
+        let tasks = MaintenanceTask::ALL
+            .iter()
+            .map(|&task| {
+                let schedule = config.schedule(task);
+                MaintenanceTaskStatus {
+                    task: task.name().to_string(),
+                    enabled: schedule.interval().is_some(),
+                    interval_minutes: schedule.interval_minutes,
+                    running: false,
+                    last_run_at: None,
+                    last_duration_ms: None,
+                    last_succeeded: None,
+                    last_error: None,
+                    last_result: None,
+                    next_run_at: None,
+                }
+            })
+            .collect();
+        Self {
+            tasks: Arc::new(RwLock::new(tasks)),
+        }
+    }
+
+    /// Every task's status, in the order of [`MaintenanceTask::ALL`]
+    pub fn tasks(&self) -> Vec<MaintenanceTaskStatus> {
+        recover_lock(self.tasks.read(), "maintenance status").clone()
+    }
+
+    fn update(&self, task: MaintenanceTask, change: impl FnOnce(&mut MaintenanceTaskStatus)) {
+        let mut tasks = recover_lock(self.tasks.write(), "maintenance status");
+        if let Some(status) = tasks.iter_mut().find(|status| status.task == task.name()) {
+            change(status);
+        }
+    }
+
+    fn scheduled(&self, task: MaintenanceTask, after: Duration) {
+        let next = Utc::now() + chrono::Duration::from_std(after).unwrap_or_default();
+        self.update(task, |status| status.next_run_at = Some(next.to_rfc3339()));
+    }
+
+    /// Mark a task as started; false if its last run hasn't finished yet
+    fn start(&self, task: MaintenanceTask) -> bool {
+        let mut started = false;
+        self.update(task, |status| {
+            if !status.running {
+                status.running = true;
+                status.last_run_at = Some(Utc::now().to_rfc3339());
+                started = true;
+            }
+        });
+        started
+    }
+
+    fn finish(&self, task: MaintenanceTask, duration: Duration, outcome: &Result<Value>) {
+        self.update(task, |status| {
+            status.running = false;
+            status.last_duration_ms = Some(duration.as_millis() as u64);
+            status.last_succeeded = Some(outcome.is_ok());
+            match outcome {
+                Ok(result) => {
+                    status.last_error = None;
+                    status.last_result = Some(result.clone());
+                }
+                Err(e) => status.last_error = Some(e.to_string()),
+            }
+        });
+    }
+}
+
+/// Background task running the maintenance tasks on their schedules
+#[derive(Clone)]
+pub struct MaintenanceScheduler {
+    service: Arc<RecommendationService>,
+    config: MaintenanceConfig,
+    status: MaintenanceStatus,
+    jobs: JobQueue,
+    history: BookHistory,
+    sessions: SessionStore,
+    preloader: Option<QueryPreloader>,
+    covers: Arc<CoverResolver>,
+    /// Books the current pass of cover revalidation has yet to check
+    cover_queue: Arc<Mutex<VecDeque<String>>>,
+}
+
+impl MaintenanceScheduler {
+    pub fn new(
+        service: Arc<RecommendationService>,
+        config: MaintenanceConfig,
+        jobs: JobQueue,
+        history: BookHistory,
+        sessions: SessionStore,
+    ) -> Result<Self> {
+        Ok(Self {
+            service,
+            status: MaintenanceStatus::new(&config),
+            config,
+            jobs,
+            history,
+            sessions,
+            preloader: None,
+            covers: Arc::new(CoverResolver::new()?),
+            cover_queue: Arc::new(Mutex::new(VecDeque::new())),
+        })
+    }
+
+    /// Refresh the preloaded top queries along with the hidden books
+    pub fn with_preloader(mut self, preloader: QueryPreloader) -> Self {
+        self.preloader = Some(preloader).filter(QueryPreloader::is_enabled);
+        self
+    }
+
+    /// Statuses of the tasks, kept up to date as they run
+    pub fn status(&self) -> MaintenanceStatus {
+        self.status.clone()
+    }
+
+    /// Run the enabled tasks on schedule until the process exits
+    pub async fn run(self) {
+        let schedules: Vec<(MaintenanceTask, Duration)> = MaintenanceTask::ALL
+            .iter()
+            .filter_map(|&task| Some((task, self.config.schedule(task).interval()?)))
+            .collect();
+        if schedules.is_empty() {
+            info!("Scheduled maintenance is disabled");
+            return;
+        }
+        for (task, interval) in &schedules {
+            info!("Running {} every {:?}", task.name(), interval);
+            self.status.scheduled(*task, *interval);
+        }
+
+        // Tasks first run one interval after startup, once startup prewarming is done
+        let mut last_runs: Vec<Instant> = vec![Instant::now(); schedules.len()];
+        let mut ticker = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            ticker.tick().await;
+            for ((task, interval), last_run) in schedules.iter().zip(last_runs.iter_mut()) {
+                if last_run.elapsed() < *interval {
+                    continue;
+                }
+                *last_run = Instant::now();
+                self.status.scheduled(*task, *interval);
+
+                // Each task runs on its own, so a slow graph sync doesn't hold up the others
+                if !self.status.start(*task) {
+                    warn!("Skipping {}: its last run is still going", task.name());
+                    continue;
+                }
+                let scheduler = self.clone();
+                let task = *task;
+                tokio::spawn(async move {
+                    let started = Instant::now();
+                    // A panicking task is recorded as failed, or it would stay
+                    // "running" and every later run would be skipped
+                    let outcome = AssertUnwindSafe(scheduler.run_task(task))
+                        .catch_unwind()
+                        .await
+                        .unwrap_or_else(|_| {
+                            Err(ApiError::InternalError(format!(
+                                "Maintenance task {} panicked",
+                                task.name()
+                            )))
+                        });
+                    if let Err(e) = &outcome {
+                        warn!("Maintenance task {} failed: {}", task.name(), e);
+                    }
+                    scheduler.status.finish(task, started.elapsed(), &outcome);
+                });
+            }
+        }
+    }
+
+    async fn run_task(&self, task: MaintenanceTask) -> Result<Value> {
+        match task {
+            MaintenanceTask::CacheCleanup => Ok(json!({
+                "expired": cache::purge_expired(),
+                "sessions": self.sessions.purge_expired(),
+            })),
+            MaintenanceTask::Trending => Ok(json!({
+                "refreshed": self.service.refresh_trending().await?,
+            })),
+            MaintenanceTask::PrewarmRefresh => {
+                let first_prewarm = self.service.prewarm().await?;
+                let hidden = self.service.hidden_books().await?.len();
+                let preloaded = match &self.preloader {
+                    Some(preloader) => preloader.preload().await,
+                    None => 0,
+                };
+                Ok(json!({
+                    "first_prewarm": first_prewarm,
+                    "hidden": hidden,
+                    "preloaded": preloaded,
+                }))
+            }
+            MaintenanceTask::GraphSync => self.sync_graph().await,
+            MaintenanceTask::CoverRevalidation => {
+                let (batch, remaining) = self.next_cover_batch().await?;
+                let stats = self
+                    .service
+                    .revalidate_covers(&self.covers, &batch, &self.history)
+                    .await?;
+                Ok(json!({
+                    "stats": stats,
+                    "checked": batch.len(),
+                    "remaining": remaining,
+                }))
+            }
+        }
+    }
+
+    /// The books the next cover revalidation checks, and how many are left
+    /// after them in the current pass
+    ///
+    /// The index is listed once per pass over it rather than for every batch;
+    /// books indexed during a pass are checked in the next one.
+    async fn next_cover_batch(&self) -> Result<(Vec<String>, usize)> {
+        if recover_lock(self.cover_queue.lock(), "cover queue").is_empty() {
+            let ids = self.service.indexed_book_ids().await?;
+            *recover_lock(self.cover_queue.lock(), "cover queue") = ids.into();
+        }
+        let mut queue = recover_lock(self.cover_queue.lock(), "cover queue");
+        let size = self.config.cover_batch_size.max(1).min(queue.len());
+        let batch = queue.drain(..size).collect();
+        Ok((batch, queue.len()))
+    }
+
+    /// Run an incremental graph sync as a job, so it shows up and can be
+    /// cancelled with the admin job endpoints, and wait for it
+    async fn sync_graph(&self) -> Result<Value> {
+        let service = self.service.clone();
+        let job = self
+            .jobs
+            .submit(
+                "graph.sync",
+                json!({ "incremental": true, "scheduled": true }),
+                move |job| async move { service.sync_graph(&job, true).await },
+            )
+            .await?;
+        let job = self.jobs.wait(job.id).await?;
+        match job.status {
+            JobStatus::Succeeded => Ok(job.result.unwrap_or(Value::Null)),
+            status => Err(ApiError::InternalError(format!(
+                "Graph sync job {} {}{}",
+                job.id,
+                status.as_str(),
+                job.error.map(|e| format!(": {}", e)).unwrap_or_default()
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tasks_without_an_interval_are_disabled() {
+        let config: MaintenanceConfig = serde_json::from_value(json!({
+            "trending": { "interval_minutes": 0 },
+            "graph_sync": { "enabled": true },
+        }))
+        .unwrap();
+
+        assert_eq!(
+            config.cache_cleanup.interval(),
+            Some(Duration::from_secs(600))
+        );
+        assert_eq!(config.trending.interval(), None);
+        // A table that only enables a task keeps that task's default interval
+        assert_eq!(config.graph_sync, TaskSchedule::every(6 * 60));
+        assert_eq!(config.cover_revalidation.interval(), None);
+    }
+
+    #[test]
+    fn test_status_records_the_last_run() {
+        let status = MaintenanceStatus::new(&MaintenanceConfig::default());
+        let task = MaintenanceTask::Trending;
+
+        assert!(status.start(task));
+        assert!(!status.start(task));
+        status.finish(
+            task,
+            Duration::from_millis(12),
+            &Ok(json!({ "refreshed": 2 })),
+        );
+        assert!(status.start(task));
+        status.finish(
+            task,
+            Duration::from_millis(3),
+            &Err(ApiError::InternalError("boom".to_string())),
+        );
+
+        let trending = status
+            .tasks()
+            .into_iter()
+            .find(|status| status.task == "trending")
+            .unwrap();
+        assert!(!trending.running);
+        assert_eq!(trending.last_duration_ms, Some(3));
+        assert_eq!(trending.last_succeeded, Some(false));
+        assert_eq!(
+            trending.last_error.as_deref(),
+            Some("Internal server error: boom")
+        );
+        // The result of the last successful run is kept
+        assert_eq!(trending.last_result, Some(json!({ "refreshed": 2 })));
+    }
+}
@@ -1,15 +1,60 @@
+pub mod audit_log;
+pub mod author_profile;
+pub mod book_patch;
+pub mod book_versions;
+pub mod bootstrap;
+pub mod catalog_events;
+pub mod collaborative;
+pub mod compare;
+pub mod conversation;
+pub mod covers;
+pub mod deadline;
+pub mod digest;
+pub mod event_buffer;
+pub mod experiments;
+pub mod explanations;
+pub mod exploration;
+pub mod feature_flags;
+pub mod graph_store;
+pub mod http_client;
+pub mod i18n;
+pub mod jobs;
+pub mod load_shedding;
+pub mod maintenance;
 pub mod neo4j;
+pub mod personalization;
 pub mod pinecone;
+pub mod post_filters;
+pub mod prewarm;
 pub mod query_enhancer;
+pub mod query_expansion;
+pub mod query_parser;
+pub mod query_preloader;
+pub mod randomness;
+pub mod ranking;
 pub mod recommendation;
+pub mod recovery;
+pub mod refinements;
+pub mod retrieval;
+pub mod search_router;
 pub mod semantic_classifier;
+pub mod session_store;
+pub mod shadow;
+pub mod similar_to;
+pub mod slow_query_log;
+pub mod supabase;
+pub mod synonyms;
 pub mod templates;
+pub mod trending;
+pub mod usage;
+pub mod vector_store;
 
 // Re-export public types
 pub use pinecone::Pinecone;
 pub use query_enhancer::QueryEnhancer;
 pub use recommendation::RecommendationService;
+pub use vector_store::VectorStore;
 
-// Neo4j types are re-exported for use in the build_graph binary
+// Neo4j types are re-exported for use by the graph rebuild command
 #[cfg(feature = "graph")]
 pub use neo4j::{BookNode, BookRelationship, GraphResponse, Neo4jClient, RelationType};
@@ -6,6 +6,28 @@ use std::sync::Arc;
 use tracing::{debug, info, warn};
 use utoipa::ToSchema;
 
+/// Share of a relationship's score that comes from co-shelving (`cf_score`) rather than
+/// its content-based `weight`, for relationships that have both
+pub const CF_SCORE_WEIGHT: f64 = 0.3;
+
+/// Cypher expression blending a relationship's `weight` with its `cf_score`, if any
+fn blended_weight(rel: &str) -> String {
+    format!(
+        "CASE WHEN {rel}.cf_score IS NULL THEN coalesce({rel}.weight, 0.0) \
+         ELSE coalesce({rel}.weight, 0.0) * {} + {rel}.cf_score * {} END",
+        1.0 - CF_SCORE_WEIGHT,
+        CF_SCORE_WEIGHT
+    )
+}
+
+/// Property set on the nodes of hidden books
+const HIDDEN_PROPERTY: &str = "hidden";
+
+/// Cypher condition that the book `node` isn't hidden
+fn visible(node: &str) -> String {
+    format!("coalesce({node}.{HIDDEN_PROPERTY}, false) = false")
+}
+
 /// Relationship types between books
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub enum RelationType {
@@ -189,6 +211,60 @@ impl Neo4jClient {
         Ok(())
     }
 
+    /// Overwrite the properties of an existing book node; returns whether it exists
+    pub async fn update_book(&self, book: &Book) -> Result<bool> {
+        let node = BookNode::from(book);
+
+        let query = Query::new(
+            "MATCH (b:Book {id: $id})
+             SET b.title = $title,
+                 b.author = $author,
+                 b.categories = $categories,
+                 b.rating = $rating,
+                 b.year = $year,
+                 b.description = $description
+             RETURN count(b) as count"
+                .to_string(),
+        )
+        .param("id", node.id)
+        .param("title", node.title)
+        .param("author", node.author.unwrap_or_default())
+        .param("categories", node.categories)
+        .param("rating", node.rating as f64)
+        .param("year", node.year.unwrap_or(0) as i64)
+        .param("description", node.description.unwrap_or_default());
+
+        let mut result = self.graph.execute(query).await.map_err(|e| {
+            ApiError::ExternalServiceError(format!("Failed to update book in Neo4j: {}", e))
+        })?;
+
+        Ok(match result.next().await {
+            Ok(Some(row)) => row.get::<i64>("count").unwrap_or(0) > 0,
+            _ => false,
+        })
+    }
+
+    /// Hide a book from the graph queries, or restore it; returns whether its node exists
+    pub async fn set_hidden(&self, book_id: &str, hidden: bool) -> Result<bool> {
+        let query = Query::new(format!(
+            "MATCH (b:Book {{id: $book_id}})
+             SET b.{} = $hidden
+             RETURN count(b) as count",
+            HIDDEN_PROPERTY
+        ))
+        .param("book_id", book_id.to_string())
+        .param("hidden", hidden);
+
+        let mut result = self.graph.execute(query).await.map_err(|e| {
+            ApiError::ExternalServiceError(format!("Failed to update book visibility: {}", e))
+        })?;
+
+        Ok(match result.next().await {
+            Ok(Some(row)) => row.get::<i64>("count").unwrap_or(0) > 0,
+            _ => false,
+        })
+    }
+
     /// Add multiple books in batch
     pub async fn add_books_batch(&self, books: &[Book]) -> Result<()> {
         info!("Adding batch of {} books to Neo4j", books.len());
@@ -249,20 +325,84 @@ impl Neo4jClient {
         Ok(())
     }
 
+    /// Remove all co-shelving scores, deleting relationships that only existed for them
+    pub async fn clear_cf_scores(&self) -> Result<()> {
+        for statement in [
+            "MATCH ()-[r]->() WHERE r.cf_score IS NOT NULL AND r.weight IS NULL DELETE r",
+            "MATCH ()-[r]->() WHERE r.cf_score IS NOT NULL REMOVE r.cf_score, r.shared_users",
+        ] {
+            self.graph
+                .run(Query::new(statement.to_string()))
+                .await
+                .map_err(|e| {
+                    ApiError::ExternalServiceError(format!("Failed to clear CF scores: {}", e))
+                })?;
+        }
+        Ok(())
+    }
+
+    /// Set `cf_score` on relationships between pairs of books, creating them if needed
+    ///
+    /// Each pair is `(from_id, to_id, cf_score, shared_users)`; books missing from the
+    /// graph are skipped.
+    pub async fn write_cf_scores(
+        &self,
+        relation_type: &RelationType,
+        pairs: &[(String, String, f32, usize)],
+    ) -> Result<()> {
+        if pairs.is_empty() {
+            return Ok(());
+        }
+
+        let query = Query::new(format!(
+            "UNWIND range(0, size($from_ids) - 1) AS i
+             MATCH (a:Book {{id: $from_ids[i]}}), (b:Book {{id: $to_ids[i]}})
+             MERGE (a)-[r:{}]->(b)
+             SET r.cf_score = $scores[i], r.shared_users = $shared_users[i]",
+            relation_type.as_str()
+        ))
+        .param(
+            "from_ids",
+            pairs.iter().map(|p| p.0.clone()).collect::<Vec<_>>(),
+        )
+        .param(
+            "to_ids",
+            pairs.iter().map(|p| p.1.clone()).collect::<Vec<_>>(),
+        )
+        .param(
+            "scores",
+            pairs.iter().map(|p| p.2 as f64).collect::<Vec<_>>(),
+        )
+        .param(
+            "shared_users",
+            pairs.iter().map(|p| p.3 as i64).collect::<Vec<_>>(),
+        );
+
+        self.graph.run(query).await.map_err(|e| {
+            ApiError::ExternalServiceError(format!("Failed to write CF scores: {}", e))
+        })?;
+
+        Ok(())
+    }
+
     /// Get books similar to a given book ID
     pub async fn get_similar_books(&self, book_id: &str, limit: usize) -> Result<Vec<BookNode>> {
         debug!("Finding similar books for: {}", book_id);
 
-        let query = Query::new(
-            "MATCH (b:Book {id: $book_id})-[r:SIMILAR_TO]->(similar:Book)
+        // Embedding similarity blended with co-shelving where the CF job has scored the pair
+        let query = Query::new(format!(
+            "MATCH (b:Book {{id: $book_id}})-[r:SIMILAR_TO]->(similar:Book)
+             WHERE {} AND {}
              RETURN similar.id as id, similar.title as title, similar.author as author,
                     similar.categories as categories, similar.rating as rating,
                     similar.year as year, similar.description as description,
-                    r.weight as weight
-             ORDER BY r.weight DESC
-             LIMIT $limit"
-                .to_string(),
-        )
+                    {} as weight
+             ORDER BY weight DESC
+             LIMIT $limit",
+            visible("b"),
+            visible("similar"),
+            blended_weight("r")
+        ))
         .param("book_id", book_id.to_string())
         .param("limit", limit as i64);
 
@@ -296,15 +436,17 @@ impl Neo4jClient {
     ) -> Result<Vec<BookNode>> {
         debug!("Finding books by same author for: {}", book_id);
 
-        let query = Query::new(
-            "MATCH (b:Book {id: $book_id})-[r:SAME_AUTHOR]->(other:Book)
+        let query = Query::new(format!(
+            "MATCH (b:Book {{id: $book_id}})-[r:SAME_AUTHOR]->(other:Book)
+             WHERE {} AND {}
              RETURN other.id as id, other.title as title, other.author as author,
                     other.categories as categories, other.rating as rating,
                     other.year as year, other.description as description
              ORDER BY other.rating DESC
-             LIMIT $limit"
-                .to_string(),
-        )
+             LIMIT $limit",
+            visible("b"),
+            visible("other")
+        ))
         .param("book_id", book_id.to_string())
         .param("limit", limit as i64);
 
@@ -338,6 +480,7 @@ impl Neo4jClient {
 
         let query = Query::new(format!(
             "MATCH path = (b:Book {{id: $book_id}})-[*1..{}]-(related:Book)
+                 WHERE none(n IN nodes(path) WHERE coalesce(n.{}, false))
                  WITH b, related, relationships(path) as rels
                  RETURN DISTINCT
                         b.id as source_id, b.title as source_title, b.author as source_author,
@@ -349,7 +492,7 @@ impl Neo4jClient {
                         [r in rels | type(r)] as rel_types,
                         [r in rels | r.weight] as weights
                  LIMIT 100",
-            depth
+            depth, HIDDEN_PROPERTY
         ))
         .param("book_id", book_id.to_string());
 
@@ -413,15 +556,95 @@ impl Neo4jClient {
         })
     }
 
+    /// Direct relationships from any of the given books, strongest first
+    pub async fn get_relationships_from(
+        &self,
+        book_ids: &[String],
+        limit: usize,
+    ) -> Result<Vec<GraphRelationshipResponse>> {
+        if book_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let query = Query::new(format!(
+            "MATCH (b:Book)-[r]->(related:Book)
+             WHERE b.id IN $book_ids AND {} AND {}
+             RETURN b.id as from_id, related.id as to_id, type(r) as relation_type,
+                    {} as weight
+             ORDER BY weight DESC
+             LIMIT $limit",
+            visible("b"),
+            visible("related"),
+            blended_weight("r")
+        ))
+        .param("book_ids", book_ids.to_vec())
+        .param("limit", limit as i64);
+
+        let mut result = self.graph.execute(query).await.map_err(|e| {
+            ApiError::ExternalServiceError(format!("Failed to query relationships: {}", e))
+        })?;
+
+        let mut relationships = Vec::new();
+        while let Ok(Some(row)) = result.next().await {
+            relationships.push(GraphRelationshipResponse {
+                from_id: row.get::<String>("from_id").unwrap_or_default(),
+                to_id: row.get::<String>("to_id").unwrap_or_default(),
+                relation_type: row.get::<String>("relation_type").unwrap_or_default(),
+                weight: row.get::<f64>("weight").unwrap_or(0.0) as f32,
+            });
+        }
+
+        Ok(relationships)
+    }
+
+    /// Direct relationships between any two of the given books, strongest first
+    pub async fn get_relationships_among(
+        &self,
+        book_ids: &[String],
+    ) -> Result<Vec<GraphRelationshipResponse>> {
+        if book_ids.len() < 2 {
+            return Ok(Vec::new());
+        }
+
+        let query = Query::new(format!(
+            "MATCH (b:Book)-[r]->(related:Book)
+             WHERE b.id IN $book_ids AND related.id IN $book_ids AND {} AND {}
+             RETURN b.id as from_id, related.id as to_id, type(r) as relation_type,
+                    {} as weight
+             ORDER BY weight DESC",
+            visible("b"),
+            visible("related"),
+            blended_weight("r")
+        ))
+        .param("book_ids", book_ids.to_vec());
+
+        let mut result = self.graph.execute(query).await.map_err(|e| {
+            ApiError::ExternalServiceError(format!("Failed to query relationships: {}", e))
+        })?;
+
+        let mut relationships = Vec::new();
+        while let Ok(Some(row)) = result.next().await {
+            relationships.push(GraphRelationshipResponse {
+                from_id: row.get::<String>("from_id").unwrap_or_default(),
+                to_id: row.get::<String>("to_id").unwrap_or_default(),
+                relation_type: row.get::<String>("relation_type").unwrap_or_default(),
+                weight: row.get::<f64>("weight").unwrap_or(0.0) as f32,
+            });
+        }
+
+        Ok(relationships)
+    }
+
     /// Get a book by ID
     pub async fn get_book_by_id(&self, book_id: &str) -> Result<Option<BookNode>> {
-        let query = Query::new(
-            "MATCH (b:Book {id: $book_id})
+        let query = Query::new(format!(
+            "MATCH (b:Book {{id: $book_id}})
+             WHERE {}
              RETURN b.id as id, b.title as title, b.author as author,
                     b.categories as categories, b.rating as rating,
-                    b.year as year, b.description as description"
-                .to_string(),
-        )
+                    b.year as year, b.description as description",
+            visible("b")
+        ))
         .param("book_id", book_id.to_string());
 
         let mut result =
@@ -446,16 +669,16 @@ impl Neo4jClient {
 
     /// Search books by title pattern
     pub async fn search_books(&self, title_pattern: &str, limit: usize) -> Result<Vec<BookNode>> {
-        let query = Query::new(
+        let query = Query::new(format!(
             "MATCH (b:Book)
-             WHERE toLower(b.title) CONTAINS toLower($pattern)
+             WHERE toLower(b.title) CONTAINS toLower($pattern) AND {}
              RETURN b.id as id, b.title as title, b.author as author,
                     b.categories as categories, b.rating as rating,
                     b.year as year, b.description as description
              ORDER BY b.rating DESC
-             LIMIT $limit"
-                .to_string(),
-        )
+             LIMIT $limit",
+            visible("b")
+        ))
         .param("pattern", title_pattern.to_string())
         .param("limit", limit as i64);
 
@@ -513,6 +736,53 @@ impl Neo4jClient {
         })
     }
 
+    /// Ids of every book node in the graph
+    pub async fn list_book_ids(&self) -> Result<Vec<String>> {
+        let query =
+            Query::new("MATCH (b:Book) WHERE b.id IS NOT NULL RETURN b.id as id".to_string());
+        let mut result = self.graph.execute(query).await.map_err(|e| {
+            ApiError::ExternalServiceError(format!("Failed to list book ids: {}", e))
+        })?;
+
+        // A partial list would make a sync treat the missing books as absent,
+        // so any row that can't be read fails the listing
+        let mut ids = Vec::new();
+        while let Some(row) = result.next().await.map_err(|e| {
+            ApiError::ExternalServiceError(format!("Failed to list book ids: {}", e))
+        })? {
+            let id = row.get::<String>("id").map_err(|e| {
+                ApiError::ExternalServiceError(format!("Failed to read a book id: {}", e))
+            })?;
+            ids.push(id);
+        }
+
+        Ok(ids)
+    }
+
+    /// Delete book nodes and their relationships, returning how many nodes were removed
+    pub async fn delete_books(&self, ids: &[String]) -> Result<usize> {
+        let query = Query::new(
+            "MATCH (b:Book) WHERE b.id IN $ids
+             DETACH DELETE b
+             RETURN count(b) as count"
+                .to_string(),
+        )
+        .param("ids", ids.to_vec());
+
+        let mut result = self.graph.execute(query).await.map_err(|e| {
+            ApiError::ExternalServiceError(format!("Failed to delete books: {}", e))
+        })?;
+
+        let deleted = if let Ok(Some(row)) = result.next().await {
+            row.get::<i64>("count").unwrap_or(0) as usize
+        } else {
+            0
+        };
+
+        info!("Deleted {} book nodes from Neo4j", deleted);
+        Ok(deleted)
+    }
+
     /// Clear all data from the graph
     pub async fn clear_graph(&self) -> Result<()> {
         warn!("Clearing all data from Neo4j graph");
@@ -0,0 +1,461 @@
+//! Rating-aware re-ranking
+//!
+//! When a recommendation request carries a user id, the user's own ratings nudge
+//! the ranked results: books they rated, or books adjacent to ones they rated,
+//! move up for high ratings and down for low ones. Adjacency comes from the
+//! Neo4j graph when it covers the results and from embedding similarity otherwise.
+//!
+//! The same ratings also build a taste profile: an embedding that averages the
+//! books a user rated highly, used to recommend books without any query. Recent
+//! queries add a lighter bias toward the genres the user keeps searching for.
+//!
+//! Content preferences are different: they don't re-rank, they filter out books
+//! the user never wants to see.
+
+use crate::error::{ApiError, Result};
+use crate::ingest::language::{is_unknown, language_names};
+use crate::models::Book;
+use serde::{Deserialize, Serialize};
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use utoipa::ToSchema;
+
+/// Embedding similarity below which two books aren't treated as adjacent
+pub const MIN_EMBEDDING_SIMILARITY: f32 = 0.5;
+
+/// Lowest rating that counts towards a user's taste profile
+pub const MIN_PROFILE_RATING: u8 = 4;
+
+/// Most entries accepted in each content preference list
+pub const MAX_PREFERENCE_TERMS: usize = 50;
+
+/// Filters a user wants applied to every recommendation they get
+///
+/// Books missing the metadata a filter needs (an unknown language, no page count) are kept.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ContentPreferences {
+    /// Only recommend books in these languages, matched case-insensitively by
+    /// English name or any ISO 639-1, 639-2 or 639-3 code (e.g. "English", "en"
+    /// or "eng"); empty allows any language
+    #[serde(default)]
+    #[schema(example = json!(["English", "eng"]))]
+    pub preferred_languages: Vec<String>,
+    /// Leave out books longer than this many pages
+    #[serde(default)]
+    #[schema(example = 400, minimum = 1)]
+    pub max_page_count: Option<u32>,
+    /// Never recommend books with a genre or category containing one of these
+    #[serde(default)]
+    #[schema(example = json!(["horror"]))]
+    pub blocked_genres: Vec<String>,
+    /// Never recommend books tagged with one of these; matched against content flags, genres
+    /// and categories
+    #[serde(default)]
+    #[schema(example = json!(["war", "violence"]))]
+    pub blocked_content_warnings: Vec<String>,
+    /// Never recommend books with any content flag
+    #[serde(default)]
+    pub safe_search: bool,
+}
+
+impl ContentPreferences {
+    /// Trimmed, lowercased and deduplicated, rejecting overlong lists and a zero page count
+    pub fn normalized(self) -> Result<Self> {
+        fn terms(name: &str, values: Vec<String>) -> Result<Vec<String>> {
+            let mut normalized: Vec<String> = Vec::new();
+            for value in values {
+                let value = value.trim().to_lowercase();
+                if !value.is_empty() && !normalized.contains(&value) {
+                    normalized.push(value);
+                }
+            }
+            if normalized.len() > MAX_PREFERENCE_TERMS {
+                return Err(ApiError::InvalidInput(format!(
+                    "{} can have at most {} entries",
+                    name, MAX_PREFERENCE_TERMS
+                )));
+            }
+            Ok(normalized)
+        }
+
+        if self.max_page_count == Some(0) {
+            return Err(ApiError::InvalidInput(
+                "max_page_count must be at least 1".to_string(),
+            ));
+        }
+        Ok(Self {
+            preferred_languages: terms("preferred_languages", self.preferred_languages)?,
+            max_page_count: self.max_page_count,
+            blocked_genres: terms("blocked_genres", self.blocked_genres)?,
+            blocked_content_warnings: terms(
+                "blocked_content_warnings",
+                self.blocked_content_warnings,
+            )?,
+            safe_search: self.safe_search,
+        })
+    }
+
+    /// Whether any filter is set
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Whether a book passes every filter; expects normalized preferences
+    pub fn allows(&self, book: &Book) -> bool {
+        if !self.preferred_languages.is_empty() && !is_unknown(book.language.as_deref()) {
+            // Either side may use any code of a language, so both are resolved
+            let names = language_names(book.language.as_deref().unwrap_or_default());
+            if !self.preferred_languages.iter().any(|preferred| {
+                language_names(preferred)
+                    .iter()
+                    .any(|name| names.contains(name))
+            }) {
+                return false;
+            }
+        }
+
+        if let (Some(max), Some(pages)) = (self.max_page_count, book.page_count) {
+            if pages > max as i32 {
+                return false;
+            }
+        }
+
+        if self.safe_search && !book.content_flags.is_empty() {
+            return false;
+        }
+
+        let labels: Vec<String> = book
+            .genres
+            .iter()
+            .chain(&book.categories)
+            .map(|label| label.to_lowercase())
+            .collect();
+        let blocked_genre = self
+            .blocked_genres
+            .iter()
+            .any(|blocked| labels.iter().any(|label| label.contains(blocked.as_str())));
+        let blocked_warning = self.blocked_content_warnings.iter().any(|blocked| {
+            book.content_flags.contains(blocked)
+                || labels.iter().any(|label| label.contains(blocked.as_str()))
+        });
+        !blocked_genre && !blocked_warning
+    }
+}
+
+/// Calculate cosine similarity between two embeddings
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot_product: f32 = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
+    let norm_a: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b: f32 = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot_product / (norm_a * norm_b)
+    }
+}
+
+/// Preference in [-1, 1] for a 1-5 star rating; 3 stars is neutral
+pub fn rating_preference(rating: u8) -> f32 {
+    ((rating as f32 - 3.0) / 2.0).clamp(-1.0, 1.0)
+}
+
+/// Taste profile embedding: the average of the embeddings of highly rated books,
+/// weighted by how much the user liked each one and normalized to unit length
+///
+/// Returns None when none of the highly rated books has a stored embedding.
+pub fn taste_profile(
+    ratings: &[(String, u8)],
+    vectors: &HashMap<String, Vec<f32>>,
+) -> Option<Vec<f32>> {
+    let mut profile: Vec<f32> = Vec::new();
+    for (book_id, rating) in ratings {
+        if *rating < MIN_PROFILE_RATING {
+            continue;
+        }
+        let Some(vector) = vectors.get(book_id) else {
+            continue;
+        };
+        if profile.is_empty() {
+            profile = vec![0.0; vector.len()];
+        } else if profile.len() != vector.len() {
+            continue;
+        }
+        let weight = rating_preference(*rating);
+        for (total, value) in profile.iter_mut().zip(vector) {
+            *total += weight * value;
+        }
+    }
+
+    let norm = profile.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm == 0.0 {
+        return None;
+    }
+    Some(profile.into_iter().map(|x| x / norm).collect())
+}
+
+/// How often each tag came up in recent queries, relative to the most frequent one
+///
+/// Tags are lowercased; a tag repeated within one query counts once.
+pub fn history_tag_weights(history: &[Vec<String>]) -> HashMap<String, f32> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for tags in history {
+        let mut seen = Vec::new();
+        for tag in tags {
+            let tag = tag.trim().to_lowercase();
+            if !tag.is_empty() && !seen.contains(&tag) {
+                *counts.entry(tag.clone()).or_default() += 1;
+                seen.push(tag);
+            }
+        }
+    }
+
+    let max = counts.values().copied().max().unwrap_or(0);
+    counts
+        .into_iter()
+        .map(|(tag, count)| (tag, count as f32 / max as f32))
+        .collect()
+}
+
+/// Strongest history weight among the tags matching a book's genres or categories
+pub fn history_affinity(book: &Book, weights: &HashMap<String, f32>) -> Option<f32> {
+    let labels: Vec<String> = book
+        .genres
+        .iter()
+        .chain(&book.categories)
+        .map(|label| label.to_lowercase())
+        .collect();
+
+    weights
+        .iter()
+        .filter(|(tag, _)| labels.iter().any(|label| label.contains(tag.as_str())))
+        .map(|(_, weight)| *weight)
+        .reduce(f32::max)
+}
+
+/// Weighted links from candidate books to books the user has rated
+#[derive(Debug, Clone, Default)]
+pub struct RatingAdjacency {
+    /// Candidate id -> (rated book id, similarity in 0..=1)
+    links: HashMap<String, Vec<(String, f32)>>,
+}
+
+impl RatingAdjacency {
+    /// Link a candidate to a rated book, keeping the strongest similarity for each pair
+    pub fn add(&mut self, candidate_id: &str, rated_id: &str, similarity: f32) {
+        let similarity = similarity.clamp(0.0, 1.0);
+        if candidate_id == rated_id || similarity <= 0.0 {
+            return;
+        }
+
+        let links = self.links.entry(candidate_id.to_string()).or_default();
+        match links.iter_mut().find(|(id, _)| id == rated_id) {
+            Some((_, existing)) => *existing = existing.max(similarity),
+            None => links.push((rated_id.to_string(), similarity)),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.links.is_empty()
+    }
+
+    /// How much the user should like a candidate, in [-1, 1], or None without evidence
+    ///
+    /// A book the user rated themselves gets that rating's preference. Otherwise it's
+    /// the similarity-weighted mean preference of its rated neighbours, scaled by the
+    /// strongest link so that loose neighbours count for less.
+    pub fn affinity(&self, candidate_id: &str, ratings: &HashMap<String, u8>) -> Option<f32> {
+        if let Some(rating) = ratings.get(candidate_id) {
+            return Some(rating_preference(*rating));
+        }
+
+        let mut weighted = 0.0;
+        let mut total_similarity = 0.0;
+        let mut strongest: f32 = 0.0;
+        for (rated_id, similarity) in self.links.get(candidate_id)? {
+            let Some(rating) = ratings.get(rated_id) else {
+                continue;
+            };
+            weighted += rating_preference(*rating) * similarity;
+            total_similarity += similarity;
+            strongest = strongest.max(*similarity);
+        }
+
+        (total_similarity > 0.0).then(|| weighted / total_similarity * strongest)
+    }
+}
+
+/// Re-rank books by their current position blended with the user's affinity for them
+///
+/// `weight` is how many places' worth of the list (as a fraction of its length) a
+/// full-strength affinity can move a book. Books without an affinity keep their
+/// position score, so the order only changes where the ratings say something.
+pub fn blend_affinities<B: Borrow<Book>>(
+    books: Vec<B>,
+    affinities: &HashMap<String, f32>,
+    weight: f32,
+) -> Vec<B> {
+    if affinities.is_empty() || books.len() <= 1 {
+        return books;
+    }
+
+    let total = books.len() as f32;
+    let mut scored: Vec<(f32, B)> = books
+        .into_iter()
+        .enumerate()
+        .map(|(idx, book)| {
+            let affinity = book
+                .borrow()
+                .id
+                .as_ref()
+                .and_then(|id| affinities.get(id))
+                .copied()
+                .unwrap_or(0.0);
+            (1.0 - idx as f32 / total + weight * affinity, book)
+        })
+        .collect();
+
+    // Stable sort keeps the original order between books with equal scores
+    scored.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+    scored.into_iter().map(|(_, book)| book).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ratings_of_neighbours_move_books() {
+        let ratings = HashMap::from([("loved".to_string(), 5), ("disliked".to_string(), 1)]);
+        let mut adjacency = RatingAdjacency::default();
+        adjacency.add("sequel", "loved", 0.9);
+        adjacency.add("lookalike", "disliked", 0.8);
+        adjacency.add("mixed", "loved", 0.6);
+        adjacency.add("mixed", "disliked", 0.6);
+
+        assert_eq!(adjacency.affinity("disliked", &ratings), Some(-1.0));
+        assert!((adjacency.affinity("sequel", &ratings).unwrap() - 0.9).abs() < 1e-6);
+        assert!(adjacency.affinity("lookalike", &ratings).unwrap() < 0.0);
+        assert_eq!(adjacency.affinity("mixed", &ratings), Some(0.0));
+        assert_eq!(adjacency.affinity("unrelated", &ratings), None);
+
+        let books: Vec<Book> = ["lookalike", "unrelated", "sequel"]
+            .iter()
+            .map(|id| {
+                serde_json::from_value(serde_json::json!({"id": id, "categories": []})).unwrap()
+            })
+            .collect();
+        let affinities: HashMap<String, f32> = ["lookalike", "unrelated", "sequel"]
+            .iter()
+            .filter_map(|id| Some((id.to_string(), adjacency.affinity(id, &ratings)?)))
+            .collect();
+
+        let ranked = blend_affinities(books, &affinities, 0.5);
+        let ids: Vec<_> = ranked.iter().filter_map(|b| b.id.as_deref()).collect();
+        assert_eq!(ids, vec!["sequel", "unrelated", "lookalike"]);
+    }
+
+    #[test]
+    fn test_history_weights_follow_repeated_tags() {
+        let history = vec![
+            vec!["Fantasy".to_string(), "Dragons".to_string()],
+            vec!["fantasy".to_string(), "Fantasy".to_string()],
+            vec!["Mystery".to_string()],
+        ];
+        let weights = history_tag_weights(&history);
+        assert_eq!(weights["fantasy"], 1.0);
+        assert_eq!(weights["dragons"], 0.5);
+
+        let book: Book = serde_json::from_value(serde_json::json!({
+            "id": "b1",
+            "categories": ["Fiction / Mystery & Detective"],
+            "genres": ["mystery", "fantasy"],
+        }))
+        .unwrap();
+        assert_eq!(history_affinity(&book, &weights), Some(1.0));
+        assert_eq!(history_affinity(&book, &HashMap::new()), None);
+    }
+
+    #[test]
+    fn test_content_preferences_filter_books() {
+        let preferences = ContentPreferences {
+            preferred_languages: vec![" English ".to_string(), "eng".to_string()],
+            max_page_count: Some(500),
+            blocked_genres: vec!["Horror".to_string(), "horror".to_string()],
+            blocked_content_warnings: vec!["Violence".to_string()],
+            safe_search: false,
+        }
+        .normalized()
+        .unwrap();
+        assert_eq!(preferences.preferred_languages, vec!["english", "eng"]);
+        assert_eq!(preferences.blocked_genres, vec!["horror"]);
+
+        let book = |language: Option<&str>, pages: Option<i32>, category: &str| -> Book {
+            serde_json::from_value(serde_json::json!({
+                "id": "b1",
+                "categories": [category],
+                "language": language,
+                "page_count": pages,
+            }))
+            .unwrap()
+        };
+        assert!(preferences.allows(&book(Some("ENG"), Some(320), "Fantasy")));
+        assert!(preferences.allows(&book(None, None, "Fantasy")));
+        assert!(preferences.allows(&book(Some("unknown"), None, "Fantasy")));
+        assert!(!preferences.allows(&book(Some("fre"), Some(320), "Fantasy")));
+        assert!(!preferences.allows(&book(Some("eng"), Some(900), "Fantasy")));
+        assert!(!preferences.allows(&book(Some("eng"), Some(320), "Fiction / Horror")));
+        let spanish_only = ContentPreferences {
+            preferred_languages: vec!["spanish".to_string()],
+            ..Default::default()
+        };
+        assert!(spanish_only.allows(&book(Some("spa"), None, "Fantasy")));
+        assert!(!spanish_only.allows(&book(Some("eng"), None, "Fantasy")));
+        let french_only = ContentPreferences {
+            preferred_languages: vec!["fr".to_string()],
+            ..Default::default()
+        };
+        assert!(french_only.allows(&book(Some("fre"), None, "Fantasy")));
+        assert!(french_only.allows(&book(Some("fra"), None, "Fantasy")));
+
+        let mut flagged = book(Some("eng"), Some(320), "Fantasy");
+        flagged.content_flags = vec!["sexual content".to_string()];
+        assert!(preferences.allows(&flagged));
+        flagged.content_flags.push("violence".to_string());
+        assert!(!preferences.allows(&flagged));
+        let safe_search = ContentPreferences {
+            safe_search: true,
+            ..Default::default()
+        };
+        assert!(!safe_search.allows(&flagged));
+        assert!(safe_search.allows(&book(None, None, "Fantasy")));
+
+        assert!(ContentPreferences {
+            max_page_count: Some(0),
+            ..Default::default()
+        }
+        .normalized()
+        .is_err());
+    }
+
+    #[test]
+    fn test_taste_profile_favours_the_best_rated_books() {
+        let vectors = HashMap::from([
+            ("liked".to_string(), vec![0.0, 1.0]),
+            ("loved".to_string(), vec![1.0, 0.0]),
+            ("meh".to_string(), vec![-1.0, -1.0]),
+        ]);
+        let ratings = vec![
+            ("liked".to_string(), 4),
+            ("loved".to_string(), 5),
+            ("meh".to_string(), 3),
+            ("missing".to_string(), 5),
+        ];
+
+        let profile = taste_profile(&ratings, &vectors).unwrap();
+        assert!((profile.iter().map(|x| x * x).sum::<f32>() - 1.0).abs() < 1e-6);
+        assert!(profile[0] > profile[1] && profile[1] > 0.0);
+
+        assert_eq!(taste_profile(&ratings[2..3], &vectors), None);
+    }
+}
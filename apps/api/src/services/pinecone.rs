@@ -1,24 +1,36 @@
-use crate::error::{ApiError, Result};
+use crate::cache::TtlCache;
+use crate::error::{recover_lock, retry_after_from_headers, ApiError, Result};
+use crate::ml::sparse_encoder::SparseValues;
+use crate::models::{EndpointCheck, EndpointProbe};
+use crate::services::http_client::{upstream_client, upstream_client_builder};
+use crate::services::{deadline, usage};
 use log::{debug, error, info, warn};
-use reqwest::Client;
+use reqwest::{Client, Url};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::collections::HashMap;
+use std::net::SocketAddr;
 use std::sync::atomic::AtomicBool;
 use std::sync::{Arc, RwLock};
 use std::time::{Duration, Instant};
 
-// Cache entry for Pinecone results to improve performance
-#[derive(Debug, Clone)]
-struct PineconeCacheEntry {
-    results: Vec<crate::models::Book>,
-    timestamp: Instant,
-}
-
 // Cache configuration
 // Cache TTL in seconds
 const CACHE_TTL_SECONDS: u64 = 3600; // 1 hour
 const CACHE_CAPACITY: usize = 100;
+/// Timeout of each request to the index
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// Longest wait for each step of an endpoint probe
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long requests use the addresses an endpoint probe resolved
+const PINNED_ADDRESSES_TTL: Duration = Duration::from_secs(10 * 60);
+/// Where a wrong host most likely comes from
+const HOST_HINT: &str = "The host is built as https://<index>.svc.<environment>.pinecone.io; \
+check APP_PINECONE_INDEX and APP_PINECONE_ENVIRONMENT against the index's host in the Pinecone console";
+
+fn query_cache(name: &'static str) -> TtlCache<String, Vec<crate::models::Book>> {
+    TtlCache::new(name, CACHE_CAPACITY, Duration::from_secs(CACHE_TTL_SECONDS))
+}
 
 #[derive(Clone)]
 pub struct Pinecone {
@@ -27,16 +39,23 @@ pub struct Pinecone {
     host: Arc<RwLock<String>>,
     dimension: usize,
     // Caches to improve performance and reduce API calls
-    vector_cache: Arc<RwLock<HashMap<String, PineconeCacheEntry>>>,
-    metadata_cache: Arc<RwLock<HashMap<String, PineconeCacheEntry>>>,
+    vector_cache: TtlCache<String, Vec<crate::models::Book>>,
+    metadata_cache: TtlCache<String, Vec<crate::models::Book>>,
     // Initialization status and parameters
     initialized: Arc<AtomicBool>,
     init_params: Option<(String, String, String)>, // (api_key, environment, index_name)
+    // Client connecting to the addresses the last probe resolved, until it expires
+    pinned: Arc<RwLock<Option<(Client, Instant)>>>,
+    last_probe: Arc<RwLock<Option<EndpointProbe>>>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct QueryMatch {
     pub id: String,
+    #[serde(default)]
+    pub score: Option<f32>,
+    #[serde(default)]
+    pub values: Option<Vec<f32>>,
     pub metadata: Option<serde_json::Value>,
 }
 
@@ -45,6 +64,123 @@ pub struct QueryResponse {
     pub matches: Option<Vec<QueryMatch>>,
 }
 
+/// A vector written by the indexer
+#[derive(Debug, Clone, Serialize)]
+pub struct UpsertVector {
+    pub id: String,
+    pub values: Vec<f32>,
+    /// Keyword vector for the fallback search, on indexes that take sparse vectors
+    #[serde(rename = "sparseValues", skip_serializing_if = "Option::is_none")]
+    pub sparse_values: Option<SparseValues>,
+    pub metadata: serde_json::Value,
+}
+
+#[derive(Debug, Serialize)]
+struct UpsertRequest<'a> {
+    vectors: &'a [UpsertVector],
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FetchResponse {
+    #[serde(default)]
+    pub vectors: HashMap<String, QueryMatch>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListResponse {
+    #[serde(default)]
+    vectors: Vec<ListedVector>,
+    pagination: Option<ListPagination>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListedVector {
+    id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListPagination {
+    next: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct DeleteRequest<'a> {
+    ids: &'a [String],
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct UpdateRequest<'a> {
+    id: &'a str,
+    set_metadata: &'a serde_json::Value,
+}
+
+/// Conditions on stored metadata, combined into a Pinecone query filter
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FilterBuilder {
+    clauses: Vec<serde_json::Value>,
+}
+
+impl FilterBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Require `field` to lie within `min..=max`; an open bound is left out,
+    /// and with neither bound nothing is added
+    pub fn with_range(mut self, field: &str, min: Option<i32>, max: Option<i32>) -> Self {
+        let mut range = serde_json::Map::new();
+        if let Some(min) = min {
+            range.insert("$gte".to_string(), json!(min));
+        }
+        if let Some(max) = max {
+            range.insert("$lte".to_string(), json!(max));
+        }
+        if !range.is_empty() {
+            self.clauses.push(json!({ field: range }));
+        }
+        self
+    }
+
+    /// Require `field`, or one of its elements for a list, to be one of `values`
+    pub fn with_any_of(mut self, field: &str, values: &[&str]) -> Self {
+        if !values.is_empty() {
+            self.clauses.push(json!({ field: { "$in": values } }));
+        }
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.clauses.is_empty()
+    }
+
+    /// The filter, or None if there are no conditions
+    pub fn build(&self) -> Option<serde_json::Value> {
+        match self.clauses.as_slice() {
+            [] => None,
+            [clause] => Some(clause.clone()),
+            clauses => Some(json!({ "$and": clauses })),
+        }
+    }
+}
+
+/// Page size for `/vectors/list` (the API maximum)
+const LIST_PAGE_SIZE: usize = 100;
+
+/// Most matches a query without metadata may ask for (the API maximum)
+const MAX_ID_QUERY_TOP_K: u32 = 10_000;
+
+/// Metadata flag of books hidden from every query until they're restored
+pub const HIDDEN_FIELD: &str = "hidden";
+
+/// Whether stored metadata marks a book hidden
+pub fn is_hidden(metadata: &serde_json::Value) -> bool {
+    metadata
+        .get(HIDDEN_FIELD)
+        .and_then(|hidden| hidden.as_bool())
+        .unwrap_or(false)
+}
+
 #[derive(Debug, Serialize)]
 pub struct QueryRequest {
     pub vector: Vec<f32>,
@@ -58,6 +194,8 @@ pub struct QueryRequest {
     pub filter: Option<serde_json::Value>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub namespace: Option<String>,
+    #[serde(rename = "sparseVector", skip_serializing_if = "Option::is_none")]
+    pub sparse_vector: Option<SparseValues>,
 }
 
 impl Pinecone {
@@ -70,15 +208,7 @@ impl Pinecone {
             &api_key[api_key.len().saturating_sub(5)..]
         );
 
-        // Create HTTP client with optimized settings for cold starts
-        let client = Client::builder()
-            .timeout(std::time::Duration::from_secs(30))
-            .connect_timeout(std::time::Duration::from_secs(10))
-            // Increase connection pool for better concurrent request handling
-            .pool_max_idle_per_host(10)
-            // Add retry capability
-            .use_rustls_tls()
-            .build()
+        let client = upstream_client(REQUEST_TIMEOUT)
             .map_err(|e| ApiError::PineconeError(format!("Failed to create HTTP client: {}", e)))?;
 
         // Validate that we have actual values and not placeholders
@@ -122,10 +252,32 @@ impl Pinecone {
             api_key: api_key.to_string(),
             host: Arc::new(RwLock::new(host)),
             dimension,
-            vector_cache: Arc::new(RwLock::new(HashMap::with_capacity(CACHE_CAPACITY))),
-            metadata_cache: Arc::new(RwLock::new(HashMap::with_capacity(CACHE_CAPACITY))),
+            vector_cache: query_cache("pinecone_vector_queries"),
+            metadata_cache: query_cache("pinecone_metadata_queries"),
+            initialized: Arc::new(AtomicBool::new(true)),
+            init_params: None,
+            pinned: Arc::default(),
+            last_probe: Arc::default(),
+        })
+    }
+
+    /// Creates a client of the index served at `host` rather than the one the
+    /// environment and index name point to, such as a mock of it in tests
+    pub fn with_host(api_key: &str, host: &str) -> Result<Self> {
+        let client = upstream_client(REQUEST_TIMEOUT)
+            .map_err(|e| ApiError::PineconeError(format!("Failed to create HTTP client: {}", e)))?;
+
+        Ok(Self {
+            client,
+            api_key: api_key.to_string(),
+            host: Arc::new(RwLock::new(host.trim_end_matches('/').to_string())),
+            dimension: 512,
+            vector_cache: query_cache("pinecone_vector_queries"),
+            metadata_cache: query_cache("pinecone_metadata_queries"),
             initialized: Arc::new(AtomicBool::new(true)),
             init_params: None,
+            pinned: Arc::default(),
+            last_probe: Arc::default(),
         })
     }
 
@@ -138,13 +290,8 @@ impl Pinecone {
     pub fn new_with_lazy_init(api_key: &str, environment: &str, index_name: &str) -> Result<Self> {
         debug!("Creating Pinecone client with lazy initialization");
 
-        // Create a client with default configuration - actual connection will happen later
-        let client = Client::builder()
-            .timeout(std::time::Duration::from_secs(30))
-            .connect_timeout(std::time::Duration::from_secs(10))
-            .pool_max_idle_per_host(10)
-            .use_rustls_tls()
-            .build()
+        // The connection will happen later
+        let client = upstream_client(REQUEST_TIMEOUT)
             .map_err(|e| ApiError::PineconeError(format!("Failed to create HTTP client: {}", e)))?;
 
         Ok(Self {
@@ -152,14 +299,16 @@ impl Pinecone {
             api_key: api_key.to_string(),
             host: Arc::new(RwLock::new(String::new())), // Will be initialized later
             dimension: 512,
-            vector_cache: Arc::new(RwLock::new(HashMap::with_capacity(CACHE_CAPACITY))),
-            metadata_cache: Arc::new(RwLock::new(HashMap::with_capacity(CACHE_CAPACITY))),
+            vector_cache: query_cache("pinecone_vector_queries"),
+            metadata_cache: query_cache("pinecone_metadata_queries"),
             initialized: Arc::new(AtomicBool::new(false)),
             init_params: Some((
                 api_key.to_string(),
                 environment.to_string(),
                 index_name.to_string(),
             )),
+            pinned: Arc::default(),
+            last_probe: Arc::default(),
         })
     }
 
@@ -235,93 +384,212 @@ impl Pinecone {
         Ok(())
     }
 
-    // Check if a result is in the metadata cache
-    fn check_metadata_cache(&self, key: &str) -> Option<Vec<crate::models::Book>> {
-        if let Ok(cache) = self.metadata_cache.read() {
-            if let Some(entry) = cache.get(key) {
-                if entry.timestamp.elapsed() < Duration::from_secs(CACHE_TTL_SECONDS) {
-                    return Some(entry.results.clone());
-                }
-            }
+    /// Client for the next request: pinned to the addresses the last probe
+    /// resolved while they're fresh, otherwise resolving the host itself
+    fn client(&self) -> Client {
+        match &*recover_lock(self.pinned.read(), "pinecone pinned client") {
+            Some((client, expires_at)) if Instant::now() < *expires_at => client.clone(),
+            _ => self.client.clone(),
         }
-        None
     }
 
-    // Update the metadata cache with new results
-    fn update_metadata_cache(&self, key: String, results: Vec<crate::models::Book>) {
-        if let Ok(mut cache) = self.metadata_cache.write() {
-            // Clean up expired cache entries if we're at capacity
-            if cache.len() >= CACHE_CAPACITY {
-                // Clean up expired entries
-                cache.retain(|_, entry| {
-                    entry.timestamp.elapsed() < Duration::from_secs(CACHE_TTL_SECONDS)
-                });
-            }
+    /// Resolve the index host and send it a HEAD request, keeping the result
+    /// for [`Self::endpoint_status`].
+    ///
+    /// When the probe passes, requests connect to the resolved addresses
+    /// without looking the host up again for the next ten minutes. When it
+    /// fails, addresses pinned by an earlier probe are dropped, so requests
+    /// resolve the host themselves again.
+    pub async fn probe_endpoint(&self) -> EndpointProbe {
+        let started = Instant::now();
+        let mut probe = EndpointProbe {
+            upstream: "pinecone".to_string(),
+            host: String::new(),
+            addresses: Vec::new(),
+            check: EndpointCheck::Ok,
+            message: None,
+            latency_ms: 0,
+            checked_at: chrono::Utc::now().to_rfc3339(),
+        };
 
-            cache.insert(
-                key,
-                PineconeCacheEntry {
-                    results,
-                    timestamp: Instant::now(),
-                },
+        if let Err((check, message)) = self.run_probe(&mut probe).await {
+            warn!("Pinecone endpoint probe failed ({:?}): {}", check, message);
+            *recover_lock(self.pinned.write(), "pinecone pinned client") = None;
+            probe.check = check;
+            probe.message = Some(message);
+        } else {
+            info!(
+                "Pinecone endpoint {} resolved to {}",
+                probe.host,
+                probe.addresses.join(", ")
             );
         }
+        probe.latency_ms = started.elapsed().as_millis() as u64;
+
+        *recover_lock(self.last_probe.write(), "pinecone endpoint probe") = Some(probe.clone());
+        probe
     }
 
-    // Check if a result is in the vector cache
-    fn check_vector_cache(&self, key: &str) -> Option<Vec<crate::models::Book>> {
-        if let Ok(cache) = self.vector_cache.read() {
-            if let Some(entry) = cache.get(key) {
-                if entry.timestamp.elapsed() < Duration::from_secs(CACHE_TTL_SECONDS) {
-                    return Some(entry.results.clone());
-                }
-            }
-        }
-        None
+    /// Result of the last [`Self::probe_endpoint`]
+    pub fn endpoint_status(&self) -> Option<EndpointProbe> {
+        recover_lock(self.last_probe.read(), "pinecone endpoint probe").clone()
     }
 
-    // Update the vector cache with new results
-    fn update_vector_cache(&self, key: String, results: Vec<crate::models::Book>) {
-        if let Ok(mut cache) = self.vector_cache.write() {
-            // Clean up expired cache entries if we're at capacity
-            if cache.len() >= CACHE_CAPACITY {
-                // Clean up expired entries
-                cache.retain(|_, entry| {
-                    entry.timestamp.elapsed() < Duration::from_secs(CACHE_TTL_SECONDS)
-                });
+    async fn run_probe(
+        &self,
+        probe: &mut EndpointProbe,
+    ) -> std::result::Result<(), (EndpointCheck, String)> {
+        self.ensure_initialized()
+            .await
+            .map_err(|e| (EndpointCheck::InvalidConfig, e.to_string()))?;
+
+        probe.host = recover_lock(self.host.read(), "pinecone host").clone();
+        let url = Url::parse(&probe.host).map_err(|e| {
+            (
+                EndpointCheck::InvalidConfig,
+                format!("{} isn't a valid URL ({}). {}", probe.host, e, HOST_HINT),
+            )
+        })?;
+        let hostname = url.host_str().unwrap_or_default().to_string();
+        let port = url.port_or_known_default().unwrap_or(443);
+
+        let addresses: Vec<SocketAddr> = match tokio::time::timeout(
+            PROBE_TIMEOUT,
+            tokio::net::lookup_host((hostname.as_str(), port)),
+        )
+        .await
+        {
+            Ok(Ok(addresses)) => addresses.collect(),
+            Ok(Err(e)) => {
+                return Err((
+                    EndpointCheck::DnsFailed,
+                    format!("{} didn't resolve ({}). {}", hostname, e, HOST_HINT),
+                ))
             }
+            Err(_) => {
+                return Err((
+                    EndpointCheck::DnsFailed,
+                    format!(
+                        "Resolving {} took over {}s; check the instance's DNS servers and outbound network",
+                        hostname,
+                        PROBE_TIMEOUT.as_secs()
+                    ),
+                ))
+            }
+        };
+        if addresses.is_empty() {
+            return Err((
+                EndpointCheck::DnsFailed,
+                format!("{} resolved to no addresses. {}", hostname, HOST_HINT),
+            ));
+        }
+        probe.addresses = addresses.iter().map(ToString::to_string).collect();
 
-            cache.insert(
-                key,
-                PineconeCacheEntry {
-                    results,
-                    timestamp: Instant::now(),
-                },
-            );
+        let pinned = upstream_client_builder()
+            .timeout(REQUEST_TIMEOUT)
+            .resolve_to_addrs(&hostname, &addresses)
+            .build()
+            .map_err(|e| {
+                (
+                    EndpointCheck::Unreachable,
+                    format!("Failed to create HTTP client: {}", e),
+                )
+            })?;
+
+        let response = pinned
+            .head(format!("{}/describe_index_stats", probe.host))
+            .header("Api-Key", &self.api_key)
+            .header("X-Pinecone-API-Version", "2025-01")
+            .header("User-Agent", "recommend-a-book-rust-api/1.0")
+            .timeout(PROBE_TIMEOUT)
+            .send()
+            .await
+            .map_err(|e| {
+                (
+                    EndpointCheck::Unreachable,
+                    format!(
+                        "{} resolved, but the request to it failed ({}); check outbound HTTPS access from the instance",
+                        hostname, e
+                    ),
+                )
+            })?;
+
+        let status = response.status();
+        match status.as_u16() {
+            401 | 403 => Err((
+                EndpointCheck::Unauthorized,
+                format!(
+                    "Pinecone rejected the API key ({}); check APP_PINECONE_API_KEY belongs to the index's project",
+                    status
+                ),
+            )),
+            404 => Err((
+                EndpointCheck::NotFound,
+                format!("No index answers at {}. {}", probe.host, HOST_HINT),
+            )),
+            _ if status.is_server_error() => Err((
+                EndpointCheck::UpstreamError,
+                format!(
+                    "Pinecone answered {}; its status page may report an incident",
+                    status
+                ),
+            )),
+            // The endpoint takes GET and POST, so a 405 still shows it's there
+            _ => {
+                *recover_lock(self.pinned.write(), "pinecone pinned client") =
+                    Some((pinned, Instant::now() + PINNED_ADDRESSES_TTL));
+                Ok(())
+            }
         }
     }
 
+    /// Drop all cached vector and metadata query results
+    pub fn clear_caches(&self) {
+        self.vector_cache.clear();
+        self.metadata_cache.clear();
+        info!("Pinecone query caches cleared");
+    }
+
     pub async fn query_metadata(
         &self,
         field: &str,
         value: &str,
         exact_match: bool,
         top_k: usize,
+    ) -> Result<Vec<crate::models::Book>> {
+        self.query_metadata_filtered(field, value, exact_match, top_k, None)
+            .await
+    }
+
+    /// [`query_metadata`](Self::query_metadata), also requiring the conditions of `filter`
+    pub async fn query_metadata_filtered(
+        &self,
+        field: &str,
+        value: &str,
+        exact_match: bool,
+        top_k: usize,
+        filter: Option<&serde_json::Value>,
     ) -> Result<Vec<crate::models::Book>> {
         // Ensure the client is initialized
         self.ensure_initialized().await?;
 
         // Generate a cache key
-        let cache_key = format!("md_{}_{}_{}_{}", field, value, exact_match, top_k);
+        let cache_key = match filter {
+            Some(filter) => format!(
+                "md_{}_{}_{}_{}_{}",
+                field, value, exact_match, top_k, filter
+            ),
+            None => format!("md_{}_{}_{}_{}", field, value, exact_match, top_k),
+        };
 
         // Check cache first
-        if let Some(results) = self.check_metadata_cache(&cache_key) {
+        if let Some(results) = self.metadata_cache.get(&cache_key) {
             debug!("Metadata query cache hit for: {} = {}", field, value);
             return Ok(results);
         }
 
         // Build filter based on match type
-        let filter = if exact_match {
+        let field_filter = if exact_match {
             json!({
                 field: {"$eq": value}
             })
@@ -336,6 +604,10 @@ impl Pinecone {
                 field: {"$in": variations}
             })
         };
+        let filter = match filter {
+            Some(filter) => json!({ "$and": [field_filter, filter] }),
+            None => field_filter,
+        };
 
         // Create query request with dummy vector and metadata filter
         let query_request = QueryRequest {
@@ -345,12 +617,13 @@ impl Pinecone {
             include_metadata: Some(true),
             filter: Some(filter),
             namespace: None,
+            sparse_vector: None,
         };
 
         let results = self.execute_query(query_request).await?;
 
         // Cache the results
-        self.update_metadata_cache(cache_key, results.clone());
+        self.metadata_cache.insert(cache_key, results.clone());
 
         Ok(results)
     }
@@ -359,6 +632,16 @@ impl Pinecone {
         &self,
         embedding: &[f32],
         top_k: usize,
+    ) -> Result<Vec<crate::models::Book>> {
+        self.query_vector_filtered(embedding, top_k, None).await
+    }
+
+    /// [`query_vector`](Self::query_vector), only matching vectors whose metadata meets `filter`
+    pub async fn query_vector_filtered(
+        &self,
+        embedding: &[f32],
+        top_k: usize,
+        filter: Option<&serde_json::Value>,
     ) -> Result<Vec<crate::models::Book>> {
         // Ensure the client is initialized
         self.ensure_initialized().await?;
@@ -375,9 +658,13 @@ impl Pinecone {
             embedding[embedding.len() - 1],
             top_k
         );
+        let cache_key = match filter {
+            Some(filter) => format!("{}_{}", cache_key, filter),
+            None => cache_key,
+        };
 
         // Check cache first
-        if let Some(results) = self.check_vector_cache(&cache_key) {
+        if let Some(results) = self.vector_cache.get(&cache_key) {
             debug!("Vector query cache hit");
             return Ok(results);
         }
@@ -388,19 +675,401 @@ impl Pinecone {
             top_k: top_k as u32,
             include_values: Some(false),
             include_metadata: Some(true),
-            filter: None,
+            filter: filter.cloned(),
             namespace: None,
+            sparse_vector: None,
         };
 
         let results = self.execute_query(query_request).await?;
 
         // Cache the results
-        self.update_vector_cache(cache_key, results.clone());
+        self.vector_cache.insert(cache_key, results.clone());
 
         Ok(results)
     }
 
+    /// Books whose keyword vectors share the most words with `sparse`, best first
+    ///
+    /// The dense half of the query is zero, so only the keywords score, and books
+    /// sharing none are left out. Needs an index with the dotproduct metric the
+    /// indexer wrote keyword vectors to.
+    pub async fn query_sparse(
+        &self,
+        sparse: &SparseValues,
+        top_k: usize,
+    ) -> Result<Vec<crate::models::Book>> {
+        self.ensure_initialized().await?;
+
+        let cache_key = format!("sparse_{:?}_{}", sparse.indices, top_k);
+        if let Some(results) = self.metadata_cache.get(&cache_key) {
+            debug!("Sparse query cache hit");
+            return Ok(results);
+        }
+
+        let query_request = QueryRequest {
+            vector: vec![0.0; self.dimension],
+            top_k: top_k as u32,
+            include_values: Some(false),
+            include_metadata: Some(true),
+            filter: None,
+            namespace: None,
+            sparse_vector: Some(sparse.clone()),
+        };
+
+        let results = self.execute_query(query_request).await?;
+        self.metadata_cache.insert(cache_key, results.clone());
+        Ok(results)
+    }
+
+    /// Write vectors to the index in a single request.
+    ///
+    /// Throttling is reported as `ServiceUnavailable` so callers can back off;
+    /// retries are left to the caller.
+    pub async fn upsert_vectors(&self, vectors: &[UpsertVector]) -> Result<()> {
+        self.ensure_initialized().await?;
+
+        let host_string = recover_lock(self.host.read(), "pinecone host").clone();
+        let url = format!("{}/vectors/upsert", host_string);
+
+        let response = self
+            .client()
+            .post(&url)
+            .header("Api-Key", &self.api_key)
+            .header("Content-Type", "application/json")
+            .header("X-Pinecone-API-Version", "2025-01")
+            .header("User-Agent", "recommend-a-book-rust-api/1.0")
+            .timeout(deadline::bounded(REQUEST_TIMEOUT))
+            .json(&UpsertRequest { vectors })
+            .send()
+            .await?;
+
+        let status = response.status();
+        if status.as_u16() == 429 || status.as_u16() == 503 {
+            let retry_after = retry_after_from_headers(response.headers());
+            return Err(ApiError::service_unavailable(
+                format!("Pinecone upsert throttled ({})", status),
+                retry_after,
+            ));
+        }
+        if !status.is_success() {
+            let text = response.text().await.unwrap_or_default();
+            return Err(ApiError::PineconeError(format!(
+                "Upsert failed with status {}: {}",
+                status, text
+            )));
+        }
+
+        debug!("Upserted {} vectors to Pinecone", vectors.len());
+        Ok(())
+    }
+
+    /// Ids of every vector in the index, following pagination.
+    ///
+    /// Listing is only supported by serverless indexes; pod-based indexes return an error.
+    pub async fn list_ids(&self) -> Result<Vec<String>> {
+        self.ensure_initialized().await?;
+
+        let host_string = recover_lock(self.host.read(), "pinecone host").clone();
+        let url = format!("{}/vectors/list", host_string);
+        let limit = LIST_PAGE_SIZE.to_string();
+        let mut ids = Vec::new();
+        let mut pagination_token: Option<String> = None;
+
+        loop {
+            let mut params = vec![("limit", limit.as_str())];
+            if let Some(token) = &pagination_token {
+                params.push(("paginationToken", token.as_str()));
+            }
+
+            usage::record_pinecone_read();
+            let response = self
+                .client()
+                .get(&url)
+                .header("Api-Key", &self.api_key)
+                .header("X-Pinecone-API-Version", "2025-01")
+                .header("User-Agent", "recommend-a-book-rust-api/1.0")
+                .timeout(deadline::bounded(REQUEST_TIMEOUT))
+                .query(&params)
+                .send()
+                .await?;
+
+            let status = response.status();
+            if status.as_u16() == 429 || status.as_u16() == 503 {
+                let retry_after = retry_after_from_headers(response.headers());
+                return Err(ApiError::service_unavailable(
+                    format!("Pinecone list throttled ({})", status),
+                    retry_after,
+                ));
+            }
+            if !status.is_success() {
+                let text = response.text().await.unwrap_or_default();
+                return Err(ApiError::PineconeError(format!(
+                    "List failed with status {}: {}",
+                    status, text
+                )));
+            }
+
+            let page: ListResponse = response
+                .json()
+                .await
+                .map_err(|e| ApiError::PineconeError(format!("Response parsing failed: {}", e)))?;
+            ids.extend(page.vectors.into_iter().map(|vector| vector.id));
+
+            match page.pagination.and_then(|p| p.next) {
+                Some(next) => pagination_token = Some(next),
+                None => break,
+            }
+        }
+
+        debug!("Listed {} vector ids from Pinecone", ids.len());
+        Ok(ids)
+    }
+
+    /// Delete vectors by id in a single request
+    pub async fn delete_vectors(&self, ids: &[String]) -> Result<()> {
+        self.ensure_initialized().await?;
+
+        if ids.is_empty() {
+            return Ok(());
+        }
+
+        let host_string = recover_lock(self.host.read(), "pinecone host").clone();
+        let url = format!("{}/vectors/delete", host_string);
+
+        let response = self
+            .client()
+            .post(&url)
+            .header("Api-Key", &self.api_key)
+            .header("Content-Type", "application/json")
+            .header("X-Pinecone-API-Version", "2025-01")
+            .header("User-Agent", "recommend-a-book-rust-api/1.0")
+            .timeout(deadline::bounded(REQUEST_TIMEOUT))
+            .json(&DeleteRequest { ids })
+            .send()
+            .await?;
+
+        let status = response.status();
+        if status.as_u16() == 429 || status.as_u16() == 503 {
+            let retry_after = retry_after_from_headers(response.headers());
+            return Err(ApiError::service_unavailable(
+                format!("Pinecone delete throttled ({})", status),
+                retry_after,
+            ));
+        }
+        if !status.is_success() {
+            let text = response.text().await.unwrap_or_default();
+            return Err(ApiError::PineconeError(format!(
+                "Delete failed with status {}: {}",
+                status, text
+            )));
+        }
+
+        // Deleted vectors may still be sitting in the query caches
+        self.clear_caches();
+        debug!("Deleted {} vectors from Pinecone", ids.len());
+        Ok(())
+    }
+
+    /// Overwrite the given metadata fields of one vector, leaving its values and other fields
+    pub async fn update_metadata(&self, id: &str, metadata: &serde_json::Value) -> Result<()> {
+        self.ensure_initialized().await?;
+
+        let host_string = recover_lock(self.host.read(), "pinecone host").clone();
+        let url = format!("{}/vectors/update", host_string);
+
+        let response = self
+            .client()
+            .post(&url)
+            .header("Api-Key", &self.api_key)
+            .header("Content-Type", "application/json")
+            .header("X-Pinecone-API-Version", "2025-01")
+            .header("User-Agent", "recommend-a-book-rust-api/1.0")
+            .timeout(deadline::bounded(REQUEST_TIMEOUT))
+            .json(&UpdateRequest {
+                id,
+                set_metadata: metadata,
+            })
+            .send()
+            .await?;
+
+        let status = response.status();
+        if status.as_u16() == 429 || status.as_u16() == 503 {
+            let retry_after = retry_after_from_headers(response.headers());
+            return Err(ApiError::service_unavailable(
+                format!("Pinecone update throttled ({})", status),
+                retry_after,
+            ));
+        }
+        if !status.is_success() {
+            let text = response.text().await.unwrap_or_default();
+            return Err(ApiError::PineconeError(format!(
+                "Update failed with status {}: {}",
+                status, text
+            )));
+        }
+
+        // Cached query results still carry the old metadata
+        self.clear_caches();
+        debug!("Updated metadata of vector {}", id);
+        Ok(())
+    }
+
+    /// Fetch the stored metadata for the given vector ids.
+    ///
+    /// Ids that don't exist in the index are simply absent from the result.
+    pub async fn fetch_metadata(
+        &self,
+        ids: &[String],
+    ) -> Result<HashMap<String, serde_json::Value>> {
+        Ok(self
+            .fetch(ids)
+            .await?
+            .vectors
+            .into_iter()
+            .filter_map(|(id, vector)| vector.metadata.map(|metadata| (id, metadata)))
+            .collect())
+    }
+
+    /// Fetch the stored books for the given vector ids.
+    ///
+    /// Ids that don't exist in the index, whose metadata isn't a book or that are
+    /// hidden are absent from the result.
+    pub async fn fetch_books(
+        &self,
+        ids: &[String],
+    ) -> Result<HashMap<String, crate::models::Book>> {
+        Ok(self
+            .fetch_metadata(ids)
+            .await?
+            .into_iter()
+            .filter(|(_, metadata)| !is_hidden(metadata))
+            .filter_map(|(id, mut metadata)| {
+                metadata
+                    .as_object_mut()?
+                    .insert("id".to_string(), serde_json::json!(id));
+                match serde_json::from_value(metadata) {
+                    Ok(book) => Some((id, book)),
+                    Err(e) => {
+                        warn!("Failed to deserialize book metadata for {}: {}", id, e);
+                        None
+                    }
+                }
+            })
+            .collect())
+    }
+
+    /// Fetch the stored embeddings for the given vector ids.
+    ///
+    /// Ids that don't exist in the index are simply absent from the result.
+    pub async fn fetch_vectors(&self, ids: &[String]) -> Result<HashMap<String, Vec<f32>>> {
+        Ok(self
+            .fetch(ids)
+            .await?
+            .vectors
+            .into_iter()
+            .filter_map(|(id, vector)| vector.values.map(|values| (id, values)))
+            .collect())
+    }
+
+    async fn fetch(&self, ids: &[String]) -> Result<FetchResponse> {
+        self.ensure_initialized().await?;
+
+        if ids.is_empty() {
+            return Ok(FetchResponse {
+                vectors: HashMap::new(),
+            });
+        }
+
+        let host_string = recover_lock(self.host.read(), "pinecone host").clone();
+        let url = format!("{}/vectors/fetch", host_string);
+        let params: Vec<(&str, &str)> = ids.iter().map(|id| ("ids", id.as_str())).collect();
+
+        usage::record_pinecone_read();
+        let response = self
+            .client()
+            .get(&url)
+            .header("Api-Key", &self.api_key)
+            .header("X-Pinecone-API-Version", "2025-01")
+            .header("User-Agent", "recommend-a-book-rust-api/1.0")
+            .timeout(deadline::bounded(REQUEST_TIMEOUT))
+            .query(&params)
+            .send()
+            .await?;
+
+        let status = response.status();
+        if status.as_u16() == 429 || status.as_u16() == 503 {
+            let retry_after = retry_after_from_headers(response.headers());
+            return Err(ApiError::service_unavailable(
+                format!("Pinecone fetch throttled ({})", status),
+                retry_after,
+            ));
+        }
+        if !status.is_success() {
+            let text = response.text().await.unwrap_or_default();
+            return Err(ApiError::PineconeError(format!(
+                "Fetch failed with status {}: {}",
+                status, text
+            )));
+        }
+
+        response
+            .json()
+            .await
+            .map_err(|e| ApiError::PineconeError(format!("Response parsing failed: {}", e)))
+    }
+
+    /// Ids of the hidden books, up to the most one query can return
+    pub async fn hidden_ids(&self) -> Result<Vec<String>> {
+        // Cosine similarity is undefined for a zero vector, so query along one axis;
+        // the filter picks the books and the ranking doesn't matter
+        let mut vector = vec![0.0; self.dimension];
+        vector[0] = 1.0;
+        let request = QueryRequest {
+            vector,
+            top_k: MAX_ID_QUERY_TOP_K,
+            include_values: Some(false),
+            include_metadata: Some(false),
+            filter: Some(json!({ HIDDEN_FIELD: { "$eq": true } })),
+            namespace: None,
+            sparse_vector: None,
+        };
+
+        Ok(self
+            .send_query(&request)
+            .await?
+            .matches
+            .unwrap_or_default()
+            .into_iter()
+            .map(|m| m.id)
+            .collect())
+    }
+
+    /// Hide a book from every query, or restore it, keeping its vector
+    pub async fn set_hidden(&self, id: &str, hidden: bool) -> Result<()> {
+        self.update_metadata(id, &json!({ HIDDEN_FIELD: hidden }))
+            .await
+    }
+
     async fn execute_query(&self, request: QueryRequest) -> Result<Vec<crate::models::Book>> {
+        let mut query_result = self.send_query(&request).await?;
+
+        if let Some(matches) = &mut query_result.matches {
+            // A keyword query scores books sharing no word with it at zero
+            if request.sparse_vector.is_some() {
+                matches.retain(|m| m.score.is_some_and(|score| score > 0.0));
+            }
+            matches.retain(|m| !m.metadata.as_ref().is_some_and(is_hidden));
+        }
+
+        debug!(
+            "Pinecone query returned {} matches",
+            query_result.matches.as_ref().map_or(0, |m| m.len())
+        );
+
+        self.process_pinecone_results(query_result)
+    }
+
+    async fn send_query(&self, request: &QueryRequest) -> Result<QueryResponse> {
         // Double-check initialization before making the actual API call
         self.ensure_initialized().await?;
 
@@ -424,39 +1093,37 @@ impl Pinecone {
 
         loop {
             attempts += 1;
+            let delay = Duration::from_millis(100 * 2u64.pow(attempts - 1));
+            let may_retry = attempts < MAX_RETRIES && deadline::allows_retry(delay);
 
+            deadline::check("Pinecone")?;
+            usage::record_pinecone_read();
             let response = self
-                .client
+                .client()
                 .post(&url)
                 .header("Api-Key", &self.api_key)
                 .header("Content-Type", "application/json")
                 .header("X-Pinecone-API-Version", "2025-01")
                 .header("User-Agent", "recommend-a-book-rust-api/1.0")
-                .json(&request)
+                .timeout(deadline::bounded(REQUEST_TIMEOUT))
+                .json(request)
                 .send()
                 .await;
 
             match response {
                 Ok(resp) if resp.status().is_success() => {
-                    let query_result: QueryResponse = resp.json().await.map_err(|e| {
+                    return resp.json().await.map_err(|e| {
                         error!("Failed to parse Pinecone response: {}", e);
                         ApiError::PineconeError(format!("Response parsing failed: {}", e))
-                    })?;
-
-                    debug!(
-                        "Pinecone query returned {} matches",
-                        query_result.matches.as_ref().map_or(0, |m| m.len())
-                    );
-
-                    return self.process_pinecone_results(query_result);
+                    });
                 }
                 Ok(resp) => {
                     let status = resp.status();
+                    let retry_after = retry_after_from_headers(resp.headers());
                     let text = resp.text().await.unwrap_or_default();
 
-                    if status.as_u16() >= 500 && attempts < MAX_RETRIES {
+                    if status.as_u16() >= 500 && may_retry {
                         // Retry on server errors
-                        let delay = std::time::Duration::from_millis(100 * 2u64.pow(attempts - 1));
                         debug!(
                             "Retrying Pinecone request in {:?} (attempt {}/{})",
                             delay, attempts, MAX_RETRIES
@@ -467,6 +1134,14 @@ impl Pinecone {
 
                     error!("Pinecone API error: {} - {}", status, text);
 
+                    // Throttling or overload: tell the caller when it is worth trying again
+                    if status.as_u16() == 429 || status.as_u16() == 503 {
+                        return Err(ApiError::service_unavailable(
+                            format!("Pinecone is throttling requests ({})", status),
+                            retry_after,
+                        ));
+                    }
+
                     // Check for specific errors that might indicate configuration issues
                     if text.contains("unauthorized")
                         || text.contains("forbidden")
@@ -483,9 +1158,8 @@ impl Pinecone {
                         status, text
                     )));
                 }
-                Err(e) if attempts < MAX_RETRIES => {
+                Err(e) if may_retry => {
                     // Retry on network errors
-                    let delay = std::time::Duration::from_millis(100 * 2u64.pow(attempts - 1));
                     debug!(
                         "Retrying Pinecone request after network error in {:?} (attempt {}/{}): {}",
                         delay, attempts, MAX_RETRIES, e
@@ -496,7 +1170,7 @@ impl Pinecone {
                 Err(e) => {
                     error!(
                         "Failed to send request to Pinecone after {} attempts: {}",
-                        MAX_RETRIES, e
+                        attempts, e
                     );
                     // Provide more helpful error for DNS issues
                     if e.to_string().contains("dns error")
@@ -580,6 +1254,12 @@ impl Pinecone {
                                 .and_then(|v| v.as_str())
                                 .map(|s| vec![s.to_string()])
                                 .unwrap_or_else(|| vec!["Unknown".to_string()]),
+                            genres: vec![],
+                            moods: vec![],
+                            awards: vec![],
+                            settings: vec![],
+                            content_flags: vec![],
+                            available_regions: vec![],
                             thumbnail: metadata_map
                                 .get("thumbnail")
                                 .and_then(|v| v.as_str())
@@ -611,8 +1291,11 @@ impl Pinecone {
                                 .get("publisher")
                                 .and_then(|v| v.as_str())
                                 .map(|s| s.to_string()),
+                            version: None,
+                            updated_at: None,
                             relevance_indicators: vec![],
                             confidence_score: 0.0,
+                            explanation: None,
                         };
 
                         debug!("Created minimal book fallback for ID: {}", match_.id);
@@ -629,6 +1312,12 @@ impl Pinecone {
                     author: Some("Unknown Author".to_string()),
                     description: None,
                     categories: vec!["Unknown".to_string()],
+                    genres: vec![],
+                    moods: vec![],
+                    awards: vec![],
+                    settings: vec![],
+                    content_flags: vec![],
+                    available_regions: vec![],
                     thumbnail: None,
                     rating: 0.0,
                     year: None,
@@ -637,8 +1326,11 @@ impl Pinecone {
                     ratings_count: None,
                     language: None,
                     publisher: None,
+                    version: None,
+                    updated_at: None,
                     relevance_indicators: vec![],
                     confidence_score: 0.0,
+                    explanation: None,
                 };
 
                 debug!("Created minimal book fallback for ID: {}", match_.id);
@@ -660,3 +1352,49 @@ impl Pinecone {
         Ok(books)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_a_true_flag_hides_a_book() {
+        assert!(is_hidden(&json!({ "title": "Dune", "hidden": true })));
+        assert!(!is_hidden(&json!({ "title": "Dune", "hidden": false })));
+        assert!(!is_hidden(&json!({ "title": "Dune" })));
+        assert!(!is_hidden(&json!({ "hidden": "true" })));
+    }
+
+    #[tokio::test]
+    async fn test_hidden_ids_query_the_hidden_flag_with_a_unit_vector() {
+        use wiremock::matchers::{body_partial_json, method, path};
+        use wiremock::{Mock, MockServer, Request, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/query"))
+            .and(body_partial_json(json!({
+                "filter": { HIDDEN_FIELD: { "$eq": true } },
+                "includeMetadata": false,
+            })))
+            .and(|request: &Request| {
+                let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+                let norm: f64 = body["vector"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|value| value.as_f64().unwrap().powi(2))
+                    .sum();
+                norm == 1.0
+            })
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "matches": [{ "id": "dune" }, { "id": "emma" }]
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let pinecone = Pinecone::with_host("test-api-key-0123456789", &server.uri()).unwrap();
+        assert_eq!(pinecone.hidden_ids().await.unwrap(), vec!["dune", "emma"]);
+    }
+}
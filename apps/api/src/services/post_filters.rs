@@ -0,0 +1,360 @@
+//! Business rules applied to recommendations after ranking
+//!
+//! A [`PostFilter`] decides whether a ranked book may be shown; the filters of a
+//! [`PostFilterChain`] run in order and a book has to pass all of them. The
+//! chain comes from the `post_filters` of the tenant's `[[tenants]]` table, or
+//! from the `[post_filters]` table for requests without a tenant and tenants
+//! without their own. Integrators add their own rules by implementing the trait
+//! and registering the filter with [`PostFilters::with_filter`], without
+//! touching the ranking.
+
+use crate::ingest::authors::author_key;
+use crate::models::Book;
+use crate::services::usage::TenantConfig;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+/// Who a request comes from, for deciding which books it may see
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FilterContext<'a> {
+    /// Tenant of the request's X-Api-Key
+    pub tenant: Option<&'a str>,
+}
+
+/// A rule deciding which ranked books a request may be shown
+pub trait PostFilter: Send + Sync {
+    /// Name the filter is reported by, e.g. when explaining a missing book
+    fn name(&self) -> &str;
+
+    /// Whether `book` may be shown to a request from `context`
+    fn allows(&self, book: &Book, context: &FilterContext) -> bool;
+}
+
+/// Lowercased and trimmed, without empty terms
+fn normalized(terms: &[String]) -> Vec<String> {
+    terms
+        .iter()
+        .map(|term| term.trim().to_lowercase())
+        .filter(|term| !term.is_empty())
+        .collect()
+}
+
+/// Leaves out books with content flags unsuitable for young readers
+pub struct AgeAppropriate {
+    /// Flags to leave out; empty leaves out every flagged book
+    flags: Vec<String>,
+}
+
+impl AgeAppropriate {
+    pub fn new(flags: &[String]) -> Self {
+        Self {
+            flags: normalized(flags),
+        }
+    }
+}
+
+impl PostFilter for AgeAppropriate {
+    fn name(&self) -> &str {
+        "age_appropriate"
+    }
+
+    fn allows(&self, book: &Book, _context: &FilterContext) -> bool {
+        if self.flags.is_empty() {
+            return book.content_flags.is_empty();
+        }
+        !book
+            .content_flags
+            .iter()
+            .any(|flag| self.flags.contains(&flag.to_lowercase()))
+    }
+}
+
+/// Keeps only books from publishers whose titles are licensed; books without a
+/// known publisher are left out
+pub struct LicensedPublishers {
+    publishers: Vec<String>,
+}
+
+impl LicensedPublishers {
+    pub fn new(publishers: &[String]) -> Self {
+        Self {
+            publishers: normalized(publishers),
+        }
+    }
+}
+
+impl PostFilter for LicensedPublishers {
+    fn name(&self) -> &str {
+        "licensed_publishers"
+    }
+
+    fn allows(&self, book: &Book, _context: &FilterContext) -> bool {
+        let Some(publisher) = &book.publisher else {
+            return false;
+        };
+        let publisher = publisher.to_lowercase();
+        self.publishers
+            .iter()
+            .any(|licensed| publisher.contains(licensed.as_str()))
+    }
+}
+
+/// Leaves out blocked books, authors and genres
+pub struct Blocklist {
+    book_ids: HashSet<String>,
+    authors: Vec<String>,
+    genres: Vec<String>,
+}
+
+impl Blocklist {
+    pub fn new(book_ids: &[String], authors: &[String], genres: &[String]) -> Self {
+        Self {
+            book_ids: book_ids.iter().map(|id| id.trim().to_string()).collect(),
+            authors: authors
+                .iter()
+                .map(|author| author_key(author))
+                .filter(|key| !key.is_empty())
+                .collect(),
+            genres: normalized(genres),
+        }
+    }
+}
+
+impl PostFilter for Blocklist {
+    fn name(&self) -> &str {
+        "blocklist"
+    }
+
+    fn allows(&self, book: &Book, _context: &FilterContext) -> bool {
+        if book
+            .id
+            .as_ref()
+            .is_some_and(|id| self.book_ids.contains(id))
+        {
+            return false;
+        }
+        if book
+            .author
+            .as_deref()
+            .is_some_and(|author| self.authors.contains(&author_key(author)))
+        {
+            return false;
+        }
+        !book
+            .genres
+            .iter()
+            .chain(&book.categories)
+            .map(|genre| genre.to_lowercase())
+            .any(|genre| self.genres.iter().any(|blocked| genre.contains(blocked)))
+    }
+}
+
+/// The built-in filters of a tenant or of the `[post_filters]` table
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PostFilterConfig {
+    /// Leave out books with the `age_blocked_flags` content flags
+    pub age_appropriate: bool,
+    /// Content flags `age_appropriate` leaves out; empty leaves out any flag
+    pub age_blocked_flags: Vec<String>,
+    /// Only recommend books from these publishers, matched case-insensitively as
+    /// part of the publisher's name; empty allows any
+    pub licensed_publishers: Vec<String>,
+    pub blocked_book_ids: Vec<String>,
+    pub blocked_authors: Vec<String>,
+    /// Matched case-insensitively as part of a book's genres and categories
+    pub blocked_genres: Vec<String>,
+}
+
+impl PostFilterConfig {
+    /// The filters this configures, in the order they run
+    pub fn chain(&self) -> PostFilterChain {
+        let mut chain = PostFilterChain::default();
+        if self.age_appropriate {
+            chain.push(Arc::new(AgeAppropriate::new(&self.age_blocked_flags)));
+        }
+        if !self.licensed_publishers.is_empty() {
+            chain.push(Arc::new(LicensedPublishers::new(&self.licensed_publishers)));
+        }
+        if !self.blocked_book_ids.is_empty()
+            || !self.blocked_authors.is_empty()
+            || !self.blocked_genres.is_empty()
+        {
+            chain.push(Arc::new(Blocklist::new(
+                &self.blocked_book_ids,
+                &self.blocked_authors,
+                &self.blocked_genres,
+            )));
+        }
+        chain
+    }
+}
+
+/// Filters a book has to pass, in order
+#[derive(Clone, Default)]
+pub struct PostFilterChain {
+    filters: Vec<Arc<dyn PostFilter>>,
+}
+
+impl PostFilterChain {
+    pub fn push(&mut self, filter: Arc<dyn PostFilter>) {
+        self.filters.push(filter);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty()
+    }
+
+    /// Name of the first filter that leaves `book` out, if any does
+    pub fn rejecting(&self, book: &Book, context: &FilterContext) -> Option<&str> {
+        self.filters
+            .iter()
+            .find(|filter| !filter.allows(book, context))
+            .map(|filter| filter.name())
+    }
+
+    pub fn allows(&self, book: &Book, context: &FilterContext) -> bool {
+        self.rejecting(book, context).is_none()
+    }
+}
+
+/// The post-filter chain of each tenant, and the one of everyone else
+#[derive(Clone, Default)]
+pub struct PostFilters {
+    default: PostFilterChain,
+    tenants: HashMap<String, PostFilterChain>,
+}
+
+impl PostFilters {
+    /// Chains of the `[post_filters]` table and of the tenants with their own `post_filters`
+    pub fn new(default: &PostFilterConfig, tenants: &[TenantConfig]) -> Self {
+        Self {
+            default: default.chain(),
+            tenants: tenants
+                .iter()
+                .filter_map(|tenant| {
+                    let config = tenant.post_filters.as_ref()?;
+                    Some((tenant.name.clone(), config.chain()))
+                })
+                .collect(),
+        }
+    }
+
+    /// Also run `filter` for `tenant`, or for requests the default chain applies
+    /// to when `tenant` is None
+    pub fn with_filter(mut self, tenant: Option<&str>, filter: Arc<dyn PostFilter>) -> Self {
+        match tenant {
+            Some(tenant) => self
+                .tenants
+                .entry(tenant.to_string())
+                .or_insert_with(|| self.default.clone())
+                .push(filter),
+            None => self.default.push(filter),
+        }
+        self
+    }
+
+    /// Chain of the requests from `tenant`
+    pub fn chain(&self, tenant: Option<&str>) -> &PostFilterChain {
+        tenant
+            .and_then(|tenant| self.tenants.get(tenant))
+            .unwrap_or(&self.default)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book(id: &str, author: &str, publisher: &str, content_flags: &[&str]) -> Book {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "title": id,
+            "author": author,
+            "publisher": publisher,
+            "rating": 4.0,
+            "categories": ["Fantasy"],
+            "content_flags": content_flags,
+        }))
+        .expect("valid book")
+    }
+
+    #[test]
+    fn test_built_in_filters_leave_out_flagged_unlicensed_and_blocked_books() {
+        let chain = PostFilterConfig {
+            age_appropriate: true,
+            age_blocked_flags: vec!["Violence".to_string()],
+            licensed_publishers: vec!["penguin".to_string()],
+            blocked_authors: vec!["R. Author".to_string()],
+            ..PostFilterConfig::default()
+        }
+        .chain();
+        let context = FilterContext::default();
+        let rejecting = |book: &Book| chain.rejecting(book, &context);
+
+        assert_eq!(rejecting(&book("a", "Someone", "Penguin Books", &[])), None);
+        assert_eq!(
+            rejecting(&book("a", "Someone", "Penguin Books", &["romance"])),
+            None
+        );
+        assert_eq!(
+            rejecting(&book("b", "Someone", "Penguin Books", &["violence"])),
+            Some("age_appropriate")
+        );
+        assert_eq!(
+            rejecting(&book("c", "Someone", "Tor", &[])),
+            Some("licensed_publishers")
+        );
+        assert_eq!(
+            rejecting(&book("d", "R Author", "Penguin", &[])),
+            Some("blocklist")
+        );
+    }
+
+    struct NoBook;
+
+    impl PostFilter for NoBook {
+        fn name(&self) -> &str {
+            "no_book"
+        }
+
+        fn allows(&self, _book: &Book, context: &FilterContext) -> bool {
+            context.tenant.is_none()
+        }
+    }
+
+    #[test]
+    fn test_tenants_get_their_own_chain_and_registered_filters() {
+        let tenant = |name: &str, post_filters| TenantConfig {
+            name: name.to_string(),
+            api_key: format!("key-{}", name),
+            quota: Default::default(),
+            post_filters,
+        };
+        let filters = PostFilters::new(
+            &PostFilterConfig {
+                blocked_book_ids: vec!["a".to_string()],
+                ..PostFilterConfig::default()
+            },
+            &[
+                tenant("acme", Some(PostFilterConfig::default())),
+                tenant("globex", None),
+            ],
+        )
+        .with_filter(Some("initech"), Arc::new(NoBook));
+        let allows = |tenant: Option<&str>, id: &str| {
+            filters
+                .chain(tenant)
+                .allows(&book(id, "Someone", "Tor", &[]), &FilterContext { tenant })
+        };
+
+        assert!(!allows(None, "a"));
+        assert!(!allows(Some("globex"), "a"));
+        assert!(allows(Some("acme"), "a"));
+        // Registered filters extend the default chain for their tenant
+        assert!(!allows(Some("initech"), "a"));
+        assert!(!allows(Some("initech"), "b"));
+        assert!(allows(None, "b"));
+    }
+}
@@ -0,0 +1,235 @@
+//! Scheduled cache warming
+//!
+//! Startup prewarming fills the caches once, but cached results expire and a
+//! serverless instance that sits idle is shut down (Render's free tier stops
+//! instances after 15 minutes without inbound traffic). The scheduler re-runs
+//! the warmup queries on an interval, and once more shortly before the idle
+//! timeout. With a keepalive URL configured it also requests the API's own
+//! health endpoint at that point, which counts as inbound traffic and keeps
+//! the instance, and its warm caches, running. After each warmup, the
+//! scheduler also refreshes the preloaded top queries once the server has
+//! been quiet for a minute.
+
+use crate::services::{query_preloader::QueryPreloader, RecommendationService};
+use recommend_a_book_client::{Client, RetryPolicy};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+/// Queries run to warm the caches when none are configured
+pub const DEFAULT_WARMUP_QUERIES: [&str; 3] =
+    ["fantasy books", "science fiction", "mystery novels"];
+
+/// Default interval between scheduled warmups
+pub const DEFAULT_PREWARM_INTERVAL_MINUTES: u64 = 30;
+
+/// Default inactivity after which the hosting platform stops the instance
+pub const DEFAULT_IDLE_TIMEOUT_MINUTES: u64 = 15;
+
+/// How long before the idle timeout the idle warmup runs
+const IDLE_WARMUP_LEAD: Duration = Duration::from_secs(2 * 60);
+
+/// How often the scheduler checks whether a warmup is due
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Quiet time before top queries are preloaded, so preloading doesn't compete with requests
+const PRELOAD_MIN_IDLE: Duration = Duration::from_secs(60);
+
+/// Time of the last inbound request, shared between the server and the scheduler
+#[derive(Clone)]
+pub struct ActivityTracker {
+    started: Instant,
+    /// Milliseconds after `started`
+    last_request_ms: Arc<AtomicU64>,
+}
+
+impl ActivityTracker {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            last_request_ms: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Record an inbound request
+    pub fn touch(&self) {
+        self.last_request_ms
+            .store(self.started.elapsed().as_millis() as u64, Ordering::Relaxed);
+    }
+
+    /// Time since the last inbound request, or since startup if there was none
+    pub fn idle(&self) -> Duration {
+        let last = Duration::from_millis(self.last_request_ms.load(Ordering::Relaxed));
+        self.started.elapsed().saturating_sub(last)
+    }
+}
+
+impl Default for ActivityTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Why a scheduled warmup runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WarmupReason {
+    Interval,
+    IdleTimeout,
+}
+
+/// When scheduled warmups run; a zero interval or timeout disables that trigger
+#[derive(Debug, Clone, Copy)]
+pub struct PrewarmSchedule {
+    pub interval: Option<Duration>,
+    pub idle_timeout: Option<Duration>,
+}
+
+impl PrewarmSchedule {
+    pub fn new(interval_minutes: u64, idle_timeout_minutes: u64) -> Self {
+        let minutes = |m: u64| (m > 0).then(|| Duration::from_secs(m * 60));
+        Self {
+            interval: minutes(interval_minutes),
+            idle_timeout: minutes(idle_timeout_minutes),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.interval.is_some() || self.idle_timeout.is_some()
+    }
+
+    /// Whether a warmup is due, given the time since the last one and since the last request
+    ///
+    /// The idle warmup runs once per idle stretch: not again until a request
+    /// comes in after it.
+    pub fn due(&self, since_warmup: Duration, idle: Duration) -> Option<WarmupReason> {
+        if let Some(timeout) = self.idle_timeout {
+            if idle >= timeout.saturating_sub(IDLE_WARMUP_LEAD) && since_warmup >= idle {
+                return Some(WarmupReason::IdleTimeout);
+            }
+        }
+        match self.interval {
+            Some(interval) if since_warmup >= interval => Some(WarmupReason::Interval),
+            _ => None,
+        }
+    }
+}
+
+/// Background task that re-runs the warmup queries on a [`PrewarmSchedule`]
+pub struct PrewarmScheduler {
+    service: Arc<RecommendationService>,
+    schedule: PrewarmSchedule,
+    activity: ActivityTracker,
+    /// Client for this API, whose health endpoint is requested before the idle timeout
+    keepalive: Option<Client>,
+    preloader: Option<QueryPreloader>,
+}
+
+impl PrewarmScheduler {
+    pub fn new(
+        service: Arc<RecommendationService>,
+        schedule: PrewarmSchedule,
+        activity: ActivityTracker,
+    ) -> Self {
+        Self {
+            service,
+            schedule,
+            activity,
+            keepalive: None,
+            preloader: None,
+        }
+    }
+
+    /// Request `<url>/api/health` before the idle timeout so the instance isn't stopped
+    pub fn with_keepalive_url(mut self, url: String) -> Self {
+        // The next keepalive is due well before the timeout, so one attempt is enough
+        self.keepalive = Some(Client::new(url).with_retry_policy(RetryPolicy::none()));
+        self
+    }
+
+    /// Refresh the preloaded top queries after each warmup
+    pub fn with_preloader(mut self, preloader: QueryPreloader) -> Self {
+        self.preloader = Some(preloader).filter(QueryPreloader::is_enabled);
+        self
+    }
+
+    /// Warm on schedule until the process exits
+    pub async fn run(self) {
+        if !self.schedule.is_enabled() {
+            info!("Scheduled prewarming is disabled");
+            return;
+        }
+        info!(
+            "Scheduled prewarming every {:?}, and before an idle timeout of {:?}",
+            self.schedule.interval, self.schedule.idle_timeout
+        );
+
+        // Startup prewarming counts as the first warmup
+        let mut last_warmup = Instant::now();
+        let mut preload_pending = self.preloader.is_some();
+        let mut ticker = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            ticker.tick().await;
+            if let Some(preloader) = &self.preloader {
+                if preload_pending && self.activity.idle() >= PRELOAD_MIN_IDLE {
+                    preloader.preload().await;
+                    preload_pending = false;
+                }
+            }
+
+            let Some(reason) = self
+                .schedule
+                .due(last_warmup.elapsed(), self.activity.idle())
+            else {
+                continue;
+            };
+
+            info!("Re-warming caches ({:?})", reason);
+            let warmed = self.service.warm_caches().await;
+            debug!("Warmed {} queries", warmed);
+            if reason == WarmupReason::IdleTimeout {
+                self.keep_alive().await;
+            }
+            last_warmup = Instant::now();
+            preload_pending = self.preloader.is_some();
+        }
+    }
+
+    async fn keep_alive(&self) {
+        let Some(client) = &self.keepalive else {
+            return;
+        };
+        match client.health().await {
+            Ok(_) => debug!("Keepalive request to {} succeeded", client.base_url()),
+            Err(e) => warn!("Keepalive request to {} failed: {}", client.base_url(), e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn test_idle_warmup_runs_once_before_the_timeout() {
+        let schedule = PrewarmSchedule::new(30, 15);
+
+        assert_eq!(schedule.due(5 * MINUTE, 5 * MINUTE), None);
+        assert_eq!(
+            schedule.due(20 * MINUTE, 13 * MINUTE),
+            Some(WarmupReason::IdleTimeout)
+        );
+        // Already warmed since the last request
+        assert_eq!(schedule.due(MINUTE, 14 * MINUTE), None);
+        assert_eq!(
+            schedule.due(30 * MINUTE, MINUTE),
+            Some(WarmupReason::Interval)
+        );
+
+        let disabled = PrewarmSchedule::new(0, 0);
+        assert!(!disabled.is_enabled());
+        assert_eq!(disabled.due(60 * MINUTE, 60 * MINUTE), None);
+    }
+}
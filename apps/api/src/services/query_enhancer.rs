@@ -1,39 +1,33 @@
+use crate::cache::{CacheStats, TtlCache};
 use crate::services::templates::{EnhancedQuery, QueryPattern};
-use std::collections::HashMap;
-use std::sync::{Arc, RwLock};
-use std::time::{Duration, Instant};
+use std::time::Duration;
 use tracing::{debug, info};
 
-/// Cache entry for enhanced queries
-struct CacheEntry {
-    enhanced_query: EnhancedQuery,
-    timestamp: Instant,
-}
+/// Most enhanced queries kept in the cache
+const CACHE_CAPACITY: usize = 1000;
 
 /// Service for enhancing user queries using template-based approach
 #[derive(Clone)]
 pub struct QueryEnhancer {
     /// Cache for enhanced queries to avoid repeated processing
-    cache: Arc<RwLock<HashMap<String, CacheEntry>>>,
-    /// Cache TTL in seconds
-    cache_ttl: Duration,
+    cache: TtlCache<String, EnhancedQuery>,
 }
 
 #[allow(dead_code)]
 impl QueryEnhancer {
     /// Create a new QueryEnhancer with default settings
     pub fn new() -> Self {
-        Self {
-            cache: Arc::new(RwLock::new(HashMap::new())),
-            cache_ttl: Duration::from_secs(3600), // 1 hour cache
-        }
+        Self::with_ttl(3600) // 1 hour cache
     }
 
     /// Create a new QueryEnhancer with custom cache TTL
     pub fn with_ttl(cache_ttl_seconds: u64) -> Self {
         Self {
-            cache: Arc::new(RwLock::new(HashMap::new())),
-            cache_ttl: Duration::from_secs(cache_ttl_seconds),
+            cache: TtlCache::new(
+                "query_enhancements",
+                CACHE_CAPACITY,
+                Duration::from_secs(cache_ttl_seconds),
+            ),
         }
     }
 
@@ -49,13 +43,9 @@ impl QueryEnhancer {
         let query_trimmed = query.trim();
 
         // Check cache first
-        if let Ok(cache) = self.cache.read() {
-            if let Some(entry) = cache.get(query_trimmed) {
-                if entry.timestamp.elapsed() < self.cache_ttl {
-                    debug!("Cache HIT for query enhancement: '{}'", query_trimmed);
-                    return entry.enhanced_query.clone();
-                }
-            }
+        if let Some(enhanced_query) = self.cache.get(&query_trimmed.to_string()) {
+            debug!("Cache HIT for query enhancement: '{}'", query_trimmed);
+            return enhanced_query;
         }
 
         info!(
@@ -70,20 +60,8 @@ impl QueryEnhancer {
         self.log_enhancement(&enhanced_query);
 
         // Update cache
-        if let Ok(mut cache) = self.cache.write() {
-            cache.insert(
-                query_trimmed.to_string(),
-                CacheEntry {
-                    enhanced_query: enhanced_query.clone(),
-                    timestamp: Instant::now(),
-                },
-            );
-
-            // Cleanup old entries if cache is too large
-            if cache.len() > 1000 {
-                self.cleanup_cache(&mut cache);
-            }
-        }
+        self.cache
+            .insert(query_trimmed.to_string(), enhanced_query.clone());
 
         enhanced_query
     }
@@ -118,49 +96,15 @@ impl QueryEnhancer {
         );
     }
 
-    /// Clean up expired cache entries
-    fn cleanup_cache(&self, cache: &mut HashMap<String, CacheEntry>) {
-        let expired_keys: Vec<String> = cache
-            .iter()
-            .filter(|(_, entry)| entry.timestamp.elapsed() > self.cache_ttl)
-            .map(|(k, _)| k.clone())
-            .collect();
-
-        for key in expired_keys {
-            cache.remove(&key);
-        }
-
-        info!(
-            "Cleaned up query enhancement cache, remaining entries: {}",
-            cache.len()
-        );
-    }
-
     /// Get cache statistics for monitoring
-    pub fn cache_stats(&self) -> Option<CacheStats> {
-        if let Ok(cache) = self.cache.read() {
-            let _now = Instant::now();
-            let valid_entries = cache
-                .values()
-                .filter(|entry| entry.timestamp.elapsed() < self.cache_ttl)
-                .count();
-
-            Some(CacheStats {
-                total_entries: cache.len(),
-                valid_entries,
-                expired_entries: cache.len() - valid_entries,
-            })
-        } else {
-            None
-        }
+    pub fn cache_stats(&self) -> CacheStats {
+        self.cache.stats()
     }
 
     /// Clear the cache (useful for testing or manual cache management)
     pub fn clear_cache(&self) {
-        if let Ok(mut cache) = self.cache.write() {
-            cache.clear();
-            info!("Query enhancement cache cleared");
-        }
+        self.cache.clear();
+        info!("Query enhancement cache cleared");
     }
 
     /// Get pattern type for a query without full enhancement (lightweight)
@@ -176,15 +120,6 @@ impl Default for QueryEnhancer {
     }
 }
 
-/// Statistics about the query enhancement cache
-#[derive(Debug, Clone)]
-#[allow(dead_code)]
-pub struct CacheStats {
-    pub total_entries: usize,
-    pub valid_entries: usize,
-    pub expired_entries: usize,
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -224,6 +159,47 @@ mod tests {
         assert_eq!(result1.pattern, result2.pattern);
     }
 
+    #[test]
+    fn test_signature_ignores_word_order() {
+        let enhancer = QueryEnhancer::new();
+        let signature = |query: &str| enhancer.enhance(query).signature();
+
+        assert!(signature("best fantasy books").is_some());
+        assert_eq!(
+            signature("best fantasy books"),
+            signature("Fantasy books, best")
+        );
+        assert_ne!(
+            signature("best fantasy books"),
+            signature("best sci-fi books")
+        );
+        assert_eq!(signature("fantasy without romance"), None);
+    }
+
+    #[test]
+    fn test_signature_keeps_modifiers_with_their_words() {
+        let enhancer = QueryEnhancer::new();
+        let signature = |query: &str| enhancer.enhance(query).signature();
+
+        // Neither gets a signature, so they can't share a cached result
+        assert_eq!(signature("dark fantasy with light romance"), None);
+        assert_eq!(signature("light fantasy with dark romance"), None);
+        assert_eq!(signature("dark fantasy and light romance"), None);
+    }
+
+    #[test]
+    fn test_signature_keeps_names_with_their_words() {
+        let enhancer = QueryEnhancer::new();
+        let signature = |query: &str| enhancer.enhance(query).signature();
+
+        // The author and the comparison trade places, so neither shares a cached result
+        assert_eq!(signature("books by Gaiman like Pratchett"), None);
+        assert_eq!(signature("books like Gaiman by Pratchett"), None);
+        assert_eq!(signature("novels by Murakami"), None);
+        assert_eq!(signature("mysteries from Japan"), None);
+        assert!(signature("Murakami novels").is_some());
+    }
+
     #[test]
     fn test_theme_extraction() {
         let enhancer = QueryEnhancer::new();
@@ -0,0 +1,215 @@
+//! Embedding-based query expansion
+//!
+//! Queries like "liminal spaces" use words the book descriptions rarely do, and
+//! that the genre, theme and mood vocabulary the ranking recognizes doesn't
+//! know. For such sparse queries the vocabulary phrases closest to the query
+//! embedding are looked up in a table of their embeddings, built once when the
+//! service warms up, and the query is searched again halfway between itself and
+//! each of up to two phrases. Those results are blended into the original ones,
+//! so books described in the vocabulary's terms can come up.
+
+use crate::error::{ApiError, Result};
+use crate::ingest::moods::{mood_named, MOODS};
+use crate::ml::embedder::Embedder;
+use crate::models::Book;
+use crate::services::personalization::cosine_similarity;
+use crate::services::templates::{
+    is_keyword_word, keyword_phrases, EnhancedQuery, QueryPattern, STOP_WORDS,
+};
+use std::collections::{BTreeSet, HashSet};
+use std::sync::Arc;
+use tokio::sync::OnceCell;
+use tracing::info;
+
+/// Most expanded phrasings searched for a query
+const MAX_EXPANSIONS: usize = 2;
+
+/// Least similarity of a vocabulary phrase to the query to expand it with
+const MIN_EXPANSION_SIMILARITY: f32 = 0.4;
+
+/// Most content words of a query that counts as sparse
+const MAX_SPARSE_WORDS: usize = 4;
+
+/// Original results kept in a row before an expanded result is blended in
+const BLEND_STRIDE: usize = 3;
+
+/// Genre, theme and mood phrases the ranking recognizes, sorted and without repeats
+fn vocabulary() -> Vec<&'static str> {
+    let phrases: BTreeSet<&'static str> = keyword_phrases()
+        .into_iter()
+        .chain(MOODS.iter().map(|mood| mood.name))
+        .collect();
+    phrases.into_iter().collect()
+}
+
+/// Whether `word`, or its singular, is a stop word, a mood or in the vocabulary
+fn is_known(word: &str) -> bool {
+    let singular = word.strip_suffix('s').unwrap_or(word);
+    [word, singular]
+        .iter()
+        .any(|form| STOP_WORDS.contains(form) || is_keyword_word(form))
+        || mood_named(word).is_some()
+}
+
+/// Whether a query is sparse: a few content words, some of which the vocabulary
+/// doesn't know. Author and "similar to" queries name what they want instead
+pub fn is_sparse(enhanced: &EnhancedQuery) -> bool {
+    if matches!(
+        enhanced.pattern,
+        QueryPattern::Author | QueryPattern::SimilarTo
+    ) {
+        return false;
+    }
+    let query_lower = enhanced.original_query.to_lowercase();
+    let content_words: Vec<&str> = query_lower
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty() && !STOP_WORDS.contains(word))
+        .collect();
+    (1..=MAX_SPARSE_WORDS).contains(&content_words.len())
+        && content_words.iter().any(|word| !is_known(word))
+}
+
+/// Embeddings of the vocabulary phrases
+struct ExpansionTable {
+    phrases: Vec<(&'static str, Vec<f32>)>,
+}
+
+/// Finds vocabulary phrases close to a query, once their embeddings are loaded
+#[derive(Clone, Default)]
+pub struct QueryExpander {
+    table: Arc<OnceCell<ExpansionTable>>,
+}
+
+impl QueryExpander {
+    /// Embed the vocabulary with the default model, unless it already is
+    pub async fn load(&self, embedder: &dyn Embedder) -> Result<()> {
+        self.table
+            .get_or_try_init(|| async {
+                let phrases = vocabulary();
+                let texts: Vec<String> = phrases.iter().map(|phrase| phrase.to_string()).collect();
+                let embeddings = embedder.encode_batch(&texts).await?;
+                info!("Embedded {} query expansion phrases", phrases.len());
+                Ok::<_, ApiError>(ExpansionTable {
+                    phrases: phrases.into_iter().zip(embeddings).collect(),
+                })
+            })
+            .await?;
+        Ok(())
+    }
+
+    /// Up to two vocabulary phrases closest to the query, each with the
+    /// embedding halfway between it and the query; none before the table is loaded
+    pub fn expansions(&self, query: &str, embedding: &[f32]) -> Vec<(&'static str, Vec<f32>)> {
+        let Some(table) = self.table.get() else {
+            return Vec::new();
+        };
+        let query_lower = query.to_lowercase();
+        let query_words: HashSet<&str> = query_lower
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .collect();
+
+        let mut nearest: Vec<(&'static str, &Vec<f32>, f32)> = table
+            .phrases
+            .iter()
+            .filter(|(phrase, vector)| {
+                vector.len() == embedding.len()
+                    && !phrase
+                        .split(|c: char| !c.is_alphanumeric())
+                        .all(|word| word.is_empty() || query_words.contains(word))
+            })
+            .map(|(phrase, vector)| (*phrase, vector, cosine_similarity(embedding, vector)))
+            .filter(|(_, _, similarity)| *similarity >= MIN_EXPANSION_SIMILARITY)
+            .collect();
+        nearest.sort_by(|a, b| b.2.total_cmp(&a.2));
+        nearest
+            .into_iter()
+            .take(MAX_EXPANSIONS)
+            .map(|(phrase, vector, _)| (phrase, midpoint(embedding, vector)))
+            .collect()
+    }
+}
+
+/// Normalized vector halfway between `a` and `b`
+fn midpoint(a: &[f32], b: &[f32]) -> Vec<f32> {
+    let sum: Vec<f32> = a.iter().zip(b).map(|(x, y)| x + y).collect();
+    let norm = sum.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm == 0.0 {
+        return sum;
+    }
+    sum.into_iter().map(|x| x / norm).collect()
+}
+
+/// `results` with the results of the expanded phrasings mixed in, one after
+/// every few original results, taking from each phrasing in turn
+pub fn blend(results: Vec<Book>, expansions: Vec<Vec<Book>>) -> Vec<Book> {
+    let mut seen: HashSet<Option<String>> = results.iter().map(|book| book.id.clone()).collect();
+    let mut expanded: Vec<std::vec::IntoIter<Book>> =
+        expansions.into_iter().map(Vec::into_iter).collect();
+    let mut turn = 0;
+    let mut next_expanded = |seen: &mut HashSet<Option<String>>| {
+        while !expanded.is_empty() {
+            let index = turn % expanded.len();
+            match expanded[index].next() {
+                Some(book) if seen.insert(book.id.clone()) => {
+                    turn += 1;
+                    return Some(book);
+                }
+                Some(_) => {}
+                None => {
+                    expanded.remove(index);
+                }
+            }
+        }
+        None
+    };
+
+    let mut blended = Vec::with_capacity(results.len());
+    for (index, book) in results.into_iter().enumerate() {
+        blended.push(book);
+        if (index + 1) % BLEND_STRIDE == 0 {
+            blended.extend(next_expanded(&mut seen));
+        }
+    }
+    while let Some(book) = next_expanded(&mut seen) {
+        blended.push(book);
+    }
+    blended
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::QueryEnhancer;
+
+    fn books(ids: &[&str]) -> Vec<Book> {
+        ids.iter()
+            .map(|id| {
+                serde_json::from_value(serde_json::json!({ "id": id, "categories": [] })).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_sparse_queries_get_expanded_results_blended_in() {
+        let enhancer = QueryEnhancer::new();
+        assert!(is_sparse(&enhancer.enhance("liminal spaces")));
+        assert!(!is_sparse(&enhancer.enhance("fantasy with dragons")));
+        assert!(!is_sparse(&enhancer.enhance("cozy reads")));
+        assert!(!is_sparse(&enhancer.enhance("books like dune")));
+
+        let blended: Vec<String> = blend(
+            books(&["a", "b", "c", "d", "e", "f"]),
+            vec![books(&["x", "a", "y"]), books(&["z"])],
+        )
+        .into_iter()
+        .filter_map(|book| book.id)
+        .collect();
+        assert_eq!(blended, ["a", "b", "c", "x", "d", "e", "f", "z", "y"]);
+
+        let expander = QueryExpander::default();
+        assert!(expander
+            .expansions("liminal spaces", &[1.0, 0.0])
+            .is_empty());
+    }
+}
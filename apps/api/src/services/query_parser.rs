@@ -0,0 +1,197 @@
+//! A free-text query's constraints, read into [`QueryFilters`]
+//!
+//! The query templates read the constraints the search applies. A language
+//! model, when one is configured and the request asks for it, reads the query
+//! too and fills in the constraints the templates found nothing for, so a
+//! client can show the reader what was understood before searching. Where both
+//! read a constraint the templates' reading stands, and the model's readings
+//! are cached per query for a day.
+
+use crate::cache::TtlCache;
+use crate::services::explanations::{ExplanationBackend, LlmBackend};
+use crate::services::templates::QueryFilters;
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+const PARSE_CACHE_SIZE: usize = 2000;
+const PARSE_CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Tokens the model may generate for the JSON object
+const MAX_PARSE_TOKENS: u32 = 200;
+
+/// Earliest and latest publication years kept from the model's reading
+const YEAR_RANGE: std::ops::RangeInclusive<i32> = 1000..=2100;
+
+/// Reads query constraints with a language model, when one is configured
+#[derive(Clone)]
+pub struct QueryParser {
+    llm: Option<Arc<LlmBackend>>,
+    cache: TtlCache<String, QueryFilters>,
+}
+
+impl Default for QueryParser {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+impl QueryParser {
+    pub fn new(llm: Option<Arc<LlmBackend>>) -> Self {
+        Self {
+            llm,
+            cache: TtlCache::new("query_parses", PARSE_CACHE_SIZE, PARSE_CACHE_TTL),
+        }
+    }
+
+    /// The model's reading of `query`; None without a model or when it fails
+    pub async fn llm_filters(&self, query: &str) -> Option<QueryFilters> {
+        let llm = self.llm.as_ref()?;
+        let key = query.trim().to_lowercase();
+        if let Some(filters) = self.cache.get(&key) {
+            return Some(filters);
+        }
+
+        let text = match llm.complete(prompt(query), MAX_PARSE_TOKENS).await {
+            Ok(text) => text,
+            Err(e) => {
+                warn!("Query parsing by {} failed: {}", llm.name(), e);
+                return None;
+            }
+        };
+        let Some(filters) = parse_filters(&text) else {
+            warn!(
+                "{} didn't return a JSON object of constraints for '{}'",
+                llm.name(),
+                query
+            );
+            return None;
+        };
+        debug!("{} read '{}' as {:?}", llm.name(), query, filters);
+        self.cache.insert(key, filters.clone());
+        Some(filters)
+    }
+}
+
+/// Prompt asking for the constraints of `query` as a JSON object
+fn prompt(query: &str) -> String {
+    format!(
+        "Read the constraints of this book search: \"{}\"\n\
+         Answer with only a JSON object with these keys, leaving out constraints the search doesn't state:\n\
+         author (string), genres (array of lowercase strings), themes (array of lowercase strings), \
+         min_rating (number from 0 to 5), min_pages and max_pages (integers), \
+         min_year and max_year (publication years), audience (\"children\" or \"young adult\"), \
+         settings (array of lowercase place names).\n\nJSON:",
+        query.trim()
+    )
+}
+
+/// The constraints in the first JSON object of `text`, without implausible values
+fn parse_filters(text: &str) -> Option<QueryFilters> {
+    let start = text.find('{')?;
+    let end = text.rfind('}')?;
+    let value: Value = serde_json::from_str(text.get(start..=end)?).ok()?;
+    let mut filters: QueryFilters = serde_json::from_value(value).ok()?;
+
+    let terms = |terms: Vec<String>| -> Vec<String> {
+        terms
+            .into_iter()
+            .map(|term| term.trim().to_lowercase())
+            .filter(|term| !term.is_empty())
+            .collect()
+    };
+    filters.author = filters
+        .author
+        .map(|author| author.trim().to_string())
+        .filter(|author| !author.is_empty());
+    filters.genres = terms(filters.genres);
+    filters.themes = terms(filters.themes);
+    filters.settings = terms(filters.settings);
+    filters.min_rating = filters
+        .min_rating
+        .filter(|rating| (0.0..=5.0).contains(rating));
+    filters.min_pages = filters.min_pages.filter(|pages| *pages > 0);
+    filters.max_pages = filters.max_pages.filter(|pages| *pages > 0);
+    filters.min_year = filters.min_year.filter(|year| YEAR_RANGE.contains(year));
+    filters.max_year = filters.max_year.filter(|year| YEAR_RANGE.contains(year));
+    filters.audience = filters
+        .audience
+        .map(|audience| audience.trim().to_lowercase())
+        .filter(|audience| matches!(audience.as_str(), "children" | "young adult"));
+    Some(filters)
+}
+
+/// The templates' constraints, with `llm`'s filling in the ones they have none for
+pub fn merge_filters(templates: QueryFilters, llm: QueryFilters) -> QueryFilters {
+    let list = |templates: Vec<String>, llm: Vec<String>| {
+        if templates.is_empty() {
+            llm
+        } else {
+            templates
+        }
+    };
+    // Page and year bounds are ranges; mixing one end from each reading would make up a range
+    let (min_pages, max_pages) = if templates.min_pages.is_some() || templates.max_pages.is_some() {
+        (templates.min_pages, templates.max_pages)
+    } else {
+        (llm.min_pages, llm.max_pages)
+    };
+    let (min_year, max_year) = if templates.min_year.is_some() || templates.max_year.is_some() {
+        (templates.min_year, templates.max_year)
+    } else {
+        (llm.min_year, llm.max_year)
+    };
+    QueryFilters {
+        author: templates.author.or(llm.author),
+        genres: list(templates.genres, llm.genres),
+        themes: list(templates.themes, llm.themes),
+        min_rating: templates.min_rating.or(llm.min_rating),
+        min_pages,
+        max_pages,
+        min_year,
+        max_year,
+        audience: templates.audience.or(llm.audience),
+        settings: list(templates.settings, llm.settings),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_model_output_is_read_and_cleaned() {
+        let filters = parse_filters(
+            "Sure! Here it is:\n```json\n{\"genres\": [\" Mystery \"], \"max_pages\": 250, \
+             \"min_year\": 1990, \"max_year\": 99999, \"audience\": \"Adults\"}\n```",
+        )
+        .unwrap();
+        assert_eq!(filters.genres, vec!["mystery"]);
+        assert_eq!(filters.max_pages, Some(250));
+        assert_eq!((filters.min_year, filters.max_year), (Some(1990), None));
+        assert_eq!(filters.audience, None);
+        assert!(parse_filters("no constraints").is_none());
+    }
+
+    #[test]
+    fn test_template_constraints_win_over_the_models() {
+        let templates = QueryFilters {
+            genres: vec!["fantasy".to_string()],
+            max_year: Some(2000),
+            ..QueryFilters::default()
+        };
+        let llm = QueryFilters {
+            genres: vec!["romance".to_string()],
+            min_year: Some(1980),
+            max_pages: Some(300),
+            settings: vec!["paris".to_string()],
+            ..QueryFilters::default()
+        };
+        let merged = merge_filters(templates, llm);
+        assert_eq!(merged.genres, vec!["fantasy"]);
+        assert_eq!((merged.min_year, merged.max_year), (None, Some(2000)));
+        assert_eq!(merged.max_pages, Some(300));
+        assert_eq!(merged.settings, vec!["paris"]);
+    }
+}
@@ -0,0 +1,205 @@
+//! Preloading of the most frequent recent queries
+//!
+//! Warmup queries are fixed in the config; the preloader instead asks the
+//! analytics events which queries users actually send, adds the queries in the
+//! slow query log, and computes their results ahead of time. Preloaded results
+//! stay cached longer than normal ones, and the prewarm scheduler refreshes them
+//! when the server is idle.
+
+use crate::error::recover_lock;
+use crate::services::{supabase::SupabaseClient, RecommendationService};
+use chrono::Utc;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+/// Default number of top queries to preload
+pub const DEFAULT_PRELOAD_QUERY_COUNT: usize = 20;
+
+/// Default window of analytics events the top queries are counted over
+pub const DEFAULT_PRELOAD_WINDOW_HOURS: u64 = 24;
+
+/// How long preloaded results stay cached; refreshed on every scheduled warmup
+const PRELOADED_RESULT_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// A query whose results were computed ahead of time
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct PreloadedQuery {
+    #[schema(example = "cozy fantasy with dragons")]
+    pub query: String,
+    /// Sessions that sent the query in the window, plus its slow query log entries
+    #[schema(example = 42)]
+    pub frequency: u64,
+    /// Number of cached results, if preloading succeeded
+    #[schema(example = 100)]
+    pub result_count: Option<usize>,
+    /// Why preloading failed
+    pub error: Option<String>,
+    /// When the results were computed, in RFC3339 format
+    #[schema(example = "2024-01-15T10:30:00Z")]
+    pub preloaded_at: String,
+}
+
+/// Computes and caches results for the most frequent recent queries
+#[derive(Clone)]
+pub struct QueryPreloader {
+    service: Arc<RecommendationService>,
+    supabase: SupabaseClient,
+    count: usize,
+    window: Duration,
+    /// Queries from the last run, most frequent first
+    preloaded: Arc<RwLock<Vec<PreloadedQuery>>>,
+    running: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl QueryPreloader {
+    pub fn new(
+        service: Arc<RecommendationService>,
+        supabase: SupabaseClient,
+        count: usize,
+        window_hours: u64,
+    ) -> Self {
+        Self {
+            service,
+            supabase,
+            count,
+            window: Duration::from_secs(window_hours * 60 * 60),
+            preloaded: Arc::new(RwLock::new(Vec::new())),
+            running: Arc::new(tokio::sync::Mutex::new(())),
+        }
+    }
+
+    /// Whether any queries are preloaded at all
+    pub fn is_enabled(&self) -> bool {
+        self.count > 0
+    }
+
+    /// Queries preloaded by the last run, most frequent first
+    pub fn preloaded(&self) -> Vec<PreloadedQuery> {
+        recover_lock(self.preloaded.read(), "preloaded queries").clone()
+    }
+
+    /// The most frequent recent queries, most frequent first
+    pub async fn top_queries(&self) -> Vec<(String, u64)> {
+        if !self.is_enabled() {
+            return Vec::new();
+        }
+
+        let since = Utc::now()
+            - chrono::Duration::from_std(self.window).unwrap_or_else(|_| chrono::Duration::days(1));
+        let analytics = match self.supabase.top_queries(since, self.count).await {
+            Ok(queries) => queries,
+            Err(e) => {
+                warn!("Failed to load top queries from analytics events: {}", e);
+                Vec::new()
+            }
+        };
+
+        let slow_queries: Vec<String> = self
+            .service
+            .slow_query_log()
+            .recent(usize::MAX)
+            .into_iter()
+            .map(|entry| entry.query)
+            .collect();
+
+        rank_queries(analytics, slow_queries, self.count)
+    }
+
+    /// Compute and cache results for the current top queries
+    ///
+    /// Returns how many were preloaded successfully. Runs one at a time.
+    pub async fn preload(&self) -> usize {
+        let _running = self.running.lock().await;
+        let queries = self.top_queries().await;
+        if queries.is_empty() {
+            return 0;
+        }
+
+        let mut preloaded = Vec::with_capacity(queries.len());
+        for (query, frequency) in queries {
+            let result = self
+                .service
+                .preload_query(&query, PRELOADED_RESULT_TTL)
+                .await;
+            if let Err(e) = &result {
+                warn!("Failed to preload query '{}': {}", query, e);
+            }
+            preloaded.push(PreloadedQuery {
+                query,
+                frequency,
+                result_count: result.as_ref().ok().copied(),
+                error: result.err().map(|e| e.to_string()),
+                preloaded_at: Utc::now().to_rfc3339(),
+            });
+        }
+
+        let succeeded = preloaded
+            .iter()
+            .filter(|query| query.error.is_none())
+            .count();
+        info!("Preloaded {} of {} top queries", succeeded, preloaded.len());
+        *recover_lock(self.preloaded.write(), "preloaded queries") = preloaded;
+        succeeded
+    }
+}
+
+/// Merge analytics counts with slow query log entries and keep the `limit` most frequent
+///
+/// Queries are trimmed; ones too short to be valid recommendation queries are dropped.
+pub fn rank_queries(
+    analytics: Vec<(String, u64)>,
+    slow_queries: Vec<String>,
+    limit: usize,
+) -> Vec<(String, u64)> {
+    let mut counts: HashMap<String, u64> = HashMap::new();
+    let queries = analytics
+        .into_iter()
+        .chain(slow_queries.into_iter().map(|query| (query, 1)));
+    for (query, count) in queries {
+        let query = query.trim();
+        if query.len() < 3 {
+            continue;
+        }
+        *counts.entry(query.to_string()).or_default() += count;
+    }
+
+    let mut ranked: Vec<(String, u64)> = counts.into_iter().collect();
+    ranked.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    ranked.truncate(limit);
+    ranked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slow_queries_add_to_analytics_counts() {
+        let ranked = rank_queries(
+            vec![
+                ("space opera".to_string(), 3),
+                ("cozy mystery".to_string(), 2),
+                ("ok".to_string(), 10),
+            ],
+            vec![
+                "cozy mystery ".to_string(),
+                "cozy mystery".to_string(),
+                "gothic horror".to_string(),
+            ],
+            3,
+        );
+
+        assert_eq!(
+            ranked,
+            vec![
+                ("cozy mystery".to_string(), 4),
+                ("space opera".to_string(), 3),
+                ("gothic horror".to_string(), 1),
+            ]
+        );
+    }
+}
@@ -0,0 +1,924 @@
+//! Ranking of search results
+//!
+//! Candidates come back from the vector search ordered by similarity; these
+//! functions re-order them by the query's intent, drop duplicates, and score and
+//! annotate the top results. They don't depend on any service, so benchmarks can
+//! run them on fixed candidates.
+
+use crate::ingest::authors::author_key;
+use crate::models::Book;
+use crate::services::semantic_classifier::{SemanticQueryInfo, MIN_INTENT_CONFIDENCE};
+use aho_corasick::AhoCorasick;
+use chrono::Datelike;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use tracing::{debug, info};
+use utoipa::ToSchema;
+
+/// Score a book's moods add for each mood the query asks for
+const MOOD_BOOST_PER_MATCH: f32 = 0.75;
+
+/// Moods of `book` the query asks for
+fn mood_matches(book: &Book, query_info: &SemanticQueryInfo) -> usize {
+    query_info
+        .moods
+        .iter()
+        .filter(|mood| book.moods.contains(mood))
+        .count()
+}
+
+/// Score a book gains for a year inside the query's range, and loses for one outside it
+const TEMPORAL_MATCH_BOOST: f32 = 1.5;
+
+/// Books this many years old or older count as equally old for the recency boost
+const RECENCY_HORIZON_YEARS: f32 = 100.0;
+
+/// Whether `book` was published within the years the query asks for: 1 if it
+/// was, -1 if it wasn't, 0 if the query has no range or the year is unknown
+fn temporal_fit(book: &Book, query_info: &SemanticQueryInfo) -> i32 {
+    let (Some(filter), Some(year)) = (
+        &query_info.temporal_filter,
+        book.year.filter(|&year| year > 0),
+    ) else {
+        return 0;
+    };
+    let in_range = filter.min_year.is_none_or(|min| year >= min)
+        && filter.max_year.is_none_or(|max| year <= max);
+    if in_range {
+        1
+    } else {
+        -1
+    }
+}
+
+/// Score from the query's temporal filter: the range boost, leaning towards newer
+/// books within the range when the recency boost is above 1 and older ones below
+fn temporal_boost(book: &Book, query_info: &SemanticQueryInfo, current_year: i32) -> f32 {
+    let (Some(filter), Some(year)) = (&query_info.temporal_filter, book.year) else {
+        return 0.0;
+    };
+    match temporal_fit(book, query_info) {
+        1 => {
+            let age = (current_year - year).clamp(0, RECENCY_HORIZON_YEARS as i32) as f32;
+            let recency = 1.0 - age / RECENCY_HORIZON_YEARS;
+            TEMPORAL_MATCH_BOOST + (filter.recency_boost - 1.0) * recency
+        }
+        -1 => -TEMPORAL_MATCH_BOOST,
+        _ => 0.0,
+    }
+}
+
+/// Score a book gains for a page count inside the query's range, and loses for one outside it
+const LENGTH_FIT_BOOST: f32 = 1.5;
+
+/// Whether `book` has the length the query asks for: 1 if it has, -1 if it
+/// hasn't, 0 if the query has no range or the page count is unknown
+fn length_fit(book: &Book, query_info: &SemanticQueryInfo) -> i32 {
+    let (Some(filter), Some(pages)) = (
+        &query_info.length_filter,
+        book.page_count.filter(|&pages| pages > 0),
+    ) else {
+        return 0;
+    };
+    let in_range = filter.min_pages.is_none_or(|min| pages >= min)
+        && filter.max_pages.is_none_or(|max| pages <= max);
+    if in_range {
+        1
+    } else {
+        -1
+    }
+}
+
+/// Score a book gains for having won the award the query asks for
+const AWARD_BOOST: f32 = 1.5;
+
+/// Whether `book` won the award the query asks for: 1 if it did, 0 otherwise
+fn award_fit(book: &Book, query_info: &SemanticQueryInfo) -> i32 {
+    query_info
+        .award
+        .as_ref()
+        .is_some_and(|award| !award.matches(book).is_empty()) as i32
+}
+
+/// Score a book gains for being set in a place the query asks for
+const SETTING_BOOST: f32 = 1.5;
+
+/// Settings of `book` the query asks for
+fn setting_matches<'a>(book: &'a Book, query_info: &SemanticQueryInfo) -> Vec<&'a str> {
+    book.settings
+        .iter()
+        .filter(|setting| query_info.settings.contains(setting))
+        .map(String::as_str)
+        .collect()
+}
+
+/// Whether `book` is set where the query asks for: 1 if it is, 0 otherwise
+fn setting_fit(book: &Book, query_info: &SemanticQueryInfo) -> i32 {
+    (!setting_matches(book, query_info).is_empty()) as i32
+}
+
+/// Whether `book` is known to meet every filter the query sets: its year range,
+/// length, award and settings
+pub fn meets_constraints(book: &Book, query_info: &SemanticQueryInfo) -> bool {
+    (query_info.temporal_filter.is_none() || temporal_fit(book, query_info) == 1)
+        && (query_info.length_filter.is_none() || length_fit(book, query_info) == 1)
+        && (query_info.award.is_none() || award_fit(book, query_info) == 1)
+        && (query_info.settings.is_empty() || setting_fit(book, query_info) == 1)
+}
+
+#[derive(Debug, Clone)]
+#[allow(dead_code)]
+pub enum QueryIntent {
+    Author {
+        name: String,
+        original_query: String,
+    },
+    Genre {
+        genre: String,
+        original_query: String,
+    },
+    SimilarTo {
+        original_query: String,
+    },
+    General {
+        query: String,
+    },
+}
+
+impl QueryIntent {
+    pub fn label(&self) -> &'static str {
+        match self {
+            QueryIntent::Author { .. } => "Author",
+            QueryIntent::Genre { .. } => "Genre",
+            QueryIntent::SimilarTo { .. } => "SimilarTo",
+            QueryIntent::General { .. } => "General",
+        }
+    }
+
+    /// Intent of an analyzed query, which picks the search strategy and ranking
+    pub fn from_query_info(info: &SemanticQueryInfo) -> Self {
+        // If author is detected, prioritize that - metadata search is best for authors
+        if let Some(author) = info.confident_author() {
+            return QueryIntent::Author {
+                name: author.to_string(),
+                original_query: info.original_query.clone(),
+            };
+        }
+
+        // If similar query, use SimilarTo intent - semantic search is best. An
+        // unsure reading of either is searched as general rather than filtered badly
+        if info.is_similar_query
+            && info.author.is_none()
+            && info.intent_confidence >= MIN_INTENT_CONFIDENCE
+        {
+            return QueryIntent::SimilarTo {
+                original_query: info.original_query.clone(),
+            };
+        }
+
+        // For all other queries, use General intent
+        // The semantic themes will be used in ranking and relevance indicators
+        // This gives the best balance between semantic search and metadata filtering
+        QueryIntent::General {
+            query: info.original_query.clone(),
+        }
+    }
+}
+
+/// Rank results with semantic information
+pub fn rank_results(
+    results: Vec<Book>,
+    intent: &QueryIntent,
+    query_info: &SemanticQueryInfo,
+    top_k: usize,
+    keyword_boost_cap: f32,
+    trusted_positions: usize,
+) -> Vec<Book> {
+    // Early return if no results or only one result
+    if results.len() <= 1 {
+        return results;
+    }
+
+    let results = order_results(
+        results,
+        intent,
+        query_info,
+        keyword_boost_cap,
+        trusted_positions,
+    );
+    finalize_results(results, query_info, top_k)
+}
+
+/// A way of ordering retrieved candidates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Ranker {
+    /// Keep the retrieval order
+    Retrieval,
+    /// Order by the query's intent, the regular ranking ([`order_results`])
+    Intent,
+    /// Fuse the retrieval and intent order ([`fuse_results`])
+    Fused,
+}
+
+impl Ranker {
+    pub const ALL: [Ranker; 3] = [Ranker::Retrieval, Ranker::Intent, Ranker::Fused];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Ranker::Retrieval => "retrieval",
+            Ranker::Intent => "intent",
+            Ranker::Fused => "fused",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|ranker| ranker.name() == name)
+    }
+
+    /// Candidates in this ranker's order, without dropping or scoring any
+    pub fn order(
+        self,
+        results: Vec<Book>,
+        intent: &QueryIntent,
+        query_info: &SemanticQueryInfo,
+        keyword_boost_cap: f32,
+        trusted_positions: usize,
+    ) -> Vec<Book> {
+        match self {
+            Ranker::Retrieval => results,
+            Ranker::Intent => order_results(
+                results,
+                intent,
+                query_info,
+                keyword_boost_cap,
+                trusted_positions,
+            ),
+            Ranker::Fused => fuse_results(
+                results,
+                intent,
+                query_info,
+                keyword_boost_cap,
+                trusted_positions,
+            ),
+        }
+    }
+
+    /// The top `top_k` candidates in this ranker's order, without duplicates and scored
+    pub fn rank(
+        self,
+        results: Vec<Book>,
+        intent: &QueryIntent,
+        query_info: &SemanticQueryInfo,
+        top_k: usize,
+        keyword_boost_cap: f32,
+        trusted_positions: usize,
+    ) -> Vec<Book> {
+        match self {
+            Ranker::Intent => rank_results(
+                results,
+                intent,
+                query_info,
+                top_k,
+                keyword_boost_cap,
+                trusted_positions,
+            ),
+            Ranker::Retrieval | Ranker::Fused => {
+                let ordered = self.order(
+                    results,
+                    intent,
+                    query_info,
+                    keyword_boost_cap,
+                    trusted_positions,
+                );
+                finalize_results(ordered, query_info, top_k)
+            }
+        }
+    }
+}
+
+/// Where a book ended up among ordered results once duplicates are dropped
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResultPlacement {
+    /// The book isn't among the results
+    Missing,
+    /// Dropped as a duplicate of the earlier book with this id
+    DuplicateOf(Option<String>),
+    /// Zero-based position among the unique results
+    Ranked(usize),
+}
+
+/// Placement of `book_id` among `ordered` results, deduplicated as [`finalize_results`] does
+pub fn locate_result(ordered: &[Book], book_id: &str) -> ResultPlacement {
+    let mut first_with_key: HashMap<String, Option<&str>> = HashMap::new();
+    let mut position = 0;
+    for book in ordered {
+        let key = dedup_key(book);
+        if let Some(first_id) = first_with_key.get(&key) {
+            if book.id.as_deref() == Some(book_id) {
+                return ResultPlacement::DuplicateOf(first_id.map(str::to_string));
+            }
+            continue;
+        }
+        if book.id.as_deref() == Some(book_id) {
+            return ResultPlacement::Ranked(position);
+        }
+        first_with_key.insert(key, book.id.as_deref());
+        position += 1;
+    }
+    ResultPlacement::Missing
+}
+
+/// Re-order candidates by the query's intent, without dropping or scoring any
+///
+/// General queries rank their first `trusted_positions` candidates mostly by
+/// retrieval position and the rest mostly by rating.
+pub fn order_results(
+    mut results: Vec<Book>,
+    intent: &QueryIntent,
+    query_info: &SemanticQueryInfo,
+    keyword_boost_cap: f32,
+    trusted_positions: usize,
+) -> Vec<Book> {
+    if results.len() <= 1 {
+        return results;
+    }
+
+    // Use existing ranking logic but with semantic information
+    match intent {
+        QueryIntent::Author { name, .. } => {
+            let name_key = author_key(name);
+            let mut indexed_books: Vec<(usize, i32, i32, usize, f32)> = results
+                .iter()
+                .enumerate()
+                .map(|(idx, book)| {
+                    let author = author_key(book.author.as_deref().unwrap_or(""));
+                    let exact_match = author.contains(&name_key) as i32;
+                    (
+                        idx,
+                        exact_match,
+                        temporal_fit(book, query_info)
+                            + length_fit(book, query_info)
+                            + award_fit(book, query_info)
+                            + setting_fit(book, query_info),
+                        mood_matches(book, query_info),
+                        book.rating,
+                    )
+                })
+                .collect();
+
+            // The author's books first, those of the asked-for years, length,
+            // awards and settings, then mood, before the rest
+            indexed_books.sort_by(|a, b| {
+                let (_, a_exact, a_fit, a_moods, a_rating) = *a;
+                let (_, b_exact, b_fit, b_moods, b_rating) = *b;
+                b_exact
+                    .cmp(&a_exact)
+                    .then(b_fit.cmp(&a_fit))
+                    .then(b_moods.cmp(&a_moods))
+                    .then_with(|| {
+                        b_rating
+                            .partial_cmp(&a_rating)
+                            .unwrap_or(std::cmp::Ordering::Equal)
+                    })
+            });
+
+            results = reorder(
+                results,
+                indexed_books.into_iter().map(|(idx, _, _, _, _)| idx),
+            );
+        }
+        QueryIntent::Genre { genre, .. } => {
+            let genre_lower = genre.to_lowercase();
+            let mut indexed_books: Vec<(usize, i32, i32, usize, f32)> = results
+                .iter()
+                .enumerate()
+                .map(|(idx, book)| {
+                    let has_match = book
+                        .genres
+                        .iter()
+                        .chain(&book.categories)
+                        .any(|g| g.to_lowercase().contains(&genre_lower))
+                        as i32;
+                    (
+                        idx,
+                        has_match,
+                        temporal_fit(book, query_info)
+                            + length_fit(book, query_info)
+                            + award_fit(book, query_info)
+                            + setting_fit(book, query_info),
+                        mood_matches(book, query_info),
+                        book.rating,
+                    )
+                })
+                .collect();
+
+            indexed_books.sort_by(|a, b| {
+                let (_, a_match, a_fit, a_moods, a_rating) = *a;
+                let (_, b_match, b_fit, b_moods, b_rating) = *b;
+                b_match
+                    .cmp(&a_match)
+                    .then(b_fit.cmp(&a_fit))
+                    .then(b_moods.cmp(&a_moods))
+                    .then_with(|| {
+                        b_rating
+                            .partial_cmp(&a_rating)
+                            .unwrap_or(std::cmp::Ordering::Equal)
+                    })
+            });
+
+            results = reorder(
+                results,
+                indexed_books.into_iter().map(|(idx, _, _, _, _)| idx),
+            );
+        }
+        _ => {
+            info!("Using GENERAL search ranking logic with keyword boost");
+
+            let keywords = ThemeKeywords::new(query_info);
+            let total_results = results.len();
+            let current_year = chrono::Utc::now().year();
+            let mut scored_results = results
+                .iter()
+                .enumerate()
+                .map(|(idx, book)| {
+                    let position_score = 3.0 * (1.0 - (idx as f32 / total_results as f32));
+                    let rating_score = 0.85 + (book.rating / 5.0) * 0.10;
+
+                    // Add keyword boost - check if query keywords appear in book metadata
+                    let mut keyword_boost: f32 = 0.0;
+                    for place in keywords.places_in(book) {
+                        keyword_boost += match place {
+                            Some(KeywordPlace::Title) => 1.0, // Title match is strongest
+                            Some(KeywordPlace::Category) => 0.8, // Category match is strong
+                            Some(KeywordPlace::Description) => 0.5, // Description match is moderate
+                            None => 0.0,
+                        };
+                    }
+
+                    keyword_boost = keyword_boost.min(keyword_boost_cap);
+                    let mood_boost = MOOD_BOOST_PER_MATCH * mood_matches(book, query_info) as f32;
+                    let temporal_boost = temporal_boost(book, query_info, current_year);
+                    let length_boost = LENGTH_FIT_BOOST * length_fit(book, query_info) as f32;
+                    let award_boost = AWARD_BOOST * award_fit(book, query_info) as f32;
+                    let setting_boost = SETTING_BOOST * setting_fit(book, query_info) as f32;
+                    let boosts = keyword_boost
+                        + mood_boost
+                        + temporal_boost
+                        + length_boost
+                        + award_boost
+                        + setting_boost;
+
+                    let final_score = if idx < trusted_positions {
+                        position_score + rating_score + boosts
+                    } else {
+                        position_score * 0.7 + rating_score * 1.3 + boosts
+                    };
+
+                    if tracing::enabled!(tracing::Level::DEBUG) {
+                        debug!(
+                            "Book scoring: {:?} - Position: {}/{} (score: {:.2}), Rating: {:.2}, Keyword boost: {:.2}, Mood boost: {:.2}, Temporal boost: {:.2}, Length boost: {:.2}, Award boost: {:.2}, Setting boost: {:.2}, Final: {:.2}",
+                            book.title, idx + 1, total_results, position_score, book.rating, keyword_boost, mood_boost, temporal_boost, length_boost, award_boost, setting_boost, final_score
+                        );
+                    }
+
+                    (idx, final_score)
+                })
+                .collect::<Vec<_>>();
+
+            scored_results
+                .sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+
+            results = reorder(results, scored_results.into_iter().map(|(idx, _)| idx));
+
+            info!(
+                "Completed scoring of {} books with keyword boosting",
+                results.len()
+            );
+        }
+    }
+
+    results
+}
+
+/// Damping of reciprocal rank fusion; higher flattens the gaps between top ranks
+const FUSION_RANK_OFFSET: f32 = 60.0;
+
+/// Re-order candidates by fusing their retrieval order with the intent order of
+/// [`order_results`], so neither a close embedding nor a strong intent match
+/// alone decides a book's place
+///
+/// Each book scores `1 / (offset + rank)` in both orders; ties keep the intent order.
+pub fn fuse_results(
+    results: Vec<Book>,
+    intent: &QueryIntent,
+    query_info: &SemanticQueryInfo,
+    keyword_boost_cap: f32,
+    trusted_positions: usize,
+) -> Vec<Book> {
+    let mut retrieval_ranks: HashMap<String, usize> = HashMap::with_capacity(results.len());
+    for (rank, book) in results.iter().enumerate() {
+        if let Some(id) = &book.id {
+            retrieval_ranks.entry(id.clone()).or_insert(rank);
+        }
+    }
+
+    let ordered = order_results(
+        results,
+        intent,
+        query_info,
+        keyword_boost_cap,
+        trusted_positions,
+    );
+    let fusion_score = |rank: usize| 1.0 / (FUSION_RANK_OFFSET + rank as f32 + 1.0);
+    let mut scored: Vec<(usize, f32)> = ordered
+        .iter()
+        .enumerate()
+        .map(|(rank, book)| {
+            let retrieval_rank = book
+                .id
+                .as_ref()
+                .and_then(|id| retrieval_ranks.get(id))
+                .copied()
+                .unwrap_or(rank);
+            (rank, fusion_score(rank) + fusion_score(retrieval_rank))
+        })
+        .collect();
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+    reorder(ordered, scored.into_iter().map(|(idx, _)| idx))
+}
+
+/// Books with the same title and author are duplicates, e.g. other editions
+fn dedup_key(book: &Book) -> String {
+    format!(
+        "{}-{}",
+        book.title.as_deref().unwrap_or("Unknown"),
+        book.author.as_deref().unwrap_or("Unknown")
+    )
+}
+
+/// Drop duplicate books and score the top `top_k` by position and rating
+pub fn finalize_results(
+    results: Vec<Book>,
+    query_info: &SemanticQueryInfo,
+    top_k: usize,
+) -> Vec<Book> {
+    let max_needed = top_k.min(results.len());
+
+    // Remove duplicates
+    let mut seen = HashSet::with_capacity(max_needed);
+    let mut unique_results = Vec::with_capacity(max_needed);
+
+    for book in results {
+        if unique_results.len() >= top_k {
+            break;
+        }
+
+        if seen.insert(dedup_key(&book)) {
+            unique_results.push(book);
+        }
+    }
+
+    // Final ranking with metadata
+    let keywords = ThemeKeywords::new(query_info);
+    let final_results = unique_results
+        .into_iter()
+        .enumerate()
+        .take(top_k)
+        .map(|(index, mut book)| {
+            let position_factor = 1.0 - (index as f32 / top_k as f32);
+            let rating_factor = book.rating / 5.0;
+            book.confidence_score = (position_factor * 0.7 + rating_factor * 0.3).min(1.0);
+
+            book.relevance_indicators = relevance_indicators(&book, query_info, &keywords);
+
+            book
+        })
+        .collect::<Vec<Book>>();
+
+    info!(
+        "FINAL RANKING: Top {} results ready. First book: {:?}",
+        final_results.len(),
+        final_results.first().map(|b| b.title.clone())
+    );
+
+    final_results
+}
+
+/// Set the relevance indicators of books that weren't ranked for the query
+pub fn annotate_relevance(books: &mut [Book], query_info: &SemanticQueryInfo) {
+    let keywords = ThemeKeywords::new(query_info);
+    for book in books {
+        book.relevance_indicators = relevance_indicators(book, query_info, &keywords);
+    }
+}
+
+/// Generate relevance indicators using semantic information
+fn relevance_indicators(
+    book: &Book,
+    query_info: &SemanticQueryInfo,
+    keywords: &ThemeKeywords,
+) -> Vec<String> {
+    let mut indicators = Vec::new();
+
+    // Check for keyword matches in title, categories and description
+    for ((keyword, _), place) in query_info.themes.iter().zip(keywords.places_in(book)) {
+        if place.is_some() {
+            indicators.push(keyword.clone());
+        }
+    }
+
+    // Add author match if applicable
+    if let Some(author) = query_info.confident_author() {
+        if book
+            .author
+            .as_ref()
+            .is_some_and(|book_author| author_key(book_author).contains(&author_key(author)))
+        {
+            indicators.push(format!("Author: {}", author));
+        }
+    }
+
+    for mood in &query_info.moods {
+        if book.moods.contains(mood) && !indicators.contains(mood) {
+            indicators.push(mood.clone());
+        }
+    }
+
+    if let Some(award) = &query_info.award {
+        for name in award.matches(book) {
+            if !indicators.iter().any(|indicator| indicator == name) {
+                indicators.push(name.to_string());
+            }
+        }
+    }
+
+    for setting in setting_matches(book, query_info) {
+        indicators.push(format!("Set in {}", setting));
+    }
+
+    // Add categories if not enough indicators
+    if indicators.len() < 2 {
+        for category in &book.categories {
+            if !indicators.contains(category) {
+                indicators.push(category.clone());
+            }
+            if indicators.len() >= 3 {
+                break;
+            }
+        }
+    }
+
+    indicators.into_iter().take(3).collect()
+}
+
+/// Where in a book a query keyword was found, strongest first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum KeywordPlace {
+    Title,
+    Category,
+    Description,
+}
+
+/// The query's theme keywords compiled into one automaton
+///
+/// Scans each of a book's fields once for all keywords, rather than lowercasing
+/// the field and searching it again per keyword. Matching ignores ASCII case.
+struct ThemeKeywords {
+    automaton: Option<AhoCorasick>,
+    count: usize,
+}
+
+impl ThemeKeywords {
+    fn new(query_info: &SemanticQueryInfo) -> Self {
+        let automaton = AhoCorasick::builder()
+            .ascii_case_insensitive(true)
+            .build(
+                query_info
+                    .themes
+                    .iter()
+                    .map(|(keyword, _)| keyword.to_lowercase()),
+            )
+            .ok();
+        Self {
+            automaton,
+            count: query_info.themes.len(),
+        }
+    }
+
+    /// For each keyword, in query order, the strongest place it appears in `book`
+    fn places_in(&self, book: &Book) -> Vec<Option<KeywordPlace>> {
+        let mut places = vec![None; self.count];
+        let Some(automaton) = &self.automaton else {
+            return places;
+        };
+
+        let fields = book
+            .title
+            .iter()
+            .map(|title| (KeywordPlace::Title, title))
+            .chain(
+                book.categories
+                    .iter()
+                    .map(|category| (KeywordPlace::Category, category)),
+            )
+            .chain(
+                book.description
+                    .iter()
+                    .map(|description| (KeywordPlace::Description, description)),
+            );
+        for (place, text) in fields {
+            for hit in automaton.find_overlapping_iter(text.as_str()) {
+                let found = &mut places[hit.pattern().as_usize()];
+                if found.is_none_or(|strongest| place < strongest) {
+                    *found = Some(place);
+                }
+            }
+        }
+        places
+    }
+}
+
+/// `items` in the order of the indices in `order`, moved rather than cloned
+///
+/// `order` must be a permutation of `items`' indices.
+fn reorder<T>(items: Vec<T>, order: impl IntoIterator<Item = usize>) -> Vec<T> {
+    let mut slots: Vec<Option<T>> = items.into_iter().map(Some).collect();
+    order
+        .into_iter()
+        .filter_map(|idx| slots.get_mut(idx).and_then(Option::take))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::semantic_classifier::{LengthFilter, TemporalFilter};
+    use crate::services::templates::QueryPattern;
+
+    fn book(title: &str, author: &str, rating: f32) -> Book {
+        serde_json::from_value(serde_json::json!({
+            "title": title,
+            "author": author,
+            "rating": rating,
+            "categories": ["Fantasy"],
+        }))
+        .expect("valid book")
+    }
+
+    #[test]
+    fn test_finalize_drops_duplicates_and_scores_by_position() {
+        let info = SemanticQueryInfo {
+            original_query: "dragons".to_string(),
+            themes: vec![("dragon".to_string(), 1.0)],
+            author: None,
+            temporal_filter: None,
+            length_filter: None,
+            is_similar_query: false,
+            semantic_tags: vec![],
+            moods: vec![],
+            award: None,
+            settings: vec![],
+            intent_confidence: 1.0,
+            pattern: QueryPattern::default(),
+        };
+        let results = vec![
+            book("Dragonflight", "Anne McCaffrey", 4.0),
+            book("Dragonflight", "Anne McCaffrey", 4.0),
+            book("Eragon", "Christopher Paolini", 4.0),
+        ];
+
+        let ranked = finalize_results(results, &info, 2);
+        let titles: Vec<_> = ranked.iter().filter_map(|b| b.title.as_deref()).collect();
+        assert_eq!(titles, vec!["Dragonflight", "Eragon"]);
+        assert!(ranked[0].confidence_score > ranked[1].confidence_score);
+        assert_eq!(ranked[0].relevance_indicators[0], "dragon");
+    }
+
+    #[test]
+    fn test_locate_result_reports_duplicates_and_unique_positions() {
+        let with_id = |id: &str, title: &str| {
+            let mut book = book(title, "Anne McCaffrey", 4.0);
+            book.id = Some(id.to_string());
+            book
+        };
+        let ordered = vec![
+            with_id("a", "Dragonflight"),
+            with_id("b", "Dragonflight"),
+            with_id("c", "Dragonquest"),
+        ];
+
+        assert_eq!(locate_result(&ordered, "c"), ResultPlacement::Ranked(1));
+        assert_eq!(
+            locate_result(&ordered, "b"),
+            ResultPlacement::DuplicateOf(Some("a".to_string()))
+        );
+        assert_eq!(locate_result(&ordered, "z"), ResultPlacement::Missing);
+    }
+
+    #[test]
+    fn test_temporal_filter_pushes_books_outside_the_range_down() {
+        let info = SemanticQueryInfo {
+            original_query: "recent sci-fi".to_string(),
+            themes: vec![],
+            author: None,
+            temporal_filter: Some(TemporalFilter {
+                min_year: Some(2015),
+                max_year: None,
+                recency_boost: 1.3,
+            }),
+            length_filter: None,
+            is_similar_query: false,
+            semantic_tags: vec![],
+            moods: vec![],
+            award: None,
+            settings: vec![],
+            intent_confidence: 1.0,
+            pattern: QueryPattern::default(),
+        };
+        let with_year = |title: &str, year: Option<i32>| {
+            let mut book = book(title, "Someone", 4.0);
+            book.year = year;
+            book
+        };
+        let results = vec![
+            with_year("Dune", Some(1965)),
+            with_year("Undated", None),
+            with_year("Project Hail Mary", Some(2021)),
+        ];
+        let intent = QueryIntent::from_query_info(&info);
+
+        let ordered = order_results(results, &intent, &info, 2.0, 50);
+        let titles: Vec<_> = ordered.iter().filter_map(|b| b.title.as_deref()).collect();
+        assert_eq!(titles, vec!["Project Hail Mary", "Undated", "Dune"]);
+    }
+
+    #[test]
+    fn test_length_filter_prefers_books_of_the_asked_for_length() {
+        let info = SemanticQueryInfo {
+            original_query: "short reads".to_string(),
+            themes: vec![],
+            author: None,
+            temporal_filter: None,
+            length_filter: LengthFilter::from_bounds(None, Some(300)),
+            is_similar_query: false,
+            semantic_tags: vec![],
+            moods: vec![],
+            award: None,
+            settings: vec![],
+            intent_confidence: 1.0,
+            pattern: QueryPattern::default(),
+        };
+        let with_pages = |title: &str, pages: i32| {
+            let mut book = book(title, "Someone", 4.0);
+            book.page_count = Some(pages);
+            book
+        };
+        let results = vec![
+            with_pages("Doorstopper", 800),
+            with_pages("Unknown length", 0),
+            with_pages("Novella", 180),
+        ];
+        let intent = QueryIntent::from_query_info(&info);
+
+        let ordered = order_results(results, &intent, &info, 2.0, 50);
+        let titles: Vec<_> = ordered.iter().filter_map(|b| b.title.as_deref()).collect();
+        assert_eq!(titles, vec!["Novella", "Unknown length", "Doorstopper"]);
+    }
+
+    #[test]
+    fn test_fused_ranking_weighs_retrieval_and_intent_order_alike() {
+        let info = SemanticQueryInfo {
+            original_query: "short reads".to_string(),
+            themes: vec![],
+            author: None,
+            temporal_filter: None,
+            length_filter: LengthFilter::from_bounds(None, Some(300)),
+            is_similar_query: false,
+            semantic_tags: vec![],
+            moods: vec![],
+            award: None,
+            settings: vec![],
+            intent_confidence: 1.0,
+            pattern: QueryPattern::default(),
+        };
+        let with_pages = |title: &str, pages: i32| {
+            let mut book = book(title, "Someone", 4.0);
+            book.id = Some(title.to_lowercase());
+            book.page_count = Some(pages);
+            book
+        };
+        let results = vec![
+            with_pages("Closest", 800),
+            with_pages("Second", 800),
+            with_pages("Third", 800),
+            with_pages("Novella", 180),
+        ];
+        let intent = QueryIntent::from_query_info(&info);
+
+        // The intent order alone puts the novella first, retrieval order last
+        let fused = fuse_results(results, &intent, &info, 2.0, 50);
+        let titles: Vec<_> = fused.iter().filter_map(|b| b.title.as_deref()).collect();
+        assert_eq!(titles, vec!["Closest", "Novella", "Second", "Third"]);
+    }
+}
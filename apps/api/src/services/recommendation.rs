@@ -1,42 +1,120 @@
-use crate::error::Result;
-use crate::services::semantic_classifier::{SemanticClassifier, SemanticQueryInfo};
+use crate::cache::{SharedCache, TtlCache};
+use crate::error::{recover_lock, Result};
+use crate::ingest::{
+    authors::{author_key, AuthorAliases},
+    corpus_stats::{CorpusStats, TitleEntry},
+    covers::{CoverResolver, CoverStats},
+    enrichment::{EnrichmentStats, MetadataEnricher},
+    fulltext::{quoted_phrases, FullTextIndex},
+    moods::{tag_moods, MoodClassifier},
+    searchable_text::{content_hash, create_searchable_text},
+    settings::settings_in_query,
+};
+use crate::services::author_profile::{
+    average_embedding, build_profile, similar_authors, AuthorProfile, SimilarAuthor,
+    MAX_PROFILE_BOOKS, SIMILAR_AUTHOR_CANDIDATES,
+};
+use crate::services::book_patch::{
+    apply_patch, validate_patch, BookPatch, PatchResult, PatchStatus,
+};
+use crate::services::book_versions::{
+    stamp_version, stored_version, BookHistory, CONTENT_HASH_FIELD,
+};
+use crate::services::compare::{compare_books, BookComparison};
+use crate::services::deadline;
+use crate::services::digest::{
+    closest_seed, same_week, Digest, DigestPick, DigestSeed, DIGEST_SNAPSHOT_KIND,
+};
+use crate::services::experiments::Experiments;
+use crate::services::explanations::{Explainer, Reader, MAX_READER_TITLES};
+use crate::services::feature_flags::FeatureFlags;
+use crate::services::graph_store::GraphStore;
+use crate::services::i18n::Locale;
+use crate::services::jobs::JobHandle;
+use crate::services::load_shedding::{ComputeQueue, DEFAULT_MAX_QUEUED_RECOMMENDATIONS};
+use crate::services::personalization::{
+    blend_affinities, cosine_similarity, history_affinity, history_tag_weights, taste_profile,
+    ContentPreferences, RatingAdjacency, MIN_EMBEDDING_SIMILARITY, MIN_PROFILE_RATING,
+};
+use crate::services::post_filters::{FilterContext, PostFilterChain, PostFilters};
+use crate::services::prewarm::DEFAULT_WARMUP_QUERIES;
+use crate::services::query_expansion::{blend, is_sparse, QueryExpander};
+use crate::services::query_parser::QueryParser;
+use crate::services::randomness::RandomSource;
+use crate::services::ranking::{
+    annotate_relevance, finalize_results, locate_result, QueryIntent, Ranker, ResultPlacement,
+};
+use crate::services::recovery::{is_weak, relax, relaxable_constraints};
+use crate::services::refinements::suggest_refinements;
+use crate::services::retrieval::RetrievalConfig;
+use crate::services::search_router::{SearchRouter, Upstream};
+use crate::services::semantic_classifier::{
+    LengthFilter, SemanticClassifier, SemanticQueryInfo, KNOWN_AUTHOR_CONFIDENCE,
+};
+use crate::services::shadow::{ShadowRanking, ShadowRun};
+use crate::services::similar_to::{
+    interleave, mean_embedding, seed_clusters, steer_away, SimilarBooks,
+};
+use crate::services::slow_query_log::{SlowQueryEntry, SlowQueryLog, UpstreamTimings};
+use crate::services::supabase::{EventType, HistoryOwner, ShelfStatus, SupabaseClient};
+use crate::services::synonyms::SynonymDictionary;
+use crate::services::templates::{set_synonyms, EnhancedQuery, QueryFilters, QueryPattern};
+use crate::services::trending::{rank_trending, TrendingBook};
 use crate::services::QueryEnhancer;
 use crate::{
-    error::ApiError, ml::huggingface_embedder::HuggingFaceEmbedder, models::Book,
-    services::pinecone::Pinecone,
+    error::ApiError,
+    ml::{
+        embedder::Embedder,
+        sparse_encoder::{encode_book, encode_query},
+    },
+    models::{
+        Book, BookExplanation, EndpointProbe, ExperimentAssignment, Refinement, RelaxedConstraint,
+        SearchPath, SemanticTag, UpstreamStatus, DEFAULT_TOP_K,
+    },
+    services::pinecone::{is_hidden, FilterBuilder, UpsertVector, HIDDEN_FIELD},
+    services::vector_store::VectorStore,
 };
 use serde::Serialize;
 use std::{
     collections::{HashMap, HashSet},
+    path::Path,
     sync::{Arc, RwLock},
     time::{Duration, Instant},
 };
 use tracing::{debug, error, info, warn};
 
-#[derive(Debug, Clone)]
-#[allow(dead_code)]
-enum QueryIntent {
-    Author {
-        name: String,
-        original_query: String,
-    },
-    Genre {
-        genre: String,
-        original_query: String,
-    },
-    SimilarTo {
-        original_query: String,
-    },
-    General {
-        query: String,
-    },
-}
-
 #[derive(Debug)]
 struct SearchStrategy {
     metadata_filter: Option<MetadataFilter>,
     semantic_weight: f32,
     hybrid_search: bool,
+    /// Metadata conditions every retrieved book should meet, e.g. a year range
+    filters: FilterBuilder,
+}
+
+/// Metadata conditions implied by a query, such as the years "recent" covers
+fn search_filters(query_info: &SemanticQueryInfo) -> FilterBuilder {
+    let mut filters = FilterBuilder::new();
+    if let Some(temporal) = &query_info.temporal_filter {
+        filters = filters.with_range("year", temporal.min_year, temporal.max_year);
+    }
+    if let Some(award) = &query_info.award {
+        filters = filters.with_any_of("awards", &award.award_names());
+    }
+    if !query_info.settings.is_empty() {
+        let settings: Vec<&str> = query_info.settings.iter().map(String::as_str).collect();
+        filters = filters.with_any_of("settings", &settings);
+    }
+    if let Some(length) = &query_info.length_filter {
+        // Unknown page counts are stored as 0; keep them out of the range so
+        // they only come back through the unfiltered top-up
+        filters = filters.with_range(
+            "page_count",
+            Some(length.min_pages.unwrap_or(1).max(1)),
+            length.max_pages,
+        );
+    }
+    filters
 }
 
 #[derive(Debug, Serialize)]
@@ -46,43 +124,1162 @@ struct MetadataFilter {
     exact_match: bool,
 }
 
-// Cache entry for query results to avoid repeated computation
-struct CacheEntry {
-    results: Vec<Book>,
-    timestamp: Instant,
-}
+impl MetadataFilter {
+    /// Whether the partial match of the metadata search would find `book`
+    fn matches(&self, book: &Book) -> bool {
+        let value = self.value.to_lowercase();
+        match self.field.as_str() {
+            "author" => book
+                .author
+                .as_ref()
+                .is_some_and(|author| author.to_lowercase().contains(&value)),
+            "genres" => book
+                .genres
+                .iter()
+                .chain(&book.categories)
+                .any(|genre| genre.to_lowercase().contains(&value)),
+            _ => false,
+        }
+    }
+}
+
+/// How a query's retrieval and ranking treated one book, from [`RecommendationService::explain_ranking`]
+#[derive(Debug)]
+pub struct RankingExplanation {
+    /// The book's stored metadata, or None if it isn't indexed
+    pub book: Option<Book>,
+    /// Intent the query was read as
+    pub intent: String,
+    /// Confidence in the query's author or "similar to" reading, see [`SemanticQueryInfo::intent_confidence`]
+    pub intent_confidence: f32,
+    /// Metadata filter the search started with, e.g. `author ~ Ursula K. Le Guin`
+    pub metadata_filter: Option<String>,
+    /// Whether the book matches that filter
+    pub matches_metadata_filter: Option<bool>,
+    /// Whether the fallback search stood in for the hybrid search
+    pub used_fallback: bool,
+    /// Candidates retrieved before ranking
+    pub candidate_count: usize,
+    /// Zero-based position of the book among the candidates
+    pub candidate_position: Option<usize>,
+    /// Where ranking and deduplication put the book
+    pub placement: ResultPlacement,
+    /// The ranked results, as the query would return them before per-request filtering
+    pub results: Vec<Book>,
+}
+
+/// Longest query searched, in bytes
+pub const MAX_QUERY_LENGTH: usize = 200;
+
+/// The trimmed query, or an error if it's empty, too short or too long
+fn validate_query(query: &str) -> Result<&str> {
+    let trimmed_query = query.trim();
+    if trimmed_query.is_empty() {
+        return Err(ApiError::InvalidInput("Query cannot be empty".into()));
+    }
+
+    // Validate query
+    if trimmed_query.len() < 3 {
+        return Err(ApiError::InvalidInput(
+            "Query too short (minimum 3 characters)".into(),
+        ));
+    }
+
+    if trimmed_query.len() > MAX_QUERY_LENGTH {
+        return Err(ApiError::InvalidInput(format!(
+            "Query too long (maximum {} characters)",
+            MAX_QUERY_LENGTH
+        )));
+    }
+
+    Ok(trimmed_query)
+}
+
+// Cache duration in seconds
+const CACHE_TTL_SECONDS: u64 = 300; // 5 minutes
+/// Most query results kept in the result cache
+const RESULT_CACHE_CAPACITY: usize = 500;
+/// How long a query's last good candidates can stand in while the upstreams are down
+const STALE_CANDIDATE_TTL: Duration = Duration::from_secs(3600);
+/// Default number of recommendation queries computed at once
+pub const DEFAULT_MAX_CONCURRENT_RECOMMENDATIONS: usize = 8;
+/// How long a query waits for a turn to be computed before the request is turned away
+const COMPUTE_QUEUE_TIMEOUT: Duration = Duration::from_secs(15);
+/// Share of the time left before the request deadline embedding the query may take
+const EMBEDDING_DEADLINE_SHARE: f32 = 2.0 / 3.0;
+
+/// How far a user's own ratings can move a result, as a fraction of the list length
+const RATING_BLEND_WEIGHT: f32 = 0.5;
+/// Most recent ratings taken into account when re-ranking for a user
+const MAX_RATINGS_CONSIDERED: usize = 50;
+/// Graph relationships fetched from the rated books when re-ranking for a user
+const MAX_RATING_LINKS: usize = 1000;
+/// Most recent ratings a taste profile is built from
+const MAX_PROFILE_RATINGS: usize = 200;
+/// How far the genres of earlier queries can move a result; lighter than ratings
+const HISTORY_BLEND_WEIGHT: f32 = 0.2;
+/// Most recent queries taken into account when biasing toward earlier genres
+const MAX_HISTORY_CONSIDERED: usize = 20;
+/// Most the keyword matches of a book can add to its score for general queries
+const KEYWORD_BOOST_CAP: f32 = 2.0;
+/// Books fetched from the index at a time while syncing the graph
+const GRAPH_SYNC_BATCH_SIZE: usize = 100;
+
+/// How long a trending list is cached; scheduled maintenance recomputes it sooner
+const TRENDING_CACHE_TTL: Duration = Duration::from_secs(30 * 60);
+/// Trending windows and lengths cached at once
+const TRENDING_CACHE_CAPACITY: usize = 16;
+
+/// Ranking knobs that experiment variants can change; the defaults are the regular ranking
+#[derive(Debug, Clone, PartialEq)]
+pub struct RankingParams {
+    /// Re-rank retrieved books by intent, rating and keyword matches; off keeps retrieval order
+    pub rerank: bool,
+    /// Most the keyword matches of a book can add to its score for general queries
+    pub keyword_boost_cap: f32,
+    /// How far a user's own ratings can move a result, as a fraction of the list length
+    pub rating_blend_weight: f32,
+    /// How far the genres of earlier queries can move a result
+    pub history_blend_weight: f32,
+    /// Model used to embed the query instead of the configured one. It has to embed
+    /// into the same space as the indexed books, e.g. a quantized copy of the same model
+    pub embedding_model: Option<String>,
+    /// Find books adjacent to a user's rated ones through the graph; off leaves only embeddings
+    pub graph_blending: bool,
+    /// Re-rank with the new ranker, which fuses retrieval and intent order
+    pub fused_ranking: bool,
+}
+
+impl RankingParams {
+    /// Ranker these parameters pick
+    pub fn ranker(&self) -> Ranker {
+        if !self.rerank {
+            Ranker::Retrieval
+        } else if self.fused_ranking {
+            Ranker::Fused
+        } else {
+            Ranker::Intent
+        }
+    }
+}
+
+impl Default for RankingParams {
+    fn default() -> Self {
+        Self {
+            rerank: true,
+            keyword_boost_cap: KEYWORD_BOOST_CAP,
+            rating_blend_weight: RATING_BLEND_WEIGHT,
+            history_blend_weight: HISTORY_BLEND_WEIGHT,
+            embedding_model: None,
+            graph_blending: true,
+            fused_ranking: false,
+        }
+    }
+}
+
+/// How a query was answered, for the response metadata
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QueryServing {
+    /// Whether the results came from the result cache
+    pub cache_hit: bool,
+    /// Whether the fallback search stood in for the vector search
+    pub degraded: bool,
+    /// Model that embedded the query; None when no embedding was used
+    pub model_used: Option<String>,
+    /// What the query was read as: its themes, genres, moods, author and era
+    pub tags: Vec<SemanticTag>,
+    /// Suggested narrower versions of the query
+    pub refinements: Vec<Refinement>,
+    /// Constraints of the query dropped because too few books met them
+    pub relaxed: Vec<RelaxedConstraint>,
+    /// Which search answered the query
+    pub search_path: SearchPath,
+    /// What the query mostly asks for, by the query templates
+    pub pattern: QueryPattern,
+}
+
+/// What happened while answering a single query, used for the slow query log
+#[derive(Default)]
+struct QueryTrace {
+    intent: Option<String>,
+    strategy: Option<String>,
+    cache_hit: bool,
+    used_fallback: bool,
+    search_path: SearchPath,
+    relaxed: Vec<RelaxedConstraint>,
+    timings: UpstreamTimings,
+}
+
+#[derive(Clone)]
+pub struct RecommendationService {
+    sentence_encoder: Arc<dyn Embedder>,
+    /// The vector index, Pinecone in production
+    pinecone: Arc<dyn VectorStore>,
+    /// Books are shared between cached and returned results rather than cloned
+    result_cache: TtlCache<String, Vec<Arc<Book>>>,
+    prewarmed: Arc<std::sync::atomic::AtomicBool>,
+    query_enhancer: QueryEnhancer,
+    semantic_classifier: SemanticClassifier,
+    slow_query_log: SlowQueryLog,
+    author_aliases: Arc<AuthorAliases>,
+    /// Snapshot of the indexed catalog, when one was loaded
+    corpus_stats: Option<Arc<CorpusStats>>,
+    user_data: Option<SupabaseClient>,
+    graph: Option<Arc<dyn GraphStore>>,
+    experiments: Arc<Experiments>,
+    feature_flags: FeatureFlags,
+    /// Candidate ranker evaluated alongside the served one
+    shadow: ShadowRanking,
+    /// Business rules applied to the ranked results of each tenant
+    post_filters: Arc<PostFilters>,
+    warmup_queries: Arc<Vec<String>>,
+    /// Turns of the uncached queries; unlimited when unset
+    compute_queue: Option<ComputeQueue>,
+    explainer: Explainer,
+    query_parser: QueryParser,
+    /// Vocabulary embeddings sparse queries are expanded with
+    query_expander: QueryExpander,
+    /// Whether the index holds keyword vectors for the fallback search
+    sparse_keywords: bool,
+    /// Local index of the catalog, when one was opened
+    fulltext: Option<Arc<FullTextIndex>>,
+    /// Health of the embedding service and Pinecone, and the path each query takes
+    search_router: SearchRouter,
+    /// Each query's last vector search candidates, served while the upstreams are down
+    stale_candidates: TtlCache<String, Vec<Book>>,
+    /// Budgets of the retrieval and ranking stages
+    retrieval: RetrievalConfig,
+    /// Ids of hidden books, left out of the full-text index and corpus snapshot
+    /// results until the indexer rebuilds them without the books
+    hidden_books: Arc<RwLock<HashSet<String>>>,
+    /// Trending lists by window in days and length
+    trending_cache: TtlCache<String, Vec<TrendingBook>>,
+    /// Windows and lengths trending has been asked for, recomputed by [`Self::refresh_trending`]
+    trending_windows: Arc<RwLock<HashSet<(i64, usize)>>>,
+    /// Seeds the random choices of exploration and shadow sampling
+    random: RandomSource,
+}
+
+/// Result cache key for a query's signature, or the trimmed query when it has none
+fn cache_key(query: &str, top_k: usize, params: &RankingParams) -> String {
+    if *params == RankingParams::default() {
+        format!("{}:{}", query, top_k)
+    } else {
+        format!("{}:{}:{:?}", query, top_k, params)
+    }
+}
+
+impl RecommendationService {
+    pub fn new(
+        sentence_encoder: impl Embedder + 'static,
+        pinecone: impl VectorStore + 'static,
+    ) -> Self {
+        let semantic_classifier = SemanticClassifier::new().unwrap_or_else(|e| {
+            warn!(
+                "Failed to initialize semantic classifier: {}. Using fallback.",
+                e
+            );
+            SemanticClassifier::new().unwrap()
+        });
+        Self {
+            sentence_encoder: Arc::new(sentence_encoder),
+            pinecone: Arc::new(pinecone),
+            result_cache: TtlCache::new(
+                "recommendation_results",
+                RESULT_CACHE_CAPACITY,
+                Duration::from_secs(CACHE_TTL_SECONDS),
+            ),
+            prewarmed: Arc::new(std::sync::atomic::AtomicBool::new(false)),
+            query_enhancer: QueryEnhancer::new(),
+            semantic_classifier,
+            slow_query_log: SlowQueryLog::default(),
+            author_aliases: Arc::new(AuthorAliases::default()),
+            corpus_stats: None,
+            user_data: None,
+            graph: None,
+            experiments: Arc::new(Experiments::default()),
+            feature_flags: FeatureFlags::default(),
+            shadow: ShadowRanking::default(),
+            post_filters: Arc::new(PostFilters::default()),
+            warmup_queries: Arc::new(
+                DEFAULT_WARMUP_QUERIES
+                    .iter()
+                    .map(|query| query.to_string())
+                    .collect(),
+            ),
+            compute_queue: Some(ComputeQueue::new(
+                DEFAULT_MAX_CONCURRENT_RECOMMENDATIONS,
+                DEFAULT_MAX_QUEUED_RECOMMENDATIONS,
+            )),
+            explainer: Explainer::default(),
+            query_parser: QueryParser::default(),
+            query_expander: QueryExpander::default(),
+            sparse_keywords: false,
+            fulltext: None,
+            search_router: SearchRouter::default(),
+            stale_candidates: TtlCache::new(
+                "stale_candidates",
+                RESULT_CACHE_CAPACITY,
+                STALE_CANDIDATE_TTL,
+            ),
+            retrieval: RetrievalConfig::default(),
+            hidden_books: Arc::new(RwLock::new(HashSet::new())),
+            trending_cache: TtlCache::new("trending", TRENDING_CACHE_CAPACITY, TRENDING_CACHE_TTL),
+            trending_windows: Arc::new(RwLock::new(HashSet::new())),
+            random: RandomSource::default(),
+        }
+    }
+
+    /// Search the index's keyword vectors when the query can't be embedded
+    pub fn with_sparse_keywords(mut self, enabled: bool) -> Self {
+        self.sparse_keywords = enabled;
+        self
+    }
+
+    /// Size the retrieval and ranking stages with `retrieval`
+    pub fn with_retrieval(mut self, retrieval: RetrievalConfig) -> Self {
+        self.retrieval = retrieval;
+        self
+    }
+
+    /// Compute at most `max` uncached queries at once, or any number if 0
+    pub fn with_max_concurrent_recommendations(mut self, max: usize) -> Self {
+        let max_queued = self
+            .compute_queue
+            .as_ref()
+            .map_or(DEFAULT_MAX_QUEUED_RECOMMENDATIONS, ComputeQueue::max_queued);
+        self.compute_queue = (max > 0).then(|| ComputeQueue::new(max, max_queued));
+        self
+    }
+
+    /// Shed new requests once `max` queries are waiting for a turn, or never if 0
+    pub fn with_max_queued_recommendations(mut self, max: usize) -> Self {
+        self.compute_queue = self
+            .compute_queue
+            .take()
+            .map(|queue| queue.with_max_queued(max));
+        self
+    }
+
+    /// Share cached results with other instances through `shared`
+    pub fn with_shared_cache(mut self, shared: Arc<dyn SharedCache>) -> Self {
+        self.result_cache = self.result_cache.with_shared(shared);
+        self
+    }
+
+    /// Use the given slow query log instead of the default one
+    pub fn with_slow_query_log(mut self, slow_query_log: SlowQueryLog) -> Self {
+        self.slow_query_log = slow_query_log;
+        self
+    }
+
+    /// Resolve author names in queries through this alias map
+    pub fn with_author_aliases(mut self, author_aliases: AuthorAliases) -> Self {
+        self.author_aliases = Arc::new(author_aliases);
+        self
+    }
+
+    /// Answer title suggestions, quoted phrases and fallback searches from `fulltext`
+    pub fn with_fulltext(mut self, fulltext: FullTextIndex) -> Self {
+        self.fulltext = Some(Arc::new(fulltext));
+        self
+    }
+
+    /// Match authors and suggest titles from this catalog snapshot
+    pub fn with_corpus_stats(mut self, corpus_stats: CorpusStats) -> Self {
+        self.corpus_stats = Some(Arc::new(corpus_stats));
+        self
+    }
+
+    /// Read user ratings from this client to personalize results
+    pub fn with_user_data(mut self, user_data: SupabaseClient) -> Self {
+        self.user_data = Some(user_data);
+        self
+    }
+
+    /// Use the book graph to find books adjacent to the ones a user rated
+    pub fn with_graph(mut self, graph: impl GraphStore + 'static) -> Self {
+        self.graph = Some(Arc::new(graph));
+        self
+    }
+
+    /// Bucket requests into the variants of these A/B experiments
+    pub fn with_experiments(mut self, experiments: Experiments) -> Self {
+        self.experiments = Arc::new(experiments);
+        self
+    }
+
+    /// Switch features per tenant and user with these flags
+    pub fn with_feature_flags(mut self, feature_flags: FeatureFlags) -> Self {
+        self.feature_flags = feature_flags;
+        self
+    }
+
+    /// Rank a sample of queries again with a candidate ranker, for comparison
+    pub fn with_shadow(mut self, shadow: ShadowRanking) -> Self {
+        self.shadow = shadow;
+        self
+    }
+
+    /// Filter the ranked results of each tenant with these chains
+    pub fn with_post_filters(mut self, post_filters: PostFilters) -> Self {
+        self.post_filters = Arc::new(post_filters);
+        self
+    }
+
+    /// Write explanations of the top results with this explainer
+    pub fn with_explainer(mut self, explainer: Explainer) -> Self {
+        self.explainer = explainer;
+        self
+    }
+
+    /// Read query constraints with this parser's language model
+    pub fn with_query_parser(mut self, query_parser: QueryParser) -> Self {
+        self.query_parser = query_parser;
+        self
+    }
+
+    /// Make the random choices of each query repeatable, seeded by `seed`
+    pub fn with_random_seed(mut self, seed: u64) -> Self {
+        self.random = RandomSource::new(Some(seed));
+        self
+    }
+
+    /// Run these queries when warming the caches
+    pub fn with_warmup_queries(mut self, queries: Vec<String>) -> Self {
+        self.warmup_queries = Arc::new(queries);
+        self
+    }
+
+    /// The constraints the templates read from `query`, and the ones a language
+    /// model reads when `llm` is set and one is configured
+    pub async fn parse_query(
+        &self,
+        query: &str,
+        llm: bool,
+    ) -> Result<(EnhancedQuery, Option<QueryFilters>)> {
+        let trimmed_query = validate_query(query)?;
+        let enhanced = self.query_enhancer.enhance(trimmed_query);
+        let llm_filters = if llm {
+            self.query_parser.llm_filters(trimmed_query).await
+        } else {
+            None
+        };
+        Ok((enhanced, llm_filters))
+    }
+
+    /// Explain why each of the books `book_ids` fits `query`, in `locale`
+    ///
+    /// The books get the relevance indicators ranking would give them for the
+    /// query, so explanations match the ones of the query's top results.
+    pub async fn explain_books(
+        &self,
+        query: &str,
+        book_ids: &[String],
+        locale: Locale,
+        llm: bool,
+    ) -> Result<Vec<BookExplanation>> {
+        let trimmed_query = validate_query(query)?;
+        let mut found = self.pinecone.fetch_books(book_ids).await?;
+        let missing: Vec<&str> = book_ids
+            .iter()
+            .filter(|id| !found.contains_key(*id))
+            .map(String::as_str)
+            .collect();
+        if !missing.is_empty() {
+            return Err(ApiError::NotFound(format!(
+                "Books not indexed: {}",
+                missing.join(", ")
+            )));
+        }
+        let mut books: Vec<Book> = book_ids.iter().filter_map(|id| found.remove(id)).collect();
+
+        let query_info = self.analyze_query(trimmed_query).await;
+        annotate_relevance(&mut books, &query_info);
+        let mut books: Vec<Arc<Book>> = books.into_iter().map(Arc::new).collect();
+        if llm {
+            self.explainer
+                .explain(query, locale, None, &mut books)
+                .await;
+        } else {
+            self.explainer
+                .templates_only()
+                .explain(query, locale, None, &mut books)
+                .await;
+        }
+
+        Ok(books
+            .iter()
+            .map(|book| BookExplanation {
+                book_id: book.id.clone().unwrap_or_default(),
+                explanation: book.explanation.clone().unwrap_or_default(),
+            })
+            .collect())
+    }
+
+    /// Explain why the top few `books` fit `query`, in `locale`; without `llm` the
+    /// templates write them even when a language model is configured
+    ///
+    /// With a signed-in `user_id`, generated explanations may mention the books
+    /// the user rated highest. Returns how many of the explanations are generic.
+    pub async fn explain_top(
+        &self,
+        query: &str,
+        locale: Locale,
+        user_id: Option<&str>,
+        books: &mut [Arc<Book>],
+        llm: bool,
+    ) -> usize {
+        if llm && self.explainer.has_backend() {
+            let reader = match user_id {
+                Some(user_id) => self.reader(user_id).await,
+                None => None,
+            };
+            self.explainer
+                .explain_top(query, locale, reader.as_ref(), books)
+                .await
+        } else {
+            self.explainer
+                .templates_only()
+                .explain_top(query, locale, None, books)
+                .await
+        }
+    }
+
+    /// The titles of the books `user_id` rated highest, for personalized
+    /// explanations; `None` without user data or highly rated books
+    async fn reader(&self, user_id: &str) -> Option<Reader> {
+        let user_data = self.user_data.as_ref()?;
+        let mut ratings = match user_data
+            .ratings(user_id, Some(MAX_RATINGS_CONSIDERED))
+            .await
+        {
+            Ok(ratings) => ratings,
+            Err(e) => {
+                warn!("Failed to load ratings for user {}: {}", user_id, e);
+                return None;
+            }
+        };
+        ratings.retain(|rating| rating.rating >= 4);
+        ratings.sort_by(|a, b| {
+            b.rating
+                .cmp(&a.rating)
+                .then(b.updated_at.cmp(&a.updated_at))
+        });
+        let ids: Vec<String> = ratings
+            .into_iter()
+            .take(MAX_READER_TITLES)
+            .map(|rating| rating.book_id)
+            .collect();
+        if ids.is_empty() {
+            return None;
+        }
+
+        let metadata = match self.pinecone.fetch_metadata(&ids).await {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                warn!("Failed to load the rated books of user {}: {}", user_id, e);
+                return None;
+            }
+        };
+        let liked_titles: Vec<String> = ids
+            .iter()
+            .filter_map(|id| metadata.get(id)?.get("title")?.as_str())
+            .map(str::to_string)
+            .collect();
+        (!liked_titles.is_empty()).then_some(Reader { liked_titles })
+    }
+
+    /// Flags that switch features per tenant and user
+    pub fn feature_flags(&self) -> &FeatureFlags {
+        &self.feature_flags
+    }
+
+    /// Post-filters of the requests from `tenant`
+    pub fn post_filters(&self, tenant: Option<&str>) -> &PostFilterChain {
+        self.post_filters.chain(tenant)
+    }
+
+    /// Whether the post-filters of `tenant` allow `book`
+    pub fn allows(&self, book: &Book, tenant: Option<&str>) -> bool {
+        self.post_filters(tenant)
+            .allows(book, &FilterContext { tenant })
+    }
+
+    /// Leave out the books the post-filters of `tenant` don't allow
+    ///
+    /// Every path that returns books to a caller runs its results through this,
+    /// so a tenant's blocklists and licensing rules hold whichever endpoint the
+    /// books come from.
+    pub fn retain_allowed<B: std::borrow::Borrow<Book>>(
+        &self,
+        books: &mut Vec<B>,
+        tenant: Option<&str>,
+    ) {
+        let chain = self.post_filters(tenant);
+        if chain.is_empty() {
+            return;
+        }
+        let context = FilterContext { tenant };
+        books.retain(|book| chain.allows(book.borrow(), &context));
+    }
+
+    /// Leave out the items the post-filters of `tenant` don't allow, judged by
+    /// the stored book of each item's id
+    ///
+    /// For results that don't carry the book's full metadata, like graph nodes
+    /// and corpus snapshot entries. Items whose book isn't in the index are left
+    /// out too, since the filters can't vouch for them.
+    pub async fn retain_allowed_ids<T>(
+        &self,
+        items: &mut Vec<T>,
+        tenant: Option<&str>,
+        id: impl Fn(&T) -> &str,
+    ) -> Result<()> {
+        let chain = self.post_filters(tenant);
+        if chain.is_empty() || items.is_empty() {
+            return Ok(());
+        }
+        let ids: Vec<String> = items.iter().map(|item| id(item).to_string()).collect();
+        let books = self.pinecone.fetch_books(&ids).await?;
+        let context = FilterContext { tenant };
+        items.retain(|item| {
+            books
+                .get(id(item))
+                .is_some_and(|book| chain.allows(book, &context))
+        });
+        Ok(())
+    }
+
+    /// Comparisons of the shadow ranker with the served rankings
+    pub fn shadow(&self) -> &ShadowRanking {
+        &self.shadow
+    }
+
+    /// Experiment variants for a user or session id and the ranking parameters they set
+    pub fn assign_experiments(&self, unit_id: &str) -> (Vec<ExperimentAssignment>, RankingParams) {
+        self.experiments.assign(unit_id)
+    }
+
+    /// Queries that exceeded the slow query threshold
+    pub fn slow_query_log(&self) -> &SlowQueryLog {
+        &self.slow_query_log
+    }
+
+    /// A generator for the random choices made answering `query`; the same
+    /// for every call with a random seed configured
+    pub fn rng(&self, query: &str) -> fastrand::Rng {
+        self.random.rng(query)
+    }
+
+    /// Whether the startup prewarm has finished
+    pub fn is_prewarmed(&self) -> bool {
+        self.prewarmed.load(std::sync::atomic::Ordering::Acquire)
+    }
+
+    /// Health of the upstreams the search depends on
+    pub fn upstream_statuses(&self) -> Vec<UpstreamStatus> {
+        self.search_router.statuses()
+    }
+
+    /// Last probe of the vector index's endpoint, once prewarm has run one
+    pub fn endpoint_probes(&self) -> Vec<EndpointProbe> {
+        self.pinecone.endpoint_status().into_iter().collect()
+    }
+
+    /// Catalog snapshot loaded at startup, if any
+    pub fn corpus_stats(&self) -> Option<&CorpusStats> {
+        self.corpus_stats.as_deref()
+    }
+
+    /// Whether titles can be suggested, from the full-text index or the corpus snapshot
+    pub fn can_suggest_titles(&self) -> bool {
+        self.fulltext.is_some() || self.corpus_stats.is_some()
+    }
+
+    /// Up to `limit` titles for autocomplete, most rated first
+    ///
+    /// The full-text index covers the whole catalog and matches the prefix's
+    /// words anywhere in a title; the corpus snapshot only keeps the most rated
+    /// books and matches the start. None when neither is loaded.
+    pub fn suggest_titles(&self, prefix: &str, limit: usize) -> Option<Vec<TitleEntry>> {
+        let visible = |titles: Vec<TitleEntry>| -> Vec<TitleEntry> {
+            titles
+                .into_iter()
+                .filter(|entry| !self.is_hidden(entry.id.as_deref()))
+                .collect()
+        };
+        if let Some(fulltext) = &self.fulltext {
+            match fulltext.titles_with_prefix(prefix, limit) {
+                Ok(titles) => return Some(visible(titles)),
+                Err(e) => warn!("Full-text title suggestions failed: {}", e),
+            }
+        }
+        self.corpus_stats.as_ref().map(|stats| {
+            visible(
+                stats
+                    .titles_with_prefix(prefix, limit)
+                    .into_iter()
+                    .cloned()
+                    .collect(),
+            )
+        })
+    }
+
+    /// Whether the book was hidden since the full-text index and corpus snapshot were built
+    pub fn is_hidden(&self, book_id: Option<&str>) -> bool {
+        book_id
+            .is_some_and(|id| recover_lock(self.hidden_books.read(), "hidden books").contains(id))
+    }
+
+    /// `books` without the hidden ones
+    fn without_hidden(&self, mut books: Vec<Book>) -> Vec<Book> {
+        books.retain(|book| !self.is_hidden(book.id.as_deref()));
+        books
+    }
+
+    /// Hide a book from searches, recommendations, listings and the graph, or
+    /// restore it; its vector and graph node are kept either way
+    pub async fn set_hidden(&self, book_id: &str, hidden: bool) -> Result<()> {
+        let indexed = self
+            .pinecone
+            .fetch_metadata(&[book_id.to_string()])
+            .await?
+            .contains_key(book_id);
+        if !indexed {
+            return Err(ApiError::NotFound(format!("Book {} not found", book_id)));
+        }
+
+        self.pinecone.set_hidden(book_id, hidden).await?;
+        if let Some(graph) = &self.graph {
+            if !graph.set_hidden(book_id, hidden).await? {
+                debug!("Book {} has no graph node to update", book_id);
+            }
+        }
+        {
+            let mut hidden_books = recover_lock(self.hidden_books.write(), "hidden books");
+            if hidden {
+                hidden_books.insert(book_id.to_string());
+            } else {
+                hidden_books.remove(book_id);
+            }
+        }
+
+        // Cached results may still list the book, or leave it out
+        self.clear_caches().await;
+        info!(
+            "Book {} {}",
+            book_id,
+            if hidden { "hidden" } else { "restored" }
+        );
+        Ok(())
+    }
+
+    /// Merge each patch over its book's stored metadata as a new version, without
+    /// re-embedding, and record the versions in `history` as written by `source`
+    ///
+    /// Patches are applied independently; the result of each is reported rather
+    /// than failing the others.
+    pub async fn patch_books(
+        &self,
+        patches: &[BookPatch],
+        source: &str,
+        history: &BookHistory,
+    ) -> Result<Vec<PatchResult>> {
+        let ids: Vec<String> = patches.iter().map(|patch| patch.id.clone()).collect();
+        let stored = self.pinecone.fetch_metadata(&ids).await?;
+        let now = chrono::Utc::now().to_rfc3339();
+
+        let mut seen = HashSet::new();
+        let mut results = Vec::with_capacity(patches.len());
+        let mut changes = Vec::new();
+        for patch in patches {
+            if !seen.insert(patch.id.as_str()) {
+                results.push(
+                    PatchResult::new(&patch.id, PatchStatus::Invalid)
+                        .with_error("the book is patched more than once"),
+                );
+                continue;
+            }
+            if let Err(e) = validate_patch(patch) {
+                results.push(PatchResult::new(&patch.id, PatchStatus::Invalid).with_error(e));
+                continue;
+            }
+            let Some(stored) = stored.get(&patch.id) else {
+                results.push(PatchResult::new(&patch.id, PatchStatus::NotFound));
+                continue;
+            };
+
+            let patched = match apply_patch(patch, stored, &now) {
+                Ok(Some(patched)) => patched,
+                Ok(None) => {
+                    results.push(
+                        PatchResult::new(&patch.id, PatchStatus::Unchanged)
+                            .with_version(stored_version(stored)),
+                    );
+                    continue;
+                }
+                Err(e) => {
+                    results.push(PatchResult::new(&patch.id, PatchStatus::Invalid).with_error(e));
+                    continue;
+                }
+            };
+
+            if let Err(e) = self
+                .pinecone
+                .update_metadata(&patch.id, &patched.set_metadata)
+                .await
+            {
+                warn!("Failed to patch book {}: {}", patch.id, e);
+                results.push(PatchResult::new(&patch.id, PatchStatus::Failed).with_error(e));
+                continue;
+            }
+            // The index is the source of truth; the graph catches up on its next sync
+            if let Some(graph) = &self.graph {
+                if let Err(e) = graph.update_book(&patched.book).await {
+                    warn!("Failed to patch graph node of book {}: {}", patch.id, e);
+                }
+            }
+            results.push(
+                PatchResult::new(&patch.id, PatchStatus::Updated)
+                    .with_version(patched.change.version),
+            );
+            changes.push(patched.change);
+        }
+
+        if !changes.is_empty() {
+            if let Err(e) = history.record(source, &changes).await {
+                warn!("Failed to record {} book versions: {}", changes.len(), e);
+            }
+            // Cached results still carry the old metadata
+            self.clear_caches().await;
+        }
+        Ok(results)
+    }
+
+    /// Ids of every indexed book, in id order; needs a serverless index
+    pub async fn indexed_book_ids(&self) -> Result<Vec<String>> {
+        let mut ids = self.pinecone.list_ids().await?;
+        ids.sort();
+        Ok(ids)
+    }
+
+    /// Check the covers of a batch of indexed books and patch a working https
+    /// cover over broken or http-only thumbnails; returns the counts
+    ///
+    /// Books without any working cover are left as they are, since a patch
+    /// can't remove a field; the cover proxy answers 404 for them.
+    pub async fn revalidate_covers(
+        &self,
+        resolver: &CoverResolver,
+        batch: &[String],
+        history: &BookHistory,
+    ) -> Result<CoverStats> {
+        if batch.is_empty() {
+            return Ok(CoverStats::default());
+        }
+
+        let stored = self.pinecone.fetch_metadata(batch).await?;
+        let mut books: Vec<Book> = stored
+            .iter()
+            .filter_map(|(book_id, metadata)| {
+                stored_book(book_id, metadata)
+                    .inspect_err(|e| warn!("Skipping cover check of book {}: {}", book_id, e))
+                    .ok()
+            })
+            .collect();
+        let thumbnails: Vec<Option<String>> =
+            books.iter().map(|book| book.thumbnail.clone()).collect();
+        let stats = resolver.resolve_all(&mut books).await;
+
+        let patches: Vec<BookPatch> = books
+            .iter()
+            .zip(thumbnails)
+            .filter_map(|(book, before)| {
+                let cover = book.thumbnail.clone()?;
+                if before.as_deref().map(str::trim) == Some(cover.as_str()) {
+                    return None;
+                }
+                let mut fields = serde_json::Map::new();
+                fields.insert("thumbnail".to_string(), serde_json::Value::String(cover));
+                Some(BookPatch {
+                    id: book.id.clone()?,
+                    fields,
+                })
+            })
+            .collect();
+        if !patches.is_empty() {
+            info!("Replacing {} broken covers", patches.len());
+            self.patch_books(&patches, "covers", history).await?;
+        }
+        Ok(stats)
+    }
+
+    /// Embed a book again from its current metadata and upsert the new vector,
+    /// so search matches it on a corrected description or enriched fields;
+    /// returns the book's version afterwards
+    ///
+    /// The book is embedded the way the indexer would: from the same searchable
+    /// text, with its moods tagged again and its hidden flag kept.
+    pub async fn reembed_book(&self, book_id: &str, history: &BookHistory) -> Result<i32> {
+        let stored = self
+            .pinecone
+            .fetch_metadata(&[book_id.to_string()])
+            .await?
+            .remove(book_id)
+            .ok_or_else(|| ApiError::NotFound(format!("Book {} not found", book_id)))?;
+        let book = stored_book(book_id, &stored)?;
+
+        let moods = self.mood_classifier().await;
+        let version = self
+            .embed_and_upsert(&book, &stored, moods.as_ref(), "reembed", history)
+            .await?;
+
+        // Cached results were ranked with the old vector
+        self.clear_caches().await;
+        info!("Re-embedded book {} at version {}", book_id, version);
+        Ok(version)
+    }
+
+    /// Fill the missing descriptions, covers, page counts, categories and regions of the
+    /// given books from Google Books and Open Library, re-embedding the ones
+    /// that changed; runs as a background job
+    pub async fn enrich_books(
+        &self,
+        book_ids: &[String],
+        enricher: &mut MetadataEnricher,
+        history: &BookHistory,
+        job: &JobHandle,
+    ) -> Result<serde_json::Value> {
+        let stored = self.pinecone.fetch_metadata(book_ids).await?;
+        let mut stats = EnrichmentStats::default();
+        // Mood prototypes are only embedded once a book needs re-embedding
+        let mut moods: Option<Option<MoodClassifier>> = None;
+        let mut enriched = Vec::new();
+        let mut not_found = Vec::new();
+        let mut failed = Vec::new();
+
+        for (done, book_id) in book_ids.iter().enumerate() {
+            if job.is_cancelled() {
+                break;
+            }
+            let Some(stored) = stored.get(book_id) else {
+                not_found.push(book_id);
+                continue;
+            };
+            let mut book = match stored_book(book_id, stored) {
+                Ok(book) => book,
+                Err(e) => {
+                    warn!("Skipping enrichment of book {}: {}", book_id, e);
+                    failed.push(book_id);
+                    continue;
+                }
+            };
+
+            if enricher.enrich(&mut book, &mut stats).await {
+                if moods.is_none() {
+                    moods = Some(self.mood_classifier().await);
+                }
+                let classifier = moods.as_ref().and_then(Option::as_ref);
+                match self
+                    .embed_and_upsert(&book, stored, classifier, "enrichment", history)
+                    .await
+                {
+                    Ok(_) => {
+                        if let Some(graph) = &self.graph {
+                            if let Err(e) = graph.update_book(&book).await {
+                                warn!("Failed to enrich graph node of book {}: {}", book_id, e);
+                            }
+                        }
+                        enriched.push(book_id);
+                    }
+                    Err(e) => {
+                        warn!("Failed to re-embed enriched book {}: {}", book_id, e);
+                        failed.push(book_id);
+                    }
+                }
+            }
+
+            job.progress(
+                done + 1,
+                book_ids.len(),
+                format!("Checked {} of {} books", done + 1, book_ids.len()),
+            )
+            .await;
+        }
+
+        if !enriched.is_empty() {
+            // Cached results still carry the old metadata and vectors
+            self.clear_caches().await;
+        }
+        Ok(serde_json::json!({
+            "stats": stats,
+            "enriched": enriched,
+            "not_found": not_found,
+            "failed": failed,
+        }))
+    }
+
+    /// Bring the graph in line with the index: add or update a node for every
+    /// indexed book, carry over hidden flags and delete the nodes of books no
+    /// longer indexed; runs as a background job
+    ///
+    /// An incremental sync only adds the books the graph is missing, leaving
+    /// existing nodes as they are, so it's cheap enough to run on a schedule.
+    pub async fn sync_graph(
+        &self,
+        job: &JobHandle,
+        incremental: bool,
+    ) -> Result<serde_json::Value> {
+        let graph = self
+            .graph
+            .as_ref()
+            .ok_or_else(|| ApiError::InternalError("Neo4j is not configured".to_string()))?;
+        let ids = self.pinecone.list_ids().await?;
+        let graph_ids: HashSet<String> = graph.list_book_ids().await?.into_iter().collect();
+        let to_sync: Vec<String> = if incremental {
+            ids.iter()
+                .filter(|id| !graph_ids.contains(id.as_str()))
+                .cloned()
+                .collect()
+        } else {
+            ids.clone()
+        };
+        info!(
+            "Syncing {} of {} indexed books to the graph",
+            to_sync.len(),
+            ids.len()
+        );
+
+        let mut synced = 0;
+        let mut done = 0;
+        for chunk in to_sync.chunks(GRAPH_SYNC_BATCH_SIZE) {
+            if job.is_cancelled() {
+                break;
+            }
+            let stored = self.pinecone.fetch_metadata(chunk).await?;
+            for (book_id, metadata) in &stored {
+                let book = match stored_book(book_id, metadata) {
+                    Ok(book) => book,
+                    Err(e) => {
+                        warn!("Skipping graph sync of book {}: {}", book_id, e);
+                        continue;
+                    }
+                };
+                if let Err(e) = graph.add_book(&book).await {
+                    warn!("Failed to sync graph node of book {}: {}", book_id, e);
+                    continue;
+                }
+                graph.set_hidden(book_id, is_hidden(metadata)).await?;
+                synced += 1;
+            }
+            done += chunk.len();
+            job.progress(
+                done,
+                to_sync.len(),
+                format!("Synced {} of {} books", done, to_sync.len()),
+            )
+            .await;
+        }
+
+        // An empty listing would otherwise wipe the whole graph
+        let mut removed = 0;
+        if !job.is_cancelled() && !ids.is_empty() {
+            let indexed: HashSet<&str> = ids.iter().map(String::as_str).collect();
+            let stale: Vec<String> = graph_ids
+                .into_iter()
+                .filter(|id| !indexed.contains(id.as_str()))
+                .collect();
+            if !stale.is_empty() {
+                removed = graph.delete_books(&stale).await?;
+            }
+        }
 
-// Cache duration in seconds
-const CACHE_TTL_SECONDS: u64 = 300; // 5 minutes
+        // Cached graph results may list removed or changed books
+        if synced > 0 || removed > 0 {
+            self.clear_caches().await;
+        }
+        Ok(serde_json::json!({
+            "incremental": incremental,
+            "indexed": ids.len(),
+            "synced": synced,
+            "removed": removed,
+        }))
+    }
 
-#[derive(Clone)]
-pub struct RecommendationService {
-    sentence_encoder: Arc<HuggingFaceEmbedder>,
-    pinecone: Pinecone,
-    // Use thread-safe cache with read-write lock for better performance
-    result_cache: std::sync::Arc<RwLock<HashMap<String, CacheEntry>>>,
-    prewarmed: Arc<std::sync::atomic::AtomicBool>,
-    query_enhancer: QueryEnhancer,
-    semantic_classifier: SemanticClassifier,
-}
+    /// Mood classifier for re-embedded books; moods come from keywords only
+    /// when its prototypes can't be embedded
+    async fn mood_classifier(&self) -> Option<MoodClassifier> {
+        match MoodClassifier::new(self.sentence_encoder.as_ref()).await {
+            Ok(classifier) => Some(classifier),
+            Err(e) => {
+                warn!(
+                    "Failed to embed mood prototypes, tagging moods from keywords only: {}",
+                    e
+                );
+                None
+            }
+        }
+    }
 
-impl RecommendationService {
-    pub fn new(sentence_encoder: HuggingFaceEmbedder, pinecone: Pinecone) -> Self {
-        let semantic_classifier = SemanticClassifier::new().unwrap_or_else(|e| {
-            warn!(
-                "Failed to initialize semantic classifier: {}. Using fallback.",
-                e
-            );
-            SemanticClassifier::new().unwrap()
-        });
-        Self {
-            sentence_encoder: Arc::new(sentence_encoder),
-            pinecone,
-            result_cache: std::sync::Arc::new(RwLock::new(HashMap::new())),
-            prewarmed: Arc::new(std::sync::atomic::AtomicBool::new(false)),
-            query_enhancer: QueryEnhancer::new(),
-            semantic_classifier,
+    /// Embed `book` and upsert it over its `stored` metadata as the indexer
+    /// would, recording a new version written by `source` when its content
+    /// changed; returns the book's version afterwards
+    async fn embed_and_upsert(
+        &self,
+        book: &Book,
+        stored: &serde_json::Value,
+        moods: Option<&MoodClassifier>,
+        source: &str,
+        history: &BookHistory,
+    ) -> Result<i32> {
+        let book_id = book.id.as_deref().unwrap_or_default();
+        let values = self
+            .sentence_encoder
+            .encode(&create_searchable_text(book))
+            .await?;
+
+        let (model_name, _) = self.sentence_encoder.model_info();
+        let mut metadata = serde_json::to_value(book)?;
+        metadata[CONTENT_HASH_FIELD] = serde_json::Value::String(content_hash(book, &model_name)?);
+        metadata["moods"] = serde_json::json!(tag_moods(book, Some(&values), moods));
+        if is_hidden(stored) {
+            metadata[HIDDEN_FIELD] = serde_json::Value::Bool(true);
+        }
+        let change = stamp_version(
+            book_id,
+            Some(stored),
+            &mut metadata,
+            &chrono::Utc::now().to_rfc3339(),
+        );
+        let version = stored_version(&metadata);
+
+        self.pinecone
+            .upsert_vectors(&[UpsertVector {
+                id: book_id.to_string(),
+                sparse_values: self.sparse_keywords.then(|| encode_book(book)).flatten(),
+                values,
+                metadata,
+            }])
+            .await?;
+        if let Some(change) = change {
+            if let Err(e) = history.record(source, &[change]).await {
+                warn!(
+                    "Failed to record version {} of book {}: {}",
+                    version, book_id, e
+                );
+            }
         }
+        Ok(version)
+    }
+
+    /// Ids of the hidden books as stored in the index; also refreshes the ones
+    /// left out of local results, which other instances may have changed
+    pub async fn hidden_books(&self) -> Result<Vec<String>> {
+        let mut ids = self.pinecone.hidden_ids().await?;
+        ids.sort();
+        *recover_lock(self.hidden_books.write(), "hidden books") = ids.iter().cloned().collect();
+        Ok(ids)
     }
 
     /// Warms up the recommendation service to mitigate cold start issues
@@ -90,7 +1287,7 @@ impl RecommendationService {
     /// This method:
     /// 1. Initializes the ML embedder
     /// 2. Establishes connection to Pinecone with a test query
-    /// 3. Executes a sample query to prepare the entire pipeline
+    /// 3. Runs the warmup queries to prepare the pipeline and fill the result cache
     ///
     /// Returns true if this was the first warm-up operation
     pub async fn prewarm(&self) -> Result<bool> {
@@ -102,10 +1299,13 @@ impl RecommendationService {
 
         info!("Warming up RecommendationService...");
 
-        // Step 1: Initialize the sentence encoder
+        // Step 1: Resolve and probe the index host; failures are reported by readiness
+        self.pinecone.probe_endpoint().await;
+
+        // Step 2: Initialize the sentence encoder
         let _encoder_prewarmed = self.sentence_encoder.prewarm().await?;
 
-        // Step 2: Initialize Pinecone connection with a simple metadata query
+        // Step 3: Initialize Pinecone connection with a simple metadata query
         let pinecone_test = self
             .pinecone
             .query_metadata("title", "test", false, 1)
@@ -118,35 +1318,13 @@ impl RecommendationService {
             // Continue anyway - this might be a temporary issue
         }
 
-        // Step 3: Prime the recommendation pipeline with a common query
-        // This helps initialize internal caches and prepares everything
-        let test_queries = ["fantasy books", "science fiction", "mystery novels"];
-
-        // Simple selection without fastrand dependency
-        let now = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs();
-        let test_query = test_queries[now as usize % test_queries.len()];
-        debug!("Running test query for prewarm: '{}'", test_query);
-
-        // Use internal implementation to avoid query intent caching
-        let _embedding = self.sentence_encoder.encode(test_query).await?;
-
-        // Create a dummy query intent
-        let intent = QueryIntent::General {
-            query: test_query.to_string(),
-        };
-
-        // Create a dummy search strategy for prewarming
-        let strategy = SearchStrategy {
-            metadata_filter: None,
-            semantic_weight: 1.0,
-            hybrid_search: true,
-        };
+        // Books hidden before this instance started are still in the local indexes
+        if let Err(e) = self.hidden_books().await {
+            warn!("Failed to load the hidden books: {}", e);
+        }
 
-        // Use a small limit for the test query
-        let _ = self.perform_hybrid_search(&intent, &strategy, 3).await;
+        // Step 4: Prime the recommendation pipeline and result cache with the warmup queries
+        self.warm_caches().await;
 
         // Mark as initialized
         self.prewarmed
@@ -156,63 +1334,301 @@ impl RecommendationService {
         Ok(true)
     }
 
+    /// Run the warmup queries, replacing their cached results with fresh ones
+    ///
+    /// Returns how many queries succeeded. Failures are logged and skipped.
+    pub async fn warm_caches(&self) -> usize {
+        // Sparse queries are only expanded once the vocabulary is embedded
+        if let Err(e) = self
+            .query_expander
+            .load(self.sentence_encoder.as_ref())
+            .await
+        {
+            warn!("Failed to embed the query expansion vocabulary: {}", e);
+        }
+
+        let mut warmed = 0;
+        for query in self.warmup_queries.iter() {
+            debug!("Running warmup query: '{}'", query);
+            match self
+                .preload_query(query, Duration::from_secs(CACHE_TTL_SECONDS))
+                .await
+            {
+                Ok(_) => warmed += 1,
+                Err(e) => warn!("Warmup query '{}' failed: {}", query, e),
+            }
+        }
+        info!(
+            "Warmed {} of {} warmup queries",
+            warmed,
+            self.warmup_queries.len()
+        );
+        warmed
+    }
+
+    /// Compute fresh results for a query at the default top_k and keep them cached for `ttl`
+    ///
+    /// Returns the number of results.
+    pub async fn preload_query(&self, query: &str, ttl: Duration) -> Result<usize> {
+        let params = RankingParams::default();
+        let key = self.result_cache_key(query.trim(), DEFAULT_TOP_K, &params);
+        self.result_cache.remove_shared(&key).await;
+
+        let (books, _, serving) = self
+            .get_recommendations_served(query, DEFAULT_TOP_K, &params)
+            .await?;
+
+        let count = books.len();
+        if !serving.degraded && serving.relaxed.is_empty() {
+            self.result_cache.insert_shared(key, books, ttl).await;
+        }
+        Ok(count)
+    }
+
+    /// Result cache key for a trimmed query
+    ///
+    /// Keyed on the enhanced query's signature, so rewordings of a query share results.
+    fn result_cache_key(&self, query: &str, top_k: usize, params: &RankingParams) -> String {
+        let signature = self.query_enhancer.enhance(query).signature();
+        cache_key(signature.as_deref().unwrap_or(query), top_k, params)
+    }
+
+    /// Number of fresh entries in the local result cache
+    pub fn cached_result_count(&self) -> usize {
+        self.result_cache.len()
+    }
+
     pub async fn get_recommendations(
         &self,
         query: &str,
         top_k: usize,
-    ) -> Result<(Vec<Book>, Vec<String>)> {
-        let trimmed_query = query.trim();
-        if trimmed_query.is_empty() {
-            return Err(ApiError::InvalidInput("Query cannot be empty".into()));
-        }
+    ) -> Result<(Vec<Arc<Book>>, Vec<String>)> {
+        self.get_recommendations_with_params(query, top_k, &RankingParams::default())
+            .await
+    }
 
-        // Validate query
-        if trimmed_query.len() < 3 {
-            return Err(ApiError::InvalidInput(
-                "Query too short (minimum 3 characters)".into(),
-            ));
-        }
+    /// Recommendations ranked with the parameters of an experiment variant
+    pub async fn get_recommendations_with_params(
+        &self,
+        query: &str,
+        top_k: usize,
+        params: &RankingParams,
+    ) -> Result<(Vec<Arc<Book>>, Vec<String>)> {
+        self.get_recommendations_served(query, top_k, params)
+            .await
+            .map(|(books, tags, _)| (books, tags))
+    }
 
-        if trimmed_query.len() > 200 {
-            return Err(ApiError::InvalidInput(
-                "Query too long (maximum 200 characters)".into(),
-            ));
-        }
+    /// Recommendations ranked with `params`, with how the query was answered
+    pub async fn get_recommendations_served(
+        &self,
+        query: &str,
+        top_k: usize,
+        params: &RankingParams,
+    ) -> Result<(Vec<Arc<Book>>, Vec<String>, QueryServing)> {
+        let started = Instant::now();
+        let mut trace = QueryTrace::default();
+
+        let result = self
+            .get_recommendations_traced(query, top_k, params, &mut trace)
+            .await;
+
+        self.slow_query_log.record(SlowQueryEntry {
+            query: query.trim().to_string(),
+            top_k,
+            intent: trace.intent,
+            strategy: trace.strategy,
+            cache_hit: trace.cache_hit,
+            used_fallback: trace.used_fallback,
+            search_path: trace.search_path,
+            result_count: result.as_ref().ok().map(|(books, _)| books.len()),
+            error: result.as_ref().err().map(|e| e.to_string()),
+            total_ms: started.elapsed().as_millis() as u64,
+            timings: trace.timings,
+            recorded_at: chrono::Utc::now().to_rfc3339(),
+        });
+
+        result.map(|(books, query_info)| {
+            let serving = QueryServing {
+                cache_hit: trace.cache_hit,
+                degraded: trace.used_fallback,
+                model_used: (!trace.used_fallback).then(|| {
+                    params
+                        .embedding_model
+                        .clone()
+                        .unwrap_or_else(|| self.sentence_encoder.model_info().0)
+                }),
+                tags: query_info.structured_tags(),
+                refinements: suggest_refinements(&query_info, &books),
+                relaxed: trace.relaxed,
+                search_path: trace.search_path,
+                pattern: query_info.pattern.clone(),
+            };
+            (books, query_info.semantic_tags, serving)
+        })
+    }
+
+    async fn get_recommendations_traced(
+        &self,
+        query: &str,
+        top_k: usize,
+        params: &RankingParams,
+        trace: &mut QueryTrace,
+    ) -> Result<(Vec<Arc<Book>>, SemanticQueryInfo)> {
+        let trimmed_query = validate_query(query)?;
 
-        // Check cache for existing results
-        let cache_key = format!("{}:{}", trimmed_query, top_k);
+        // Check cache for existing results; variants with other parameters rank differently
+        let cache_key = self.result_cache_key(trimmed_query, top_k, params);
         info!("Generated cache key: {}", cache_key);
 
-        // Try to read from cache first
-        if let Ok(cache) = self.result_cache.read() {
-            if let Some(entry) = cache.get(&cache_key) {
-                if entry.timestamp.elapsed() < Duration::from_secs(CACHE_TTL_SECONDS) {
-                    info!("CACHE HIT for query: {}", trimmed_query);
-                    // For cached results, extract keywords
-                    let query_info = self
-                        .semantic_classifier
-                        .analyze_query(trimmed_query)
-                        .await
-                        .unwrap_or_else(|e| {
-                            warn!("Failed to analyze query for cached result: {}", e);
-                            SemanticQueryInfo {
-                                original_query: trimmed_query.to_string(),
-                                themes: vec![],
-                                author: None,
-                                temporal_filter: None,
-                                is_similar_query: false,
-                                semantic_tags: vec![],
-                            }
-                        });
-                    return Ok((entry.results.clone(), query_info.semantic_tags));
-                }
-            }
+        // Try the local cache first, then the shared one if configured
+        let cached_results = self.result_cache.get_shared(&cache_key).await;
+
+        if let Some(results) = cached_results {
+            info!("CACHE HIT for query: {}", trimmed_query);
+            trace.cache_hit = true;
+            trace.search_path = SearchPath::Cached;
+            // Read the query as a miss would, so cached results get the same tags
+            let query_info = self.analyze_query(trimmed_query).await;
+            return Ok((results, query_info));
         }
 
         info!("CACHE MISS for query: {}", trimmed_query);
 
+        // Queue for a turn so bursts don't overload the embedder and Pinecone; the
+        // wait counts against the request deadline, so a query never waits out its whole budget
+        let _permit = match &self.compute_queue {
+            Some(queue) => Some(queue.turn(deadline::bounded(COMPUTE_QUEUE_TIMEOUT)).await?),
+            None => None,
+        };
+
+        let query_info = self.analyze_query(trimmed_query).await;
+
+        info!("Keyword extraction results:");
+        info!("  - Keywords: {:?}", query_info.themes);
+        info!("  - Author: {:?}", query_info.author);
+        info!("  - Temporal filter: {:?}", query_info.temporal_filter);
+        info!("  - Is similar query: {}", query_info.is_similar_query);
+        info!("  - Intent confidence: {:.2}", query_info.intent_confidence);
+        info!("  - Display tags: {:?}", query_info.semantic_tags);
+
+        // Convert to intent format
+        let intent = QueryIntent::from_query_info(&query_info);
+        info!(?intent, "Converted to intent format");
+        trace.intent = Some(intent.label().to_string());
+
+        // Get search strategy
+        let strategy = self.get_search_strategy(&intent, &query_info);
+        info!("Performing hybrid search with strategy: {:?}", strategy);
+        trace.strategy = Some(format!("{:?}", strategy));
+
+        let mut ranked_results = self
+            .search_ranked(trimmed_query, &query_info, top_k, params, trace)
+            .await?;
+
+        // Too few results meet the query's filters: drop them one at a time until
+        // enough do. The fallback search doesn't filter, so there's nothing to drop
+        let mut relaxed_info = query_info.clone();
+        for constraint in relaxable_constraints(&query_info) {
+            if trace.used_fallback || !is_weak(&ranked_results, &relaxed_info, top_k) {
+                break;
+            }
+            info!(
+                "Too few results for '{}', relaxing {:?}",
+                trimmed_query, constraint
+            );
+            relax(&mut relaxed_info, constraint);
+            trace.relaxed.push(constraint);
+            ranked_results = self
+                .search_ranked(trimmed_query, &relaxed_info, top_k, params, trace)
+                .await?;
+        }
+
+        // From here on books are shared, not cloned
+        let ranked_results: Vec<Arc<Book>> = ranked_results.into_iter().map(Arc::new).collect();
+        info!(
+            "Returning {} ranked results for query '{}'",
+            ranked_results.len(),
+            trimmed_query
+        );
+
+        // Update cache with new results; fallback and relaxed results aren't kept,
+        // so a cache hit is never degraded or relaxed and the next query retries
+        if trace.used_fallback || !trace.relaxed.is_empty() {
+            info!(
+                "Not caching fallback or relaxed results for key '{}'",
+                cache_key
+            );
+        } else {
+            info!(
+                "Updating cache for key '{}' with {} results",
+                cache_key,
+                ranked_results.len()
+            );
+            self.result_cache
+                .insert_shared(cache_key, ranked_results.clone(), self.result_cache.ttl())
+                .await;
+        }
+
+        Ok((ranked_results, query_info))
+    }
+
+    /// Candidates of an analyzed query, ranked and cut to `top_k`
+    async fn search_ranked(
+        &self,
+        trimmed_query: &str,
+        query_info: &SemanticQueryInfo,
+        top_k: usize,
+        params: &RankingParams,
+        trace: &mut QueryTrace,
+    ) -> Result<Vec<Book>> {
+        let intent = QueryIntent::from_query_info(query_info);
+        let strategy = self.get_search_strategy(&intent, query_info);
+
+        // Increase search scope
+        let expanded_k = self.retrieval.candidates(top_k);
+
+        // Perform hybrid search
+        let raw_results = self
+            .retrieve_candidates(trimmed_query, &intent, &strategy, expanded_k, params, trace)
+            .await?;
+
+        // Rank and process results with keywords; a sample is ranked again in shadow
+        let shadow = self
+            .shadow
+            .sample(params.ranker(), &mut self.random.rng(trimmed_query))
+            .map(|shadow_ranker| (shadow_ranker, raw_results.clone()));
+        let ranked = params.ranker().rank(
+            raw_results,
+            &intent,
+            query_info,
+            top_k,
+            params.keyword_boost_cap,
+            self.retrieval.trusted_positions,
+        );
+        if let Some((shadow_ranker, candidates)) = shadow {
+            self.shadow.compare_in_background(
+                ShadowRun {
+                    query: trimmed_query.to_string(),
+                    production: params.ranker(),
+                    shadow: shadow_ranker,
+                    candidates,
+                    intent,
+                    query_info: query_info.clone(),
+                    top_k,
+                    keyword_boost_cap: params.keyword_boost_cap,
+                    trusted_positions: self.retrieval.trusted_positions,
+                },
+                &ranked,
+            );
+        }
+        Ok(ranked)
+    }
+
+    /// Keywords, author and intent hints of a query, falling back to none on failure
+    async fn analyze_query(&self, trimmed_query: &str) -> SemanticQueryInfo {
         // Extract keywords and metadata (no ML classification needed)
-        let query_info = self
+        let mut query_info = self
             .semantic_classifier
             .analyze_query(trimmed_query)
             .await
@@ -223,387 +1639,1012 @@ impl RecommendationService {
                     themes: vec![],
                     author: None,
                     temporal_filter: None,
+                    length_filter: None,
                     is_similar_query: false,
                     semantic_tags: vec![],
+                    moods: vec![],
+                    award: None,
+                    settings: vec![],
+                    intent_confidence: 1.0,
+                    pattern: QueryPattern::default(),
                 }
             });
+        // The pattern, page-count bounds and settings come from the query
+        // templates ("short reads", "novels set in japan")
+        let enhanced = self.query_enhancer.enhance(trimmed_query);
+        let filters = enhanced.filters;
+        query_info.pattern = enhanced.pattern;
+        query_info.length_filter = LengthFilter::from_bounds(filters.min_pages, filters.max_pages);
+        query_info.settings = settings_in_query(&filters.settings);
+        // Match authors by the same canonical name the indexer stored, and by the
+        // catalog's spelling when the query only gives part of it
+        if let Some(author) = query_info.author.take() {
+            let author = self.author_aliases.canonicalize_name(&author);
+            let cataloged = self
+                .corpus_stats
+                .as_ref()
+                .and_then(|stats| stats.author_named(&author))
+                .map(str::to_string);
+            // An author the catalog has by that full name is one however it was typed
+            if cataloged
+                .as_deref()
+                .is_some_and(|name| author_key(name) == author_key(&author))
+            {
+                query_info.intent_confidence =
+                    query_info.intent_confidence.max(KNOWN_AUTHOR_CONFIDENCE);
+            }
+            query_info.author = Some(cataloged.unwrap_or(author));
+        }
+        query_info
+    }
 
-        info!("Keyword extraction results:");
-        info!("  - Keywords: {:?}", query_info.themes);
-        info!("  - Author: {:?}", query_info.author);
-        info!("  - Temporal filter: {:?}", query_info.temporal_filter);
-        info!("  - Is similar query: {}", query_info.is_similar_query);
-        info!("  - Display tags: {:?}", query_info.semantic_tags);
+    /// Candidates from the path the upstreams' health allows: the hybrid search,
+    /// the fallback search while it can't run or when it fails, or the query's
+    /// last good candidates while neither can
+    async fn retrieve_candidates(
+        &self,
+        trimmed_query: &str,
+        intent: &QueryIntent,
+        strategy: &SearchStrategy,
+        expanded_k: usize,
+        params: &RankingParams,
+        trace: &mut QueryTrace,
+    ) -> Result<Vec<Book>> {
+        let candidates_key = format!(
+            "{}:{}:{:?}:{:?}",
+            trimmed_query, expanded_k, params.embedding_model, strategy
+        );
+        let path = self.search_router.route(self.fulltext.is_some());
+        trace.search_path = path;
+
+        let error = match path {
+            SearchPath::Vector => {
+                let result = self
+                    .perform_hybrid_search(
+                        intent,
+                        strategy,
+                        expanded_k,
+                        params.embedding_model.as_deref(),
+                        trace,
+                    )
+                    .await;
+                // The hybrid search handles embedding failures itself, so its errors are Pinecone's
+                if result.is_err() || trace.search_path == SearchPath::Vector {
+                    self.search_router.record(Upstream::Pinecone, &result);
+                }
+                match result {
+                    Ok(results) => {
+                        debug!(
+                            "Vector search returned {} results, first book: {:?}",
+                            results.len(),
+                            results.first().map(|r| r.title.clone())
+                        );
+                        if trace.search_path == SearchPath::Vector {
+                            self.stale_candidates
+                                .insert(candidates_key, results.clone());
+                        }
+                        return Ok(self.with_phrase_matches(trimmed_query, results, expanded_k));
+                    }
+                    Err(e) => {
+                        error!("Search error: {}. Trying fallback strategy", e);
+                        Some(e)
+                    }
+                }
+            }
+            SearchPath::Sparse => {
+                info!(
+                    "Embedding service unavailable, searching keywords for '{}'",
+                    trimmed_query
+                );
+                None
+            }
+            SearchPath::Cached => {
+                trace.used_fallback = true;
+                return match self.stale_candidates.get(&candidates_key) {
+                    Some(candidates) => {
+                        info!(
+                            "Upstreams unavailable, serving cached candidates for '{}'",
+                            trimmed_query
+                        );
+                        Ok(candidates)
+                    }
+                    None => Err(ApiError::service_unavailable(
+                        "Search is unavailable",
+                        self.search_router.retry_after_secs(),
+                    )),
+                };
+            }
+        };
 
-        // Convert to intent format
-        let intent = self.semantic_info_to_intent(&query_info);
-        info!(?intent, "Converted to intent format");
+        trace.search_path = SearchPath::Sparse;
+        trace.used_fallback = true;
+        let fallback_started = Instant::now();
+        let fallback_results = self
+            .perform_fallback_search(trimmed_query, expanded_k)
+            .await?;
+        trace
+            .timings
+            .add_fallback_search(fallback_started.elapsed());
+
+        // When an upstream is throttling us and the fallback found nothing,
+        // surface the 503 so clients honour Retry-After instead of retrying blindly
+        if fallback_results.is_empty() {
+            if let Some(e @ ApiError::ServiceUnavailable { .. }) = error {
+                return Err(e);
+            }
+        }
+        Ok(fallback_results)
+    }
 
-        // Get search strategy
-        let strategy = self.get_search_strategy(&intent);
-        info!("Performing hybrid search with strategy: {:?}", strategy);
+    /// `results` led by the books containing the query's quoted phrases verbatim,
+    /// which embeddings only match loosely
+    fn with_phrase_matches(&self, query: &str, results: Vec<Book>, limit: usize) -> Vec<Book> {
+        let phrases = quoted_phrases(query);
+        let Some(fulltext) = self.fulltext.as_ref().filter(|_| !phrases.is_empty()) else {
+            return results;
+        };
+        let matches = match fulltext.phrase_matches(&phrases, limit) {
+            Ok(matches) => self.without_hidden(matches),
+            Err(e) => {
+                warn!("Phrase search failed: {}", e);
+                return results;
+            }
+        };
+        let mut seen: HashSet<Option<String>> =
+            matches.iter().map(|book| book.id.clone()).collect();
+        matches
+            .into_iter()
+            .chain(
+                results
+                    .into_iter()
+                    .filter(|book| seen.insert(book.id.clone())),
+            )
+            .collect()
+    }
 
-        // Increase search scope
-        let expanded_k = top_k * 3;
+    /// Run a query through retrieval and ranking, bypassing the cache, and report
+    /// where `book_id` ended up
+    ///
+    /// `top_k` is the number of results asked of the ranking, before any
+    /// per-request filtering.
+    pub async fn explain_ranking(
+        &self,
+        query: &str,
+        book_id: &str,
+        top_k: usize,
+        params: &RankingParams,
+    ) -> Result<RankingExplanation> {
+        let trimmed_query = validate_query(query)?;
+        let book = self.get_book(book_id).await?;
+
+        let query_info = self.analyze_query(trimmed_query).await;
+        let intent = QueryIntent::from_query_info(&query_info);
+        let strategy = self.get_search_strategy(&intent, &query_info);
+        let mut trace = QueryTrace::default();
+        let candidates = self
+            .retrieve_candidates(
+                trimmed_query,
+                &intent,
+                &strategy,
+                self.retrieval.candidates(top_k),
+                params,
+                &mut trace,
+            )
+            .await?;
+
+        let candidate_count = candidates.len();
+        let candidate_position = candidates
+            .iter()
+            .position(|candidate| candidate.id.as_deref() == Some(book_id));
+        let ordered = params.ranker().order(
+            candidates,
+            &intent,
+            &query_info,
+            params.keyword_boost_cap,
+            self.retrieval.trusted_positions,
+        );
+        let placement = locate_result(&ordered, book_id);
+        let results = finalize_results(ordered, &query_info, top_k);
 
-        // Perform hybrid search
-        let raw_results = match self
-            .perform_hybrid_search(&intent, &strategy, expanded_k)
+        Ok(RankingExplanation {
+            matches_metadata_filter: strategy
+                .metadata_filter
+                .as_ref()
+                .zip(book.as_ref())
+                .map(|(filter, book)| filter.matches(book)),
+            metadata_filter: strategy
+                .metadata_filter
+                .as_ref()
+                .map(|filter| format!("{} ~ {}", filter.field, filter.value)),
+            book,
+            intent: intent.label().to_string(),
+            intent_confidence: query_info.intent_confidence,
+            used_fallback: trace.used_fallback,
+            candidate_count,
+            candidate_position,
+            placement,
+            results,
+        })
+    }
+
+    /// Re-rank results using the user's ratings of the same or adjacent books
+    ///
+    /// Personalization is best effort: without user data, ratings or any
+    /// adjacency evidence the results are returned unchanged.
+    pub async fn apply_user_ratings(
+        &self,
+        books: Vec<Arc<Book>>,
+        user_id: &str,
+        params: &RankingParams,
+    ) -> Vec<Arc<Book>> {
+        let Some(user_data) = &self.user_data else {
+            return books;
+        };
+
+        let ratings: HashMap<String, u8> = match user_data
+            .ratings(user_id, Some(MAX_RATINGS_CONSIDERED))
             .await
         {
-            Ok(results) => {
-                debug!(
-                    "Vector search returned {} results, first book: {:?}",
-                    results.len(),
-                    results.first().map(|r| r.title.clone())
-                );
-                results
-            }
+            Ok(ratings) => ratings
+                .into_iter()
+                .map(|rating| (rating.book_id, rating.rating))
+                .collect(),
             Err(e) => {
-                error!("Search error: {}. Trying fallback strategy", e);
-                self.perform_fallback_search(trimmed_query, expanded_k)
-                    .await?
+                warn!("Failed to load ratings for user {}: {}", user_id, e);
+                return books;
             }
         };
+        if ratings.is_empty() {
+            return books;
+        }
+
+        let candidate_ids: HashSet<&str> = books.iter().filter_map(|b| b.id.as_deref()).collect();
+        let rated_ids: Vec<String> = ratings.keys().cloned().collect();
+
+        let mut adjacency = if params.graph_blending {
+            self.graph_adjacency(&rated_ids, &candidate_ids).await
+        } else {
+            RatingAdjacency::default()
+        };
+        if adjacency.is_empty() {
+            adjacency = self.embedding_adjacency(&rated_ids, &candidate_ids).await;
+        }
 
-        // Rank and process results with keywords
-        let ranked_results =
-            self.rank_results_with_semantic_info(raw_results, &intent, &query_info, top_k);
+        let affinities: HashMap<String, f32> = candidate_ids
+            .iter()
+            .filter_map(|id| Some((id.to_string(), adjacency.affinity(id, &ratings)?)))
+            .collect();
         info!(
-            "Returning {} ranked results for query '{}'",
-            ranked_results.len(),
-            trimmed_query
+            "Blending {} ratings into {} results for user {} ({} affected)",
+            ratings.len(),
+            books.len(),
+            user_id,
+            affinities.len()
         );
 
-        // Update cache with new results
-        if let Ok(mut cache) = self.result_cache.write() {
-            info!(
-                "Updating cache for key '{}' with {} results",
-                cache_key,
-                ranked_results.len()
-            );
+        blend_affinities(books, &affinities, params.rating_blend_weight)
+    }
+
+    /// Nudge results toward the genres of a user's or session's earlier queries
+    pub async fn apply_query_history(
+        &self,
+        books: Vec<Arc<Book>>,
+        owner: &HistoryOwner,
+        params: &RankingParams,
+    ) -> Vec<Arc<Book>> {
+        let Some(user_data) = &self.user_data else {
+            return books;
+        };
+
+        let history = match user_data.query_history(owner, MAX_HISTORY_CONSIDERED).await {
+            Ok(history) => history,
+            Err(e) => {
+                warn!("Failed to load query history for {}: {}", owner.id(), e);
+                return books;
+            }
+        };
+        let tags: Vec<Vec<String>> = history
+            .into_iter()
+            .map(|entry| entry.semantic_tags)
+            .collect();
+        let weights = history_tag_weights(&tags);
+        if weights.is_empty() {
+            return books;
+        }
+
+        let affinities: HashMap<String, f32> = books
+            .iter()
+            .filter_map(|book| Some((book.id.clone()?, history_affinity(book, &weights)?)))
+            .collect();
+        debug!(
+            "Query history of {} matches {} of {} results",
+            owner.id(),
+            affinities.len(),
+            books.len()
+        );
 
-            cache.insert(
-                cache_key,
-                CacheEntry {
-                    results: ranked_results.clone(),
-                    timestamp: Instant::now(),
-                },
-            );
+        blend_affinities(books, &affinities, params.history_blend_weight)
+    }
 
-            if cache.len() > 100 {
-                self.cleanup_cache(&mut cache);
+    /// The user's content preferences, or None if they have none or they can't be loaded
+    pub async fn user_preferences(&self, user_id: &str) -> Option<ContentPreferences> {
+        let user_data = self.user_data.as_ref()?;
+        match user_data.preferences(user_id).await {
+            Ok(saved) => saved
+                .map(|saved| saved.preferences)
+                .filter(|preferences| !preferences.is_empty()),
+            Err(e) => {
+                warn!("Failed to load preferences for user {}: {}", user_id, e);
+                None
             }
-
-            info!("Current cache size: {} entries", cache.len());
         }
+    }
 
-        Ok((ranked_results, query_info.semantic_tags))
+    /// Content preferences for a query: the user's, with safe search set by the
+    /// request or, failing that, turned on for queries for children or young adults
+    pub async fn query_preferences(
+        &self,
+        query: &str,
+        user_id: Option<&str>,
+        safe_search: Option<bool>,
+    ) -> Option<ContentPreferences> {
+        let saved = match user_id {
+            Some(user_id) => self.user_preferences(user_id).await,
+            None => None,
+        };
+        let for_young_readers = self
+            .query_enhancer
+            .enhance(query.trim())
+            .filters
+            .audience
+            .is_some();
+        let safe_search = match safe_search {
+            Some(safe_search) => safe_search,
+            None if for_young_readers => true,
+            None => return saved,
+        };
+        Some(ContentPreferences {
+            safe_search,
+            ..saved.unwrap_or_default()
+        })
+        .filter(|preferences| !preferences.is_empty())
     }
 
-    /// Convert semantic query info to legacy QueryIntent format
-    fn semantic_info_to_intent(&self, info: &SemanticQueryInfo) -> QueryIntent {
-        // If author is detected, prioritize that - metadata search is best for authors
-        if let Some(author) = &info.author {
-            return QueryIntent::Author {
-                name: author.clone(),
-                original_query: info.original_query.clone(),
-            };
-        }
+    /// Add a query to the history of a user or session in the background, so the
+    /// response doesn't wait on the writes; failures are only logged
+    pub fn remember_query(
+        &self,
+        owner: HistoryOwner,
+        query: &str,
+        semantic_tags: &[String],
+        result_count: usize,
+    ) {
+        let Some(user_data) = self.user_data.clone() else {
+            return;
+        };
+        let query = query.to_string();
+        let semantic_tags = semantic_tags.to_vec();
 
-        // If similar query, use SimilarTo intent - semantic search is best
-        if info.is_similar_query {
-            return QueryIntent::SimilarTo {
-                original_query: info.original_query.clone(),
-            };
-        }
+        tokio::spawn(async move {
+            if let Err(e) = user_data
+                .record_query(&owner, &query, &semantic_tags, result_count)
+                .await
+            {
+                warn!("Failed to record query history for {}: {}", owner.id(), e);
+            }
+        });
+    }
 
-        // For all other queries, use General intent
-        // The semantic themes will be used in ranking and relevance indicators
-        // This gives the best balance between semantic search and metadata filtering
-        QueryIntent::General {
-            query: info.original_query.clone(),
+    /// Recommend books from the user's taste profile, leaving out books they've read or rated
+    ///
+    /// Returns the books and the number of rated books the profile was built from.
+    /// Both are empty when the user hasn't rated any indexed book highly yet.
+    pub async fn recommend_for_user(
+        &self,
+        user_id: &str,
+        top_k: usize,
+    ) -> Result<(Vec<Book>, usize)> {
+        let user_data = self
+            .user_data
+            .as_ref()
+            .ok_or_else(|| ApiError::InternalError("User data is not configured".to_string()))?;
+
+        let ratings: Vec<(String, u8)> = user_data
+            .ratings(user_id, Some(MAX_PROFILE_RATINGS))
+            .await?
+            .into_iter()
+            .map(|rating| (rating.book_id, rating.rating))
+            .collect();
+        let liked_ids: Vec<String> = ratings
+            .iter()
+            .filter(|(_, rating)| *rating >= MIN_PROFILE_RATING)
+            .map(|(book_id, _)| book_id.clone())
+            .collect();
+        if liked_ids.is_empty() {
+            info!(
+                "User {} has no highly rated books to build a profile from",
+                user_id
+            );
+            return Ok((Vec::new(), 0));
         }
+
+        let vectors = self.pinecone.fetch_vectors(&liked_ids).await?;
+        let Some(profile) = taste_profile(&ratings, &vectors) else {
+            info!(
+                "None of the books user {} rated highly are indexed",
+                user_id
+            );
+            return Ok((Vec::new(), 0));
+        };
+        let profile_size = liked_ids
+            .iter()
+            .filter(|id| vectors.contains_key(*id))
+            .count();
+
+        let mut excluded: HashSet<String> =
+            ratings.into_iter().map(|(book_id, _)| book_id).collect();
+        excluded.extend(
+            user_data
+                .shelf(user_id, Some(ShelfStatus::Read))
+                .await?
+                .into_iter()
+                .map(|entry| entry.book_id),
+        );
+
+        let matches = self
+            .pinecone
+            .query_vector(&profile, top_k + excluded.len())
+            .await?;
+
+        let mut seen = HashSet::new();
+        let books: Vec<Book> = matches
+            .into_iter()
+            .filter(|book| {
+                book.id
+                    .as_ref()
+                    .is_some_and(|id| !excluded.contains(id) && seen.insert(id.clone()))
+            })
+            .take(top_k)
+            .enumerate()
+            .map(|(index, mut book)| {
+                let position_factor = 1.0 - (index as f32 / top_k as f32);
+                let rating_factor = book.rating / 5.0;
+                book.confidence_score = (position_factor * 0.7 + rating_factor * 0.3).min(1.0);
+                book
+            })
+            .collect();
+
+        info!(
+            "Recommending {} books for user {} from a profile of {} rated books ({} excluded)",
+            books.len(),
+            user_id,
+            profile_size,
+            excluded.len()
+        );
+        Ok((books, profile_size))
     }
 
-    /// Rank results with semantic information
-    fn rank_results_with_semantic_info(
+    /// Books similar to the seed books `book_ids`, searched from a centroid per group of similar
+    /// seeds, steered away from the books `avoid_ids`
+    ///
+    /// Errors with NotFound when none of the seeds has a stored embedding.
+    pub async fn recommend_similar_to(
         &self,
-        mut results: Vec<Book>,
-        intent: &QueryIntent,
-        query_info: &SemanticQueryInfo,
+        book_ids: &[String],
+        avoid_ids: &[String],
         top_k: usize,
-    ) -> Vec<Book> {
-        // Early return if no results or only one result
-        if results.len() <= 1 {
-            return results;
+    ) -> Result<SimilarBooks> {
+        let ids: Vec<String> = book_ids.iter().chain(avoid_ids).cloned().collect();
+        let vectors = self.pinecone.fetch_vectors(&ids).await?;
+        let not_indexed = |ids: &[String]| -> Vec<String> {
+            ids.iter()
+                .filter(|id| !vectors.contains_key(*id))
+                .cloned()
+                .collect()
+        };
+        let missing = not_indexed(book_ids);
+        let missing_avoided = not_indexed(avoid_ids);
+        let mut clusters = seed_clusters(book_ids, &vectors);
+        if clusters.is_empty() {
+            return Err(ApiError::NotFound(format!(
+                "Books not indexed: {}",
+                missing.join(", ")
+            )));
         }
 
-        let max_needed = (top_k * 3).min(results.len());
+        if let Some(avoided) = mean_embedding(avoid_ids, &vectors) {
+            steer_away(&mut clusters, &avoided);
+        }
 
-        // Use existing ranking logic but with semantic information
-        match intent {
-            QueryIntent::Author { name, .. } => {
-                let name_lower = name.to_lowercase();
-                let mut indexed_books: Vec<(usize, i32, f32)> = results
-                    .iter()
-                    .enumerate()
-                    .map(|(idx, book)| {
-                        let author = book.author.as_deref().unwrap_or("").to_lowercase();
-                        let exact_match = author.contains(&name_lower) as i32;
-                        (idx, exact_match, book.rating)
-                    })
-                    .collect();
-
-                indexed_books.sort_by(|a, b| {
-                    let (_, a_exact, a_rating) = *a;
-                    let (_, b_exact, b_rating) = *b;
-                    b_exact.cmp(&a_exact).then_with(|| {
-                        b_rating
-                            .partial_cmp(&a_rating)
-                            .unwrap_or(std::cmp::Ordering::Equal)
-                    })
-                });
-
-                let mut sorted_results = Vec::with_capacity(results.len());
-                for (idx, _, _) in indexed_books {
-                    sorted_results.push(results[idx].clone());
-                }
-                results = sorted_results;
-            }
-            QueryIntent::Genre { genre, .. } => {
-                let genre_lower = genre.to_lowercase();
-                let mut indexed_books: Vec<(usize, i32, f32)> = results
-                    .iter()
-                    .enumerate()
-                    .map(|(idx, book)| {
-                        let categories = book.categories.join(", ").to_lowercase();
-                        let has_match = categories.contains(&genre_lower) as i32;
-                        (idx, has_match, book.rating)
-                    })
-                    .collect();
-
-                indexed_books.sort_by(|a, b| {
-                    let (_, a_match, a_rating) = *a;
-                    let (_, b_match, b_rating) = *b;
-                    b_match.cmp(&a_match).then_with(|| {
-                        b_rating
-                            .partial_cmp(&a_rating)
-                            .unwrap_or(std::cmp::Ordering::Equal)
-                    })
-                });
-
-                let mut sorted_results = Vec::with_capacity(results.len());
-                for (idx, _, _) in indexed_books {
-                    sorted_results.push(results[idx].clone());
+        let results = futures::future::try_join_all(clusters.iter().map(|cluster| {
+            self.pinecone
+                .query_vector(&cluster.centroid, top_k + ids.len())
+        }))
+        .await?;
+        let books: Vec<Book> = interleave(&clusters, results, avoid_ids, top_k)
+            .into_iter()
+            .enumerate()
+            .map(|(index, mut book)| {
+                let position_factor = 1.0 - (index as f32 / top_k as f32);
+                let rating_factor = book.rating / 5.0;
+                book.confidence_score = (position_factor * 0.7 + rating_factor * 0.3).min(1.0);
+                book
+            })
+            .collect();
+
+        info!(
+            "Recommending {} books similar to {} seed books in {} groups, avoiding {} ({} not indexed)",
+            books.len(),
+            book_ids.len() - missing.len(),
+            clusters.len(),
+            avoid_ids.len() - missing_avoided.len(),
+            missing.len() + missing_avoided.len()
+        );
+        Ok(SimilarBooks {
+            books,
+            clusters: clusters
+                .into_iter()
+                .map(|cluster| cluster.book_ids)
+                .collect(),
+            missing,
+            missing_avoided,
+        })
+    }
+
+    /// Build a digest of new picks from the user's shelves and ratings
+    ///
+    /// Books the user shelved, rated or was already sent are left out. When
+    /// `record` is set the digest is stored as a snapshot and its books are
+    /// remembered, so the next digest doesn't repeat them. A digest already
+    /// recorded this week (ISO, UTC) is returned instead of recording another.
+    /// Picks go through the default post-filters.
+    pub async fn weekly_digest(&self, user_id: &str, count: usize, record: bool) -> Result<Digest> {
+        let user_data = self
+            .user_data
+            .as_ref()
+            .ok_or_else(|| ApiError::InternalError("User data is not configured".to_string()))?;
+
+        if record {
+            if let Some(digest) = self.latest_digest(user_id).await? {
+                if same_week(&digest.generated_at, chrono::Utc::now()) {
+                    info!("User {} already has this week's digest", user_id);
+                    return Ok(digest);
                 }
-                results = sorted_results;
             }
-            _ => {
-                info!("Using GENERAL search ranking logic with keyword boost");
+        }
 
-                let total_results = results.len();
-                let mut scored_results = results
-                    .iter()
-                    .enumerate()
-                    .map(|(idx, book)| {
-                        let position_score = 3.0 * (1.0 - (idx as f32 / total_results as f32));
-                        let rating_score = 0.85 + (book.rating / 5.0) * 0.10;
-
-                        // Add keyword boost - check if query keywords appear in book metadata
-                        let mut keyword_boost: f32 = 0.0;
-                        for (keyword, _) in &query_info.themes {
-                            let keyword_lower = keyword.to_lowercase();
-
-                            // Check categories
-                            let category_match = book.categories.iter().any(|cat| {
-                                cat.to_lowercase().contains(&keyword_lower)
-                            });
-
-                            // Check title
-                            let title_match = book.title.as_ref().map_or(false, |title| {
-                                title.to_lowercase().contains(&keyword_lower)
-                            });
-
-                            // Check description
-                            let desc_match = book.description.as_ref().map_or(false, |desc| {
-                                desc.to_lowercase().contains(&keyword_lower)
-                            });
-
-                            if title_match {
-                                keyword_boost += 1.0; // Title match is strongest
-                            } else if category_match {
-                                keyword_boost += 0.8; // Category match is strong
-                            } else if desc_match {
-                                keyword_boost += 0.5; // Description match is moderate
-                            }
-                        }
+        let mut seeds: HashMap<String, DigestSeed> = HashMap::new();
+        for entry in user_data.shelf(user_id, None).await? {
+            seeds.insert(
+                entry.book_id.clone(),
+                DigestSeed {
+                    book_id: entry.book_id,
+                    title: None,
+                    shelf: Some(entry.status),
+                    rating: None,
+                },
+            );
+        }
+        for rating in user_data
+            .ratings(user_id, Some(MAX_PROFILE_RATINGS))
+            .await?
+        {
+            seeds
+                .entry(rating.book_id.clone())
+                .or_insert_with(|| DigestSeed {
+                    book_id: rating.book_id,
+                    title: None,
+                    shelf: None,
+                    rating: None,
+                })
+                .rating = Some(rating.rating);
+        }
 
-                        // Cap keyword boost at 2.0
-                        keyword_boost = keyword_boost.min(2.0);
+        let mut excluded: HashSet<String> = seeds.keys().cloned().collect();
+        excluded.extend(user_data.recommended_book_ids(user_id).await?);
 
-                        let final_score = if idx < 50 {
-                            position_score + rating_score + keyword_boost
-                        } else {
-                            position_score * 0.7 + rating_score * 1.3 + keyword_boost
-                        };
-
-                        if tracing::enabled!(tracing::Level::DEBUG) {
-                            debug!(
-                                "Book scoring: {:?} - Position: {}/{} (score: {:.2}), Rating: {:.2}, Keyword boost: {:.2}, Final: {:.2}",
-                                book.title, idx + 1, total_results, position_score, book.rating, keyword_boost, final_score
-                            );
-                        }
+        let mut seeds: Vec<DigestSeed> = seeds.into_values().collect();
+        seeds.sort_by(|a, b| a.book_id.cmp(&b.book_id));
+        let seed_ids: Vec<String> = seeds.iter().map(|seed| seed.book_id.clone()).collect();
+        let seed_vectors = self.pinecone.fetch_vectors(&seed_ids).await?;
+        let profile_ratings: Vec<(String, u8)> = seeds
+            .iter()
+            .map(|seed| (seed.book_id.clone(), seed.profile_rating()))
+            .collect();
+
+        let generated_at = chrono::Utc::now().to_rfc3339();
+        let Some(profile) = taste_profile(&profile_ratings, &seed_vectors) else {
+            info!(
+                "User {} has no indexed books on their shelves to build a digest from",
+                user_id
+            );
+            return Ok(Digest {
+                user_id: user_id.to_string(),
+                generated_at,
+                seed_count: 0,
+                picks: Vec::new(),
+            });
+        };
 
-                        (book.clone(), final_score)
-                    })
-                    .collect::<Vec<_>>();
+        let metadata = self.pinecone.fetch_metadata(&seed_ids).await?;
+        for seed in &mut seeds {
+            seed.title = metadata
+                .get(&seed.book_id)
+                .and_then(|metadata| metadata.get("title"))
+                .and_then(|title| title.as_str())
+                .map(|title| title.to_string());
+        }
 
-                scored_results
-                    .sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        let matches = self
+            .pinecone
+            .query_vector(&profile, count + excluded.len())
+            .await?;
+        let mut seen = HashSet::new();
+        let mut books: Vec<Book> = matches
+            .into_iter()
+            .filter(|book| {
+                book.id
+                    .as_ref()
+                    .is_some_and(|id| !excluded.contains(id) && seen.insert(id.clone()))
+            })
+            .collect();
+        // Digests belong to a user rather than a tenant, so the default chain applies
+        self.retain_allowed(&mut books, None);
+        books.truncate(count);
+
+        let pick_ids: Vec<String> = books.iter().filter_map(|book| book.id.clone()).collect();
+        let pick_vectors = self.pinecone.fetch_vectors(&pick_ids).await?;
+        let picks: Vec<DigestPick> = books
+            .into_iter()
+            .map(|book| {
+                let seed = book
+                    .id
+                    .as_ref()
+                    .and_then(|id| pick_vectors.get(id))
+                    .and_then(|vector| closest_seed(vector, &seeds, &seed_vectors));
+                DigestPick {
+                    explanation: seed.map(DigestSeed::explanation).unwrap_or_else(|| {
+                        "Picked for you from the books on your shelves".to_string()
+                    }),
+                    because_of: seed.map(|seed| seed.book_id.clone()),
+                    book,
+                }
+            })
+            .collect();
 
-                results = scored_results.into_iter().map(|(book, _)| book).collect();
+        let digest = Digest {
+            user_id: user_id.to_string(),
+            generated_at,
+            seed_count: seeds
+                .iter()
+                .filter(|seed| seed_vectors.contains_key(&seed.book_id))
+                .count(),
+            picks,
+        };
 
-                info!(
-                    "Completed scoring of {} books with keyword boosting",
-                    results.len()
-                );
-            }
+        if record && !digest.picks.is_empty() {
+            let payload = serde_json::to_value(&digest).map_err(|e| {
+                ApiError::InternalError(format!("Failed to serialize digest: {}", e))
+            })?;
+            user_data
+                .save_snapshot(user_id, DIGEST_SNAPSHOT_KIND, &payload, &pick_ids)
+                .await?;
         }
 
-        // Remove duplicates
-        let mut seen = HashSet::with_capacity(max_needed);
-        let mut unique_results = Vec::with_capacity(max_needed);
+        info!(
+            "Generated a digest of {} picks for user {} from {} seed books ({} excluded)",
+            digest.picks.len(),
+            user_id,
+            digest.seed_count,
+            excluded.len()
+        );
+        Ok(digest)
+    }
 
-        for book in results {
-            if unique_results.len() >= top_k * 3 {
-                break;
-            }
+    /// The last digest recorded for a user
+    pub async fn latest_digest(&self, user_id: &str) -> Result<Option<Digest>> {
+        let user_data = self
+            .user_data
+            .as_ref()
+            .ok_or_else(|| ApiError::InternalError("User data is not configured".to_string()))?;
+
+        user_data
+            .latest_snapshot(user_id, DIGEST_SNAPSHOT_KIND)
+            .await?
+            .map(|payload| {
+                serde_json::from_value(payload).map_err(|e| {
+                    ApiError::InternalError(format!("Stored digest is invalid: {}", e))
+                })
+            })
+            .transpose()
+    }
 
-            let key = format!(
-                "{}-{}",
-                book.title.as_deref().unwrap_or("Unknown"),
-                book.author.as_deref().unwrap_or("Unknown")
-            );
+    /// The stored metadata of one book, or None if it isn't indexed
+    pub async fn get_book(&self, book_id: &str) -> Result<Option<Book>> {
+        Ok(self
+            .pinecone
+            .fetch_books(&[book_id.to_string()])
+            .await?
+            .remove(book_id))
+    }
 
-            if seen.insert(key) {
-                unique_results.push(book);
-            }
+    /// Profile of an author in the corpus statistics snapshot, or None if they have no books in it
+    pub async fn author_profile(&self, name: &str) -> Result<Option<AuthorProfile>> {
+        let stats = self.corpus_stats.as_ref().ok_or_else(|| {
+            ApiError::NotFound("No corpus statistics snapshot is loaded".to_string())
+        })?;
+        let name = self.author_aliases.canonicalize_name(name.trim());
+        let name = stats.author_named(&name).unwrap_or(&name).to_string();
+        let ids: Vec<String> = stats
+            .titles_by(&name)
+            .into_iter()
+            .filter_map(|entry| entry.id.clone())
+            .take(MAX_PROFILE_BOOKS)
+            .collect();
+        if ids.is_empty() {
+            return Ok(None);
         }
 
-        // Final ranking with metadata
-        let final_results = unique_results
-            .iter()
-            .enumerate()
-            .take(top_k)
-            .map(|(index, book)| {
-                let mut book_clone = book.clone();
-                let position_factor = 1.0 - (index as f32 / top_k as f32);
-                let rating_factor = book_clone.rating / 5.0;
-                book_clone.confidence_score =
-                    (position_factor * 0.7 + rating_factor * 0.3).min(1.0);
+        let mut found = self.pinecone.fetch_books(&ids).await?;
+        let books: Vec<Book> = ids.iter().filter_map(|id| found.remove(id)).collect();
+        let mut profile = build_profile(&name, books);
 
-                book_clone.relevance_indicators =
-                    self.generate_relevance_indicators_semantic(&book_clone, query_info);
+        // Similar authors are a nice-to-have; the profile stands without them
+        match self.similar_authors(&name, &ids).await {
+            Ok(similar) => profile.similar_authors = similar,
+            Err(e) => warn!("Failed to find authors similar to {}: {}", name, e),
+        }
+        Ok(Some(profile))
+    }
 
-                book_clone
-            })
-            .collect::<Vec<Book>>();
+    /// Authors of the books closest to the average embedding of `book_ids`
+    async fn similar_authors(&self, name: &str, book_ids: &[String]) -> Result<Vec<SimilarAuthor>> {
+        let vectors = self.pinecone.fetch_vectors(book_ids).await?;
+        let Some(average) = average_embedding(vectors.values()) else {
+            return Ok(Vec::new());
+        };
 
-        info!(
-            "FINAL RANKING: Top {} results ready. First book: {:?}",
-            final_results.len(),
-            final_results.first().map(|b| b.title.clone())
-        );
+        let candidates: Vec<Book> = self
+            .pinecone
+            .query_vector(&average, SIMILAR_AUTHOR_CANDIDATES)
+            .await?
+            .into_iter()
+            .filter(|book| book.id.as_ref().is_some_and(|id| !book_ids.contains(id)))
+            .collect();
+        let candidate_ids: Vec<String> = candidates
+            .iter()
+            .filter_map(|book| book.id.clone())
+            .collect();
+        let candidate_vectors = self.pinecone.fetch_vectors(&candidate_ids).await?;
+        Ok(similar_authors(
+            name,
+            &average,
+            &candidates,
+            &candidate_vectors,
+        ))
+    }
+
+    /// Compare indexed books, in the order of `book_ids`
+    ///
+    /// Embeddings and graph relationships that can't be loaded are left out of
+    /// the comparison; books that aren't indexed are an error.
+    pub async fn compare_books(&self, book_ids: &[String]) -> Result<BookComparison> {
+        let mut found = self.pinecone.fetch_books(book_ids).await?;
+        let missing: Vec<&str> = book_ids
+            .iter()
+            .filter(|id| !found.contains_key(*id))
+            .map(String::as_str)
+            .collect();
+        if !missing.is_empty() {
+            return Err(ApiError::NotFound(format!(
+                "Books not indexed: {}",
+                missing.join(", ")
+            )));
+        }
+        let books: Vec<Book> = book_ids.iter().filter_map(|id| found.remove(id)).collect();
+
+        let vectors = match self.pinecone.fetch_vectors(book_ids).await {
+            Ok(vectors) => vectors,
+            Err(e) => {
+                warn!("Failed to fetch embeddings of compared books: {}", e);
+                HashMap::new()
+            }
+        };
+        let relationships = match &self.graph {
+            Some(graph) => match graph.get_relationships_among(book_ids).await {
+                Ok(relationships) => relationships,
+                Err(e) => {
+                    warn!("Graph lookup for compared books failed: {}", e);
+                    Vec::new()
+                }
+            },
+            None => Vec::new(),
+        };
 
-        final_results
+        Ok(compare_books(books, &vectors, relationships))
     }
 
-    /// Generate relevance indicators using semantic information
-    fn generate_relevance_indicators_semantic(
+    /// The `limit` books clicked and shelved most since `since`, most popular first
+    ///
+    /// Books no longer in the index are left out.
+    pub async fn trending_books(
         &self,
-        book: &Book,
-        query_info: &SemanticQueryInfo,
-    ) -> Vec<String> {
-        let mut indicators = Vec::new();
+        since: chrono::DateTime<chrono::Utc>,
+        limit: usize,
+    ) -> Result<Vec<TrendingBook>> {
+        let Some(user_data) = &self.user_data else {
+            return Ok(Vec::new());
+        };
 
-        // Check for keyword matches in description and title
-        for (keyword, _) in &query_info.themes {
-            let keyword_lower = keyword.to_lowercase();
+        let clicks = user_data.event_counts(EventType::Click, since).await?;
+        let shelvings = user_data.event_counts(EventType::AddToShelf, since).await?;
+        let ranked = rank_trending(clicks, shelvings, limit);
+        if ranked.is_empty() {
+            return Ok(Vec::new());
+        }
 
-            // Check if keyword appears in title
-            if book
-                .title
-                .as_ref()
-                .map_or(false, |title| title.to_lowercase().contains(&keyword_lower))
-            {
-                indicators.push(keyword.clone());
-                continue;
-            }
+        let ids: Vec<String> = ranked.iter().map(|count| count.book_id.clone()).collect();
+        let mut books = self.pinecone.fetch_books(&ids).await?;
+        Ok(ranked
+            .into_iter()
+            .filter_map(|count| {
+                Some(TrendingBook {
+                    book: books.remove(&count.book_id)?,
+                    clicks: count.clicks,
+                    shelvings: count.shelvings,
+                })
+            })
+            .collect())
+    }
 
-            // Check if keyword appears in categories
-            if book
-                .categories
+    /// Trending books over the last `window_days`, cached so feeds don't count
+    /// events on every request
+    pub async fn cached_trending(
+        &self,
+        window_days: i64,
+        limit: usize,
+    ) -> Result<Vec<TrendingBook>> {
+        recover_lock(self.trending_windows.write(), "trending windows")
+            .insert((window_days, limit));
+        let key = format!("{}:{}", window_days, limit);
+        if let Some(trending) = self.trending_cache.get(&key) {
+            return Ok(trending);
+        }
+        let trending = self
+            .trending_books(
+                chrono::Utc::now() - chrono::Duration::days(window_days),
+                limit,
+            )
+            .await?;
+        self.trending_cache.insert(key, trending.clone());
+        Ok(trending)
+    }
+
+    /// Recompute every trending list asked for so far; returns how many were refreshed
+    pub async fn refresh_trending(&self) -> Result<usize> {
+        let windows: Vec<(i64, usize)> =
+            recover_lock(self.trending_windows.read(), "trending windows")
                 .iter()
-                .any(|cat| cat.to_lowercase().contains(&keyword_lower))
-            {
-                indicators.push(keyword.clone());
-                continue;
-            }
+                .copied()
+                .collect();
+        let now = chrono::Utc::now();
+        for (window_days, limit) in &windows {
+            let trending = self
+                .trending_books(now - chrono::Duration::days(*window_days), *limit)
+                .await?;
+            self.trending_cache
+                .insert(format!("{}:{}", window_days, limit), trending);
+        }
+        Ok(windows.len())
+    }
 
-            // Check if keyword appears in description
-            if book
-                .description
-                .as_ref()
-                .map_or(false, |desc| desc.to_lowercase().contains(&keyword_lower))
-            {
-                indicators.push(keyword.clone());
+    /// Links from candidates to rated books through direct graph relationships
+    async fn graph_adjacency(
+        &self,
+        rated_ids: &[String],
+        candidate_ids: &HashSet<&str>,
+    ) -> RatingAdjacency {
+        let mut adjacency = RatingAdjacency::default();
+        let Some(graph) = &self.graph else {
+            return adjacency;
+        };
+
+        match graph
+            .get_relationships_from(rated_ids, MAX_RATING_LINKS)
+            .await
+        {
+            Ok(relationships) => {
+                for rel in relationships {
+                    if candidate_ids.contains(rel.to_id.as_str()) {
+                        adjacency.add(&rel.to_id, &rel.from_id, rel.weight);
+                    }
+                }
             }
+            Err(e) => warn!("Graph lookup for rated books failed: {}", e),
         }
+        adjacency
+    }
+
+    /// Links from candidates to rated books whose stored embeddings are close
+    async fn embedding_adjacency(
+        &self,
+        rated_ids: &[String],
+        candidate_ids: &HashSet<&str>,
+    ) -> RatingAdjacency {
+        let mut adjacency = RatingAdjacency::default();
+        let ids: Vec<String> = rated_ids
+            .iter()
+            .cloned()
+            .chain(candidate_ids.iter().map(|id| id.to_string()))
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
 
-        // Add author match if applicable
-        if let Some(author) = &query_info.author {
-            if book.author.as_ref().map_or(false, |book_author| {
-                book_author.to_lowercase().contains(&author.to_lowercase())
-            }) {
-                indicators.push(format!("Author: {}", author));
+        let vectors = match self.pinecone.fetch_vectors(&ids).await {
+            Ok(vectors) => vectors,
+            Err(e) => {
+                warn!("Failed to fetch embeddings for rated books: {}", e);
+                return adjacency;
             }
-        }
+        };
 
-        // Add categories if not enough indicators
-        if indicators.len() < 2 {
-            for category in &book.categories {
-                if !indicators.contains(category) {
-                    indicators.push(category.clone());
-                }
-                if indicators.len() >= 3 {
-                    break;
+        for candidate_id in candidate_ids {
+            let Some(candidate) = vectors.get(*candidate_id) else {
+                continue;
+            };
+            for rated_id in rated_ids {
+                let Some(rated) = vectors.get(rated_id) else {
+                    continue;
+                };
+                let similarity = cosine_similarity(candidate, rated);
+                if similarity >= MIN_EMBEDDING_SIMILARITY {
+                    adjacency.add(candidate_id, rated_id, similarity);
                 }
             }
         }
-
-        indicators.into_iter().take(3).collect()
+        adjacency
     }
 
-    #[allow(dead_code)]
-    pub fn get_cache_stats(&self) -> Option<usize> {
-        self.query_enhancer.cache_stats().map(|s| s.valid_entries)
+    /// Re-read the synonyms file and match queries with its keywords from now on
+    ///
+    /// Query enhancements are dropped so cached readings of queries don't keep
+    /// the old keywords. Returns the file's synonyms.
+    pub fn reload_synonyms(&self, path: &Path) -> Result<SynonymDictionary> {
+        let synonyms = SynonymDictionary::load(path)?;
+        set_synonyms(&synonyms);
+        self.query_enhancer.clear_cache();
+        info!(
+            "Reloaded synonyms for {} genres and {} themes from {}",
+            synonyms.genres.len(),
+            synonyms.themes.len(),
+            path.display()
+        );
+        Ok(synonyms)
     }
 
-    // Helper method to clean up expired cache entries
-    fn cleanup_cache(&self, cache: &mut HashMap<String, CacheEntry>) {
-        let expired_keys: Vec<String> = cache
-            .iter()
-            .filter(|(_, entry)| entry.timestamp.elapsed() > Duration::from_secs(CACHE_TTL_SECONDS))
-            .map(|(k, _)| k.clone())
-            .collect();
+    /// Drop cached recommendation results, including shared ones and the
+    /// candidates kept for outages, trending lists, and the query enhancement
+    /// and Pinecone caches
+    pub async fn clear_caches(&self) {
+        self.result_cache.clear_shared().await;
+        self.stale_candidates.clear();
+        self.trending_cache.clear();
+        self.query_enhancer.clear_cache();
+        self.pinecone.clear_caches();
+        info!("Recommendation caches cleared");
+    }
 
-        for key in expired_keys {
-            cache.remove(&key);
-        }
+    #[allow(dead_code)]
+    pub fn get_cache_stats(&self) -> Option<usize> {
+        Some(self.query_enhancer.cache_stats().entries)
     }
 
-    fn get_search_strategy(&self, intent: &QueryIntent) -> SearchStrategy {
+    fn get_search_strategy(
+        &self,
+        intent: &QueryIntent,
+        query_info: &SemanticQueryInfo,
+    ) -> SearchStrategy {
+        let filters = search_filters(query_info);
         match intent {
             QueryIntent::Author { name, .. } => SearchStrategy {
                 metadata_filter: Some(MetadataFilter {
@@ -613,25 +2654,29 @@ impl RecommendationService {
                 }),
                 semantic_weight: 0.3,
                 hybrid_search: true,
+                filters,
             },
             QueryIntent::Genre { genre, .. } => SearchStrategy {
                 metadata_filter: Some(MetadataFilter {
-                    field: "categories".into(),
+                    field: "genres".into(),
                     value: genre.clone(),
                     exact_match: false,
                 }),
                 semantic_weight: 0.7,
                 hybrid_search: true,
+                filters,
             },
             QueryIntent::SimilarTo { .. } => SearchStrategy {
                 metadata_filter: None,
                 semantic_weight: 0.8,
                 hybrid_search: true,
+                filters,
             },
             QueryIntent::General { .. } => SearchStrategy {
                 metadata_filter: None,
                 semantic_weight: 0.6, // Lower weight gives more importance to ratings
                 hybrid_search: true,  // Enable hybrid search for better results
+                filters,
             },
         }
     }
@@ -641,27 +2686,50 @@ impl RecommendationService {
         intent: &QueryIntent,
         strategy: &SearchStrategy,
         top_k: usize,
+        embedding_model: Option<&str>,
+        trace: &mut QueryTrace,
     ) -> Result<Vec<Book>> {
         info!("Performing hybrid search with strategy: {:?}", strategy);
         let mut results = Vec::new();
+        let filter = strategy.filters.build();
 
         // Try metadata filtering if applicable
-        if let Some(filter) = &strategy.metadata_filter {
+        if let Some(metadata_filter) = &strategy.metadata_filter {
             // Try exact match first
-            if filter.exact_match {
+            if metadata_filter.exact_match {
+                let metadata_started = Instant::now();
                 let exact_matches = self
                     .pinecone
-                    .query_metadata(&filter.field, &filter.value, true, top_k * 3)
+                    .query_metadata_filtered(
+                        &metadata_filter.field,
+                        &metadata_filter.value,
+                        true,
+                        self.retrieval.matches(top_k),
+                        filter.as_ref(),
+                    )
                     .await?;
+                trace
+                    .timings
+                    .add_metadata_search(metadata_started.elapsed());
                 results.extend(exact_matches);
             }
 
             // If we need more results, try partial matching
             if results.len() < top_k {
+                let metadata_started = Instant::now();
                 let partial_matches = self
                     .pinecone
-                    .query_metadata(&filter.field, &filter.value, false, top_k * 3)
+                    .query_metadata_filtered(
+                        &metadata_filter.field,
+                        &metadata_filter.value,
+                        false,
+                        self.retrieval.matches(top_k),
+                        filter.as_ref(),
+                    )
                     .await?;
+                trace
+                    .timings
+                    .add_metadata_search(metadata_started.elapsed());
 
                 // Add only new results
                 let existing_ids: HashSet<_> = results.iter().map(|r| r.id.clone()).collect();
@@ -682,13 +2750,24 @@ impl RecommendationService {
                 QueryIntent::General { query } => query,
             };
 
-            // Try to get embeddings with fallback strategy
-            let (semantic_results, using_fallback) =
-                match self.sentence_encoder.encode(query_text).await {
-                    Ok(embedding) => {
-                        // Successfully got embedding, proceed with vector search
-                        info!("Successfully encoded query '{}'", query_text);
-                        debug!(
+            // Try to get embeddings with fallback strategy; the embedder only gets part of
+            // the deadline so the fallback search still has time when it fails
+            let embedding_started = Instant::now();
+            let embedding_result = deadline::within_share(
+                EMBEDDING_DEADLINE_SHARE,
+                self.sentence_encoder
+                    .encode_with_model(query_text, embedding_model),
+            )
+            .await;
+            trace.timings.add_embedding(embedding_started.elapsed());
+            self.search_router
+                .record(Upstream::Embeddings, &embedding_result);
+
+            let (semantic_results, using_fallback) = match embedding_result {
+                Ok(embedding) => {
+                    // Successfully got embedding, proceed with vector search
+                    info!("Successfully encoded query '{}'", query_text);
+                    debug!(
                         "Embedding stats: length={}, avg={:.4}, min={:.4}, max={:.4}, sum={:.4}",
                         embedding.len(),
                         embedding.iter().sum::<f32>() / embedding.len() as f32,
@@ -696,33 +2775,81 @@ impl RecommendationService {
                         embedding.iter().fold(f32::NEG_INFINITY, |a, &b| a.max(b)),
                         embedding.iter().sum::<f32>()
                     );
+                    debug!(
+                        "Performing vector search with embedding for '{}', semantic_weight={}",
+                        query_text, strategy.semantic_weight
+                    );
+                    let vector_started = Instant::now();
+                    let mut results = self
+                        .pinecone
+                        .query_vector_filtered(
+                            &embedding,
+                            self.retrieval.matches(top_k),
+                            filter.as_ref(),
+                        )
+                        .await?;
+                    // Books without the filtered fields never match a filter, so top up
+                    // from the unfiltered search and leave ranking to push them down
+                    if filter.is_some() && results.len() < top_k {
                         debug!(
-                            "Performing vector search with embedding for '{}', semantic_weight={}",
-                            query_text, strategy.semantic_weight
+                            "Filtered vector search returned {} results, topping up without filters",
+                            results.len()
+                        );
+                        let filtered_ids: HashSet<_> =
+                            results.iter().map(|r| r.id.clone()).collect();
+                        let unfiltered = self
+                            .pinecone
+                            .query_vector(&embedding, self.retrieval.matches(top_k))
+                            .await?;
+                        results.extend(
+                            unfiltered
+                                .into_iter()
+                                .filter(|book| !filtered_ids.contains(&book.id)),
                         );
-                        let results = self.pinecone.query_vector(&embedding, top_k * 3).await?;
-                        (results, false) // Not using fallback
                     }
-                    Err(e) => {
-                        // Check if the error is a timeout
-                        if e.to_string().contains("timed out") || e.to_string().contains("timeout")
+                    // Sparse queries also search near the vocabulary phrases closest to them
+                    if embedding_model.is_none()
+                        && is_sparse(&self.query_enhancer.enhance(query_text))
+                    {
+                        let mut expanded = Vec::new();
+                        for (phrase, vector) in
+                            self.query_expander.expansions(query_text, &embedding)
                         {
-                            // Log the timeout but continue with fallback strategy
-                            warn!(
-                                "HuggingFace API timed out, using fallback search strategy: {}",
-                                e
-                            );
-
-                            // Use fallback search strategy when embeddings are unavailable
-                            let fallback_results =
-                                self.perform_fallback_search(query_text, top_k).await?;
-                            (fallback_results, true) // Using fallback
-                        } else {
-                            // For non-timeout errors, propagate them
-                            return Err(e);
+                            match self
+                                .pinecone
+                                .query_vector_filtered(&vector, top_k, filter.as_ref())
+                                .await
+                            {
+                                Ok(books) => {
+                                    info!("Expanded query '{}' with '{}'", query_text, phrase);
+                                    expanded.push(books);
+                                }
+                                Err(e) => warn!(
+                                    "Expanded search of '{}' with '{}' failed: {}",
+                                    query_text, phrase, e
+                                ),
+                            }
                         }
+                        results = blend(results, expanded);
                     }
-                };
+                    trace.timings.add_vector_search(vector_started.elapsed());
+                    (results, false) // Not using fallback
+                }
+                Err(e) => {
+                    warn!(
+                        "Embedding the query failed, using fallback search strategy: {}",
+                        e
+                    );
+                    trace.search_path = SearchPath::Sparse;
+                    trace.used_fallback = true;
+                    let fallback_started = Instant::now();
+                    let fallback_results = self.perform_fallback_search(query_text, top_k).await?;
+                    trace
+                        .timings
+                        .add_fallback_search(fallback_started.elapsed());
+                    (fallback_results, true) // Using fallback
+                }
+            };
 
             if strategy.hybrid_search && !using_fallback {
                 // Weight semantic results (only if not using fallback)
@@ -755,84 +2882,124 @@ impl RecommendationService {
     }
 
     /// Fallback search when HuggingFace embedding service is unavailable
-    /// Uses metadata search based on query terms or falls back to popular books
+    ///
+    /// Searches the local full-text index when one is open, which needs neither
+    /// Pinecone nor embeddings. Otherwise scores books by the words they share
+    /// with the query when the index holds keyword vectors, or looks the query's
+    /// longer words up as title and description values, and tops up too few
+    /// results with popular books.
     async fn perform_fallback_search(&self, query_text: &str, top_k: usize) -> Result<Vec<Book>> {
         info!("Using fallback search strategy for query: {}", query_text);
+        let wanted = self.retrieval.fallback_results(top_k);
+        let matches = self.retrieval.matches(top_k);
 
-        // Extract meaningful terms from the query - optimized for better term extraction
-        let terms: Vec<&str> = query_text
-            .split_whitespace()
-            .filter(|word| {
-                // Use a more sophisticated filter for meaningful words
-                let word_lower = word.to_lowercase();
-                word.len() > 3
-                    && !["this", "that", "with", "from", "have", "like"]
-                        .contains(&word_lower.as_str())
-            })
-            .take(5) // Take more terms for better coverage
-            .collect();
-
-        // Use sequential search instead for simplicity
         let mut fallback_results = Vec::new();
         let mut seen_ids = HashSet::new();
 
-        // If we have terms, search with each term
-        if !terms.is_empty() {
-            for term in &terms {
-                // Search in title field
-                if let Ok(title_matches) = self
-                    .pinecone
-                    .query_metadata("title", term, false, top_k * 3)
-                    .await
-                {
-                    // Add unique books to results
-                    for book in title_matches {
-                        if let Some(id) = &book.id {
-                            if seen_ids.insert(id.clone()) {
+        if let Some(fulltext) = &self.fulltext {
+            match fulltext.search(query_text, wanted) {
+                Ok(books) => {
+                    let books = self.without_hidden(books);
+                    info!("Full-text index found {} results", books.len());
+                    // With no match, the searches below still top the results up with popular books
+                    if !books.is_empty() {
+                        return Ok(mark_as_fallback(books));
+                    }
+                }
+                Err(e) => warn!("Full-text search failed: {}", e),
+            }
+        }
+
+        if self.sparse_keywords {
+            match encode_query(query_text) {
+                Some(keywords) => match self.pinecone.query_sparse(&keywords, wanted).await {
+                    Ok(books) => {
+                        for book in books {
+                            let unseen = book
+                                .id
+                                .as_ref()
+                                .is_none_or(|id| seen_ids.insert(id.clone()));
+                            if unseen {
                                 fallback_results.push(book);
                             }
-                        } else {
-                            fallback_results.push(book);
                         }
+                    }
+                    Err(e) => warn!("Keyword search failed: {}", e),
+                },
+                None => debug!("No keywords to search for in '{}'", query_text),
+            }
+        } else {
+            // Extract meaningful terms from the query - optimized for better term extraction
+            let terms: Vec<&str> = query_text
+                .split_whitespace()
+                .filter(|word| {
+                    // Use a more sophisticated filter for meaningful words
+                    let word_lower = word.to_lowercase();
+                    word.len() > 3
+                        && !["this", "that", "with", "from", "have", "like"]
+                            .contains(&word_lower.as_str())
+                })
+                .take(self.retrieval.fallback_terms)
+                .collect();
+
+            // If we have terms, search with each term
+            if !terms.is_empty() {
+                for term in &terms {
+                    // Search in title field
+                    if let Ok(title_matches) = self
+                        .pinecone
+                        .query_metadata("title", term, false, matches)
+                        .await
+                    {
+                        // Add unique books to results
+                        for book in title_matches {
+                            if let Some(id) = &book.id {
+                                if seen_ids.insert(id.clone()) {
+                                    fallback_results.push(book);
+                                }
+                            } else {
+                                fallback_results.push(book);
+                            }
 
-                        // If we have enough results, stop processing
-                        if fallback_results.len() >= top_k * 2 {
-                            break;
+                            // If we have enough results, stop processing
+                            if fallback_results.len() >= wanted {
+                                break;
+                            }
                         }
                     }
-                }
 
-                // If we already have enough results, don't keep searching
-                if fallback_results.len() >= top_k * 2 {
-                    break;
-                }
+                    // If we already have enough results, don't keep searching
+                    if fallback_results.len() >= wanted {
+                        break;
+                    }
 
-                // Search in description field
-                if let Ok(desc_matches) = self
-                    .pinecone
-                    .query_metadata("description", term, false, top_k * 3)
-                    .await
-                {
-                    // Add unique books to results
-                    for book in desc_matches {
-                        if let Some(id) = &book.id {
-                            if seen_ids.insert(id.clone()) {
+                    // Search in description field
+                    if let Ok(desc_matches) = self
+                        .pinecone
+                        .query_metadata("description", term, false, matches)
+                        .await
+                    {
+                        // Add unique books to results
+                        for book in desc_matches {
+                            if let Some(id) = &book.id {
+                                if seen_ids.insert(id.clone()) {
+                                    fallback_results.push(book);
+                                }
+                            } else {
                                 fallback_results.push(book);
                             }
-                        } else {
-                            fallback_results.push(book);
-                        }
 
-                        // If we have enough results, stop processing
-                        if fallback_results.len() >= top_k * 2 {
-                            break;
+                            // If we have enough results, stop processing
+                            if fallback_results.len() >= wanted {
+                                break;
+                            }
                         }
                     }
-                }
 
-                // If we already have enough results, don't keep searching
-                if fallback_results.len() >= top_k * 2 {
-                    break;
+                    // If we already have enough results, don't keep searching
+                    if fallback_results.len() >= wanted {
+                        break;
+                    }
                 }
             }
         }
@@ -845,7 +3012,7 @@ impl RecommendationService {
             // Try high rating books first
             if let Ok(popular_books) = self
                 .pinecone
-                .query_metadata("rating", "4.5", false, top_k * 3)
+                .query_metadata("rating", "4.5", false, matches)
                 .await
             {
                 // Use our existing seen_ids HashSet to filter duplicates
@@ -864,7 +3031,7 @@ impl RecommendationService {
             if fallback_results.len() < top_k {
                 if let Ok(recent_books) = self
                     .pinecone
-                    .query_metadata("year", "2020", false, top_k * 3)
+                    .query_metadata("year", "2020", false, matches)
                     .await
                 {
                     // Use our existing seen_ids HashSet to filter duplicates
@@ -882,16 +3049,30 @@ impl RecommendationService {
         }
 
         info!("Fallback strategy found {} results", fallback_results.len());
+        Ok(mark_as_fallback(fallback_results))
+    }
+}
 
-        // Ensure each fallback result has a recognizable ID for analytics
-        for book in &mut fallback_results {
-            if let Some(id) = &mut book.id {
-                if !id.starts_with("fallback-") {
-                    *id = format!("fallback-{}", id);
-                }
+/// `books` with recognizable fallback ids, for analytics
+fn mark_as_fallback(mut books: Vec<Book>) -> Vec<Book> {
+    for book in &mut books {
+        if let Some(id) = &mut book.id {
+            if !id.starts_with("fallback-") {
+                *id = format!("fallback-{}", id);
             }
         }
-
-        Ok(fallback_results)
     }
+    books
+}
+
+/// A book from its stored metadata, hidden or not
+fn stored_book(book_id: &str, stored: &serde_json::Value) -> Result<Book> {
+    let mut book: Book = serde_json::from_value(stored.clone()).map_err(|e| {
+        ApiError::InternalError(format!(
+            "Stored metadata of book {} is invalid: {}",
+            book_id, e
+        ))
+    })?;
+    book.id = Some(book_id.to_string());
+    Ok(book)
 }
@@ -0,0 +1,112 @@
+//! Recovery from empty or weak results
+//!
+//! A query's year range, length, award and settings narrow the vector search
+//! with metadata filters. When too few of the ranked results meet them, they
+//! are dropped one at a time, least central first, and the search repeated
+//! until enough results do; the response lists what was dropped. Filters never
+//! empty a result list on their own, since the vector search tops up from its
+//! unfiltered results, so these nearest matches stand in until then.
+
+use crate::models::{Book, RelaxedConstraint};
+use crate::services::ranking::meets_constraints;
+use crate::services::semantic_classifier::SemanticQueryInfo;
+
+/// Results that must meet the query's constraints for none to be relaxed
+const MIN_MATCHING_RESULTS: usize = 3;
+
+/// The order constraints are relaxed in
+const RELAXATION_ORDER: [RelaxedConstraint; 4] = [
+    RelaxedConstraint::Year,
+    RelaxedConstraint::Length,
+    RelaxedConstraint::Award,
+    RelaxedConstraint::Setting,
+];
+
+/// Whether the query sets `constraint`
+fn has_constraint(query_info: &SemanticQueryInfo, constraint: RelaxedConstraint) -> bool {
+    match constraint {
+        RelaxedConstraint::Year => query_info.temporal_filter.is_some(),
+        RelaxedConstraint::Length => query_info.length_filter.is_some(),
+        RelaxedConstraint::Award => query_info.award.is_some(),
+        RelaxedConstraint::Setting => !query_info.settings.is_empty(),
+    }
+}
+
+/// Constraints the query sets, in the order they're relaxed
+pub fn relaxable_constraints(query_info: &SemanticQueryInfo) -> Vec<RelaxedConstraint> {
+    RELAXATION_ORDER
+        .into_iter()
+        .filter(|&constraint| has_constraint(query_info, constraint))
+        .collect()
+}
+
+/// Drop `constraint` from the query
+pub fn relax(query_info: &mut SemanticQueryInfo, constraint: RelaxedConstraint) {
+    match constraint {
+        RelaxedConstraint::Year => query_info.temporal_filter = None,
+        RelaxedConstraint::Length => query_info.length_filter = None,
+        RelaxedConstraint::Award => query_info.award = None,
+        RelaxedConstraint::Setting => query_info.settings.clear(),
+    }
+}
+
+/// Whether too few of the results of a query for `top_k` books meet its constraints
+pub fn is_weak(results: &[Book], query_info: &SemanticQueryInfo, top_k: usize) -> bool {
+    let needed = MIN_MATCHING_RESULTS.min(top_k).max(1);
+    results
+        .iter()
+        .filter(|book| meets_constraints(book, query_info))
+        .take(needed)
+        .count()
+        < needed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::semantic_classifier::{LengthFilter, SemanticClassifier, TemporalFilter};
+
+    fn book(year: i32, page_count: i32) -> Book {
+        serde_json::from_value(serde_json::json!({
+            "categories": [],
+            "year": year,
+            "page_count": page_count,
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_constraints_are_relaxed_until_enough_results_meet_them() {
+        let mut query_info = SemanticClassifier::new()
+            .unwrap()
+            .analyze_query("fantasy")
+            .await
+            .unwrap();
+        query_info.temporal_filter = Some(TemporalFilter {
+            min_year: Some(2015),
+            max_year: None,
+            recency_boost: 1.0,
+        });
+        query_info.length_filter = LengthFilter::from_bounds(None, Some(300));
+
+        let results = vec![
+            book(2020, 250),
+            book(1990, 200),
+            book(1985, 280),
+            book(2018, 600),
+        ];
+        assert_eq!(
+            relaxable_constraints(&query_info),
+            vec![RelaxedConstraint::Year, RelaxedConstraint::Length]
+        );
+        assert!(is_weak(&results, &query_info, 10));
+        assert!(!is_weak(&results, &query_info, 1));
+
+        relax(&mut query_info, RelaxedConstraint::Year);
+        assert!(!is_weak(&results, &query_info, 10));
+        assert_eq!(
+            relaxable_constraints(&query_info),
+            vec![RelaxedConstraint::Length]
+        );
+    }
+}
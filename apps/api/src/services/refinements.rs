@@ -0,0 +1,217 @@
+//! Query refinement suggestions
+//!
+//! After a search, a few ways to narrow it are suggested, each as the query
+//! extended with words the query analysis already understands: a genre,
+//! "recent" or "classic", "short" or "long", a mood, or "set in" a place. Only
+//! dimensions the query doesn't constrain yet are considered, a refinement is
+//! only offered when it keeps a useful share of the current results, and those
+//! splitting the results most evenly come first.
+
+use crate::models::{Book, Refinement, RefinementKind};
+use crate::services::semantic_classifier::SemanticQueryInfo;
+use crate::services::templates::{genre_of, LONG_READ_MIN_PAGES, SHORT_READ_MAX_PAGES};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Most refinements suggested for a query
+pub const MAX_REFINEMENTS: usize = 5;
+
+/// Fewest results a query needs before narrowing it is worth suggesting
+const MIN_RESULTS: usize = 5;
+
+/// Share of the results a refinement must keep; fewer is a dead end, more narrows nothing
+const MIN_KEPT_SHARE: f32 = 0.15;
+const MAX_KEPT_SHARE: f32 = 0.85;
+
+/// Genres, moods and settings of the results each dimension considers, most common first
+const CANDIDATES_PER_DIMENSION: usize = 2;
+
+/// First year "recent" covers in the query analysis
+const RECENT_FROM_YEAR: i32 = 2015;
+
+/// Last year "classic" covers in the query analysis
+const CLASSIC_UNTIL_YEAR: i32 = 2000;
+
+/// Labels of `labels` with the number of results carrying each, most common first
+fn most_common<'a>(labels: impl Iterator<Item = &'a String>) -> Vec<(&'a str, usize)> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for label in labels {
+        *counts.entry(label.as_str()).or_default() += 1;
+    }
+    let mut counts: Vec<(&str, usize)> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    counts
+}
+
+/// "similar but darker" for dark, "similar but more whimsical" for whimsical
+fn mood_label(mood: &str) -> String {
+    let comparative = match mood {
+        "dark" => "darker",
+        "funny" => "funnier",
+        "cozy" => "cozier",
+        "gritty" => "grittier",
+        _ => return format!("similar but more {}", mood),
+    };
+    format!("similar but {}", comparative)
+}
+
+/// Up to [`MAX_REFINEMENTS`] ways to narrow the query behind `books`
+pub fn suggest_refinements(query_info: &SemanticQueryInfo, books: &[Arc<Book>]) -> Vec<Refinement> {
+    if books.len() < MIN_RESULTS {
+        return Vec::new();
+    }
+    let query = query_info.original_query.trim();
+    let mut candidates: Vec<Refinement> = Vec::new();
+    let mut candidate = |label: String, query: String, kind: RefinementKind, matches: usize| {
+        candidates.push(Refinement {
+            label,
+            query,
+            kind,
+            matches,
+        })
+    };
+
+    let queried_genres: Vec<&str> = query_info
+        .themes
+        .iter()
+        .filter_map(|(keyword, _)| genre_of(keyword))
+        .collect();
+    for (genre, matches) in most_common(books.iter().flat_map(|book| &book.genres))
+        .into_iter()
+        .filter(|(genre, _)| genre_of(genre).is_none_or(|key| !queried_genres.contains(&key)))
+        .take(CANDIDATES_PER_DIMENSION)
+    {
+        candidate(
+            format!("only {}", genre),
+            format!("{} {}", genre, query),
+            RefinementKind::Genre,
+            matches,
+        );
+    }
+
+    if query_info.temporal_filter.is_none() {
+        let years = books.iter().filter_map(|book| book.year);
+        candidate(
+            format!("published since {}", RECENT_FROM_YEAR),
+            format!("recent {}", query),
+            RefinementKind::Era,
+            years
+                .clone()
+                .filter(|&year| year >= RECENT_FROM_YEAR)
+                .count(),
+        );
+        candidate(
+            format!("published by {}", CLASSIC_UNTIL_YEAR),
+            format!("classic {}", query),
+            RefinementKind::Era,
+            years.filter(|&year| year <= CLASSIC_UNTIL_YEAR).count(),
+        );
+    }
+
+    if query_info.length_filter.is_none() {
+        // Unknown page counts are stored as 0
+        let pages = books
+            .iter()
+            .filter_map(|book| book.page_count)
+            .filter(|&pages| pages > 0);
+        candidate(
+            format!("short reads, up to {} pages", SHORT_READ_MAX_PAGES),
+            format!("short {}", query),
+            RefinementKind::Length,
+            pages
+                .clone()
+                .filter(|&pages| pages <= SHORT_READ_MAX_PAGES)
+                .count(),
+        );
+        candidate(
+            format!("long reads, {} pages or more", LONG_READ_MIN_PAGES),
+            format!("long {}", query),
+            RefinementKind::Length,
+            pages.filter(|&pages| pages >= LONG_READ_MIN_PAGES).count(),
+        );
+    }
+
+    if query_info.moods.is_empty() {
+        for (mood, matches) in most_common(books.iter().flat_map(|book| &book.moods))
+            .into_iter()
+            .take(CANDIDATES_PER_DIMENSION)
+        {
+            candidate(
+                mood_label(mood),
+                format!("{} {}", mood, query),
+                RefinementKind::Mood,
+                matches,
+            );
+        }
+    }
+
+    if query_info.settings.is_empty() {
+        for (setting, matches) in most_common(books.iter().flat_map(|book| &book.settings))
+            .into_iter()
+            .take(CANDIDATES_PER_DIMENSION)
+        {
+            candidate(
+                format!("set in {}", setting),
+                format!("{} set in {}", query, setting),
+                RefinementKind::Setting,
+                matches,
+            );
+        }
+    }
+
+    let total = books.len() as f32;
+    let evenness = |refinement: &Refinement| (refinement.matches as f32 / total - 0.5).abs();
+    candidates.retain(|refinement| {
+        let share = refinement.matches as f32 / total;
+        (MIN_KEPT_SHARE..=MAX_KEPT_SHARE).contains(&share)
+    });
+    candidates.sort_by(|a, b| evenness(a).total_cmp(&evenness(b)));
+    candidates.truncate(MAX_REFINEMENTS);
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::semantic_classifier::SemanticClassifier;
+
+    fn book(i: usize) -> Arc<Book> {
+        Arc::new(
+            serde_json::from_value(serde_json::json!({
+                "id": i.to_string(),
+                "categories": [],
+                "genres": if i < 3 { vec!["fantasy", "mystery"] } else { vec!["mystery"] },
+                "moods": if i.is_multiple_of(2) { vec!["dark"] } else { vec![] },
+                "year": 2005 + i as i32 * 2,
+                "page_count": 0,
+            }))
+            .unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_refinements_split_results_on_unconstrained_dimensions() {
+        let books: Vec<Arc<Book>> = (0..10).map(book).collect();
+        let query_info = SemanticClassifier::new()
+            .unwrap()
+            .analyze_query("detective novels")
+            .await
+            .unwrap();
+
+        let refinements = suggest_refinements(&query_info, &books);
+        let suggested: Vec<(&str, &str, usize)> = refinements
+            .iter()
+            .map(|r| (r.label.as_str(), r.query.as_str(), r.matches))
+            .collect();
+        assert_eq!(
+            suggested,
+            vec![
+                ("published since 2015", "recent detective novels", 5),
+                ("similar but darker", "dark detective novels", 5),
+                ("only fantasy", "fantasy detective novels", 3),
+            ]
+        );
+
+        assert!(suggest_refinements(&query_info, &books[..4]).is_empty());
+    }
+}
@@ -0,0 +1,73 @@
+//! Budgets of the retrieval and ranking stages
+//!
+//! A query for `top_k` books retrieves a few times as many candidates, asks
+//! Pinecone for a few times as many matches again so filtered and duplicate
+//! ones can be dropped, and ranks the leading candidates mostly by retrieval
+//! position. Larger budgets find more of the relevant books for more latency
+//! and Pinecone reads; a small instance can trade some recall for speed with
+//! the `[retrieval]` table.
+
+use serde::Deserialize;
+
+/// Budgets of each stage of answering a query, the `[retrieval]` table
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct RetrievalConfig {
+    /// Candidates retrieved for ranking per result asked for
+    pub candidates_per_result: usize,
+    /// Matches each metadata and vector query asks Pinecone for, per candidate wanted
+    pub matches_per_candidate: usize,
+    /// Results the fallback search collects per candidate wanted
+    pub fallback_results_per_candidate: usize,
+    /// Words of the query the metadata fallback looks up
+    pub fallback_terms: usize,
+    /// Leading candidates of general queries ranked mostly by retrieval
+    /// position; those after them lean on their rating
+    pub trusted_positions: usize,
+}
+
+impl Default for RetrievalConfig {
+    fn default() -> Self {
+        Self {
+            candidates_per_result: 3,
+            matches_per_candidate: 3,
+            fallback_results_per_candidate: 2,
+            fallback_terms: 5,
+            trusted_positions: 50,
+        }
+    }
+}
+
+impl RetrievalConfig {
+    /// Candidates to retrieve for `top_k` results
+    pub fn candidates(&self, top_k: usize) -> usize {
+        top_k * self.candidates_per_result.max(1)
+    }
+
+    /// Matches to ask each Pinecone query for when `wanted` candidates are
+    pub fn matches(&self, wanted: usize) -> usize {
+        wanted * self.matches_per_candidate.max(1)
+    }
+
+    /// Results the fallback search collects when `wanted` candidates are
+    pub fn fallback_results(&self, wanted: usize) -> usize {
+        wanted * self.fallback_results_per_candidate.max(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_budgets_scale_with_the_results_asked_for() {
+        let config: RetrievalConfig =
+            serde_json::from_str(r#"{ "candidates_per_result": 2, "matches_per_candidate": 0 }"#)
+                .unwrap();
+        assert_eq!(config.candidates(10), 20);
+        // A zero multiplier would retrieve nothing, so it counts as 1
+        assert_eq!(config.matches(20), 20);
+        assert_eq!(config.fallback_results(20), 40);
+        assert_eq!(config.trusted_positions, 50);
+    }
+}
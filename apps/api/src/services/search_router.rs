@@ -0,0 +1,206 @@
+//! Health-aware routing between the search paths
+//!
+//! Each upstream a query depends on, the embedding service and Pinecone, has a
+//! circuit breaker fed by the outcome of every call made to it. A few failures
+//! in a row open the breaker and requests stop waiting on the upstream; once a
+//! cooldown passes the next request probes it again, and an answer closes the
+//! breaker. Every query is routed to the best path the breakers allow: the
+//! vector search, the keyword search while embeddings are down, or the query's
+//! last good candidates while Pinecone is down too and no local index can answer.
+
+use crate::error::{recover_lock, ApiError, Result};
+use crate::models::{SearchPath, UpstreamState, UpstreamStatus};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Failures in a row that open a breaker
+const FAILURES_TO_OPEN: u32 = 3;
+
+/// How long an open breaker keeps requests away from its upstream
+const OPEN_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// An upstream the search depends on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Upstream {
+    Embeddings,
+    Pinecone,
+}
+
+impl Upstream {
+    const ALL: [Upstream; 2] = [Upstream::Embeddings, Upstream::Pinecone];
+
+    fn name(self) -> &'static str {
+        match self {
+            Upstream::Embeddings => "embeddings",
+            Upstream::Pinecone => "pinecone",
+        }
+    }
+}
+
+/// Whether an error means the upstream failed, rather than the request being
+/// one it can't answer
+fn is_upstream_failure(error: &ApiError) -> bool {
+    !matches!(error, ApiError::InvalidInput(_) | ApiError::NotFound(_))
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Breaker {
+    consecutive_failures: u32,
+    /// When the breaker last opened, or None while it's closed
+    opened_at: Option<Instant>,
+}
+
+impl Breaker {
+    fn state(&self, now: Instant) -> UpstreamState {
+        match self.opened_at {
+            None => UpstreamState::Closed,
+            Some(at) if now.duration_since(at) < OPEN_COOLDOWN => UpstreamState::Open,
+            Some(_) => UpstreamState::HalfOpen,
+        }
+    }
+
+    fn record(&mut self, succeeded: bool, now: Instant) {
+        if succeeded {
+            *self = Self::default();
+            return;
+        }
+        self.consecutive_failures += 1;
+        // A failed probe reopens the breaker at once
+        if self.opened_at.is_some() || self.consecutive_failures >= FAILURES_TO_OPEN {
+            self.opened_at = Some(now);
+        }
+    }
+
+    /// Time until an open breaker lets a probe through
+    fn remaining_cooldown(&self, now: Instant) -> Option<Duration> {
+        let opened_at = self.opened_at?;
+        OPEN_COOLDOWN.checked_sub(now.duration_since(opened_at))
+    }
+}
+
+/// Tracks the health of the search's upstreams and picks each query's path
+#[derive(Clone, Default)]
+pub struct SearchRouter {
+    breakers: Arc<Mutex<[Breaker; 2]>>,
+}
+
+impl SearchRouter {
+    fn breakers(&self) -> std::sync::MutexGuard<'_, [Breaker; 2]> {
+        recover_lock(self.breakers.lock(), "search router")
+    }
+
+    /// Record the outcome of a call to `upstream`; errors that aren't the
+    /// upstream's fault, such as invalid input, don't count against it
+    pub fn record<T>(&self, upstream: Upstream, result: &Result<T>) {
+        self.record_at(upstream, result, Instant::now());
+    }
+
+    fn record_at<T>(&self, upstream: Upstream, result: &Result<T>, now: Instant) {
+        let succeeded = match result {
+            Ok(_) => true,
+            Err(e) if is_upstream_failure(e) => false,
+            Err(_) => return,
+        };
+        self.breakers()[upstream as usize].record(succeeded, now);
+    }
+
+    /// The path a query should take: the vector search while both upstreams
+    /// are up, the keyword search while Pinecone or a local index is, and the
+    /// query's cached candidates otherwise
+    pub fn route(&self, local_keywords: bool) -> SearchPath {
+        self.route_at(local_keywords, Instant::now())
+    }
+
+    fn route_at(&self, local_keywords: bool, now: Instant) -> SearchPath {
+        let breakers = self.breakers();
+        let available =
+            |upstream: Upstream| breakers[upstream as usize].state(now) != UpstreamState::Open;
+        if available(Upstream::Embeddings) && available(Upstream::Pinecone) {
+            SearchPath::Vector
+        } else if local_keywords || available(Upstream::Pinecone) {
+            SearchPath::Sparse
+        } else {
+            SearchPath::Cached
+        }
+    }
+
+    /// Seconds until every open breaker lets a probe through, for Retry-After
+    pub fn retry_after_secs(&self) -> Option<u64> {
+        let now = Instant::now();
+        self.breakers()
+            .iter()
+            .filter_map(|breaker| breaker.remaining_cooldown(now))
+            .max()
+            .map(|cooldown| cooldown.as_secs().max(1))
+    }
+
+    /// Health of each upstream, for the health endpoint
+    pub fn statuses(&self) -> Vec<UpstreamStatus> {
+        let now = Instant::now();
+        let breakers = self.breakers();
+        Upstream::ALL
+            .into_iter()
+            .map(|upstream| {
+                let breaker = breakers[upstream as usize];
+                UpstreamStatus {
+                    upstream: upstream.name().to_string(),
+                    state: breaker.state(now),
+                    consecutive_failures: breaker.consecutive_failures,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failure() -> Result<()> {
+        Err(ApiError::ExternalServiceError("timed out".to_string()))
+    }
+
+    #[test]
+    fn test_failing_upstreams_route_queries_away_until_a_probe_succeeds() {
+        let router = SearchRouter::default();
+        let start = Instant::now();
+        assert_eq!(router.route_at(false, start), SearchPath::Vector);
+
+        // Invalid input isn't the upstream's fault
+        for _ in 0..FAILURES_TO_OPEN {
+            router.record_at(
+                Upstream::Embeddings,
+                &Err::<(), _>(ApiError::InvalidInput("unknown model".to_string())),
+                start,
+            );
+        }
+        assert_eq!(router.route_at(false, start), SearchPath::Vector);
+
+        for _ in 0..FAILURES_TO_OPEN {
+            router.record_at(Upstream::Embeddings, &failure(), start);
+        }
+        assert_eq!(router.route_at(false, start), SearchPath::Sparse);
+
+        for _ in 0..FAILURES_TO_OPEN {
+            router.record_at(Upstream::Pinecone, &failure(), start);
+        }
+        assert_eq!(router.route_at(false, start), SearchPath::Cached);
+        assert_eq!(router.route_at(true, start), SearchPath::Sparse);
+
+        // After the cooldown a probe goes through; a failed one reopens at once
+        let later = start + OPEN_COOLDOWN;
+        assert_eq!(router.route_at(false, later), SearchPath::Vector);
+        router.record_at(Upstream::Pinecone, &failure(), later);
+        assert_eq!(router.route_at(false, later), SearchPath::Cached);
+        router.record_at(Upstream::Embeddings, &Ok(()), later);
+        assert_eq!(router.route_at(false, later), SearchPath::Cached);
+
+        let last = later + OPEN_COOLDOWN;
+        router.record_at(Upstream::Pinecone, &Ok(()), last);
+        assert_eq!(router.route_at(false, last), SearchPath::Vector);
+        assert!(router
+            .statuses()
+            .iter()
+            .all(|status| status.state == UpstreamState::Closed));
+    }
+}
@@ -1,4 +1,8 @@
 use crate::error::Result;
+use crate::ingest::awards::{award_in_query, AwardQuery};
+use crate::ingest::moods::{mood_named, moods_in_query};
+use crate::models::{SemanticTag, TagFilter, TagKind};
+use crate::services::templates::{genre_of, is_keyword_word, QueryPattern, STOP_WORDS};
 use tracing::{debug, info};
 
 /// Semantic classifier using HuggingFace zero-shot classification
@@ -73,17 +77,83 @@ impl SemanticClassifier {
             || query_lower.contains("reminds me of")
             || query_lower.contains("in the style of")
     }
+
+    /// How likely a phrase an author pattern captured is a name rather than a topic
+    ///
+    /// Capitalized words read as a name. Lowercase stop words, moods and genre or
+    /// theme keywords count against it, as in "stories of love and loss".
+    pub fn author_confidence(&self, author: &str) -> f32 {
+        let words: Vec<&str> = author.split_whitespace().collect();
+        if words.is_empty() {
+            return 0.0;
+        }
+        let capitalized = |word: &&str| word.starts_with(|c: char| c.is_uppercase());
+        if words.iter().all(capitalized) {
+            return PATTERN_CONFIDENCE;
+        }
+        let topical = words
+            .iter()
+            .filter(|word| !capitalized(word))
+            .map(|word| word.trim_matches(|c: char| !c.is_alphanumeric()))
+            .filter(|word| {
+                STOP_WORDS.contains(word) || is_keyword_word(word) || mood_named(word).is_some()
+            })
+            .count();
+        LOWERCASE_AUTHOR_CONFIDENCE * (words.len() - topical) as f32 / words.len() as f32
+    }
+
+    /// How likely a query the similarity check matched asks for books like another
+    pub fn similar_confidence(&self, query: &str) -> f32 {
+        let query_lower = query.to_lowercase();
+        if ["similar to", "reminds me of", "in the style of"]
+            .iter()
+            .any(|phrase| query_lower.contains(phrase))
+        {
+            return PATTERN_CONFIDENCE;
+        }
+        let words: Vec<&str> = query_lower
+            .split(|c: char| !c.is_alphanumeric() && c != '\'')
+            .filter(|word| !word.is_empty())
+            .collect();
+        match words.iter().position(|&word| word == "like") {
+            // Only part of a word, as in "unlikely"
+            None => 0.0,
+            // A request rather than a comparison, as in "I'd like a thriller"
+            Some(index)
+                if index > 0 && ["i", "i'd", "we", "we'd", "would"].contains(&words[index - 1]) =>
+            {
+                REQUEST_LIKE_CONFIDENCE
+            }
+            Some(_) => LOOSE_SIMILAR_CONFIDENCE,
+        }
+    }
 }
 
 /// Temporal filter information extracted from query
 #[derive(Debug, Clone)]
-#[allow(dead_code)]
 pub struct TemporalFilter {
     pub min_year: Option<i32>,
     pub max_year: Option<i32>,
     pub recency_boost: f32,
 }
 
+/// Page-count range asked for by a query, e.g. at most 300 pages for "short reads"
+#[derive(Debug, Clone, PartialEq)]
+pub struct LengthFilter {
+    pub min_pages: Option<i32>,
+    pub max_pages: Option<i32>,
+}
+
+impl LengthFilter {
+    /// The range, or None if neither bound is set
+    pub fn from_bounds(min_pages: Option<i32>, max_pages: Option<i32>) -> Option<Self> {
+        (min_pages.is_some() || max_pages.is_some()).then_some(Self {
+            min_pages,
+            max_pages,
+        })
+    }
+}
+
 /// Enhanced query information from semantic classification
 #[derive(Debug, Clone)]
 pub struct SemanticQueryInfo {
@@ -91,8 +161,134 @@ pub struct SemanticQueryInfo {
     pub themes: Vec<(String, f32)>,
     pub author: Option<String>,
     pub temporal_filter: Option<TemporalFilter>,
+    pub length_filter: Option<LengthFilter>,
     pub is_similar_query: bool,
     pub semantic_tags: Vec<String>,
+    /// Moods asked for, from the mood vocabulary books are tagged with
+    pub moods: Vec<String>,
+    /// Award the books should have won, e.g. for "award-winning science fiction"
+    pub award: Option<AwardQuery>,
+    /// Places the books should be set in, e.g. "Japan" for "novels set in japan"
+    pub settings: Vec<String>,
+    /// Confidence in the author or "similar to" reading of the query, 1 for
+    /// other queries; below [`MIN_INTENT_CONFIDENCE`] it's searched as general
+    pub intent_confidence: f32,
+    /// What the query mostly asks for, as the query templates read it
+    pub pattern: QueryPattern,
+}
+
+/// Least confidence in an author or "similar to" reading to search by it
+pub const MIN_INTENT_CONFIDENCE: f32 = 0.5;
+
+/// Confidence of an author the catalog has under the same name
+pub const KNOWN_AUTHOR_CONFIDENCE: f32 = 0.95;
+
+/// Confidence of a mood from the mood vocabulary
+const MOOD_CONFIDENCE: f32 = 0.85;
+
+/// Confidence of an author or era read from a query pattern
+const PATTERN_CONFIDENCE: f32 = 0.9;
+
+/// Confidence of an author typed in lowercase, before topical words count against it
+const LOWERCASE_AUTHOR_CONFIDENCE: f32 = 0.7;
+
+/// Confidence of a "like" comparison without a clearer phrase, as in "books like dune"
+const LOOSE_SIMILAR_CONFIDENCE: f32 = 0.6;
+
+/// Confidence of "like" after "I" or "would", which usually asks rather than compares
+const REQUEST_LIKE_CONFIDENCE: f32 = 0.3;
+
+/// Label of a year range, e.g. "1990-1999", "since 2015" or "before 1900"
+fn era_label(filter: &TemporalFilter) -> Option<String> {
+    match (filter.min_year, filter.max_year) {
+        (Some(min), Some(max)) => Some(format!("{}-{}", min, max)),
+        (Some(min), None) => Some(format!("since {}", min)),
+        (None, Some(max)) => Some(format!("before {}", max)),
+        (None, None) => None,
+    }
+}
+
+impl SemanticQueryInfo {
+    /// The query's author, unless the pattern that read it is too unsure it's a name
+    pub fn confident_author(&self) -> Option<&str> {
+        self.author
+            .as_deref()
+            .filter(|_| self.intent_confidence >= MIN_INTENT_CONFIDENCE)
+    }
+
+    /// The query's author, genres, moods, themes and era as typed tags
+    ///
+    /// Theme keywords naming a vocabulary genre become that genre, and keywords
+    /// that are a mood or part of the author's name aren't repeated as themes.
+    pub fn structured_tags(&self) -> Vec<SemanticTag> {
+        let mut tags: Vec<SemanticTag> = Vec::new();
+        let mut push = |label: String, kind: TagKind, confidence: f32, filter: TagFilter| {
+            if !tags
+                .iter()
+                .any(|tag| tag.kind == kind && tag.label.eq_ignore_ascii_case(&label))
+            {
+                tags.push(SemanticTag {
+                    label,
+                    kind,
+                    confidence,
+                    filter,
+                });
+            }
+        };
+        let named = |label: &str, kind: TagKind| TagFilter::named(kind, label);
+
+        let author = self.confident_author().map(str::to_lowercase);
+        if let Some(author) = self.confident_author() {
+            push(
+                author.to_string(),
+                TagKind::Author,
+                self.intent_confidence,
+                named(author, TagKind::Author),
+            );
+        }
+        for (keyword, confidence) in &self.themes {
+            if self.moods.contains(keyword)
+                || author
+                    .as_ref()
+                    .is_some_and(|author| author.contains(&keyword.to_lowercase()))
+            {
+                continue;
+            }
+            match genre_of(keyword) {
+                Some(genre) => push(
+                    genre.to_string(),
+                    TagKind::Genre,
+                    *confidence,
+                    named(genre, TagKind::Genre),
+                ),
+                None => push(
+                    keyword.clone(),
+                    TagKind::Theme,
+                    *confidence,
+                    named(keyword, TagKind::Theme),
+                ),
+            }
+        }
+        for mood in &self.moods {
+            push(
+                mood.clone(),
+                TagKind::Mood,
+                MOOD_CONFIDENCE,
+                named(mood, TagKind::Mood),
+            );
+        }
+        if let Some(filter) = &self.temporal_filter {
+            if let Some(era) = era_label(filter) {
+                let years = TagFilter {
+                    min_year: filter.min_year,
+                    max_year: filter.max_year,
+                    ..TagFilter::default()
+                };
+                push(era, TagKind::Era, PATTERN_CONFIDENCE, years);
+            }
+        }
+        tags
+    }
 }
 
 impl SemanticClassifier {
@@ -113,13 +309,25 @@ impl SemanticClassifier {
         // Create semantic tags from keywords
         let semantic_tags = keywords.clone();
 
+        let intent_confidence = match &author {
+            Some(author) => self.author_confidence(author),
+            None if is_similar_query => self.similar_confidence(query),
+            None => 1.0,
+        };
+
         Ok(SemanticQueryInfo {
             original_query: query.to_string(),
             themes: keywords.into_iter().map(|k| (k, 0.8)).collect(), // Uniform confidence
             author,
             temporal_filter,
+            length_filter: None,
             is_similar_query,
             semantic_tags,
+            moods: moods_in_query(query),
+            award: award_in_query(query),
+            settings: vec![],
+            intent_confidence,
+            pattern: QueryPattern::default(),
         })
     }
 
@@ -206,3 +414,78 @@ impl SemanticClassifier {
             .collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::ranking::QueryIntent;
+
+    #[tokio::test]
+    async fn test_structured_tags_have_kinds() {
+        let mut info = SemanticClassifier::new()
+            .unwrap()
+            .analyze_query("cozy sci-fi detective novels with dragons")
+            .await
+            .unwrap();
+        info.temporal_filter = Some(TemporalFilter {
+            min_year: Some(2015),
+            max_year: None,
+            recency_boost: 1.0,
+        });
+
+        let structured = info.structured_tags();
+        let tags: Vec<(String, TagKind)> = structured
+            .iter()
+            .map(|tag| (tag.label.clone(), tag.kind))
+            .collect();
+        assert_eq!(
+            tags,
+            vec![
+                ("sci-fi".to_string(), TagKind::Genre),
+                ("mystery".to_string(), TagKind::Genre),
+                ("dragons".to_string(), TagKind::Theme),
+                ("cozy".to_string(), TagKind::Mood),
+                ("since 2015".to_string(), TagKind::Era),
+            ]
+        );
+        assert_eq!(structured[0].filter.genre.as_deref(), Some("sci-fi"));
+        assert_eq!(structured[3].filter.mood.as_deref(), Some("cozy"));
+        assert_eq!(
+            (structured[4].filter.min_year, structured[4].filter.max_year),
+            (Some(2015), None)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unsure_author_and_similar_readings_fall_back_to_general() {
+        let classifier = SemanticClassifier::new().unwrap();
+        let intent = |info: &SemanticQueryInfo| QueryIntent::from_query_info(info).label();
+
+        let info = classifier
+            .analyze_query("books by Ursula K. Le Guin")
+            .await
+            .unwrap();
+        assert_eq!(intent(&info), "Author");
+        let info = classifier
+            .analyze_query("stories of love and loss")
+            .await
+            .unwrap();
+        assert_eq!(info.author.as_deref(), Some("love and loss"));
+        assert!(info.intent_confidence < MIN_INTENT_CONFIDENCE);
+        assert_eq!(intent(&info), "General");
+        assert!(info
+            .structured_tags()
+            .iter()
+            .all(|tag| tag.kind != TagKind::Author));
+
+        let info = classifier.analyze_query("books like dune").await.unwrap();
+        assert_eq!(intent(&info), "SimilarTo");
+        let info = classifier
+            .analyze_query("i'd like a cozy mystery")
+            .await
+            .unwrap();
+        assert_eq!(intent(&info), "General");
+        let info = classifier.analyze_query("unlikely heroes").await.unwrap();
+        assert_eq!(intent(&info), "General");
+    }
+}
@@ -0,0 +1,140 @@
+//! Anonymous session state that doesn't need an account
+//!
+//! Sessions hold the books a visitor dismissed with "don't show again", so later
+//! queries in the same session can leave them out. They live in memory, expire
+//! after a period of inactivity and are lost on restart, which is fine for
+//! something meant to last a browsing session.
+
+use crate::error::recover_lock;
+use moka::notification::RemovalCause;
+use moka::sync::Cache;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Default inactivity after which a session is forgotten
+pub const DEFAULT_SESSION_TTL_MINUTES: u64 = 24 * 60;
+
+/// Most dismissed books remembered per session; the oldest dismissal goes first
+pub const MAX_DISMISSED_PER_SESSION: usize = 500;
+
+/// Most sessions kept at once; the least recently used goes first
+const MAX_SESSIONS: u64 = 10_000;
+
+/// Dismissed book ids, oldest first
+type Dismissed = Arc<Mutex<Vec<String>>>;
+
+/// In-memory store of per-session dismissed books
+///
+/// Sessions are looked up without a global lock; each one has its own lock for
+/// changes to its dismissed books.
+#[derive(Clone)]
+pub struct SessionStore {
+    sessions: Cache<String, Dismissed>,
+    /// Sessions that expired since the last purge
+    expired: Arc<AtomicU64>,
+}
+
+impl SessionStore {
+    pub fn new(ttl_minutes: u64) -> Self {
+        let expired = Arc::new(AtomicU64::new(0));
+        let listener_expired = expired.clone();
+        Self {
+            sessions: Cache::builder()
+                .max_capacity(MAX_SESSIONS)
+                .time_to_idle(Duration::from_secs(ttl_minutes * 60))
+                .eviction_listener(move |_key, _value, cause| {
+                    if cause == RemovalCause::Expired {
+                        listener_expired.fetch_add(1, Ordering::Relaxed);
+                    }
+                })
+                .build(),
+            expired,
+        }
+    }
+
+    /// Forget the sessions that have been idle too long; returns how many
+    /// expired since the last purge
+    pub fn purge_expired(&self) -> u64 {
+        self.sessions.run_pending_tasks();
+        self.expired.swap(0, Ordering::Relaxed)
+    }
+
+    /// Books dismissed in a session, most recent first
+    pub fn dismissed(&self, session_id: &str) -> Vec<String> {
+        match self.sessions.get(session_id) {
+            Some(dismissed) => recover_lock(dismissed.lock(), "session store")
+                .iter()
+                .rev()
+                .cloned()
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Stop showing a book in a session; dismissing it again is a no-op
+    pub fn dismiss(&self, session_id: &str, book_id: &str) {
+        let dismissed = self
+            .sessions
+            .get_with(session_id.to_string(), Dismissed::default);
+        let mut dismissed = recover_lock(dismissed.lock(), "session store");
+
+        if !dismissed.iter().any(|id| id == book_id) {
+            if dismissed.len() >= MAX_DISMISSED_PER_SESSION {
+                dismissed.remove(0);
+            }
+            dismissed.push(book_id.to_string());
+        }
+    }
+
+    /// Show a dismissed book again; returns false if it wasn't dismissed
+    pub fn undismiss(&self, session_id: &str, book_id: &str) -> bool {
+        let Some(dismissed) = self.sessions.get(session_id) else {
+            return false;
+        };
+
+        let mut dismissed = recover_lock(dismissed.lock(), "session store");
+        let before = dismissed.len();
+        dismissed.retain(|id| id != book_id);
+        dismissed.len() != before
+    }
+}
+
+impl Default for SessionStore {
+    fn default() -> Self {
+        Self::new(DEFAULT_SESSION_TTL_MINUTES)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dismissals_are_per_session() {
+        let store = SessionStore::default();
+        store.dismiss("s1", "dune");
+        store.dismiss("s1", "emma");
+        store.dismiss("s1", "dune");
+        store.dismiss("s2", "ulysses");
+
+        assert_eq!(store.dismissed("s1"), vec!["emma", "dune"]);
+        assert_eq!(store.dismissed("s2"), vec!["ulysses"]);
+        assert!(store.dismissed("s3").is_empty());
+
+        assert!(store.undismiss("s1", "dune"));
+        assert!(!store.undismiss("s1", "dune"));
+        assert_eq!(store.dismissed("s1"), vec!["emma"]);
+    }
+
+    #[test]
+    fn test_sessions_expire() {
+        let store = SessionStore::new(0);
+        store.dismiss("s1", "dune");
+        store.dismiss("s2", "emma");
+        assert!(store.dismissed("s1").is_empty());
+
+        assert_eq!(store.purge_expired(), 2);
+        assert_eq!(store.purge_expired(), 0);
+    }
+}
@@ -0,0 +1,423 @@
+//! Shadow evaluation of candidate rankers
+//!
+//! With a shadow ranker set in the `[shadow]` table, `sample_percent` of the
+//! queries that get ranked (cache hits don't) are ranked again by it, off the
+//! request path, and its order is compared with the production order that was
+//! served: how many pairs of books the two put in a different order (Kendall's
+//! tau), how many of the served books it would have returned too, how far books
+//! move, and how the mean rating of its results differs. Comparisons are kept
+//! for `/api/admin/shadow`; the shadow order is never served.
+
+use crate::error::recover_lock;
+use crate::models::Book;
+use crate::services::ranking::{QueryIntent, Ranker};
+use crate::services::semantic_classifier::SemanticQueryInfo;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex, RwLock};
+use tracing::info;
+use utoipa::ToSchema;
+
+/// Books that moved the most kept with each comparison
+const MAX_MOVES: usize = 5;
+
+/// The candidate ranker and how much traffic it shadows, the `[shadow]` table
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ShadowConfig {
+    /// Ranker to evaluate in shadow; none turns shadow ranking off
+    pub ranker: Option<Ranker>,
+    /// Share of ranked queries, from 0 to 100, ranked again in shadow
+    pub sample_percent: u8,
+    /// Comparisons kept in memory
+    pub log_capacity: usize,
+}
+
+impl Default for ShadowConfig {
+    fn default() -> Self {
+        Self {
+            ranker: None,
+            sample_percent: 10,
+            log_capacity: 200,
+        }
+    }
+}
+
+/// A book placed differently by the two rankers
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct RankMove {
+    #[schema(example = "9780441172719")]
+    pub book_id: String,
+    /// Zero-based position in the served results; None when it wasn't served
+    #[schema(example = 7)]
+    pub production_position: Option<usize>,
+    /// Zero-based position in the shadow results; None when the shadow ranker left it out
+    #[schema(example = 1)]
+    pub shadow_position: Option<usize>,
+}
+
+/// How the shadow order of one query differed from the served one
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ShadowComparison {
+    #[schema(example = "books like dune")]
+    pub query: String,
+    pub production: Ranker,
+    pub shadow: Ranker,
+    /// Books both rankers returned
+    #[schema(example = 9)]
+    pub compared: usize,
+    /// Pairs of those books the two rankers order differently
+    #[schema(example = 4)]
+    pub discordant_pairs: usize,
+    /// Kendall's tau of the two orders of those books: 1 is the same order, -1 reversed
+    #[schema(example = 0.78)]
+    pub kendall_tau: f32,
+    /// Share of the served books the shadow ranker returned too
+    #[schema(example = 0.9)]
+    pub overlap: f32,
+    /// Mean distance the books both returned moved by
+    #[schema(example = 1.2)]
+    pub mean_position_shift: f32,
+    /// Mean rating of the shadow results minus that of the served ones
+    #[schema(example = 0.05)]
+    pub rating_delta: f32,
+    /// Books that moved the most, left out or brought in first
+    pub largest_moves: Vec<RankMove>,
+    /// When the comparison was made, in RFC3339 format
+    #[schema(example = "2026-10-16T10:30:00Z")]
+    pub recorded_at: String,
+}
+
+impl ShadowComparison {
+    /// Compare the `shadow` results of `query` with the `production` ones that were served
+    pub fn between(
+        query: &str,
+        (production_ranker, production): (Ranker, &[Book]),
+        (shadow_ranker, shadow): (Ranker, &[Book]),
+    ) -> Self {
+        let positions = |books: &[Book]| -> HashMap<String, usize> {
+            let mut positions = HashMap::with_capacity(books.len());
+            for (position, book) in books.iter().enumerate() {
+                if let Some(id) = &book.id {
+                    positions.entry(id.clone()).or_insert(position);
+                }
+            }
+            positions
+        };
+        let production_positions = positions(production);
+        let shadow_positions = positions(shadow);
+
+        // Shadow positions of the books both returned, in production order
+        let mut common: Vec<(&str, usize, usize)> = production_positions
+            .iter()
+            .filter_map(|(id, &at)| Some((id.as_str(), at, *shadow_positions.get(id)?)))
+            .collect();
+        common.sort_by_key(|&(_, production_at, _)| production_at);
+
+        let mut discordant_pairs = 0;
+        for (i, (_, _, earlier)) in common.iter().enumerate() {
+            discordant_pairs += common[i + 1..]
+                .iter()
+                .filter(|(_, _, later)| later < earlier)
+                .count();
+        }
+        let pairs = common.len() * common.len().saturating_sub(1) / 2;
+        let kendall_tau = if pairs == 0 {
+            1.0
+        } else {
+            1.0 - 2.0 * discordant_pairs as f32 / pairs as f32
+        };
+        let mean_position_shift = if common.is_empty() {
+            0.0
+        } else {
+            common
+                .iter()
+                .map(|&(_, production_at, shadow_at)| production_at.abs_diff(shadow_at) as f32)
+                .sum::<f32>()
+                / common.len() as f32
+        };
+
+        let mut largest_moves: Vec<RankMove> = production_positions
+            .keys()
+            .chain(shadow_positions.keys())
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .map(|id| RankMove {
+                book_id: id.clone(),
+                production_position: production_positions.get(id).copied(),
+                shadow_position: shadow_positions.get(id).copied(),
+            })
+            .filter(|moved| moved.production_position != moved.shadow_position)
+            .collect();
+        largest_moves.sort_by(|a, b| {
+            let shift = |moved: &RankMove| match (moved.production_position, moved.shadow_position)
+            {
+                (Some(from), Some(to)) => from.abs_diff(to),
+                _ => usize::MAX,
+            };
+            shift(b)
+                .cmp(&shift(a))
+                .then_with(|| a.book_id.cmp(&b.book_id))
+        });
+        largest_moves.truncate(MAX_MOVES);
+
+        let mean_rating = |books: &[Book]| {
+            if books.is_empty() {
+                0.0
+            } else {
+                books.iter().map(|book| book.rating).sum::<f32>() / books.len() as f32
+            }
+        };
+
+        Self {
+            query: query.to_string(),
+            production: production_ranker,
+            shadow: shadow_ranker,
+            compared: common.len(),
+            discordant_pairs,
+            kendall_tau,
+            overlap: if production_positions.is_empty() {
+                1.0
+            } else {
+                common.len() as f32 / production_positions.len() as f32
+            },
+            mean_position_shift,
+            rating_delta: mean_rating(shadow) - mean_rating(production),
+            largest_moves,
+            recorded_at: Utc::now().to_rfc3339(),
+        }
+    }
+}
+
+/// Averages over every comparison since startup
+#[derive(Debug, Clone, Default, PartialEq, Serialize, ToSchema)]
+pub struct ShadowSummary {
+    #[schema(example = 1250)]
+    pub comparisons: u64,
+    #[schema(example = 0.81)]
+    pub mean_kendall_tau: f32,
+    #[schema(example = 0.92)]
+    pub mean_overlap: f32,
+    #[schema(example = 0.03)]
+    pub mean_rating_delta: f32,
+}
+
+#[derive(Debug, Default)]
+struct Totals {
+    comparisons: u64,
+    kendall_tau: f64,
+    overlap: f64,
+    rating_delta: f64,
+}
+
+/// A query's candidates to rank again in shadow
+pub struct ShadowRun {
+    pub query: String,
+    pub production: Ranker,
+    pub shadow: Ranker,
+    pub candidates: Vec<Book>,
+    pub intent: QueryIntent,
+    pub query_info: SemanticQueryInfo,
+    pub top_k: usize,
+    pub keyword_boost_cap: f32,
+    pub trusted_positions: usize,
+}
+
+impl ShadowRun {
+    /// Rank the candidates with the shadow ranker and compare them with the served `production` results
+    pub fn compare(self, production: &[Book]) -> ShadowComparison {
+        let shadow = self.shadow.rank(
+            self.candidates,
+            &self.intent,
+            &self.query_info,
+            self.top_k,
+            self.keyword_boost_cap,
+            self.trusted_positions,
+        );
+        ShadowComparison::between(
+            &self.query,
+            (self.production, production),
+            (self.shadow, &shadow),
+        )
+    }
+}
+
+/// Samples ranked queries into shadow runs and keeps their comparisons, newest last
+#[derive(Clone)]
+pub struct ShadowRanking {
+    config: ShadowConfig,
+    comparisons: Arc<RwLock<VecDeque<ShadowComparison>>>,
+    totals: Arc<Mutex<Totals>>,
+}
+
+impl Default for ShadowRanking {
+    fn default() -> Self {
+        Self::new(ShadowConfig::default())
+    }
+}
+
+impl ShadowRanking {
+    pub fn new(config: ShadowConfig) -> Self {
+        Self {
+            comparisons: Arc::new(RwLock::new(VecDeque::with_capacity(config.log_capacity))),
+            config,
+            totals: Arc::new(Mutex::new(Totals::default())),
+        }
+    }
+
+    pub fn config(&self) -> &ShadowConfig {
+        &self.config
+    }
+
+    /// The shadow ranker when a query ranked by `production` is sampled, drawn
+    /// from `rng`; never the production ranker itself
+    pub fn sample(&self, production: Ranker, rng: &mut fastrand::Rng) -> Option<Ranker> {
+        self.config
+            .ranker
+            .filter(|&shadow| shadow != production && rng.u8(0..100) < self.config.sample_percent)
+    }
+
+    /// Run `run` on the blocking pool and record how it compares to `production`
+    pub fn compare_in_background(&self, run: ShadowRun, production: &[Book]) {
+        let production = production.to_vec();
+        let shadow = self.clone();
+        tokio::task::spawn_blocking(move || shadow.record(run.compare(&production)));
+    }
+
+    /// Keep a comparison, evicting the oldest when the log is full
+    pub fn record(&self, comparison: ShadowComparison) {
+        info!(
+            "Shadow {} vs {} for '{}': tau {:.2}, overlap {:.2}, rating delta {:+.2}",
+            comparison.shadow.name(),
+            comparison.production.name(),
+            comparison.query,
+            comparison.kendall_tau,
+            comparison.overlap,
+            comparison.rating_delta
+        );
+
+        {
+            let mut totals = recover_lock(self.totals.lock(), "shadow totals");
+            totals.comparisons += 1;
+            totals.kendall_tau += comparison.kendall_tau as f64;
+            totals.overlap += comparison.overlap as f64;
+            totals.rating_delta += comparison.rating_delta as f64;
+        }
+
+        if self.config.log_capacity == 0 {
+            return;
+        }
+        let mut comparisons = recover_lock(self.comparisons.write(), "shadow log");
+        if comparisons.len() >= self.config.log_capacity {
+            comparisons.pop_front();
+        }
+        comparisons.push_back(comparison);
+    }
+
+    /// Most recent comparisons, newest first
+    pub fn recent(&self, limit: usize) -> Vec<ShadowComparison> {
+        recover_lock(self.comparisons.read(), "shadow log")
+            .iter()
+            .rev()
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    pub fn summary(&self) -> ShadowSummary {
+        let totals = recover_lock(self.totals.lock(), "shadow totals");
+        if totals.comparisons == 0 {
+            return ShadowSummary::default();
+        }
+        let mean = |total: f64| (total / totals.comparisons as f64) as f32;
+        ShadowSummary {
+            comparisons: totals.comparisons,
+            mean_kendall_tau: mean(totals.kendall_tau),
+            mean_overlap: mean(totals.overlap),
+            mean_rating_delta: mean(totals.rating_delta),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn books(ids_and_ratings: &[(&str, f32)]) -> Vec<Book> {
+        ids_and_ratings
+            .iter()
+            .map(|(id, rating)| {
+                serde_json::from_value(serde_json::json!({
+                    "id": id,
+                    "title": id,
+                    "author": "Someone",
+                    "rating": rating,
+                    "categories": ["Fantasy"],
+                }))
+                .expect("valid book")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_comparison_counts_reordered_pairs_and_dropped_books() {
+        let production = books(&[("a", 4.0), ("b", 4.0), ("c", 3.0), ("d", 3.0)]);
+        let shadow = books(&[("b", 4.0), ("a", 4.0), ("c", 3.0), ("e", 5.0)]);
+
+        let comparison = ShadowComparison::between(
+            "dragons",
+            (Ranker::Intent, &production),
+            (Ranker::Fused, &shadow),
+        );
+        assert_eq!(comparison.compared, 3);
+        assert_eq!(comparison.discordant_pairs, 1);
+        assert!((comparison.kendall_tau - 1.0 / 3.0).abs() < 1e-6);
+        assert_eq!(comparison.overlap, 0.75);
+        assert!((comparison.rating_delta - 0.5).abs() < 1e-6);
+        // Books left out or brought in come before the swapped pair
+        let moved: Vec<&str> = comparison
+            .largest_moves
+            .iter()
+            .map(|moved| moved.book_id.as_str())
+            .collect();
+        assert_eq!(moved, vec!["d", "e", "a", "b"]);
+    }
+
+    #[test]
+    fn test_only_other_rankers_are_sampled_and_comparisons_are_summarized() {
+        let shadow = ShadowRanking::new(ShadowConfig {
+            ranker: Some(Ranker::Fused),
+            sample_percent: 100,
+            log_capacity: 1,
+        });
+        let mut rng = fastrand::Rng::with_seed(7);
+        assert_eq!(shadow.sample(Ranker::Intent, &mut rng), Some(Ranker::Fused));
+        assert_eq!(shadow.sample(Ranker::Fused, &mut rng), None);
+        assert_eq!(
+            ShadowRanking::default().sample(Ranker::Intent, &mut rng),
+            None
+        );
+
+        let served = books(&[("a", 4.0), ("b", 4.0)]);
+        for query in ["first", "second"] {
+            shadow.record(ShadowComparison::between(
+                query,
+                (Ranker::Intent, &served),
+                (Ranker::Fused, &served),
+            ));
+        }
+        let recent = shadow.recent(10);
+        assert_eq!(recent.len(), 1);
+        assert_eq!(recent[0].query, "second");
+        assert_eq!(
+            shadow.summary(),
+            ShadowSummary {
+                comparisons: 2,
+                mean_kendall_tau: 1.0,
+                mean_overlap: 1.0,
+                mean_rating_delta: 0.0,
+            }
+        );
+    }
+}
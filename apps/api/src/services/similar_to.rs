@@ -0,0 +1,253 @@
+//! "More like these": books similar to several seed books at once
+//!
+//! The mean of the embeddings of seeds with little in common lands between
+//! them, close to none of them, so the seeds are grouped first: each joins the
+//! group whose centroid it's most similar to when that's close enough, and
+//! starts a group of its own otherwise. Each group's centroid is searched
+//! separately and the results are interleaved, larger groups contributing more
+//! books per round, so a list seeded with two space operas and a cookbook is
+//! mostly space opera with some cooking.
+//!
+//! Books to avoid steer the search the other way: a scaled mean of their
+//! embeddings is subtracted from each centroid, so "like these, but less like
+//! that one" moves away from what the reader disliked without leaving the
+//! seeds' neighbourhood.
+
+use crate::models::Book;
+use crate::services::personalization::cosine_similarity;
+use std::collections::{HashMap, HashSet};
+
+/// Most seed books, and most books to avoid, a request may list
+pub const MAX_SEED_BOOKS: usize = 10;
+
+/// Similarity to a group's centroid a seed needs to join the group
+const CLUSTER_SIMILARITY: f32 = 0.8;
+
+/// Most groups searched; further seeds join the group they're closest to
+const MAX_SEED_CLUSTERS: usize = 3;
+
+/// Weight of the mean of the avoided books' embeddings subtracted from each centroid
+const AVOID_WEIGHT: f32 = 0.5;
+
+/// Seeds similar enough to be searched together
+#[derive(Debug, Clone)]
+pub struct SeedCluster {
+    pub book_ids: Vec<String>,
+    /// Mean of the seeds' embeddings, normalized to unit length
+    pub centroid: Vec<f32>,
+}
+
+/// Books similar to a set of seeds
+#[derive(Debug, Clone, Default)]
+pub struct SimilarBooks {
+    pub books: Vec<Book>,
+    /// Ids of the seeds searched together, one list per centroid
+    pub clusters: Vec<Vec<String>>,
+    /// Seeds without a stored embedding
+    pub missing: Vec<String>,
+    /// Books to avoid without a stored embedding
+    pub missing_avoided: Vec<String>,
+}
+
+fn normalized(vector: &[f32]) -> Vec<f32> {
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm == 0.0 {
+        return vector.to_vec();
+    }
+    vector.iter().map(|x| x / norm).collect()
+}
+
+/// Group the seeds with embeddings in `vectors`, in the order given
+pub fn seed_clusters(seeds: &[String], vectors: &HashMap<String, Vec<f32>>) -> Vec<SeedCluster> {
+    // Each group keeps the sum of its seeds' unit embeddings
+    let mut sums: Vec<Vec<f32>> = Vec::new();
+    let mut clusters: Vec<SeedCluster> = Vec::new();
+    for seed in seeds {
+        let Some(vector) = vectors.get(seed) else {
+            continue;
+        };
+        if clusters
+            .first()
+            .is_some_and(|cluster| cluster.centroid.len() != vector.len())
+        {
+            continue;
+        }
+        let vector = normalized(vector);
+        let closest = clusters
+            .iter()
+            .enumerate()
+            .map(|(index, cluster)| (index, cosine_similarity(&vector, &cluster.centroid)))
+            .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+        let joins = match closest {
+            Some((index, similarity))
+                if similarity >= CLUSTER_SIMILARITY || clusters.len() >= MAX_SEED_CLUSTERS =>
+            {
+                Some(index)
+            }
+            _ => None,
+        };
+        match joins {
+            Some(index) => {
+                for (total, value) in sums[index].iter_mut().zip(&vector) {
+                    *total += value;
+                }
+                clusters[index].book_ids.push(seed.clone());
+                clusters[index].centroid = normalized(&sums[index]);
+            }
+            None => {
+                clusters.push(SeedCluster {
+                    book_ids: vec![seed.clone()],
+                    centroid: vector.clone(),
+                });
+                sums.push(vector);
+            }
+        }
+    }
+    clusters
+}
+
+/// Unit-length mean of the embeddings in `vectors` of `book_ids`; None when none has one
+pub fn mean_embedding(
+    book_ids: &[String],
+    vectors: &HashMap<String, Vec<f32>>,
+) -> Option<Vec<f32>> {
+    let mut sum: Vec<f32> = Vec::new();
+    for vector in book_ids.iter().filter_map(|id| vectors.get(id)) {
+        if sum.is_empty() {
+            sum = vec![0.0; vector.len()];
+        } else if sum.len() != vector.len() {
+            continue;
+        }
+        for (total, value) in sum.iter_mut().zip(normalized(vector)) {
+            *total += value;
+        }
+    }
+    if sum.iter().all(|x| *x == 0.0) {
+        return None;
+    }
+    Some(normalized(&sum))
+}
+
+/// Each cluster's centroid moved away from `avoided`, a unit-length embedding
+pub fn steer_away(clusters: &mut [SeedCluster], avoided: &[f32]) {
+    for cluster in clusters {
+        if cluster.centroid.len() != avoided.len() {
+            continue;
+        }
+        let steered: Vec<f32> = cluster
+            .centroid
+            .iter()
+            .zip(avoided)
+            .map(|(value, avoid)| value - AVOID_WEIGHT * avoid)
+            .collect();
+        // Avoiding the seeds themselves would leave nothing to search
+        if steered.iter().any(|x| *x != 0.0) {
+            cluster.centroid = normalized(&steered);
+        }
+    }
+}
+
+/// Up to `top_k` books from the clusters' results in turn, each round taking
+/// as many books from a cluster as it has seeds, without repeats, the seeds or
+/// the `avoided` books
+pub fn interleave(
+    clusters: &[SeedCluster],
+    results: Vec<Vec<Book>>,
+    avoided: &[String],
+    top_k: usize,
+) -> Vec<Book> {
+    let seeds: HashSet<&str> = clusters
+        .iter()
+        .flat_map(|cluster| cluster.book_ids.iter().map(String::as_str))
+        .chain(avoided.iter().map(String::as_str))
+        .collect();
+    let mut queues: Vec<_> = results.into_iter().map(|books| books.into_iter()).collect();
+    let mut seen = HashSet::new();
+    let mut books = Vec::with_capacity(top_k);
+    while books.len() < top_k {
+        let mut took_any = false;
+        for (cluster, queue) in clusters.iter().zip(queues.iter_mut()) {
+            let mut taken = 0;
+            while taken < cluster.book_ids.len() && books.len() < top_k {
+                let Some(book) = queue.next() else {
+                    break;
+                };
+                took_any = true;
+                let Some(id) = book.id.clone() else {
+                    continue;
+                };
+                if !seeds.contains(id.as_str()) && seen.insert(id) {
+                    books.push(book);
+                    taken += 1;
+                }
+            }
+        }
+        if !took_any {
+            break;
+        }
+    }
+    books
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book(id: &str) -> Book {
+        serde_json::from_value(serde_json::json!({ "id": id, "title": id, "categories": [] }))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_dissimilar_seeds_are_searched_apart() {
+        let seeds: Vec<String> = ["dune", "hyperion", "salt"]
+            .iter()
+            .map(|id| id.to_string())
+            .collect();
+        let vectors = HashMap::from([
+            ("dune".to_string(), vec![1.0, 0.1]),
+            ("hyperion".to_string(), vec![0.9, 0.2]),
+            ("salt".to_string(), vec![0.0, 1.0]),
+        ]);
+        let clusters = seed_clusters(&seeds, &vectors);
+        assert_eq!(clusters.len(), 2);
+        assert_eq!(clusters[0].book_ids, vec!["dune", "hyperion"]);
+        assert_eq!(clusters[1].book_ids, vec!["salt"]);
+
+        // Two books from the space opera group for each cookbook, no seeds or repeats
+        let results = vec![
+            vec![book("dune"), book("a"), book("b"), book("c"), book("d")],
+            vec![book("x"), book("a"), book("y")],
+        ];
+        let ids: Vec<String> = interleave(&clusters, results, &[], 6)
+            .into_iter()
+            .filter_map(|book| book.id)
+            .collect();
+        assert_eq!(ids, vec!["a", "b", "x", "c", "d", "y"]);
+    }
+
+    #[test]
+    fn test_avoided_books_steer_the_centroids_away() {
+        let vectors = HashMap::from([
+            ("dune".to_string(), vec![1.0, 1.0]),
+            ("twilight".to_string(), vec![0.0, 1.0]),
+        ]);
+        let mut clusters = seed_clusters(&["dune".to_string()], &vectors);
+        let avoided = mean_embedding(&["twilight".to_string()], &vectors).unwrap();
+        steer_away(&mut clusters, &avoided);
+        let centroid = &clusters[0].centroid;
+        assert!(centroid[0] > centroid[1]);
+        assert!((centroid[0].powi(2) + centroid[1].powi(2) - 1.0).abs() < 1e-5);
+
+        let ids: Vec<String> = interleave(
+            &clusters,
+            vec![vec![book("twilight"), book("a")]],
+            &["twilight".to_string()],
+            5,
+        )
+        .into_iter()
+        .filter_map(|book| book.id)
+        .collect();
+        assert_eq!(ids, vec!["a"]);
+    }
+}
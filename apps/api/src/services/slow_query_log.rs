@@ -0,0 +1,201 @@
+use crate::error::recover_lock;
+use crate::models::SearchPath;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tracing::warn;
+use utoipa::ToSchema;
+
+/// Default latency above which a recommendation query is considered slow
+pub const DEFAULT_SLOW_QUERY_THRESHOLD_MS: u64 = 2000;
+
+/// Default number of slow queries kept in memory
+pub const DEFAULT_SLOW_QUERY_LOG_CAPACITY: usize = 100;
+
+/// Time spent in each upstream call while answering a query
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct UpstreamTimings {
+    /// Time spent generating the query embedding
+    #[schema(example = 850)]
+    pub embedding_ms: Option<u64>,
+    /// Time spent in Pinecone vector queries
+    #[schema(example = 320)]
+    pub vector_search_ms: Option<u64>,
+    /// Time spent in Pinecone metadata queries
+    #[schema(example = 140)]
+    pub metadata_search_ms: Option<u64>,
+    /// Time spent in the keyword fallback search
+    #[schema(example = 0)]
+    pub fallback_search_ms: Option<u64>,
+}
+
+impl UpstreamTimings {
+    fn add(slot: &mut Option<u64>, elapsed: Duration) {
+        *slot = Some(slot.unwrap_or(0) + elapsed.as_millis() as u64);
+    }
+
+    pub fn add_embedding(&mut self, elapsed: Duration) {
+        Self::add(&mut self.embedding_ms, elapsed);
+    }
+
+    pub fn add_vector_search(&mut self, elapsed: Duration) {
+        Self::add(&mut self.vector_search_ms, elapsed);
+    }
+
+    pub fn add_metadata_search(&mut self, elapsed: Duration) {
+        Self::add(&mut self.metadata_search_ms, elapsed);
+    }
+
+    pub fn add_fallback_search(&mut self, elapsed: Duration) {
+        Self::add(&mut self.fallback_search_ms, elapsed);
+    }
+}
+
+/// A recommendation query whose end-to-end latency exceeded the threshold
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SlowQueryEntry {
+    /// The query as submitted
+    #[schema(example = "books like dune")]
+    pub query: String,
+    /// Number of results requested
+    #[schema(example = 50)]
+    pub top_k: usize,
+    /// Detected query intent
+    #[schema(example = "SimilarTo")]
+    pub intent: Option<String>,
+    /// Search strategy used for retrieval
+    #[schema(
+        example = "SearchStrategy { metadata_filter: None, semantic_weight: 0.8, hybrid_search: true }"
+    )]
+    pub strategy: Option<String>,
+    /// Whether the result cache answered the query
+    #[schema(example = false)]
+    pub cache_hit: bool,
+    /// Whether the keyword fallback path was used
+    #[schema(example = false)]
+    pub used_fallback: bool,
+    /// Which search answered the query
+    pub search_path: SearchPath,
+    /// Number of results returned, if the query succeeded
+    #[schema(example = 50)]
+    pub result_count: Option<usize>,
+    /// Error message, if the query failed
+    pub error: Option<String>,
+    /// End-to-end latency in milliseconds
+    #[schema(example = 4210)]
+    pub total_ms: u64,
+    /// Per-upstream latency breakdown
+    pub timings: UpstreamTimings,
+    /// When the query finished, in RFC3339 format
+    #[schema(example = "2024-01-15T10:30:00Z")]
+    pub recorded_at: String,
+}
+
+/// Bounded in-memory log of slow recommendation queries, newest last
+#[derive(Clone)]
+pub struct SlowQueryLog {
+    entries: Arc<RwLock<VecDeque<SlowQueryEntry>>>,
+    threshold: Duration,
+    capacity: usize,
+}
+
+impl SlowQueryLog {
+    pub fn new(threshold_ms: u64, capacity: usize) -> Self {
+        Self {
+            entries: Arc::new(RwLock::new(VecDeque::with_capacity(capacity))),
+            threshold: Duration::from_millis(threshold_ms),
+            capacity,
+        }
+    }
+
+    /// Latency above which queries are recorded
+    pub fn threshold(&self) -> Duration {
+        self.threshold
+    }
+
+    /// Record the entry if it exceeded the threshold, evicting the oldest entry when full
+    pub fn record(&self, entry: SlowQueryEntry) {
+        if self.capacity == 0 || Duration::from_millis(entry.total_ms) < self.threshold {
+            return;
+        }
+
+        warn!(
+            "Slow recommendation query ({} ms): '{}' - timings: {:?}",
+            entry.total_ms, entry.query, entry.timings
+        );
+
+        let mut entries = recover_lock(self.entries.write(), "slow query log");
+        if entries.len() >= self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+
+    /// Most recent slow queries, newest first
+    pub fn recent(&self, limit: usize) -> Vec<SlowQueryEntry> {
+        recover_lock(self.entries.read(), "slow query log")
+            .iter()
+            .rev()
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    pub fn clear(&self) {
+        recover_lock(self.entries.write(), "slow query log").clear();
+    }
+}
+
+impl Default for SlowQueryLog {
+    fn default() -> Self {
+        Self::new(
+            DEFAULT_SLOW_QUERY_THRESHOLD_MS,
+            DEFAULT_SLOW_QUERY_LOG_CAPACITY,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(query: &str, total_ms: u64) -> SlowQueryEntry {
+        SlowQueryEntry {
+            query: query.to_string(),
+            top_k: 10,
+            intent: None,
+            strategy: None,
+            cache_hit: false,
+            used_fallback: false,
+            search_path: SearchPath::Vector,
+            result_count: Some(10),
+            error: None,
+            total_ms,
+            timings: UpstreamTimings::default(),
+            recorded_at: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_only_slow_queries_are_recorded() {
+        let log = SlowQueryLog::new(100, 10);
+        log.record(entry("fast", 50));
+        log.record(entry("slow", 150));
+
+        let recent = log.recent(10);
+        assert_eq!(recent.len(), 1);
+        assert_eq!(recent[0].query, "slow");
+    }
+
+    #[test]
+    fn test_oldest_entries_are_evicted() {
+        let log = SlowQueryLog::new(0, 2);
+        log.record(entry("first", 1));
+        log.record(entry("second", 1));
+        log.record(entry("third", 1));
+
+        let queries: Vec<_> = log.recent(10).into_iter().map(|e| e.query).collect();
+        assert_eq!(queries, vec!["third", "second"]);
+    }
+}
@@ -0,0 +1,1066 @@
+//! Supabase (Postgres) client for per-user data such as reading shelves, ratings,
+//! query history, content preferences and recommendation snapshots, plus
+//! anonymous analytics events
+//!
+//! Backed by the database in `database_url` when configured. Without one, data
+//! is kept in process memory so the endpoints still work in development, but it
+//! is lost on restart.
+
+use crate::error::{recover_lock, ApiError, Result};
+use crate::services::personalization::ContentPreferences;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{postgres::PgPoolOptions, Executor, PgPool, Row};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tracing::info;
+use utoipa::ToSchema;
+
+/// Schema for per-user tables, applied on startup (safe to run repeatedly)
+const USER_DATA_MIGRATIONS: &[&str] = &[
+    include_str!("../../migrations/0002_user_shelves.sql"),
+    include_str!("../../migrations/0003_user_ratings.sql"),
+    include_str!("../../migrations/0004_query_history.sql"),
+    include_str!("../../migrations/0005_user_preferences.sql"),
+    include_str!("../../migrations/0006_analytics_events.sql"),
+    include_str!("../../migrations/0007_recommendation_snapshots.sql"),
+    include_str!("../../migrations/0008_safe_search.sql"),
+    include_str!("../../migrations/0012_query_history_owners.sql"),
+];
+
+/// Longest accepted user or book id
+const MAX_ID_LENGTH: usize = 128;
+
+/// Queries kept per user or session; older ones are dropped
+pub const MAX_HISTORY_ENTRIES: usize = 100;
+
+/// Analytics events kept when no database is configured; the oldest are dropped
+const IN_MEMORY_EVENT_CAPACITY: usize = 10_000;
+
+/// Accepted star ratings
+pub const RATING_RANGE: std::ops::RangeInclusive<u8> = 1..=5;
+
+/// Which shelf a book is on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ShelfStatus {
+    WantToRead,
+    Reading,
+    Read,
+}
+
+impl ShelfStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ShelfStatus::WantToRead => "want_to_read",
+            ShelfStatus::Reading => "reading",
+            ShelfStatus::Read => "read",
+        }
+    }
+}
+
+impl fmt::Display for ShelfStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ShelfStatus {
+    type Err = ApiError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "want_to_read" => Ok(ShelfStatus::WantToRead),
+            "reading" => Ok(ShelfStatus::Reading),
+            "read" => Ok(ShelfStatus::Read),
+            other => Err(ApiError::InvalidInput(format!(
+                "Unknown shelf '{}', expected want_to_read, reading or read",
+                other
+            ))),
+        }
+    }
+}
+
+/// A book on one of a user's shelves
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ShelfEntry {
+    #[schema(example = "3f1c2a9e-8d4b-4c47-9a51-0f6a7b2d1e55")]
+    pub user_id: String,
+    #[schema(example = "9780547928227")]
+    pub book_id: String,
+    pub status: ShelfStatus,
+    /// When the book was first shelved, in RFC3339 format
+    #[schema(example = "2024-01-15T10:30:00Z")]
+    pub added_at: String,
+    /// When the book last moved between shelves, in RFC3339 format
+    #[schema(example = "2024-02-01T18:05:00Z")]
+    pub updated_at: String,
+}
+
+/// What a user did with a recommended book
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum EventType {
+    /// The book was shown
+    Impression,
+    /// The book was opened
+    Click,
+    /// The book was put on a shelf
+    AddToShelf,
+}
+
+impl EventType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventType::Impression => "impression",
+            EventType::Click => "click",
+            EventType::AddToShelf => "add_to_shelf",
+        }
+    }
+}
+
+impl fmt::Display for EventType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for EventType {
+    type Err = ApiError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "impression" => Ok(EventType::Impression),
+            "click" => Ok(EventType::Click),
+            "add_to_shelf" => Ok(EventType::AddToShelf),
+            other => Err(ApiError::InvalidInput(format!(
+                "Unknown event type '{}', expected impression, click or add_to_shelf",
+                other
+            ))),
+        }
+    }
+}
+
+/// An anonymous analytics event
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct AnalyticsEvent {
+    pub event_type: EventType,
+    #[schema(example = "9780547928227")]
+    pub book_id: String,
+    /// Anonymous session the event came from
+    #[schema(example = "session-6b1f0c")]
+    pub session_id: Option<String>,
+    /// Query whose results the book was in
+    #[schema(example = "cozy fantasy with dragons")]
+    pub query: Option<String>,
+    /// Zero-based position of the book in those results
+    #[schema(example = 3)]
+    pub position: Option<u32>,
+    /// When the event happened, in RFC3339 format
+    #[schema(example = "2024-01-15T10:30:00Z")]
+    pub occurred_at: String,
+}
+
+/// A user's star rating of a book
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct UserRating {
+    #[schema(example = "3f1c2a9e-8d4b-4c47-9a51-0f6a7b2d1e55")]
+    pub user_id: String,
+    #[schema(example = "9780547928227")]
+    pub book_id: String,
+    /// Stars from 1 to 5
+    #[schema(example = 4, minimum = 1, maximum = 5)]
+    pub rating: u8,
+    /// When the book was first rated, in RFC3339 format
+    #[schema(example = "2024-01-15T10:30:00Z")]
+    pub created_at: String,
+    /// When the rating last changed, in RFC3339 format
+    #[schema(example = "2024-02-01T18:05:00Z")]
+    pub updated_at: String,
+}
+
+/// Whose recent queries a history holds
+///
+/// Users and anonymous sessions are stored under separately prefixed keys, so
+/// a session id can never name a user's history.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum HistoryOwner {
+    User(String),
+    Session(String),
+}
+
+impl HistoryOwner {
+    /// The user or session id
+    pub fn id(&self) -> &str {
+        match self {
+            HistoryOwner::User(id) | HistoryOwner::Session(id) => id,
+        }
+    }
+
+    /// Key the history is stored under
+    fn key(&self) -> String {
+        match self {
+            HistoryOwner::User(id) => format!("user:{}", id),
+            HistoryOwner::Session(id) => format!("session:{}", id),
+        }
+    }
+}
+
+/// A recommendation query made by a user or anonymous session
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct QueryHistoryEntry {
+    #[schema(example = "3f1c2a9e-8d4b-4c47-9a51-0f6a7b2d1e55")]
+    pub user_id: String,
+    #[schema(example = "cozy fantasy with dragons")]
+    pub query: String,
+    /// Semantic tags extracted from the query
+    #[schema(example = json!(["Fantasy", "Dragons"]))]
+    pub semantic_tags: Vec<String>,
+    /// Number of recommendations returned
+    #[schema(example = 20)]
+    pub result_count: usize,
+    /// When the query was made, in RFC3339 format
+    #[schema(example = "2024-01-15T10:30:00Z")]
+    pub created_at: String,
+}
+
+/// A user's saved content preferences
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct UserPreferences {
+    #[schema(example = "3f1c2a9e-8d4b-4c47-9a51-0f6a7b2d1e55")]
+    pub user_id: String,
+    #[serde(flatten)]
+    pub preferences: ContentPreferences,
+    /// When the preferences last changed, in RFC3339 format
+    #[schema(example = "2024-02-01T18:05:00Z")]
+    pub updated_at: String,
+}
+
+#[derive(Default)]
+struct MemoryStore {
+    shelves: HashMap<(String, String), ShelfEntry>,
+    ratings: HashMap<(String, String), UserRating>,
+    /// Newest first
+    history: HashMap<String, VecDeque<QueryHistoryEntry>>,
+    preferences: HashMap<String, UserPreferences>,
+    /// Oldest first
+    events: VecDeque<AnalyticsEvent>,
+    /// Newest last, keyed by user id and snapshot kind
+    snapshots: HashMap<(String, String), Vec<serde_json::Value>>,
+    recommended: HashMap<String, HashSet<String>>,
+}
+
+/// Client for per-user data stored in Supabase
+#[derive(Clone)]
+pub struct SupabaseClient {
+    pool: Option<PgPool>,
+    memory: Arc<RwLock<MemoryStore>>,
+}
+
+impl SupabaseClient {
+    /// Client that keeps user data in memory only
+    pub fn in_memory() -> Self {
+        Self {
+            pool: None,
+            memory: Arc::new(RwLock::new(MemoryStore::default())),
+        }
+    }
+
+    /// Connect to the database and make sure the user data tables exist
+    pub async fn connect(database_url: &str) -> Result<Self> {
+        let pool = PgPoolOptions::new()
+            .max_connections(10)
+            .acquire_timeout(Duration::from_secs(10))
+            .connect(database_url)
+            .await?;
+
+        for migration in USER_DATA_MIGRATIONS {
+            pool.execute(*migration).await?;
+        }
+        info!("Supabase user data tables are ready");
+
+        Ok(Self {
+            pool: Some(pool),
+            ..Self::in_memory()
+        })
+    }
+
+    /// The database pool, when one is configured
+    pub fn pool(&self) -> Option<&PgPool> {
+        self.pool.as_ref()
+    }
+
+    /// Whether user data is persisted to the database
+    pub fn is_persistent(&self) -> bool {
+        self.pool.is_some()
+    }
+
+    /// Books on a user's shelves, most recently updated first
+    pub async fn shelf(
+        &self,
+        user_id: &str,
+        status: Option<ShelfStatus>,
+    ) -> Result<Vec<ShelfEntry>> {
+        let Some(pool) = &self.pool else {
+            let memory = recover_lock(self.memory.read(), "shelves");
+            let mut entries: Vec<ShelfEntry> = memory
+                .shelves
+                .values()
+                .filter(|entry| entry.user_id == user_id)
+                .filter(|entry| status.is_none_or(|status| entry.status == status))
+                .cloned()
+                .collect();
+            entries.sort_by(|a, b| b.updated_at.cmp(&a.updated_at));
+            return Ok(entries);
+        };
+
+        let rows = sqlx::query(
+            "SELECT user_id, book_id, status, added_at, updated_at FROM user_shelves \
+             WHERE user_id = $1 AND ($2::text IS NULL OR status = $2) \
+             ORDER BY updated_at DESC, book_id",
+        )
+        .bind(user_id)
+        .bind(status.map(|s| s.as_str()))
+        .fetch_all(pool)
+        .await?;
+
+        rows.iter().map(shelf_entry_from_row).collect()
+    }
+
+    /// Every user's shelf entries, for offline jobs such as co-shelving
+    pub async fn all_shelf_entries(&self) -> Result<Vec<ShelfEntry>> {
+        let Some(pool) = &self.pool else {
+            return Ok(recover_lock(self.memory.read(), "shelves")
+                .shelves
+                .values()
+                .cloned()
+                .collect());
+        };
+
+        let rows = sqlx::query(
+            "SELECT user_id, book_id, status, added_at, updated_at FROM user_shelves \
+             ORDER BY user_id, added_at",
+        )
+        .fetch_all(pool)
+        .await?;
+
+        rows.iter().map(shelf_entry_from_row).collect()
+    }
+
+    /// The shelf entry for one book, if the user has shelved it
+    pub async fn shelf_entry(&self, user_id: &str, book_id: &str) -> Result<Option<ShelfEntry>> {
+        let Some(pool) = &self.pool else {
+            return Ok(recover_lock(self.memory.read(), "shelves")
+                .shelves
+                .get(&(user_id.to_string(), book_id.to_string()))
+                .cloned());
+        };
+
+        let row = sqlx::query(
+            "SELECT user_id, book_id, status, added_at, updated_at FROM user_shelves \
+             WHERE user_id = $1 AND book_id = $2",
+        )
+        .bind(user_id)
+        .bind(book_id)
+        .fetch_optional(pool)
+        .await?;
+
+        row.as_ref().map(shelf_entry_from_row).transpose()
+    }
+
+    /// Put a book on a shelf, moving it if it's already on another one
+    pub async fn set_shelf(
+        &self,
+        user_id: &str,
+        book_id: &str,
+        status: ShelfStatus,
+    ) -> Result<ShelfEntry> {
+        let Some(pool) = &self.pool else {
+            let now = Utc::now().to_rfc3339();
+            let mut memory = recover_lock(self.memory.write(), "shelves");
+            let entry = memory
+                .shelves
+                .entry((user_id.to_string(), book_id.to_string()))
+                .or_insert_with(|| ShelfEntry {
+                    user_id: user_id.to_string(),
+                    book_id: book_id.to_string(),
+                    status,
+                    added_at: now.clone(),
+                    updated_at: now.clone(),
+                });
+            entry.status = status;
+            entry.updated_at = now;
+            return Ok(entry.clone());
+        };
+
+        let row = sqlx::query(
+            "INSERT INTO user_shelves (user_id, book_id, status) VALUES ($1, $2, $3) \
+             ON CONFLICT (user_id, book_id) DO UPDATE SET status = EXCLUDED.status, updated_at = now() \
+             RETURNING user_id, book_id, status, added_at, updated_at",
+        )
+        .bind(user_id)
+        .bind(book_id)
+        .bind(status.as_str())
+        .fetch_one(pool)
+        .await?;
+
+        shelf_entry_from_row(&row)
+    }
+
+    /// Take a book off the user's shelves; returns whether it was shelved
+    pub async fn remove_from_shelf(&self, user_id: &str, book_id: &str) -> Result<bool> {
+        let Some(pool) = &self.pool else {
+            return Ok(recover_lock(self.memory.write(), "shelves")
+                .shelves
+                .remove(&(user_id.to_string(), book_id.to_string()))
+                .is_some());
+        };
+
+        let result = sqlx::query("DELETE FROM user_shelves WHERE user_id = $1 AND book_id = $2")
+            .bind(user_id)
+            .bind(book_id)
+            .execute(pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// A user's ratings, most recently updated first
+    pub async fn ratings(&self, user_id: &str, limit: Option<usize>) -> Result<Vec<UserRating>> {
+        let Some(pool) = &self.pool else {
+            let memory = recover_lock(self.memory.read(), "ratings");
+            let mut ratings: Vec<UserRating> = memory
+                .ratings
+                .values()
+                .filter(|rating| rating.user_id == user_id)
+                .cloned()
+                .collect();
+            ratings.sort_by(|a, b| b.updated_at.cmp(&a.updated_at));
+            ratings.truncate(limit.unwrap_or(usize::MAX));
+            return Ok(ratings);
+        };
+
+        let rows = sqlx::query(
+            "SELECT user_id, book_id, rating, created_at, updated_at FROM user_ratings \
+             WHERE user_id = $1 ORDER BY updated_at DESC, book_id LIMIT $2",
+        )
+        .bind(user_id)
+        .bind(limit.map(|limit| limit as i64))
+        .fetch_all(pool)
+        .await?;
+
+        rows.iter().map(user_rating_from_row).collect()
+    }
+
+    /// The user's rating of one book, if they rated it
+    pub async fn rating(&self, user_id: &str, book_id: &str) -> Result<Option<UserRating>> {
+        let Some(pool) = &self.pool else {
+            return Ok(recover_lock(self.memory.read(), "ratings")
+                .ratings
+                .get(&(user_id.to_string(), book_id.to_string()))
+                .cloned());
+        };
+
+        let row = sqlx::query(
+            "SELECT user_id, book_id, rating, created_at, updated_at FROM user_ratings \
+             WHERE user_id = $1 AND book_id = $2",
+        )
+        .bind(user_id)
+        .bind(book_id)
+        .fetch_optional(pool)
+        .await?;
+
+        row.as_ref().map(user_rating_from_row).transpose()
+    }
+
+    /// Rate a book, replacing any earlier rating by the same user
+    pub async fn set_rating(&self, user_id: &str, book_id: &str, rating: u8) -> Result<UserRating> {
+        if !RATING_RANGE.contains(&rating) {
+            return Err(ApiError::InvalidInput(format!(
+                "Rating must be between {} and {}, got {}",
+                RATING_RANGE.start(),
+                RATING_RANGE.end(),
+                rating
+            )));
+        }
+
+        let Some(pool) = &self.pool else {
+            let now = Utc::now().to_rfc3339();
+            let mut memory = recover_lock(self.memory.write(), "ratings");
+            let entry = memory
+                .ratings
+                .entry((user_id.to_string(), book_id.to_string()))
+                .or_insert_with(|| UserRating {
+                    user_id: user_id.to_string(),
+                    book_id: book_id.to_string(),
+                    rating,
+                    created_at: now.clone(),
+                    updated_at: now.clone(),
+                });
+            entry.rating = rating;
+            entry.updated_at = now;
+            return Ok(entry.clone());
+        };
+
+        let row = sqlx::query(
+            "INSERT INTO user_ratings (user_id, book_id, rating) VALUES ($1, $2, $3) \
+             ON CONFLICT (user_id, book_id) DO UPDATE SET rating = EXCLUDED.rating, updated_at = now() \
+             RETURNING user_id, book_id, rating, created_at, updated_at",
+        )
+        .bind(user_id)
+        .bind(book_id)
+        .bind(rating as i16)
+        .fetch_one(pool)
+        .await?;
+
+        user_rating_from_row(&row)
+    }
+
+    /// Delete the user's rating of a book; returns whether there was one
+    pub async fn remove_rating(&self, user_id: &str, book_id: &str) -> Result<bool> {
+        let Some(pool) = &self.pool else {
+            return Ok(recover_lock(self.memory.write(), "ratings")
+                .ratings
+                .remove(&(user_id.to_string(), book_id.to_string()))
+                .is_some());
+        };
+
+        let result = sqlx::query("DELETE FROM user_ratings WHERE user_id = $1 AND book_id = $2")
+            .bind(user_id)
+            .bind(book_id)
+            .execute(pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Recent queries of a user or session, newest first
+    pub async fn query_history(
+        &self,
+        owner: &HistoryOwner,
+        limit: usize,
+    ) -> Result<Vec<QueryHistoryEntry>> {
+        let Some(pool) = &self.pool else {
+            return Ok(recover_lock(self.memory.read(), "query history")
+                .history
+                .get(&owner.key())
+                .map(|entries| entries.iter().take(limit).cloned().collect())
+                .unwrap_or_default());
+        };
+
+        let rows = sqlx::query(
+            "SELECT user_id, query, semantic_tags, result_count, created_at FROM query_history \
+             WHERE user_id = $1 ORDER BY created_at DESC, id DESC LIMIT $2",
+        )
+        .bind(owner.key())
+        .bind(limit as i64)
+        .fetch_all(pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                Ok(QueryHistoryEntry {
+                    user_id: owner.id().to_string(),
+                    ..query_history_entry_from_row(row)?
+                })
+            })
+            .collect()
+    }
+
+    /// Record a query, keeping the newest [`MAX_HISTORY_ENTRIES`] per user or session
+    ///
+    /// A query identical to the previous one (ignoring case) isn't recorded again.
+    pub async fn record_query(
+        &self,
+        owner: &HistoryOwner,
+        query: &str,
+        semantic_tags: &[String],
+        result_count: usize,
+    ) -> Result<()> {
+        let query = query.trim();
+        let latest = self.query_history(owner, 1).await?;
+        if latest
+            .first()
+            .is_some_and(|entry| entry.query.eq_ignore_ascii_case(query))
+        {
+            return Ok(());
+        }
+
+        let Some(pool) = &self.pool else {
+            let mut memory = recover_lock(self.memory.write(), "query history");
+            let entries = memory.history.entry(owner.key()).or_default();
+            entries.push_front(QueryHistoryEntry {
+                user_id: owner.id().to_string(),
+                query: query.to_string(),
+                semantic_tags: semantic_tags.to_vec(),
+                result_count,
+                created_at: Utc::now().to_rfc3339(),
+            });
+            entries.truncate(MAX_HISTORY_ENTRIES);
+            return Ok(());
+        };
+
+        sqlx::query(
+            "INSERT INTO query_history (user_id, query, semantic_tags, result_count) \
+             VALUES ($1, $2, $3, $4)",
+        )
+        .bind(owner.key())
+        .bind(query)
+        .bind(semantic_tags)
+        .bind(result_count as i32)
+        .execute(pool)
+        .await?;
+
+        sqlx::query(
+            "DELETE FROM query_history WHERE user_id = $1 AND id NOT IN \
+             (SELECT id FROM query_history WHERE user_id = $1 ORDER BY created_at DESC, id DESC LIMIT $2)",
+        )
+        .bind(owner.key())
+        .bind(MAX_HISTORY_ENTRIES as i64)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// The user's content preferences, if they saved any
+    pub async fn preferences(&self, user_id: &str) -> Result<Option<UserPreferences>> {
+        let Some(pool) = &self.pool else {
+            return Ok(recover_lock(self.memory.read(), "preferences")
+                .preferences
+                .get(user_id)
+                .cloned());
+        };
+
+        let row = sqlx::query(
+            "SELECT user_id, preferred_languages, max_page_count, blocked_genres, \
+             blocked_content_warnings, safe_search, updated_at FROM user_preferences \
+             WHERE user_id = $1",
+        )
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+
+        row.as_ref().map(user_preferences_from_row).transpose()
+    }
+
+    /// Replace the user's content preferences
+    pub async fn set_preferences(
+        &self,
+        user_id: &str,
+        preferences: ContentPreferences,
+    ) -> Result<UserPreferences> {
+        let preferences = preferences.normalized()?;
+        // Stored in an INTEGER column
+        let max_page_count = preferences
+            .max_page_count
+            .map(i32::try_from)
+            .transpose()
+            .map_err(|_| {
+                ApiError::InvalidInput(format!("max_page_count can be at most {}", i32::MAX))
+            })?;
+
+        let Some(pool) = &self.pool else {
+            let saved = UserPreferences {
+                user_id: user_id.to_string(),
+                preferences,
+                updated_at: Utc::now().to_rfc3339(),
+            };
+            recover_lock(self.memory.write(), "preferences")
+                .preferences
+                .insert(user_id.to_string(), saved.clone());
+            return Ok(saved);
+        };
+
+        let row = sqlx::query(
+            "INSERT INTO user_preferences (user_id, preferred_languages, max_page_count, \
+             blocked_genres, blocked_content_warnings, safe_search) VALUES ($1, $2, $3, $4, $5, $6) \
+             ON CONFLICT (user_id) DO UPDATE SET preferred_languages = EXCLUDED.preferred_languages, \
+             max_page_count = EXCLUDED.max_page_count, blocked_genres = EXCLUDED.blocked_genres, \
+             blocked_content_warnings = EXCLUDED.blocked_content_warnings, \
+             safe_search = EXCLUDED.safe_search, updated_at = now() \
+             RETURNING user_id, preferred_languages, max_page_count, blocked_genres, \
+             blocked_content_warnings, safe_search, updated_at",
+        )
+        .bind(user_id)
+        .bind(&preferences.preferred_languages)
+        .bind(max_page_count)
+        .bind(&preferences.blocked_genres)
+        .bind(&preferences.blocked_content_warnings)
+        .bind(preferences.safe_search)
+        .fetch_one(pool)
+        .await?;
+
+        user_preferences_from_row(&row)
+    }
+
+    /// Delete the user's content preferences; returns whether there were any
+    pub async fn remove_preferences(&self, user_id: &str) -> Result<bool> {
+        let Some(pool) = &self.pool else {
+            return Ok(recover_lock(self.memory.write(), "preferences")
+                .preferences
+                .remove(user_id)
+                .is_some());
+        };
+
+        let result = sqlx::query("DELETE FROM user_preferences WHERE user_id = $1")
+            .bind(user_id)
+            .execute(pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Store a batch of analytics events
+    pub async fn insert_events(&self, events: &[AnalyticsEvent]) -> Result<()> {
+        if events.is_empty() {
+            return Ok(());
+        }
+
+        let Some(pool) = &self.pool else {
+            let mut memory = recover_lock(self.memory.write(), "analytics events");
+            memory.events.extend(events.iter().cloned());
+            let excess = memory.events.len().saturating_sub(IN_MEMORY_EVENT_CAPACITY);
+            memory.events.drain(..excess);
+            return Ok(());
+        };
+
+        let mut occurred_at = Vec::with_capacity(events.len());
+        for event in events {
+            occurred_at.push(
+                DateTime::parse_from_rfc3339(&event.occurred_at)
+                    .map_err(|e| {
+                        ApiError::InvalidInput(format!(
+                            "Invalid event timestamp '{}': {}",
+                            event.occurred_at, e
+                        ))
+                    })?
+                    .with_timezone(&Utc),
+            );
+        }
+
+        sqlx::query(
+            "INSERT INTO analytics_events (event_type, book_id, session_id, query, position, occurred_at) \
+             SELECT * FROM UNNEST($1::text[], $2::text[], $3::text[], $4::text[], $5::int[], $6::timestamptz[])",
+        )
+        .bind(events.iter().map(|e| e.event_type.as_str()).collect::<Vec<_>>())
+        .bind(events.iter().map(|e| e.book_id.clone()).collect::<Vec<_>>())
+        .bind(events.iter().map(|e| e.session_id.clone()).collect::<Vec<_>>())
+        .bind(events.iter().map(|e| e.query.clone()).collect::<Vec<_>>())
+        .bind(
+            events
+                .iter()
+                .map(|e| e.position.map(|p| p as i32))
+                .collect::<Vec<_>>(),
+        )
+        .bind(occurred_at)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Number of events of one type per book since the given time
+    pub async fn event_counts(
+        &self,
+        event_type: EventType,
+        since: DateTime<Utc>,
+    ) -> Result<HashMap<String, u64>> {
+        let Some(pool) = &self.pool else {
+            let mut counts = HashMap::new();
+            for event in &recover_lock(self.memory.read(), "analytics events").events {
+                let recent = DateTime::parse_from_rfc3339(&event.occurred_at)
+                    .is_ok_and(|occurred| occurred >= since);
+                if event.event_type == event_type && recent {
+                    *counts.entry(event.book_id.clone()).or_default() += 1;
+                }
+            }
+            return Ok(counts);
+        };
+
+        let rows = sqlx::query(
+            "SELECT book_id, COUNT(*) AS events FROM analytics_events \
+             WHERE event_type = $1 AND occurred_at >= $2 GROUP BY book_id",
+        )
+        .bind(event_type.as_str())
+        .bind(since)
+        .fetch_all(pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                Ok((
+                    row.try_get("book_id")?,
+                    row.try_get::<i64, _>("events")?.max(0) as u64,
+                ))
+            })
+            .collect()
+    }
+
+    /// Most frequent queries in events since the given time, most frequent first
+    ///
+    /// A query counts once per session that sent it; events without a session
+    /// count individually.
+    pub async fn top_queries(
+        &self,
+        since: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<Vec<(String, u64)>> {
+        let Some(pool) = &self.pool else {
+            let mut sessions: HashMap<&str, HashSet<String>> = HashMap::new();
+            let memory = recover_lock(self.memory.read(), "analytics events");
+            for (index, event) in memory.events.iter().enumerate() {
+                let recent = DateTime::parse_from_rfc3339(&event.occurred_at)
+                    .is_ok_and(|occurred| occurred >= since);
+                let Some(query) = event.query.as_deref().filter(|_| recent) else {
+                    continue;
+                };
+                sessions.entry(query).or_default().insert(
+                    event
+                        .session_id
+                        .clone()
+                        .unwrap_or_else(|| index.to_string()),
+                );
+            }
+            let mut counts: Vec<(String, u64)> = sessions
+                .into_iter()
+                .map(|(query, sessions)| (query.to_string(), sessions.len() as u64))
+                .collect();
+            counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+            counts.truncate(limit);
+            return Ok(counts);
+        };
+
+        let rows = sqlx::query(
+            "SELECT query, COUNT(DISTINCT COALESCE(session_id, id::text)) AS searches \
+             FROM analytics_events WHERE query IS NOT NULL AND occurred_at >= $1 \
+             GROUP BY query ORDER BY searches DESC, query LIMIT $2",
+        )
+        .bind(since)
+        .bind(limit as i64)
+        .fetch_all(pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                Ok((
+                    row.try_get("query")?,
+                    row.try_get::<i64, _>("searches")?.max(0) as u64,
+                ))
+            })
+            .collect()
+    }
+
+    /// Books already recommended to the user in a stored snapshot
+    pub async fn recommended_book_ids(&self, user_id: &str) -> Result<HashSet<String>> {
+        let Some(pool) = &self.pool else {
+            return Ok(recover_lock(self.memory.read(), "recommendation snapshots")
+                .recommended
+                .get(user_id)
+                .cloned()
+                .unwrap_or_default());
+        };
+
+        let rows = sqlx::query("SELECT book_id FROM recommended_books WHERE user_id = $1")
+            .bind(user_id)
+            .fetch_all(pool)
+            .await?;
+
+        rows.iter().map(|row| Ok(row.try_get("book_id")?)).collect()
+    }
+
+    /// Store a snapshot of recommendations sent to a user and remember its books
+    /// so they aren't recommended again
+    pub async fn save_snapshot(
+        &self,
+        user_id: &str,
+        kind: &str,
+        payload: &serde_json::Value,
+        book_ids: &[String],
+    ) -> Result<()> {
+        let Some(pool) = &self.pool else {
+            let mut memory = recover_lock(self.memory.write(), "recommendation snapshots");
+            memory
+                .snapshots
+                .entry((user_id.to_string(), kind.to_string()))
+                .or_default()
+                .push(payload.clone());
+            memory
+                .recommended
+                .entry(user_id.to_string())
+                .or_default()
+                .extend(book_ids.iter().cloned());
+            return Ok(());
+        };
+
+        let mut tx = pool.begin().await?;
+        sqlx::query(
+            "INSERT INTO recommendation_snapshots (user_id, kind, payload) VALUES ($1, $2, $3)",
+        )
+        .bind(user_id)
+        .bind(kind)
+        .bind(payload)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(
+            "INSERT INTO recommended_books (user_id, book_id) \
+             SELECT $1, book_id FROM UNNEST($2::text[]) AS book_id \
+             ON CONFLICT (user_id, book_id) DO NOTHING",
+        )
+        .bind(user_id)
+        .bind(book_ids)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// The most recent snapshot of one kind stored for a user
+    pub async fn latest_snapshot(
+        &self,
+        user_id: &str,
+        kind: &str,
+    ) -> Result<Option<serde_json::Value>> {
+        let Some(pool) = &self.pool else {
+            return Ok(recover_lock(self.memory.read(), "recommendation snapshots")
+                .snapshots
+                .get(&(user_id.to_string(), kind.to_string()))
+                .and_then(|snapshots| snapshots.last().cloned()));
+        };
+
+        let row = sqlx::query(
+            "SELECT payload FROM recommendation_snapshots WHERE user_id = $1 AND kind = $2 \
+             ORDER BY created_at DESC, id DESC LIMIT 1",
+        )
+        .bind(user_id)
+        .bind(kind)
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(row.map(|row| row.try_get("payload")).transpose()?)
+    }
+}
+
+impl Default for SupabaseClient {
+    fn default() -> Self {
+        Self::in_memory()
+    }
+}
+
+/// Reject empty, overlong or oddly formed user and book ids before they reach the database
+pub fn validate_id(kind: &str, id: &str) -> Result<()> {
+    let valid = !id.is_empty()
+        && id.len() <= MAX_ID_LENGTH
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+
+    if valid {
+        Ok(())
+    } else {
+        Err(ApiError::InvalidInput(format!(
+            "Invalid {} '{}': expected 1-{} letters, digits, '-', '_' or '.'",
+            kind, id, MAX_ID_LENGTH
+        )))
+    }
+}
+
+fn shelf_entry_from_row(row: &sqlx::postgres::PgRow) -> Result<ShelfEntry> {
+    Ok(ShelfEntry {
+        user_id: row.try_get("user_id")?,
+        book_id: row.try_get("book_id")?,
+        status: row.try_get::<String, _>("status")?.parse()?,
+        added_at: row.try_get::<DateTime<Utc>, _>("added_at")?.to_rfc3339(),
+        updated_at: row.try_get::<DateTime<Utc>, _>("updated_at")?.to_rfc3339(),
+    })
+}
+
+fn user_rating_from_row(row: &sqlx::postgres::PgRow) -> Result<UserRating> {
+    Ok(UserRating {
+        user_id: row.try_get("user_id")?,
+        book_id: row.try_get("book_id")?,
+        rating: row.try_get::<i16, _>("rating")? as u8,
+        created_at: row.try_get::<DateTime<Utc>, _>("created_at")?.to_rfc3339(),
+        updated_at: row.try_get::<DateTime<Utc>, _>("updated_at")?.to_rfc3339(),
+    })
+}
+
+fn query_history_entry_from_row(row: &sqlx::postgres::PgRow) -> Result<QueryHistoryEntry> {
+    Ok(QueryHistoryEntry {
+        user_id: row.try_get("user_id")?,
+        query: row.try_get("query")?,
+        semantic_tags: row.try_get("semantic_tags")?,
+        result_count: row.try_get::<i32, _>("result_count")?.max(0) as usize,
+        created_at: row.try_get::<DateTime<Utc>, _>("created_at")?.to_rfc3339(),
+    })
+}
+
+fn user_preferences_from_row(row: &sqlx::postgres::PgRow) -> Result<UserPreferences> {
+    Ok(UserPreferences {
+        user_id: row.try_get("user_id")?,
+        preferences: ContentPreferences {
+            preferred_languages: row.try_get("preferred_languages")?,
+            max_page_count: row
+                .try_get::<Option<i32>, _>("max_page_count")?
+                .map(|pages| pages.max(1) as u32),
+            blocked_genres: row.try_get("blocked_genres")?,
+            blocked_content_warnings: row.try_get("blocked_content_warnings")?,
+            safe_search: row.try_get("safe_search")?,
+        },
+        updated_at: row.try_get::<DateTime<Utc>, _>("updated_at")?.to_rfc3339(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_web::test]
+    async fn test_in_memory_shelves_move_books_between_statuses() {
+        let client = SupabaseClient::in_memory();
+        client
+            .set_shelf("alice", "dune", ShelfStatus::WantToRead)
+            .await
+            .unwrap();
+        client
+            .set_shelf("alice", "emma", ShelfStatus::Read)
+            .await
+            .unwrap();
+        let moved = client
+            .set_shelf("alice", "dune", ShelfStatus::Reading)
+            .await
+            .unwrap();
+        assert_eq!(moved.status, ShelfStatus::Reading);
+
+        let reading = client
+            .shelf("alice", Some(ShelfStatus::Reading))
+            .await
+            .unwrap();
+        assert_eq!(reading.len(), 1);
+        assert!(client.shelf("bob", None).await.unwrap().is_empty());
+
+        assert!(client.remove_from_shelf("alice", "emma").await.unwrap());
+        assert!(!client.remove_from_shelf("alice", "emma").await.unwrap());
+        assert_eq!(client.shelf("alice", None).await.unwrap().len(), 1);
+    }
+
+    #[actix_web::test]
+    async fn test_user_and_session_histories_with_the_same_id_are_separate() {
+        let client = SupabaseClient::in_memory();
+        let user = HistoryOwner::User("alice".to_string());
+        let session = HistoryOwner::Session("alice".to_string());
+        client
+            .record_query(&user, "cozy mysteries", &[], 10)
+            .await
+            .unwrap();
+
+        assert!(client.query_history(&session, 10).await.unwrap().is_empty());
+        let history = client.query_history(&user, 10).await.unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].user_id, "alice");
+    }
+}
@@ -0,0 +1,159 @@
+//! Curated synonyms for the genre and theme keyword tables
+//!
+//! The keyword tables queries are matched against are compiled in. A synonyms
+//! file, in TOML like the rest of the configuration, adds to them so coverage
+//! gaps such as "solarpunk" or "dark academia" can be fixed without a deploy:
+//!
+//! ```toml
+//! replace = []
+//!
+//! [genres]
+//! sci-fi = ["solarpunk", "climate fiction"]
+//!
+//! [themes]
+//! "dark academia" = ["secret society", "boarding school"]
+//! ```
+//!
+//! Keywords are added to those of the genre or theme with the same key, and a
+//! new key adds a genre or theme matched by its own name as well. Keys listed
+//! under `replace` drop their compiled-in keywords for the file's. The file is
+//! read at startup and again on `POST /api/admin/synonyms/reload`; query
+//! expansion embeds its vocabulary once, so it learns new keywords on restart.
+
+use crate::error::{ApiError, Result};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// A keyword table: keywords by genre or theme
+pub type KeywordTable = HashMap<&'static str, Vec<&'static str>>;
+
+/// Genre and theme keywords read from a synonyms file
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SynonymDictionary {
+    #[serde(default)]
+    pub genres: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    pub themes: BTreeMap<String, Vec<String>>,
+    /// Genres and themes whose compiled-in keywords are dropped for the file's
+    #[serde(default)]
+    pub replace: Vec<String>,
+}
+
+/// Curated keywords, allocated once each for the life of the process like the
+/// compiled-in ones, however often the file is reloaded
+static INTERNED: Mutex<Option<HashSet<&'static str>>> = Mutex::new(None);
+
+fn intern(word: &str) -> &'static str {
+    let mut interned = INTERNED
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+    let interned = interned.get_or_insert_with(HashSet::new);
+    match interned.get(word) {
+        Some(&word) => word,
+        None => {
+            let word: &'static str = Box::leak(word.to_string().into_boxed_str());
+            interned.insert(word);
+            word
+        }
+    }
+}
+
+/// Lowercased and trimmed, or None if blank
+fn normalize(word: &str) -> Option<String> {
+    let word = word.trim().to_lowercase();
+    (!word.is_empty()).then_some(word)
+}
+
+impl SynonymDictionary {
+    /// Read a synonyms file
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path).map_err(|e| {
+            ApiError::InternalError(format!("Failed to read synonyms {}: {}", path.display(), e))
+        })?;
+        Self::parse(&text)
+    }
+
+    /// Parse a synonyms dictionary from TOML
+    pub fn parse(text: &str) -> Result<Self> {
+        toml::from_str(text).map_err(|e| ApiError::InvalidInput(format!("Invalid synonyms: {}", e)))
+    }
+
+    /// `base` with the file's `entries` merged in
+    fn merge(&self, base: &KeywordTable, entries: &BTreeMap<String, Vec<String>>) -> KeywordTable {
+        let replaced: HashSet<String> = self
+            .replace
+            .iter()
+            .filter_map(|key| normalize(key))
+            .collect();
+        let mut table = base.clone();
+        for (key, keywords) in entries {
+            let Some(key) = normalize(key) else {
+                continue;
+            };
+            let key = intern(&key);
+            let existing = table.entry(key).or_insert_with(|| vec![key]);
+            if replaced.contains(key) {
+                existing.clear();
+            }
+            for keyword in keywords.iter().filter_map(|keyword| normalize(keyword)) {
+                let keyword = intern(&keyword);
+                if !existing.contains(&keyword) {
+                    existing.push(keyword);
+                }
+            }
+            if existing.is_empty() {
+                existing.push(key);
+            }
+        }
+        table
+    }
+
+    /// The genre keyword table `base` with the file's genres merged in
+    pub fn merge_genres(&self, base: &KeywordTable) -> KeywordTable {
+        self.merge(base, &self.genres)
+    }
+
+    /// The theme keyword table `base` with the file's themes merged in
+    pub fn merge_themes(&self, base: &KeywordTable) -> KeywordTable {
+        self.merge(base, &self.themes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_synonyms_add_to_and_replace_compiled_in_keywords() {
+        let dictionary = SynonymDictionary::parse(
+            r#"
+            replace = ["romance"]
+
+            [genres]
+            Sci-Fi = ["Solarpunk", " "]
+            romance = ["romantasy"]
+
+            [themes]
+            "dark academia" = ["secret society"]
+            "#,
+        )
+        .unwrap();
+        let base: KeywordTable = HashMap::from([
+            ("sci-fi", vec!["sci-fi", "space opera"]),
+            ("romance", vec!["romance", "love story"]),
+        ]);
+
+        let genres = dictionary.merge_genres(&base);
+        assert_eq!(genres["sci-fi"], ["sci-fi", "space opera", "solarpunk"]);
+        assert_eq!(genres["romance"], ["romantasy"]);
+        let themes = dictionary.merge_themes(&HashMap::new());
+        assert_eq!(themes["dark academia"], ["dark academia", "secret society"]);
+
+        assert!(SynonymDictionary::parse("[genre]").is_err());
+        assert!(SynonymDictionary::parse("genres = [\"sci-fi\"]").is_err());
+        assert!(SynonymDictionary::parse("").unwrap().genres.is_empty());
+    }
+}
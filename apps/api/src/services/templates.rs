@@ -1,10 +1,23 @@
+use crate::cache::CacheWeight;
+use crate::ingest::awards::award_in_query;
+use crate::ingest::moods::mood_words;
+use crate::services::synonyms::{KeywordTable, SynonymDictionary};
+use aho_corasick::AhoCorasick;
 use lazy_static::lazy_static;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, PoisonError, RwLock};
+use utoipa::ToSchema;
+
+/// Most pages of a book that counts as a short read
+pub const SHORT_READ_MAX_PAGES: i32 = 300;
+
+/// Fewest pages of a book that counts as a long read
+pub const LONG_READ_MIN_PAGES: i32 = 500;
 
 /// Query pattern types for template matching
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
 pub enum QueryPattern {
     Author,
     Genre,
@@ -19,6 +32,7 @@ pub enum QueryPattern {
     Setting,
     Pace,
     Perspective,
+    #[default]
     General,
 }
 
@@ -34,16 +48,30 @@ pub struct EnhancedQuery {
 }
 
 /// Filters to apply during search
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(default)]
 pub struct QueryFilters {
+    #[schema(example = "Agatha Christie")]
     pub author: Option<String>,
+    /// Genre names, with the spellings the genre is also known by
+    #[schema(example = json!(["mystery", "detective", "crime"]))]
     pub genres: Vec<String>,
+    #[schema(example = json!(["friendship"]))]
     pub themes: Vec<String>,
+    #[schema(example = 4.0, minimum = 0.0, maximum = 5.0)]
     pub min_rating: Option<f32>,
+    pub min_pages: Option<i32>,
+    #[schema(example = 300)]
     pub max_pages: Option<i32>,
+    #[schema(example = 1990)]
     pub min_year: Option<i32>,
+    #[schema(example = 1999)]
     pub max_year: Option<i32>,
+    /// "children" or "young adult"
+    #[schema(example = "young adult")]
     pub audience: Option<String>,
+    /// Places the books should be set in
+    #[schema(example = json!(["scotland"]))]
     pub settings: Vec<String>,
 }
 
@@ -67,6 +95,25 @@ impl Default for SearchHints {
     }
 }
 
+impl CacheWeight for EnhancedQuery {
+    fn heap_size(&self) -> usize {
+        self.original_query.heap_size()
+            + self.extracted_terms.heap_size()
+            + self.expanded_terms.heap_size()
+            + self.filters.heap_size()
+    }
+}
+
+impl CacheWeight for QueryFilters {
+    fn heap_size(&self) -> usize {
+        self.author.heap_size()
+            + self.genres.heap_size()
+            + self.themes.heap_size()
+            + self.audience.heap_size()
+            + self.settings.heap_size()
+    }
+}
+
 lazy_static! {
     /// Common genre synonyms and expansions
     pub static ref GENRE_EXPANSIONS: HashMap<&'static str, Vec<&'static str>> = {
@@ -118,10 +165,14 @@ lazy_static! {
         Regex::new(r"(?i)genre:?\s*([a-zA-Z\s-]+?)(?:\s|$)").unwrap(),
     ];
 
-    /// Mood/atmosphere patterns
+    /// Mood/atmosphere patterns; mood words come from the vocabulary books are tagged with
     pub static ref MOOD_PATTERNS: Vec<Regex> = vec![
         Regex::new(r"(?i)(?:feel|feeling|mood|atmosphere|vibe|tone)\s+(?:like\s+)?([a-zA-Z\s-]+)").unwrap(),
-        Regex::new(r"(?i)\b(cozy|dark|light|uplifting|depressing|happy|sad|emotional|funny|humorous|serious|intense|relaxing|heartwarming|bittersweet|melancholic|optimistic|pessimistic|suspenseful|tense|peaceful|violent|gritty|whimsical|playful)\b").unwrap(),
+        Regex::new(&format!(
+            r"(?i)\b({})\b",
+            mood_words().map(regex::escape).collect::<Vec<_>>().join("|")
+        ))
+        .unwrap(),
     ];
 
     /// Similar-to patterns
@@ -266,6 +317,11 @@ lazy_static! {
         m
     };
 
+    /// The keyword tables in use, replaced when synonyms are loaded
+    static ref KEYWORD_TABLES: RwLock<Arc<KeywordTables>> = RwLock::new(Arc::new(
+        KeywordTables::new(GENRE_EXPANSIONS.clone(), THEME_KEYWORDS.clone()),
+    ));
+
     /// Historical periods and eras
     pub static ref HISTORICAL_PERIODS: HashMap<&'static str, (i32, i32)> = {
         let mut m = HashMap::new();
@@ -292,10 +348,148 @@ lazy_static! {
     ];
 }
 
+/// Words that make a query mean something else when they move, as in
+/// "fantasy without romance" and "romance without fantasy", or that join
+/// parts whose modifiers could trade places, as in "dark fantasy with light
+/// romance" and "light fantasy with dark romance", or that tie a name to one
+/// part, as in "books by Gaiman like Pratchett" and "books like Gaiman by Pratchett"
+const ORDER_SENSITIVE_WORDS: &[&str] = &[
+    "not", "no", "without", "except", "than", "like", "unlike", "similar", "before", "after", "vs",
+    "versus", "with", "and", "or", "but", "plus", "meets", "by", "from",
+];
+
+/// Aho-Corasick automaton over the keyword lists of a genre or theme table
+///
+/// Finds every listed keyword in one scan of the text instead of a substring
+/// search per keyword.
+struct KeywordMatcher {
+    automaton: AhoCorasick,
+    /// Table key each pattern belongs to, by pattern index
+    keys: Vec<&'static str>,
+}
+
+impl KeywordMatcher {
+    fn new(table: &HashMap<&'static str, Vec<&'static str>>) -> Self {
+        // Patterns in key order, so keys sharing a keyword resolve the same way every run
+        let mut entries: Vec<_> = table.iter().collect();
+        entries.sort_unstable_by_key(|&(&key, _)| key);
+        let (keys, patterns): (Vec<_>, Vec<_>) = entries
+            .into_iter()
+            .flat_map(|(&key, keywords)| keywords.iter().map(move |&keyword| (key, keyword)))
+            .unzip();
+        let automaton = AhoCorasick::new(patterns).expect("keyword tables are valid patterns");
+        Self { automaton, keys }
+    }
+
+    /// Keys with a keyword in `text`, ordered by where their first keyword ends
+    fn keys_in(&self, text: &str) -> Vec<&'static str> {
+        let mut found = Vec::new();
+        for hit in self.automaton.find_overlapping_iter(text) {
+            let key = self.keys[hit.pattern().as_usize()];
+            if !found.contains(&key) {
+                found.push(key);
+            }
+        }
+        found
+    }
+}
+
+/// Genre and theme keywords: the compiled-in tables with any curated synonyms merged in
+struct KeywordTables {
+    genres: KeywordTable,
+    themes: KeywordTable,
+    /// Every genre keyword in one automaton, for a single pass over the query
+    genre_matcher: KeywordMatcher,
+    /// Every theme keyword in one automaton
+    theme_matcher: KeywordMatcher,
+    /// Words of every keyword
+    words: HashSet<&'static str>,
+}
+
+impl KeywordTables {
+    fn new(genres: KeywordTable, themes: KeywordTable) -> Self {
+        let words = genres
+            .values()
+            .chain(themes.values())
+            .flatten()
+            .flat_map(|keyword| keyword.split(|c: char| !c.is_alphanumeric()))
+            .filter(|word| !word.is_empty())
+            .collect();
+        Self {
+            genre_matcher: KeywordMatcher::new(&genres),
+            theme_matcher: KeywordMatcher::new(&themes),
+            genres,
+            themes,
+            words,
+        }
+    }
+}
+
+/// The keyword tables in use
+fn keyword_tables() -> Arc<KeywordTables> {
+    KEYWORD_TABLES
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+}
+
+/// Match queries against the compiled-in keyword tables with `synonyms` merged in
+///
+/// Replaces the synonyms loaded before, so a reloaded file takes effect in full.
+pub fn set_synonyms(synonyms: &SynonymDictionary) {
+    let tables = KeywordTables::new(
+        synonyms.merge_genres(&GENRE_EXPANSIONS),
+        synonyms.merge_themes(&THEME_KEYWORDS),
+    );
+    *KEYWORD_TABLES
+        .write()
+        .unwrap_or_else(PoisonError::into_inner) = Arc::new(tables);
+}
+
+/// The genre a term names, as its key in the genre table, e.g. "sci-fi" for "science fiction"
+pub fn genre_of(term: &str) -> Option<&'static str> {
+    genres_in(term).first().copied()
+}
+
+/// The genres a text names, as keys in the genre table, ordered by where they're named
+pub fn genres_in(text: &str) -> Vec<&'static str> {
+    keyword_tables().genre_matcher.keys_in(&text.to_lowercase())
+}
+
+/// Keywords of a genre of the genre table, e.g. "ya" and "teen" for "young adult"
+pub fn genre_keywords(genre: &str) -> Vec<&'static str> {
+    keyword_tables()
+        .genres
+        .get(genre)
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Every genre and theme keyword, sorted and without repeats
+pub fn keyword_phrases() -> Vec<&'static str> {
+    let tables = keyword_tables();
+    let mut phrases: Vec<&'static str> = tables
+        .genres
+        .values()
+        .chain(tables.themes.values())
+        .flatten()
+        .copied()
+        .collect();
+    phrases.sort_unstable();
+    phrases.dedup();
+    phrases
+}
+
+/// Whether `word` is a word of a genre or theme keyword
+pub fn is_keyword_word(word: &str) -> bool {
+    keyword_tables().words.contains(word)
+}
+
 impl EnhancedQuery {
     /// Create a new enhanced query from user input
     pub fn from_query(query: &str) -> Self {
         let query_lower = query.to_lowercase();
+        let tables = keyword_tables();
         let mut pattern = QueryPattern::General;
         let mut extracted_terms = Vec::new();
         let mut expanded_terms = Vec::new();
@@ -325,20 +519,30 @@ impl EnhancedQuery {
                 if let Some(captures) = pattern_regex.captures(&query_lower) {
                     if let Some(genre_match) = captures.get(1) {
                         let genre = genre_match.as_str().trim();
-                        for (base_genre, expansions) in GENRE_EXPANSIONS.iter() {
-                            if expansions
-                                .iter()
-                                .any(|&exp| genre.contains(exp) || exp.contains(genre))
-                            {
-                                pattern = QueryPattern::Genre;
-                                extracted_terms.push(base_genre.to_string());
-                                filters.genres =
-                                    expansions.iter().map(|&s| s.to_string()).collect();
-                                expanded_terms.extend(expansions.iter().map(|&s| s.to_string()));
-                                hints.semantic_weight = 0.7;
-                                hints.metadata_weight = 0.3;
-                                break;
-                            }
+                        // Genres the captured phrase mentions, else genres mentioning it
+                        let base_genre = tables
+                            .genre_matcher
+                            .keys_in(genre)
+                            .first()
+                            .copied()
+                            .or_else(|| {
+                                tables
+                                    .genres
+                                    .iter()
+                                    .filter(|(_, expansions)| {
+                                        expansions.iter().any(|&exp| exp.contains(genre))
+                                    })
+                                    .map(|(&base_genre, _)| base_genre)
+                                    .min()
+                            });
+                        if let Some(base_genre) = base_genre {
+                            let expansions = &tables.genres[base_genre];
+                            pattern = QueryPattern::Genre;
+                            extracted_terms.push(base_genre.to_string());
+                            filters.genres = expansions.iter().map(|&s| s.to_string()).collect();
+                            expanded_terms.extend(expansions.iter().map(|&s| s.to_string()));
+                            hints.semantic_weight = 0.7;
+                            hints.metadata_weight = 0.3;
                         }
                         if pattern == QueryPattern::Genre {
                             break;
@@ -349,16 +553,14 @@ impl EnhancedQuery {
 
             // Also check for genre keywords in general text
             if pattern == QueryPattern::General {
-                for (base_genre, expansions) in GENRE_EXPANSIONS.iter() {
-                    if expansions.iter().any(|&exp| query_lower.contains(exp)) {
-                        pattern = QueryPattern::Genre;
-                        extracted_terms.push(base_genre.to_string());
-                        filters.genres = expansions.iter().map(|&s| s.to_string()).collect();
-                        expanded_terms.extend(expansions.iter().map(|&s| s.to_string()));
-                        hints.semantic_weight = 0.7;
-                        hints.metadata_weight = 0.3;
-                        break;
-                    }
+                if let Some(&base_genre) = tables.genre_matcher.keys_in(&query_lower).first() {
+                    let expansions = &tables.genres[base_genre];
+                    pattern = QueryPattern::Genre;
+                    extracted_terms.push(base_genre.to_string());
+                    filters.genres = expansions.iter().map(|&s| s.to_string()).collect();
+                    expanded_terms.extend(expansions.iter().map(|&s| s.to_string()));
+                    hints.semantic_weight = 0.7;
+                    hints.metadata_weight = 0.3;
                 }
             }
         }
@@ -428,8 +630,10 @@ impl EnhancedQuery {
             }
         }
 
-        // Check for historical periods
-        for (period, (start_year, end_year)) in HISTORICAL_PERIODS.iter() {
+        // Check for historical periods, in name order so overlapping ones apply the same way every run
+        let mut periods: Vec<_> = HISTORICAL_PERIODS.iter().collect();
+        periods.sort_unstable_by_key(|&(&period, _)| period);
+        for (period, (start_year, end_year)) in periods {
             if query_lower.contains(period) {
                 filters.min_year = Some(*start_year);
                 filters.max_year = Some(*end_year);
@@ -464,13 +668,24 @@ impl EnhancedQuery {
                 || query_lower.contains("quick")
                 || query_lower.contains("brief")
             {
-                filters.max_pages = Some(300);
+                filters.max_pages = Some(SHORT_READ_MAX_PAGES);
+            }
+            if query_lower
+                .split(|c: char| !c.is_alphanumeric())
+                .any(|word| word == "long" || word == "lengthy")
+            {
+                filters.min_pages = Some(LONG_READ_MIN_PAGES);
             }
             if pattern == QueryPattern::General {
                 pattern = QueryPattern::Length;
             }
         }
 
+        // Check for award patterns; winners are credited from the award list when indexing
+        if award_in_query(&query_lower).is_some() && pattern == QueryPattern::General {
+            pattern = QueryPattern::Award;
+        }
+
         // Check for complexity patterns
         if COMPLEXITY_PATTERNS.iter().any(|p| p.is_match(&query_lower)) {
             if query_lower.contains("easy")
@@ -485,14 +700,12 @@ impl EnhancedQuery {
         }
 
         // Extract theme keywords
-        for (theme, keywords) in THEME_KEYWORDS.iter() {
-            if keywords.iter().any(|&kw| query_lower.contains(kw)) {
-                extracted_terms.push(theme.to_string());
-                expanded_terms.extend(keywords.iter().map(|&s| s.to_string()));
-                filters.themes.push(theme.to_string());
-                if pattern == QueryPattern::General {
-                    pattern = QueryPattern::Theme;
-                }
+        for theme in tables.theme_matcher.keys_in(&query_lower) {
+            extracted_terms.push(theme.to_string());
+            expanded_terms.extend(tables.themes[theme].iter().map(|&s| s.to_string()));
+            filters.themes.push(theme.to_string());
+            if pattern == QueryPattern::General {
+                pattern = QueryPattern::Theme;
             }
         }
 
@@ -525,4 +738,55 @@ impl EnhancedQuery {
             search_hints: hints,
         }
     }
+
+    /// Normalized form of the query: its pattern, sorted content words and filters
+    ///
+    /// Rewordings such as "best fantasy books" and "fantasy books best" share a
+    /// signature. None when word order could change the meaning.
+    pub fn signature(&self) -> Option<String> {
+        let query_lower = self.original_query.to_lowercase();
+        let tokens: Vec<&str> = query_lower
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .collect();
+        // Checked before stop words go, as some of them join parts of the query
+        if tokens
+            .iter()
+            .any(|word| ORDER_SENSITIVE_WORDS.contains(word))
+        {
+            return None;
+        }
+        let mut words: Vec<&str> = tokens
+            .into_iter()
+            .filter(|word| !STOP_WORDS.contains(word))
+            .collect();
+        if words.is_empty() {
+            return None;
+        }
+        words.sort_unstable();
+        words.dedup();
+
+        let filters = &self.filters;
+        let sorted = |values: &[String]| {
+            let mut values: Vec<String> = values.iter().map(|value| value.to_lowercase()).collect();
+            values.sort_unstable();
+            values.dedup();
+            values.join(",")
+        };
+        Some(format!(
+            "{:?}|{}|author={}|genres={}|themes={}|settings={}|audience={}|rating={:?}|pages={:?}-{:?}|years={:?}-{:?}",
+            self.pattern,
+            words.join(" "),
+            filters.author.as_deref().unwrap_or_default().to_lowercase(),
+            sorted(&filters.genres),
+            sorted(&filters.themes),
+            sorted(&filters.settings),
+            filters.audience.as_deref().unwrap_or_default(),
+            filters.min_rating,
+            filters.min_pages,
+            filters.max_pages,
+            filters.min_year,
+            filters.max_year,
+        ))
+    }
 }
@@ -0,0 +1,101 @@
+//! Books readers are opening and shelving right now
+//!
+//! Trending is computed from the analytics events of a recent window: each
+//! click counts once and each shelving, a stronger signal, counts
+//! [`SHELVING_WEIGHT`] times. Impressions are left out since they only say
+//! what the ranking already showed.
+
+use crate::cache::CacheWeight;
+use crate::models::Book;
+use std::collections::HashMap;
+
+/// Clicks a shelving is worth
+pub const SHELVING_WEIGHT: u64 = 3;
+
+/// Event counts of one book in the trending window
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrendingCount {
+    pub book_id: String,
+    pub clicks: u64,
+    pub shelvings: u64,
+}
+
+impl CacheWeight for TrendingBook {
+    fn heap_size(&self) -> usize {
+        self.book.heap_size()
+    }
+}
+
+impl TrendingCount {
+    pub fn score(&self) -> u64 {
+        self.clicks + SHELVING_WEIGHT * self.shelvings
+    }
+}
+
+/// A trending book with the events that put it there
+#[derive(Debug, Clone)]
+pub struct TrendingBook {
+    pub book: Book,
+    pub clicks: u64,
+    pub shelvings: u64,
+}
+
+/// The `limit` books with the highest scores, ties broken by book id
+pub fn rank_trending(
+    clicks: HashMap<String, u64>,
+    shelvings: HashMap<String, u64>,
+    limit: usize,
+) -> Vec<TrendingCount> {
+    let mut counts: HashMap<String, TrendingCount> = HashMap::new();
+    for (book_id, clicks) in clicks {
+        counts
+            .entry(book_id.clone())
+            .or_insert_with(|| TrendingCount {
+                book_id,
+                clicks: 0,
+                shelvings: 0,
+            })
+            .clicks = clicks;
+    }
+    for (book_id, shelvings) in shelvings {
+        counts
+            .entry(book_id.clone())
+            .or_insert_with(|| TrendingCount {
+                book_id,
+                clicks: 0,
+                shelvings: 0,
+            })
+            .shelvings = shelvings;
+    }
+
+    let mut ranked: Vec<TrendingCount> = counts.into_values().collect();
+    ranked.sort_by(|a, b| {
+        b.score()
+            .cmp(&a.score())
+            .then_with(|| a.book_id.cmp(&b.book_id))
+    });
+    ranked.truncate(limit);
+    ranked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shelvings_outweigh_clicks() {
+        let clicks = HashMap::from([
+            ("dune".to_string(), 5),
+            ("emma".to_string(), 1),
+            ("ulysses".to_string(), 2),
+        ]);
+        let shelvings = HashMap::from([("emma".to_string(), 2), ("beloved".to_string(), 1)]);
+
+        let ranked = rank_trending(clicks, shelvings, 3);
+        let ids: Vec<&str> = ranked.iter().map(|count| count.book_id.as_str()).collect();
+        assert_eq!(ids, vec!["emma", "dune", "beloved"]);
+        assert_eq!(ranked[0].score(), 7);
+        assert_eq!(ranked[0].clicks, 1);
+        assert_eq!(ranked[0].shelvings, 2);
+    }
+}
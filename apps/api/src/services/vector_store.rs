@@ -0,0 +1,208 @@
+//! The vector index the recommendation service searches and maintains
+//!
+//! [`Pinecone`] is the index in production. The trait lets tests, and users of
+//! the crate, swap in another one, such as the in-memory index of the
+//! `test_support` module.
+
+use crate::error::Result;
+use crate::ml::sparse_encoder::SparseValues;
+use crate::models::{Book, EndpointProbe};
+use crate::services::pinecone::{Pinecone, UpsertVector};
+use futures::future::BoxFuture;
+use std::collections::HashMap;
+
+/// Reads and writes of the book vectors and their metadata
+///
+/// Queries and book fetches leave out hidden books; metadata and vector
+/// fetches don't. Ids that aren't indexed are absent from fetch results.
+pub trait VectorStore: Send + Sync {
+    /// Books whose metadata `field` equals `value` (exactly, or in any case), meeting `filter`
+    fn query_metadata_filtered<'a>(
+        &'a self,
+        field: &'a str,
+        value: &'a str,
+        exact_match: bool,
+        top_k: usize,
+        filter: Option<&'a serde_json::Value>,
+    ) -> BoxFuture<'a, Result<Vec<Book>>>;
+
+    /// Books closest to `embedding` meeting `filter`, closest first
+    fn query_vector_filtered<'a>(
+        &'a self,
+        embedding: &'a [f32],
+        top_k: usize,
+        filter: Option<&'a serde_json::Value>,
+    ) -> BoxFuture<'a, Result<Vec<Book>>>;
+
+    /// Books sharing keywords with `sparse`, best match first
+    fn query_sparse<'a>(
+        &'a self,
+        sparse: &'a SparseValues,
+        top_k: usize,
+    ) -> BoxFuture<'a, Result<Vec<Book>>>;
+
+    fn upsert_vectors<'a>(&'a self, vectors: &'a [UpsertVector]) -> BoxFuture<'a, Result<()>>;
+
+    /// Ids of every indexed vector
+    fn list_ids(&self) -> BoxFuture<'_, Result<Vec<String>>>;
+
+    fn delete_vectors<'a>(&'a self, ids: &'a [String]) -> BoxFuture<'a, Result<()>>;
+
+    /// Set the fields of `metadata` on a vector's stored metadata
+    fn update_metadata<'a>(
+        &'a self,
+        id: &'a str,
+        metadata: &'a serde_json::Value,
+    ) -> BoxFuture<'a, Result<()>>;
+
+    fn fetch_metadata<'a>(
+        &'a self,
+        ids: &'a [String],
+    ) -> BoxFuture<'a, Result<HashMap<String, serde_json::Value>>>;
+
+    fn fetch_books<'a>(&'a self, ids: &'a [String])
+        -> BoxFuture<'a, Result<HashMap<String, Book>>>;
+
+    fn fetch_vectors<'a>(
+        &'a self,
+        ids: &'a [String],
+    ) -> BoxFuture<'a, Result<HashMap<String, Vec<f32>>>>;
+
+    /// Ids of the hidden books
+    fn hidden_ids(&self) -> BoxFuture<'_, Result<Vec<String>>>;
+
+    fn set_hidden<'a>(&'a self, id: &'a str, hidden: bool) -> BoxFuture<'a, Result<()>>;
+
+    /// Drop cached query results
+    fn clear_caches(&self);
+
+    /// Books whose metadata `field` equals `value`
+    fn query_metadata<'a>(
+        &'a self,
+        field: &'a str,
+        value: &'a str,
+        exact_match: bool,
+        top_k: usize,
+    ) -> BoxFuture<'a, Result<Vec<Book>>> {
+        self.query_metadata_filtered(field, value, exact_match, top_k, None)
+    }
+
+    /// Books closest to `embedding`, closest first
+    fn query_vector<'a>(
+        &'a self,
+        embedding: &'a [f32],
+        top_k: usize,
+    ) -> BoxFuture<'a, Result<Vec<Book>>> {
+        self.query_vector_filtered(embedding, top_k, None)
+    }
+
+    /// Resolve and probe the index's endpoint, for stores reached over the network
+    fn probe_endpoint(&self) -> BoxFuture<'_, Option<EndpointProbe>> {
+        Box::pin(async { None })
+    }
+
+    /// Result of the last endpoint probe
+    fn endpoint_status(&self) -> Option<EndpointProbe> {
+        None
+    }
+}
+
+impl VectorStore for Pinecone {
+    fn query_metadata_filtered<'a>(
+        &'a self,
+        field: &'a str,
+        value: &'a str,
+        exact_match: bool,
+        top_k: usize,
+        filter: Option<&'a serde_json::Value>,
+    ) -> BoxFuture<'a, Result<Vec<Book>>> {
+        Box::pin(Pinecone::query_metadata_filtered(
+            self,
+            field,
+            value,
+            exact_match,
+            top_k,
+            filter,
+        ))
+    }
+
+    fn query_vector_filtered<'a>(
+        &'a self,
+        embedding: &'a [f32],
+        top_k: usize,
+        filter: Option<&'a serde_json::Value>,
+    ) -> BoxFuture<'a, Result<Vec<Book>>> {
+        Box::pin(Pinecone::query_vector_filtered(
+            self, embedding, top_k, filter,
+        ))
+    }
+
+    fn query_sparse<'a>(
+        &'a self,
+        sparse: &'a SparseValues,
+        top_k: usize,
+    ) -> BoxFuture<'a, Result<Vec<Book>>> {
+        Box::pin(Pinecone::query_sparse(self, sparse, top_k))
+    }
+
+    fn upsert_vectors<'a>(&'a self, vectors: &'a [UpsertVector]) -> BoxFuture<'a, Result<()>> {
+        Box::pin(Pinecone::upsert_vectors(self, vectors))
+    }
+
+    fn list_ids(&self) -> BoxFuture<'_, Result<Vec<String>>> {
+        Box::pin(Pinecone::list_ids(self))
+    }
+
+    fn delete_vectors<'a>(&'a self, ids: &'a [String]) -> BoxFuture<'a, Result<()>> {
+        Box::pin(Pinecone::delete_vectors(self, ids))
+    }
+
+    fn update_metadata<'a>(
+        &'a self,
+        id: &'a str,
+        metadata: &'a serde_json::Value,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(Pinecone::update_metadata(self, id, metadata))
+    }
+
+    fn fetch_metadata<'a>(
+        &'a self,
+        ids: &'a [String],
+    ) -> BoxFuture<'a, Result<HashMap<String, serde_json::Value>>> {
+        Box::pin(Pinecone::fetch_metadata(self, ids))
+    }
+
+    fn fetch_books<'a>(
+        &'a self,
+        ids: &'a [String],
+    ) -> BoxFuture<'a, Result<HashMap<String, Book>>> {
+        Box::pin(Pinecone::fetch_books(self, ids))
+    }
+
+    fn fetch_vectors<'a>(
+        &'a self,
+        ids: &'a [String],
+    ) -> BoxFuture<'a, Result<HashMap<String, Vec<f32>>>> {
+        Box::pin(Pinecone::fetch_vectors(self, ids))
+    }
+
+    fn hidden_ids(&self) -> BoxFuture<'_, Result<Vec<String>>> {
+        Box::pin(Pinecone::hidden_ids(self))
+    }
+
+    fn set_hidden<'a>(&'a self, id: &'a str, hidden: bool) -> BoxFuture<'a, Result<()>> {
+        Box::pin(Pinecone::set_hidden(self, id, hidden))
+    }
+
+    fn clear_caches(&self) {
+        Pinecone::clear_caches(self)
+    }
+
+    fn probe_endpoint(&self) -> BoxFuture<'_, Option<EndpointProbe>> {
+        Box::pin(async move { Some(Pinecone::probe_endpoint(self).await) })
+    }
+
+    fn endpoint_status(&self) -> Option<EndpointProbe> {
+        Pinecone::endpoint_status(self)
+    }
+}
@@ -0,0 +1,350 @@
+//! Lightweight in-process counters for operational telemetry
+//!
+//! Counters are plain atomics so they can be bumped from any handler or service
+//! without locking. Per-variant experiment counters are the exception: variants
+//! come from configuration, so they live in a map behind a lock, as do the
+//! per-pattern recommendation quality counters and their daily rollup.
+//! Everything resets on restart and is exposed through `/api/metrics`.
+
+use crate::cache::{CacheMemory, CacheStats};
+use crate::error::{recover_lock, ApiError};
+use chrono::{Days, NaiveDate};
+use lazy_static::lazy_static;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+use std::time::Duration;
+use utoipa::ToSchema;
+
+lazy_static! {
+    // One counter per ApiError variant, registered up front so increments never need a lock
+    static ref ERROR_COUNTS: HashMap<&'static str, AtomicU64> = ApiError::CODES
+        .iter()
+        .map(|code| (*code, AtomicU64::new(0)))
+        .collect();
+
+    // Handler panics caught by the CatchPanic middleware
+    static ref PANIC_COUNT: AtomicU64 = AtomicU64::new(0);
+
+    // Poisoned cache locks that had to be recovered
+    static ref POISONED_LOCK_COUNT: AtomicU64 = AtomicU64::new(0);
+
+    // Recommendation requests turned away because too many queries were queued
+    static ref SHED_REQUEST_COUNT: AtomicU64 = AtomicU64::new(0);
+
+    // Recommendation requests per experiment variant, keyed by "experiment/variant"
+    static ref VARIANT_STATS: RwLock<BTreeMap<String, VariantStats>> =
+        RwLock::new(BTreeMap::new());
+
+    // Recommendation quality per query pattern, since the process started
+    static ref QUALITY_STATS: RwLock<BTreeMap<String, QualityStats>> =
+        RwLock::new(BTreeMap::new());
+
+    // Recommendation quality per UTC day and query pattern, the last QUALITY_ROLLUP_DAYS days
+    static ref QUALITY_DAYS: RwLock<BTreeMap<NaiveDate, PatternQuality>> =
+        RwLock::new(BTreeMap::new());
+
+    // Process start, used to report uptime alongside the counters
+    static ref STARTED_AT: chrono::DateTime<chrono::Utc> = chrono::Utc::now();
+}
+
+/// Record that an error of the given code was returned to a client
+pub fn record_error(code: &str) {
+    if let Some(counter) = ERROR_COUNTS.get(code) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Record a handler panic that was converted into a 500 response
+pub fn record_panic() {
+    PANIC_COUNT.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Record a recommendation request shed because too many queries were queued
+pub fn record_shed_request() {
+    SHED_REQUEST_COUNT.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Record a poisoned lock that was recovered instead of being given up on
+pub fn record_poisoned_lock() {
+    POISONED_LOCK_COUNT.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Record the outcome of a recommendation request bucketed into an experiment variant
+///
+/// `result_count` is None when the request failed.
+pub fn record_variant_request(variant: &str, elapsed: Duration, result_count: Option<usize>) {
+    let mut stats = recover_lock(VARIANT_STATS.write(), "experiment telemetry");
+    let stats = stats.entry(variant.to_string()).or_default();
+    stats.requests += 1;
+    stats.total_latency_ms += elapsed.as_millis() as u64;
+    match result_count {
+        None => stats.errors += 1,
+        Some(0) => stats.empty_results += 1,
+        Some(_) => {}
+    }
+}
+
+/// Days of quality counters kept for the daily rollup
+pub const QUALITY_ROLLUP_DAYS: usize = 30;
+
+/// Quality counters per query pattern
+type PatternQuality = BTreeMap<String, QualityStats>;
+
+/// Record how well a recommendation request for a query of `pattern` was understood
+///
+/// `tagged` is whether any semantic tag was read from the query; `explained` of
+/// its results got an explanation, `generic` of them one that names nothing the
+/// book and query share.
+pub fn record_quality(pattern: &str, tagged: bool, explained: usize, generic: usize) {
+    let record = |stats: &mut QualityStats| {
+        stats.requests += 1;
+        stats.tagged_requests += tagged as u64;
+        stats.explanations += explained as u64;
+        stats.generic_explanations += generic as u64;
+    };
+    record(
+        recover_lock(QUALITY_STATS.write(), "quality telemetry")
+            .entry(pattern.to_string())
+            .or_default(),
+    );
+
+    let today = chrono::Utc::now().date_naive();
+    let mut days = recover_lock(QUALITY_DAYS.write(), "quality telemetry");
+    record(
+        days.entry(today)
+            .or_default()
+            .entry(pattern.to_string())
+            .or_default(),
+    );
+    *days = days.split_off(&first_day(today, QUALITY_ROLLUP_DAYS));
+}
+
+/// First of the `days` UTC days ending with `today`
+fn first_day(today: NaiveDate, days: usize) -> NaiveDate {
+    today - Days::new(days.saturating_sub(1) as u64)
+}
+
+/// Recommendation quality counters of one query pattern
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct QualityStats {
+    /// Recommendation requests for queries of the pattern
+    #[schema(example = 800)]
+    pub requests: u64,
+    /// Requests any semantic tag was read from
+    #[schema(example = 760)]
+    pub tagged_requests: u64,
+    /// Results explained
+    #[schema(example = 1500)]
+    pub explanations: u64,
+    /// Explanations that name nothing the book and query share
+    #[schema(example = 90)]
+    pub generic_explanations: u64,
+}
+
+impl QualityStats {
+    fn add(&mut self, other: &QualityStats) {
+        self.requests += other.requests;
+        self.tagged_requests += other.tagged_requests;
+        self.explanations += other.explanations;
+        self.generic_explanations += other.generic_explanations;
+    }
+
+    /// Share of requests any semantic tag was read from; None without requests
+    pub fn tag_coverage(&self) -> Option<f64> {
+        (self.requests > 0).then(|| self.tagged_requests as f64 / self.requests as f64)
+    }
+
+    /// Share of explanations that are generic; None without explanations
+    pub fn generic_explanation_rate(&self) -> Option<f64> {
+        (self.explanations > 0).then(|| self.generic_explanations as f64 / self.explanations as f64)
+    }
+}
+
+/// Recommendation quality of one UTC day
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct QualityDay {
+    #[schema(example = "2024-01-15")]
+    pub date: String,
+    /// Counters of all query patterns together
+    pub total: QualityStats,
+    /// Share of requests any semantic tag was read from
+    #[schema(example = 0.95)]
+    pub tag_coverage: Option<f64>,
+    /// Share of explanations that are generic
+    #[schema(example = 0.06)]
+    pub generic_explanation_rate: Option<f64>,
+    /// Counters per query pattern
+    pub patterns: PatternQuality,
+}
+
+/// Quality counters of the last `days` UTC days, most recent first; days
+/// without requests are left out
+pub fn quality_rollup(days: usize) -> Vec<QualityDay> {
+    let counters = recover_lock(QUALITY_DAYS.read(), "quality telemetry");
+    rollup(&counters, chrono::Utc::now().date_naive(), days)
+}
+
+fn rollup(
+    counters: &BTreeMap<NaiveDate, PatternQuality>,
+    today: NaiveDate,
+    days: usize,
+) -> Vec<QualityDay> {
+    if days == 0 {
+        return Vec::new();
+    }
+    counters
+        .range(first_day(today, days)..=today)
+        .rev()
+        .map(|(date, patterns)| {
+            let mut total = QualityStats::default();
+            for stats in patterns.values() {
+                total.add(stats);
+            }
+            QualityDay {
+                date: date.to_string(),
+                tag_coverage: total.tag_coverage(),
+                generic_explanation_rate: total.generic_explanation_rate(),
+                total,
+                patterns: patterns.clone(),
+            }
+        })
+        .collect()
+}
+
+/// Recommendation outcomes of one experiment variant
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct VariantStats {
+    /// Requests bucketed into the variant
+    #[schema(example = 1200)]
+    pub requests: u64,
+    /// Requests that failed
+    #[schema(example = 4)]
+    pub errors: u64,
+    /// Requests that succeeded without any results
+    #[schema(example = 17)]
+    pub empty_results: u64,
+    /// Summed end-to-end latency; divide by requests for the mean
+    #[schema(example = 1560000)]
+    pub total_latency_ms: u64,
+}
+
+/// Point-in-time view of all telemetry counters
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct TelemetrySnapshot {
+    /// Seconds since the process started collecting telemetry
+    #[schema(example = 3600)]
+    pub uptime_seconds: i64,
+    /// Error responses returned, keyed by error code
+    #[schema(example = json!({"invalid_input": 3, "pinecone_error": 1}))]
+    pub errors: BTreeMap<String, u64>,
+    /// Handler panics caught and answered with a 500
+    #[schema(example = 0)]
+    pub panics: u64,
+    /// Poisoned locks that were recovered
+    #[schema(example = 0)]
+    pub poisoned_locks: u64,
+    /// Recommendation requests answered 503 at once because too many queries were queued
+    #[schema(example = 0)]
+    pub shed_requests: u64,
+    /// Recommendation requests per experiment variant, keyed by `experiment/variant`
+    pub experiments: BTreeMap<String, VariantStats>,
+    /// Recommendation quality per query pattern, keyed by pattern
+    pub quality: BTreeMap<String, QualityStats>,
+    /// Hit, miss and eviction counters of every in-process cache
+    pub caches: Vec<CacheStats>,
+    /// Approximate memory held by the caches together, against their budget
+    pub cache_memory: CacheMemory,
+}
+
+/// Capture the current value of every counter
+pub fn snapshot() -> TelemetrySnapshot {
+    TelemetrySnapshot {
+        uptime_seconds: (chrono::Utc::now() - *STARTED_AT).num_seconds(),
+        errors: ERROR_COUNTS
+            .iter()
+            .map(|(code, count)| (code.to_string(), count.load(Ordering::Relaxed)))
+            .collect(),
+        panics: PANIC_COUNT.load(Ordering::Relaxed),
+        poisoned_locks: POISONED_LOCK_COUNT.load(Ordering::Relaxed),
+        shed_requests: SHED_REQUEST_COUNT.load(Ordering::Relaxed),
+        experiments: recover_lock(VARIANT_STATS.read(), "experiment telemetry").clone(),
+        quality: recover_lock(QUALITY_STATS.read(), "quality telemetry").clone(),
+        caches: crate::cache::all_stats(),
+        cache_memory: crate::cache::memory_usage(),
+    }
+}
+
+/// Touch the lazily initialized statics so uptime is measured from startup
+pub fn init() {
+    lazy_static::initialize(&STARTED_AT);
+    lazy_static::initialize(&ERROR_COUNTS);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(day: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(day, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn test_quality_is_recorded_per_pattern_and_day() {
+        // Other tests record requests too, so only this test's pattern is checked
+        record_quality("TestPattern", true, 5, 1);
+        record_quality("TestPattern", false, 0, 0);
+
+        let stats = recover_lock(QUALITY_STATS.read(), "quality telemetry")["TestPattern"].clone();
+        assert_eq!(
+            (stats.requests, stats.tagged_requests, stats.explanations),
+            (2, 1, 5)
+        );
+        assert_eq!(stats.tag_coverage(), Some(0.5));
+        assert_eq!(stats.generic_explanation_rate(), Some(0.2));
+
+        let today = &quality_rollup(1)[0];
+        assert_eq!(today.date, chrono::Utc::now().date_naive().to_string());
+        assert_eq!(today.patterns["TestPattern"].requests, 2);
+    }
+
+    #[test]
+    fn test_rollup_covers_calendar_days_not_days_with_traffic() {
+        let stats = |requests| QualityStats {
+            requests,
+            tagged_requests: requests,
+            ..QualityStats::default()
+        };
+        let counters: BTreeMap<NaiveDate, PatternQuality> = [
+            ("2024-01-31", "Genre", 4),
+            ("2024-01-29", "Author", 2),
+            ("2024-01-29", "Genre", 1),
+            ("2023-12-01", "Genre", 9),
+        ]
+        .into_iter()
+        .fold(BTreeMap::new(), |mut counters, (day, pattern, requests)| {
+            counters
+                .entry(date(day))
+                .or_default()
+                .insert(pattern.to_string(), stats(requests));
+            counters
+        });
+
+        let days = rollup(&counters, date("2024-01-31"), 3);
+        assert_eq!(
+            days.iter().map(|day| day.date.as_str()).collect::<Vec<_>>(),
+            vec!["2024-01-31", "2024-01-29"]
+        );
+        assert_eq!(days[1].total.requests, 3);
+        assert_eq!(days[1].tag_coverage, Some(1.0));
+        assert_eq!(days[1].generic_explanation_rate, None);
+
+        // December is outside any rollup the endpoint allows
+        assert_eq!(
+            rollup(&counters, date("2024-01-31"), QUALITY_ROLLUP_DAYS).len(),
+            2
+        );
+        assert!(rollup(&counters, date("2024-01-31"), 0).is_empty());
+    }
+}
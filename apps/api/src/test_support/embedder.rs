@@ -0,0 +1,100 @@
+use crate::error::ApiError;
+use crate::ml::embedder::Embedder;
+use crate::services::randomness::stable_hash;
+use futures::future::BoxFuture;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Size of the fake embeddings, the size of the production ones
+pub const FAKE_EMBEDDING_SIZE: usize = 512;
+
+const FAKE_MODEL_NAME: &str = "test-support/hashed-words";
+
+/// Deterministic embedder hashing each word of a text into one dimension
+///
+/// Texts sharing words end up close, so searches rank the books sharing the
+/// most words with the query first. Clones share their state.
+#[derive(Clone, Default)]
+pub struct FakeEmbedder {
+    unavailable: Arc<AtomicBool>,
+    prewarmed: Arc<AtomicBool>,
+    calls: Arc<AtomicUsize>,
+}
+
+impl FakeEmbedder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fail every request as an overloaded inference API would, or recover
+    pub fn set_unavailable(&self, unavailable: bool) {
+        self.unavailable.store(unavailable, Ordering::Relaxed);
+    }
+
+    /// Texts embedded so far
+    pub fn calls(&self) -> usize {
+        self.calls.load(Ordering::Relaxed)
+    }
+
+    /// The unit-length embedding of `text`, or zeros when it has no words
+    pub fn embed(text: &str) -> Vec<f32> {
+        let mut vector = vec![0.0; FAKE_EMBEDDING_SIZE];
+        for word in text
+            .to_lowercase()
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+        {
+            vector[(stable_hash(word) % FAKE_EMBEDDING_SIZE as u64) as usize] += 1.0;
+        }
+        let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            vector.iter_mut().for_each(|x| *x /= norm);
+        }
+        vector
+    }
+
+    fn check_available(&self, texts: usize) -> Result<(), ApiError> {
+        if self.unavailable.load(Ordering::Relaxed) {
+            return Err(ApiError::ServiceUnavailable {
+                message: "Fake embedder is unavailable".to_string(),
+                retry_after: None,
+            });
+        }
+        self.calls.fetch_add(texts, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+impl Embedder for FakeEmbedder {
+    fn model_info(&self) -> (String, usize) {
+        (FAKE_MODEL_NAME.to_string(), FAKE_EMBEDDING_SIZE)
+    }
+
+    fn encode_with_model<'a>(
+        &'a self,
+        text: &'a str,
+        _model: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<Vec<f32>, ApiError>> {
+        Box::pin(async move {
+            self.check_available(1)?;
+            Ok(Self::embed(text))
+        })
+    }
+
+    fn encode_batch<'a>(
+        &'a self,
+        texts: &'a [String],
+    ) -> BoxFuture<'a, Result<Vec<Vec<f32>>, ApiError>> {
+        Box::pin(async move {
+            self.check_available(texts.len())?;
+            Ok(texts.iter().map(|text| Self::embed(text)).collect())
+        })
+    }
+
+    fn prewarm(&self) -> BoxFuture<'_, Result<bool, ApiError>> {
+        Box::pin(async move {
+            self.check_available(0)?;
+            Ok(!self.prewarmed.swap(true, Ordering::Relaxed))
+        })
+    }
+}
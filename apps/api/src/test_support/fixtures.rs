@@ -0,0 +1,214 @@
+use crate::models::Book;
+use serde_json::{json, Map, Value};
+
+/// Builds a [`Book`] with only the fields a test cares about
+///
+/// Fields left unset get the defaults of a book read from the index.
+#[derive(Debug, Clone)]
+pub struct BookBuilder {
+    fields: Map<String, Value>,
+}
+
+impl BookBuilder {
+    pub fn new(id: &str) -> Self {
+        let mut fields = Map::new();
+        fields.insert("id".to_string(), json!(id));
+        fields.insert("title".to_string(), json!(id));
+        fields.insert("categories".to_string(), json!([]));
+        Self { fields }
+    }
+
+    fn with(mut self, field: &str, value: Value) -> Self {
+        self.fields.insert(field.to_string(), value);
+        self
+    }
+
+    pub fn title(self, title: &str) -> Self {
+        self.with("title", json!(title))
+    }
+
+    pub fn author(self, author: &str) -> Self {
+        self.with("author", json!(author))
+    }
+
+    pub fn description(self, description: &str) -> Self {
+        self.with("description", json!(description))
+    }
+
+    pub fn categories(self, categories: &[&str]) -> Self {
+        self.with("categories", json!(categories))
+    }
+
+    pub fn genres(self, genres: &[&str]) -> Self {
+        self.with("genres", json!(genres))
+    }
+
+    pub fn moods(self, moods: &[&str]) -> Self {
+        self.with("moods", json!(moods))
+    }
+
+    pub fn settings(self, settings: &[&str]) -> Self {
+        self.with("settings", json!(settings))
+    }
+
+    pub fn year(self, year: i32) -> Self {
+        self.with("year", json!(year))
+    }
+
+    pub fn page_count(self, page_count: i32) -> Self {
+        self.with("page_count", json!(page_count))
+    }
+
+    pub fn rating(self, rating: f32, ratings_count: i32) -> Self {
+        self.with("rating", json!(rating))
+            .with("ratings_count", json!(ratings_count))
+    }
+
+    pub fn language(self, language: &str) -> Self {
+        self.with("language", json!(language))
+    }
+
+    pub fn build(self) -> Book {
+        serde_json::from_value(Value::Object(self.fields)).expect("builder fields form a book")
+    }
+}
+
+/// A dozen books across genres, eras and lengths
+pub fn sample_catalog() -> Vec<Book> {
+    vec![
+        BookBuilder::new("dune")
+            .title("Dune")
+            .author("Frank Herbert")
+            .description("A desert planet, a noble family and a war over the spice that powers space travel.")
+            .categories(&["Science Fiction"])
+            .genres(&["science fiction"])
+            .moods(&["epic"])
+            .year(1965)
+            .page_count(688)
+            .rating(4.3, 1_400_000)
+            .build(),
+        BookBuilder::new("hyperion")
+            .title("Hyperion")
+            .author("Dan Simmons")
+            .description("Seven pilgrims travel to a distant planet and tell the stories that brought them there.")
+            .categories(&["Science Fiction"])
+            .genres(&["science fiction"])
+            .moods(&["dark", "epic"])
+            .year(1989)
+            .page_count(482)
+            .rating(4.2, 250_000)
+            .build(),
+        BookBuilder::new("murderbot")
+            .title("All Systems Red")
+            .author("Martha Wells")
+            .description("A security robot that hacked its governor module would rather watch serials than protect its clients.")
+            .categories(&["Science Fiction"])
+            .genres(&["science fiction"])
+            .moods(&["funny"])
+            .year(2017)
+            .page_count(152)
+            .rating(4.1, 200_000)
+            .build(),
+        BookBuilder::new("hobbit")
+            .title("The Hobbit")
+            .author("J.R.R. Tolkien")
+            .description("A hobbit joins a company of dwarves on a quest to reclaim a treasure guarded by a dragon.")
+            .categories(&["Fantasy"])
+            .genres(&["fantasy"])
+            .moods(&["adventurous", "whimsical"])
+            .year(1937)
+            .page_count(310)
+            .rating(4.3, 3_800_000)
+            .build(),
+        BookBuilder::new("earthsea")
+            .title("A Wizard of Earthsea")
+            .author("Ursula K. Le Guin")
+            .description("A young wizard unleashes a shadow and must hunt it across the islands of Earthsea.")
+            .categories(&["Fantasy"])
+            .genres(&["fantasy"])
+            .moods(&["reflective"])
+            .year(1968)
+            .page_count(183)
+            .rating(4.0, 300_000)
+            .build(),
+        BookBuilder::new("name-of-the-wind")
+            .title("The Name of the Wind")
+            .author("Patrick Rothfuss")
+            .description("A gifted young man grows up to become the most notorious wizard his world has ever seen.")
+            .categories(&["Fantasy"])
+            .genres(&["fantasy"])
+            .moods(&["adventurous"])
+            .year(2007)
+            .page_count(662)
+            .rating(4.5, 900_000)
+            .build(),
+        BookBuilder::new("and-then-there-were-none")
+            .title("And Then There Were None")
+            .author("Agatha Christie")
+            .description("Ten strangers are lured to an island off the coast of Devon and murdered one by one.")
+            .categories(&["Mystery"])
+            .genres(&["mystery"])
+            .moods(&["suspenseful", "dark"])
+            .settings(&["Devon", "England", "United Kingdom", "Europe"])
+            .year(1939)
+            .page_count(272)
+            .rating(4.3, 1_200_000)
+            .build(),
+        BookBuilder::new("thursday-murder-club")
+            .title("The Thursday Murder Club")
+            .author("Richard Osman")
+            .description("Four friends in a retirement village meet weekly to solve cold cases, until a real murder happens.")
+            .categories(&["Mystery"])
+            .genres(&["mystery"])
+            .moods(&["cozy", "funny"])
+            .settings(&["Kent", "England", "United Kingdom", "Europe"])
+            .year(2020)
+            .page_count(382)
+            .rating(4.0, 600_000)
+            .build(),
+        BookBuilder::new("pride-and-prejudice")
+            .title("Pride and Prejudice")
+            .author("Jane Austen")
+            .description("Elizabeth Bennet and the proud Mr. Darcy misjudge each other in Regency England.")
+            .categories(&["Romance", "Classics"])
+            .genres(&["romance", "classics"])
+            .moods(&["witty"])
+            .settings(&["England", "United Kingdom", "Europe"])
+            .year(1813)
+            .page_count(279)
+            .rating(4.3, 4_000_000)
+            .build(),
+        BookBuilder::new("salt-fat-acid-heat")
+            .title("Salt, Fat, Acid, Heat")
+            .author("Samin Nosrat")
+            .description("The four elements of good cooking, and how to use them to cook anything well.")
+            .categories(&["Cooking", "Nonfiction"])
+            .genres(&["cooking", "nonfiction"])
+            .year(2017)
+            .page_count(480)
+            .rating(4.4, 60_000)
+            .build(),
+        BookBuilder::new("sapiens")
+            .title("Sapiens")
+            .author("Yuval Noah Harari")
+            .description("A brief history of humankind, from foragers to the scientific revolution.")
+            .categories(&["History", "Nonfiction"])
+            .genres(&["history", "nonfiction"])
+            .moods(&["informative"])
+            .year(2011)
+            .page_count(443)
+            .rating(4.4, 1_000_000)
+            .build(),
+        BookBuilder::new("the-road")
+            .title("The Road")
+            .author("Cormac McCarthy")
+            .description("A father and his son walk through a burned America after an unnamed catastrophe.")
+            .categories(&["Fiction", "Post-Apocalyptic"])
+            .genres(&["literary fiction"])
+            .moods(&["dark", "sad"])
+            .year(2006)
+            .page_count(287)
+            .rating(4.0, 800_000)
+            .build(),
+    ]
+}
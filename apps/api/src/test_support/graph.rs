@@ -0,0 +1,170 @@
+use crate::error::{recover_lock, Result};
+use crate::models::Book;
+use crate::services::graph_store::GraphStore;
+use crate::services::neo4j::GraphRelationshipResponse;
+use futures::future::BoxFuture;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::{Arc, RwLock};
+
+#[derive(Default)]
+struct Graph {
+    /// Book nodes, and whether each is hidden
+    nodes: BTreeMap<String, (Book, bool)>,
+    relationships: Vec<GraphRelationshipResponse>,
+}
+
+/// Book graph held in memory in place of Neo4j
+///
+/// Clones share their nodes and relationships.
+#[derive(Clone, Default)]
+pub struct FakeGraph {
+    graph: Arc<RwLock<Graph>>,
+}
+
+impl FakeGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A graph with a node for each of `books`
+    pub fn with_books(books: &[Book]) -> Self {
+        let graph = Self::new();
+        for book in books {
+            graph.insert_book(book);
+        }
+        graph
+    }
+
+    pub fn insert_book(&self, book: &Book) {
+        let id = book.id.clone().unwrap_or_default();
+        recover_lock(self.graph.write(), "fake graph")
+            .nodes
+            .insert(id, (book.clone(), false));
+    }
+
+    /// Relate `from_id` to `to_id`, as the graph rebuild would
+    pub fn relate(&self, from_id: &str, to_id: &str, relation_type: &str, weight: f32) {
+        recover_lock(self.graph.write(), "fake graph")
+            .relationships
+            .push(GraphRelationshipResponse {
+                from_id: from_id.to_string(),
+                to_id: to_id.to_string(),
+                relation_type: relation_type.to_string(),
+                weight,
+            });
+    }
+
+    /// The node of `book_id`, and whether it's hidden
+    pub fn node(&self, book_id: &str) -> Option<(Book, bool)> {
+        recover_lock(self.graph.read(), "fake graph")
+            .nodes
+            .get(book_id)
+            .cloned()
+    }
+
+    /// Relationships between visible books meeting `keep`, strongest first
+    fn relationships(
+        &self,
+        keep: impl Fn(&GraphRelationshipResponse) -> bool,
+    ) -> Vec<GraphRelationshipResponse> {
+        let graph = recover_lock(self.graph.read(), "fake graph");
+        let visible = |id: &str| graph.nodes.get(id).is_some_and(|(_, hidden)| !hidden);
+        let mut relationships: Vec<GraphRelationshipResponse> = graph
+            .relationships
+            .iter()
+            .filter(|rel| visible(&rel.from_id) && visible(&rel.to_id) && keep(rel))
+            .cloned()
+            .collect();
+        relationships.sort_by(|a, b| {
+            b.weight
+                .partial_cmp(&a.weight)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        relationships
+    }
+}
+
+impl GraphStore for FakeGraph {
+    fn add_book<'a>(&'a self, book: &'a Book) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let id = book.id.clone().unwrap_or_default();
+            let mut graph = recover_lock(self.graph.write(), "fake graph");
+            let hidden = graph.nodes.get(&id).is_some_and(|(_, hidden)| *hidden);
+            graph.nodes.insert(id, (book.clone(), hidden));
+            Ok(())
+        })
+    }
+
+    fn update_book<'a>(&'a self, book: &'a Book) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(async move {
+            let id = book.id.as_deref().unwrap_or_default();
+            let mut graph = recover_lock(self.graph.write(), "fake graph");
+            Ok(match graph.nodes.get_mut(id) {
+                Some((node, _)) => {
+                    *node = book.clone();
+                    true
+                }
+                None => false,
+            })
+        })
+    }
+
+    fn set_hidden<'a>(&'a self, book_id: &'a str, hidden: bool) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(async move {
+            let mut graph = recover_lock(self.graph.write(), "fake graph");
+            Ok(match graph.nodes.get_mut(book_id) {
+                Some((_, node_hidden)) => {
+                    *node_hidden = hidden;
+                    true
+                }
+                None => false,
+            })
+        })
+    }
+
+    fn list_book_ids(&self) -> BoxFuture<'_, Result<Vec<String>>> {
+        Box::pin(async move {
+            Ok(recover_lock(self.graph.read(), "fake graph")
+                .nodes
+                .keys()
+                .cloned()
+                .collect())
+        })
+    }
+
+    fn delete_books<'a>(&'a self, ids: &'a [String]) -> BoxFuture<'a, Result<usize>> {
+        Box::pin(async move {
+            let ids: HashSet<&String> = ids.iter().collect();
+            let mut graph = recover_lock(self.graph.write(), "fake graph");
+            let before = graph.nodes.len();
+            graph.nodes.retain(|id, _| !ids.contains(id));
+            graph
+                .relationships
+                .retain(|rel| !ids.contains(&rel.from_id) && !ids.contains(&rel.to_id));
+            Ok(before - graph.nodes.len())
+        })
+    }
+
+    fn get_relationships_from<'a>(
+        &'a self,
+        book_ids: &'a [String],
+        limit: usize,
+    ) -> BoxFuture<'a, Result<Vec<GraphRelationshipResponse>>> {
+        Box::pin(async move {
+            let mut relationships = self.relationships(|rel| book_ids.contains(&rel.from_id));
+            relationships.truncate(limit);
+            Ok(relationships)
+        })
+    }
+
+    fn get_relationships_among<'a>(
+        &'a self,
+        book_ids: &'a [String],
+    ) -> BoxFuture<'a, Result<Vec<GraphRelationshipResponse>>> {
+        Box::pin(async move {
+            Ok(self.relationships(|rel| {
+                book_ids.contains(&rel.from_id) && book_ids.contains(&rel.to_id)
+            }))
+        })
+    }
+}
@@ -0,0 +1,79 @@
+//! In-memory stand-ins for the embedding model, the vector index and the book
+//! graph, with book fixtures, so tests run the whole recommendation pipeline
+//! without API keys or network access
+//!
+//! Compiled into the crate's own tests, and for other crates with the
+//! `test_support` feature.
+
+pub mod embedder;
+pub mod fixtures;
+pub mod graph;
+pub mod vector_store;
+
+pub use embedder::{FakeEmbedder, FAKE_EMBEDDING_SIZE};
+pub use fixtures::{sample_catalog, BookBuilder};
+pub use graph::FakeGraph;
+pub use vector_store::InMemoryVectorStore;
+
+use crate::models::Book;
+use crate::services::RecommendationService;
+
+/// A recommendation service over the fakes, with `books` indexed and in the graph
+///
+/// Clones of the fakes share their state with the ones the service holds.
+pub struct TestServices {
+    pub service: RecommendationService,
+    pub embedder: FakeEmbedder,
+    pub vector_store: InMemoryVectorStore,
+    pub graph: FakeGraph,
+}
+
+impl TestServices {
+    pub fn new(books: &[Book]) -> Self {
+        let embedder = FakeEmbedder::new();
+        let vector_store = InMemoryVectorStore::with_books(books);
+        let graph = FakeGraph::with_books(books);
+        let service = RecommendationService::new(embedder.clone(), vector_store.clone())
+            .with_graph(graph.clone());
+        Self {
+            service,
+            embedder,
+            vector_store,
+            graph,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::recommendation::RankingParams;
+    use crate::services::vector_store::VectorStore;
+
+    #[tokio::test]
+    async fn test_recommends_from_the_fakes() {
+        let services = TestServices::new(&sample_catalog());
+        let (books, _, _) = services
+            .service
+            .get_recommendations_served(
+                "a dragon guarding a treasure",
+                3,
+                &RankingParams::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(books[0].id.as_deref(), Some("hobbit"));
+        assert!(services.embedder.calls() > 0);
+
+        services.service.set_hidden("hobbit", true).await.unwrap();
+        assert_eq!(
+            services.graph.node("hobbit").map(|(_, hidden)| hidden),
+            Some(true)
+        );
+        let hidden = services
+            .vector_store
+            .fetch_books(&["hobbit".to_string()])
+            .await;
+        assert!(hidden.unwrap().is_empty());
+    }
+}
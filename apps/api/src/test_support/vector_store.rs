@@ -0,0 +1,398 @@
+use crate::error::{recover_lock, ApiError, Result};
+use crate::ingest::searchable_text::create_searchable_text;
+use crate::ml::sparse_encoder::{encode_book, SparseValues};
+use crate::models::Book;
+use crate::services::personalization::cosine_similarity;
+use crate::services::pinecone::{is_hidden, UpsertVector, HIDDEN_FIELD};
+use crate::services::vector_store::VectorStore;
+use crate::test_support::embedder::FakeEmbedder;
+use futures::future::BoxFuture;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+
+#[derive(Debug, Clone)]
+struct StoredVector {
+    values: Vec<f32>,
+    sparse_values: Option<SparseValues>,
+    metadata: Value,
+}
+
+/// Vector index held in memory, answering queries and metadata filters as
+/// Pinecone does for the filters the service builds
+///
+/// Clones share their vectors, so a test can keep one to inspect what the
+/// service wrote.
+#[derive(Clone, Default)]
+pub struct InMemoryVectorStore {
+    vectors: Arc<RwLock<BTreeMap<String, StoredVector>>>,
+    unavailable: Arc<AtomicBool>,
+}
+
+impl InMemoryVectorStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// An index of `books`, embedded from their searchable text as the indexer would
+    pub fn with_books(books: &[Book]) -> Self {
+        let store = Self::new();
+        for book in books {
+            store.insert_book(book);
+        }
+        store
+    }
+
+    /// Index `book` under its id, embedded with [`FakeEmbedder`]
+    pub fn insert_book(&self, book: &Book) {
+        let id = book.id.clone().unwrap_or_default();
+        let metadata = serde_json::to_value(book).expect("books serialize");
+        recover_lock(self.vectors.write(), "in-memory vectors").insert(
+            id,
+            StoredVector {
+                values: FakeEmbedder::embed(&create_searchable_text(book)),
+                sparse_values: encode_book(book),
+                metadata,
+            },
+        );
+    }
+
+    /// Fail every request as an unreachable index would, or recover
+    pub fn set_unavailable(&self, unavailable: bool) {
+        self.unavailable.store(unavailable, Ordering::Relaxed);
+    }
+
+    pub fn len(&self) -> usize {
+        recover_lock(self.vectors.read(), "in-memory vectors").len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn check_available(&self) -> Result<()> {
+        if self.unavailable.load(Ordering::Relaxed) {
+            return Err(ApiError::PineconeError(
+                "In-memory index is unavailable".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Visible books meeting `filter`, best `score` first, at most `top_k`
+    fn search(
+        &self,
+        top_k: usize,
+        filter: Option<&Value>,
+        score: impl Fn(&StoredVector) -> Option<f32>,
+    ) -> Result<Vec<Book>> {
+        self.check_available()?;
+        let vectors = recover_lock(self.vectors.read(), "in-memory vectors");
+        let mut scored: Vec<(f32, Book)> = vectors
+            .iter()
+            .filter(|(_, stored)| !is_hidden(&stored.metadata))
+            .filter(|(_, stored)| filter.is_none_or(|filter| matches(&stored.metadata, filter)))
+            .filter_map(|(id, stored)| Some((score(stored)?, book_of(id, &stored.metadata)?)))
+            .collect();
+        // Stable, so equal scores keep the order of the ids
+        scored.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+        Ok(scored
+            .into_iter()
+            .take(top_k)
+            .map(|(_, book)| book)
+            .collect())
+    }
+}
+
+fn book_of(id: &str, metadata: &Value) -> Option<Book> {
+    let mut metadata = metadata.clone();
+    metadata
+        .as_object_mut()?
+        .insert("id".to_string(), Value::String(id.to_string()));
+    serde_json::from_value(metadata).ok()
+}
+
+/// The values of `field` in `metadata`: a list's elements, or the value itself
+fn field_values<'a>(metadata: &'a Value, field: &str) -> Vec<&'a Value> {
+    match metadata.get(field) {
+        Some(Value::Array(values)) => values.iter().collect(),
+        Some(Value::Null) | None => Vec::new(),
+        Some(value) => vec![value],
+    }
+}
+
+fn same(a: &Value, b: &Value) -> bool {
+    match (a.as_f64(), b.as_f64()) {
+        (Some(a), Some(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+fn compare(values: &[&Value], bound: &Value, holds: fn(f64, f64) -> bool) -> bool {
+    let Some(bound) = bound.as_f64() else {
+        return false;
+    };
+    values
+        .iter()
+        .any(|value| value.as_f64().is_some_and(|value| holds(value, bound)))
+}
+
+/// Whether `metadata` meets a Pinecone metadata filter
+fn matches(metadata: &Value, filter: &Value) -> bool {
+    let Some(clauses) = filter.as_object() else {
+        return false;
+    };
+    clauses.iter().all(|(key, condition)| match key.as_str() {
+        "$and" => condition
+            .as_array()
+            .is_some_and(|filters| filters.iter().all(|filter| matches(metadata, filter))),
+        "$or" => condition
+            .as_array()
+            .is_some_and(|filters| filters.iter().any(|filter| matches(metadata, filter))),
+        field => {
+            let values = field_values(metadata, field);
+            match condition.as_object() {
+                Some(operators) => operators.iter().all(|(operator, operand)| {
+                    let listed = || operand.as_array().cloned().unwrap_or_default();
+                    match operator.as_str() {
+                        "$eq" => values.iter().any(|value| same(value, operand)),
+                        "$ne" => !values.iter().any(|value| same(value, operand)),
+                        "$in" => values
+                            .iter()
+                            .any(|value| listed().iter().any(|listed| same(value, listed))),
+                        "$nin" => !values
+                            .iter()
+                            .any(|value| listed().iter().any(|listed| same(value, listed))),
+                        "$gt" => compare(&values, operand, |value, bound| value > bound),
+                        "$gte" => compare(&values, operand, |value, bound| value >= bound),
+                        "$lt" => compare(&values, operand, |value, bound| value < bound),
+                        "$lte" => compare(&values, operand, |value, bound| value <= bound),
+                        "$exists" => operand.as_bool() == Some(!values.is_empty()),
+                        _ => false,
+                    }
+                }),
+                None => values.iter().any(|value| same(value, condition)),
+            }
+        }
+    })
+}
+
+fn sparse_score(query: &SparseValues, stored: Option<&SparseValues>) -> Option<f32> {
+    let stored = stored?;
+    let weights: HashMap<u32, f32> = stored
+        .indices
+        .iter()
+        .copied()
+        .zip(stored.values.iter().copied())
+        .collect();
+    let score: f32 = query
+        .indices
+        .iter()
+        .zip(&query.values)
+        .filter_map(|(index, value)| weights.get(index).map(|weight| weight * value))
+        .sum();
+    (score > 0.0).then_some(score)
+}
+
+impl VectorStore for InMemoryVectorStore {
+    fn query_metadata_filtered<'a>(
+        &'a self,
+        field: &'a str,
+        value: &'a str,
+        exact_match: bool,
+        top_k: usize,
+        filter: Option<&'a Value>,
+    ) -> BoxFuture<'a, Result<Vec<Book>>> {
+        Box::pin(async move {
+            let field_filter = if exact_match {
+                serde_json::json!({ field: { "$eq": value } })
+            } else {
+                serde_json::json!({
+                    field: { "$in": [value.to_lowercase(), value.to_uppercase(), value] }
+                })
+            };
+            let filter = match filter {
+                Some(filter) => serde_json::json!({ "$and": [field_filter, filter] }),
+                None => field_filter,
+            };
+            self.search(top_k, Some(&filter), |_| Some(0.0))
+        })
+    }
+
+    fn query_vector_filtered<'a>(
+        &'a self,
+        embedding: &'a [f32],
+        top_k: usize,
+        filter: Option<&'a Value>,
+    ) -> BoxFuture<'a, Result<Vec<Book>>> {
+        Box::pin(async move {
+            self.search(top_k, filter, |stored| {
+                Some(cosine_similarity(embedding, &stored.values))
+            })
+        })
+    }
+
+    fn query_sparse<'a>(
+        &'a self,
+        sparse: &'a SparseValues,
+        top_k: usize,
+    ) -> BoxFuture<'a, Result<Vec<Book>>> {
+        Box::pin(async move {
+            self.search(top_k, None, |stored| {
+                sparse_score(sparse, stored.sparse_values.as_ref())
+            })
+        })
+    }
+
+    fn upsert_vectors<'a>(&'a self, vectors: &'a [UpsertVector]) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            self.check_available()?;
+            let mut stored = recover_lock(self.vectors.write(), "in-memory vectors");
+            for vector in vectors {
+                stored.insert(
+                    vector.id.clone(),
+                    StoredVector {
+                        values: vector.values.clone(),
+                        sparse_values: vector.sparse_values.clone(),
+                        metadata: vector.metadata.clone(),
+                    },
+                );
+            }
+            Ok(())
+        })
+    }
+
+    fn list_ids(&self) -> BoxFuture<'_, Result<Vec<String>>> {
+        Box::pin(async move {
+            self.check_available()?;
+            Ok(recover_lock(self.vectors.read(), "in-memory vectors")
+                .keys()
+                .cloned()
+                .collect())
+        })
+    }
+
+    fn delete_vectors<'a>(&'a self, ids: &'a [String]) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            self.check_available()?;
+            let mut stored = recover_lock(self.vectors.write(), "in-memory vectors");
+            for id in ids {
+                stored.remove(id);
+            }
+            Ok(())
+        })
+    }
+
+    fn update_metadata<'a>(
+        &'a self,
+        id: &'a str,
+        metadata: &'a Value,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            self.check_available()?;
+            let mut stored = recover_lock(self.vectors.write(), "in-memory vectors");
+            let vector = stored
+                .get_mut(id)
+                .ok_or_else(|| ApiError::NotFound(format!("Vector {} not found", id)))?;
+            if let (Some(stored), Some(fields)) =
+                (vector.metadata.as_object_mut(), metadata.as_object())
+            {
+                for (field, value) in fields {
+                    stored.insert(field.clone(), value.clone());
+                }
+            }
+            Ok(())
+        })
+    }
+
+    fn fetch_metadata<'a>(
+        &'a self,
+        ids: &'a [String],
+    ) -> BoxFuture<'a, Result<HashMap<String, Value>>> {
+        Box::pin(async move {
+            self.check_available()?;
+            let stored = recover_lock(self.vectors.read(), "in-memory vectors");
+            Ok(ids
+                .iter()
+                .filter_map(|id| Some((id.clone(), stored.get(id)?.metadata.clone())))
+                .collect())
+        })
+    }
+
+    fn fetch_books<'a>(
+        &'a self,
+        ids: &'a [String],
+    ) -> BoxFuture<'a, Result<HashMap<String, Book>>> {
+        Box::pin(async move {
+            Ok(self
+                .fetch_metadata(ids)
+                .await?
+                .into_iter()
+                .filter(|(_, metadata)| !is_hidden(metadata))
+                .filter_map(|(id, metadata)| Some((id.clone(), book_of(&id, &metadata)?)))
+                .collect())
+        })
+    }
+
+    fn fetch_vectors<'a>(
+        &'a self,
+        ids: &'a [String],
+    ) -> BoxFuture<'a, Result<HashMap<String, Vec<f32>>>> {
+        Box::pin(async move {
+            self.check_available()?;
+            let stored = recover_lock(self.vectors.read(), "in-memory vectors");
+            Ok(ids
+                .iter()
+                .filter_map(|id| Some((id.clone(), stored.get(id)?.values.clone())))
+                .collect())
+        })
+    }
+
+    fn hidden_ids(&self) -> BoxFuture<'_, Result<Vec<String>>> {
+        Box::pin(async move {
+            self.check_available()?;
+            Ok(recover_lock(self.vectors.read(), "in-memory vectors")
+                .iter()
+                .filter(|(_, stored)| is_hidden(&stored.metadata))
+                .map(|(id, _)| id.clone())
+                .collect())
+        })
+    }
+
+    fn set_hidden<'a>(&'a self, id: &'a str, hidden: bool) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            self.update_metadata(id, &serde_json::json!({ HIDDEN_FIELD: hidden }))
+                .await
+        })
+    }
+
+    fn clear_caches(&self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_filters_match_as_pinecone_does() {
+        let metadata = json!({ "genres": ["fantasy", "romance"], "year": 1990, "language": "en" });
+        assert!(matches(
+            &metadata,
+            &json!({ "genres": { "$in": ["romance"] } })
+        ));
+        assert!(matches(
+            &metadata,
+            &json!({ "$and": [
+                { "year": { "$gte": 1980, "$lte": 2000 } },
+                { "language": { "$eq": "en" } },
+            ] })
+        ));
+        assert!(!matches(&metadata, &json!({ "year": { "$gte": 2000 } })));
+        assert!(!matches(
+            &metadata,
+            &json!({ "page_count": { "$lte": 300 } })
+        ));
+    }
+}
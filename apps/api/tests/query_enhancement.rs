@@ -0,0 +1,33 @@
+//! Snapshots of how the query templates read a corpus of representative
+//! queries, so a change to the patterns or keyword tables shows up as a
+//! reviewable diff of every query it affects
+
+use recommend_a_book_api::services::QueryEnhancer;
+use serde::Deserialize;
+
+const CORPUS: &str = include_str!("data/enhancement_queries.yaml");
+
+#[derive(Deserialize)]
+struct Corpus {
+    sections: Vec<Section>,
+}
+
+#[derive(Deserialize)]
+struct Section {
+    name: String,
+    queries: Vec<String>,
+}
+
+#[test]
+fn test_query_enhancement_snapshots() {
+    let corpus: Corpus = serde_yaml::from_str(CORPUS).unwrap();
+    let enhancer = QueryEnhancer::new();
+    for section in corpus.sections {
+        let enhanced: Vec<_> = section
+            .queries
+            .iter()
+            .map(|query| enhancer.enhance(query))
+            .collect();
+        insta::assert_json_snapshot!(section.name, enhanced);
+    }
+}
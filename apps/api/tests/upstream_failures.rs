@@ -0,0 +1,369 @@
+//! The HuggingFace and Pinecone clients, and the recommendation service over
+//! them, against mock servers failing the ways the real ones do: throttling,
+//! a model still loading, timeouts and bodies that don't parse
+
+use recommend_a_book_api::ml::huggingface_embedder::HuggingFaceEmbedder;
+use recommend_a_book_api::models::{EndpointCheck, SearchPath, UpstreamState};
+use recommend_a_book_api::services::recommendation::RankingParams;
+use recommend_a_book_api::services::{deadline, Pinecone, RecommendationService};
+use recommend_a_book_api::ApiError;
+use serde_json::json;
+use std::time::{Duration, Instant};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const API_KEY: &str = "test-api-key-0123456789";
+
+/// An encoder of the mock `server`; the model name also keeps each test's
+/// texts apart in the process-wide embedding cache
+fn embedder(server: &MockServer, model: &str) -> HuggingFaceEmbedder {
+    HuggingFaceEmbedder::with_endpoint(&server.uri(), model, API_KEY, Duration::from_millis(200))
+        .unwrap()
+        .with_retries(3, Duration::from_millis(10))
+}
+
+fn embedding() -> serde_json::Value {
+    json!(vec![0.1_f32; 512])
+}
+
+fn matches() -> serde_json::Value {
+    json!({
+        "matches": [{
+            "id": "hobbit",
+            "score": 0.9,
+            "metadata": {
+                "title": "The Hobbit",
+                "author": "J.R.R. Tolkien",
+                "description": "A hobbit's journey to reclaim a treasure guarded by a dragon.",
+                "categories": ["Fantasy"],
+                "rating": 4.3,
+            },
+        }]
+    })
+}
+
+async fn mount_model(server: &MockServer, model: &str, response: ResponseTemplate) {
+    Mock::given(method("POST"))
+        .and(path(format!("/models/{}", model)))
+        .respond_with(response)
+        .mount(server)
+        .await;
+}
+
+#[tokio::test]
+async fn test_embedder_rate_limit_surfaces_retry_after_without_retrying() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/models/test/rate-limited"))
+        .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "7"))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let error = embedder(&server, "test/rate-limited")
+        .encode("dragons and treasure")
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        error,
+        ApiError::ServiceUnavailable {
+            retry_after: Some(7),
+            ..
+        }
+    ));
+}
+
+#[tokio::test]
+async fn test_embedder_loading_model_reports_its_estimated_time() {
+    let server = MockServer::start().await;
+    mount_model(
+        &server,
+        "test/loading",
+        ResponseTemplate::new(503).set_body_json(json!({
+            "error": "Model test/loading is currently loading",
+            "estimated_time": 19.2,
+        })),
+    )
+    .await;
+
+    let error = embedder(&server, "test/loading")
+        .encode("dragons and treasure")
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        error,
+        ApiError::ServiceUnavailable {
+            retry_after: Some(20),
+            ..
+        }
+    ));
+}
+
+#[tokio::test]
+async fn test_embedder_retries_timeouts() {
+    let server = MockServer::start().await;
+    // The first attempt times out, the second is answered
+    Mock::given(method("POST"))
+        .and(path("/models/test/slow"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(embedding())
+                .set_delay(Duration::from_secs(2)),
+        )
+        .up_to_n_times(1)
+        .expect(1)
+        .with_priority(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/models/test/slow"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(embedding()))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let embedding = embedder(&server, "test/slow")
+        .encode("dragons and treasure")
+        .await
+        .unwrap();
+    assert_eq!(embedding.len(), 512);
+}
+
+#[tokio::test]
+async fn test_embedder_gives_up_after_its_attempts_time_out() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/models/test/hanging"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(embedding())
+                .set_delay(Duration::from_secs(2)),
+        )
+        .expect(3)
+        .mount(&server)
+        .await;
+
+    let error = embedder(&server, "test/hanging")
+        .encode("dragons and treasure")
+        .await
+        .unwrap_err();
+    assert!(matches!(error, ApiError::ExternalServiceError(_)));
+}
+
+#[tokio::test]
+async fn test_embedder_retries_stop_at_the_request_deadline() {
+    let server = MockServer::start().await;
+    mount_model(
+        &server,
+        "test/deadline",
+        ResponseTemplate::new(200)
+            .set_body_json(embedding())
+            .set_delay(Duration::from_secs(2)),
+    )
+    .await;
+    let embedder = HuggingFaceEmbedder::with_endpoint(
+        &server.uri(),
+        "test/deadline",
+        API_KEY,
+        Duration::from_secs(5),
+    )
+    .unwrap()
+    .with_retries(3, Duration::from_millis(10));
+
+    // Three attempts could take 15 seconds; the deadline cuts the first short
+    let started = Instant::now();
+    let error = deadline::within(Duration::from_millis(300), embedder.encode("dragons"))
+        .await
+        .unwrap_err();
+    assert!(matches!(error, ApiError::ServiceUnavailable { .. }));
+    assert!(started.elapsed() < Duration::from_secs(1));
+    assert_eq!(server.received_requests().await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_embedder_rejects_a_malformed_body() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/models/test/malformed"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("<html>Bad gateway</html>"))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let error = embedder(&server, "test/malformed")
+        .encode("dragons and treasure")
+        .await
+        .unwrap_err();
+    assert!(matches!(error, ApiError::SerializationError(_)));
+}
+
+#[tokio::test]
+async fn test_pinecone_retries_server_errors() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/query"))
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(2)
+        .expect(2)
+        .with_priority(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/query"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(matches()))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let pinecone = Pinecone::with_host(API_KEY, &server.uri()).unwrap();
+    let books = pinecone.query_vector(&[0.1; 512], 5).await.unwrap();
+    assert_eq!(books[0].id.as_deref(), Some("hobbit"));
+}
+
+#[tokio::test]
+async fn test_pinecone_throttling_surfaces_retry_after_without_retrying() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/query"))
+        .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "3"))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let pinecone = Pinecone::with_host(API_KEY, &server.uri()).unwrap();
+    let error = pinecone.query_vector(&[0.1; 512], 5).await.unwrap_err();
+    assert!(matches!(
+        error,
+        ApiError::ServiceUnavailable {
+            retry_after: Some(3),
+            ..
+        }
+    ));
+}
+
+#[tokio::test]
+async fn test_pinecone_rejects_a_malformed_body() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/query"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("{\"matches\": [{"))
+        .mount(&server)
+        .await;
+
+    let pinecone = Pinecone::with_host(API_KEY, &server.uri()).unwrap();
+    let error = pinecone.query_vector(&[0.1; 512], 5).await.unwrap_err();
+    assert!(matches!(error, ApiError::PineconeError(_)));
+}
+
+#[tokio::test]
+async fn test_pinecone_probe_pins_the_resolved_host_and_reports_a_rejected_key() {
+    let server = MockServer::start().await;
+    Mock::given(method("HEAD"))
+        .and(path("/describe_index_stats"))
+        .respond_with(ResponseTemplate::new(200))
+        .up_to_n_times(1)
+        .with_priority(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("HEAD"))
+        .and(path("/describe_index_stats"))
+        .respond_with(ResponseTemplate::new(401))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/query"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(matches()))
+        .mount(&server)
+        .await;
+
+    let pinecone = Pinecone::with_host(API_KEY, &server.uri()).unwrap();
+    assert!(pinecone.endpoint_status().is_none());
+
+    let probe = pinecone.probe_endpoint().await;
+    assert_eq!(probe.check, EndpointCheck::Ok);
+    assert_eq!(probe.addresses, vec![server.address().to_string()]);
+    let books = pinecone.query_vector(&[0.1; 512], 5).await.unwrap();
+    assert_eq!(books[0].id.as_deref(), Some("hobbit"));
+
+    let probe = pinecone.probe_endpoint().await;
+    assert_eq!(probe.check, EndpointCheck::Unauthorized);
+    assert!(probe.message.unwrap().contains("APP_PINECONE_API_KEY"));
+    assert_eq!(
+        pinecone.endpoint_status().unwrap().check,
+        EndpointCheck::Unauthorized
+    );
+}
+
+/// A service over mocks of both upstreams, Pinecone answering every query
+async fn service(model: &str, embeddings: ResponseTemplate) -> (RecommendationService, MockServer) {
+    let server = MockServer::start().await;
+    mount_model(&server, model, embeddings).await;
+    Mock::given(method("POST"))
+        .and(path("/query"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(matches()))
+        .mount(&server)
+        .await;
+    let service = RecommendationService::new(
+        embedder(&server, model),
+        Pinecone::with_host(API_KEY, &server.uri()).unwrap(),
+    );
+    (service, server)
+}
+
+#[tokio::test]
+async fn test_service_falls_back_to_keywords_when_embeddings_fail() {
+    let (service, _server) = service(
+        "test/fallback",
+        ResponseTemplate::new(503).set_body_json(json!({ "estimated_time": 30.0 })),
+    )
+    .await;
+
+    let (books, _, serving) = service
+        .get_recommendations_served("the hobbit", 5, &RankingParams::default())
+        .await
+        .unwrap();
+    // Fallback results carry ids marked for analytics
+    assert_eq!(books[0].id.as_deref(), Some("fallback-hobbit"));
+    assert!(serving.degraded);
+    assert_eq!(serving.search_path, SearchPath::Sparse);
+}
+
+#[tokio::test]
+async fn test_service_stops_calling_a_failing_embedder() {
+    let (service, server) = service(
+        "test/breaker",
+        ResponseTemplate::new(503).set_body_json(json!({ "estimated_time": 30.0 })),
+    )
+    .await;
+
+    for query in ["the hobbit", "hobbit adventures", "dragon treasure"] {
+        service
+            .get_recommendations_served(query, 5, &RankingParams::default())
+            .await
+            .unwrap();
+    }
+    let embeddings = service
+        .upstream_statuses()
+        .into_iter()
+        .find(|status| status.upstream == "embeddings")
+        .unwrap();
+    assert_eq!(embeddings.state, UpstreamState::Open);
+
+    // With the breaker open, queries go straight to the keyword search
+    let requests = server.received_requests().await.unwrap().len();
+    let (books, _, serving) = service
+        .get_recommendations_served("hobbit journeys", 5, &RankingParams::default())
+        .await
+        .unwrap();
+    assert!(!books.is_empty());
+    assert_eq!(serving.search_path, SearchPath::Sparse);
+    let model_requests = server
+        .received_requests()
+        .await
+        .unwrap()
+        .into_iter()
+        .skip(requests)
+        .filter(|request| request.url.path().starts_with("/models/"))
+        .count();
+    assert_eq!(model_requests, 0);
+}
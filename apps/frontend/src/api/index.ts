@@ -189,6 +189,8 @@ export async function fetchRecommendations(
         body: JSON.stringify({
           query: trimmedQuery,
           topK,
+          // The description accordion shows the whole text
+          description_full: true,
         } satisfies RecommendationRequest),
         signal: requestSignal,
       });
@@ -26,6 +26,10 @@ export interface Book {
 export interface RecommendationRequest {
   query: string;
   topK?: number;
+  /** Book fields to return; all when omitted */
+  fields?: string[];
+  /** Full descriptions instead of 300-character previews */
+  description_full?: boolean;
 }
 
 /**